@@ -0,0 +1,469 @@
+//! `# @auth oidc <config-name>` on a request: a valid token for the named
+//! OIDC config is injected as `Authorization: Bearer <token>` at send time.
+//!
+//! Configs are looked up in the request's environment, among the
+//! `Security.Auth` configs of its env files (see [`AuthConfig`]), and then in
+//! the workspace settings under `oidc.<name>` as an [`OidcConfig`] JSON
+//! object.
+
+use crate::env::{AuthConfig, GrantType};
+use crate::history::{HistoryContext, HistoryDb};
+use crate::http_client::HttpRequest;
+use crate::oidc::{
+    client_credentials_grant, fetch_discovery, password_grant, OidcConfig, TokenResponse,
+};
+use crate::tokens::{TokenStore, DEFAULT_SKEW_SECS, REAUTH_REQUIRED_ERROR};
+use crate::variables::{environment_variables, substitute};
+use std::collections::HashMap;
+
+/// Header the token is sent in, masked when the request is recorded
+pub const AUTHORIZATION_HEADER: &str = "Authorization";
+
+/// Prefix of workspace settings holding OIDC configs by name
+pub const OIDC_CONFIG_SETTING_PREFIX: &str = "oidc.";
+
+/// Redirect URL for env file configs that don't give one; the port is
+/// chosen when the login starts
+const DEFAULT_REDIRECT_URL: &str = "http://127.0.0.1:0/callback";
+
+/// How tokens for a named config are obtained when none is cached
+#[derive(Debug, Clone, PartialEq)]
+pub enum Grant {
+    /// A browser login, which the UI runs. Implicit and device configs log
+    /// in this way too, with the authorization code flow.
+    Interactive,
+    ClientCredentials,
+    Password {
+        username: String,
+        password: String,
+    },
+}
+
+/// A named config with its variables substituted
+#[derive(Debug, Clone)]
+pub struct NamedAuthConfig {
+    pub config: OidcConfig,
+    pub grant: Grant,
+}
+
+/// The config a `# @auth` value names, when it is `oidc <config-name>`
+pub fn oidc_config_name(auth: &str) -> Option<&str> {
+    let mut parts = auth.split_whitespace();
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(kind), Some(name), None) if kind.eq_ignore_ascii_case("oidc") => Some(name),
+        _ => None,
+    }
+}
+
+/// The OIDC config named `name` for a request sent in `context`
+pub async fn find_oidc_config(
+    history_db: &HistoryDb,
+    context: &HistoryContext,
+    name: &str,
+) -> Result<OidcConfig, String> {
+    find_auth_config(history_db, context, name)
+        .await
+        .map(|named| named.config)
+}
+
+/// The config named `name` for a request sent in `context`, with the grant
+/// that gets its tokens
+pub async fn find_auth_config(
+    history_db: &HistoryDb,
+    context: &HistoryContext,
+    name: &str,
+) -> Result<NamedAuthConfig, String> {
+    let mut env_config = crate::env::load_environment_config(
+        context.workspace.clone(),
+        context.file_path.clone(),
+        context.env_glob.clone(),
+    )
+    .await?;
+    let environment = context.environment.as_deref();
+    let auth = environment
+        .and_then(|env| env_config.environments.iter().find(|e| e.name == env))
+        .and_then(|env| env.auth.get(name))
+        .cloned();
+    if let Some(auth) = auth {
+        let inputs: Vec<&str> = [
+            &auth.issuer,
+            &auth.auth_url,
+            &auth.token_url,
+            &auth.client_id,
+            &auth.client_secret,
+            &auth.redirect_url,
+            &auth.username,
+            &auth.password,
+        ]
+        .into_iter()
+        .filter_map(|value| value.as_deref())
+        .chain(auth.scopes.iter().map(String::as_str))
+        .collect();
+        crate::env::run_referenced_commands(
+            &context.workspace,
+            &mut env_config,
+            environment,
+            &inputs,
+        )
+        .await;
+        let vars = environment_variables(&env_config, environment)?;
+        return from_env(name, &auth, &vars);
+    }
+
+    let workspace = context.workspace.clone();
+    let key = format!("{}{}", OIDC_CONFIG_SETTING_PREFIX, name);
+    let setting = history_db
+        .run(move |db| db.get_workspace_setting(&workspace, &key))
+        .await
+        .map_err(|e| format!("Failed to load workspace settings: {}", e))?;
+    match setting {
+        Some(json) => serde_json::from_str(&json)
+            .map(|config| NamedAuthConfig {
+                config,
+                grant: Grant::Interactive,
+            })
+            .map_err(|e| {
+                format!(
+                    "Invalid OIDC config '{}' in workspace settings: {}",
+                    name, e
+                )
+            }),
+        None => Err(format!(
+            "OIDC config '{}' not found in the environment or workspace settings",
+            name
+        )),
+    }
+}
+
+/// Env file values may reference other variables, such as a secret
+fn from_env(
+    name: &str,
+    auth: &AuthConfig,
+    vars: &HashMap<String, String>,
+) -> Result<NamedAuthConfig, String> {
+    if !auth.is_supported() {
+        return Err(format!(
+            "Auth config '{}' has type '{}'; only {} configs can be used",
+            name,
+            auth.auth_type.as_deref().unwrap_or_default(),
+            crate::env::OAUTH2_AUTH_TYPE
+        ));
+    }
+    let resolve = |value: &Option<String>| value.as_ref().map(|value| substitute(value, vars));
+    let client_id = resolve(&auth.client_id)
+        .ok_or_else(|| format!("Auth config '{}' has no Client ID", name))?;
+    let grant = match auth.grant_type {
+        Some(GrantType::ClientCredentials) => Grant::ClientCredentials,
+        Some(GrantType::Password) => match (resolve(&auth.username), resolve(&auth.password)) {
+            (Some(username), Some(password)) => Grant::Password { username, password },
+            _ => {
+                return Err(format!(
+                    "Auth config '{}' uses the password grant but has no Username or Password",
+                    name
+                ))
+            }
+        },
+        _ => Grant::Interactive,
+    };
+    let mut scopes: Vec<String> = auth.scopes.iter().map(|s| substitute(s, vars)).collect();
+    if scopes.is_empty() && grant == Grant::Interactive {
+        scopes.push("openid".to_string());
+    }
+    let redirect_url = resolve(&auth.redirect_url);
+    let config = OidcConfig {
+        issuer: resolve(&auth.issuer),
+        authorization_endpoint: resolve(&auth.auth_url),
+        token_endpoint: resolve(&auth.token_url),
+        client_id,
+        client_secret: resolve(&auth.client_secret),
+        token_endpoint_auth_method: auth.client_auth_method,
+        use_random_port: redirect_url.is_none(),
+        redirect_url: redirect_url.unwrap_or_else(|| DEFAULT_REDIRECT_URL.to_string()),
+        use_pkce: auth.pkce.unwrap_or(true),
+        scopes,
+        extra_params: HashMap::new(),
+        callback_page: Default::default(),
+    };
+    Ok(NamedAuthConfig { config, grant })
+}
+
+/// Tokens for a grant that needs no browser; `None` for interactive ones
+async fn fetch_tokens(named: &NamedAuthConfig) -> Result<Option<TokenResponse>, String> {
+    if named.grant == Grant::Interactive {
+        return Ok(None);
+    }
+    let discovery = match &named.config.issuer {
+        Some(issuer) => Some(fetch_discovery(issuer).await?),
+        None => None,
+    };
+    let tokens = match &named.grant {
+        Grant::ClientCredentials => {
+            client_credentials_grant(&named.config, discovery.as_ref()).await?
+        }
+        Grant::Password { username, password } => {
+            password_grant(&named.config, discovery.as_ref(), username, password).await?
+        }
+        Grant::Interactive => unreachable!(),
+    };
+    Ok(Some(tokens))
+}
+
+/// Add a bearer token for the named config to the request, replacing any
+/// Authorization header it has. Tokens close to expiry are refreshed, and
+/// client credentials and password configs get new ones; when there is none
+/// to use, fails with an error starting with [`REAUTH_REQUIRED_ERROR`] so
+/// the UI can start a login.
+pub async fn authorize(
+    request: &mut HttpRequest,
+    token_store: &TokenStore,
+    history_db: &HistoryDb,
+    context: &HistoryContext,
+    name: &str,
+) -> Result<(), String> {
+    let named = find_auth_config(history_db, context, name).await?;
+    let cached = match token_store
+        .get_valid_token(&context.workspace, name, &named.config, DEFAULT_SKEW_SECS)
+        .await
+    {
+        Err(e) if e.starts_with(REAUTH_REQUIRED_ERROR) => match fetch_tokens(&named).await? {
+            Some(tokens) => {
+                token_store
+                    .store_tokens(&context.workspace, name, tokens)?
+                    .0
+            }
+            None => return Err(e),
+        },
+        cached => cached?,
+    };
+    request
+        .headers
+        .retain(|key, _| !key.eq_ignore_ascii_case(AUTHORIZATION_HEADER));
+    request.headers.insert(
+        AUTHORIZATION_HEADER.to_string(),
+        format!("Bearer {}", cached.token.access_token),
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::send_recorded;
+    use crate::oidc::mock::serve_once;
+    use crate::oidc::TokenEndpointAuthMethod;
+
+    fn context(workspace: &str) -> HistoryContext {
+        HistoryContext {
+            workspace: workspace.to_string(),
+            file_path: None,
+            request_name: Some("me".to_string()),
+            environment: Some("dev".to_string()),
+            variables: None,
+            env_glob: None,
+            replayed_from: None,
+        }
+    }
+
+    #[test]
+    fn test_oidc_config_name() {
+        assert_eq!(oidc_config_name("oidc keycloak"), Some("keycloak"));
+        assert_eq!(oidc_config_name("  OIDC  dev-login "), Some("dev-login"));
+        assert_eq!(oidc_config_name("oidc"), None);
+        assert_eq!(oidc_config_name("bearer"), None);
+        assert_eq!(oidc_config_name("oidc a b"), None);
+    }
+
+    #[tokio::test]
+    async fn test_find_oidc_config() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("http-client.env.json"),
+            r#"{"dev": {
+                "Security": {"Auth": {"keycloak": {
+                    "Type": "OAuth2",
+                    "Grant Type": "Authorization Code",
+                    "Token URL": "https://idp.test/token",
+                    "Client ID": "kvile",
+                    "Client Credentials": "basic",
+                    "Scope": "openid profile",
+                    "PKCE": false
+                }, "ldap": {"Type": "LDAP"}}}
+            }}"#,
+        )
+        .unwrap();
+        // The private env file adds the secret, from a private variable
+        std::fs::write(
+            dir.path().join("http-client.private.env.json"),
+            r#"{"dev": {
+                "secret": "s3cret",
+                "Security": {"Auth": {"keycloak": {"Client Secret": "{{secret}}"}}}
+            }}"#,
+        )
+        .unwrap();
+        let workspace = dir.path().to_string_lossy().to_string();
+        let db = HistoryDb::in_memory().unwrap();
+
+        let config = find_oidc_config(&db, &context(&workspace), "keycloak")
+            .await
+            .unwrap();
+        assert_eq!(
+            config.token_endpoint.as_deref(),
+            Some("https://idp.test/token")
+        );
+        assert_eq!(config.client_id, "kvile");
+        assert_eq!(config.client_secret.as_deref(), Some("s3cret"));
+        assert_eq!(
+            config.token_endpoint_auth_method,
+            Some(TokenEndpointAuthMethod::ClientSecretBasic)
+        );
+        assert_eq!(config.scopes, vec!["openid", "profile"]);
+        assert!(config.use_random_port);
+        assert!(!config.use_pkce);
+        assert_eq!(
+            find_oidc_config(&db, &context(&workspace), "ldap")
+                .await
+                .unwrap_err(),
+            "Auth config 'ldap' has type 'LDAP'; only OAuth2 configs can be used"
+        );
+
+        // Names not in the env file come from workspace settings
+        db.set_workspace_setting(
+            &workspace,
+            "oidc.entra",
+            Some(r#"{"issuer":"https://login.test","client_id":"app","redirect_url":"http://localhost:8080/cb","scopes":["openid"]}"#),
+        )
+        .unwrap();
+        let config = find_oidc_config(&db, &context(&workspace), "entra")
+            .await
+            .unwrap();
+        assert_eq!(config.issuer.as_deref(), Some("https://login.test"));
+        assert_eq!(config.redirect_url, "http://localhost:8080/cb");
+
+        assert_eq!(
+            find_oidc_config(&db, &context(&workspace), "missing")
+                .await
+                .unwrap_err(),
+            "OIDC config 'missing' not found in the environment or workspace settings"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_client_credentials_config_fetches_token_without_login() {
+        crate::secrets::memory::install();
+        let (idp_url, idp) = serve_once(
+            "200 OK",
+            r#"{"access_token":"service-token","token_type":"Bearer","expires_in":3600}"#,
+        )
+        .await;
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("http-client.env.json"),
+            format!(
+                r#"{{"dev": {{"Security": {{"Auth": {{"svc": {{
+                    "Type": "OAuth2",
+                    "Grant Type": "Client Credentials",
+                    "Token URL": "{}",
+                    "Client ID": "svc",
+                    "Client Secret": "svc-secret"
+                }}}}}}}}}}"#,
+                idp_url
+            ),
+        )
+        .unwrap();
+        let workspace = dir.path().to_string_lossy().to_string();
+        let db = HistoryDb::in_memory().unwrap();
+        let token_store = TokenStore::with_fallback_dir(dir.path().join("tokens"));
+        let context = context(&workspace);
+
+        let mut request = HttpRequest {
+            method: "GET".to_string(),
+            url: "https://api.test".to_string(),
+            headers: HashMap::new(),
+            body: None,
+        };
+        authorize(&mut request, &token_store, &db, &context, "svc")
+            .await
+            .unwrap();
+        assert_eq!(
+            request.headers[AUTHORIZATION_HEADER],
+            "Bearer service-token"
+        );
+        assert!(idp.await.unwrap().contains("grant_type=client_credentials"));
+
+        // The token is cached for the next request
+        let cached = token_store.load_tokens(&workspace, "svc").unwrap().unwrap();
+        assert_eq!(cached.token.access_token, "service-token");
+    }
+
+    #[tokio::test]
+    async fn test_bearer_token_injected_and_masked_in_history() {
+        crate::secrets::memory::install();
+        let (idp_url, idp) = serve_once(
+            "200 OK",
+            r#"{"access_token":"fresh-token","token_type":"Bearer","expires_in":3600}"#,
+        )
+        .await;
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("http-client.env.json"),
+            format!(
+                r#"{{"dev": {{"Security": {{"Auth": {{"api": {{"Token URL": "{}", "Client ID": "kvile"}}}}}}}}}}"#,
+                idp_url
+            ),
+        )
+        .unwrap();
+        let workspace = dir.path().to_string_lossy().to_string();
+        let db = HistoryDb::in_memory().unwrap();
+        let token_store = TokenStore::with_fallback_dir(dir.path().join("tokens"));
+        let context = context(&workspace);
+
+        let mut request = HttpRequest {
+            method: "GET".to_string(),
+            url: String::new(),
+            headers: HashMap::from([("authorization".to_string(), "stale".to_string())]),
+            body: None,
+        };
+
+        // Without a cached token the UI has to log in first
+        let err = authorize(&mut request, &token_store, &db, &context, "api")
+            .await
+            .unwrap_err();
+        assert_eq!(err, "reauth_required: No token cached for 'api'");
+
+        // An expired token is refreshed at the mock IdP before sending
+        token_store
+            .store_tokens(
+                &workspace,
+                "api",
+                TokenResponse {
+                    access_token: "old-token".to_string(),
+                    token_type: Some("Bearer".to_string()),
+                    expires_in: Some(0),
+                    refresh_token: Some("rt-1".to_string()),
+                    id_token: None,
+                    scope: None,
+                    issued_token_type: None,
+                },
+            )
+            .unwrap();
+        authorize(&mut request, &token_store, &db, &context, "api")
+            .await
+            .unwrap();
+        assert!(idp.await.unwrap().contains("refresh_token=rt-1"));
+
+        let (echo_url, echo) = serve_once("200 OK", "{}").await;
+        request.url = echo_url;
+        let response = send_recorded(&db, request, &context, None, &[AUTHORIZATION_HEADER])
+            .await
+            .unwrap();
+        let received = echo.await.unwrap().to_lowercase();
+        assert!(received.contains("authorization: bearer fresh-token"));
+        assert!(!received.contains("stale"));
+
+        let entry = db.get_entry(response.history_id.unwrap()).unwrap().unwrap();
+        assert!(!entry.request_headers.contains("fresh-token"));
+        assert!(entry
+            .request_headers
+            .contains(r#""Authorization":"Bearer ********""#));
+    }
+}
@@ -0,0 +1,127 @@
+//! Headless kvile: runs .http files and reports the results, for CI.
+//!
+//! Exits 0 when every request succeeds, 1 when any fails (including non-2xx
+//! responses and requests with scripts) and 2 for usage or setup errors.
+
+use kvile_lib::runner::{self, RunOptions, RunReport};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+const USAGE: &str = "Usage: kvile-cli run <file|dir>... [options]
+
+Options:
+  --env <name>          Environment to resolve variables with
+  --workspace <dir>     Workspace root env files are found from [default: .]
+  --env-glob <glob>     Extra env files to merge, as in the app's setting
+  --process-env         Fall back to OS environment variables
+  --allow-error-status  Pass requests answered with a non-2xx status
+  --skip-scripts        Send requests that have scripts without running them
+  --format <format>     Output: human, json or junit [default: human]
+  --report <file>       Also write a report; JSON for .json files, else JUnit XML
+  -h, --help            Print this help";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Human,
+    Json,
+    Junit,
+}
+
+struct Args {
+    options: RunOptions,
+    format: Format,
+    report: Option<PathBuf>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Args>, String> {
+    match args.next().as_deref() {
+        Some("run") => {}
+        Some("-h" | "--help") | None => return Ok(None),
+        Some(other) => return Err(format!("Unknown command '{}'", other)),
+    }
+    let mut parsed = Args {
+        options: RunOptions {
+            workspace: PathBuf::from("."),
+            ..Default::default()
+        },
+        format: Format::Human,
+        report: None,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("Missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "--env" => parsed.options.environment = Some(value()?),
+            "--workspace" => parsed.options.workspace = PathBuf::from(value()?),
+            "--env-glob" => parsed.options.env_glob = Some(value()?),
+            "--report" => parsed.report = Some(PathBuf::from(value()?)),
+            "--format" => {
+                parsed.format = match value()?.as_str() {
+                    "human" => Format::Human,
+                    "json" => Format::Json,
+                    "junit" => Format::Junit,
+                    other => return Err(format!("Unknown format '{}'", other)),
+                }
+            }
+            "--process-env" => parsed.options.process_env = true,
+            "--allow-error-status" => parsed.options.allow_error_status = true,
+            "--skip-scripts" => parsed.options.skip_scripts = true,
+            "-h" | "--help" => return Ok(None),
+            flag if flag.starts_with('-') => return Err(format!("Unknown option '{}'", flag)),
+            path => parsed.options.paths.push(PathBuf::from(path)),
+        }
+    }
+    if parsed.options.paths.is_empty() {
+        return Err("Nothing to run: pass a .http file or directory".to_string());
+    }
+    Ok(Some(parsed))
+}
+
+fn render(report: &RunReport, format: Format) -> Result<String, String> {
+    match format {
+        Format::Human => Ok(runner::to_human(report)),
+        Format::Json => serde_json::to_string_pretty(report)
+            .map(|json| json + "\n")
+            .map_err(|e| e.to_string()),
+        Format::Junit => Ok(runner::to_junit(report)),
+    }
+}
+
+async fn run(args: Args) -> Result<bool, String> {
+    let report = runner::run(&args.options).await?;
+    print!("{}", render(&report, args.format)?);
+    if let Some(path) = &args.report {
+        let format = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Format::Json,
+            _ => Format::Junit,
+        };
+        std::fs::write(path, render(&report, format)?)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    }
+    Ok(report.success())
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match run(args).await {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::from(2)
+        }
+    }
+}
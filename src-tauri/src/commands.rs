@@ -1,21 +1,170 @@
-use crate::history::{HistoryDb, HistoryEntry, NewHistoryEntry};
+use crate::compare::{DiffIgnore, EnvironmentComparison};
+use crate::env::{merge_env_file, EnvMergeDiff};
+use crate::files::{
+    list_workspace_files, FileListing, FileOpError, ListOptions, DEFAULT_LIST_LIMIT,
+};
+use crate::history::{
+    resend, send_recorded, variables_snapshot, BodySearchHit, EndpointStats, HistoryBodies,
+    HistoryContext, HistoryCursor, HistoryDb, HistoryEntry, HistoryExportFormat, HistoryFilter,
+    HistoryImportMode, HistoryImportReport, HistoryPage, HistoryStats, HttpExportOptions,
+    LatestResult, NewHistoryEntry, PruneReport, RecentWorkspace, RetentionSettings, StatsPeriod,
+    TagCount, CALLBACK_AUTO_CLOSE_KEY, CALLBACK_ERROR_HTML_KEY, CALLBACK_SUCCESS_HTML_KEY,
+    EXEC_TRUSTED_KEY, SELECTED_ENVIRONMENT_KEY,
+};
 use crate::http_client::{execute_request, HttpRequest, HttpResponse};
-use crate::parser::{parse_http_content, ParsedRequest};
+use crate::ignores::WorkspaceIgnore;
+use crate::import::har::{HarImportOptions, HarImportResult};
+use crate::import::{ImportResult, ImportSummary};
+use crate::jsonpath::JsonMatch;
+use crate::logging::LogRecord;
+use crate::parser::{execution_order, parse_http_content, ParsedRequest};
+use crate::search::{SearchOptions, SearchResults};
+use crate::templates::TemplateInfo;
+use crate::variables::{secret_names, ResolveOptions, ResolvedRequest};
+use crate::watcher::WatcherManager;
+use crate::workspace_settings::{LoadedSettings, WorkspaceSettings};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use tauri::State;
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct FileInfo {
-    pub path: String,
-    pub name: String,
-    pub is_http_file: bool,
+/// A request [`send_request`] sends as it is, such as the `request` of a
+/// [`ResolvedRequest`], or one it resolves first
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum SendInput {
+    Unresolved(Box<ResolveArgs>),
+    Resolved(HttpRequest),
 }
 
-/// Send an HTTP request and return the response
+/// Send an HTTP request and return the response. With a `history` context
+/// the outcome is recorded in history, including failures. A context without
+/// an environment is recorded with the one last selected in the workspace.
+///
+/// An unresolved request is resolved like [`resolve_request`] does, with the
+/// workspace, file, environment and env glob of `history` unless it names
+/// its own, and fails when any variable is undefined.
+///
+/// `auth` is the request's `# @auth` value, taken from an unresolved
+/// request's metadata when not given. For `oidc <config-name>` a token for
+/// that config is sent as a bearer token, and masked in history; without one
+/// the request fails with a `reauth_required` error.
 #[tauri::command]
-pub async fn send_request(request: HttpRequest) -> Result<HttpResponse, String> {
-    execute_request(request).await.map_err(|e| e.to_string())
+#[tracing::instrument(
+    skip_all,
+    fields(request_id = crate::logging::next_request_id(), method = tracing::field::Empty)
+)]
+pub async fn send_request(
+    request: SendInput,
+    mut history: Option<HistoryContext>,
+    mut auth: Option<String>,
+    history_db: State<'_, HistoryDb>,
+    token_store: State<'_, TokenStore>,
+) -> Result<HttpResponse, String> {
+    let mut request = match request {
+        SendInput::Resolved(request) => request,
+        SendInput::Unresolved(mut args) => {
+            if let Some(context) = &history {
+                args.workspace = args.workspace.or_else(|| Some(context.workspace.clone()));
+                args.file_path = args.file_path.or_else(|| context.file_path.clone());
+                args.environment = args.environment.or_else(|| context.environment.clone());
+                args.env_glob = args.env_glob.or_else(|| context.env_glob.clone());
+            }
+            let resolved = resolve(&history_db, *args).await?;
+            if !resolved.missing.is_empty() {
+                return Err(format!(
+                    "Undefined variables: {}",
+                    resolved.missing.join(", ")
+                ));
+            }
+            if let Some(context) = &mut history {
+                context.environment = resolved.environment.or(context.environment.take());
+                if context.request_name.is_none() {
+                    context.request_name = resolved.request.name.clone();
+                }
+            }
+            if auth.is_none() {
+                auth = resolved.request.metadata.get("auth").cloned();
+            }
+            HttpRequest::from(resolved.request)
+        }
+    };
+    tracing::Span::current().record("method", request.method.as_str());
+
+    let oidc_config = auth.as_deref().and_then(crate::auth::oidc_config_name);
+    let Some(mut context) = history else {
+        if oidc_config.is_some() {
+            return Err("OIDC auth needs a workspace to find its config".to_string());
+        }
+        return execute_request(request, &WorkspaceSettings::default())
+            .await
+            .map_err(|e| e.to_string());
+    };
+    if context.environment.is_none() {
+        context.environment = selected_environment(&history_db, &context.workspace).await;
+    }
+    let mut masked_headers = Vec::new();
+    if let Some(name) = oidc_config {
+        crate::auth::authorize(&mut request, &token_store, &history_db, &context, name).await?;
+        masked_headers.push(crate::auth::AUTHORIZATION_HEADER);
+    }
+
+    let snapshot = match &context.variables {
+        Some(variables) => {
+            snapshot_variables(
+                &context.workspace,
+                context.file_path.as_deref(),
+                context.environment.as_deref(),
+                context.env_glob.as_deref(),
+                variables,
+            )
+            .await
+        }
+        None => None,
+    };
+    send_recorded(&history_db, request, &context, snapshot, &masked_headers).await
+}
+
+/// The environment for requests that don't name one: the workspace settings
+/// file's, else the one last selected on this machine
+async fn selected_environment(history_db: &HistoryDb, workspace: &str) -> Option<String> {
+    let key = workspace.to_string();
+    let local = history_db
+        .run(move |db| db.get_workspace_setting(&key, SELECTED_ENVIRONMENT_KEY))
+        .await
+        .ok()
+        .flatten();
+    WorkspaceSettings::load(Path::new(workspace))
+        .settings
+        .default_environment(local)
+}
+
+/// Serialize the variables a request was resolved with, masking secrets.
+/// Without the env config the secrets are unknown, so there is no snapshot.
+async fn snapshot_variables(
+    workspace: &str,
+    file_path: Option<&str>,
+    environment: Option<&str>,
+    env_glob: Option<&str>,
+    variables: &HashMap<String, String>,
+) -> Option<String> {
+    crate::env::load_environment_config(
+        workspace.to_string(),
+        file_path.map(str::to_string),
+        env_glob.map(str::to_string),
+    )
+    .await
+    .ok()
+    .map(|config| {
+        let secrets = secret_names(&config, environment);
+        // Kept out of the logs as well as history
+        for value in secrets.iter().filter_map(|name| variables.get(name)) {
+            crate::logging::add_secret(value);
+        }
+        variables_snapshot(variables, &secrets)
+    })
 }
 
 /// Parse an HTTP file and return all requests found in it
@@ -24,105 +173,809 @@ pub async fn parse_http_file(content: String) -> Result<Vec<ParsedRequest>, Stri
     parse_http_content(&content).map_err(|e| e.to_string())
 }
 
+/// The order to run a file's requests in, as indexes into `requests`:
+/// `# @depends-on` dependencies first, otherwise document order
+#[tauri::command]
+pub fn order_requests(requests: Vec<ParsedRequest>) -> Result<Vec<usize>, String> {
+    execution_order(&requests)
+}
+
+/// Why [`read_file`] failed, tagged so the frontend can tell a deleted file
+/// from other failures such as permissions
+#[derive(Debug, Clone, PartialEq, Serialize, thiserror::Error)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ReadFileError {
+    #[error("File not found: {path}")]
+    NotFound { path: String },
+    #[error("Failed to read file: {message}")]
+    Other { message: String },
+}
+
 /// Read a file from the filesystem
 #[tauri::command]
-pub async fn read_file(path: String) -> Result<String, String> {
+pub async fn read_file(path: String) -> Result<String, ReadFileError> {
     tokio::fs::read_to_string(&path)
         .await
-        .map_err(|e| format!("Failed to read file: {}", e))
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => ReadFileError::NotFound { path },
+            _ => ReadFileError::Other {
+                message: e.to_string(),
+            },
+        })
 }
 
-/// Write content to a file
+/// Write content to a file. The watcher doesn't report the write back.
 #[tauri::command]
-pub async fn write_file(path: String, content: String) -> Result<(), String> {
+pub async fn write_file(
+    path: String,
+    content: String,
+    watchers: State<'_, WatcherManager>,
+) -> Result<(), String> {
+    watchers.suppress_write(Path::new(&path), content.as_bytes());
     tokio::fs::write(&path, &content)
         .await
         .map_err(|e| format!("Failed to write file: {}", e))
 }
 
-/// List all .http and .rest files in a directory recursively
+/// `workspace` if the app has it open, meaning it is a root the watcher was
+/// started on, so file operations can't be aimed at any directory
+fn open_workspace<'a>(
+    workspace: &'a str,
+    watchers: &WatcherManager,
+) -> Result<&'a Path, FileOpError> {
+    if watchers.is_watched(workspace) {
+        Ok(Path::new(workspace))
+    } else {
+        Err(FileOpError::Other {
+            message: format!("Workspace is not open: {}", workspace),
+        })
+    }
+}
+
+/// Create a file inside `workspace`, failing if it exists unless
+/// `overwrite`. The watcher doesn't report the write back. Returns the path.
+#[tauri::command]
+pub async fn create_file(
+    workspace: String,
+    path: String,
+    content: String,
+    overwrite: bool,
+    watchers: State<'_, WatcherManager>,
+) -> Result<Vec<String>, FileOpError> {
+    let root = open_workspace(&workspace, &watchers)?;
+    let path = crate::files::workspace_path(root, Path::new(&path))?;
+    watchers.suppress_write(&path, content.as_bytes());
+    crate::files::create_file(root, &path, &content, overwrite).await
+}
+
+/// Templates new .http files can be created from: the built-in ones and,
+/// with a workspace, those in its `.kvile/templates`
+#[tauri::command]
+pub async fn list_templates(workspace: Option<String>) -> Result<Vec<TemplateInfo>, String> {
+    tokio::task::spawn_blocking(move || {
+        crate::templates::list_templates(workspace.as_deref().map(Path::new))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Create an .http file inside `workspace` from a template, with the
+/// template's variables filled in from `vars`. Fails if the file exists.
+/// Returns the path.
+#[tauri::command]
+pub async fn create_http_file_from_template(
+    workspace: String,
+    path: String,
+    template_id: String,
+    vars: HashMap<String, String>,
+    watchers: State<'_, WatcherManager>,
+) -> Result<Vec<String>, FileOpError> {
+    let path =
+        crate::files::workspace_path(open_workspace(&workspace, &watchers)?, Path::new(&path))?;
+    let root = PathBuf::from(&workspace);
+    let content = tokio::task::spawn_blocking(move || {
+        crate::templates::render_template(&root, &template_id, &vars)
+    })
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|content| content)
+    .map_err(|message| FileOpError::Other { message })?;
+    watchers.suppress_write(&path, content.as_bytes());
+    crate::files::create_file(Path::new(&workspace), &path, &content, false).await
+}
+
+/// Create a directory inside `workspace`. Returns the path.
+#[tauri::command]
+pub async fn create_directory(
+    workspace: String,
+    path: String,
+    watchers: State<'_, WatcherManager>,
+) -> Result<Vec<String>, FileOpError> {
+    let root = open_workspace(&workspace, &watchers)?;
+    crate::files::create_directory(root, Path::new(&path)).await
+}
+
+/// Rename a file or directory inside `workspace`, failing if `to` exists.
+/// Returns both paths.
+#[tauri::command]
+pub async fn rename_path(
+    workspace: String,
+    from: String,
+    to: String,
+    watchers: State<'_, WatcherManager>,
+) -> Result<Vec<String>, FileOpError> {
+    let root = open_workspace(&workspace, &watchers)?;
+    crate::files::rename_path(root, Path::new(&from), Path::new(&to)).await
+}
+
+/// Delete a file or directory inside `workspace`, to the system trash unless
+/// `to_trash` is false. Returns the path.
+#[tauri::command]
+pub async fn delete_path(
+    workspace: String,
+    path: String,
+    to_trash: bool,
+    watchers: State<'_, WatcherManager>,
+) -> Result<Vec<String>, FileOpError> {
+    let root = open_workspace(&workspace, &watchers)?;
+    crate::files::delete_path(root, Path::new(&path), to_trash).await
+}
+
+/// List the .http, .rest and env files in a directory recursively, or with
+/// `include_all` every file, skipping paths its ignore files exclude unless
+/// the workspace's [`crate::history::INCLUDE_IGNORED_KEY`] setting includes
+/// them. The workspace's ignore pattern, depth and symlink settings apply too.
+///
+/// At most `limit` files (default [`DEFAULT_LIST_LIMIT`]) are returned, sorted
+/// by path; pass `next_cursor` back as `cursor` for the rest.
+#[tauri::command]
+pub async fn list_http_files(
+    directory: String,
+    include_all: Option<bool>,
+    limit: Option<usize>,
+    cursor: Option<String>,
+    history_db: State<'_, HistoryDb>,
+) -> Result<FileListing, String> {
+    let workspace = directory.clone();
+    let ignore = history_db
+        .run(move |db| WorkspaceIgnore::for_workspace(db, &workspace))
+        .await;
+    let options = ListOptions {
+        include_all: include_all.unwrap_or(false),
+        limit: Some(limit.unwrap_or(DEFAULT_LIST_LIMIT)),
+        cursor,
+    };
+    list_workspace_files(&directory, &ignore, &options).await
+}
+
+/// Search the workspace's .http and .rest files, skipping the paths
+/// [`list_http_files`] skips, for literal text or with `options.regex` a
+/// regular expression. Each matching line names the request it belongs to.
+///
+/// A search given a `search_id` can be stopped with [`cancel_search`], and is
+/// stopped by a newer search with the same id.
+#[tauri::command]
+pub async fn search_workspace(
+    workspace: String,
+    query: String,
+    options: Option<SearchOptions>,
+    search_id: Option<String>,
+    history_db: State<'_, HistoryDb>,
+) -> Result<SearchResults, String> {
+    let directory = workspace.clone();
+    let ignore = history_db
+        .run(move |db| WorkspaceIgnore::for_workspace(db, &directory))
+        .await;
+    let cancelled = match &search_id {
+        Some(id) => crate::search::register_search(id),
+        None => Default::default(),
+    };
+    let results = crate::search::search_workspace(
+        &workspace,
+        &ignore,
+        &query,
+        &options.unwrap_or_default(),
+        cancelled.clone(),
+    )
+    .await;
+    if let Some(id) = &search_id {
+        crate::search::finish_search(id, &cancelled);
+    }
+    results
+}
+
+/// Stop a running [`search_workspace`]. Returns whether one was running.
+#[tauri::command]
+pub fn cancel_search(search_id: String) -> bool {
+    crate::search::cancel_search(&search_id)
+}
+
+// ===== HISTORY COMMANDS =====
+
+/// Get history entries for a workspace
 #[tauri::command]
-pub async fn list_http_files(directory: String) -> Result<Vec<FileInfo>, String> {
-    let mut files = Vec::new();
-    list_http_files_recursive(Path::new(&directory), &mut files).await?;
-    Ok(files)
+pub async fn get_history(
+    workspace: String,
+    limit: Option<i32>,
+    history_db: State<'_, HistoryDb>,
+) -> Result<Vec<HistoryEntry>, String> {
+    let limit = limit.unwrap_or(100);
+    history_db
+        .run(move |db| db.get_entries(&workspace, limit))
+        .await
+        .map_err(|e| format!("Failed to get history: {}", e))
 }
 
-async fn list_http_files_recursive(dir: &Path, files: &mut Vec<FileInfo>) -> Result<(), String> {
-    let mut entries = tokio::fs::read_dir(dir)
+/// Get a single history entry by ID
+#[tauri::command]
+pub async fn get_history_entry(
+    id: i64,
+    history_db: State<'_, HistoryDb>,
+) -> Result<Option<HistoryEntry>, String> {
+    history_db
+        .run(move |db| db.get_entry(id))
         .await
-        .map_err(|e| format!("Failed to read directory: {}", e))?;
+        .map_err(|e| format!("Failed to get history entry: {}", e))
+}
 
-    while let Some(entry) = entries
-        .next_entry()
+/// Get the request and response bodies of a history entry
+#[tauri::command]
+pub async fn get_history_entry_body(
+    id: i64,
+    history_db: State<'_, HistoryDb>,
+) -> Result<Option<HistoryBodies>, String> {
+    history_db
+        .run(move |db| db.get_entry_body(id))
         .await
-        .map_err(|e| format!("Failed to read entry: {}", e))?
-    {
-        let path = entry.path();
-        let metadata = entry
-            .metadata()
-            .await
-            .map_err(|e| format!("Failed to get metadata: {}", e))?;
+        .map_err(|e| format!("Failed to get history entry body: {}", e))
+}
 
-        if metadata.is_dir() {
-            // Skip hidden directories and common non-relevant directories
-            let name = path.file_name().unwrap_or_default().to_string_lossy();
-            if !name.starts_with('.') && name != "node_modules" && name != "target" {
-                Box::pin(list_http_files_recursive(&path, files)).await?;
-            }
-        } else if metadata.is_file() {
-            let name = path
-                .file_name()
-                .unwrap_or_default()
-                .to_string_lossy()
-                .to_string();
-            let is_http_file = name.ends_with(".http") || name.ends_with(".rest");
-
-            files.push(FileInfo {
-                path: path.to_string_lossy().to_string(),
-                name,
-                is_http_file,
-            });
+/// A response body, or the ID of the history entry whose response body to use
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum BodyOrHistoryId {
+    HistoryId(i64),
+    Body(String),
+}
+
+/// Query a JSON response body with a JSONPath such as `$.items[?(@.id > 3)]`,
+/// as `{{name.response.body.$...}}` references are. Returns every match with
+/// its normalized path; invalid paths fail with the position of the error.
+#[tauri::command]
+pub async fn extract_json(
+    body_or_history_id: BodyOrHistoryId,
+    jsonpath: String,
+    history_db: State<'_, HistoryDb>,
+) -> Result<Vec<JsonMatch>, String> {
+    let body = match body_or_history_id {
+        BodyOrHistoryId::Body(body) => body,
+        BodyOrHistoryId::HistoryId(id) => {
+            history_db
+                .run(move |db| db.get_entry_body(id))
+                .await
+                .map_err(|e| format!("Failed to get history entry body: {}", e))?
+                .ok_or_else(|| format!("History entry {} not found", id))?
+                .response_body
         }
+    };
+    tokio::task::spawn_blocking(move || crate::jsonpath::extract(&body, &jsonpath))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Get history entries for a workspace matching a filter
+#[tauri::command]
+pub async fn get_history_filtered(
+    workspace: String,
+    filter: HistoryFilter,
+    limit: Option<i32>,
+    history_db: State<'_, HistoryDb>,
+) -> Result<Vec<HistoryEntry>, String> {
+    history_db
+        .run(move |db| db.get_entries_filtered(&workspace, &filter, limit.unwrap_or(100)))
+        .await
+        .map_err(|e| format!("Failed to get history: {}", e))
+}
+
+/// Get a page of history entries for a workspace, with the total count
+#[tauri::command]
+pub async fn get_history_page(
+    workspace: String,
+    filter: Option<HistoryFilter>,
+    cursor: Option<HistoryCursor>,
+    limit: Option<i32>,
+    history_db: State<'_, HistoryDb>,
+) -> Result<HistoryPage, String> {
+    history_db
+        .run(move |db| {
+            db.get_page(
+                &workspace,
+                &filter.unwrap_or_default(),
+                cursor.as_ref(),
+                limit.unwrap_or(100),
+            )
+        })
+        .await
+        .map_err(|e| format!("Failed to get history: {}", e))
+}
+
+/// Count the history entries for a workspace matching a filter
+#[tauri::command]
+pub async fn count_history(
+    workspace: String,
+    filter: Option<HistoryFilter>,
+    history_db: State<'_, HistoryDb>,
+) -> Result<i64, String> {
+    history_db
+        .run(move |db| db.count_entries(&workspace, &filter.unwrap_or_default()))
+        .await
+        .map_err(|e| format!("Failed to count history: {}", e))
+}
+
+/// Re-send the request recorded in a history entry and record the result
+/// as a new entry linked to the original
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(request_id = crate::logging::next_request_id(), history_id = id))]
+pub async fn resend_from_history(
+    id: i64,
+    history_db: State<'_, HistoryDb>,
+) -> Result<HistoryEntry, String> {
+    resend(&history_db, id).await
+}
+
+/// Export a workspace's history matching a filter to a JSON or CSV file.
+/// Returns the number of entries written.
+#[tauri::command]
+pub async fn export_history(
+    workspace: String,
+    format: HistoryExportFormat,
+    path: String,
+    filter: Option<HistoryFilter>,
+    body_limit: Option<usize>,
+    history_db: State<'_, HistoryDb>,
+) -> Result<usize, String> {
+    history_db
+        .run(move |db| {
+            db.export_to_file(
+                &workspace,
+                &filter.unwrap_or_default(),
+                format,
+                body_limit.unwrap_or(1024),
+                Path::new(&path),
+            )
+        })
+        .await
+}
+
+/// Rebuild requests from a workspace's history matching a filter as an
+/// .http file, oldest first. With `path` the text is also written there,
+/// replacing any file.
+#[tauri::command]
+pub async fn export_history_as_http(
+    workspace: String,
+    filter: Option<HistoryFilter>,
+    dedupe: Option<bool>,
+    auth_placeholders: Option<bool>,
+    path: Option<String>,
+    history_db: State<'_, HistoryDb>,
+) -> Result<String, String> {
+    let options = HttpExportOptions {
+        dedupe: dedupe.unwrap_or(false),
+        auth_placeholders: auth_placeholders.unwrap_or(false),
+    };
+    let http = history_db
+        .run(move |db| db.export_http(&workspace, &filter.unwrap_or_default(), &options))
+        .await?;
+    if let Some(path) = path {
+        tokio::fs::write(&path, &http)
+            .await
+            .map_err(|e| format!("Failed to write {}: {}", path, e))?;
     }
+    Ok(http)
+}
+
+/// Workspaces opened before, pinned first and then most recent first.
+/// Workspaces whose directory is gone are flagged `missing`.
+#[tauri::command]
+pub async fn get_recent_workspaces(
+    history_db: State<'_, HistoryDb>,
+) -> Result<Vec<RecentWorkspace>, String> {
+    history_db
+        .run(|db| db.get_recent_workspaces())
+        .await
+        .map_err(|e| format!("Failed to get recent workspaces: {}", e))
+}
+
+/// Record that a workspace was opened
+#[tauri::command]
+pub async fn touch_workspace(path: String, history_db: State<'_, HistoryDb>) -> Result<(), String> {
+    history_db
+        .run(move |db| db.touch_workspace(&path))
+        .await
+        .map_err(|e| format!("Failed to record workspace: {}", e))
+}
+
+/// Pin or unpin a recent workspace, so it stays in the list
+#[tauri::command]
+pub async fn pin_workspace(
+    path: String,
+    pinned: bool,
+    history_db: State<'_, HistoryDb>,
+) -> Result<bool, String> {
+    history_db
+        .run(move |db| db.pin_workspace(&path, pinned))
+        .await
+        .map_err(|e| format!("Failed to pin workspace: {}", e))
+}
+
+/// Remove a workspace from the recent list
+#[tauri::command]
+pub async fn remove_recent_workspace(
+    path: String,
+    history_db: State<'_, HistoryDb>,
+) -> Result<bool, String> {
+    history_db
+        .run(move |db| db.remove_recent_workspace(&path))
+        .await
+        .map_err(|e| format!("Failed to remove recent workspace: {}", e))
+}
+
+/// Save a history entry's response body to a file, decoded when it is
+/// binary. With a directory as `dest_path` the file is named after the URL
+/// and Content-Type. Returns the number of bytes written.
+#[tauri::command]
+pub async fn save_response_body(
+    history_id: i64,
+    dest_path: String,
+    overwrite: Option<bool>,
+    history_db: State<'_, HistoryDb>,
+) -> Result<u64, String> {
+    let entry = history_db
+        .run(move |db| db.get_entry(history_id))
+        .await
+        .map_err(|e| format!("Failed to get history entry: {}", e))?
+        .ok_or_else(|| format!("History entry {} not found", history_id))?;
+    tokio::task::spawn_blocking(move || {
+        crate::history::save_response_body(
+            &entry,
+            Path::new(&dest_path),
+            overwrite.unwrap_or(false),
+        )
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
 
+/// The most recent log records at `level` (`error`, `warn`, `info`, `debug`
+/// or `trace`, by default `trace`) or more severe, oldest first
+#[tauri::command]
+pub fn get_recent_logs(
+    level: Option<String>,
+    limit: Option<usize>,
+) -> Result<Vec<LogRecord>, String> {
+    let level = match level {
+        Some(level) => level
+            .parse::<tracing::Level>()
+            .map_err(|_| format!("Unknown log level: {}", level))?,
+        None => tracing::Level::TRACE,
+    };
+    Ok(crate::logging::recent_logs(level, limit.unwrap_or(500)))
+}
+
+/// Open the directory the log files are written to in the file manager.
+/// Returns its path.
+#[tauri::command]
+pub fn open_log_directory() -> Result<String, String> {
+    let dir = crate::logging::log_dir();
+    std::fs::create_dir_all(&dir).map_err(|e| format!("Failed to create log directory: {}", e))?;
+    let program = if cfg!(target_os = "macos") {
+        "open"
+    } else if cfg!(target_os = "windows") {
+        "explorer"
+    } else {
+        "xdg-open"
+    };
+    std::process::Command::new(program)
+        .arg(&dir)
+        .spawn()
+        .map_err(|e| format!("Failed to open {}: {}", dir.display(), e))?;
+    Ok(dir.to_string_lossy().to_string())
+}
+
+/// Back up the history database to a standalone file, safe while the app runs
+#[tauri::command]
+pub async fn export_history_db(
+    dest_path: String,
+    history_db: State<'_, HistoryDb>,
+) -> Result<(), String> {
+    history_db
+        .run(move |db| db.export_db(Path::new(&dest_path)))
+        .await
+        .map_err(|e| format!("Failed to export history database: {}", e))
+}
+
+/// Restore history from a backup, merging with or replacing existing history
+#[tauri::command]
+pub async fn import_history_db(
+    src_path: String,
+    mode: HistoryImportMode,
+    history_db: State<'_, HistoryDb>,
+) -> Result<HistoryImportReport, String> {
+    history_db
+        .run(move |db| db.import_db(Path::new(&src_path), mode))
+        .await
+        .map_err(|e| format!("Failed to import history database: {}", e))
+}
+
+/// Get the history entry count, database size and oldest entry
+#[tauri::command]
+pub async fn get_history_stats(history_db: State<'_, HistoryDb>) -> Result<HistoryStats, String> {
+    history_db
+        .run(|db| db.stats())
+        .await
+        .map_err(|e| format!("Failed to get history stats: {}", e))
+}
+
+/// Get the settings stored for a workspace on this machine, such as
+/// `selected_environment`. Shared settings are in its settings file; see
+/// [`get_workspace_settings`].
+#[tauri::command]
+pub async fn get_local_workspace_settings(
+    workspace: String,
+    history_db: State<'_, HistoryDb>,
+) -> Result<HashMap<String, String>, String> {
+    history_db
+        .run(move |db| db.get_workspace_settings(&workspace))
+        .await
+        .map_err(|e| format!("Failed to get workspace settings: {}", e))
+}
+
+/// Store a setting for a workspace on this machine; `None` removes it
+#[tauri::command]
+pub async fn set_local_workspace_setting(
+    workspace: String,
+    key: String,
+    value: Option<String>,
+    history_db: State<'_, HistoryDb>,
+) -> Result<(), String> {
+    history_db
+        .run(move |db| db.set_workspace_setting(&workspace, &key, value.as_deref()))
+        .await
+        .map_err(|e| format!("Failed to save workspace setting: {}", e))
+}
+
+/// Load a workspace's settings file, kvile.json. A missing file gives the
+/// defaults; values of the wrong type are left out and reported in
+/// `warnings`.
+#[tauri::command]
+pub async fn get_workspace_settings(workspace: String) -> Result<LoadedSettings, String> {
+    tokio::task::spawn_blocking(move || WorkspaceSettings::load(Path::new(&workspace)))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Replace a workspace's settings in its settings file, keeping keys kvile
+/// doesn't know
+#[tauri::command]
+pub async fn update_workspace_settings(
+    workspace: String,
+    settings: WorkspaceSettings,
+) -> Result<(), String> {
+    tokio::task::spawn_blocking(move || settings.save(Path::new(&workspace)))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Allow or forbid `$exec` env values to run commands in a workspace. The
+/// choice is kept across restarts.
+#[tauri::command]
+pub async fn set_exec_trust(
+    workspace: String,
+    trusted: bool,
+    history_db: State<'_, HistoryDb>,
+) -> Result<(), String> {
+    let key = workspace.clone();
+    history_db
+        .run(move |db| db.set_workspace_setting(&key, EXEC_TRUSTED_KEY, trusted.then_some("true")))
+        .await
+        .map_err(|e| format!("Failed to save workspace setting: {}", e))?;
+    crate::exec::set_trusted(&workspace, trusted);
     Ok(())
 }
 
-// ===== HISTORY COMMANDS =====
+/// Get the history retention settings
+#[tauri::command]
+pub async fn get_history_retention(
+    history_db: State<'_, HistoryDb>,
+) -> Result<RetentionSettings, String> {
+    history_db
+        .run(|db| db.get_retention())
+        .await
+        .map_err(|e| format!("Failed to get retention settings: {}", e))
+}
+
+/// Save the history retention settings
+#[tauri::command]
+pub async fn set_history_retention(
+    settings: RetentionSettings,
+    history_db: State<'_, HistoryDb>,
+) -> Result<(), String> {
+    history_db
+        .run(move |db| db.set_retention(&settings))
+        .await
+        .map_err(|e| format!("Failed to save retention settings: {}", e))
+}
+
+/// Prune history according to the retention settings right away
+#[tauri::command]
+pub async fn run_prune_now(history_db: State<'_, HistoryDb>) -> Result<PruneReport, String> {
+    history_db
+        .run(|db| db.prune_now())
+        .await
+        .map_err(|e| format!("Failed to prune history: {}", e))
+}
+
+/// Why history is kept in memory instead of on disk, if the database file
+/// could not be opened at startup
+#[tauri::command]
+pub fn get_history_db_warning(history_db: State<'_, HistoryDb>) -> Option<String> {
+    history_db.warning()
+}
+
+/// Back up the history database file and recreate it empty. Returns the
+/// backup path, if there was a file to back up.
+#[tauri::command]
+pub async fn repair_history_db(history_db: State<'_, HistoryDb>) -> Result<Option<String>, String> {
+    history_db
+        .run(|db| db.repair())
+        .await
+        .map(|backup| backup.map(|path| path.to_string_lossy().to_string()))
+        .map_err(|e| format!("Failed to repair history database: {}", e))
+}
+
+/// Get per-endpoint counts, error rates, durations and sizes over a period
+#[tauri::command]
+pub async fn get_endpoint_stats(
+    workspace: String,
+    period: StatsPeriod,
+    normalize: Option<bool>,
+    history_db: State<'_, HistoryDb>,
+) -> Result<Vec<EndpointStats>, String> {
+    history_db
+        .run(move |db| db.endpoint_stats(&workspace, period, normalize.unwrap_or(true)))
+        .await
+        .map_err(|e| format!("Failed to get endpoint stats: {}", e))
+}
+
+/// Get the newest result of each request sent from a file (or from any file
+/// when `file_path` is omitted)
+#[tauri::command]
+pub async fn get_latest_per_request(
+    workspace: String,
+    file_path: Option<String>,
+    history_db: State<'_, HistoryDb>,
+) -> Result<Vec<LatestResult>, String> {
+    history_db
+        .run(move |db| db.latest_per_request(&workspace, file_path.as_deref()))
+        .await
+        .map_err(|e| format!("Failed to get latest results: {}", e))
+}
+
+/// Tag a history entry; returns false if it already had the tag
+#[tauri::command]
+pub async fn add_history_tag(
+    id: i64,
+    tag: String,
+    history_db: State<'_, HistoryDb>,
+) -> Result<bool, String> {
+    let tag = tag.trim().to_string();
+    if tag.is_empty() {
+        return Err("Tag cannot be empty".to_string());
+    }
+    history_db
+        .run(move |db| db.add_tag(id, &tag))
+        .await
+        .map_err(|e| format!("Failed to add tag: {}", e))
+}
+
+/// Remove a tag from a history entry
+#[tauri::command]
+pub async fn remove_history_tag(
+    id: i64,
+    tag: String,
+    history_db: State<'_, HistoryDb>,
+) -> Result<bool, String> {
+    history_db
+        .run(move |db| db.remove_tag(id, tag.trim()))
+        .await
+        .map_err(|e| format!("Failed to remove tag: {}", e))
+}
+
+/// Get the tags of a history entry
+#[tauri::command]
+pub async fn get_history_entry_tags(
+    id: i64,
+    history_db: State<'_, HistoryDb>,
+) -> Result<Vec<String>, String> {
+    history_db
+        .run(move |db| db.entry_tags(id))
+        .await
+        .map_err(|e| format!("Failed to get tags: {}", e))
+}
+
+/// List the tags used in a workspace with their counts, for autocomplete
+#[tauri::command]
+pub async fn list_history_tags(
+    workspace: String,
+    history_db: State<'_, HistoryDb>,
+) -> Result<Vec<TagCount>, String> {
+    history_db
+        .run(move |db| db.list_tags(&workspace))
+        .await
+        .map_err(|e| format!("Failed to list tags: {}", e))
+}
 
-/// Get history entries for a workspace
+/// Search a workspace's history; supports `method:` and `status:` prefixes
 #[tauri::command]
-pub async fn get_history(
+pub async fn search_history(
     workspace: String,
+    query: String,
     limit: Option<i32>,
+    include_bodies: Option<bool>,
     history_db: State<'_, HistoryDb>,
 ) -> Result<Vec<HistoryEntry>, String> {
-    let limit = limit.unwrap_or(100);
     history_db
-        .get_entries(&workspace, limit)
-        .map_err(|e| format!("Failed to get history: {}", e))
+        .run(move |db| {
+            db.search(
+                &workspace,
+                &query,
+                limit.unwrap_or(100),
+                include_bodies.unwrap_or(false),
+            )
+        })
+        .await
+        .map_err(|e| format!("Failed to search history: {}", e))
 }
 
-/// Get a single history entry by ID
+/// Full-text search a workspace's request and response bodies; matches in the
+/// returned snippets are wrapped in `<mark>` tags
 #[tauri::command]
-pub async fn get_history_entry(
-    id: i64,
+pub async fn search_history_bodies(
+    workspace: String,
+    query: String,
+    limit: Option<i32>,
     history_db: State<'_, HistoryDb>,
-) -> Result<Option<HistoryEntry>, String> {
+) -> Result<Vec<BodySearchHit>, String> {
     history_db
-        .get_entry(id)
-        .map_err(|e| format!("Failed to get history entry: {}", e))
+        .run(move |db| db.search_bodies(&workspace, &query, limit.unwrap_or(100)))
+        .await
+        .map_err(|e| format!("Failed to search history bodies: {}", e))
 }
 
-/// Add a new history entry
+/// Add a new history entry. `send_request` records history itself when given
+/// a context; this remains for entries recorded elsewhere.
+///
+/// `variables` are the values the request was resolved with; they are stored
+/// as the entry's snapshot with private env file values masked.
 #[tauri::command]
 pub async fn add_history_entry(
-    entry: NewHistoryEntry,
+    mut entry: NewHistoryEntry,
+    variables: Option<HashMap<String, String>>,
     history_db: State<'_, HistoryDb>,
 ) -> Result<i64, String> {
+    if let Some(variables) = variables {
+        entry.variables_snapshot = snapshot_variables(
+            &entry.workspace,
+            entry.file_path.as_deref(),
+            entry.environment.as_deref(),
+            None,
+            &variables,
+        )
+        .await;
+    }
     history_db
-        .add_entry(entry)
+        .run(move |db| db.add_entry(entry))
+        .await
         .map_err(|e| format!("Failed to add history entry: {}", e))
 }
 
@@ -133,7 +986,8 @@ pub async fn delete_history_entry(
     history_db: State<'_, HistoryDb>,
 ) -> Result<bool, String> {
     history_db
-        .delete_entry(id)
+        .run(move |db| db.delete_entry(id))
+        .await
         .map_err(|e| format!("Failed to delete history entry: {}", e))
 }
 
@@ -144,27 +998,573 @@ pub async fn clear_history(
     history_db: State<'_, HistoryDb>,
 ) -> Result<usize, String> {
     history_db
-        .clear_workspace(&workspace)
+        .run(move |db| db.clear_workspace(&workspace))
+        .await
         .map_err(|e| format!("Failed to clear history: {}", e))
 }
 
+/// Delete a workspace's history from before a timestamp (exclusive), keeping
+/// entries tagged `pinned`. Returns the number of entries deleted.
+#[tauri::command]
+pub async fn delete_history_before(
+    workspace: String,
+    timestamp: DateTime<Utc>,
+    history_db: State<'_, HistoryDb>,
+) -> Result<usize, String> {
+    history_db
+        .run(move |db| db.delete_before(&workspace, timestamp))
+        .await
+        .map_err(|e| format!("Failed to delete old history: {}", e))
+}
+
 // ===== IMPORT COMMANDS =====
 
-/// Convert a cURL command to HTTP file format
+/// Convert a cURL command to HTTP file format, with warnings for skipped flags
+///
+/// `base_dir` resolves `@file` references such as `-H @headers.txt`.
+#[tauri::command]
+pub async fn convert_curl_to_http(
+    curl_command: String,
+    base_dir: Option<String>,
+) -> Result<ImportResult, String> {
+    crate::import::curl::import_curl(&curl_command, base_dir.as_deref().map(Path::new))
+}
+
+/// Convert text containing several cURL commands into one HTTP file
+#[tauri::command]
+pub async fn convert_curls_to_http(
+    text: String,
+    base_dir: Option<String>,
+) -> Result<String, String> {
+    Ok(crate::import::curl::curls_to_http(
+        &text,
+        base_dir.as_deref().map(Path::new),
+    ))
+}
+
+/// Convert a browser "Copy as fetch" snippet to HTTP file format
+#[tauri::command]
+pub async fn convert_fetch_to_http(snippet: String) -> Result<String, String> {
+    crate::import::fetch::fetch_to_http(&snippet)
+}
+
+/// Convert an Invoke-RestMethod / Invoke-WebRequest command to HTTP file format
+#[tauri::command]
+pub async fn convert_powershell_to_http(command: String) -> Result<ImportResult, String> {
+    crate::import::powershell::powershell_to_http(&command)
+}
+
+/// Convert a wget command to HTTP file format
+#[tauri::command]
+pub async fn convert_wget_to_http(command: String) -> Result<ImportResult, String> {
+    crate::import::wget::wget_to_http(&command)
+}
+
+/// Convert a raw HTTP request message (from logs or a proxy) to HTTP file format
+#[tauri::command]
+pub fn import_raw_http(text: String, use_http: Option<bool>) -> Result<String, String> {
+    crate::import::raw::raw_http_to_http(&text, use_http.unwrap_or(false))
+}
+
+/// Import a Postman v2.1 collection into .http files under `output_dir`
+#[tauri::command]
+pub async fn import_postman_collection(
+    json: String,
+    output_dir: String,
+) -> Result<ImportSummary, String> {
+    let converted = crate::import::postman::postman_to_http(&json)?;
+    crate::import::write_collection(Path::new(&output_dir), converted).await
+}
+
+/// Import a Thunder Client collection or environment export into `output_dir`
+#[tauri::command]
+pub async fn import_thunder_client(
+    json: String,
+    output_dir: String,
+) -> Result<ImportSummary, String> {
+    let converted = crate::import::thunder::thunder_to_http(&json)?;
+    crate::import::write_collection(Path::new(&output_dir), converted).await
+}
+
+/// Outcome of merging a Postman environment into the workspace env files
+#[derive(Debug, Serialize)]
+pub struct PostmanEnvironmentImport {
+    pub environment: String,
+    /// Changes to http-client.env.json
+    pub public: EnvMergeDiff,
+    /// Changes to http-client.private.env.json (secret values)
+    pub private: EnvMergeDiff,
+    pub dry_run: bool,
+}
+
+/// Merge a Postman environment into http-client.env.json, with secret values
+/// going to http-client.private.env.json. With `dry_run` only the diff is returned.
+#[tauri::command]
+pub async fn import_postman_environment(
+    workspace: String,
+    json: String,
+    environment_name: Option<String>,
+    dry_run: Option<bool>,
+) -> Result<PostmanEnvironmentImport, String> {
+    let parsed = crate::import::postman::parse_postman_environment(&json)?;
+    let environment = environment_name.unwrap_or(parsed.name);
+    let dry_run = dry_run.unwrap_or(false);
+    let workspace = Path::new(&workspace);
+
+    let public = merge_env_file(
+        &workspace.join("http-client.env.json"),
+        &environment,
+        parsed.variables,
+        dry_run,
+    )
+    .await?;
+    let private = if parsed.secrets.is_empty() {
+        EnvMergeDiff::default()
+    } else {
+        merge_env_file(
+            &workspace.join("http-client.private.env.json"),
+            &environment,
+            parsed.secrets,
+            dry_run,
+        )
+        .await?
+    };
+
+    Ok(PostmanEnvironmentImport {
+        environment,
+        public,
+        private,
+        dry_run,
+    })
+}
+
+/// Import an Insomnia v4 export (JSON or YAML) into .http files under `output_dir`
+#[tauri::command]
+pub async fn import_insomnia_export(
+    content: String,
+    output_dir: String,
+) -> Result<ImportSummary, String> {
+    let converted = crate::import::insomnia::insomnia_to_http(&content)?;
+    crate::import::write_collection(Path::new(&output_dir), converted).await
+}
+
+/// Generate .http files from an OpenAPI 3.x or Swagger 2.0 spec.
+///
+/// `path_or_content` is either a path to the spec or the spec itself.
+#[tauri::command]
+pub async fn import_openapi(
+    path_or_content: String,
+    output_dir: String,
+    base_url_var: Option<String>,
+) -> Result<ImportSummary, String> {
+    let content = if Path::new(&path_or_content).is_file() {
+        tokio::fs::read_to_string(&path_or_content)
+            .await
+            .map_err(|e| format!("Failed to read spec: {}", e))?
+    } else {
+        path_or_content
+    };
+    let base_url_var = base_url_var.unwrap_or_else(|| "baseUrl".to_string());
+    let converted = crate::import::openapi::openapi_to_http(&content, &base_url_var)?;
+    crate::import::write_collection(Path::new(&output_dir), converted).await
+}
+
+/// Convert a HAR capture to .http requests; large or binary bodies are
+/// written to `har-assets` next to where the .http file will be saved
+#[tauri::command]
+pub async fn import_har(
+    path: String,
+    options: Option<HarImportOptions>,
+) -> Result<HarImportResult, String> {
+    use crate::import::har::{har_to_http, ASSET_DIR};
+
+    let options = options.unwrap_or_default();
+    let content = tokio::fs::read_to_string(&path)
+        .await
+        .map_err(|e| format!("Failed to read HAR file: {}", e))?;
+    let output_dir = match &options.output_dir {
+        Some(dir) => PathBuf::from(dir),
+        None => Path::new(&path)
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default(),
+    };
+    let asset_dir = output_dir.join(ASSET_DIR);
+
+    // Continue numbering after assets from earlier imports
+    let mut first_asset = 1;
+    if let Ok(mut entries) = tokio::fs::read_dir(&asset_dir).await {
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            if let Ok(n) = entry.file_name().to_string_lossy().parse::<usize>() {
+                first_asset = first_asset.max(n + 1);
+            }
+        }
+    }
+
+    let conversion = har_to_http(&content, &options, first_asset)?;
+    let mut assets = Vec::new();
+    if !conversion.assets.is_empty() {
+        tokio::fs::create_dir_all(&asset_dir)
+            .await
+            .map_err(|e| format!("Failed to create directory: {}", e))?;
+    }
+    for (name, bytes) in conversion.assets {
+        let asset = asset_dir.join(name);
+        tokio::fs::write(&asset, bytes)
+            .await
+            .map_err(|e| format!("Failed to write file: {}", e))?;
+        assets.push(asset.to_string_lossy().to_string());
+    }
+
+    Ok(HarImportResult {
+        http: conversion.http,
+        skipped: conversion.skipped,
+        warnings: conversion.warnings,
+        assets,
+    })
+}
+
+/// Export an environment, with `$shared` folded in, as a Postman environment (JSON)
+#[tauri::command]
+pub async fn export_postman_environment(
+    workspace: String,
+    environment_name: String,
+) -> Result<String, String> {
+    let mut config = crate::env::load_environment_config(workspace.clone(), None, None).await?;
+    // Every value is exported, so every command the environment uses runs
+    let names: Vec<String> =
+        crate::variables::environment_variables(&config, Some(&environment_name))?
+            .into_keys()
+            .collect();
+    crate::env::run_commands(&workspace, &mut config, Some(&environment_name), names).await;
+    let environment = crate::import::postman::environment_to_postman(&config, &environment_name)?;
+    serde_json::to_string_pretty(&environment)
+        .map_err(|e| format!("Failed to serialize environment: {}", e))
+}
+
+/// Export history entries as a HAR 1.2 document, oldest first
+#[tauri::command]
+pub async fn export_har(
+    entry_ids: Vec<i64>,
+    history_db: State<'_, HistoryDb>,
+) -> Result<String, String> {
+    let entries = history_db
+        .run(move |db| {
+            entry_ids
+                .into_iter()
+                .map(|id| {
+                    db.get_entry(id)
+                        .map_err(|e| format!("Failed to get history entry: {}", e))?
+                        .ok_or_else(|| format!("History entry {} not found", id))
+                })
+                .collect::<Result<Vec<_>, String>>()
+        })
+        .await?;
+
+    let har = crate::import::har::entries_to_har(&entries);
+    serde_json::to_string_pretty(&har).map_err(|e| format!("Failed to serialize HAR: {}", e))
+}
+
+/// Export an .http file as a bash script of curl commands
+///
+/// Variables come from `environment` in `workspace`; with `secrets_as_env`,
+/// private env values are left as `$VAR` references for the caller to set.
+#[tauri::command]
+pub async fn export_http_as_script(
+    content: String,
+    environment: Option<String>,
+    workspace: Option<String>,
+    secrets_as_env: Option<bool>,
+) -> Result<String, String> {
+    use crate::variables::environment_variables;
+
+    let requests =
+        parse_http_content(&content).map_err(|e| format!("Failed to parse HTTP file: {}", e))?;
+
+    let (vars, secrets) = match workspace {
+        Some(workspace) => {
+            let mut config =
+                crate::env::load_environment_config(workspace.clone(), None, None).await?;
+            let inputs: Vec<&str> = requests
+                .iter()
+                .flat_map(|request| {
+                    std::iter::once(request.url.as_str())
+                        .chain(request.headers.values().map(String::as_str))
+                        .chain(request.body.as_deref())
+                        .chain(request.variables.values().map(String::as_str))
+                })
+                .collect();
+            crate::env::run_referenced_commands(
+                &workspace,
+                &mut config,
+                environment.as_deref(),
+                &inputs,
+            )
+            .await;
+            (
+                environment_variables(&config, environment.as_deref())?,
+                secret_names(&config, environment.as_deref()),
+            )
+        }
+        None => Default::default(),
+    };
+
+    Ok(crate::import::curl::requests_to_script(
+        &requests,
+        &vars,
+        &secrets,
+        secrets_as_env.unwrap_or(false),
+    ))
+}
+
+/// The .http content to take a request from, or a request already parsed
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RequestSource {
+    Content(String),
+    /// An .http file, which also locates the env files unless `file_path`
+    /// is given
+    Path(String),
+    Parsed(Box<ParsedRequest>),
+}
+
+/// A request in an .http file, by index or name
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum RequestSelector {
+    Index(usize),
+    Name(String),
+}
+
+/// The arguments of [`resolve_request`], which [`send_request`] also takes
+/// for requests it resolves itself
+#[derive(Debug, Clone, Deserialize)]
+pub struct ResolveArgs {
+    pub source: RequestSource,
+    /// The first request when not given
+    #[serde(default)]
+    pub request: Option<RequestSelector>,
+    #[serde(default)]
+    pub environment: Option<String>,
+    #[serde(default)]
+    pub workspace: Option<String>,
+    #[serde(default)]
+    pub prompt_answers: HashMap<String, String>,
+    #[serde(default)]
+    pub file_path: Option<String>,
+    #[serde(default)]
+    pub variables: HashMap<String, String>,
+    #[serde(default)]
+    pub globals: HashMap<String, String>,
+    #[serde(default)]
+    pub process_env: bool,
+    #[serde(default)]
+    pub env_glob: Option<String>,
+}
+
+/// Substitute variables in a request and report where each value came from
+/// and which names are undefined
+///
+/// `source` is .http content or a file, with `request` picking one of its
+/// requests, or a request already parsed. Values are looked up, highest
+/// precedence first, in `prompt_answers`, the runtime `variables` (such as
+/// those a pre-request script set), `globals` from `client.global.set`, the
+/// file's `@name = value` variables, the environment, `$shared` values and,
+/// with `process_env`, the OS environment, so CI can supply e.g.
+/// `{{API_TOKEN}}` without an env file. A variable the request declares with
+/// `# @prompt` comes only from `prompt_answers`. Dynamic variables such as
+/// `{{$uuid}}` are generated.
+///
+/// `env_glob` selects the env files merged across the workspace. Without an
+/// `environment`, the one last selected in the workspace is used.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn resolve_request(
+    source: RequestSource,
+    request: Option<RequestSelector>,
+    environment: Option<String>,
+    workspace: Option<String>,
+    prompt_answers: Option<HashMap<String, String>>,
+    file_path: Option<String>,
+    variables: Option<HashMap<String, String>>,
+    globals: Option<HashMap<String, String>>,
+    process_env: Option<bool>,
+    env_glob: Option<String>,
+    history_db: State<'_, HistoryDb>,
+) -> Result<ResolvedRequest, String> {
+    let args = ResolveArgs {
+        source,
+        request,
+        environment,
+        workspace,
+        prompt_answers: prompt_answers.unwrap_or_default(),
+        file_path,
+        variables: variables.unwrap_or_default(),
+        globals: globals.unwrap_or_default(),
+        process_env: process_env.unwrap_or(false),
+        env_glob,
+    };
+    resolve(&history_db, args).await
+}
+
+/// Resolve a request for [`resolve_request`] and [`send_request`]
+async fn resolve(history_db: &HistoryDb, args: ResolveArgs) -> Result<ResolvedRequest, String> {
+    // Answers are often passwords or one-time codes, and end up in the URL
+    // and body that get logged
+    for answer in args.prompt_answers.values() {
+        crate::logging::add_secret(answer);
+    }
+    let (request, file_path) = match args.source {
+        RequestSource::Parsed(request) => (*request, args.file_path),
+        RequestSource::Content(content) => (
+            select_request(&content, args.request.as_ref())?,
+            args.file_path,
+        ),
+        RequestSource::Path(path) => {
+            let content = tokio::fs::read_to_string(&path)
+                .await
+                .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+            let request = select_request(&content, args.request.as_ref())?;
+            (request, args.file_path.or(Some(path)))
+        }
+    };
+    let environment = match (args.environment, &args.workspace) {
+        (None, Some(workspace)) => selected_environment(history_db, workspace).await,
+        (environment, _) => environment,
+    };
+    let options = ResolveOptions {
+        prompt_answers: args.prompt_answers,
+        variables: args.variables,
+        globals: args.globals,
+        process_env: args.process_env,
+    };
+    match args.workspace {
+        Some(workspace) => {
+            let mut config =
+                crate::env::load_environment_config(workspace.clone(), file_path, args.env_glob)
+                    .await?;
+            crate::variables::resolve_request_with_commands(
+                &workspace,
+                &request,
+                &mut config,
+                environment.as_deref(),
+                &options,
+            )
+            .await
+        }
+        None => crate::variables::resolve_request(&request, None, None, &options),
+    }
+}
+
+/// The request `selector` picks from .http content, the first by default
+fn select_request(
+    content: &str,
+    selector: Option<&RequestSelector>,
+) -> Result<ParsedRequest, String> {
+    let mut requests =
+        parse_http_content(content).map_err(|e| format!("Failed to parse HTTP file: {}", e))?;
+    let index = match selector {
+        None => 0,
+        Some(RequestSelector::Index(index)) => *index,
+        Some(RequestSelector::Name(name)) => requests
+            .iter()
+            .position(|r| r.name.as_deref() == Some(name.as_str()))
+            .ok_or_else(|| format!("No request named '{}'", name))?,
+    };
+    if index >= requests.len() {
+        return Err(format!(
+            "No request {} in a file of {}",
+            index,
+            requests.len()
+        ));
+    }
+    Ok(requests.swap_remove(index))
+}
+
+/// Send the request named `request_name` in `file` once per environment and
+/// diff the responses of every pair
+///
+/// `ignore_headers` and `ignore_paths` leave out values expected to differ,
+/// such as hostnames: header names, and JSON paths like `$.links[*].href`.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn compare_across_environments(
+    workspace: String,
+    file: String,
+    request_name: String,
+    environments: Vec<String>,
+    ignore_headers: Option<Vec<String>>,
+    ignore_paths: Option<Vec<String>>,
+    env_glob: Option<String>,
+) -> Result<EnvironmentComparison, String> {
+    if environments.len() < 2 {
+        return Err("Choose at least two environments to compare".to_string());
+    }
+    let content = tokio::fs::read_to_string(&file)
+        .await
+        .map_err(|e| format!("Failed to read {}: {}", file, e))?;
+    let request = select_request(&content, Some(&RequestSelector::Name(request_name)))?;
+    if let Some(auth) = request.metadata.get("auth") {
+        if crate::auth::oidc_config_name(auth).is_some() {
+            return Err(
+                "Requests with OIDC auth can't be compared across environments".to_string(),
+            );
+        }
+    }
+    let settings = WorkspaceSettings::load(Path::new(&workspace)).settings;
+    let config =
+        crate::env::load_environment_config(workspace.clone(), Some(file), env_glob).await?;
+    let ignore = DiffIgnore {
+        headers: ignore_headers.unwrap_or_default(),
+        json_paths: ignore_paths.unwrap_or_default(),
+    };
+    Ok(crate::compare::compare_across_environments(
+        &workspace,
+        &request,
+        &config,
+        &environments,
+        &settings,
+        &ignore,
+    )
+    .await)
+}
+
+/// Export .http files as a Postman v2.1 collection, one folder per file
 #[tauri::command]
-pub async fn convert_curl_to_http(curl_command: String) -> Result<String, String> {
-    use crate::curl::{curl_to_http, parse_curl};
+pub async fn export_postman_collection(files: Vec<String>, name: String) -> Result<String, String> {
+    let mut folders = Vec::new();
+    for file in files {
+        let content = tokio::fs::read_to_string(&file)
+            .await
+            .map_err(|e| format!("Failed to read file: {}", e))?;
+        let requests =
+            parse_http_content(&content).map_err(|e| format!("Failed to parse {}: {}", file, e))?;
+        let folder = Path::new(&file)
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or(file);
+        folders.push((folder, requests));
+    }
 
-    let cmd = parse_curl(&curl_command)?;
-    Ok(curl_to_http(&cmd))
+    let collection = crate::import::postman::http_to_postman(&name, &folders);
+    serde_json::to_string_pretty(&collection)
+        .map_err(|e| format!("Failed to serialize collection: {}", e))
 }
 
 // ===== OIDC COMMANDS =====
 
 use crate::oidc::{
-    build_auth_url, exchange_code_for_tokens, fetch_discovery, generate_pkce, generate_state,
-    refresh_access_token, start_callback_server, OidcConfig, OidcDiscovery, TokenResponse,
+    bind_callback_listener, build_auth_url, build_logout_url, cancel_callback,
+    client_credentials_grant, exchange_code_for_tokens, expect_deep_link, fetch_discovery,
+    generate_nonce, generate_pkce, generate_state, hold_listener, introspect_token,
+    is_deep_link_redirect, password_grant, pkce_method, refresh_access_token,
+    register_callback_cancel, release_listeners_for, take_listener, token_exchange,
+    validate_id_token_nonce, wait_for_callback, wait_for_deep_link, CallbackOutcome, OidcConfig,
+    OidcDiscovery, PkceMethod, TokenIntrospection, TokenResponse, ACCESS_TOKEN_TYPE,
+    DEFAULT_CALLBACK_TIMEOUT, LOGGED_OUT_HTML,
 };
+use crate::tokens::{CachedToken, TokenStore};
+use url::Url;
 
 /// OIDC Discovery - fetch the openid-configuration document
 #[tauri::command]
@@ -177,11 +1577,26 @@ pub async fn oidc_discover(issuer: String) -> Result<OidcDiscovery, String> {
 pub struct OidcAuthStartResult {
     pub auth_url: String,
     pub state: String,
-    pub code_verifier: String,
+    /// Nonce sent with the auth request, to check against the ID token
+    pub nonce: String,
+    /// PKCE verifier for the code exchange; None when PKCE is off
+    pub code_verifier: Option<String>,
+    /// PKCE method the auth request used
+    pub pkce_method: PkceMethod,
+    /// Redirect URL with the callback server's actual port, to use for the
+    /// code exchange
+    pub redirect_url: String,
 }
 
+/// With a `workspace`, parts of the callback page the config leaves unset
+/// come from its settings. A `kvile://` redirect URL is received as a deep
+/// link instead of by a loopback server.
 #[tauri::command]
-pub async fn oidc_start_auth(config: OidcConfig) -> Result<OidcAuthStartResult, String> {
+pub async fn oidc_start_auth(
+    config: OidcConfig,
+    workspace: Option<String>,
+    history_db: State<'_, HistoryDb>,
+) -> Result<OidcAuthStartResult, String> {
     // Fetch discovery if issuer is provided
     let discovery = if let Some(ref issuer) = config.issuer {
         Some(fetch_discovery(issuer).await?)
@@ -189,27 +1604,100 @@ pub async fn oidc_start_auth(config: OidcConfig) -> Result<OidcAuthStartResult,
         None
     };
 
-    // Generate PKCE and state
-    let pkce = generate_pkce();
+    // Generate PKCE, state and nonce
+    let pkce_method = pkce_method(&config, discovery.as_ref());
+    let pkce = generate_pkce(pkce_method);
     let state = generate_state();
+    let nonce = generate_nonce();
+
+    if is_deep_link_redirect(&config.redirect_url) {
+        expect_deep_link(&state, &config.redirect_url)?;
+        let auth_url = build_auth_url(&config, discovery.as_ref(), &state, &nonce, pkce.as_ref())?;
+        return Ok(OidcAuthStartResult {
+            auth_url,
+            state,
+            nonce,
+            code_verifier: pkce.map(|pkce| pkce.code_verifier),
+            pkce_method,
+            redirect_url: config.redirect_url,
+        });
+    }
+
+    // Bind the callback server now, so a random port is known for the
+    // redirect URI and a fixed one is held until the callback arrives
+    release_listeners_for(&config.redirect_url);
+    let mut listener = bind_callback_listener(&config.redirect_url, config.use_random_port).await?;
+    listener.page = config.callback_page.clone();
+    if let Some(workspace) = workspace {
+        let settings = history_db
+            .run(move |db| db.get_workspace_settings(&workspace))
+            .await
+            .map_err(|e| format!("Failed to load workspace settings: {}", e))?;
+        let page = &mut listener.page;
+        page.success_html = page
+            .success_html
+            .take()
+            .or_else(|| settings.get(CALLBACK_SUCCESS_HTML_KEY).cloned());
+        page.error_html = page
+            .error_html
+            .take()
+            .or_else(|| settings.get(CALLBACK_ERROR_HTML_KEY).cloned());
+        page.auto_close |=
+            settings.get(CALLBACK_AUTO_CLOSE_KEY).map(String::as_str) == Some("true");
+    }
+    let config = OidcConfig {
+        redirect_url: listener.redirect_url.clone(),
+        ..config
+    };
 
     // Build authorization URL
-    let auth_url = build_auth_url(&config, discovery.as_ref(), &state, &pkce)?;
+    let auth_url = build_auth_url(&config, discovery.as_ref(), &state, &nonce, pkce.as_ref())?;
+    hold_listener(&state, listener);
 
     Ok(OidcAuthStartResult {
         auth_url,
         state,
-        code_verifier: pkce.code_verifier,
+        nonce,
+        code_verifier: pkce.map(|pkce| pkce.code_verifier),
+        pkce_method,
+        redirect_url: config.redirect_url,
     })
 }
 
-/// Wait for OIDC callback on localhost
+/// Wait for OIDC callback on localhost, for `timeout_secs` or five minutes.
+/// Uses the callback server `oidc_start_auth` bound for the flow, binding
+/// `redirect_url` only if there is none. For a `kvile://` redirect URL the
+/// callback is waited for as a deep link.
 #[tauri::command]
 pub async fn oidc_wait_for_callback(
     redirect_url: String,
     expected_state: String,
+    timeout_secs: Option<u64>,
 ) -> Result<String, String> {
-    let result = start_callback_server(&redirect_url, &expected_state).await?;
+    let timeout = timeout_secs
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_CALLBACK_TIMEOUT);
+    let outcome = if is_deep_link_redirect(&redirect_url) {
+        let cancel = register_callback_cancel(&expected_state);
+        wait_for_deep_link(&redirect_url, &expected_state, timeout, cancel).await?
+    } else {
+        let listener = match take_listener(&expected_state) {
+            Some(listener) => listener,
+            None => bind_callback_listener(&redirect_url, false).await?,
+        };
+        let cancel = register_callback_cancel(&expected_state);
+        wait_for_callback(listener, &expected_state, timeout, cancel).await?
+    };
+    let result = match outcome {
+        CallbackOutcome::Received(result) => result,
+        CallbackOutcome::TimedOut => {
+            return Err(format!(
+                "Timed out after {}s waiting for the login to complete",
+                timeout.as_secs()
+            ))
+        }
+        CallbackOutcome::Cancelled => return Err("Login cancelled".to_string()),
+    };
 
     if let Some(error) = result.error {
         let desc = result.error_description.unwrap_or_default();
@@ -221,12 +1709,109 @@ pub async fn oidc_wait_for_callback(
         .ok_or_else(|| "No authorization code received".to_string())
 }
 
-/// Exchange authorization code for tokens
+/// Where to send the browser to log out at the IdP
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OidcLogoutResult {
+    pub logout_url: String,
+    pub state: String,
+}
+
+/// Log out of an auth config: forget its cached token and build the IdP's
+/// end-session URL for the frontend to open. Without an `id_token_hint` the
+/// cached token's id token is used. With a loopback
+/// `post_logout_redirect_uri`, a callback server is bound for
+/// `oidc_wait_for_logout`. Providers without an end_session_endpoint fail
+/// after the cached token is cleared.
+#[tauri::command]
+pub async fn oidc_logout(
+    workspace: String,
+    auth_id: String,
+    config: OidcConfig,
+    id_token_hint: Option<String>,
+    post_logout_redirect_uri: Option<String>,
+    token_store: State<'_, TokenStore>,
+) -> Result<OidcLogoutResult, String> {
+    let id_token_hint = token_store.end_session(&workspace, &auth_id, id_token_hint)?;
+
+    let discovery = if let Some(ref issuer) = config.issuer {
+        Some(fetch_discovery(issuer).await?)
+    } else {
+        None
+    };
+    let state = generate_state();
+    let logout_url = build_logout_url(
+        &config,
+        discovery.as_ref(),
+        id_token_hint.as_deref(),
+        post_logout_redirect_uri.as_deref(),
+        &state,
+    )
+    .map_err(|e| format!("{}; only the cached token was cleared", e))?;
+
+    let loopback = post_logout_redirect_uri
+        .as_deref()
+        .and_then(|uri| Url::parse(uri).ok())
+        .is_some_and(|url| matches!(url.host_str(), Some("127.0.0.1" | "localhost" | "[::1]")));
+    if let (true, Some(redirect)) = (loopback, post_logout_redirect_uri) {
+        release_listeners_for(&redirect);
+        let mut listener = bind_callback_listener(&redirect, false).await?;
+        listener.page.success_html = Some(LOGGED_OUT_HTML.to_string());
+        hold_listener(&state, listener);
+    }
+
+    Ok(OidcLogoutResult { logout_url, state })
+}
+
+/// Wait for the IdP to redirect back after `oidc_logout`, for `timeout_secs`
+/// or five minutes
+#[tauri::command]
+pub async fn oidc_wait_for_logout(state: String, timeout_secs: Option<u64>) -> Result<(), String> {
+    let listener = take_listener(&state).ok_or("No post-logout redirect is being waited for")?;
+    let timeout = timeout_secs
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_CALLBACK_TIMEOUT);
+    let cancel = register_callback_cancel(&state);
+    match wait_for_callback(listener, &state, timeout, cancel).await? {
+        CallbackOutcome::Received(_) => Ok(()),
+        CallbackOutcome::TimedOut => Err(format!(
+            "Timed out after {}s waiting for the logout to complete",
+            timeout.as_secs()
+        )),
+        CallbackOutcome::Cancelled => Err("Logout cancelled".to_string()),
+    }
+}
+
+/// Ask the IdP whether a token is active and what it grants (RFC 7662).
+/// Fails when the provider has no introspection endpoint.
+#[tauri::command]
+pub async fn oidc_introspect_token(
+    config: OidcConfig,
+    token: String,
+) -> Result<TokenIntrospection, String> {
+    let issuer = config
+        .issuer
+        .as_deref()
+        .ok_or("Token introspection needs an issuer to discover its endpoint")?;
+    let discovery = fetch_discovery(issuer).await?;
+    introspect_token(&config, Some(&discovery), &token).await
+}
+
+/// Stop waiting for the OIDC callback, releasing its port. Returns whether
+/// a login was waiting.
+#[tauri::command]
+pub fn oidc_cancel_callback() -> bool {
+    cancel_callback()
+}
+
+/// Exchange authorization code for tokens, with the PKCE `code_verifier`
+/// unless the flow ran without PKCE. With the auth request's `nonce`, an ID
+/// token in the response must carry it.
 #[tauri::command]
 pub async fn oidc_exchange_code(
     config: OidcConfig,
     code: String,
-    code_verifier: String,
+    code_verifier: Option<String>,
+    nonce: Option<String>,
 ) -> Result<TokenResponse, String> {
     // Fetch discovery if needed
     let discovery = if let Some(ref issuer) = config.issuer {
@@ -235,7 +1820,13 @@ pub async fn oidc_exchange_code(
         None
     };
 
-    exchange_code_for_tokens(&config, discovery.as_ref(), &code, &code_verifier).await
+    let tokens =
+        exchange_code_for_tokens(&config, discovery.as_ref(), &code, code_verifier.as_deref())
+            .await?;
+    if let (Some(id_token), Some(nonce)) = (&tokens.id_token, &nonce) {
+        validate_id_token_nonce(id_token, nonce)?;
+    }
+    Ok(tokens)
 }
 
 /// Refresh an access token
@@ -253,3 +1844,136 @@ pub async fn oidc_refresh_token(
 
     refresh_access_token(&config, discovery.as_ref(), &refresh_token).await
 }
+
+/// Get tokens for the client itself with the client credentials grant
+#[tauri::command]
+pub async fn oidc_client_credentials(config: OidcConfig) -> Result<TokenResponse, String> {
+    // Fetch discovery if needed
+    let discovery = if let Some(ref issuer) = config.issuer {
+        Some(fetch_discovery(issuer).await?)
+    } else {
+        None
+    };
+
+    client_credentials_grant(&config, discovery.as_ref()).await
+}
+
+/// Exchange a token for one for another audience (RFC 8693).
+/// `subject_token_type` defaults to an access token. Error responses fail
+/// with their OAuth error code first, such as `invalid_target`.
+#[tauri::command]
+pub async fn oidc_token_exchange(
+    config: OidcConfig,
+    subject_token: String,
+    subject_token_type: Option<String>,
+    audience: Option<String>,
+    requested_token_type: Option<String>,
+) -> Result<TokenResponse, String> {
+    // Fetch discovery if needed
+    let discovery = if let Some(ref issuer) = config.issuer {
+        Some(fetch_discovery(issuer).await?)
+    } else {
+        None
+    };
+
+    token_exchange(
+        &config,
+        discovery.as_ref(),
+        &subject_token,
+        subject_token_type.as_deref().unwrap_or(ACCESS_TOKEN_TYPE),
+        audience.as_deref(),
+        requested_token_type.as_deref(),
+    )
+    .await
+}
+
+/// Get tokens with the legacy resource owner password credentials grant.
+/// The credentials are not recorded anywhere; a wrong username or password
+/// fails with an error starting with `invalid_grant`.
+#[tauri::command]
+pub async fn oidc_password_grant(
+    config: OidcConfig,
+    username: String,
+    password: String,
+) -> Result<TokenResponse, String> {
+    // Fetch discovery if needed
+    let discovery = if let Some(ref issuer) = config.issuer {
+        Some(fetch_discovery(issuer).await?)
+    } else {
+        None
+    };
+
+    password_grant(&config, discovery.as_ref(), &username, &password).await
+}
+
+/// The OIDC config a `# @auth oidc <config-name>` directive names, for the UI
+/// to log in with when sending fails with `reauth_required`. `context` is the
+/// request's history context, which says where to look.
+#[tauri::command]
+pub async fn get_oidc_auth_config(
+    name: String,
+    context: HistoryContext,
+    history_db: State<'_, HistoryDb>,
+) -> Result<OidcConfig, String> {
+    crate::auth::find_oidc_config(&history_db, &context, &name).await
+}
+
+/// Result of caching tokens, with a warning when they could not go to the
+/// keychain
+#[derive(Debug, Serialize)]
+pub struct StoreTokensResult {
+    pub token: CachedToken,
+    pub warning: Option<String>,
+}
+
+/// Cache the tokens obtained for an auth config so later requests reuse them
+#[tauri::command]
+pub async fn store_tokens(
+    workspace: String,
+    auth_id: String,
+    token: TokenResponse,
+    token_store: State<'_, TokenStore>,
+) -> Result<StoreTokensResult, String> {
+    let (token, warning) = token_store.store_tokens(&workspace, &auth_id, token)?;
+    Ok(StoreTokensResult {
+        token,
+        warning: warning.map(str::to_string),
+    })
+}
+
+/// The tokens cached for an auth config, whether or not they have expired
+#[tauri::command]
+pub async fn load_tokens(
+    workspace: String,
+    auth_id: String,
+    token_store: State<'_, TokenStore>,
+) -> Result<Option<CachedToken>, String> {
+    token_store.load_tokens(&workspace, &auth_id)
+}
+
+/// The cached token for an auth config, refreshed when it expires within
+/// `skew_seconds`. Fails with an error starting with `reauth_required` when
+/// the user has to log in again.
+#[tauri::command]
+pub async fn get_valid_token(
+    workspace: String,
+    auth_id: String,
+    config: OidcConfig,
+    skew_seconds: Option<i64>,
+    token_store: State<'_, TokenStore>,
+) -> Result<CachedToken, String> {
+    let skew = skew_seconds.unwrap_or(crate::tokens::DEFAULT_SKEW_SECS);
+    token_store
+        .get_valid_token(&workspace, &auth_id, &config, skew)
+        .await
+}
+
+/// Forget the tokens cached for an auth config
+#[tauri::command]
+pub async fn delete_tokens(
+    workspace: String,
+    auth_id: String,
+    token_store: State<'_, TokenStore>,
+) -> Result<(), String> {
+    token_store.delete_tokens(&workspace, &auth_id)
+}
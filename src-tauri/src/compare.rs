@@ -0,0 +1,249 @@
+//! Running one request against several environments, such as staging and
+//! prod before a release, and diffing what each returned
+
+mod diff;
+
+pub use diff::{diff_responses, DiffIgnore, ResponseDiff};
+
+use crate::env::EnvironmentConfig;
+use crate::http_client::{execute_request, HttpRequest, HttpResponse};
+use crate::parser::ParsedRequest;
+use crate::variables::{resolve_request_with_commands, ResolveOptions, ENV_METADATA};
+use crate::workspace_settings::WorkspaceSettings;
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+/// How many environments are sent the request at once
+const MAX_CONCURRENT: usize = 4;
+
+/// What one environment returned, or why the request wasn't sent or failed
+#[derive(Debug, Clone, Serialize)]
+pub struct EnvironmentResponse {
+    pub environment: String,
+    /// URL with the environment's variables substituted, once resolved
+    pub url: Option<String>,
+    pub response: Option<HttpResponse>,
+    pub error: Option<String>,
+}
+
+/// How the responses of two environments differ
+#[derive(Debug, Clone, Serialize)]
+pub struct EnvironmentDiff {
+    pub left: String,
+    pub right: String,
+    pub diff: ResponseDiff,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EnvironmentComparison {
+    /// In the order the environments were given
+    pub responses: Vec<EnvironmentResponse>,
+    /// Every pair of environments that both responded, in the order given
+    pub diffs: Vec<EnvironmentDiff>,
+}
+
+/// Resolve and send `request` once per environment, at most
+/// [`MAX_CONCURRENT`] at a time, and diff each pair of responses. A
+/// `# @env` annotation on the request is ignored so every environment is
+/// used. `$exec` commands run in `workspace` for the values each
+/// environment's request uses.
+pub async fn compare_across_environments(
+    workspace: &str,
+    request: &ParsedRequest,
+    config: &EnvironmentConfig,
+    environments: &[String],
+    settings: &WorkspaceSettings,
+    ignore: &DiffIgnore,
+) -> EnvironmentComparison {
+    let mut request = request.clone();
+    request.metadata.remove(ENV_METADATA);
+
+    let permits = Arc::new(Semaphore::new(MAX_CONCURRENT));
+    let tasks: Vec<_> = environments
+        .iter()
+        .map(|environment| {
+            let mut result = EnvironmentResponse {
+                environment: environment.clone(),
+                url: None,
+                response: None,
+                error: None,
+            };
+            let workspace = workspace.to_string();
+            let request = request.clone();
+            let mut config = config.clone();
+            let environment = environment.clone();
+            let permits = permits.clone();
+            let settings = settings.clone();
+            tokio::spawn(async move {
+                let resolved = resolve_request_with_commands(
+                    &workspace,
+                    &request,
+                    &mut config,
+                    Some(&environment),
+                    &ResolveOptions::default(),
+                )
+                .await;
+                let resolved = match resolved {
+                    Ok(resolved) => resolved,
+                    Err(e) => {
+                        result.error = Some(e);
+                        return result;
+                    }
+                };
+                result.url = Some(resolved.request.url.clone());
+                if !resolved.missing.is_empty() {
+                    result.error = Some(format!(
+                        "Undefined variables: {}",
+                        resolved.missing.join(", ")
+                    ));
+                    return result;
+                }
+                let _permit = permits.acquire().await;
+                match execute_request(HttpRequest::from(resolved.request), &settings).await {
+                    Ok(response) => result.response = Some(response),
+                    Err(e) => result.error = Some(e.to_string()),
+                }
+                result
+            })
+        })
+        .collect();
+
+    let mut responses = Vec::with_capacity(tasks.len());
+    for (task, environment) in tasks.into_iter().zip(environments) {
+        responses.push(task.await.unwrap_or_else(|e| EnvironmentResponse {
+            environment: environment.clone(),
+            url: None,
+            response: None,
+            error: Some(format!("Request task failed: {}", e)),
+        }));
+    }
+
+    let mut diffs = Vec::new();
+    for (i, left) in responses.iter().enumerate() {
+        for right in &responses[i + 1..] {
+            if let (Some(l), Some(r)) = (&left.response, &right.response) {
+                diffs.push(EnvironmentDiff {
+                    left: left.environment.clone(),
+                    right: right.environment.clone(),
+                    diff: diff_responses(l, r, ignore),
+                });
+            }
+        }
+    }
+    EnvironmentComparison { responses, diffs }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::diff::BodyDiff;
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// Serve one JSON response naming `host`, as each environment's server
+    /// would
+    async fn serve_once(host: &'static str, version: &'static str) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut received = Vec::new();
+            let mut buf = [0; 1024];
+            while !received.ends_with(b"\r\n\r\n") {
+                let n = socket.read(&mut buf).await.unwrap();
+                if n == 0 {
+                    break;
+                }
+                received.extend_from_slice(&buf[..n]);
+            }
+            let body = format!(
+                r#"{{"host":"{}","version":"{}","items":[{{"id":1,"self":"https://{}/items/1"}}]}}"#,
+                host, version, host
+            );
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nX-Served-By: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                host,
+                body.len(),
+                body
+            );
+            let _ = socket.write_all(response.as_bytes()).await;
+        });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_compare_across_environments() {
+        let staging = serve_once("staging.test", "1.1").await;
+        let prod = serve_once("prod.test", "1.0").await;
+        let config: EnvironmentConfig = serde_json::from_value(serde_json::json!({
+            "environments": [
+                {"name": "staging", "variables": {"base": staging}, "source_file": "env.json"},
+                {"name": "prod", "variables": {"base": prod}, "source_file": "env.json"},
+                {"name": "qa", "variables": {}, "source_file": "env.json"}
+            ],
+            "shared": {}
+        }))
+        .unwrap();
+        let mut request = ParsedRequest::new();
+        request.url = "{{base}}/status".into();
+        // Pinning the request to one environment doesn't stop the comparison
+        request.metadata.insert("env".into(), "prod".into());
+
+        let environments: Vec<String> = ["staging", "prod", "qa", "missing"]
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+        let ignore = DiffIgnore {
+            headers: vec!["x-served-by".to_string(), "date".to_string()],
+            json_paths: vec!["$.host".to_string(), "$.items[*].self".to_string()],
+        };
+        let comparison = compare_across_environments(
+            "/ws",
+            &request,
+            &config,
+            &environments,
+            &WorkspaceSettings::default(),
+            &ignore,
+        )
+        .await;
+
+        let order: Vec<&str> = comparison
+            .responses
+            .iter()
+            .map(|r| r.environment.as_str())
+            .collect();
+        assert_eq!(order, vec!["staging", "prod", "qa", "missing"]);
+        assert_eq!(
+            comparison.responses[0].url.as_deref(),
+            Some(format!("{}/status", staging).as_str())
+        );
+        assert_eq!(
+            comparison.responses[1].response.as_ref().unwrap().status,
+            200
+        );
+        assert_eq!(
+            comparison.responses[2].error.as_deref(),
+            Some("Undefined variables: base")
+        );
+        assert_eq!(
+            comparison.responses[3].error.as_deref(),
+            Some("Environment 'missing' not found")
+        );
+
+        // Only the pair that both responded is diffed, without what's ignored
+        assert_eq!(comparison.diffs.len(), 1);
+        let diff = &comparison.diffs[0];
+        assert_eq!(
+            (diff.left.as_str(), diff.right.as_str()),
+            ("staging", "prod")
+        );
+        assert!(diff.diff.headers.is_empty());
+        let BodyDiff::Json { changes } = &diff.diff.body else {
+            panic!("not diffed as JSON");
+        };
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].path, "$.version");
+        assert_eq!(changes[0].left, Some(serde_json::json!("1.1")));
+        assert_eq!(changes[0].right, Some(serde_json::json!("1.0")));
+    }
+}
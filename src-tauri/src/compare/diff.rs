@@ -0,0 +1,395 @@
+use crate::http_client::HttpResponse;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Text bodies with more lines than this on both sides are diffed as a
+/// whole rather than line by line
+const MAX_LINE_DIFF: usize = 2000;
+
+/// Parts of two responses left out of their diff, such as values that are
+/// expected to differ between environments
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DiffIgnore {
+    /// Header names, matched case-insensitively
+    #[serde(default)]
+    pub headers: Vec<String>,
+    /// Paths into JSON bodies such as `$.meta.host` or `items[*].url`. `*`
+    /// matches any key or index, and a path ignores everything below it.
+    #[serde(default)]
+    pub json_paths: Vec<String>,
+}
+
+/// A header whose value differs; `None` where a response doesn't have it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HeaderChange {
+    /// Lowercased
+    pub name: String,
+    pub left: Option<String>,
+    pub right: Option<String>,
+}
+
+/// A JSON value that differs, at a path like `$.items[0].id`; `None` where
+/// a body doesn't have it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct JsonChange {
+    pub path: String,
+    pub left: Option<Value>,
+    pub right: Option<Value>,
+}
+
+/// A line only one body has, with its 1-based number in that body
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LineChange {
+    pub left_line: Option<usize>,
+    pub right_line: Option<usize>,
+    pub text: String,
+}
+
+/// How two bodies differ: value by value when both are JSON, otherwise
+/// line by line
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum BodyDiff {
+    Json { changes: Vec<JsonChange> },
+    Text { changes: Vec<LineChange> },
+}
+
+impl BodyDiff {
+    pub fn is_empty(&self) -> bool {
+        match self {
+            BodyDiff::Json { changes } => changes.is_empty(),
+            BodyDiff::Text { changes } => changes.is_empty(),
+        }
+    }
+}
+
+/// How two responses differ. Timings and sizes, including the
+/// Content-Length header, are not compared.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ResponseDiff {
+    /// Both statuses, when they differ
+    pub status: Option<[u16; 2]>,
+    /// Sorted by name
+    pub headers: Vec<HeaderChange>,
+    pub body: BodyDiff,
+    /// Nothing differs outside what was ignored
+    pub identical: bool,
+}
+
+/// Diff `left` against `right`, leaving out what `ignore` names
+pub fn diff_responses(
+    left: &HttpResponse,
+    right: &HttpResponse,
+    ignore: &DiffIgnore,
+) -> ResponseDiff {
+    let status = (left.status != right.status).then_some([left.status, right.status]);
+    let headers = diff_headers(left, right, ignore);
+    let body = diff_bodies(&left.body, &right.body, ignore);
+    ResponseDiff {
+        identical: status.is_none() && headers.is_empty() && body.is_empty(),
+        status,
+        headers,
+        body,
+    }
+}
+
+fn diff_headers(
+    left: &HttpResponse,
+    right: &HttpResponse,
+    ignore: &DiffIgnore,
+) -> Vec<HeaderChange> {
+    let lowercased = |response: &HttpResponse| -> std::collections::BTreeMap<String, String> {
+        response
+            .headers
+            .iter()
+            .map(|(name, value)| (name.to_ascii_lowercase(), value.clone()))
+            .collect()
+    };
+    let (left, right) = (lowercased(left), lowercased(right));
+    let mut names: Vec<&String> = left.keys().chain(right.keys()).collect();
+    names.sort();
+    names.dedup();
+    names
+        .into_iter()
+        .filter(|name| *name != "content-length")
+        .filter(|name| !ignore.headers.iter().any(|h| h.eq_ignore_ascii_case(name)))
+        .filter(|name| left.get(*name) != right.get(*name))
+        .map(|name| HeaderChange {
+            name: name.clone(),
+            left: left.get(name).cloned(),
+            right: right.get(name).cloned(),
+        })
+        .collect()
+}
+
+fn diff_bodies(left: &str, right: &str, ignore: &DiffIgnore) -> BodyDiff {
+    match (
+        serde_json::from_str::<Value>(left),
+        serde_json::from_str::<Value>(right),
+    ) {
+        (Ok(left), Ok(right)) => {
+            let patterns: Vec<Vec<String>> =
+                ignore.json_paths.iter().map(|p| parse_path(p)).collect();
+            let mut changes = Vec::new();
+            diff_json(
+                &mut Vec::new(),
+                Some(&left),
+                Some(&right),
+                &patterns,
+                &mut changes,
+            );
+            BodyDiff::Json { changes }
+        }
+        _ => BodyDiff::Text {
+            changes: diff_lines(left, right),
+        },
+    }
+}
+
+/// Split a path like `$.items[*].url` into `["items", "*", "url"]`
+fn parse_path(path: &str) -> Vec<String> {
+    let path = path.trim();
+    let path = path.strip_prefix('$').unwrap_or(path);
+    path.split(['.', '['])
+        .map(|segment| segment.trim_end_matches(']'))
+        .map(|segment| segment.trim_matches(|c| c == '"' || c == '\''))
+        .filter(|segment| !segment.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn diff_json(
+    path: &mut Vec<String>,
+    left: Option<&Value>,
+    right: Option<&Value>,
+    ignore: &[Vec<String>],
+    changes: &mut Vec<JsonChange>,
+) {
+    if left == right || is_ignored(path, ignore) {
+        return;
+    }
+    match (left, right) {
+        (Some(Value::Object(l)), Some(Value::Object(r))) => {
+            let mut keys: Vec<&String> = l.keys().chain(r.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                path.push(key.clone());
+                diff_json(path, l.get(key), r.get(key), ignore, changes);
+                path.pop();
+            }
+        }
+        (Some(Value::Array(l)), Some(Value::Array(r))) => {
+            for i in 0..l.len().max(r.len()) {
+                path.push(i.to_string());
+                diff_json(path, l.get(i), r.get(i), ignore, changes);
+                path.pop();
+            }
+        }
+        _ => changes.push(JsonChange {
+            path: format_path(path),
+            left: left.cloned(),
+            right: right.cloned(),
+        }),
+    }
+}
+
+fn is_ignored(path: &[String], ignore: &[Vec<String>]) -> bool {
+    ignore.iter().any(|pattern| {
+        !pattern.is_empty()
+            && pattern.len() <= path.len()
+            && pattern
+                .iter()
+                .zip(path)
+                .all(|(p, segment)| p == "*" || p == segment)
+    })
+}
+
+fn format_path(path: &[String]) -> String {
+    let mut formatted = "$".to_string();
+    for segment in path {
+        if segment.parse::<usize>().is_ok() {
+            formatted.push_str(&format!("[{}]", segment));
+        } else if segment
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            formatted.push('.');
+            formatted.push_str(segment);
+        } else {
+            formatted.push_str(&format!("[{}]", Value::from(segment.as_str())));
+        }
+    }
+    formatted
+}
+
+/// Lines only one side has, from a longest common subsequence of lines
+fn diff_lines(left: &str, right: &str) -> Vec<LineChange> {
+    let left: Vec<&str> = left.lines().collect();
+    let right: Vec<&str> = right.lines().collect();
+    let removed = |i: usize, text: &str| LineChange {
+        left_line: Some(i + 1),
+        right_line: None,
+        text: text.to_string(),
+    };
+    let added = |j: usize, text: &str| LineChange {
+        left_line: None,
+        right_line: Some(j + 1),
+        text: text.to_string(),
+    };
+    if left.len() > MAX_LINE_DIFF && right.len() > MAX_LINE_DIFF {
+        if left == right {
+            return Vec::new();
+        }
+        return left
+            .iter()
+            .enumerate()
+            .map(|(i, text)| removed(i, text))
+            .chain(right.iter().enumerate().map(|(j, text)| added(j, text)))
+            .collect();
+    }
+
+    // lengths[i][j]: longest common subsequence of left[i..] and right[j..]
+    let mut lengths = vec![vec![0usize; right.len() + 1]; left.len() + 1];
+    for i in (0..left.len()).rev() {
+        for j in (0..right.len()).rev() {
+            lengths[i][j] = if left[i] == right[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut changes = Vec::new();
+    while i < left.len() || j < right.len() {
+        if i < left.len() && j < right.len() && left[i] == right[j] {
+            i += 1;
+            j += 1;
+        } else if j < right.len() && (i == left.len() || lengths[i][j + 1] >= lengths[i + 1][j]) {
+            changes.push(added(j, right[j]));
+            j += 1;
+        } else {
+            changes.push(removed(i, left[i]));
+            i += 1;
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn response(status: u16, headers: &[(&str, &str)], body: &str) -> HttpResponse {
+        HttpResponse {
+            status,
+            status_text: String::new(),
+            headers: headers
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            body: body.to_string(),
+            time: 0,
+            size: body.len(),
+            body_base64: false,
+            history_id: None,
+        }
+    }
+
+    #[test]
+    fn test_diff_json_responses() {
+        let left = response(
+            200,
+            &[("Content-Type", "application/json"), ("X-Host", "a")],
+            r#"{"id":1,"host":"a.test","items":[{"url":"a/1"},{"url":"a/2"}],"tags":["x"],"only":true}"#,
+        );
+        let right = response(
+            201,
+            &[
+                ("content-type", "application/json"),
+                ("x-host", "b"),
+                ("Etag", "1"),
+                ("Content-Length", "99"),
+            ],
+            r#"{"id":2,"host":"b.test","items":[{"url":"b/1"},{"url":"b/2"}],"tags":["x","y"],"odd key":1}"#,
+        );
+
+        let diff = diff_responses(&left, &right, &DiffIgnore::default());
+        assert!(!diff.identical);
+        assert_eq!(diff.status, Some([200, 201]));
+        let headers: Vec<&str> = diff.headers.iter().map(|h| h.name.as_str()).collect();
+        assert_eq!(headers, vec!["etag", "x-host"]);
+        assert_eq!(diff.headers[0].left, None);
+
+        let BodyDiff::Json { changes } = diff.body else {
+            panic!("not diffed as JSON");
+        };
+        let paths: Vec<&str> = changes.iter().map(|c| c.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "$.host",
+                "$.id",
+                "$.items[0].url",
+                "$.items[1].url",
+                "$[\"odd key\"]",
+                "$.only",
+                "$.tags[1]"
+            ]
+        );
+        assert_eq!(changes[1].left, Some(json!(1)));
+        assert_eq!(changes[1].right, Some(json!(2)));
+        assert_eq!(changes[5].right, None);
+
+        let ignore = DiffIgnore {
+            headers: vec!["X-HOST".to_string(), "etag".to_string()],
+            json_paths: vec![
+                "$.host".to_string(),
+                "items[*].url".to_string(),
+                "$['odd key']".to_string(),
+                "only".to_string(),
+                "tags".to_string(),
+            ],
+        };
+        let diff = diff_responses(&left, &right, &ignore);
+        assert!(diff.headers.is_empty());
+        let BodyDiff::Json { changes } = diff.body else {
+            panic!("not diffed as JSON");
+        };
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].path, "$.id");
+
+        let same = diff_responses(&left, &left, &DiffIgnore::default());
+        assert!(same.identical);
+    }
+
+    #[test]
+    fn test_diff_text_responses() {
+        let left = response(200, &[], "a\nb\nc\nd");
+        let right = response(200, &[], "a\nc\nd\ne");
+        let diff = diff_responses(&left, &right, &DiffIgnore::default());
+        assert_eq!(
+            diff.body,
+            BodyDiff::Text {
+                changes: vec![
+                    LineChange {
+                        left_line: Some(2),
+                        right_line: None,
+                        text: "b".to_string()
+                    },
+                    LineChange {
+                        left_line: None,
+                        right_line: Some(4),
+                        text: "e".to_string()
+                    },
+                ]
+            }
+        );
+
+        // JSON on one side only is compared as text
+        let diff = diff_responses(&response(200, &[], "{}"), &left, &DiffIgnore::default());
+        assert!(matches!(diff.body, BodyDiff::Text { ref changes } if changes.len() == 5));
+    }
+}
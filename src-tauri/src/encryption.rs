@@ -0,0 +1,181 @@
+//! Passphrase encryption of the private env file, for machines without a
+//! usable keychain.
+//!
+//! The file is replaced by a JSON envelope holding AES-256-GCM ciphertext
+//! under a key derived from the passphrase with Argon2id. Unlocked files are
+//! kept decrypted in memory for the rest of the session.
+
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use argon2::Argon2;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Key marking an env file as an encrypted envelope
+pub const ENVELOPE_KEY: &str = "$kvileEncrypted";
+
+/// Envelope format version
+const VERSION: u32 = 1;
+
+/// Binds the ciphertext to this format so it can't be replayed elsewhere
+const ASSOCIATED_DATA: &[u8] = b"kvile-private-env-v1";
+
+/// Decrypted content of unlocked files, by path
+static UNLOCKED: Mutex<Option<HashMap<String, String>>> = Mutex::new(None);
+
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    #[serde(rename = "$kvileEncrypted")]
+    version: u32,
+    kdf: String,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Whether env file content is an encrypted envelope
+pub fn is_encrypted(content: &str) -> bool {
+    serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(content)
+        .is_ok_and(|map| map.contains_key(ENVELOPE_KEY))
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<[u8; 32], String> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| format!("Failed to derive key: {}", e))?;
+    Ok(key)
+}
+
+/// Encrypt env file content into a pretty-printed envelope
+pub fn encrypt(plaintext: &str, passphrase: &str) -> Result<String, String> {
+    if passphrase.is_empty() {
+        return Err("Passphrase must not be empty".to_string());
+    }
+    let salt: [u8; 16] = rand::random();
+    let nonce: [u8; 12] = rand::random();
+    let key = derive_key(passphrase, &salt)?;
+
+    let ciphertext = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: plaintext.as_bytes(),
+                aad: ASSOCIATED_DATA,
+            },
+        )
+        .map_err(|_| "Failed to encrypt private env file".to_string())?;
+
+    let envelope = Envelope {
+        version: VERSION,
+        kdf: "argon2id".to_string(),
+        salt: STANDARD.encode(salt),
+        nonce: STANDARD.encode(nonce),
+        ciphertext: STANDARD.encode(ciphertext),
+    };
+    let mut content = serde_json::to_string_pretty(&envelope)
+        .map_err(|e| format!("Failed to serialize envelope: {}", e))?;
+    content.push('\n');
+    Ok(content)
+}
+
+/// Decrypt an envelope. A wrong passphrase and a modified file fail the same way.
+pub fn decrypt(content: &str, passphrase: &str) -> Result<String, String> {
+    let envelope: Envelope = serde_json::from_str(content)
+        .map_err(|e| format!("Failed to read encrypted env file: {}", e))?;
+    if envelope.version != VERSION || envelope.kdf != "argon2id" {
+        return Err(format!(
+            "Unsupported encrypted env file (version {}, {})",
+            envelope.version, envelope.kdf
+        ));
+    }
+    let decode = |field: &str, value: &str| {
+        STANDARD
+            .decode(value)
+            .map_err(|e| format!("Invalid {} in encrypted env file: {}", field, e))
+    };
+    let salt = decode("salt", &envelope.salt)?;
+    let nonce = decode("nonce", &envelope.nonce)?;
+    let ciphertext = decode("ciphertext", &envelope.ciphertext)?;
+    if nonce.len() != 12 {
+        return Err("Invalid nonce in encrypted env file".to_string());
+    }
+
+    let key = derive_key(passphrase, &salt)?;
+    let plaintext = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))
+        .decrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: &ciphertext,
+                aad: ASSOCIATED_DATA,
+            },
+        )
+        .map_err(|_| "Wrong passphrase, or the encrypted env file was modified".to_string())?;
+    String::from_utf8(plaintext).map_err(|_| "Decrypted env file is not UTF-8".to_string())
+}
+
+/// Keep a file's decrypted content for the rest of the session
+pub fn unlock(path: &Path, plaintext: String) {
+    UNLOCKED
+        .lock()
+        .unwrap()
+        .get_or_insert_with(HashMap::new)
+        .insert(path.to_string_lossy().to_string(), plaintext);
+}
+
+/// Decrypted content of a file unlocked this session
+pub fn unlocked(path: &Path) -> Option<String> {
+    UNLOCKED
+        .lock()
+        .unwrap()
+        .as_ref()?
+        .get(&*path.to_string_lossy())
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let plaintext = r#"{"dev": {"token": "abc"}}"#;
+        let envelope = encrypt(plaintext, "hunter2").unwrap();
+        assert!(is_encrypted(&envelope));
+        assert!(!is_encrypted(plaintext));
+        assert!(!envelope.contains("abc"));
+        assert_eq!(decrypt(&envelope, "hunter2").unwrap(), plaintext);
+
+        // Fresh salt and nonce every time
+        assert_ne!(encrypt(plaintext, "hunter2").unwrap(), envelope);
+    }
+
+    #[test]
+    fn test_wrong_passphrase_and_tampering_fail() {
+        let envelope = encrypt(r#"{"dev": {}}"#, "hunter2").unwrap();
+        assert_eq!(
+            decrypt(&envelope, "hunter3").unwrap_err(),
+            "Wrong passphrase, or the encrypted env file was modified"
+        );
+
+        let mut parsed: serde_json::Value = serde_json::from_str(&envelope).unwrap();
+        let mut ciphertext = STANDARD
+            .decode(parsed["ciphertext"].as_str().unwrap())
+            .unwrap();
+        ciphertext[0] ^= 1;
+        parsed["ciphertext"] = STANDARD.encode(ciphertext).into();
+        assert_eq!(
+            decrypt(&parsed.to_string(), "hunter2").unwrap_err(),
+            "Wrong passphrase, or the encrypted env file was modified"
+        );
+
+        parsed["nonce"] = "not base64!".into();
+        assert!(decrypt(&parsed.to_string(), "hunter2")
+            .unwrap_err()
+            .starts_with("Invalid nonce"));
+        assert!(encrypt("{}", "").is_err());
+    }
+}
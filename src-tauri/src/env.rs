@@ -1,6 +1,11 @@
+use crate::encryption;
+use crate::exec;
+use crate::oidc::TokenEndpointAuthMethod;
+use crate::secrets;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Environment {
@@ -9,7 +14,15 @@ pub struct Environment {
     /// Variables from the private env file (tracked separately for editing)
     #[serde(default)]
     pub private_variables: HashMap<String, String>,
+    /// The nearest env file defining this environment
     pub source_file: String,
+    /// Env file each variable's value came from
+    #[serde(default)]
+    pub variable_sources: HashMap<String, String>,
+    /// Named auth configs from the `Security.Auth` section, with the private
+    /// env file's fields merged in
+    #[serde(default)]
+    pub auth: HashMap<String, AuthConfig>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -19,43 +32,281 @@ pub struct EnvironmentConfig {
     /// Shared variables from the private env file
     #[serde(default)]
     pub private_shared: HashMap<String, String>,
+    /// Secrets that could not be read from the keychain, and auth configs
+    /// kvile can't use
+    #[serde(default)]
+    pub warnings: Vec<String>,
+    /// Encrypted private env files not unlocked this session
+    #[serde(default)]
+    pub locked: Vec<String>,
+    /// Env files that contributed, lowest precedence first
+    #[serde(default)]
+    pub files: Vec<String>,
+    /// Names of variables `$exec` commands provide, by environment
+    /// (`$shared` for shared ones). Their values stay markers until
+    /// [`run_commands`] runs them.
+    #[serde(default)]
+    pub command_variables: HashMap<String, Vec<String>>,
+    /// `$exec` commands not run because the workspace isn't trusted
+    #[serde(default)]
+    pub untrusted_commands: Vec<String>,
+}
+
+/// Key of an environment's auth section, as the JetBrains HTTP client names it
+const SECURITY_KEY: &str = "Security";
+
+/// The auth config type kvile can get tokens for
+pub const OAUTH2_AUTH_TYPE: &str = "OAuth2";
+
+/// OAuth 2.0 grant an auth config gets its tokens with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GrantType {
+    AuthorizationCode,
+    ClientCredentials,
+    Password,
+    Implicit,
+    DeviceAuthorization,
+}
+
+impl GrantType {
+    /// A `Grant Type` as the JetBrains client writes it, e.g.
+    /// `Authorization Code`
+    fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().replace(['_', '-'], " ").as_str() {
+            "authorization code" => Some(Self::AuthorizationCode),
+            "client credentials" => Some(Self::ClientCredentials),
+            "password" => Some(Self::Password),
+            "implicit" => Some(Self::Implicit),
+            "device authorization" => Some(Self::DeviceAuthorization),
+            _ => None,
+        }
+    }
+}
+
+/// A named auth config from an environment's `Security.Auth` section, in the
+/// JetBrains HTTP client's format. Values may hold `{{variables}}`, which are
+/// substituted when the config is used.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuthConfig {
+    /// `Type`; only [`OAUTH2_AUTH_TYPE`] is usable, and it is assumed when
+    /// unset, as in private env files that only add a secret
+    pub auth_type: Option<String>,
+    pub grant_type: Option<GrantType>,
+    pub issuer: Option<String>,
+    /// Authorization endpoint
+    pub auth_url: Option<String>,
+    pub token_url: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    /// `Client Credentials`: `basic`, `in body` or `none`
+    pub client_auth_method: Option<TokenEndpointAuthMethod>,
+    pub redirect_url: Option<String>,
+    /// `Scope`, split on whitespace
+    pub scopes: Vec<String>,
+    /// For the password grant
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// `PKCE`; unset leaves it to kvile, which uses PKCE
+    pub pkce: Option<bool>,
+    /// The config as written, so fields and types kvile doesn't know are kept
+    pub raw: serde_json::Map<String, serde_json::Value>,
+}
+
+impl AuthConfig {
+    /// Read a config from its JSON. JetBrains' field names are used, and the
+    /// snake_case names of [`crate::oidc::OidcConfig`] are accepted too.
+    pub fn from_raw(raw: serde_json::Map<String, serde_json::Value>) -> Self {
+        let field = |names: &[&str]| {
+            names
+                .iter()
+                .find_map(|name| raw.get(*name))
+                .filter(|value| !value.is_null())
+                .cloned()
+                .map(value_string)
+        };
+        AuthConfig {
+            auth_type: field(&["Type", "type"]),
+            grant_type: field(&["Grant Type", "grant_type"])
+                .as_deref()
+                .and_then(GrantType::parse),
+            issuer: field(&["Issuer", "issuer"]),
+            auth_url: field(&["Auth URL", "authorization_endpoint"]),
+            token_url: field(&["Token URL", "token_endpoint"]),
+            client_id: field(&["Client ID", "client_id"]),
+            client_secret: field(&["Client Secret", "client_secret"]),
+            client_auth_method: field(&["Client Credentials", "client_credentials"]).map(
+                |method| match method.to_ascii_lowercase().as_str() {
+                    "basic" | "client_secret_basic" => TokenEndpointAuthMethod::ClientSecretBasic,
+                    "none" => TokenEndpointAuthMethod::None,
+                    _ => TokenEndpointAuthMethod::ClientSecretPost,
+                },
+            ),
+            redirect_url: field(&["Redirect URL", "redirect_url"]),
+            scopes: field(&["Scope", "scope"])
+                .map(|scope| scope.split_whitespace().map(str::to_string).collect())
+                .unwrap_or_default(),
+            username: field(&["Username", "username"]),
+            password: field(&["Password", "password"]),
+            // An object sets the challenge method, which discovery picks here
+            pkce: match raw.get("PKCE").or_else(|| raw.get("use_pkce")) {
+                Some(serde_json::Value::Bool(pkce)) => Some(*pkce),
+                Some(serde_json::Value::String(pkce)) => pkce.parse().ok(),
+                Some(serde_json::Value::Object(_)) => Some(true),
+                _ => None,
+            },
+            raw,
+        }
+    }
+
+    /// Whether kvile can get tokens for the config
+    pub fn is_supported(&self) -> bool {
+        self.auth_type
+            .as_deref()
+            .is_none_or(|auth_type| auth_type.eq_ignore_ascii_case(OAUTH2_AUTH_TYPE))
+    }
+}
+
+/// Parse an environment's `Security` section into its named auth configs.
+/// Configs of types kvile can't use are kept with a warning.
+fn parse_security(
+    path: &Path,
+    env_name: &str,
+    security: serde_json::Value,
+    warnings: &mut Vec<String>,
+) -> HashMap<String, AuthConfig> {
+    let mut configs = HashMap::new();
+    let auth = match security {
+        serde_json::Value::Object(mut security) => security.remove("Auth"),
+        _ => None,
+    };
+    let Some(serde_json::Value::Object(auth)) = auth else {
+        warnings.push(format!(
+            "'{}' in environment '{}' of {} has no 'Auth' object; it is ignored",
+            SECURITY_KEY,
+            env_name,
+            path.display()
+        ));
+        return configs;
+    };
+    for (name, raw) in auth {
+        let serde_json::Value::Object(raw) = raw else {
+            warnings.push(format!(
+                "Auth config '{}' in environment '{}' of {} is not an object; it is ignored",
+                name,
+                env_name,
+                path.display()
+            ));
+            continue;
+        };
+        let config = AuthConfig::from_raw(raw);
+        if !config.is_supported() {
+            warnings.push(format!(
+                "Auth config '{}' in environment '{}' of {} has type '{}', which kvile can't get tokens for",
+                name,
+                env_name,
+                path.display(),
+                config.auth_type.as_deref().unwrap_or_default()
+            ));
+        } else if let (None, Some(grant)) = (config.grant_type, config.raw.get("Grant Type")) {
+            warnings.push(format!(
+                "Auth config '{}' in environment '{}' of {} has unknown grant type {}",
+                name,
+                env_name,
+                path.display(),
+                grant
+            ));
+        }
+        configs.insert(name, config);
+    }
+    configs
+}
+
+/// Merge auth configs from a private env file into the public file's,
+/// field by field, since private files usually only add the secrets
+fn merge_auth(auth: &mut HashMap<String, AuthConfig>, private: HashMap<String, AuthConfig>) {
+    for (name, private) in private {
+        match auth.get_mut(&name) {
+            Some(config) => {
+                let mut raw = config.raw.clone();
+                raw.extend(private.raw);
+                *config = AuthConfig::from_raw(raw);
+            }
+            None => {
+                auth.insert(name, private);
+            }
+        }
+    }
 }
 
+/// Default glob for env files merged across the workspace
+pub const DEFAULT_ENV_GLOB: &str = "**/http-client.env.json";
+
+/// Name of the env file each directory may hold
+const ENV_FILE: &str = "http-client.env.json";
+
 /// Parse http-client.env.json format (JetBrains style)
 pub async fn parse_http_client_env(path: &Path) -> Result<EnvironmentConfig, String> {
     let content = tokio::fs::read_to_string(path)
         .await
-        .map_err(|e| format!("Failed to read env file: {}", e))?;
+        .map_err(|e| format!("Failed to read env file {}: {}", path.display(), e))?;
+    parse_env_content(path, &content)
+}
+
+/// Parse a private env file, decrypting it if it was unlocked this session.
+/// `None` when it is encrypted and still locked.
+async fn parse_private_env(path: &Path) -> Result<Option<EnvironmentConfig>, String> {
+    let content = tokio::fs::read_to_string(path)
+        .await
+        .map_err(|e| format!("Failed to read env file {}: {}", path.display(), e))?;
+    if !encryption::is_encrypted(&content) {
+        return parse_env_content(path, &content).map(Some);
+    }
+    match encryption::unlocked(path) {
+        Some(plaintext) => parse_env_content(path, &plaintext).map(Some),
+        None => Ok(None),
+    }
+}
 
-    let parsed: HashMap<String, HashMap<String, serde_json::Value>> =
-        serde_json::from_str(&content).map_err(|e| format!("Failed to parse env file: {}", e))?;
+/// Parse the content of an http-client env file read from `path`
+fn parse_env_content(path: &Path, content: &str) -> Result<EnvironmentConfig, String> {
+    let parsed: HashMap<String, HashMap<String, serde_json::Value>> = serde_json::from_str(content)
+        .map_err(|e| format!("Failed to parse env file {}: {}", path.display(), e))?;
 
     let mut environments = Vec::new();
     let mut shared = HashMap::new();
+    let mut warnings = Vec::new();
+
+    for (name, mut vars) in parsed {
+        // Auth configs are not variables
+        let auth = match vars.remove(SECURITY_KEY) {
+            Some(security) if name != "$shared" => {
+                parse_security(path, &name, security, &mut warnings)
+            }
+            Some(security) => {
+                vars.insert(SECURITY_KEY.to_string(), security);
+                HashMap::new()
+            }
+            None => HashMap::new(),
+        };
 
-    for (name, vars) in parsed {
         // Convert values to strings
         let string_vars: HashMap<String, String> = vars
             .into_iter()
-            .map(|(k, v)| {
-                let string_val = match v {
-                    serde_json::Value::String(s) => s,
-                    serde_json::Value::Number(n) => n.to_string(),
-                    serde_json::Value::Bool(b) => b.to_string(),
-                    _ => v.to_string(),
-                };
-                (k, string_val)
-            })
+            .map(|(k, v)| (k, value_string(v)))
             .collect();
 
         if name == "$shared" {
             shared = string_vars;
         } else {
+            let source_file = path.to_string_lossy().to_string();
             environments.push(Environment {
                 name,
+                variable_sources: sources(&string_vars, &source_file),
                 variables: string_vars,
                 private_variables: HashMap::new(),
-                source_file: path.to_string_lossy().to_string(),
+                source_file,
+                auth,
             });
         }
     }
@@ -67,52 +318,600 @@ pub async fn parse_http_client_env(path: &Path) -> Result<EnvironmentConfig, Str
         environments,
         shared,
         private_shared: HashMap::new(),
+        warnings,
+        locked: vec![],
+        files: vec![path.to_string_lossy().to_string()],
+        command_variables: HashMap::new(),
+        untrusted_commands: vec![],
     })
 }
 
+/// An env file value as a variable string. Secret markers stay as their JSON.
+fn value_string(value: serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s,
+        serde_json::Value::Number(n) => n.to_string(),
+        serde_json::Value::Bool(b) => b.to_string(),
+        _ => value.to_string(),
+    }
+}
+
 /// Parse .env file format
 pub fn parse_dotenv(content: &str) -> HashMap<String, String> {
-    let mut vars = HashMap::new();
+    parse_dotenv_entries(content).vars
+}
+
+/// Variables of a .env file, plus the entries that couldn't be parsed
+struct Dotenv {
+    vars: HashMap<String, String>,
+    /// 1-based line and column of each malformed entry, with what's wrong
+    errors: Vec<(usize, usize, String)>,
+}
 
-    for line in content.lines() {
-        let line = line.trim();
+/// Parse .env content following dotenv conventions: an optional `export`
+/// prefix, inline comments outside quotes, literal single-quoted values,
+/// double-quoted values with escapes that may span lines, and `$VAR` /
+/// `${VAR}` expansion against keys defined earlier in the file.
+fn parse_dotenv_entries(content: &str) -> Dotenv {
+    let mut parser = DotenvParser {
+        chars: content.chars().collect(),
+        pos: 0,
+        line: 1,
+        line_start: 0,
+    };
+    let mut dotenv = Dotenv {
+        vars: HashMap::new(),
+        errors: Vec::new(),
+    };
 
-        // Skip comments and empty lines
-        if line.is_empty() || line.starts_with('#') {
+    loop {
+        while parser.peek().is_some_and(char::is_whitespace) {
+            parser.bump();
+        }
+        let Some(c) = parser.peek() else { break };
+        if c == '#' {
+            parser.skip_line();
             continue;
         }
 
-        if let Some(pos) = line.find('=') {
-            let key = line[..pos].trim().to_string();
-            let value = line[pos + 1..].trim().to_string();
-            // Remove quotes if present
-            let value = value
-                .trim_start_matches('"')
-                .trim_end_matches('"')
-                .trim_start_matches('\'')
-                .trim_end_matches('\'')
-                .to_string();
-            vars.insert(key, value);
+        let (line, column) = (parser.line, parser.pos - parser.line_start + 1);
+        match parser.entry(&dotenv.vars) {
+            Ok((key, value)) => {
+                dotenv.vars.insert(key, value);
+            }
+            Err(message) => dotenv.errors.push((line, column, message)),
+        }
+    }
+
+    dotenv
+}
+
+enum Quote {
+    None,
+    Single,
+    Double,
+}
+
+struct DotenvParser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+    line_start: usize,
+}
+
+impl DotenvParser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.line_start = self.pos;
+        }
+        Some(c)
+    }
+
+    fn skip_line(&mut self) {
+        while let Some(c) = self.bump() {
+            if c == '\n' {
+                break;
+            }
+        }
+    }
+
+    /// Parse one `KEY=value` entry starting at the current position. On
+    /// error the rest of the line (or of the unterminated value) is skipped.
+    fn entry(&mut self, vars: &HashMap<String, String>) -> Result<(String, String), String> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c != '=' && c != '\n') {
+            self.bump();
+        }
+        let raw: String = self.chars[start..self.pos].iter().collect();
+        if self.peek() != Some('=') {
+            return Err(format!("Expected KEY=value, found '{}'", raw.trim()));
+        }
+        self.bump();
+
+        let key = raw.trim();
+        let key = match key.strip_prefix("export") {
+            Some(rest) if rest.starts_with(char::is_whitespace) => rest.trim_start(),
+            _ => key,
+        };
+        if key.is_empty() {
+            self.skip_line();
+            return Err("Missing variable name before '='".to_string());
+        }
+        if key.contains(char::is_whitespace) {
+            self.skip_line();
+            return Err(format!("Variable name '{}' contains whitespace", key));
+        }
+        let key = key.to_string();
+
+        let value = self.value(vars)?;
+        Ok((key, value))
+    }
+
+    fn value(&mut self, vars: &HashMap<String, String>) -> Result<String, String> {
+        let mut value = String::new();
+        // Unquoted whitespace, kept only if more of the value follows
+        let mut pending = String::new();
+        let mut after_space = false;
+        let mut quote = Quote::None;
+
+        while self.peek().is_some_and(|c| c == ' ' || c == '\t') {
+            self.bump();
+            after_space = true;
+        }
+
+        loop {
+            let c = self.peek();
+            match quote {
+                Quote::None => match c {
+                    None | Some('\n') => break,
+                    Some('#') if after_space => {
+                        self.skip_line();
+                        return Ok(value);
+                    }
+                    Some(c) if c.is_whitespace() => {
+                        self.bump();
+                        pending.push(c);
+                        after_space = true;
+                        continue;
+                    }
+                    Some(c) => {
+                        self.bump();
+                        value.push_str(&std::mem::take(&mut pending));
+                        match c {
+                            '\'' => quote = Quote::Single,
+                            '"' => quote = Quote::Double,
+                            '\\' => match self.bump() {
+                                Some(escaped) if escaped != '\n' => value.push(escaped),
+                                _ => break,
+                            },
+                            '$' => self.expand(vars, &mut value),
+                            c => value.push(c),
+                        }
+                    }
+                },
+                Quote::Single => match self.bump() {
+                    None => return Err("Unterminated single-quoted value".to_string()),
+                    Some('\'') => quote = Quote::None,
+                    Some(c) => value.push(c),
+                },
+                Quote::Double => match self.bump() {
+                    None => return Err("Unterminated double-quoted value".to_string()),
+                    Some('"') => quote = Quote::None,
+                    Some('\\') => match self.bump() {
+                        Some('n') => value.push('\n'),
+                        Some('r') => value.push('\r'),
+                        Some('t') => value.push('\t'),
+                        Some(c @ ('"' | '\\' | '$')) => value.push(c),
+                        Some(c) => {
+                            value.push('\\');
+                            value.push(c);
+                        }
+                        None => return Err("Unterminated double-quoted value".to_string()),
+                    },
+                    Some('$') => self.expand(vars, &mut value),
+                    Some(c) => value.push(c),
+                },
+            }
+            after_space = false;
         }
+
+        Ok(value)
     }
 
-    vars
+    /// Expand the variable after a `$`. Unknown names expand to nothing, and
+    /// a `$` not followed by a name is kept as is.
+    fn expand(&mut self, vars: &HashMap<String, String>, value: &mut String) {
+        let braced = self.peek() == Some('{');
+        let start = self.pos + braced as usize;
+        let mut end = start;
+        while self
+            .chars
+            .get(end)
+            .is_some_and(|c| c.is_ascii_alphanumeric() || *c == '_')
+        {
+            end += 1;
+        }
+        if end == start || (braced && self.chars.get(end) != Some(&'}')) {
+            value.push('$');
+            return;
+        }
+
+        let name: String = self.chars[start..end].iter().collect();
+        if let Some(expanded) = vars.get(&name) {
+            value.push_str(expanded);
+        }
+        self.pos = end + braced as usize;
+    }
 }
 
-/// Load environment configuration from workspace
-#[tauri::command]
-pub async fn load_environment_config(workspace: String) -> Result<EnvironmentConfig, String> {
+/// Load environment configuration for a workspace.
+///
+/// With the path of the .http file being run, env files in every directory
+/// from the workspace root down to the file's are merged, the closest file
+/// winning per variable. Other files matching `env_glob` (default
+/// [`DEFAULT_ENV_GLOB`]), such as `envs/orders.http-client.env.json`, are
+/// merged in wherever the .http file is; see [`env_layers`] for the order.
+#[cfg_attr(feature = "app", tauri::command)]
+pub async fn load_environment_config(
+    workspace: String,
+    file_path: Option<String>,
+    env_glob: Option<String>,
+) -> Result<EnvironmentConfig, String> {
     let workspace_path = Path::new(&workspace);
+    let mut config = EnvironmentConfig {
+        environments: vec![],
+        shared: HashMap::new(),
+        private_shared: HashMap::new(),
+        warnings: vec![],
+        locked: vec![],
+        files: vec![],
+        command_variables: HashMap::new(),
+        untrusted_commands: vec![],
+    };
+    let layers = env_layers(
+        workspace_path,
+        file_path.as_deref().map(Path::new),
+        env_glob.as_deref(),
+    )
+    .await?;
+    for layer in layers {
+        let nearer = match layer {
+            EnvLayer::Dir(dir) => load_dir_config(&dir).await?,
+            EnvLayer::Fragment(path) => load_env_json(&path, &private_env_path(&path)).await?,
+        };
+        if let Some(nearer) = nearer {
+            merge_config(&mut config, nearer);
+        }
+    }
+    resolve_secrets(&workspace, &mut config);
+    find_commands(&workspace, &mut config);
+    Ok(config)
+}
+
+/// Record the variables `$exec` markers provide, in `command_variables`,
+/// and in untrusted workspaces the commands in `untrusted_commands`. The
+/// markers stay; [`run_commands`] runs those a request uses.
+fn find_commands(workspace: &str, config: &mut EnvironmentConfig) {
+    let trusted = exec::is_trusted(workspace);
+    let mut scopes = vec![
+        ("$shared", &config.shared),
+        ("$shared", &config.private_shared),
+    ];
+    for env in &config.environments {
+        scopes.push((&env.name, &env.variables));
+        scopes.push((&env.name, &env.private_variables));
+    }
+    for (scope, variables) in scopes {
+        for (name, value) in variables {
+            let Some(marker) = exec::parse_marker(value) else {
+                continue;
+            };
+            let names = config
+                .command_variables
+                .entry(scope.to_string())
+                .or_default();
+            if !names.contains(name) {
+                names.push(name.clone());
+                names.sort();
+            }
+            if !trusted && !config.untrusted_commands.contains(&marker.command) {
+                config.untrusted_commands.push(marker.command);
+            }
+        }
+    }
+    config.untrusted_commands.sort();
+}
+
+/// Run the `$exec` commands behind `names` in `environment`, replacing each
+/// marker with its output. Only the value a name resolves to counts, the
+/// environment's over `$shared` and private over public, so commands of other
+/// names, overridden values and other environments don't run. A command that
+/// fails, or may not run as the workspace isn't trusted, leaves its variable
+/// out, failures with a warning. Returns whether any marker was replaced.
+pub async fn run_commands(
+    workspace: &str,
+    config: &mut EnvironmentConfig,
+    environment: Option<&str>,
+    names: impl IntoIterator<Item = String>,
+) -> bool {
+    let trusted = exec::is_trusted(workspace);
+    let mut changed = false;
+    for name in names {
+        let env = environment.and_then(|environment| {
+            config
+                .environments
+                .iter_mut()
+                .find(|env| env.name == environment)
+        });
+        // Highest precedence first
+        let mut layers: Vec<&mut HashMap<String, String>> = Vec::new();
+        if let Some(env) = env {
+            layers.push(&mut env.private_variables);
+            layers.push(&mut env.variables);
+        }
+        layers.push(&mut config.private_shared);
+        layers.push(&mut config.shared);
+        let Some(variables) = layers.into_iter().find(|vars| vars.contains_key(&name)) else {
+            continue;
+        };
+        let Some(marker) = exec::parse_marker(&variables[&name]) else {
+            continue;
+        };
+        changed = true;
+        if !trusted {
+            variables.remove(&name);
+            continue;
+        }
+        match exec::run(workspace, &marker).await {
+            Ok(value) => {
+                variables.insert(name, value);
+            }
+            Err(e) => {
+                tracing::warn!("Command for '{}' failed: {}", name, e);
+                variables.remove(&name);
+                config.warnings.push(format!("'{}': {}", name, e));
+            }
+        }
+    }
+    changed
+}
+
+/// [`run_commands`] for the variables `inputs` use, and those their values
+/// use in turn, for values substituted outside a request such as an auth
+/// config's
+pub async fn run_referenced_commands(
+    workspace: &str,
+    config: &mut EnvironmentConfig,
+    environment: Option<&str>,
+    inputs: &[&str],
+) {
+    let mut seen = HashSet::new();
+    let mut pending: Vec<String> = inputs
+        .iter()
+        .flat_map(|input| crate::variables::referenced_names(input))
+        .collect();
+    while !pending.is_empty() {
+        pending.retain(|name| seen.insert(name.clone()));
+        run_commands(workspace, config, environment, pending.clone()).await;
+        let Ok(vars) = crate::variables::environment_variables(config, environment) else {
+            return;
+        };
+        pending = pending
+            .iter()
+            .filter_map(|name| vars.get(name))
+            .flat_map(|value| crate::variables::referenced_names(value))
+            .collect();
+    }
+}
+
+/// Replace secret markers with their keychain values. Secrets that can't be
+/// read are left out and reported in `warnings`.
+fn resolve_secrets(workspace: &str, config: &mut EnvironmentConfig) {
+    let marker = secrets::marker().to_string();
+    let mut resolve = |environment: &str, variables: &mut HashMap<String, String>| {
+        variables.retain(|name, value| {
+            if *value != marker {
+                return true;
+            }
+            match secrets::get_secret(workspace, environment, name) {
+                Ok(secret) => {
+                    *value = secret;
+                    true
+                }
+                Err(e) => {
+                    config.warnings.push(e);
+                    false
+                }
+            }
+        });
+    };
+    resolve("$shared", &mut config.shared);
+    resolve("$shared", &mut config.private_shared);
+    for env in &mut config.environments {
+        resolve(&env.name, &mut env.variables);
+        resolve(&env.name, &mut env.private_variables);
+    }
+}
+
+/// Directories to look for env files in, workspace root first and the .http
+/// file's own directory last. Files outside the workspace only use the root.
+fn env_dirs(workspace: &Path, file_path: Option<&Path>) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = file_path
+        .and_then(Path::parent)
+        .filter(|dir| dir.starts_with(workspace))
+        .map(|dir| {
+            dir.ancestors()
+                .take_while(|ancestor| ancestor.starts_with(workspace))
+                .map(Path::to_path_buf)
+                .collect()
+        })
+        .unwrap_or_else(|| vec![workspace.to_path_buf()]);
+    dirs.reverse();
+    dirs
+}
+
+/// Where a layer of env config is loaded from
+#[derive(Debug, Clone, PartialEq)]
+enum EnvLayer {
+    /// The env files of a directory on the way to the .http file
+    Dir(PathBuf),
+    /// A file matching the env glob, with its private counterpart
+    Fragment(PathBuf),
+}
+
+impl EnvLayer {
+    fn path(&self) -> &Path {
+        match self {
+            EnvLayer::Dir(path) | EnvLayer::Fragment(path) => path,
+        }
+    }
+
+    fn dir(&self) -> &Path {
+        match self {
+            EnvLayer::Dir(dir) => dir,
+            EnvLayer::Fragment(path) => path.parent().unwrap_or(path),
+        }
+    }
+}
+
+/// Env config layers for a .http file, lowest precedence first.
+///
+/// Deeper paths override shallower ones; at the same depth a directory's own
+/// env file comes before fragments, which are ordered by path. Glob matches
+/// named `http-client.env.json` only apply through [`env_dirs`], so
+/// directories off the .http file's path don't leak into it.
+async fn env_layers(
+    workspace: &Path,
+    file_path: Option<&Path>,
+    env_glob: Option<&str>,
+) -> Result<Vec<EnvLayer>, String> {
+    let matcher = globset::Glob::new(env_glob.unwrap_or(DEFAULT_ENV_GLOB))
+        .map_err(|e| format!("Invalid env file glob: {}", e))?
+        .compile_matcher();
+    let mut candidates = Vec::new();
+    find_env_files(workspace, &mut candidates).await?;
+
+    let mut layers: Vec<EnvLayer> = env_dirs(workspace, file_path)
+        .into_iter()
+        .map(EnvLayer::Dir)
+        .collect();
+    layers.extend(
+        candidates
+            .into_iter()
+            .filter(|path| {
+                path.strip_prefix(workspace)
+                    .is_ok_and(|relative| matcher.is_match(relative))
+            })
+            .map(EnvLayer::Fragment),
+    );
+    layers.sort_by(|a, b| {
+        let key = |layer: &EnvLayer| {
+            (
+                layer.dir().components().count(),
+                matches!(layer, EnvLayer::Fragment(_)),
+            )
+        };
+        key(a).cmp(&key(b)).then_with(|| a.path().cmp(b.path()))
+    });
+    Ok(layers)
+}
+
+/// Collect public env files other than the per-directory `http-client.env.json`
+async fn find_env_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), String> {
+    let mut entries = tokio::fs::read_dir(dir)
+        .await
+        .map_err(|e| format!("Failed to read directory {}: {}", dir.display(), e))?;
+
+    while let Some(entry) = entries
+        .next_entry()
+        .await
+        .map_err(|e| format!("Failed to read entry: {}", e))?
+    {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        let file_type = entry
+            .file_type()
+            .await
+            .map_err(|e| format!("Failed to get metadata: {}", e))?;
+
+        if file_type.is_dir() {
+            // Skip hidden directories and common non-relevant directories
+            if !name.starts_with('.') && name != "node_modules" && name != "target" {
+                Box::pin(find_env_files(&path, files)).await?;
+            }
+        } else if name.ends_with(".env.json")
+            && name != ENV_FILE
+            && !name.ends_with("private.env.json")
+        {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// The private counterpart of a public env file:
+/// `orders.http-client.env.json` pairs with `orders.http-client.private.env.json`
+fn private_env_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let stem = name.strip_suffix(".env.json").unwrap_or(&name);
+    path.with_file_name(format!("{}.private.env.json", stem))
+}
 
-    // Try http-client.env.json first
-    let env_json_path = workspace_path.join("http-client.env.json");
+/// Overlay the config from a layer with higher precedence
+fn merge_config(config: &mut EnvironmentConfig, nearer: EnvironmentConfig) {
+    config.shared.extend(nearer.shared);
+    config.private_shared.extend(nearer.private_shared);
+    config.warnings.extend(nearer.warnings);
+    config.locked.extend(nearer.locked);
+    config.files.extend(nearer.files);
+    for env in nearer.environments {
+        match config.environments.iter_mut().find(|e| e.name == env.name) {
+            Some(existing) => {
+                existing.variables.extend(env.variables);
+                existing.private_variables.extend(env.private_variables);
+                existing.variable_sources.extend(env.variable_sources);
+                existing.auth.extend(env.auth);
+                existing.source_file = env.source_file;
+            }
+            None => config.environments.push(env),
+        }
+    }
+    config.environments.sort_by(|a, b| a.name.cmp(&b.name));
+}
+
+/// Map each variable to the file it came from
+fn sources(variables: &HashMap<String, String>, source_file: &str) -> HashMap<String, String> {
+    variables
+        .keys()
+        .map(|name| (name.clone(), source_file.to_string()))
+        .collect()
+}
+
+/// Load a public env file and its private counterpart, if either exists
+async fn load_env_json(
+    env_json_path: &Path,
+    private_env_path: &Path,
+) -> Result<Option<EnvironmentConfig>, String> {
     if env_json_path.exists() {
-        let mut config = parse_http_client_env(&env_json_path).await?;
+        let mut config = parse_http_client_env(env_json_path).await?;
 
         // Also try to load private env file - keep separate for editing
-        let private_env_path = workspace_path.join("http-client.private.env.json");
         if private_env_path.exists() {
-            if let Ok(private_config) = parse_http_client_env(&private_env_path).await {
+            let private_config = parse_private_env(private_env_path).await;
+            if let Ok(None) = private_config {
+                config
+                    .locked
+                    .push(private_env_path.to_string_lossy().to_string());
+            }
+            if let Ok(Some(private_config)) = private_config {
                 // Store private variables separately for each environment
                 for private_env in private_config.environments {
                     if let Some(env) = config
@@ -121,7 +920,9 @@ pub async fn load_environment_config(workspace: String) -> Result<EnvironmentCon
                         .find(|e| e.name == private_env.name)
                     {
                         // Store in private_variables, don't merge into variables
+                        env.variable_sources.extend(private_env.variable_sources);
                         env.private_variables = private_env.variables;
+                        merge_auth(&mut env.auth, private_env.auth);
                     } else {
                         // Environment only exists in private file
                         config.environments.push(Environment {
@@ -129,26 +930,40 @@ pub async fn load_environment_config(workspace: String) -> Result<EnvironmentCon
                             variables: HashMap::new(),
                             private_variables: private_env.variables,
                             source_file: private_env_path.to_string_lossy().to_string(),
+                            variable_sources: private_env.variable_sources,
+                            auth: private_env.auth,
                         });
                     }
                 }
                 // Store private shared variables separately
                 config.private_shared = private_config.shared;
+                config.warnings.extend(private_config.warnings);
+                config.files.extend(private_config.files);
             }
         }
 
         // Re-sort after potentially adding private-only environments
         config.environments.sort_by(|a, b| a.name.cmp(&b.name));
 
-        return Ok(config);
+        return Ok(Some(config));
     }
 
-    // Try http-client.private.env.json alone
-    let private_env_path = workspace_path.join("http-client.private.env.json");
+    // Try the private file alone
     if private_env_path.exists() {
-        let private_config = parse_http_client_env(&private_env_path).await?;
+        let Some(private_config) = parse_private_env(private_env_path).await? else {
+            return Ok(Some(EnvironmentConfig {
+                environments: vec![],
+                shared: HashMap::new(),
+                private_shared: HashMap::new(),
+                warnings: vec![],
+                locked: vec![private_env_path.to_string_lossy().to_string()],
+                files: vec![],
+                command_variables: HashMap::new(),
+                untrusted_commands: vec![],
+            }));
+        };
         // When only private file exists, put all vars in private_variables
-        return Ok(EnvironmentConfig {
+        return Ok(Some(EnvironmentConfig {
             environments: private_config
                 .environments
                 .into_iter()
@@ -157,91 +972,865 @@ pub async fn load_environment_config(workspace: String) -> Result<EnvironmentCon
                     variables: HashMap::new(),
                     private_variables: e.variables,
                     source_file: e.source_file,
+                    variable_sources: e.variable_sources,
+                    auth: e.auth,
                 })
                 .collect(),
             shared: HashMap::new(),
             private_shared: private_config.shared,
-        });
+            warnings: private_config.warnings,
+            locked: vec![],
+            files: private_config.files,
+            command_variables: HashMap::new(),
+            untrusted_commands: vec![],
+        }));
+    }
+
+    Ok(None)
+}
+
+/// Load the env files in one directory, if it has any
+async fn load_dir_config(dir: &Path) -> Result<Option<EnvironmentConfig>, String> {
+    // Try http-client.env.json and its private file first
+    let env_json_path = dir.join(ENV_FILE);
+    let private_env_path = dir.join("http-client.private.env.json");
+    if let Some(config) = load_env_json(&env_json_path, &private_env_path).await? {
+        return Ok(Some(config));
     }
 
     // Fallback to .env file
-    let dotenv_path = workspace_path.join(".env");
+    let dotenv_path = dir.join(".env");
     if dotenv_path.exists() {
         let content = tokio::fs::read_to_string(&dotenv_path)
             .await
-            .map_err(|e| e.to_string())?;
+            .map_err(|e| format!("Failed to read env file {}: {}", dotenv_path.display(), e))?;
 
         let vars = parse_dotenv(&content);
+        let source_file = dotenv_path.to_string_lossy().to_string();
 
-        return Ok(EnvironmentConfig {
+        return Ok(Some(EnvironmentConfig {
             environments: vec![Environment {
                 name: "default".to_string(),
+                variable_sources: sources(&vars, &source_file),
                 variables: vars,
                 private_variables: HashMap::new(),
-                source_file: dotenv_path.to_string_lossy().to_string(),
+                source_file: source_file.clone(),
+                auth: HashMap::new(),
             }],
             shared: HashMap::new(),
             private_shared: HashMap::new(),
-        });
+            warnings: vec![],
+            locked: vec![],
+            files: vec![source_file],
+            command_variables: HashMap::new(),
+            untrusted_commands: vec![],
+        }));
     }
 
-    // No env files found - return empty config
-    Ok(EnvironmentConfig {
-        environments: vec![],
-        shared: HashMap::new(),
-        private_shared: HashMap::new(),
-    })
+    // No env files in this directory
+    Ok(None)
 }
 
-/// Save or update an environment in the workspace
-#[tauri::command]
-pub async fn save_environment(
-    workspace: String,
-    env_name: String,
-    variables: HashMap<String, String>,
-    is_private: bool,
-) -> Result<(), String> {
-    let workspace_path = Path::new(&workspace);
-    let file_name = if is_private {
-        "http-client.private.env.json"
-    } else {
-        "http-client.env.json"
-    };
-    let file_path = workspace_path.join(file_name);
+/// How serious a diagnostic about an env or .http file is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiagnosticSeverity {
+    /// The file can't be loaded
+    Error,
+    /// The file loads, but probably not the way it was meant to
+    Warning,
+}
 
-    // Read existing config or create new one
-    let mut config: HashMap<String, HashMap<String, serde_json::Value>> = if file_path.exists() {
-        let content = tokio::fs::read_to_string(&file_path)
-            .await
-            .map_err(|e| format!("Failed to read env file: {}", e))?;
-        serde_json::from_str(&content).map_err(|e| format!("Failed to parse env file: {}", e))?
-    } else {
-        HashMap::new()
-    };
+/// A problem found in an env file. Lines and columns are 1-based.
+#[derive(Debug, Clone, Serialize)]
+pub struct EnvDiagnostic {
+    pub file: String,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub severity: DiagnosticSeverity,
+    pub message: String,
+}
 
-    // Convert variables to JSON values
-    let json_variables: HashMap<String, serde_json::Value> = variables
-        .into_iter()
-        .map(|(k, v)| (k, serde_json::Value::String(v)))
-        .collect();
+/// Check the env files a .http file would load (see [`load_environment_config`])
+/// and report syntax errors and likely mistakes, including variables that a
+/// glob-matched file and another file define differently. With `file_path`,
+/// `# @env` annotations in that file naming unknown environments are reported too.
+#[cfg_attr(feature = "app", tauri::command)]
+pub async fn validate_environment_config(
+    workspace: String,
+    file_path: Option<String>,
+    env_glob: Option<String>,
+) -> Result<Vec<EnvDiagnostic>, String> {
+    let mut diagnostics = Vec::new();
+    if let Some(file_path) = &file_path {
+        validate_env_annotations(
+            &workspace,
+            Path::new(file_path),
+            env_glob.clone(),
+            &mut diagnostics,
+        )
+        .await?;
+    }
+    let layers = env_layers(
+        Path::new(&workspace),
+        file_path.as_deref().map(Path::new),
+        env_glob.as_deref(),
+    )
+    .await?;
+    let mut checked = Vec::new();
+    for layer in layers {
+        let (public, private) = match &layer {
+            EnvLayer::Dir(dir) => (dir.join(ENV_FILE), dir.join("http-client.private.env.json")),
+            EnvLayer::Fragment(path) => (path.clone(), private_env_path(path)),
+        };
+        // Shared keys of the layer's public and private files, for the
+        // shadowing check
+        let mut files = Vec::new();
+        for (path, private) in [(public, false), (private, true)] {
+            if !path.exists() {
+                continue;
+            }
+            let content = tokio::fs::read_to_string(&path)
+                .await
+                .map_err(|e| format!("Failed to read env file {}: {}", path.display(), e))?;
+            // Encrypted files can't be checked without the passphrase
+            if encryption::is_encrypted(&content) {
+                continue;
+            }
+            if let Some(config) = validate_env_json(&path, &content, &mut diagnostics) {
+                files.push(CheckedEnvFile {
+                    path,
+                    content,
+                    config,
+                    private,
+                    fragment: matches!(layer, EnvLayer::Fragment(_)),
+                });
+            }
+        }
+        let shared: Vec<&String> = files
+            .iter()
+            .filter_map(|file| file.config.get("$shared")?.as_object())
+            .flat_map(|vars| vars.keys())
+            .collect();
+        for file in &files {
+            for (env_name, vars) in file.config.iter().filter(|(name, _)| *name != "$shared") {
+                let Some(vars) = vars.as_object() else {
+                    continue;
+                };
+                for key in vars.keys().filter(|key| shared.contains(key)) {
+                    diagnostics.push(diagnostic(
+                        &file.path,
+                        locate(&file.content, Some(env_name), key),
+                        DiagnosticSeverity::Warning,
+                        format!(
+                            "'{}' is also defined in $shared; the value in '{}' wins",
+                            key, env_name
+                        ),
+                    ));
+                }
+            }
+        }
 
-    // Update or insert the environment
-    config.insert(env_name, json_variables);
+        if let EnvLayer::Dir(dir) = &layer {
+            let dotenv_path = dir.join(".env");
+            if files.is_empty() && dotenv_path.exists() {
+                let content = tokio::fs::read_to_string(&dotenv_path).await.map_err(|e| {
+                    format!("Failed to read env file {}: {}", dotenv_path.display(), e)
+                })?;
+                validate_dotenv(&dotenv_path, &content, &mut diagnostics);
+            }
+        }
+        checked.extend(files);
+    }
+    validate_conflicts(&checked, &mut diagnostics);
+    Ok(diagnostics)
+}
 
-    // Write back to file with pretty formatting
-    let content = serde_json::to_string_pretty(&config)
-        .map_err(|e| format!("Failed to serialize env file: {}", e))?;
+/// An env file that parsed, kept for checks spanning several files
+struct CheckedEnvFile {
+    path: PathBuf,
+    content: String,
+    config: serde_json::Map<String, serde_json::Value>,
+    private: bool,
+    /// Matched by the env glob rather than loaded for its directory
+    fragment: bool,
+}
 
-    tokio::fs::write(&file_path, content)
+/// Report variables defined differently in a glob-matched file and another
+/// file. Overrides between directories are by design and not reported.
+/// `files` are in precedence order, lowest first.
+fn validate_conflicts(files: &[CheckedEnvFile], diagnostics: &mut Vec<EnvDiagnostic>) {
+    for (i, file) in files.iter().enumerate() {
+        for (env_name, vars) in &file.config {
+            let Some(vars) = vars.as_object() else {
+                continue;
+            };
+            for (key, value) in vars {
+                let defined = |other: &&CheckedEnvFile| {
+                    other.private == file.private
+                        && other
+                            .config
+                            .get(env_name)
+                            .and_then(|vars| vars.get(key))
+                            .is_some()
+                };
+                let Some(winner) = files[i + 1..].iter().rev().find(defined) else {
+                    continue;
+                };
+                if !(file.fragment || winner.fragment) || winner.config[env_name][key] == *value {
+                    continue;
+                }
+                diagnostics.push(diagnostic(
+                    &file.path,
+                    locate(&file.content, Some(env_name), key),
+                    DiagnosticSeverity::Warning,
+                    format!(
+                        "'{}' in '{}' is overridden by {}",
+                        key,
+                        env_name,
+                        winner.path.display()
+                    ),
+                ));
+            }
+        }
+    }
+}
+
+/// Report `# @env` annotations in a .http file naming environments that the
+/// env files don't define
+async fn validate_env_annotations(
+    workspace: &str,
+    file_path: &Path,
+    env_glob: Option<String>,
+    diagnostics: &mut Vec<EnvDiagnostic>,
+) -> Result<(), String> {
+    let content = tokio::fs::read_to_string(file_path)
         .await
-        .map_err(|e| format!("Failed to write env file: {}", e))?;
+        .map_err(|e| format!("Failed to read {}: {}", file_path.display(), e))?;
+    let requests = crate::parser::parse_http_content(&content)
+        .map_err(|e| format!("Failed to parse {}: {}", file_path.display(), e))?;
+    // Env files that don't parse are reported on their own
+    let Ok(config) = load_environment_config(
+        workspace.to_string(),
+        Some(file_path.to_string_lossy().to_string()),
+        env_glob,
+    )
+    .await
+    else {
+        return Ok(());
+    };
 
+    let annotation = Regex::new(&format!(
+        r"^#\s*@{}\s+(\S+)",
+        crate::variables::ENV_METADATA
+    ))
+    .unwrap();
+    for request in requests {
+        let Some(name) = request.metadata.get(crate::variables::ENV_METADATA) else {
+            continue;
+        };
+        if config.environments.iter().any(|env| &env.name == name) {
+            continue;
+        }
+        // The annotation naming it that is closest to the request
+        let line = content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| {
+                annotation
+                    .captures(line.trim())
+                    .is_some_and(|caps| &caps[1] == name)
+            })
+            .map(|(i, _)| i + 1)
+            .min_by_key(|line| line.abs_diff(request.line_number));
+        diagnostics.push(diagnostic(
+            file_path,
+            line.map(|line| (line, 1)),
+            DiagnosticSeverity::Error,
+            format!("Unknown environment '{}' in # @env", name),
+        ));
+    }
     Ok(())
 }
 
-#[cfg(test)]
+fn diagnostic(
+    path: &Path,
+    position: Option<(usize, usize)>,
+    severity: DiagnosticSeverity,
+    message: String,
+) -> EnvDiagnostic {
+    EnvDiagnostic {
+        file: path.to_string_lossy().to_string(),
+        line: position.map(|(line, _)| line),
+        column: position.map(|(_, column)| column),
+        severity,
+        message,
+    }
+}
+
+/// Check one http-client env file, returning it when it parses
+fn validate_env_json(
+    path: &Path,
+    content: &str,
+    diagnostics: &mut Vec<EnvDiagnostic>,
+) -> Option<serde_json::Map<String, serde_json::Value>> {
+    let value: serde_json::Value = match serde_json::from_str(content) {
+        Ok(value) => value,
+        Err(e) => {
+            // serde_json appends the position to its message; report it separately
+            let message = e.to_string();
+            let suffix = format!(" at line {} column {}", e.line(), e.column());
+            diagnostics.push(diagnostic(
+                path,
+                Some((e.line(), e.column())),
+                DiagnosticSeverity::Error,
+                message
+                    .strip_suffix(&suffix)
+                    .unwrap_or(&message)
+                    .to_string(),
+            ));
+            return None;
+        }
+    };
+    let serde_json::Value::Object(config) = value else {
+        diagnostics.push(diagnostic(
+            path,
+            Some((1, 1)),
+            DiagnosticSeverity::Error,
+            "Expected an object of environments".to_string(),
+        ));
+        return None;
+    };
+
+    let mut valid = true;
+    for (env_name, vars) in &config {
+        let Some(vars) = vars.as_object() else {
+            diagnostics.push(diagnostic(
+                path,
+                locate(content, None, env_name),
+                DiagnosticSeverity::Error,
+                format!("Environment '{}' must be an object of variables", env_name),
+            ));
+            valid = false;
+            continue;
+        };
+        if vars.is_empty() {
+            diagnostics.push(diagnostic(
+                path,
+                locate(content, None, env_name),
+                DiagnosticSeverity::Warning,
+                format!("Environment '{}' has no variables", env_name),
+            ));
+        }
+        for (key, value) in vars {
+            let message = match value {
+                serde_json::Value::String(_) => continue,
+                serde_json::Value::Number(_) | serde_json::Value::Bool(_) => format!(
+                    "'{}' is a {}; it is used as the string \"{}\"",
+                    key,
+                    if value.is_number() {
+                        "number"
+                    } else {
+                        "boolean"
+                    },
+                    value
+                ),
+                // Objects and arrays are reachable with dotted paths
+                serde_json::Value::Object(_) | serde_json::Value::Array(_) => continue,
+                serde_json::Value::Null => {
+                    format!("'{}' is null; it is used as the string \"null\"", key)
+                }
+            };
+            diagnostics.push(diagnostic(
+                path,
+                locate(content, Some(env_name), key),
+                DiagnosticSeverity::Warning,
+                message,
+            ));
+        }
+    }
+    valid.then_some(config)
+}
+
+/// Check a .env file for lines that aren't `KEY=value` assignments
+fn validate_dotenv(path: &Path, content: &str, diagnostics: &mut Vec<EnvDiagnostic>) {
+    for (line, column, message) in parse_dotenv_entries(content).errors {
+        diagnostics.push(diagnostic(
+            path,
+            Some((line, column)),
+            DiagnosticSeverity::Warning,
+            message,
+        ));
+    }
+}
+
+/// Line and column of a `"key":` in env file text, searching after the
+/// environment's own key when given
+fn locate(content: &str, env_name: Option<&str>, key: &str) -> Option<(usize, usize)> {
+    let find = |from: usize, name: &str| {
+        let quoted = serde_json::to_string(name).ok()?;
+        content[from..]
+            .match_indices(&quoted)
+            .find_map(|(offset, _)| {
+                let end = from + offset + quoted.len();
+                content[end..]
+                    .trim_start()
+                    .starts_with(':')
+                    .then_some(from + offset)
+            })
+    };
+    let start = match env_name {
+        Some(env_name) => find(0, env_name)?,
+        None => 0,
+    };
+    let offset = find(start, key)?;
+    let before = &content[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before[before.rfind('\n').map_or(0, |i| i + 1)..]
+        .chars()
+        .count()
+        + 1;
+    Some((line, column))
+}
+
+/// Save or update an environment in the workspace
+#[cfg_attr(feature = "app", tauri::command)]
+pub async fn save_environment(
+    workspace: String,
+    env_name: String,
+    variables: HashMap<String, String>,
+    is_private: bool,
+) -> Result<(), String> {
+    let workspace_path = Path::new(&workspace);
+    let file_name = if is_private {
+        "http-client.private.env.json"
+    } else {
+        "http-client.env.json"
+    };
+    let file_path = workspace_path.join(file_name);
+
+    // Read existing config or create new one
+    let mut config: HashMap<String, HashMap<String, serde_json::Value>> = if file_path.exists() {
+        let content = tokio::fs::read_to_string(&file_path)
+            .await
+            .map_err(|e| format!("Failed to read env file {}: {}", file_path.display(), e))?;
+        if encryption::is_encrypted(&content) {
+            return Err(encrypted_error(&file_path));
+        }
+        serde_json::from_str(&content)
+            .map_err(|e| format!("Failed to parse env file {}: {}", file_path.display(), e))?
+    } else {
+        HashMap::new()
+    };
+
+    // Convert variables to JSON values. Variables already kept in the
+    // keychain stay there; the file keeps its marker. Command-provided values
+    // keep their `$exec` marker.
+    let existing = config.get(&env_name);
+    let mut json_variables: HashMap<String, serde_json::Value> = variables
+        .into_iter()
+        .map(|(k, v)| {
+            if let Some(marker) = existing
+                .and_then(|env| env.get(&k))
+                .filter(|value| exec::is_marker(value))
+            {
+                return (k, marker.clone());
+            }
+            let secret = existing
+                .and_then(|env| env.get(&k))
+                .is_some_and(secrets::is_marker)
+                && secrets::set_secret(&workspace, &env_name, &k, &v).is_ok();
+            let value = if secret {
+                secrets::marker()
+            } else {
+                serde_json::Value::String(v)
+            };
+            (k, value)
+        })
+        .collect();
+
+    // Auth configs aren't edited as variables; keep them as written
+    if let Some(security) = existing.and_then(|env| env.get(SECURITY_KEY)) {
+        json_variables.insert(SECURITY_KEY.to_string(), security.clone());
+    }
+
+    // Update or insert the environment
+    config.insert(env_name, json_variables);
+
+    // Write back to file with pretty formatting
+    let content = serde_json::to_string_pretty(&config).map_err(|e| {
+        format!(
+            "Failed to serialize env file {}: {}",
+            file_path.display(),
+            e
+        )
+    })?;
+
+    tokio::fs::write(&file_path, content)
+        .await
+        .map_err(|e| format!("Failed to write env file {}: {}", file_path.display(), e))?;
+
+    Ok(())
+}
+
+/// Keys an environment merge adds or changes
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EnvMergeDiff {
+    pub added: Vec<String>,
+    pub overwritten: Vec<String>,
+}
+
+/// Merge variables into one environment of an env file, creating the file if needed.
+///
+/// Other environments and keys are left alone, key order is kept, and the
+/// file's indentation is reused. With `dry_run` nothing is written.
+pub async fn merge_env_file(
+    path: &Path,
+    env_name: &str,
+    variables: serde_json::Map<String, serde_json::Value>,
+    dry_run: bool,
+) -> Result<EnvMergeDiff, String> {
+    let (existing, mut config) = read_env_file(path).await?;
+    let env = env_object(&mut config, env_name)?;
+
+    let mut diff = EnvMergeDiff::default();
+    for (key, value) in variables {
+        match env.get(&key) {
+            None => diff.added.push(key.clone()),
+            Some(current) if *current != value => diff.overwritten.push(key.clone()),
+            Some(_) => continue,
+        }
+        env.insert(key, value);
+    }
+
+    if dry_run || (diff.added.is_empty() && diff.overwritten.is_empty()) {
+        return Ok(diff);
+    }
+    write_env_file(path, &config, existing.as_deref()).await?;
+    Ok(diff)
+}
+
+/// Env file a variable is written to
+fn env_file_path(workspace: &str, private: bool) -> PathBuf {
+    Path::new(workspace).join(if private {
+        "http-client.private.env.json"
+    } else {
+        "http-client.env.json"
+    })
+}
+
+/// Set one variable of an environment, creating the env file if needed.
+///
+/// With `secret` the value goes to the keychain and the file only gets a
+/// marker. If the keychain is unavailable the value is written to the file
+/// and a warning is returned.
+#[cfg_attr(feature = "app", tauri::command)]
+pub async fn save_environment_variable(
+    workspace: String,
+    environment: String,
+    key: String,
+    value: String,
+    private: bool,
+    secret: Option<bool>,
+) -> Result<Option<String>, String> {
+    let path = env_file_path(&workspace, private);
+    let (existing, mut config) = read_env_file(&path).await?;
+    let env = env_object(&mut config, &environment)?;
+
+    let mut warning = None;
+    let stored = if secret.unwrap_or(false) {
+        match secrets::set_secret(&workspace, &environment, &key, &value) {
+            Ok(()) => secrets::marker(),
+            Err(e) => {
+                warning = Some(format!("{}; saved to {} instead", e, path.display()));
+                serde_json::Value::String(value)
+            }
+        }
+    } else {
+        serde_json::Value::String(value)
+    };
+    if env.get(&key) == Some(&stored) {
+        return Ok(warning);
+    }
+    env.insert(key, stored);
+    write_env_file(&path, &config, existing.as_deref()).await?;
+    Ok(warning)
+}
+
+/// Remove one variable from an environment. Missing files and keys are not an error.
+#[cfg_attr(feature = "app", tauri::command)]
+pub async fn delete_environment_variable(
+    workspace: String,
+    environment: String,
+    key: String,
+    private: bool,
+) -> Result<(), String> {
+    let path = env_file_path(&workspace, private);
+    let (existing, mut config) = read_env_file(&path).await?;
+    let removed = match config.get_mut(&environment).and_then(|e| e.as_object_mut()) {
+        Some(env) => env.shift_remove(&key),
+        None => None,
+    };
+    let Some(removed) = removed else {
+        return Ok(());
+    };
+    write_env_file(&path, &config, existing.as_deref()).await?;
+    if secrets::is_marker(&removed) {
+        secrets::delete_secret(&workspace, &environment, &key)?;
+    }
+    Ok(())
+}
+
+/// What [`create_private_env`] changed in a workspace
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PrivateEnvScaffold {
+    /// Path of the private env file
+    pub env_file: String,
+    pub created_env_file: bool,
+    /// Whether the environment was added to the private env file
+    pub added_environment: bool,
+    /// Path of the .gitignore, if the workspace is in a git repo
+    pub gitignore: Option<String>,
+    pub created_gitignore: bool,
+    /// Lines appended to .gitignore
+    pub gitignore_entries: Vec<String>,
+}
+
+/// Files that hold secrets and should never be committed
+const PRIVATE_ENV_FILES: [&str; 2] = ["http-client.private.env.json", ".env"];
+
+/// Create http-client.private.env.json with an empty `environment` if it's
+/// missing, and list the private env files in the workspace .gitignore when
+/// the workspace is inside a git repo
+#[cfg_attr(feature = "app", tauri::command)]
+pub async fn create_private_env(
+    workspace: String,
+    environment: String,
+) -> Result<PrivateEnvScaffold, String> {
+    let path = env_file_path(&workspace, true);
+    let mut scaffold = PrivateEnvScaffold {
+        env_file: path.to_string_lossy().to_string(),
+        created_env_file: !path.exists(),
+        ..Default::default()
+    };
+
+    let (existing, mut config) = read_env_file(&path).await?;
+    if !config.contains_key(&environment) {
+        env_object(&mut config, &environment)?;
+        write_env_file(&path, &config, existing.as_deref()).await?;
+        scaffold.added_environment = true;
+    }
+
+    let workspace_path = Path::new(&workspace);
+    if workspace_path
+        .ancestors()
+        .any(|dir| dir.join(".git").exists())
+    {
+        let gitignore = workspace_path.join(".gitignore");
+        scaffold.created_gitignore = !gitignore.exists();
+        let mut content = if scaffold.created_gitignore {
+            String::new()
+        } else {
+            tokio::fs::read_to_string(&gitignore)
+                .await
+                .map_err(|e| format!("Failed to read {}: {}", gitignore.display(), e))?
+        };
+        let listed: Vec<&str> = content
+            .lines()
+            .map(|line| line.trim().trim_start_matches('/'))
+            .collect();
+        scaffold.gitignore_entries = PRIVATE_ENV_FILES
+            .iter()
+            .filter(|name| !listed.contains(name))
+            .map(|name| name.to_string())
+            .collect();
+
+        if !scaffold.gitignore_entries.is_empty() {
+            if !content.is_empty() && !content.ends_with('\n') {
+                content.push('\n');
+            }
+            for entry in &scaffold.gitignore_entries {
+                content.push_str(entry);
+                content.push('\n');
+            }
+            tokio::fs::write(&gitignore, content)
+                .await
+                .map_err(|e| format!("Failed to write {}: {}", gitignore.display(), e))?;
+        }
+        scaffold.gitignore = Some(gitignore.to_string_lossy().to_string());
+    }
+
+    Ok(scaffold)
+}
+
+/// Encrypt the workspace's private env file with a passphrase. The file
+/// stays unlocked for the rest of the session.
+#[cfg_attr(feature = "app", tauri::command)]
+pub async fn encrypt_private_env(workspace: String, passphrase: String) -> Result<(), String> {
+    let path = env_file_path(&workspace, true);
+    let content = tokio::fs::read_to_string(&path)
+        .await
+        .map_err(|e| format!("Failed to read env file {}: {}", path.display(), e))?;
+    if encryption::is_encrypted(&content) {
+        return Err(format!("{} is already encrypted", path.display()));
+    }
+    serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(&content)
+        .map_err(|e| format!("Failed to parse env file {}: {}", path.display(), e))?;
+
+    let envelope = encryption::encrypt(&content, &passphrase)?;
+    write_file_atomic(&path, envelope.as_bytes()).await?;
+    encryption::unlock(&path, content);
+    Ok(())
+}
+
+/// Unlock the workspace's encrypted private env file for this session
+#[cfg_attr(feature = "app", tauri::command)]
+pub async fn unlock_private_env(workspace: String, passphrase: String) -> Result<(), String> {
+    let path = env_file_path(&workspace, true);
+    let content = tokio::fs::read_to_string(&path)
+        .await
+        .map_err(|e| format!("Failed to read env file {}: {}", path.display(), e))?;
+    if !encryption::is_encrypted(&content) {
+        return Err(format!("{} is not encrypted", path.display()));
+    }
+    let plaintext = encryption::decrypt(&content, &passphrase)?;
+    parse_env_content(&path, &plaintext)?;
+    encryption::unlock(&path, plaintext);
+    Ok(())
+}
+
+/// Move every string value in the workspace's private env file to the
+/// keychain, leaving secret markers behind. `Security` blocks and other
+/// non-string values, such as `$exec` commands, stay in the file. Returns
+/// how many values were moved.
+#[cfg_attr(feature = "app", tauri::command)]
+pub async fn move_private_values_to_keychain(workspace: String) -> Result<usize, String> {
+    let path = env_file_path(&workspace, true);
+    let (existing, mut config) = read_env_file(&path).await?;
+
+    let mut moved = 0;
+    let mut failure = None;
+    'envs: for (env_name, variables) in config.iter_mut() {
+        let Some(variables) = variables.as_object_mut() else {
+            continue;
+        };
+        for (name, value) in variables.iter_mut() {
+            let Some(plain) = value.as_str().filter(|_| name != SECURITY_KEY) else {
+                continue;
+            };
+            if let Err(e) = secrets::set_secret(&workspace, env_name, name, plain) {
+                failure = Some(e);
+                break 'envs;
+            }
+            *value = secrets::marker();
+            moved += 1;
+        }
+    }
+
+    // Keep whatever made it into the keychain before a failure
+    if moved > 0 {
+        write_env_file(&path, &config, existing.as_deref()).await?;
+    }
+    match failure {
+        Some(e) => Err(format!("{} ({} values moved)", e, moved)),
+        None => Ok(moved),
+    }
+}
+
+/// Read an env file as an ordered JSON object, along with its raw content
+async fn read_env_file(
+    path: &Path,
+) -> Result<(Option<String>, serde_json::Map<String, serde_json::Value>), String> {
+    let existing = if path.exists() {
+        Some(
+            tokio::fs::read_to_string(path)
+                .await
+                .map_err(|e| format!("Failed to read env file {}: {}", path.display(), e))?,
+        )
+    } else {
+        None
+    };
+    let config = match &existing {
+        Some(content) if encryption::is_encrypted(content) => return Err(encrypted_error(path)),
+        Some(content) if !content.trim().is_empty() => serde_json::from_str(content)
+            .map_err(|e| format!("Failed to parse env file {}: {}", path.display(), e))?,
+        _ => serde_json::Map::new(),
+    };
+    Ok((existing, config))
+}
+
+fn encrypted_error(path: &Path) -> String {
+    format!(
+        "{} is encrypted; decrypt it before editing variables",
+        path.display()
+    )
+}
+
+/// The named environment's variables, inserting an empty environment if missing
+fn env_object<'a>(
+    config: &'a mut serde_json::Map<String, serde_json::Value>,
+    env_name: &str,
+) -> Result<&'a mut serde_json::Map<String, serde_json::Value>, String> {
+    config
+        .entry(env_name.to_string())
+        .or_insert_with(|| serde_json::Value::Object(serde_json::Map::new()))
+        .as_object_mut()
+        .ok_or_else(|| format!("Environment '{}' is not an object", env_name))
+}
+
+/// Write an env file atomically, reusing the indentation and trailing newline
+/// of `existing`
+async fn write_env_file(
+    path: &Path,
+    config: &serde_json::Map<String, serde_json::Value>,
+    existing: Option<&str>,
+) -> Result<(), String> {
+    // Reuse the indentation of the first indented line
+    let indent = existing
+        .and_then(|content| {
+            content
+                .lines()
+                .find(|line| line.starts_with([' ', '\t']))
+                .map(|line| &line[..line.len() - line.trim_start().len()])
+        })
+        .unwrap_or("  ");
+    let mut content = Vec::new();
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(indent.as_bytes());
+    let mut serializer = serde_json::Serializer::with_formatter(&mut content, formatter);
+    config
+        .serialize(&mut serializer)
+        .map_err(|e| format!("Failed to serialize env file {}: {}", path.display(), e))?;
+    if existing.is_none_or(|c| c.ends_with('\n')) {
+        content.push(b'\n');
+    }
+
+    write_file_atomic(path, &content).await
+}
+
+/// Write a file through a temp file next to it that is then renamed over it,
+/// so a crash never leaves a half-written env file behind
+async fn write_file_atomic(path: &Path, content: &[u8]) -> Result<(), String> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+    tokio::fs::write(&tmp_path, content)
+        .await
+        .map_err(|e| format!("Failed to write env file {}: {}", path.display(), e))?;
+    if let Err(e) = tokio::fs::rename(&tmp_path, path).await {
+        tokio::fs::remove_file(&tmp_path).await.ok();
+        return Err(format!(
+            "Failed to write env file {}: {}",
+            path.display(),
+            e
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::HashSet;
 
     #[test]
     fn test_parse_dotenv() {
@@ -264,4 +1853,1161 @@ SINGLE_QUOTED='single quotes'
             Some(&"single quotes".to_string())
         );
     }
+
+    fn dotenv(content: &str) -> Vec<(String, String)> {
+        let mut vars: Vec<_> = parse_dotenv(content).into_iter().collect();
+        vars.sort();
+        vars
+    }
+
+    fn pairs(expected: &[(&str, &str)]) -> Vec<(String, String)> {
+        let mut pairs: Vec<_> = expected
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        pairs.sort();
+        pairs
+    }
+
+    // The fixtures below are ported from the dotenvy test suite
+
+    #[test]
+    fn test_parse_dotenv_basic_fixture() {
+        let content = r#"
+KEY=1
+KEY2="2"
+KEY3='3'
+KEY4='fo ur'
+KEY5="fi ve"
+KEY6=s\ ix
+KEY7=
+KEY8=     
+KEY9=   # foo
+KEY10  ="whitespace before ="
+KEY11=    "whitespace after ="
+export="export as key"
+export   SHELL_LOVER=1
+"#;
+        assert_eq!(
+            dotenv(content),
+            pairs(&[
+                ("KEY", "1"),
+                ("KEY2", "2"),
+                ("KEY3", "3"),
+                ("KEY4", "fo ur"),
+                ("KEY5", "fi ve"),
+                ("KEY6", "s ix"),
+                ("KEY7", ""),
+                ("KEY8", ""),
+                ("KEY9", ""),
+                ("KEY10", "whitespace before ="),
+                ("KEY11", "whitespace after ="),
+                ("export", "export as key"),
+                ("SHELL_LOVER", "1"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_dotenv_multiline_fixture() {
+        let content = r#"
+KEY=my\ cool\ value
+KEY3="awesome \"stuff\"
+more
+on other
+lines"
+KEY4='hello '\''fds'"good ' \'morning"
+WEAK="KEY4"
+"#;
+        assert_eq!(
+            dotenv(content),
+            pairs(&[
+                ("KEY", "my cool value"),
+                ("KEY3", "awesome \"stuff\"\nmore\non other\nlines"),
+                ("KEY4", "hello 'fdsgood ' \\'morning"),
+                ("WEAK", "KEY4"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_dotenv_comments_fixture() {
+        let content = r#"
+# Start of .env
+# Comment line with single ' quote
+# Comment line with double " quote
+ # Comment line with double " quote and starts with a space
+TESTKEY1=test_val # 1 '" comment
+TESTKEY2=test_val_with_#_hash # 2 '" comment
+TESTKEY3="test_val quoted with # hash" # 3 '" comment
+TESTKEY4="Line 1
+# Line 2
+Line 3" # 4 Multi-line quoted value
+TESTKEY5="Line 4 \" # with escaped quote" # 5
+TESTKEY6='Line 5 # single quoted' # 6
+# End of .env
+"#;
+        assert_eq!(
+            dotenv(content),
+            pairs(&[
+                ("TESTKEY1", "test_val"),
+                ("TESTKEY2", "test_val_with_#_hash"),
+                ("TESTKEY3", "test_val quoted with # hash"),
+                ("TESTKEY4", "Line 1\n# Line 2\nLine 3"),
+                ("TESTKEY5", "Line 4 \" # with escaped quote"),
+                ("TESTKEY6", "Line 5 # single quoted"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_dotenv_substitution_fixture() {
+        let content = r#"
+KEY=value
+KEY1=${KEY}
+KEY2=$KEY
+KEY3=\$KEY
+KEY4='$KEY'
+KEY5="$KEY"
+KEY6=${KEY}1
+KEY7=${KEY}$KEY
+KEY8="${KEY}/api"
+KEY9=${UNDEFINED}x
+KEY10=$
+KEY11=${KEY
+"#;
+        assert_eq!(
+            dotenv(content),
+            pairs(&[
+                ("KEY", "value"),
+                ("KEY1", "value"),
+                ("KEY2", "value"),
+                ("KEY3", "$KEY"),
+                ("KEY4", "$KEY"),
+                ("KEY5", "value"),
+                ("KEY6", "value1"),
+                ("KEY7", "valuevalue"),
+                ("KEY8", "value/api"),
+                ("KEY9", "x"),
+                ("KEY10", "$"),
+                ("KEY11", "${KEY"),
+            ])
+        );
+
+        // Only keys defined earlier in the file are expanded
+        assert_eq!(
+            dotenv("URL=${HOST}/api\nHOST=example.com\n"),
+            pairs(&[("URL", "/api"), ("HOST", "example.com")])
+        );
+    }
+
+    #[test]
+    fn test_parse_dotenv_reports_unterminated_quotes() {
+        let parsed = parse_dotenv_entries("OK=1\nBROKEN=\"never closed\nLATER=2\n");
+        assert_eq!(parsed.vars.get("OK"), Some(&"1".to_string()));
+        assert!(!parsed.vars.contains_key("BROKEN"));
+        assert_eq!(
+            parsed.errors,
+            vec![(2, 1, "Unterminated double-quoted value".to_string())]
+        );
+    }
+
+    fn vars(pairs: &[(&str, &str)]) -> serde_json::Map<String, serde_json::Value> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), serde_json::Value::String(v.to_string())))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_merge_creates_env_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("http-client.env.json");
+
+        let diff = merge_env_file(&path, "dev", vars(&[("host", "localhost")]), false)
+            .await
+            .unwrap();
+        assert_eq!(diff.added, vec!["host"]);
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "{\n  \"dev\": {\n    \"host\": \"localhost\"\n  }\n}\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_merge_into_existing_env_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("http-client.env.json");
+        let original = "{\n    \"prod\": {\n        \"host\": \"example.com\"\n    },\n    \"dev\": {\n        \"port\": \"3000\",\n        \"host\": \"localhost\"\n    }\n}";
+        std::fs::write(&path, original).unwrap();
+
+        let incoming = vars(&[("host", "127.0.0.1"), ("port", "3000"), ("token", "abc")]);
+        let diff = merge_env_file(&path, "dev", incoming.clone(), true)
+            .await
+            .unwrap();
+        assert_eq!(diff.added, vec!["token"]);
+        assert_eq!(diff.overwritten, vec!["host"]);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), original);
+
+        merge_env_file(&path, "dev", incoming, false).await.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "{\n    \"prod\": {\n        \"host\": \"example.com\"\n    },\n    \"dev\": {\n        \"port\": \"3000\",\n        \"host\": \"127.0.0.1\",\n        \"token\": \"abc\"\n    }\n}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_save_variable_creates_private_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = dir.path().to_string_lossy().to_string();
+
+        save_environment_variable(
+            workspace,
+            "dev".into(),
+            "token".into(),
+            "abc".into(),
+            true,
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("http-client.private.env.json")).unwrap(),
+            "{\n  \"dev\": {\n    \"token\": \"abc\"\n  }\n}\n"
+        );
+        assert!(!dir.path().join("http-client.env.json").exists());
+        assert!(!dir.path().join("http-client.private.env.json.tmp").exists());
+    }
+
+    #[tokio::test]
+    async fn test_save_and_delete_variable_keep_other_environments() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = dir.path().to_string_lossy().to_string();
+        let path = dir.path().join("http-client.env.json");
+        std::fs::write(
+            &path,
+            "{\n    \"prod\": {\n        \"host\": \"example.com\"\n    },\n    \"dev\": {\n        \"host\": \"localhost\",\n        \"port\": \"3000\"\n    }\n}\n",
+        )
+        .unwrap();
+
+        save_environment_variable(
+            workspace.clone(),
+            "dev".into(),
+            "host".into(),
+            "127.0.0.1".into(),
+            false,
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "{\n    \"prod\": {\n        \"host\": \"example.com\"\n    },\n    \"dev\": {\n        \"host\": \"127.0.0.1\",\n        \"port\": \"3000\"\n    }\n}\n"
+        );
+
+        delete_environment_variable(workspace.clone(), "dev".into(), "host".into(), false)
+            .await
+            .unwrap();
+        // Deleting something that is not there leaves the file alone
+        delete_environment_variable(workspace, "staging".into(), "host".into(), false)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "{\n    \"prod\": {\n        \"host\": \"example.com\"\n    },\n    \"dev\": {\n        \"port\": \"3000\"\n    }\n}\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_secret_variable_round_trips_through_keychain() {
+        secrets::memory::install();
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = dir.path().to_string_lossy().to_string();
+
+        let warning = save_environment_variable(
+            workspace.clone(),
+            "dev".into(),
+            "token".into(),
+            "abc".into(),
+            true,
+            Some(true),
+        )
+        .await
+        .unwrap();
+        assert_eq!(warning, None);
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("http-client.private.env.json")).unwrap(),
+            "{\n  \"dev\": {\n    \"token\": {\n      \"$secret\": true\n    }\n  }\n}\n"
+        );
+        assert_eq!(
+            secrets::memory::stored(&workspace, "dev", "token").as_deref(),
+            Some("abc")
+        );
+
+        let config = load_environment_config(workspace.clone(), None, None)
+            .await
+            .unwrap();
+        assert_eq!(config.environments[0].private_variables["token"], "abc");
+        assert!(config.warnings.is_empty());
+
+        delete_environment_variable(workspace.clone(), "dev".into(), "token".into(), true)
+            .await
+            .unwrap();
+        assert_eq!(secrets::memory::stored(&workspace, "dev", "token"), None);
+    }
+
+    #[tokio::test]
+    async fn test_secret_falls_back_to_file_without_keychain() {
+        secrets::memory::install();
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = dir.path().to_string_lossy().to_string();
+        secrets::memory::lock(&workspace);
+
+        let warning = save_environment_variable(
+            workspace.clone(),
+            "dev".into(),
+            "token".into(),
+            "abc".into(),
+            true,
+            Some(true),
+        )
+        .await
+        .unwrap();
+        assert!(warning.unwrap().contains("keychain"));
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("http-client.private.env.json")).unwrap(),
+            "{\n  \"dev\": {\n    \"token\": \"abc\"\n  }\n}\n"
+        );
+
+        // A marker that can't be resolved is dropped and reported
+        std::fs::write(
+            dir.path().join("http-client.env.json"),
+            r#"{"dev": {"host": "localhost", "key": {"$secret": true}}}"#,
+        )
+        .unwrap();
+        let config = load_environment_config(workspace, None, None)
+            .await
+            .unwrap();
+        let dev = &config.environments[0];
+        assert_eq!(dev.variables.get("key"), None);
+        assert_eq!(dev.variables["host"], "localhost");
+        assert_eq!(config.warnings.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_move_private_values_to_keychain() {
+        secrets::memory::install();
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = dir.path().to_string_lossy().to_string();
+        std::fs::write(
+            dir.path().join("http-client.private.env.json"),
+            r#"{"$shared": {"apiKey": "k1"}, "dev": {"token": "t1", "port": 3000, "done": {"$secret": true}}}"#,
+        )
+        .unwrap();
+        secrets::set_secret(&workspace, "dev", "done", "already").unwrap();
+
+        let moved = move_private_values_to_keychain(workspace.clone())
+            .await
+            .unwrap();
+        assert_eq!(moved, 2);
+        let content =
+            std::fs::read_to_string(dir.path().join("http-client.private.env.json")).unwrap();
+        assert!(!content.contains("t1") && !content.contains("k1"));
+        assert!(content.contains("3000"));
+
+        let config = load_environment_config(workspace, None, None)
+            .await
+            .unwrap();
+        assert_eq!(config.private_shared["apiKey"], "k1");
+        let dev = &config.environments[0];
+        assert_eq!(dev.private_variables["token"], "t1");
+        assert_eq!(dev.private_variables["port"], "3000");
+        assert_eq!(dev.private_variables["done"], "already");
+    }
+
+    #[tokio::test]
+    async fn test_move_private_values_keeps_security_and_commands() {
+        secrets::memory::install();
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = dir.path().to_string_lossy().to_string();
+        let private = dir.path().join("http-client.private.env.json");
+        std::fs::write(
+            &private,
+            r#"{"dev": {
+                "token": "t2",
+                "cmd": {"$exec": "echo hi"},
+                "Security": {"Auth": {"auth-id": {"Client Secret": "my-client-secret"}}}
+            }}"#,
+        )
+        .unwrap();
+
+        let moved = move_private_values_to_keychain(workspace.clone())
+            .await
+            .unwrap();
+        assert_eq!(moved, 1);
+        let content: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&private).unwrap()).unwrap();
+        assert_eq!(
+            content["dev"]["token"],
+            serde_json::json!({"$secret": true})
+        );
+        assert_eq!(
+            content["dev"]["cmd"],
+            serde_json::json!({"$exec": "echo hi"})
+        );
+        assert_eq!(
+            content["dev"]["Security"]["Auth"]["auth-id"]["Client Secret"],
+            "my-client-secret"
+        );
+
+        let config = load_environment_config(workspace, None, None)
+            .await
+            .unwrap();
+        assert!(config.warnings.is_empty(), "{:?}", config.warnings);
+        let dev = &config.environments[0];
+        assert_eq!(dev.private_variables["token"], "t2");
+        assert_eq!(
+            dev.auth["auth-id"].client_secret.as_deref(),
+            Some("my-client-secret")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_validate_reports_syntax_error_position() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("http-client.env.json");
+        std::fs::write(
+            &path,
+            "{\n  \"dev\": {\n    \"host\": \"localhost\",\n  }\n}\n",
+        )
+        .unwrap();
+
+        let diagnostics =
+            validate_environment_config(dir.path().to_string_lossy().to_string(), None, None)
+                .await
+                .unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        let error = &diagnostics[0];
+        assert_eq!(error.file, path.to_string_lossy());
+        assert_eq!(error.severity, DiagnosticSeverity::Error);
+        assert_eq!((error.line, error.column), (Some(4), Some(3)));
+        assert_eq!(error.message, "trailing comma");
+
+        // The loader names the file too
+        let err = load_environment_config(dir.path().to_string_lossy().to_string(), None, None)
+            .await
+            .unwrap_err();
+        assert!(err.contains(&*path.to_string_lossy()));
+    }
+
+    #[tokio::test]
+    async fn test_validate_reports_semantic_warnings() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("http-client.env.json"),
+            "{\n  \"$shared\": {\"host\": \"shared\"},\n  \"dev\": {\n    \"host\": \"dev\",\n    \"port\": 3000,\n    \"opts\": null\n  },\n  \"prod\": {}\n}\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("http-client.private.env.json"),
+            r#"{"$shared": {"token": "x"}, "dev": {"token": "y", "key": {"$secret": true}}}"#,
+        )
+        .unwrap();
+
+        let diagnostics =
+            validate_environment_config(dir.path().to_string_lossy().to_string(), None, None)
+                .await
+                .unwrap();
+        let found: Vec<_> = diagnostics
+            .iter()
+            .map(|d| (d.line, d.severity, d.message.as_str()))
+            .collect();
+        assert!(found.contains(&(
+            Some(5),
+            DiagnosticSeverity::Warning,
+            "'port' is a number; it is used as the string \"3000\""
+        )));
+        assert!(found.contains(&(
+            Some(6),
+            DiagnosticSeverity::Warning,
+            "'opts' is null; it is used as the string \"null\""
+        )));
+        assert!(found.contains(&(
+            Some(8),
+            DiagnosticSeverity::Warning,
+            "Environment 'prod' has no variables"
+        )));
+        assert!(found.contains(&(
+            Some(4),
+            DiagnosticSeverity::Warning,
+            "'host' is also defined in $shared; the value in 'dev' wins"
+        )));
+        assert!(found.contains(&(
+            Some(1),
+            DiagnosticSeverity::Warning,
+            "'token' is also defined in $shared; the value in 'dev' wins"
+        )));
+        assert_eq!(diagnostics.len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_validate_reports_malformed_dotenv_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join(".env"),
+            "# comment\nHOST=localhost\nnot an assignment\n=value\nMY KEY=1\n",
+        )
+        .unwrap();
+
+        let diagnostics =
+            validate_environment_config(dir.path().to_string_lossy().to_string(), None, None)
+                .await
+                .unwrap();
+        let found: Vec<_> = diagnostics
+            .iter()
+            .map(|d| (d.line.unwrap(), d.message.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (3, "Expected KEY=value, found 'not an assignment'"),
+                (4, "Missing variable name before '='"),
+                (5, "Variable name 'MY KEY' contains whitespace"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_nested_values_resolve_by_path() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("http-client.env.json"),
+            r#"{"dev": {"auth": {"clientId": "x", "tenant": "y"}, "hosts": ["a", "b"]}}"#,
+        )
+        .unwrap();
+
+        let config = load_environment_config(dir.path().to_string_lossy().to_string(), None, None)
+            .await
+            .unwrap();
+        let vars = crate::variables::environment_variables(&config, Some("dev")).unwrap();
+        assert_eq!(vars["auth"], r#"{"clientId":"x","tenant":"y"}"#);
+        assert_eq!(
+            crate::variables::substitute("{{auth.clientId}}/{{auth.tenant}}/{{hosts.1}}", &vars),
+            "x/y/b"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_security_auth_configs() {
+        let dir = tempfile::tempdir().unwrap();
+        // The example from the JetBrains HTTP client documentation
+        std::fs::write(
+            dir.path().join("http-client.env.json"),
+            r#"{
+                "dev": {
+                    "host": "dev.test",
+                    "Security": {
+                        "Auth": {
+                            "auth-id": {
+                                "Type": "OAuth2",
+                                "Grant Type": "Authorization Code",
+                                "Auth URL": "https://my-authorization-server/authorize",
+                                "Token URL": "https://my-authorization-server/token",
+                                "Redirect URL": "https://my-redirect-url",
+                                "Client ID": "my-client-id",
+                                "Client Credentials": "in body",
+                                "Scope": "openid my-scope",
+                                "PKCE": {"Code Challenge Method": "Plain"}
+                            },
+                            "kerberos": {"Type": "Kerberos", "Realm": "CORP"},
+                            "service": {"Type": "OAuth2", "Grant Type": "Magic"}
+                        }
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("http-client.private.env.json"),
+            r#"{"dev": {"Security": {"Auth": {"auth-id": {"Client Secret": "my-client-secret"}}}}}"#,
+        )
+        .unwrap();
+
+        let workspace = dir.path().to_string_lossy().to_string();
+        let config = load_environment_config(workspace.clone(), None, None)
+            .await
+            .unwrap();
+        let dev = &config.environments[0];
+        // Auth configs are not variables
+        assert_eq!(dev.variables.keys().collect::<Vec<_>>(), vec!["host"]);
+
+        let auth = &dev.auth["auth-id"];
+        assert!(auth.is_supported());
+        assert_eq!(auth.grant_type, Some(GrantType::AuthorizationCode));
+        assert_eq!(
+            auth.auth_url.as_deref(),
+            Some("https://my-authorization-server/authorize")
+        );
+        assert_eq!(
+            auth.token_url.as_deref(),
+            Some("https://my-authorization-server/token")
+        );
+        assert_eq!(
+            auth.redirect_url.as_deref(),
+            Some("https://my-redirect-url")
+        );
+        assert_eq!(auth.client_id.as_deref(), Some("my-client-id"));
+        assert_eq!(auth.client_secret.as_deref(), Some("my-client-secret"));
+        assert_eq!(
+            auth.client_auth_method,
+            Some(TokenEndpointAuthMethod::ClientSecretPost)
+        );
+        assert_eq!(auth.scopes, vec!["openid", "my-scope"]);
+        assert_eq!(auth.pkce, Some(true));
+
+        // Unknown types are kept as written, with a warning
+        let kerberos = &dev.auth["kerberos"];
+        assert!(!kerberos.is_supported());
+        assert_eq!(kerberos.raw["Realm"], "CORP");
+        assert_eq!(config.warnings.len(), 2);
+        assert!(config.warnings[0].contains("'kerberos'"));
+        assert!(config.warnings[0].contains("has type 'Kerberos'"));
+        assert!(config
+            .warnings
+            .iter()
+            .any(|w| w.contains("unknown grant type \"Magic\"")));
+
+        // Editing the environment's variables keeps its auth configs
+        let variables = HashMap::from([("host".to_string(), "dev2.test".to_string())]);
+        save_environment(workspace.clone(), "dev".into(), variables, false)
+            .await
+            .unwrap();
+        let config = load_environment_config(workspace, None, None)
+            .await
+            .unwrap();
+        assert_eq!(config.environments[0].variables["host"], "dev2.test");
+        assert_eq!(config.environments[0].auth.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_create_private_env_in_fresh_repo() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join(".git")).unwrap();
+        let workspace = dir.path().to_string_lossy().to_string();
+
+        let scaffold = create_private_env(workspace.clone(), "dev".into())
+            .await
+            .unwrap();
+        assert!(scaffold.created_env_file && scaffold.added_environment);
+        assert!(scaffold.created_gitignore);
+        assert_eq!(
+            scaffold.gitignore_entries,
+            vec!["http-client.private.env.json", ".env"]
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("http-client.private.env.json")).unwrap(),
+            "{\n  \"dev\": {}\n}\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join(".gitignore")).unwrap(),
+            "http-client.private.env.json\n.env\n"
+        );
+
+        // A second environment goes into the same file; .gitignore is untouched
+        let scaffold = create_private_env(workspace, "prod".into()).await.unwrap();
+        assert!(!scaffold.created_env_file && scaffold.added_environment);
+        assert!(!scaffold.created_gitignore && scaffold.gitignore_entries.is_empty());
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("http-client.private.env.json")).unwrap(),
+            "{\n  \"dev\": {},\n  \"prod\": {}\n}\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_private_env_keeps_existing_gitignore_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = dir.path().join("api");
+        std::fs::create_dir_all(&workspace).unwrap();
+        // The repo root is above the workspace
+        std::fs::create_dir(dir.path().join(".git")).unwrap();
+        std::fs::write(
+            workspace.join(".gitignore"),
+            "node_modules\n/http-client.private.env.json",
+        )
+        .unwrap();
+
+        let scaffold = create_private_env(workspace.to_string_lossy().to_string(), "dev".into())
+            .await
+            .unwrap();
+        assert!(!scaffold.created_gitignore);
+        assert_eq!(scaffold.gitignore_entries, vec![".env"]);
+        assert_eq!(
+            std::fs::read_to_string(workspace.join(".gitignore")).unwrap(),
+            "node_modules\n/http-client.private.env.json\n.env\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_private_env_outside_git() {
+        let dir = tempfile::tempdir().unwrap();
+        let scaffold = create_private_env(dir.path().to_string_lossy().to_string(), "dev".into())
+            .await
+            .unwrap();
+        assert!(scaffold.created_env_file);
+        assert_eq!(scaffold.gitignore, None);
+        assert!(!dir.path().join(".gitignore").exists());
+    }
+
+    #[tokio::test]
+    async fn test_validate_reports_unknown_env_annotation() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("http-client.env.json"),
+            r#"{"prod": {"host": "prod.test"}, "staging": {"host": "staging.test"}}"#,
+        )
+        .unwrap();
+        let http = dir.path().join("api.http");
+        std::fs::write(
+            &http,
+            "### Health\n# @env prod\nGET https://{{host}}/health\n\n### Update\n# @env qa\nPOST https://{{host}}/update\n",
+        )
+        .unwrap();
+
+        let diagnostics = validate_environment_config(
+            dir.path().to_string_lossy().to_string(),
+            Some(http.to_string_lossy().to_string()),
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].file, http.to_string_lossy());
+        assert_eq!(diagnostics[0].line, Some(6));
+        assert_eq!(diagnostics[0].message, "Unknown environment 'qa' in # @env");
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_private_env_is_locked_until_unlocked() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = dir.path().to_string_lossy().to_string();
+        let private = dir.path().join("http-client.private.env.json");
+        std::fs::write(
+            dir.path().join("http-client.env.json"),
+            r#"{"dev": {"host": "localhost"}}"#,
+        )
+        .unwrap();
+        std::fs::write(&private, r#"{"dev": {"token": "abc"}}"#).unwrap();
+
+        encrypt_private_env(workspace.clone(), "hunter2".into())
+            .await
+            .unwrap();
+        let content = std::fs::read_to_string(&private).unwrap();
+        assert!(encryption::is_encrypted(&content) && !content.contains("abc"));
+        // Encrypting stays unlocked for the session
+        let config = load_environment_config(workspace.clone(), None, None)
+            .await
+            .unwrap();
+        assert_eq!(config.environments[0].private_variables["token"], "abc");
+        assert!(config.locked.is_empty());
+
+        // A fresh session sees the file as locked rather than failing
+        let other = tempfile::tempdir().unwrap();
+        let other_workspace = other.path().to_string_lossy().to_string();
+        std::fs::write(other.path().join("http-client.private.env.json"), &content).unwrap();
+        let config = load_environment_config(other_workspace.clone(), None, None)
+            .await
+            .unwrap();
+        assert!(config.environments.is_empty());
+        assert_eq!(
+            config.locked,
+            vec![other
+                .path()
+                .join("http-client.private.env.json")
+                .to_string_lossy()
+                .to_string()]
+        );
+        assert!(save_environment_variable(
+            other_workspace.clone(),
+            "dev".into(),
+            "x".into(),
+            "1".into(),
+            true,
+            None
+        )
+        .await
+        .unwrap_err()
+        .contains("is encrypted"));
+
+        assert_eq!(
+            unlock_private_env(other_workspace.clone(), "wrong".into())
+                .await
+                .unwrap_err(),
+            "Wrong passphrase, or the encrypted env file was modified"
+        );
+        unlock_private_env(other_workspace.clone(), "hunter2".into())
+            .await
+            .unwrap();
+        let config = load_environment_config(other_workspace, None, None)
+            .await
+            .unwrap();
+        assert!(config.locked.is_empty());
+        assert_eq!(config.environments[0].private_variables["token"], "abc");
+    }
+
+    #[tokio::test]
+    async fn test_glob_merges_env_fragments() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        write(
+            &root.join("http-client.env.json"),
+            r#"{"dev": {"host": "root.test", "timeout": "30"}, "prod": {"host": "prod.test"}}"#,
+        );
+        write(
+            &root.join("envs").join("orders.http-client.env.json"),
+            r#"{"dev": {"host": "orders.test", "ordersPath": "/orders"}}"#,
+        );
+        write(
+            &root
+                .join("envs")
+                .join("orders.http-client.private.env.json"),
+            r#"{"dev": {"ordersKey": "k1"}}"#,
+        );
+        write(
+            &root
+                .join("envs")
+                .join("teams")
+                .join("billing.http-client.env.json"),
+            r#"{"dev": {"host": "billing.test"}, "prod": {"billingPath": "/billing"}}"#,
+        );
+        let workspace = root.to_string_lossy().to_string();
+        let source = |path: PathBuf| path.to_string_lossy().to_string();
+
+        // The default glob only picks up per-directory files
+        let config = load_environment_config(workspace.clone(), None, None)
+            .await
+            .unwrap();
+        assert_eq!(config.environments[0].variables["host"], "root.test");
+        assert_eq!(
+            config.files,
+            vec![source(root.join("http-client.env.json"))]
+        );
+
+        let config = load_environment_config(
+            workspace.clone(),
+            None,
+            Some("**/*http-client.env.json".to_string()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            config.files,
+            vec![
+                source(root.join("http-client.env.json")),
+                source(root.join("envs").join("orders.http-client.env.json")),
+                source(
+                    root.join("envs")
+                        .join("orders.http-client.private.env.json")
+                ),
+                source(
+                    root.join("envs")
+                        .join("teams")
+                        .join("billing.http-client.env.json")
+                ),
+            ]
+        );
+        let dev = &config.environments[0];
+        assert_eq!(dev.variables["host"], "billing.test");
+        assert_eq!(dev.variables["ordersPath"], "/orders");
+        assert_eq!(dev.variables["timeout"], "30");
+        assert_eq!(dev.private_variables["ordersKey"], "k1");
+        assert_eq!(
+            dev.variable_sources["ordersPath"],
+            source(root.join("envs").join("orders.http-client.env.json"))
+        );
+        assert_eq!(
+            dev.variable_sources["ordersKey"],
+            source(
+                root.join("envs")
+                    .join("orders.http-client.private.env.json")
+            )
+        );
+        let prod = &config.environments[1];
+        assert_eq!(prod.variables["host"], "prod.test");
+        assert_eq!(prod.variables["billingPath"], "/billing");
+
+        let err = load_environment_config(workspace, None, Some("[".to_string()))
+            .await
+            .unwrap_err();
+        assert!(err.starts_with("Invalid env file glob"));
+    }
+
+    #[tokio::test]
+    async fn test_validate_reports_fragment_conflicts() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        write(
+            &root.join("http-client.env.json"),
+            r#"{"dev": {"host": "root.test", "timeout": "30"}}"#,
+        );
+        write(
+            &root.join("envs").join("orders.http-client.env.json"),
+            r#"{"dev": {"host": "orders.test", "timeout": "30"}}"#,
+        );
+        write(
+            &root.join("envs").join("users.http-client.env.json"),
+            r#"{"dev": {"host": "users.test"}}"#,
+        );
+
+        let diagnostics = validate_environment_config(
+            root.to_string_lossy().to_string(),
+            None,
+            Some("envs/*.http-client.env.json".to_string()),
+        )
+        .await
+        .unwrap();
+        let users = root.join("envs").join("users.http-client.env.json");
+        let found: Vec<_> = diagnostics
+            .iter()
+            .map(|d| (d.file.clone(), d.line, d.message.clone()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    root.join("http-client.env.json")
+                        .to_string_lossy()
+                        .to_string(),
+                    Some(1),
+                    format!("'host' in 'dev' is overridden by {}", users.display()),
+                ),
+                (
+                    root.join("envs")
+                        .join("orders.http-client.env.json")
+                        .to_string_lossy()
+                        .to_string(),
+                    Some(1),
+                    format!("'host' in 'dev' is overridden by {}", users.display()),
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_exec_values_run_only_when_trusted() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            &dir.path().join("http-client.env.json"),
+            r#"{"$shared": {"region": {"$exec": "echo eu", "ttl": 60}},
+                "dev": {"token": {"$exec": "echo dev-token"}, "broken": {"$exec": "exit 2"}, "host": "dev.test"}}"#,
+        );
+        let workspace = dir.path().to_string_lossy().to_string();
+        let mut request = crate::parser::ParsedRequest::new();
+        request.url = "https://{{host}}/{{region}}?broken={{broken}}".into();
+        request
+            .headers
+            .insert("Authorization".into(), "Bearer {{token}}".into());
+        let resolve = |mut config: EnvironmentConfig| {
+            let workspace = workspace.clone();
+            let request = request.clone();
+            async move {
+                let resolved = crate::variables::resolve_request_with_commands(
+                    &workspace,
+                    &request,
+                    &mut config,
+                    Some("dev"),
+                    &Default::default(),
+                )
+                .await
+                .unwrap();
+                (resolved, config)
+            }
+        };
+
+        let config = load_environment_config(workspace.clone(), None, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            config.untrusted_commands,
+            vec!["echo dev-token", "echo eu", "exit 2"]
+        );
+        assert_eq!(config.command_variables["$shared"], vec!["region"]);
+        assert_eq!(config.command_variables["dev"], vec!["broken", "token"]);
+        let (resolved, _) = resolve(config).await;
+        assert_eq!(resolved.missing, vec!["broken", "region", "token"]);
+        assert_eq!(
+            resolved.request.url,
+            "https://dev.test/{{region}}?broken={{broken}}"
+        );
+
+        exec::set_trusted(&workspace, true);
+        let config = load_environment_config(workspace.clone(), None, None)
+            .await
+            .unwrap();
+        assert!(config.untrusted_commands.is_empty());
+        // Commands run as a request uses their values, not on load
+        assert!(exec::parse_marker(&config.environments[0].variables["token"]).is_some());
+        let (resolved, config) = resolve(config).await;
+        assert_eq!(
+            resolved.request.url,
+            "https://dev.test/eu?broken={{broken}}"
+        );
+        assert_eq!(
+            resolved.request.headers["Authorization"],
+            "Bearer dev-token"
+        );
+        assert_eq!(resolved.missing, vec!["broken"]);
+        assert_eq!(config.warnings.len(), 1);
+        assert!(config.warnings[0].starts_with("'broken': Command 'exit 2' failed"));
+        assert_eq!(
+            crate::variables::secret_names(&config, Some("dev"))
+                .into_iter()
+                .collect::<HashSet<_>>(),
+            HashSet::from([
+                "region".to_string(),
+                "token".to_string(),
+                "broken".to_string()
+            ])
+        );
+
+        // Saving the environment keeps the commands, not their output
+        let dev = &config.environments[0];
+        save_environment(
+            workspace.clone(),
+            "dev".to_string(),
+            dev.variables.clone(),
+            false,
+        )
+        .await
+        .unwrap();
+        let saved: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(dir.path().join("http-client.env.json")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(saved["dev"]["token"]["$exec"], "echo dev-token");
+        exec::set_trusted(&workspace, false);
+    }
+
+    #[tokio::test]
+    async fn test_exec_values_not_used_never_run() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            &dir.path().join("http-client.env.json"),
+            r#"{"$shared": {"unused": {"$exec": "touch ran-unused"}},
+                "staging": {"token": {"$exec": "touch ran-staging && echo s"}, "host": "staging.test"},
+                "prod": {"token": {"$exec": "touch ran-prod"}}}"#,
+        );
+        write(
+            &dir.path().join("http-client.private.env.json"),
+            r#"{"staging": {"host": {"$exec": "touch ran-private"}}}"#,
+        );
+        let workspace = dir.path().to_string_lossy().to_string();
+        exec::set_trusted(&workspace, true);
+        let mut request = crate::parser::ParsedRequest::new();
+        request.url = "https://api.test/{{token}}".into();
+
+        let mut config = load_environment_config(workspace.clone(), None, None)
+            .await
+            .unwrap();
+        let resolved = crate::variables::resolve_request_with_commands(
+            &workspace,
+            &request,
+            &mut config,
+            Some("staging"),
+            &Default::default(),
+        )
+        .await
+        .unwrap();
+        exec::set_trusted(&workspace, false);
+
+        assert_eq!(resolved.request.url, "https://api.test/s");
+        assert!(dir.path().join("ran-staging").exists());
+        for unused in ["ran-unused", "ran-prod", "ran-private"] {
+            assert!(!dir.path().join(unused).exists(), "{} ran", unused);
+        }
+    }
+
+    fn write(path: &Path, content: &str) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_nested_env_files_closest_wins() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let orders = root.join("services").join("orders");
+        write(
+            &root.join("http-client.env.json"),
+            r#"{"$shared": {"region": "eu"}, "dev": {"host": "root.test", "timeout": "30"}}"#,
+        );
+        write(
+            &root.join("services").join("http-client.env.json"),
+            r#"{"dev": {"host": "services.test", "version": "v2"}, "staging": {"host": "staging.test"}}"#,
+        );
+        write(
+            &orders.join("http-client.env.json"),
+            r#"{"$shared": {"region": "us"}, "dev": {"host": "orders.test"}}"#,
+        );
+        write(
+            &orders.join("http-client.private.env.json"),
+            r#"{"dev": {"token": "secret"}}"#,
+        );
+        let workspace = root.to_string_lossy().to_string();
+        let source = |path: PathBuf| path.to_string_lossy().to_string();
+
+        let config = load_environment_config(
+            workspace.clone(),
+            Some(source(orders.join("api.http"))),
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(config.shared["region"], "us");
+        assert_eq!(
+            config
+                .environments
+                .iter()
+                .map(|e| e.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["dev", "staging"]
+        );
+        let dev = &config.environments[0];
+        assert_eq!(dev.variables["host"], "orders.test");
+        assert_eq!(dev.variables["version"], "v2");
+        assert_eq!(dev.variables["timeout"], "30");
+        assert_eq!(dev.private_variables["token"], "secret");
+        assert_eq!(dev.source_file, source(orders.join("http-client.env.json")));
+        assert_eq!(
+            dev.variable_sources["version"],
+            source(root.join("services").join("http-client.env.json"))
+        );
+        assert_eq!(
+            dev.variable_sources["timeout"],
+            source(root.join("http-client.env.json"))
+        );
+        assert_eq!(
+            dev.variable_sources["token"],
+            source(orders.join("http-client.private.env.json"))
+        );
+
+        // A sibling directory only sees the levels above it
+        let billing = load_environment_config(
+            workspace.clone(),
+            Some(source(
+                root.join("services").join("billing").join("api.http"),
+            )),
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(billing.environments[0].variables["host"], "services.test");
+        assert_eq!(billing.shared["region"], "eu");
+
+        // Without a file only the workspace root is used
+        let root_only = load_environment_config(workspace, None, None)
+            .await
+            .unwrap();
+        assert_eq!(root_only.environments.len(), 1);
+        assert_eq!(root_only.environments[0].variables["host"], "root.test");
+    }
 }
@@ -0,0 +1,244 @@
+//! Env values provided by shell commands.
+//!
+//! A value of `{"$exec": "gcloud auth print-access-token", "ttl": 300}` is
+//! replaced by the trimmed output of the command when a request uses it,
+//! run in the workspace directory and cached for `ttl` seconds. Env files can come from a repo, so
+//! commands only run in workspaces the user has trusted.
+
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Key of the object marking an env value as command-provided
+pub const EXEC_MARKER_KEY: &str = "$exec";
+
+/// How long a command may run before it is killed
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Workspaces whose env file commands may run
+static TRUSTED: Mutex<Option<HashSet<String>>> = Mutex::new(None);
+
+/// Command output by workspace and command, with when it expires
+type Cache = HashMap<(String, String), (String, Instant)>;
+
+static CACHE: Mutex<Option<Cache>> = Mutex::new(None);
+
+/// A `{"$exec": ...}` env value
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExecMarker {
+    #[serde(rename = "$exec")]
+    pub command: String,
+    /// Seconds to reuse the output for; without it the command runs every time
+    #[serde(default)]
+    pub ttl: Option<u64>,
+}
+
+/// The command marker an env value holds, if it is one. Values reach here as
+/// the JSON text of the object.
+pub fn parse_marker(value: &str) -> Option<ExecMarker> {
+    if !value.starts_with('{') || !value.contains(EXEC_MARKER_KEY) {
+        return None;
+    }
+    serde_json::from_str(value).ok()
+}
+
+/// Whether an env file value is a command marker
+pub fn is_marker(value: &serde_json::Value) -> bool {
+    value
+        .as_object()
+        .is_some_and(|obj| obj.contains_key(EXEC_MARKER_KEY))
+}
+
+/// Allow or forbid running env file commands in a workspace
+pub fn set_trusted(workspace: &str, trusted: bool) {
+    let mut guard = TRUSTED.lock().unwrap();
+    let set = guard.get_or_insert_with(HashSet::new);
+    if trusted {
+        set.insert(workspace.to_string());
+    } else {
+        set.remove(workspace);
+    }
+}
+
+/// Whether env file commands may run in a workspace
+pub fn is_trusted(workspace: &str) -> bool {
+    TRUSTED
+        .lock()
+        .unwrap()
+        .as_ref()
+        .is_some_and(|set| set.contains(workspace))
+}
+
+/// Run a marker's command in `workspace`, reusing output cached within its ttl
+pub async fn run(workspace: &str, marker: &ExecMarker) -> Result<String, String> {
+    run_with_timeout(workspace, marker, TIMEOUT).await
+}
+
+async fn run_with_timeout(
+    workspace: &str,
+    marker: &ExecMarker,
+    timeout: Duration,
+) -> Result<String, String> {
+    let key = (workspace.to_string(), marker.command.clone());
+    let ttl = marker.ttl.filter(|ttl| *ttl > 0);
+    let cached = ttl.and_then(|_| {
+        CACHE
+            .lock()
+            .unwrap()
+            .as_ref()
+            .and_then(|cache| cache.get(&key).cloned())
+    });
+    if let Some((value, expires)) = cached {
+        if Instant::now() < expires {
+            return Ok(value);
+        }
+    }
+
+    let value = execute(Path::new(workspace), &marker.command, timeout).await?;
+    if let Some(ttl) = ttl {
+        CACHE
+            .lock()
+            .unwrap()
+            .get_or_insert_with(HashMap::new)
+            .insert(
+                key,
+                (value.clone(), Instant::now() + Duration::from_secs(ttl)),
+            );
+    }
+    Ok(value)
+}
+
+async fn execute(dir: &Path, command: &str, timeout: Duration) -> Result<String, String> {
+    #[cfg(windows)]
+    let mut cmd = {
+        let mut cmd = tokio::process::Command::new("cmd");
+        cmd.arg("/C").arg(command);
+        cmd
+    };
+    #[cfg(not(windows))]
+    let mut cmd = {
+        let mut cmd = tokio::process::Command::new("sh");
+        cmd.arg("-c").arg(command);
+        cmd
+    };
+    let child = cmd
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("Failed to run '{}': {}", command, e))?;
+
+    let output = tokio::time::timeout(timeout, child.wait_with_output())
+        .await
+        .map_err(|_| {
+            format!(
+                "Command '{}' timed out after {:.1}s",
+                command,
+                timeout.as_secs_f64()
+            )
+        })?
+        .map_err(|e| format!("Failed to run '{}': {}", command, e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Command '{}' failed ({}): {}",
+            command,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn marker(command: &str, ttl: Option<u64>) -> ExecMarker {
+        ExecMarker {
+            command: command.to_string(),
+            ttl,
+        }
+    }
+
+    #[test]
+    fn test_parse_marker() {
+        assert_eq!(
+            parse_marker(r#"{"$exec":"gcloud auth print-access-token","ttl":300}"#),
+            Some(marker("gcloud auth print-access-token", Some(300)))
+        );
+        assert_eq!(
+            parse_marker(r#"{"$exec":"echo hi"}"#),
+            Some(marker("echo hi", None))
+        );
+        assert_eq!(parse_marker("plain value"), None);
+        assert_eq!(parse_marker(r#"{"$exec":"echo hi","extra":1}"#), None);
+        assert_eq!(parse_marker(r#"{"$secret":true}"#), None);
+    }
+
+    #[tokio::test]
+    async fn test_run_trims_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = dir.path().to_string_lossy().to_string();
+        assert_eq!(
+            run(&workspace, &marker("echo '  token-123  '", None))
+                .await
+                .unwrap(),
+            "token-123"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_caches_for_ttl() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = dir.path().to_string_lossy().to_string();
+        // Prints how many times it has run
+        let counter = "echo x >> runs && wc -l < runs";
+
+        let cached = marker(counter, Some(300));
+        assert_eq!(run(&workspace, &cached).await.unwrap(), "1");
+        assert_eq!(run(&workspace, &cached).await.unwrap(), "1");
+
+        let uncached = marker(counter, None);
+        assert_eq!(run(&workspace, &uncached).await.unwrap(), "2");
+        assert_eq!(run(&workspace, &uncached).await.unwrap(), "3");
+    }
+
+    #[tokio::test]
+    async fn test_run_reports_failure_and_timeout() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = dir.path().to_string_lossy().to_string();
+
+        let err = run(
+            &workspace,
+            &marker("echo 'not logged in' >&2; exit 3", None),
+        )
+        .await
+        .unwrap_err();
+        assert!(err.starts_with("Command 'echo 'not logged in' >&2; exit 3' failed"));
+        assert!(err.ends_with(": not logged in"));
+
+        let err = run_with_timeout(
+            &workspace,
+            &marker("sleep 5", Some(300)),
+            Duration::from_millis(100),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err, "Command 'sleep 5' timed out after 0.1s");
+    }
+
+    #[test]
+    fn test_trust_is_per_workspace() {
+        set_trusted("/trusted-ws", true);
+        assert!(is_trusted("/trusted-ws"));
+        assert!(!is_trusted("/other-ws"));
+        set_trusted("/trusted-ws", false);
+        assert!(!is_trusted("/trusted-ws"));
+    }
+}
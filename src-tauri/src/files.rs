@@ -0,0 +1,648 @@
+//! Listing a workspace's files, shared by the app's file tree, the watcher,
+//! the variable lint and the command-line runner, and creating, renaming and
+//! deleting them without leaving the workspace.
+
+use crate::ignores::WorkspaceIgnore;
+use chrono::{DateTime, Utc};
+use ignore::{WalkBuilder, WalkState};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// How many files [`crate::commands::list_http_files`] returns at once
+/// without a limit of its own
+pub const DEFAULT_LIST_LIMIT: usize = 5000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileInfo {
+    pub path: String,
+    pub name: String,
+    pub is_http_file: bool,
+    /// Size in bytes
+    pub size: u64,
+    pub modified: Option<DateTime<Utc>>,
+}
+
+/// Which files to list, and which page of them
+#[derive(Debug, Clone, Default)]
+pub struct ListOptions {
+    /// List every file rather than only .http, .rest and env files
+    pub include_all: bool,
+    /// Most files to return; the rest are fetched with `next_cursor`
+    pub limit: Option<usize>,
+    /// `next_cursor` of the previous page
+    pub cursor: Option<String>,
+}
+
+/// A page of files, sorted by path
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FileListing {
+    pub files: Vec<FileInfo>,
+    /// Pass back to fetch the next page; `None` on the last page
+    pub next_cursor: Option<String>,
+    /// Directories that could not be read, such as for lack of permission
+    pub warnings: Vec<String>,
+}
+
+/// .http and .rest files, by name
+pub fn is_http_file_name(name: &str) -> bool {
+    let name = name.to_lowercase();
+    name.ends_with(".http") || name.ends_with(".rest")
+}
+
+/// Env files, including fragments such as orders.http-client.env.json, by name
+pub fn is_env_file_name(name: &str) -> bool {
+    let name = name.to_lowercase();
+    name.ends_with("http-client.env.json")
+        || name.ends_with("http-client.private.env.json")
+        || name == ".env"
+}
+
+/// The files in a directory, recursively, except hidden directories and
+/// paths `ignore` excludes. Each directory is listed once, however many
+/// symlinks lead to it.
+pub async fn list_workspace_files(
+    directory: &str,
+    ignore: &WorkspaceIgnore,
+    options: &ListOptions,
+) -> Result<FileListing, String> {
+    if let Err(e) = tokio::fs::read_dir(directory).await {
+        return Err(format!("Failed to read directory: {}", e));
+    }
+    let root = PathBuf::from(directory);
+    let ignore = ignore.clone();
+    let include_all = options.include_all;
+    let mut listing = tokio::task::spawn_blocking(move || walk(&root, &ignore, include_all))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    listing.files.sort_by(|a, b| a.path.cmp(&b.path));
+    if let Some(cursor) = &options.cursor {
+        let start = listing.files.partition_point(|file| file.path <= *cursor);
+        listing.files.drain(..start);
+    }
+    if let Some(limit) = options.limit.filter(|&limit| listing.files.len() > limit) {
+        listing.files.truncate(limit);
+        listing.next_cursor = listing.files.last().map(|file| file.path.clone());
+    }
+    Ok(listing)
+}
+
+/// Walk a directory on all cores, blocking until done. Unreadable
+/// directories become warnings; dangling symlinks and symlink loops are
+/// skipped. Files are in no particular order.
+pub fn walk(root: &Path, ignore: &WorkspaceIgnore, include_all: bool) -> FileListing {
+    let follow_symlinks = ignore.follow_symlinks;
+    let filter_ignore = ignore.clone();
+    let visited = Arc::new(Mutex::new(HashSet::new()));
+    if let Ok(root) = std::fs::canonicalize(root) {
+        visited.lock().unwrap().insert(root);
+    }
+    let walker = WalkBuilder::new(root)
+        .standard_filters(false)
+        .follow_links(follow_symlinks)
+        .max_depth(Some(ignore.max_depth))
+        .filter_entry(move |entry| {
+            let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+            if entry.depth() == 0 || !is_dir {
+                return !filter_ignore.is_excluded(entry.path(), is_dir);
+            }
+            if entry.file_name().to_string_lossy().starts_with('.')
+                || filter_ignore.is_excluded(entry.path(), true)
+            {
+                return false;
+            }
+            // Only followed symlinks can lead to a directory twice
+            !follow_symlinks
+                || std::fs::canonicalize(entry.path())
+                    .is_ok_and(|dir| visited.lock().unwrap().insert(dir))
+        })
+        .build_parallel();
+
+    let listing = Mutex::new(FileListing::default());
+    walker.run(|| {
+        let listing = &listing;
+        Box::new(move |entry| {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    let unreadable = e
+                        .io_error()
+                        .is_some_and(|io| io.kind() != std::io::ErrorKind::NotFound);
+                    if unreadable {
+                        listing.lock().unwrap().warnings.push(e.to_string());
+                    }
+                    return WalkState::Continue;
+                }
+            };
+            if !entry.file_type().is_some_and(|t| t.is_file()) {
+                return WalkState::Continue;
+            }
+            let name = entry.file_name().to_string_lossy().to_string();
+            let is_http_file = is_http_file_name(&name);
+            if !include_all && !is_http_file && !is_env_file_name(&name) {
+                return WalkState::Continue;
+            }
+            let metadata = entry.metadata().ok();
+            listing.lock().unwrap().files.push(FileInfo {
+                path: entry.path().to_string_lossy().to_string(),
+                name,
+                is_http_file,
+                size: metadata.as_ref().map_or(0, |m| m.len()),
+                modified: metadata
+                    .and_then(|m| m.modified().ok())
+                    .map(DateTime::<Utc>::from),
+            });
+            WalkState::Continue
+        })
+    });
+    let mut listing = listing.into_inner().unwrap();
+    listing.warnings.sort();
+    listing
+}
+
+/// Why creating, renaming or deleting a path failed, tagged so the frontend
+/// can offer to overwrite an existing file
+#[derive(Debug, Clone, PartialEq, Serialize, thiserror::Error)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FileOpError {
+    #[error("Already exists: {path}")]
+    AlreadyExists { path: String },
+    #[error("Not found: {path}")]
+    NotFound { path: String },
+    /// The path is outside the workspace, or is its root
+    #[error("Not inside the workspace: {path}")]
+    OutsideWorkspace { path: String },
+    #[error("{message}")]
+    Other { message: String },
+}
+
+impl FileOpError {
+    fn io(path: &Path, e: std::io::Error) -> Self {
+        let path = path.to_string_lossy().to_string();
+        match e.kind() {
+            std::io::ErrorKind::AlreadyExists => FileOpError::AlreadyExists { path },
+            std::io::ErrorKind::NotFound => FileOpError::NotFound { path },
+            _ => FileOpError::Other {
+                message: format!("{}: {}", path, e),
+            },
+        }
+    }
+}
+
+/// `path`, relative paths taken from `workspace`, if it is inside the
+/// workspace and isn't its root. `..` is refused outright, and directories
+/// on the way are resolved so a symlink can't lead out; the last component
+/// isn't, so a symlink itself can still be renamed or deleted.
+pub fn workspace_path(workspace: &Path, path: &Path) -> Result<PathBuf, FileOpError> {
+    let outside = || FileOpError::OutsideWorkspace {
+        path: path.to_string_lossy().to_string(),
+    };
+    let root = std::fs::canonicalize(workspace).map_err(|e| FileOpError::io(workspace, e))?;
+    let path = workspace.join(path);
+    if path
+        .components()
+        .any(|c| matches!(c, std::path::Component::ParentDir))
+    {
+        return Err(outside());
+    }
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+        return Err(outside());
+    };
+    // The nearest existing directory, resolved, then the rest as written
+    let mut existing = parent;
+    let mut rest = Vec::new();
+    while !existing.exists() {
+        rest.push(existing.file_name().ok_or_else(outside)?);
+        existing = existing.parent().ok_or_else(outside)?;
+    }
+    let mut resolved = std::fs::canonicalize(existing).map_err(|e| FileOpError::io(existing, e))?;
+    resolved.extend(rest.iter().rev());
+    if !resolved.join(name).starts_with(&root) || resolved.join(name) == root {
+        return Err(outside());
+    }
+    Ok(path)
+}
+
+/// Create a file in the workspace, and any directories it needs. Without
+/// `overwrite` an existing file is left alone; a symlink never is written
+/// through, since it may lead out of the workspace. Returns the path created.
+pub async fn create_file(
+    workspace: &Path,
+    path: &Path,
+    content: &str,
+    overwrite: bool,
+) -> Result<Vec<String>, FileOpError> {
+    let path = workspace_path(workspace, path)?;
+    if tokio::fs::symlink_metadata(&path)
+        .await
+        .is_ok_and(|m| m.is_symlink())
+    {
+        return Err(FileOpError::OutsideWorkspace {
+            path: path.to_string_lossy().to_string(),
+        });
+    }
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(|e| FileOpError::io(parent, e))?;
+    }
+    let mut options = tokio::fs::OpenOptions::new();
+    options.write(true);
+    if overwrite {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+    let mut file = options
+        .open(&path)
+        .await
+        .map_err(|e| FileOpError::io(&path, e))?;
+    // Flushed so the write has finished when this returns
+    let written = match tokio::io::AsyncWriteExt::write_all(&mut file, content.as_bytes()).await {
+        Ok(()) => tokio::io::AsyncWriteExt::flush(&mut file).await,
+        Err(e) => Err(e),
+    };
+    written.map_err(|e| FileOpError::io(&path, e))?;
+    Ok(vec![path.to_string_lossy().to_string()])
+}
+
+/// Create a directory in the workspace, and any parents it needs. Returns
+/// the path created.
+pub async fn create_directory(workspace: &Path, path: &Path) -> Result<Vec<String>, FileOpError> {
+    let path = workspace_path(workspace, path)?;
+    if tokio::fs::symlink_metadata(&path).await.is_ok() {
+        return Err(FileOpError::AlreadyExists {
+            path: path.to_string_lossy().to_string(),
+        });
+    }
+    tokio::fs::create_dir_all(&path)
+        .await
+        .map_err(|e| FileOpError::io(&path, e))?;
+    Ok(vec![path.to_string_lossy().to_string()])
+}
+
+/// Rename or move a file or directory within the workspace, failing if
+/// something is already at `to`. Returns both paths.
+pub async fn rename_path(
+    workspace: &Path,
+    from: &Path,
+    to: &Path,
+) -> Result<Vec<String>, FileOpError> {
+    let from = workspace_path(workspace, from)?;
+    let to = workspace_path(workspace, to)?;
+    tokio::fs::symlink_metadata(&from)
+        .await
+        .map_err(|e| FileOpError::io(&from, e))?;
+    if tokio::fs::symlink_metadata(&to).await.is_ok() {
+        return Err(FileOpError::AlreadyExists {
+            path: to.to_string_lossy().to_string(),
+        });
+    }
+    if let Some(parent) = to.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(|e| FileOpError::io(parent, e))?;
+    }
+    tokio::fs::rename(&from, &to)
+        .await
+        .map_err(|e| FileOpError::io(&from, e))?;
+    Ok(vec![
+        from.to_string_lossy().to_string(),
+        to.to_string_lossy().to_string(),
+    ])
+}
+
+/// Delete a file or directory in the workspace, to the system trash or, with
+/// `to_trash` false, for good. Returns the path deleted.
+pub async fn delete_path(
+    workspace: &Path,
+    path: &Path,
+    to_trash: bool,
+) -> Result<Vec<String>, FileOpError> {
+    let path = workspace_path(workspace, path)?;
+    let metadata = tokio::fs::symlink_metadata(&path)
+        .await
+        .map_err(|e| FileOpError::io(&path, e))?;
+    if to_trash {
+        let trashed = path.clone();
+        tokio::task::spawn_blocking(move || trash::delete(&trashed))
+            .await
+            .map_err(|e| FileOpError::Other {
+                message: e.to_string(),
+            })?
+            .map_err(|e| FileOpError::Other {
+                message: format!("Failed to move {} to the trash: {}", path.display(), e),
+            })?;
+    } else if metadata.is_dir() {
+        tokio::fs::remove_dir_all(&path)
+            .await
+            .map_err(|e| FileOpError::io(&path, e))?;
+    } else {
+        tokio::fs::remove_file(&path)
+            .await
+            .map_err(|e| FileOpError::io(&path, e))?;
+    }
+    Ok(vec![path.to_string_lossy().to_string()])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn write(path: &Path, content: &str) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    fn relative(root: &Path, files: &[FileInfo]) -> Vec<String> {
+        files
+            .iter()
+            .map(|file| {
+                Path::new(&file.path)
+                    .strip_prefix(root)
+                    .unwrap()
+                    .to_string_lossy()
+                    .replace('\\', "/")
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_list_deep_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        // 20 branches, each 25 directories deep, with an .http file and two
+        // others at every level
+        let mut expected = Vec::new();
+        for branch in 0..20 {
+            let mut path = root.join(format!("b{:02}", branch));
+            for level in 0..25 {
+                path = path.join(format!("l{:02}", level));
+                write(&path.join("api.http"), "GET /");
+                write(&path.join("notes.md"), "# notes");
+                write(&path.join("data.json"), "{}");
+                expected.push(path.join("api.http"));
+            }
+        }
+        write(&root.join("http-client.env.json"), "{}");
+        expected.push(root.join("http-client.env.json"));
+        expected.sort();
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|path| path.to_string_lossy().to_string())
+            .collect();
+
+        let ignore = WorkspaceIgnore::load(root);
+        let workspace = root.to_string_lossy().to_string();
+        let started = Instant::now();
+        let listing = list_workspace_files(&workspace, &ignore, &ListOptions::default())
+            .await
+            .unwrap();
+        let elapsed = started.elapsed();
+        let paths: Vec<_> = listing.files.iter().map(|f| f.path.clone()).collect();
+        assert_eq!(paths, expected);
+        assert!(elapsed.as_secs() < 10, "listing took {:?}", elapsed);
+        let env = listing.files.iter().find(|f| !f.is_http_file).unwrap();
+        assert_eq!(env.size, 2);
+        assert!(env.modified.is_some());
+
+        let all = ListOptions {
+            include_all: true,
+            ..Default::default()
+        };
+        let listing = list_workspace_files(&workspace, &ignore, &all)
+            .await
+            .unwrap();
+        assert_eq!(listing.files.len(), 20 * 25 * 3 + 1);
+
+        // Paged, the pages add up to the whole listing
+        let mut pages = 0;
+        let mut paged = Vec::new();
+        let mut cursor = None;
+        loop {
+            let options = ListOptions {
+                limit: Some(120),
+                cursor,
+                ..Default::default()
+            };
+            let listing = list_workspace_files(&workspace, &ignore, &options)
+                .await
+                .unwrap();
+            pages += 1;
+            paged.extend(listing.files.into_iter().map(|f| f.path));
+            cursor = listing.next_cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+        assert_eq!(pages, 5);
+        assert_eq!(paged, expected);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_list_symlink_cycle() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        write(&root.join("a/b/users.http"), "GET /users");
+        std::os::unix::fs::symlink(root.join("a"), root.join("a/b/up")).unwrap();
+        std::os::unix::fs::symlink(root.join("a/b"), root.join("b-link")).unwrap();
+        std::os::unix::fs::symlink(root.join("missing"), root.join("dangling")).unwrap();
+        let workspace = root.to_string_lossy().to_string();
+
+        let mut ignore = WorkspaceIgnore::load(root);
+        ignore.follow_symlinks = true;
+        let listing = list_workspace_files(&workspace, &ignore, &ListOptions::default())
+            .await
+            .unwrap();
+        // Whichever path reaches a/b first lists it
+        assert_eq!(listing.files.len(), 1);
+        assert!(listing.files[0].path.ends_with("users.http"));
+        assert_eq!(listing.warnings, Vec::<String>::new());
+
+        let listing = list_workspace_files(
+            &workspace,
+            &WorkspaceIgnore::load(root),
+            &ListOptions::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(relative(root, &listing.files), vec!["a/b/users.http"]);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_list_unreadable_directory_warns() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        write(&root.join("api/users.http"), "GET /users");
+        write(&root.join("secret/keys.http"), "GET /keys");
+        let secret = root.join("secret");
+        std::fs::set_permissions(&secret, std::fs::Permissions::from_mode(0o000)).unwrap();
+        // Root reads anything, so there is nothing to warn about
+        let readable = std::fs::read_dir(&secret).is_ok();
+
+        let workspace = root.to_string_lossy().to_string();
+        let listing = list_workspace_files(
+            &workspace,
+            &WorkspaceIgnore::load(root),
+            &ListOptions::default(),
+        )
+        .await
+        .unwrap();
+        std::fs::set_permissions(&secret, std::fs::Permissions::from_mode(0o755)).unwrap();
+        if !readable {
+            assert_eq!(relative(root, &listing.files), vec!["api/users.http"]);
+            assert_eq!(listing.warnings.len(), 1);
+            assert!(listing.warnings[0].contains("secret"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_file_overwrite_protection() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let path = root.join("api/users.http");
+
+        let created = create_file(root, &path, "GET /users", false).await.unwrap();
+        assert_eq!(created, vec![path.to_string_lossy().to_string()]);
+        assert_eq!(
+            create_file(root, &path, "GET /other", false).await,
+            Err(FileOpError::AlreadyExists {
+                path: path.to_string_lossy().to_string()
+            })
+        );
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "GET /users");
+
+        create_file(root, &path, "GET /other", true).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "GET /other");
+        // Relative to the workspace
+        create_file(root, Path::new("new.http"), "", false)
+            .await
+            .unwrap();
+        assert!(root.join("new.http").is_file());
+    }
+
+    #[tokio::test]
+    async fn test_workspace_escape_rejected() {
+        let outer = tempfile::tempdir().unwrap();
+        let root = outer.path().join("ws");
+        std::fs::create_dir(&root).unwrap();
+        write(&outer.path().join("secret.http"), "GET /");
+        let is_outside = |result: Result<Vec<String>, FileOpError>| {
+            matches!(result, Err(FileOpError::OutsideWorkspace { .. }))
+        };
+
+        assert!(is_outside(
+            create_file(&root, Path::new("../escape.http"), "", false).await
+        ));
+        assert!(is_outside(
+            create_file(&root, &root.join("api/../../escape.http"), "", false).await
+        ));
+        assert!(is_outside(
+            create_file(&root, &outer.path().join("escape.http"), "", false).await
+        ));
+        assert!(is_outside(
+            delete_path(&root, &outer.path().join("secret.http"), false).await
+        ));
+        assert!(is_outside(delete_path(&root, &root, false).await));
+        assert!(is_outside(create_directory(&root, Path::new("/")).await));
+        assert!(is_outside(
+            rename_path(
+                &root,
+                &outer.path().join("secret.http"),
+                &root.join("x.http")
+            )
+            .await
+        ));
+        assert!(!outer.path().join("escape.http").exists());
+        assert!(outer.path().join("secret.http").exists());
+
+        #[cfg(unix)]
+        {
+            // A symlinked directory leading out can't be written through,
+            // and a symlinked file isn't overwritten; the links themselves
+            // can go
+            std::os::unix::fs::symlink(outer.path(), root.join("out")).unwrap();
+            std::os::unix::fs::symlink(outer.path().join("secret.http"), root.join("s.http"))
+                .unwrap();
+            assert!(is_outside(
+                create_file(&root, &root.join("out/escape.http"), "", false).await
+            ));
+            assert!(is_outside(
+                delete_path(&root, &root.join("out/secret.http"), false).await
+            ));
+            assert!(is_outside(
+                create_file(&root, &root.join("s.http"), "", true).await
+            ));
+            delete_path(&root, &root.join("out"), false).await.unwrap();
+            delete_path(&root, &root.join("s.http"), false)
+                .await
+                .unwrap();
+            assert_eq!(
+                std::fs::read_to_string(outer.path().join("secret.http")).unwrap(),
+                "GET /"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rename_and_delete() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        write(&root.join("a.http"), "GET /a");
+        write(&root.join("b.http"), "GET /b");
+
+        assert_eq!(
+            rename_path(root, &root.join("a.http"), &root.join("b.http")).await,
+            Err(FileOpError::AlreadyExists {
+                path: root.join("b.http").to_string_lossy().to_string()
+            })
+        );
+        assert_eq!(
+            std::fs::read_to_string(root.join("b.http")).unwrap(),
+            "GET /b"
+        );
+        assert!(matches!(
+            rename_path(root, &root.join("missing.http"), &root.join("c.http")).await,
+            Err(FileOpError::NotFound { .. })
+        ));
+
+        let renamed = rename_path(root, &root.join("a.http"), &root.join("api/a.http"))
+            .await
+            .unwrap();
+        assert_eq!(
+            renamed,
+            vec![
+                root.join("a.http").to_string_lossy().to_string(),
+                root.join("api/a.http").to_string_lossy().to_string()
+            ]
+        );
+        assert_eq!(
+            std::fs::read_to_string(root.join("api/a.http")).unwrap(),
+            "GET /a"
+        );
+
+        create_directory(root, Path::new("empty/nested"))
+            .await
+            .unwrap();
+        assert!(matches!(
+            create_directory(root, Path::new("empty")).await,
+            Err(FileOpError::AlreadyExists { .. })
+        ));
+        delete_path(root, &root.join("api"), false).await.unwrap();
+        delete_path(root, &root.join("b.http"), false)
+            .await
+            .unwrap();
+        assert!(!root.join("api").exists());
+        assert!(!root.join("b.http").exists());
+        assert!(matches!(
+            delete_path(root, &root.join("b.http"), false).await,
+            Err(FileOpError::NotFound { .. })
+        ));
+    }
+}
@@ -1,8 +1,25 @@
+mod http_export;
+mod migrations;
+mod response_body;
+
+use crate::http_client::{add_default_headers, execute_request, HttpRequest, HttpResponse};
+use crate::workspace_settings::WorkspaceSettings;
 use chrono::{DateTime, Utc};
+use rusqlite::functions::FunctionFlags;
+use rusqlite::types::{Type, Value};
 use rusqlite::{Connection, Result as SqliteResult};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
-use std::sync::Mutex;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub use http_export::HttpExportOptions;
+#[cfg(feature = "app")]
+pub use response_body::save_response_body;
 
 /// A single request/response history entry
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -22,6 +39,34 @@ pub struct HistoryEntry {
     pub response_body: String,
     pub duration_ms: i64,
     pub response_size: i64,
+    /// The entry this one re-sent, if it was replayed from history
+    pub replayed_from: Option<i64>,
+    /// Active environment when the request was sent
+    pub environment: Option<String>,
+    pub variables_snapshot: Option<String>, // JSON object, secrets masked
+    /// Whether `response_body` holds the bytes received base64-encoded
+    #[serde(default)]
+    pub response_body_base64: bool,
+}
+
+impl HistoryEntry {
+    /// Rebuild the request that produced this entry
+    pub fn to_request(&self) -> Result<HttpRequest, String> {
+        let headers: HashMap<String, String> = serde_json::from_str(&self.request_headers)
+            .map_err(|e| {
+                format!(
+                    "History entry {} has unreadable request headers: {}",
+                    self.id, e
+                )
+            })?;
+
+        Ok(HttpRequest {
+            method: self.method.clone(),
+            url: self.url.clone(),
+            headers,
+            body: self.request_body.clone(),
+        })
+    }
 }
 
 /// Input for creating a new history entry (without id)
@@ -40,16 +85,569 @@ pub struct NewHistoryEntry {
     pub response_body: String,
     pub duration_ms: i64,
     pub response_size: i64,
+    #[serde(default)]
+    pub replayed_from: Option<i64>,
+    #[serde(default)]
+    pub environment: Option<String>,
+    /// JSON object of the variables used to resolve the request
+    #[serde(default)]
+    pub variables_snapshot: Option<String>,
+    #[serde(default)]
+    pub response_body_base64: bool,
+}
+
+/// Where a request was sent from, for recording it in history
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HistoryContext {
+    pub workspace: String,
+    pub file_path: Option<String>,
+    pub request_name: Option<String>,
+    pub environment: Option<String>,
+    /// Values the request was resolved with
+    pub variables: Option<HashMap<String, String>>,
+    /// Glob the env files were loaded with, when not the default
+    #[serde(default)]
+    pub env_glob: Option<String>,
+    /// History entry the request re-sends
+    #[serde(default)]
+    pub replayed_from: Option<i64>,
+}
+
+impl From<HistoryEntry> for NewHistoryEntry {
+    fn from(entry: HistoryEntry) -> Self {
+        Self {
+            workspace: entry.workspace,
+            file_path: entry.file_path,
+            request_name: entry.request_name,
+            method: entry.method,
+            url: entry.url,
+            request_headers: entry.request_headers,
+            request_body: entry.request_body,
+            status: entry.status,
+            status_text: entry.status_text,
+            response_headers: entry.response_headers,
+            response_body: entry.response_body,
+            duration_ms: entry.duration_ms,
+            response_size: entry.response_size,
+            replayed_from: entry.replayed_from,
+            environment: entry.environment,
+            variables_snapshot: entry.variables_snapshot,
+            response_body_base64: entry.response_body_base64,
+        }
+    }
+}
+
+impl NewHistoryEntry {
+    /// Record the outcome of sending `request`. A request that got no
+    /// response is recorded with status 0 and the error as its status text.
+    pub fn sent(
+        context: &HistoryContext,
+        variables_snapshot: Option<String>,
+        request: &HttpRequest,
+        result: &Result<HttpResponse, String>,
+        elapsed_ms: i64,
+    ) -> Self {
+        let (status, status_text, response_headers, response_body, duration_ms, response_size) =
+            match result {
+                Ok(response) => (
+                    response.status as i32,
+                    response.status_text.clone(),
+                    serde_json::to_string(&response.headers).unwrap_or_default(),
+                    response.body.clone(),
+                    response.time as i64,
+                    response.size as i64,
+                ),
+                Err(e) => (0, e.clone(), "{}".to_string(), String::new(), elapsed_ms, 0),
+            };
+        let response_body_base64 = result.as_ref().is_ok_and(|response| response.body_base64);
+
+        Self {
+            workspace: context.workspace.clone(),
+            file_path: context.file_path.clone(),
+            request_name: context.request_name.clone(),
+            method: request.method.clone(),
+            url: request.url.clone(),
+            request_headers: serde_json::to_string(&request.headers).unwrap_or_default(),
+            request_body: request.body.clone(),
+            status,
+            status_text,
+            response_headers,
+            response_body,
+            duration_ms,
+            response_size,
+            replayed_from: context.replayed_from,
+            environment: context.environment.clone(),
+            variables_snapshot,
+            response_body_base64,
+        }
+    }
+}
+
+/// Send a request with its workspace's settings and record the outcome,
+/// successful or not, in history before returning it. The response carries
+/// the new entry's id; failing to record it doesn't fail the request.
+pub async fn send_recorded(
+    db: &HistoryDb,
+    mut request: HttpRequest,
+    context: &HistoryContext,
+    variables_snapshot: Option<String>,
+    masked_headers: &[&str],
+) -> Result<HttpResponse, String> {
+    let settings = WorkspaceSettings::load(Path::new(&context.workspace)).settings;
+    add_default_headers(&mut request, &settings);
+    let start = Instant::now();
+    let result = execute_request(request.clone(), &settings)
+        .await
+        .map_err(|e| e.to_string());
+    let entry = NewHistoryEntry::sent(
+        context,
+        variables_snapshot,
+        &mask_headers(request, masked_headers),
+        &result,
+        start.elapsed().as_millis() as i64,
+    );
+
+    let recorded = db.run(move |db| db.add_entry(entry)).await;
+    let mut response = result?;
+    match recorded {
+        Ok(id) => response.history_id = Some(id),
+        Err(e) => tracing::error!("Failed to record history entry: {}", e),
+    }
+    Ok(response)
+}
+
+/// Send the request of history entry `id` again and record it as a replay
+/// of the entry. Entries sent with a masked token fail, since history
+/// doesn't keep the token to send.
+pub async fn resend(db: &HistoryDb, id: i64) -> Result<HistoryEntry, String> {
+    let original = db
+        .run(move |db| db.get_entry(id))
+        .await
+        .map_err(|e| format!("Failed to get history entry: {}", e))?
+        .ok_or_else(|| format!("History entry {} not found", id))?;
+    let request = original.to_request()?;
+    if request.headers.values().any(|value| is_masked(value)) {
+        return Err(format!(
+            "History entry {} was sent with an OIDC token, which history doesn't keep; send the request from its file instead",
+            id
+        ));
+    }
+
+    let context = HistoryContext {
+        workspace: original.workspace,
+        file_path: original.file_path,
+        request_name: original.request_name,
+        environment: original.environment,
+        replayed_from: Some(original.id),
+        ..Default::default()
+    };
+    let response = send_recorded(db, request, &context, original.variables_snapshot, &[]).await?;
+    let new_id = response
+        .history_id
+        .ok_or_else(|| "Failed to record the replay in history".to_string())?;
+    db.run(move |db| db.get_entry(new_id))
+        .await
+        .map_err(|e| format!("Failed to get history entry: {}", e))?
+        .ok_or_else(|| "History entry not found after adding it".to_string())
+}
+
+/// Value shown in place of secret variables in snapshots
+const MASKED_VALUE: &str = "********";
+
+/// Whether a header value was masked by [`mask_headers`]
+fn is_masked(value: &str) -> bool {
+    value.rsplit(' ').next() == Some(MASKED_VALUE)
+}
+
+/// The request with the values of `names` masked, keeping an auth scheme
+/// such as `Bearer` readable
+fn mask_headers(mut request: HttpRequest, names: &[&str]) -> HttpRequest {
+    for (key, value) in request.headers.iter_mut() {
+        if names.iter().any(|name| key.eq_ignore_ascii_case(name)) {
+            *value = match value.split_once(' ') {
+                Some((scheme, _)) => format!("{} {}", scheme, MASKED_VALUE),
+                None => MASKED_VALUE.to_string(),
+            };
+        }
+    }
+    request
+}
+
+/// Serialize the variables a request was resolved with, masking `secrets`
+pub fn variables_snapshot(variables: &HashMap<String, String>, secrets: &[String]) -> String {
+    let masked: BTreeMap<&str, &str> = variables
+        .iter()
+        .map(|(name, value)| {
+            let value = if secrets.contains(name) {
+                MASKED_VALUE
+            } else {
+                value.as_str()
+            };
+            (name.as_str(), value)
+        })
+        .collect();
+    serde_json::to_string(&masked).unwrap_or_default()
+}
+
+/// Optional filters for listing history; unset fields match everything
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HistoryFilter {
+    /// Any of these methods (case-insensitive)
+    pub methods: Vec<String>,
+    /// Status class, e.g. `5` for 5xx
+    pub status_class: Option<i32>,
+    pub status_min: Option<i32>,
+    pub status_max: Option<i32>,
+    pub file_path: Option<String>,
+    pub request_name: Option<String>,
+    /// Inclusive lower bound on the timestamp
+    pub from: Option<DateTime<Utc>>,
+    /// Inclusive upper bound on the timestamp
+    pub to: Option<DateTime<Utc>>,
+    /// Entries carrying all of these tags
+    pub tags: Vec<String>,
+}
+
+/// A workspace opened before
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecentWorkspace {
+    pub path: String,
+    pub last_opened: DateTime<Utc>,
+    /// Kept however many workspaces are opened after it
+    pub pinned: bool,
+    /// The directory no longer exists
+    pub missing: bool,
+}
+
+/// A tag and how many entries carry it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TagCount {
+    pub tag: String,
+    pub count: i64,
+}
+
+impl HistoryFilter {
+    /// Add this filter's conditions to a WHERE clause
+    fn apply(&self, clause: &mut WhereClause) {
+        if !self.methods.is_empty() {
+            let placeholders = vec!["?"; self.methods.len()].join(", ");
+            clause.push(
+                format!("UPPER(method) IN ({})", placeholders),
+                self.methods.iter().map(|m| Value::from(m.to_uppercase())),
+            );
+        }
+        if let Some(class) = self.status_class {
+            clause.push(
+                "status BETWEEN ? AND ?",
+                [Value::from(class * 100), Value::from(class * 100 + 99)],
+            );
+        }
+        if let Some(min) = self.status_min {
+            clause.push("status >= ?", [Value::from(min)]);
+        }
+        if let Some(max) = self.status_max {
+            clause.push("status <= ?", [Value::from(max)]);
+        }
+        if let Some(file_path) = &self.file_path {
+            clause.push("file_path = ?", [Value::from(file_path.clone())]);
+        }
+        if let Some(request_name) = &self.request_name {
+            clause.push("request_name = ?", [Value::from(request_name.clone())]);
+        }
+        // Timestamps are stored as RFC 3339 text, which sorts chronologically
+        if let Some(from) = self.from {
+            clause.push("timestamp >= ?", [Value::from(from.to_rfc3339())]);
+        }
+        if let Some(to) = self.to {
+            clause.push("timestamp <= ?", [Value::from(to.to_rfc3339())]);
+        }
+        if !self.tags.is_empty() {
+            let mut tags = self.tags.clone();
+            tags.sort();
+            tags.dedup();
+            let placeholders = vec!["?"; tags.len()].join(", ");
+            let count = tags.len() as i64;
+            clause.push(
+                format!(
+                    "id IN (SELECT entry_id FROM tags WHERE tag IN ({})
+                            GROUP BY entry_id HAVING COUNT(*) = ?)",
+                    placeholders
+                ),
+                tags.into_iter()
+                    .map(Value::from)
+                    .chain(std::iter::once(Value::from(count))),
+            );
+        }
+    }
+}
+
+/// Time window for endpoint statistics, ending now
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatsPeriod {
+    Day,
+    Week,
+    Month,
+    All,
+}
+
+impl StatsPeriod {
+    /// Start of the window, or `None` for all of history
+    fn since(self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            StatsPeriod::Day => Some(now - chrono::Duration::days(1)),
+            StatsPeriod::Week => Some(now - chrono::Duration::weeks(1)),
+            StatsPeriod::Month => Some(now - chrono::Duration::days(30)),
+            StatsPeriod::All => None,
+        }
+    }
+}
+
+/// Aggregated history for one method and endpoint
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EndpointStats {
+    pub method: String,
+    pub endpoint: String,
+    pub count: i64,
+    /// Share of responses with a 4xx or 5xx status
+    pub error_rate: f64,
+    pub avg_duration_ms: f64,
+    pub p50_duration_ms: i64,
+    pub p95_duration_ms: i64,
+    pub total_bytes: i64,
+}
+
+/// The newest result of one request in a file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LatestResult {
+    pub id: i64,
+    pub file_path: String,
+    pub request_name: Option<String>,
+    pub status: i32,
+    pub duration_ms: i64,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// File format for exporting history
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HistoryExportFormat {
+    /// An array of full entries
+    Json,
+    /// One row per entry, with bodies truncated
+    Csv,
+}
+
+/// Header row of a CSV history export
+const CSV_HEADER: [&str; 19] = [
+    "id",
+    "timestamp",
+    "workspace",
+    "file_path",
+    "request_name",
+    "method",
+    "url",
+    "status",
+    "status_text",
+    "duration_ms",
+    "response_size",
+    "replayed_from",
+    "environment",
+    "request_headers",
+    "request_body",
+    "request_body_truncated",
+    "response_headers",
+    "response_body",
+    "response_body_truncated",
+];
+
+/// Position after the last entry of a history page
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryCursor {
+    pub timestamp: DateTime<Utc>,
+    pub id: i64,
+}
+
+/// A page of history entries with the total matching count
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryPage {
+    pub entries: Vec<HistoryEntry>,
+    pub total: i64,
+    pub has_more: bool,
+    /// Pass back to fetch the next page; `None` on the last page
+    pub next_cursor: Option<HistoryCursor>,
+}
+
+/// A WHERE clause assembled from conditions with bound parameters
+struct WhereClause {
+    conditions: Vec<String>,
+    params: Vec<Value>,
+}
+
+impl WhereClause {
+    fn new(workspace: &str) -> Self {
+        Self {
+            conditions: vec!["workspace = ?".to_string()],
+            params: vec![Value::from(workspace.to_string())],
+        }
+    }
+
+    fn push(&mut self, condition: impl Into<String>, params: impl IntoIterator<Item = Value>) {
+        self.conditions.push(condition.into());
+        self.params.extend(params);
+    }
+
+    fn sql(&self) -> String {
+        self.conditions.join(" AND ")
+    }
+}
+
+/// How much history to keep; unset limits are not enforced
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionSettings {
+    pub max_entries_per_workspace: Option<i64>,
+    pub max_age_days: Option<i64>,
+    /// Cap on the space used by the database file, in bytes
+    pub max_db_size_bytes: Option<i64>,
+}
+
+impl Default for RetentionSettings {
+    fn default() -> Self {
+        Self {
+            max_entries_per_workspace: Some(1000),
+            max_age_days: None,
+            max_db_size_bytes: None,
+        }
+    }
+}
+
+/// Outcome of a pruning run
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PruneReport {
+    pub deleted: usize,
+    /// Whether the database file was compacted afterwards
+    pub vacuumed: bool,
+}
+
+/// Size and age of the history database
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryStats {
+    pub entry_count: i64,
+    pub db_size_bytes: i64,
+    pub oldest_entry: Option<DateTime<Utc>>,
+}
+
+/// How imported history combines with existing history
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HistoryImportMode {
+    /// Add imported entries, skipping ones already present
+    Merge,
+    /// Delete all existing history first
+    Replace,
+}
+
+/// Outcome of importing a history database
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryImportReport {
+    pub imported: usize,
+    /// Entries with the same workspace, timestamp, URL and method as an
+    /// existing one
+    pub skipped: usize,
+}
+
+/// Settings key holding the JSON-encoded `RetentionSettings`
+const RETENTION_KEY: &str = "history_retention";
+
+/// Workspace setting naming the environment last selected in the workspace
+pub const SELECTED_ENVIRONMENT_KEY: &str = "selected_environment";
+
+/// Workspace setting recording that its env files may run `$exec` commands
+pub const EXEC_TRUSTED_KEY: &str = "exec_trusted";
+
+/// Workspace setting listing and watching files its ignore files exclude
+pub const INCLUDE_IGNORED_KEY: &str = "include_ignored";
+
+/// Workspace settings for which files are listed and watched: extra ignore
+/// patterns in .gitignore syntax, one per line; how many directories deep to
+/// look; and whether to follow symlinks
+pub const IGNORE_PATTERNS_KEY: &str = "ignore_patterns";
+pub const MAX_DEPTH_KEY: &str = "max_depth";
+pub const FOLLOW_SYMLINKS_KEY: &str = "follow_symlinks";
+
+/// Workspace settings for the page shown after an OAuth login
+pub const CALLBACK_SUCCESS_HTML_KEY: &str = "callback_success_html";
+pub const CALLBACK_ERROR_HTML_KEY: &str = "callback_error_html";
+pub const CALLBACK_AUTO_CLOSE_KEY: &str = "callback_auto_close";
+
+/// How long to wait for another connection's lock before failing
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Attempts for a write that keeps hitting a busy database
+const BUSY_RETRIES: u32 = 5;
+
+/// Entries with this tag survive deleting old history
+const PINNED_TAG: &str = "pinned";
+
+/// Rows indexed per batch when backfilling the body search index
+const SEARCH_BACKFILL_BATCH: i64 = 200;
+
+/// Retention is enforced after this many inserts
+const PRUNE_INTERVAL: u32 = 100;
+
+/// Unpinned workspaces kept in the recent list
+pub const MAX_RECENT_WORKSPACES: i64 = 20;
+
+/// Response bodies larger than this are stored in blob files
+const INLINE_BODY_LIMIT: usize = 256 * 1024;
+
+/// Settings key recording that oversized inline bodies were moved to blobs
+const BLOBS_MIGRATED_KEY: &str = "response_body_blobs";
+
+/// Request and response bodies of a history entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryBodies {
+    pub request_body: Option<String>,
+    pub response_body: String,
+}
+
+/// Which body of an entry matched a body search
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchedBody {
+    Request,
+    Response,
+}
+
+/// An entry (without bodies) found by a body search
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BodySearchHit {
+    #[serde(flatten)]
+    pub entry: HistoryEntry,
+    pub matched: MatchedBody,
+    /// Excerpt of the matching body with matches wrapped in `<mark>` tags
+    pub snippet: String,
 }
 
-/// Thread-safe wrapper for database connection
+/// Thread-safe wrapper for database connection. Clones share the connection.
+#[derive(Clone)]
 pub struct HistoryDb {
-    conn: Mutex<Connection>,
+    conn: Arc<Mutex<Connection>>,
+    inserts_since_prune: Arc<AtomicU32>,
+    /// Directory of content-addressed response body files
+    blob_dir: PathBuf,
+    /// The database file; None for in-memory test databases
+    path: Option<PathBuf>,
+    /// Why the file could not be used while history is kept in memory
+    warning: Arc<Mutex<Option<String>>>,
 }
 
 impl HistoryDb {
-    /// Create a new HistoryDb, initializing the database if needed
-    pub fn new() -> SqliteResult<Self> {
+    /// Open the history database. If the file can't be opened or is corrupt,
+    /// history is kept in memory instead and `warning` says why.
+    pub fn new() -> Self {
         let db_path = get_database_path();
 
         // Ensure parent directory exists
@@ -57,86 +655,188 @@ impl HistoryDb {
             std::fs::create_dir_all(parent).ok();
         }
 
-        let conn = Connection::open(&db_path)?;
-        init_database(&conn)?;
+        let db = Self::open_or_fallback(db_path, get_blob_dir());
+        // Enforce retention at startup; a failed prune must not block the app
+        db.prune_now().ok();
 
-        Ok(Self {
-            conn: Mutex::new(conn),
-        })
+        // Index entries from before body search existed without holding up
+        // startup
+        let indexer = db.clone();
+        std::thread::spawn(move || {
+            while let Ok(false) = indexer.backfill_search_index(SEARCH_BACKFILL_BATCH) {
+                std::thread::sleep(Duration::from_millis(50));
+            }
+        });
+
+        db
+    }
+
+    fn open_or_fallback(db_path: PathBuf, blob_dir: PathBuf) -> Self {
+        match Self::open_file(&db_path, &blob_dir) {
+            Ok(conn) => Self::wrap(conn, blob_dir, Some(db_path), None),
+            Err(e) => {
+                let warning = format!(
+                    "History database {} could not be opened ({}). History is kept in memory until it is repaired.",
+                    db_path.display(),
+                    e
+                );
+                tracing::warn!("{}", warning);
+                let conn = Connection::open_in_memory()
+                    .and_then(|conn| prepare(conn, &blob_dir))
+                    .expect("Failed to create in-memory history database");
+                Self::wrap(conn, blob_dir, Some(db_path), Some(warning))
+            }
+        }
+    }
+
+    /// Open a database file shared safely with other windows and processes
+    #[cfg(test)]
+    fn open(db_path: &Path, blob_dir: PathBuf) -> SqliteResult<Self> {
+        let conn = Self::open_file(db_path, &blob_dir)?;
+        Ok(Self::wrap(
+            conn,
+            blob_dir,
+            Some(db_path.to_path_buf()),
+            None,
+        ))
+    }
+
+    fn open_file(db_path: &Path, blob_dir: &Path) -> SqliteResult<Connection> {
+        let conn = Connection::open(db_path)?;
+        // Wait for another window's write instead of failing straight away
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        // WAL lets readers and a writer work concurrently; NORMAL sync is
+        // durable enough for history in WAL mode
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        // A WAL left behind by a crash was already recovered on open; fold it
+        // into the database so it does not linger. Another window may hold
+        // it, which is fine.
+        conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))
+            .ok();
+        check_integrity(&conn)?;
+
+        prepare(conn, blob_dir)
+    }
+
+    /// An empty in-memory database
+    #[cfg(test)]
+    pub fn in_memory() -> SqliteResult<Self> {
+        Self::from_connection(
+            Connection::open_in_memory()?,
+            std::env::temp_dir().join("kvile-test-blobs"),
+        )
+    }
+
+    #[cfg(test)]
+    fn from_connection(conn: Connection, blob_dir: PathBuf) -> SqliteResult<Self> {
+        let conn = prepare(conn, &blob_dir)?;
+        Ok(Self::wrap(conn, blob_dir, None, None))
+    }
+
+    fn wrap(
+        conn: Connection,
+        blob_dir: PathBuf,
+        path: Option<PathBuf>,
+        warning: Option<String>,
+    ) -> Self {
+        Self {
+            conn: Arc::new(Mutex::new(conn)),
+            inserts_since_prune: Arc::new(AtomicU32::new(0)),
+            blob_dir,
+            path,
+            warning: Arc::new(Mutex::new(warning)),
+        }
+    }
+
+    /// Why history is kept in memory instead of the database file, if it is
+    pub fn warning(&self) -> Option<String> {
+        self.warning.lock().unwrap().clone()
+    }
+
+    /// Move the database file aside and start over with a fresh one. History
+    /// kept in memory since startup is dropped. Returns where the old file
+    /// was backed up, if there was one.
+    pub fn repair(&self) -> SqliteResult<Option<PathBuf>> {
+        let path = self
+            .path
+            .as_ref()
+            .ok_or_else(|| rusqlite::Error::InvalidPath(PathBuf::from(":memory:")))?;
+        let mut conn = self.conn.lock().unwrap();
+
+        // Close the file before moving it
+        drop(std::mem::replace(
+            &mut *conn,
+            prepare(Connection::open_in_memory()?, &self.blob_dir)?,
+        ));
+        let backup = back_up_database(path)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        *conn = Self::open_file(path, &self.blob_dir)?;
+        *self.warning.lock().unwrap() = None;
+
+        Ok(backup)
+    }
+
+    /// Remove blob files no entry references. Skipped while history is kept
+    /// in memory, as it doesn't know which blobs the file references.
+    fn collect_garbage(&self, conn: &Connection) -> SqliteResult<usize> {
+        if self.warning.lock().unwrap().is_some() {
+            return Ok(0);
+        }
+        collect_blob_garbage(conn, &self.blob_dir)
+    }
+
+    /// Run database work on the blocking thread pool so queries never stall
+    /// the async runtime
+    pub async fn run<T, F>(&self, f: F) -> T
+    where
+        T: Send + 'static,
+        F: FnOnce(&HistoryDb) -> T + Send + 'static,
+    {
+        let db = self.clone();
+        match tokio::task::spawn_blocking(move || f(&db)).await {
+            Ok(result) => result,
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
     }
 
     /// Add a new entry to history
     pub fn add_entry(&self, entry: NewHistoryEntry) -> SqliteResult<i64> {
+        // Hold the lock while writing the blob so garbage collection cannot
+        // remove it before the row referencing it exists
         let conn = self.conn.lock().unwrap();
-        let now = Utc::now();
+        let timestamp = Utc::now().to_rfc3339();
 
-        conn.execute(
-            "INSERT INTO history (
-                timestamp, workspace, file_path, request_name,
-                method, url, request_headers, request_body,
-                status, status_text, response_headers, response_body,
-                duration_ms, response_size
-            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
-            rusqlite::params![
-                now.to_rfc3339(),
-                entry.workspace,
-                entry.file_path,
-                entry.request_name,
-                entry.method,
-                entry.url,
-                entry.request_headers,
-                entry.request_body,
-                entry.status,
-                entry.status_text,
-                entry.response_headers,
-                entry.response_body,
-                entry.duration_ms,
-                entry.response_size,
-            ],
-        )?;
+        let id = retry_busy(|| {
+            let tx = conn.unchecked_transaction()?;
+            let id = insert_entry(&tx, &self.blob_dir, &entry, &timestamp)?;
+            tx.commit()?;
+            Ok(id)
+        })?;
+        drop(conn);
+
+        if self.inserts_since_prune.fetch_add(1, Ordering::Relaxed) + 1 >= PRUNE_INTERVAL {
+            self.inserts_since_prune.store(0, Ordering::Relaxed);
+            // Recording the entry succeeded even if pruning fails
+            self.prune_now().ok();
+        }
 
-        Ok(conn.last_insert_rowid())
+        Ok(id)
     }
 
-    /// Get history entries for a workspace (most recent first)
+    /// Get history entries for a workspace (most recent first), without bodies
     pub fn get_entries(&self, workspace: &str, limit: i32) -> SqliteResult<Vec<HistoryEntry>> {
         let conn = self.conn.lock().unwrap();
 
-        let mut stmt = conn.prepare(
-            "SELECT id, timestamp, workspace, file_path, request_name,
-                    method, url, request_headers, request_body,
-                    status, status_text, response_headers, response_body,
-                    duration_ms, response_size
-             FROM history
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM history
              WHERE workspace = ?1
              ORDER BY timestamp DESC
              LIMIT ?2",
-        )?;
+            LIST_COLUMNS
+        ))?;
 
-        let entries = stmt.query_map(rusqlite::params![workspace, limit], |row| {
-            let timestamp_str: String = row.get(1)?;
-            let timestamp = DateTime::parse_from_rfc3339(&timestamp_str)
-                .map(|dt| dt.with_timezone(&Utc))
-                .unwrap_or_else(|_| Utc::now());
-
-            Ok(HistoryEntry {
-                id: row.get(0)?,
-                timestamp,
-                workspace: row.get(2)?,
-                file_path: row.get(3)?,
-                request_name: row.get(4)?,
-                method: row.get(5)?,
-                url: row.get(6)?,
-                request_headers: row.get(7)?,
-                request_body: row.get(8)?,
-                status: row.get(9)?,
-                status_text: row.get(10)?,
-                response_headers: row.get(11)?,
-                response_body: row.get(12)?,
-                duration_ms: row.get(13)?,
-                response_size: row.get(14)?,
-            })
-        })?;
+        let entries = stmt.query_map(rusqlite::params![workspace, limit], entry_from_row)?;
 
         entries.collect()
     }
@@ -145,37 +845,13 @@ impl HistoryDb {
     pub fn get_entry(&self, id: i64) -> SqliteResult<Option<HistoryEntry>> {
         let conn = self.conn.lock().unwrap();
 
-        let mut stmt = conn.prepare(
-            "SELECT id, timestamp, workspace, file_path, request_name,
-                    method, url, request_headers, request_body,
-                    status, status_text, response_headers, response_body,
-                    duration_ms, response_size
-             FROM history WHERE id = ?1",
-        )?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM history WHERE id = ?1",
+            ENTRY_COLUMNS
+        ))?;
 
         let result = stmt.query_row(rusqlite::params![id], |row| {
-            let timestamp_str: String = row.get(1)?;
-            let timestamp = DateTime::parse_from_rfc3339(&timestamp_str)
-                .map(|dt| dt.with_timezone(&Utc))
-                .unwrap_or_else(|_| Utc::now());
-
-            Ok(HistoryEntry {
-                id: row.get(0)?,
-                timestamp,
-                workspace: row.get(2)?,
-                file_path: row.get(3)?,
-                request_name: row.get(4)?,
-                method: row.get(5)?,
-                url: row.get(6)?,
-                request_headers: row.get(7)?,
-                request_body: row.get(8)?,
-                status: row.get(9)?,
-                status_text: row.get(10)?,
-                response_headers: row.get(11)?,
-                response_body: row.get(12)?,
-                duration_ms: row.get(13)?,
-                response_size: row.get(14)?,
-            })
+            full_entry_from_row(&self.blob_dir, row)
         });
 
         match result {
@@ -185,111 +861,3468 @@ impl HistoryDb {
         }
     }
 
-    /// Delete a specific history entry
-    pub fn delete_entry(&self, id: i64) -> SqliteResult<bool> {
+    /// Get the request and response bodies of an entry
+    pub fn get_entry_body(&self, id: i64) -> SqliteResult<Option<HistoryBodies>> {
         let conn = self.conn.lock().unwrap();
-        let affected = conn.execute("DELETE FROM history WHERE id = ?1", rusqlite::params![id])?;
-        Ok(affected > 0)
+
+        let result = conn.query_row(
+            "SELECT request_body,
+                COALESCE((SELECT content FROM bodies WHERE hash = response_body_hash), response_body),
+                response_body_blob
+             FROM history WHERE id = ?1",
+            rusqlite::params![id],
+            |row| {
+                let blob: Option<String> = row.get(2)?;
+                let response_body = match blob {
+                    Some(hash) => read_blob(&self.blob_dir, &hash).map_err(|e| {
+                        rusqlite::Error::FromSqlConversionFailure(2, Type::Text, Box::new(e))
+                    })?,
+                    None => row.get(1)?,
+                };
+                Ok(HistoryBodies {
+                    request_body: row.get(0)?,
+                    response_body,
+                })
+            },
+        );
+
+        match result {
+            Ok(bodies) => Ok(Some(bodies)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e),
+        }
     }
 
-    /// Clear all history for a workspace
-    pub fn clear_workspace(&self, workspace: &str) -> SqliteResult<usize> {
-        let conn = self.conn.lock().unwrap();
-        let affected = conn.execute(
-            "DELETE FROM history WHERE workspace = ?1",
-            rusqlite::params![workspace],
-        )?;
-        Ok(affected)
+    /// Search a workspace's history, most recent first.
+    ///
+    /// Free-text terms must all match the URL, request name or method (and the
+    /// request and response bodies with `include_bodies`, except bodies kept in
+    /// blob files). `method:POST` and `status:500` (or `status:5xx`) narrow the
+    /// results further. Entries are returned without bodies.
+    pub fn search(
+        &self,
+        workspace: &str,
+        query: &str,
+        limit: i32,
+        include_bodies: bool,
+    ) -> SqliteResult<Vec<HistoryEntry>> {
+        let mut clause = WhereClause::new(workspace);
+
+        for term in query.split_whitespace() {
+            let lower = term.to_ascii_lowercase();
+            if let Some(method) = lower.strip_prefix("method:") {
+                clause.push(
+                    "method = ? COLLATE NOCASE",
+                    [Value::from(method.to_string())],
+                );
+                continue;
+            }
+            if let Some(status) = lower.strip_prefix("status:") {
+                if let Some(class) = status
+                    .strip_suffix("xx")
+                    .and_then(|c| c.parse::<i32>().ok())
+                {
+                    clause.push(
+                        "status BETWEEN ? AND ?",
+                        [Value::from(class * 100), Value::from(class * 100 + 99)],
+                    );
+                    continue;
+                }
+                if let Ok(code) = status.parse::<i32>() {
+                    clause.push("status = ?", [Value::from(code)]);
+                    continue;
+                }
+            }
+
+            let mut columns = vec!["url", "request_name", "method"];
+            if include_bodies {
+                columns.extend(["request_body", RESPONSE_BODY]);
+            }
+            let pattern = format!("%{}%", escape_like(term));
+            clause.push(
+                format!(
+                    "({})",
+                    columns
+                        .iter()
+                        .map(|c| format!("{} LIKE ? ESCAPE '\\'", c))
+                        .collect::<Vec<_>>()
+                        .join(" OR ")
+                ),
+                columns.iter().map(|_| Value::from(pattern.clone())),
+            );
+        }
+
+        self.select(&clause, limit)
     }
 
-    /// Clear all history
-    #[allow(dead_code)]
-    pub fn clear_all(&self) -> SqliteResult<usize> {
+    /// Full-text search a workspace's request and response bodies, most recent
+    /// first. Every term must match a whole token of the same body; JSON
+    /// punctuation separates tokens, so `order_id: 98231` finds
+    /// `{"order_id":98231}`. Bodies kept in blob files are not searched, nor
+    /// are older entries until the index backfill reaches them.
+    pub fn search_bodies(
+        &self,
+        workspace: &str,
+        query: &str,
+        limit: i32,
+    ) -> SqliteResult<Vec<BodySearchHit>> {
+        let Some(fts_query) = fts_query(query) else {
+            return Ok(Vec::new());
+        };
+
         let conn = self.conn.lock().unwrap();
-        let affected = conn.execute("DELETE FROM history", [])?;
-        Ok(affected)
+        let mut stmt = conn.prepare(&format!(
+            "WITH hits AS (
+                SELECT history.id AS entry_id, 'response' AS matched,
+                       snippet(response_fts, 0, '<mark>', '</mark>', '…', 16) AS excerpt
+                FROM response_fts
+                JOIN bodies ON bodies.id = response_fts.rowid
+                JOIN history ON history.response_body_hash = bodies.hash
+                WHERE response_fts MATCH ?2 AND history.workspace = ?1
+                UNION ALL
+                SELECT history.id, 'request',
+                       snippet(request_fts, 0, '<mark>', '</mark>', '…', 16)
+                FROM request_fts
+                JOIN history ON history.id = request_fts.rowid
+                WHERE request_fts MATCH ?2 AND history.workspace = ?1
+            ),
+            best AS (
+                -- Prefer the response excerpt when both bodies match
+                SELECT entry_id, MAX(matched) AS matched, excerpt
+                FROM hits GROUP BY entry_id
+            )
+            SELECT {}, best.matched, best.excerpt
+            FROM history JOIN best ON best.entry_id = history.id
+            ORDER BY timestamp DESC, id DESC
+            LIMIT ?3",
+            LIST_COLUMNS
+        ))?;
+
+        let hits = stmt.query_map(rusqlite::params![workspace, fts_query, limit], |row| {
+            let matched: String = row.get(20)?;
+            Ok(BodySearchHit {
+                entry: entry_from_row(row)?,
+                matched: if matched == "request" {
+                    MatchedBody::Request
+                } else {
+                    MatchedBody::Response
+                },
+                snippet: row.get(21)?,
+            })
+        })?;
+        hits.collect()
     }
 
-    /// Prune old entries, keeping only the most recent N entries per workspace
-    #[allow(dead_code)]
-    pub fn prune(&self, max_entries_per_workspace: i32) -> SqliteResult<usize> {
+    /// Index the next batch of rows that existed before body search did.
+    /// Returns true once everything is indexed.
+    pub fn backfill_search_index(&self, batch: i64) -> SqliteResult<bool> {
         let conn = self.conn.lock().unwrap();
+        let pending: Vec<(String, i64, i64)> = conn
+            .prepare("SELECT source, next_id, last_id FROM search_backfill")?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<SqliteResult<_>>()?;
 
-        // Get all unique workspaces
-        let mut stmt = conn.prepare("SELECT DISTINCT workspace FROM history")?;
-        let workspaces: Vec<String> = stmt
-            .query_map([], |row| row.get(0))?
-            .filter_map(|r| r.ok())
-            .collect();
+        for (source, next_id, last_id) in &pending {
+            let end = (next_id + batch - 1).min(*last_id);
+            let sql = match source.as_str() {
+                "bodies" => {
+                    "INSERT INTO response_fts (rowid, content)
+                     SELECT id, content FROM bodies WHERE id BETWEEN ?1 AND ?2"
+                }
+                _ => {
+                    "INSERT INTO request_fts (rowid, content)
+                     SELECT id, request_body FROM history
+                     WHERE id BETWEEN ?1 AND ?2 AND request_body <> ''"
+                }
+            };
+            retry_busy(|| {
+                let tx = conn.unchecked_transaction()?;
+                tx.execute(sql, [next_id, &end])?;
+                if end >= *last_id {
+                    tx.execute("DELETE FROM search_backfill WHERE source = ?1", [source])?;
+                } else {
+                    tx.execute(
+                        "UPDATE search_backfill SET next_id = ?1 WHERE source = ?2",
+                        rusqlite::params![end + 1, source],
+                    )?;
+                }
+                tx.commit()
+            })?;
+        }
 
-        let mut total_deleted = 0;
+        Ok(pending
+            .iter()
+            .all(|(_, next_id, last_id)| next_id + batch > *last_id))
+    }
 
-        for workspace in workspaces {
-            // Delete entries beyond the limit for each workspace
-            let deleted = conn.execute(
-                "DELETE FROM history WHERE workspace = ?1 AND id NOT IN (
-                    SELECT id FROM history WHERE workspace = ?1
-                    ORDER BY timestamp DESC LIMIT ?2
-                )",
-                rusqlite::params![workspace, max_entries_per_workspace],
-            )?;
-            total_deleted += deleted;
+    /// Get history entries for a workspace matching a filter (most recent
+    /// first), without bodies
+    pub fn get_entries_filtered(
+        &self,
+        workspace: &str,
+        filter: &HistoryFilter,
+        limit: i32,
+    ) -> SqliteResult<Vec<HistoryEntry>> {
+        let mut clause = WhereClause::new(workspace);
+        filter.apply(&mut clause);
+        self.select(&clause, limit)
+    }
+
+    /// Get one page of history entries matching a filter (most recent first),
+    /// without bodies.
+    ///
+    /// Pages are keyed on `(timestamp, id)`, so entries added while paging
+    /// neither shift nor repeat later pages.
+    pub fn get_page(
+        &self,
+        workspace: &str,
+        filter: &HistoryFilter,
+        cursor: Option<&HistoryCursor>,
+        limit: i32,
+    ) -> SqliteResult<HistoryPage> {
+        let mut clause = WhereClause::new(workspace);
+        filter.apply(&mut clause);
+        if let Some(cursor) = cursor {
+            let timestamp = cursor.timestamp.to_rfc3339();
+            clause.push(
+                "(timestamp < ? OR (timestamp = ? AND id < ?))",
+                [
+                    Value::from(timestamp.clone()),
+                    Value::from(timestamp),
+                    Value::from(cursor.id),
+                ],
+            );
         }
 
-        Ok(total_deleted)
+        // Fetch one extra row to learn whether another page follows
+        let mut entries = self.select(&clause, limit + 1)?;
+        let has_more = entries.len() > limit.max(0) as usize;
+        entries.truncate(limit.max(0) as usize);
+        let next_cursor = has_more
+            .then(|| entries.last())
+            .flatten()
+            .map(|entry| HistoryCursor {
+                timestamp: entry.timestamp,
+                id: entry.id,
+            });
+
+        Ok(HistoryPage {
+            entries,
+            total: self.count_entries(workspace, filter)?,
+            has_more,
+            next_cursor,
+        })
     }
-}
 
-/// Get the database file path
-fn get_database_path() -> PathBuf {
-    let data_dir = dirs::data_dir()
-        .unwrap_or_else(|| PathBuf::from("."))
-        .join("kvile");
+    /// Count the history entries for a workspace matching a filter
+    pub fn count_entries(&self, workspace: &str, filter: &HistoryFilter) -> SqliteResult<i64> {
+        let mut clause = WhereClause::new(workspace);
+        filter.apply(&mut clause);
 
-    data_dir.join("history.db")
-}
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            &format!("SELECT COUNT(*) FROM history WHERE {}", clause.sql()),
+            rusqlite::params_from_iter(clause.params.iter()),
+            |row| row.get(0),
+        )
+    }
 
-/// Initialize the database schema
-fn init_database(conn: &Connection) -> SqliteResult<()> {
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS history (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            timestamp TEXT NOT NULL,
-            workspace TEXT NOT NULL,
-            file_path TEXT,
-            request_name TEXT,
-            method TEXT NOT NULL,
-            url TEXT NOT NULL,
-            request_headers TEXT NOT NULL,
-            request_body TEXT,
-            status INTEGER NOT NULL,
-            status_text TEXT NOT NULL,
-            response_headers TEXT NOT NULL,
-            response_body TEXT NOT NULL,
-            duration_ms INTEGER NOT NULL,
-            response_size INTEGER NOT NULL
-        )",
-        [],
-    )?;
+    /// Aggregate a workspace's history per method and URL over a period, busiest
+    /// endpoints first.
+    ///
+    /// With `normalize`, query strings are dropped and ID-like path segments
+    /// collapse to `:id` (see `normalize_endpoint`). Aggregation runs in SQLite.
+    pub fn endpoint_stats(
+        &self,
+        workspace: &str,
+        period: StatsPeriod,
+        normalize: bool,
+    ) -> SqliteResult<Vec<EndpointStats>> {
+        let since = period.since(Utc::now()).map(|t| t.to_rfc3339());
+        let conn = self.conn.lock().unwrap();
 
-    // Create indexes for faster queries
-    conn.execute(
-        "CREATE INDEX IF NOT EXISTS idx_history_workspace_timestamp
-         ON history(workspace, timestamp DESC)",
-        [],
-    )?;
+        // Percentiles use the nearest-rank method over each group's durations
+        let mut stmt = conn.prepare(
+            "WITH base AS (
+                SELECT method,
+                       CASE WHEN ?3 THEN normalize_endpoint(url) ELSE url END AS endpoint,
+                       status, duration_ms, response_size
+                FROM history
+                WHERE workspace = ?1 AND (?2 IS NULL OR timestamp >= ?2)
+            ),
+            ranked AS (
+                SELECT *,
+                       ROW_NUMBER() OVER (PARTITION BY method, endpoint ORDER BY duration_ms) AS rn,
+                       COUNT(*) OVER (PARTITION BY method, endpoint) AS n
+                FROM base
+            )
+            SELECT method, endpoint, COUNT(*),
+                   AVG(status >= 400),
+                   AVG(duration_ms),
+                   MIN(CASE WHEN rn >= n * 0.5 THEN duration_ms END),
+                   MIN(CASE WHEN rn >= n * 0.95 THEN duration_ms END),
+                   SUM(response_size)
+            FROM ranked
+            GROUP BY method, endpoint
+            ORDER BY COUNT(*) DESC, method, endpoint",
+        )?;
 
-    Ok(())
-}
+        let stats = stmt.query_map(rusqlite::params![workspace, since, normalize], |row| {
+            Ok(EndpointStats {
+                method: row.get(0)?,
+                endpoint: row.get(1)?,
+                count: row.get(2)?,
+                error_rate: row.get(3)?,
+                avg_duration_ms: row.get(4)?,
+                p50_duration_ms: row.get(5)?,
+                p95_duration_ms: row.get(6)?,
+                total_bytes: row.get(7)?,
+            })
+        })?;
+        stats.collect()
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Get the newest entry of each request (file path and request name) sent
+    /// from a file, or from any file in the workspace when `file_path` is None.
+    /// Requests never sent have no result.
+    pub fn latest_per_request(
+        &self,
+        workspace: &str,
+        file_path: Option<&str>,
+    ) -> SqliteResult<Vec<LatestResult>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, file_path, request_name, status, duration_ms, timestamp
+             FROM (
+                SELECT *,
+                       ROW_NUMBER() OVER (
+                           PARTITION BY file_path, request_name
+                           ORDER BY timestamp DESC, id DESC
+                       ) AS rn
+                FROM history
+                WHERE workspace = ?1 AND file_path IS NOT NULL
+                  AND (?2 IS NULL OR file_path = ?2)
+             )
+             WHERE rn = 1
+             ORDER BY file_path, request_name",
+        )?;
 
-    #[test]
-    fn test_database_path() {
-        let path = get_database_path();
-        assert!(path.to_string_lossy().contains("kvile"));
-        assert!(path.to_string_lossy().ends_with("history.db"));
+        let results = stmt.query_map(rusqlite::params![workspace, file_path], |row| {
+            let timestamp: String = row.get(5)?;
+            Ok(LatestResult {
+                id: row.get(0)?,
+                file_path: row.get(1)?,
+                request_name: row.get(2)?,
+                status: row.get(3)?,
+                duration_ms: row.get(4)?,
+                timestamp: DateTime::parse_from_rfc3339(&timestamp)
+                    .map(|dt| dt.with_timezone(&Utc))
+                    .map_err(|e| {
+                        rusqlite::Error::FromSqlConversionFailure(5, Type::Text, Box::new(e))
+                    })?,
+            })
+        })?;
+        results.collect()
+    }
+
+    /// Write a workspace's history matching a filter to `writer`, oldest first.
+    ///
+    /// Rows are streamed from the database one at a time. CSV bodies are cut
+    /// to `body_limit` characters. Returns the number of entries written.
+    pub fn export<W: Write>(
+        &self,
+        workspace: &str,
+        filter: &HistoryFilter,
+        format: HistoryExportFormat,
+        body_limit: usize,
+        writer: W,
+    ) -> Result<usize, String> {
+        self.export_with(workspace, filter, |entries| match format {
+            HistoryExportFormat::Json => write_json(entries, writer),
+            HistoryExportFormat::Csv => write_csv(entries, body_limit, writer),
+        })
+    }
+
+    /// [`export`](Self::export) to the file at `dest`, replacing it only once
+    /// the export has succeeded
+    pub fn export_to_file(
+        &self,
+        workspace: &str,
+        filter: &HistoryFilter,
+        format: HistoryExportFormat,
+        body_limit: usize,
+        dest: &Path,
+    ) -> Result<usize, String> {
+        let temp = response_body::temp_path(dest);
+        let result = std::fs::File::create(&temp)
+            .map_err(|e| format!("Failed to create file: {}", e))
+            .and_then(|file| self.export(workspace, filter, format, body_limit, file))
+            .and_then(|count| {
+                std::fs::rename(&temp, dest)
+                    .map(|_| count)
+                    .map_err(|e| format!("Failed to write {}: {}", dest.display(), e))
+            });
+        if result.is_err() {
+            let _ = std::fs::remove_file(&temp);
+        }
+        result
+    }
+
+    /// The requests of a workspace's history matching a filter as an .http
+    /// file, oldest first
+    pub fn export_http(
+        &self,
+        workspace: &str,
+        filter: &HistoryFilter,
+        options: &HttpExportOptions,
+    ) -> Result<String, String> {
+        self.export_with(workspace, filter, |entries| {
+            http_export::write_http(entries, options)
+        })
+    }
+
+    /// Stream full entries matching a filter, oldest first, to `write`
+    fn export_with<T>(
+        &self,
+        workspace: &str,
+        filter: &HistoryFilter,
+        write: impl FnOnce(&mut dyn Iterator<Item = SqliteResult<HistoryEntry>>) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let mut clause = WhereClause::new(workspace);
+        filter.apply(&mut clause);
+
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT {} FROM history WHERE {} ORDER BY timestamp ASC, id ASC",
+                ENTRY_COLUMNS,
+                clause.sql()
+            ))
+            .map_err(|e| format!("Failed to query history: {}", e))?;
+        let mut entries = stmt
+            .query_map(rusqlite::params_from_iter(clause.params.iter()), |row| {
+                full_entry_from_row(&self.blob_dir, row)
+            })
+            .map_err(|e| format!("Failed to query history: {}", e))?;
+        write(&mut entries)
+    }
+
+    /// Run a SELECT of entries without bodies with a WHERE clause, most
+    /// recent first
+    fn select(&self, clause: &WhereClause, limit: i32) -> SqliteResult<Vec<HistoryEntry>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM history
+             WHERE {}
+             ORDER BY timestamp DESC, id DESC
+             LIMIT ?",
+            LIST_COLUMNS,
+            clause.sql()
+        ))?;
+
+        let limit = Value::from(limit);
+        let params = clause.params.iter().chain(std::iter::once(&limit));
+        let entries = stmt.query_map(rusqlite::params_from_iter(params), entry_from_row)?;
+        entries.collect()
+    }
+
+    /// Tag an entry; returns false if it already had the tag
+    pub fn add_tag(&self, id: i64, tag: &str) -> SqliteResult<bool> {
+        let conn = self.conn.lock().unwrap();
+        let added = retry_busy(|| {
+            conn.execute(
+                "INSERT OR IGNORE INTO tags (entry_id, tag) VALUES (?1, ?2)",
+                rusqlite::params![id, tag],
+            )
+        })?;
+        Ok(added > 0)
+    }
+
+    /// Remove a tag from an entry; returns false if it did not have the tag
+    pub fn remove_tag(&self, id: i64, tag: &str) -> SqliteResult<bool> {
+        let conn = self.conn.lock().unwrap();
+        let removed = retry_busy(|| {
+            conn.execute(
+                "DELETE FROM tags WHERE entry_id = ?1 AND tag = ?2",
+                rusqlite::params![id, tag],
+            )
+        })?;
+        Ok(removed > 0)
+    }
+
+    /// Tags of an entry, alphabetically
+    pub fn entry_tags(&self, id: i64) -> SqliteResult<Vec<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT tag FROM tags WHERE entry_id = ?1 ORDER BY tag")?;
+        let tags = stmt.query_map([id], |row| row.get(0))?;
+        tags.collect()
+    }
+
+    /// Distinct tags used in a workspace with their entry counts, most used
+    /// first, for autocomplete
+    pub fn list_tags(&self, workspace: &str) -> SqliteResult<Vec<TagCount>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT tags.tag, COUNT(*) FROM tags
+             JOIN history ON history.id = tags.entry_id
+             WHERE history.workspace = ?1
+             GROUP BY tags.tag
+             ORDER BY COUNT(*) DESC, tags.tag",
+        )?;
+        let tags = stmt.query_map([workspace], |row| {
+            Ok(TagCount {
+                tag: row.get(0)?,
+                count: row.get(1)?,
+            })
+        })?;
+        tags.collect()
+    }
+
+    /// Delete a specific history entry
+    pub fn delete_entry(&self, id: i64) -> SqliteResult<bool> {
+        let conn = self.conn.lock().unwrap();
+        let affected = retry_busy(|| {
+            conn.execute("DELETE FROM history WHERE id = ?1", rusqlite::params![id])
+        })?;
+        self.collect_garbage(&conn)?;
+        Ok(affected > 0)
+    }
+
+    /// Clear all history for a workspace
+    pub fn clear_workspace(&self, workspace: &str) -> SqliteResult<usize> {
+        let conn = self.conn.lock().unwrap();
+        let affected = retry_busy(|| {
+            conn.execute(
+                "DELETE FROM history WHERE workspace = ?1",
+                rusqlite::params![workspace],
+            )
+        })?;
+        self.collect_garbage(&conn)?;
+        Ok(affected)
+    }
+
+    /// Delete a workspace's entries from strictly before `before`, keeping
+    /// entries tagged `pinned`. Returns the number of entries deleted.
+    pub fn delete_before(&self, workspace: &str, before: DateTime<Utc>) -> Result<usize, String> {
+        if before > Utc::now() {
+            return Err(format!("{} is in the future", before.to_rfc3339()));
+        }
+
+        let conn = self.conn.lock().unwrap();
+        let affected = retry_busy(|| {
+            conn.execute(
+                "DELETE FROM history
+                 WHERE workspace = ?1 AND timestamp < ?2
+                   AND id NOT IN (SELECT entry_id FROM tags WHERE tag = ?3)",
+                rusqlite::params![workspace, before.to_rfc3339(), PINNED_TAG],
+            )
+        })
+        .map_err(|e| e.to_string())?;
+        self.collect_garbage(&conn).map_err(|e| e.to_string())?;
+        Ok(affected)
+    }
+
+    /// Clear all history
+    #[allow(dead_code)]
+    pub fn clear_all(&self) -> SqliteResult<usize> {
+        let conn = self.conn.lock().unwrap();
+        let affected = retry_busy(|| conn.execute("DELETE FROM history", []))?;
+        self.collect_garbage(&conn)?;
+        Ok(affected)
+    }
+
+    /// The stored retention settings, or the defaults if none were saved
+    pub fn get_retention(&self) -> SqliteResult<RetentionSettings> {
+        let conn = self.conn.lock().unwrap();
+        let value: Option<String> = conn
+            .query_row(
+                "SELECT value FROM settings WHERE key = ?1",
+                [RETENTION_KEY],
+                |row| row.get(0),
+            )
+            .map(Some)
+            .or_else(|e| match e {
+                rusqlite::Error::QueryReturnedNoRows => Ok(None),
+                e => Err(e),
+            })?;
+
+        Ok(value
+            .and_then(|v| serde_json::from_str(&v).ok())
+            .unwrap_or_default())
+    }
+
+    /// Save the retention settings
+    pub fn set_retention(&self, settings: &RetentionSettings) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        let value = serde_json::to_string(settings).unwrap_or_default();
+        retry_busy(|| {
+            conn.execute(
+                "INSERT INTO settings (key, value) VALUES (?1, ?2)
+                 ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+                rusqlite::params![RETENTION_KEY, value],
+            )
+        })?;
+        Ok(())
+    }
+
+    /// All settings stored for a workspace
+    pub fn get_workspace_settings(&self, workspace: &str) -> SqliteResult<HashMap<String, String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt =
+            conn.prepare("SELECT key, value FROM workspace_settings WHERE workspace = ?1")?;
+        let settings = stmt
+            .query_map([workspace], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect();
+        settings
+    }
+
+    /// One setting stored for a workspace, if it was set
+    pub fn get_workspace_setting(
+        &self,
+        workspace: &str,
+        key: &str,
+    ) -> SqliteResult<Option<String>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT value FROM workspace_settings WHERE workspace = ?1 AND key = ?2",
+            [workspace, key],
+            |row| row.get(0),
+        )
+        .map(Some)
+        .or_else(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => Ok(None),
+            e => Err(e),
+        })
+    }
+
+    /// Workspaces that have a setting stored
+    pub fn workspaces_with_setting(&self, key: &str) -> SqliteResult<Vec<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT workspace FROM workspace_settings WHERE key = ?1 ORDER BY workspace",
+        )?;
+        let workspaces = stmt.query_map([key], |row| row.get(0))?.collect();
+        workspaces
+    }
+
+    /// Store a setting for a workspace, replacing any previous value. `None`
+    /// removes it.
+    pub fn set_workspace_setting(
+        &self,
+        workspace: &str,
+        key: &str,
+        value: Option<&str>,
+    ) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        retry_busy(|| match value {
+            Some(value) => conn.execute(
+                "INSERT INTO workspace_settings (workspace, key, value) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(workspace, key) DO UPDATE SET value = excluded.value",
+                rusqlite::params![workspace, key, value],
+            ),
+            None => conn.execute(
+                "DELETE FROM workspace_settings WHERE workspace = ?1 AND key = ?2",
+                [workspace, key],
+            ),
+        })?;
+        Ok(())
+    }
+
+    /// Workspaces opened before, pinned ones first and then the most recently
+    /// opened
+    pub fn get_recent_workspaces(&self) -> SqliteResult<Vec<RecentWorkspace>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT path, last_opened, pinned FROM recent_workspaces
+             ORDER BY pinned DESC, last_opened DESC",
+        )?;
+        let workspaces = stmt.query_map([], |row| {
+            let path: String = row.get(0)?;
+            let last_opened: String = row.get(1)?;
+            Ok(RecentWorkspace {
+                missing: !Path::new(&path).is_dir(),
+                path,
+                last_opened: DateTime::parse_from_rfc3339(&last_opened)
+                    .map(|dt| dt.with_timezone(&Utc))
+                    .map_err(|e| {
+                        rusqlite::Error::FromSqlConversionFailure(1, Type::Text, Box::new(e))
+                    })?,
+                pinned: row.get(2)?,
+            })
+        })?;
+        workspaces.collect()
+    }
+
+    /// Record that a workspace was opened now, dropping the least recently
+    /// opened unpinned workspaces beyond [`MAX_RECENT_WORKSPACES`]
+    pub fn touch_workspace(&self, path: &str) -> SqliteResult<()> {
+        self.touch_workspace_at(path, Utc::now())
+    }
+
+    fn touch_workspace_at(&self, path: &str, opened: DateTime<Utc>) -> SqliteResult<()> {
+        let mut conn = self.conn.lock().unwrap();
+        retry_busy(|| {
+            let tx = conn.transaction()?;
+            tx.execute(
+                "INSERT INTO recent_workspaces (path, last_opened) VALUES (?1, ?2)
+                 ON CONFLICT(path) DO UPDATE SET last_opened = excluded.last_opened",
+                rusqlite::params![path, opened.to_rfc3339()],
+            )?;
+            tx.execute(
+                "DELETE FROM recent_workspaces WHERE pinned = 0 AND path NOT IN (
+                    SELECT path FROM recent_workspaces WHERE pinned = 0
+                    ORDER BY last_opened DESC LIMIT ?1
+                )",
+                [MAX_RECENT_WORKSPACES],
+            )?;
+            tx.commit()
+        })
+    }
+
+    /// Pin or unpin a recent workspace. Returns false if it isn't in the list.
+    pub fn pin_workspace(&self, path: &str, pinned: bool) -> SqliteResult<bool> {
+        let conn = self.conn.lock().unwrap();
+        let affected = retry_busy(|| {
+            conn.execute(
+                "UPDATE recent_workspaces SET pinned = ?2 WHERE path = ?1",
+                rusqlite::params![path, pinned],
+            )
+        })?;
+        Ok(affected > 0)
+    }
+
+    /// Remove a workspace from the recent list. Returns false if it wasn't in
+    /// it.
+    pub fn remove_recent_workspace(&self, path: &str) -> SqliteResult<bool> {
+        let conn = self.conn.lock().unwrap();
+        let affected =
+            retry_busy(|| conn.execute("DELETE FROM recent_workspaces WHERE path = ?1", [path]))?;
+        Ok(affected > 0)
+    }
+
+    /// Delete entries beyond the retention settings, compacting the database
+    /// when a large share of it was freed
+    pub fn prune_now(&self) -> SqliteResult<PruneReport> {
+        let settings = self.get_retention()?;
+        let conn = self.conn.lock().unwrap();
+        let mut deleted = 0;
+
+        if let Some(max_age_days) = settings.max_age_days {
+            let cutoff = Utc::now() - chrono::Duration::days(max_age_days);
+            deleted += retry_busy(|| {
+                conn.execute(
+                    "DELETE FROM history WHERE timestamp < ?1",
+                    [cutoff.to_rfc3339()],
+                )
+            })?;
+        }
+
+        if let Some(max_entries) = settings.max_entries_per_workspace {
+            deleted += prune_per_workspace(&conn, max_entries)?;
+        }
+
+        if let Some(max_bytes) = settings.max_db_size_bytes {
+            // Drop the oldest entries in batches until the used pages fit
+            while used_bytes(&conn)? > max_bytes {
+                let removed = retry_busy(|| {
+                    conn.execute(
+                        "DELETE FROM history WHERE id IN (
+                            SELECT id FROM history ORDER BY timestamp ASC, id ASC
+                            LIMIT MAX(1, (SELECT COUNT(*) FROM history) / 20)
+                        )",
+                        [],
+                    )
+                })?;
+                if removed == 0 {
+                    break;
+                }
+                deleted += removed;
+            }
+        }
+
+        let page_count: i64 = conn.query_row("PRAGMA page_count", [], |row| row.get(0))?;
+        let free_pages: i64 = conn.query_row("PRAGMA freelist_count", [], |row| row.get(0))?;
+        let vacuumed = deleted > 0 && free_pages * 4 > page_count;
+        if deleted > 0 {
+            self.collect_garbage(&conn)?;
+        }
+        if vacuumed {
+            conn.execute("VACUUM", [])?;
+        }
+
+        Ok(PruneReport { deleted, vacuumed })
+    }
+
+    /// Write a copy of the database to `dest`, replacing any file there. Safe
+    /// while the app runs. Response bodies kept in blob files are stored in
+    /// the copy so it stands alone.
+    pub fn export_db(&self, dest: &Path) -> Result<(), String> {
+        if dest.exists() {
+            std::fs::remove_file(dest)
+                .map_err(|e| format!("Failed to replace {}: {}", dest.display(), e))?;
+        }
+
+        let conn = self.conn.lock().unwrap();
+        conn.execute("VACUUM INTO ?1", [dest.to_string_lossy()])
+            .map_err(|e| format!("Failed to copy history database: {}", e))?;
+
+        let copy = Connection::open(dest).map_err(|e| e.to_string())?;
+        inline_blobs(&copy, &self.blob_dir).map_err(|e| e.to_string())
+    }
+
+    /// Import the history in a database file written by `export_db` (or any
+    /// version's history database), along with entry tags. Replayed entries
+    /// are linked to their imported originals. Older schemas are migrated on
+    /// a temporary copy; the source file is left untouched.
+    pub fn import_db(
+        &self,
+        src: &Path,
+        mode: HistoryImportMode,
+    ) -> Result<HistoryImportReport, String> {
+        let staging = std::env::temp_dir().join(format!(
+            "kvile-import-{}-{}.db",
+            std::process::id(),
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        ));
+        let result = self.import_staged(src, &staging, mode);
+        for suffix in ["", "-wal", "-shm"] {
+            std::fs::remove_file(with_suffix(&staging, suffix)).ok();
+        }
+        result
+    }
+
+    fn import_staged(
+        &self,
+        src: &Path,
+        staging: &Path,
+        mode: HistoryImportMode,
+    ) -> Result<HistoryImportReport, String> {
+        let source = Connection::open_with_flags(src, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(|e| format!("Failed to open {}: {}", src.display(), e))?;
+        source
+            .execute("VACUUM INTO ?1", [staging.to_string_lossy()])
+            .map_err(|e| format!("Failed to read {}: {}", src.display(), e))?;
+        drop(source);
+
+        let mut source = Connection::open(staging).map_err(|e| e.to_string())?;
+        if migrations::is_newer(&source).map_err(|e| e.to_string())? {
+            return Err(format!(
+                "{} was written by a newer version of Kvile",
+                src.display()
+            ));
+        }
+        migrations::migrate(&mut source)
+            .map_err(|e| format!("Failed to upgrade {}: {}", src.display(), e))?;
+
+        let mut tags: HashMap<i64, Vec<String>> = HashMap::new();
+        for row in source
+            .prepare("SELECT entry_id, tag FROM tags")
+            .and_then(|mut stmt| {
+                stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                    .collect::<SqliteResult<Vec<(i64, String)>>>()
+            })
+            .map_err(|e| e.to_string())?
+        {
+            tags.entry(row.0).or_default().push(row.1);
+        }
+
+        let mut stmt = source
+            .prepare(&format!(
+                "SELECT {} FROM history ORDER BY id",
+                ENTRY_COLUMNS
+            ))
+            .map_err(|e| e.to_string())?;
+        let entries = stmt
+            .query_map([], |row| {
+                let mut entry = entry_from_row(row)?;
+                // Blob files only exist if the file came from this machine
+                if let Some(hash) = row.get::<_, Option<String>>(16)? {
+                    entry.response_body = read_blob(&self.blob_dir, &hash).unwrap_or_default();
+                }
+                Ok(entry)
+            })
+            .map_err(|e| e.to_string())?;
+
+        let conn = self.conn.lock().unwrap();
+        let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+        if mode == HistoryImportMode::Replace {
+            tx.execute("DELETE FROM history", [])
+                .map_err(|e| e.to_string())?;
+        }
+
+        let mut report = HistoryImportReport {
+            imported: 0,
+            skipped: 0,
+        };
+        // Source ids to ids in this database
+        let mut ids: HashMap<i64, i64> = HashMap::new();
+        for entry in entries {
+            let entry = entry.map_err(|e| e.to_string())?;
+            let source_id = entry.id;
+            let timestamp = entry.timestamp.to_rfc3339();
+
+            let existing = tx.query_row(
+                "SELECT id FROM history
+                 WHERE workspace = ?1 AND timestamp = ?2 AND url = ?3 AND method = ?4",
+                rusqlite::params![entry.workspace, timestamp, entry.url, entry.method],
+                |row| row.get(0),
+            );
+            let id = match existing {
+                Ok(id) => {
+                    report.skipped += 1;
+                    id
+                }
+                Err(rusqlite::Error::QueryReturnedNoRows) => {
+                    let mut new_entry = NewHistoryEntry::from(entry);
+                    new_entry.replayed_from = new_entry
+                        .replayed_from
+                        .and_then(|original| ids.get(&original).copied());
+                    let id = insert_entry(&tx, &self.blob_dir, &new_entry, &timestamp)
+                        .map_err(|e| e.to_string())?;
+                    for tag in tags.get(&source_id).into_iter().flatten() {
+                        tx.execute(
+                            "INSERT OR IGNORE INTO tags (entry_id, tag) VALUES (?1, ?2)",
+                            rusqlite::params![id, tag],
+                        )
+                        .map_err(|e| e.to_string())?;
+                    }
+                    report.imported += 1;
+                    id
+                }
+                Err(e) => return Err(e.to_string()),
+            };
+            ids.insert(source_id, id);
+        }
+        tx.commit().map_err(|e| e.to_string())?;
+
+        if mode == HistoryImportMode::Replace {
+            self.collect_garbage(&conn).map_err(|e| e.to_string())?;
+        }
+        Ok(report)
+    }
+
+    /// Entry count, database size and the oldest entry's timestamp
+    pub fn stats(&self) -> SqliteResult<HistoryStats> {
+        let conn = self.conn.lock().unwrap();
+        let (entry_count, oldest): (i64, Option<String>) =
+            conn.query_row("SELECT COUNT(*), MIN(timestamp) FROM history", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?;
+        let page_count: i64 = conn.query_row("PRAGMA page_count", [], |row| row.get(0))?;
+        let page_size: i64 = conn.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+
+        Ok(HistoryStats {
+            entry_count,
+            db_size_bytes: page_count * page_size,
+            oldest_entry: oldest
+                .and_then(|t| DateTime::parse_from_rfc3339(&t).ok())
+                .map(|t| t.with_timezone(&Utc)),
+        })
+    }
+}
+
+/// Set up a freshly opened connection: schema, SQL functions and blob
+/// offloading
+fn prepare(mut conn: Connection, blob_dir: &Path) -> SqliteResult<Connection> {
+    // Deleting an entry cascades to its tags
+    conn.pragma_update(None, "foreign_keys", true)?;
+    migrations::migrate(&mut conn)?;
+    conn.create_scalar_function(
+        "normalize_endpoint",
+        1,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| Ok(normalize_endpoint(&ctx.get::<String>(0)?)),
+    )?;
+    offload_inline_bodies(&conn, blob_dir)?;
+    Ok(conn)
+}
+
+/// Fail with SQLITE_CORRUPT unless `PRAGMA integrity_check` passes
+fn check_integrity(conn: &Connection) -> SqliteResult<()> {
+    let result: String = conn.query_row("PRAGMA integrity_check(1)", [], |row| row.get(0))?;
+    if result == "ok" {
+        return Ok(());
+    }
+    Err(rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CORRUPT),
+        Some(result),
+    ))
+}
+
+/// Rename the database file and its WAL files to a timestamped backup
+fn back_up_database(path: &Path) -> std::io::Result<Option<PathBuf>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let backup = with_suffix(
+        path,
+        &format!(".corrupt-{}", Utc::now().format("%Y%m%d%H%M%S")),
+    );
+    std::fs::rename(path, &backup)?;
+    for sidecar in ["-wal", "-shm"] {
+        let file = with_suffix(path, sidecar);
+        if file.exists() {
+            std::fs::rename(&file, with_suffix(&backup, sidecar))?;
+        }
+    }
+
+    Ok(Some(backup))
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Run a write, retrying with backoff while the database is busy or locked.
+///
+/// The busy timeout covers most contention, but SQLite reports some busy
+/// cases (such as two connections upgrading to write at once in WAL mode)
+/// immediately.
+fn retry_busy<T>(mut write: impl FnMut() -> SqliteResult<T>) -> SqliteResult<T> {
+    let mut attempt = 0;
+    loop {
+        match write() {
+            Err(rusqlite::Error::SqliteFailure(e, _))
+                if attempt < BUSY_RETRIES
+                    && matches!(
+                        e.code,
+                        rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked
+                    ) =>
+            {
+                attempt += 1;
+                std::thread::sleep(Duration::from_millis(20 << attempt));
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Keep only the most recent `max_entries` entries of each workspace
+fn prune_per_workspace(conn: &Connection, max_entries: i64) -> SqliteResult<usize> {
+    // Get all unique workspaces
+    let mut stmt = conn.prepare("SELECT DISTINCT workspace FROM history")?;
+    let workspaces: Vec<String> = stmt
+        .query_map([], |row| row.get(0))?
+        .filter_map(|r| r.ok())
+        .collect();
+
+    let mut total_deleted = 0;
+
+    for workspace in workspaces {
+        // Delete entries beyond the limit for each workspace
+        let deleted = retry_busy(|| {
+            conn.execute(
+                "DELETE FROM history WHERE workspace = ?1 AND id NOT IN (
+                    SELECT id FROM history WHERE workspace = ?1
+                    ORDER BY timestamp DESC, id DESC LIMIT ?2
+                )",
+                rusqlite::params![workspace, max_entries],
+            )
+        })?;
+        total_deleted += deleted;
+    }
+
+    Ok(total_deleted)
+}
+
+/// Bytes in the database's pages that hold data (excluding free pages)
+fn used_bytes(conn: &Connection) -> SqliteResult<i64> {
+    let page_count: i64 = conn.query_row("PRAGMA page_count", [], |row| row.get(0))?;
+    let free_pages: i64 = conn.query_row("PRAGMA freelist_count", [], |row| row.get(0))?;
+    let page_size: i64 = conn.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+    Ok((page_count - free_pages) * page_size)
+}
+
+/// Columns selected for a full history entry, in `entry_from_row` order
+const ENTRY_COLUMNS: &str = "id, timestamp, workspace, file_path, request_name,
+    method, url, request_headers, request_body,
+    status, status_text, response_headers,
+    COALESCE((SELECT content FROM bodies WHERE hash = response_body_hash), response_body),
+    duration_ms, response_size, replayed_from, response_body_blob,
+    environment, variables_snapshot, response_body_base64";
+
+/// An inline response body, stored once in `bodies` or (for entries added
+/// before bodies were shared) in the history row
+const RESPONSE_BODY: &str =
+    "COALESCE((SELECT content FROM bodies WHERE hash = response_body_hash), response_body)";
+
+/// `ENTRY_COLUMNS` with the bodies left out, for listing entries
+const LIST_COLUMNS: &str = "id, timestamp, workspace, file_path, request_name,
+    method, url, request_headers, NULL,
+    status, status_text, response_headers, '',
+    duration_ms, response_size, replayed_from, NULL,
+    environment, variables_snapshot, response_body_base64";
+
+/// Map a row selected with `ENTRY_COLUMNS` or `LIST_COLUMNS` to a history
+/// entry, leaving an offloaded response body empty
+fn entry_from_row(row: &rusqlite::Row) -> SqliteResult<HistoryEntry> {
+    let timestamp_str: String = row.get(1)?;
+    let timestamp = DateTime::parse_from_rfc3339(&timestamp_str)
+        .map(|dt| dt.with_timezone(&Utc))
+        .unwrap_or_else(|_| Utc::now());
+
+    Ok(HistoryEntry {
+        id: row.get(0)?,
+        timestamp,
+        workspace: row.get(2)?,
+        file_path: row.get(3)?,
+        request_name: row.get(4)?,
+        method: row.get(5)?,
+        url: row.get(6)?,
+        request_headers: row.get(7)?,
+        request_body: row.get(8)?,
+        status: row.get(9)?,
+        status_text: row.get(10)?,
+        response_headers: row.get(11)?,
+        response_body: row.get(12)?,
+        duration_ms: row.get(13)?,
+        response_size: row.get(14)?,
+        replayed_from: row.get(15)?,
+        environment: row.get(17)?,
+        variables_snapshot: row.get(18)?,
+        response_body_base64: row.get(19)?,
+    })
+}
+
+/// Group URLs by endpoint: drop the query string and fragment, and replace
+/// path segments that look like IDs (numbers, UUIDs, long hex strings) with
+/// `:id`, so `/users/123?x=1` becomes `/users/:id`.
+pub fn normalize_endpoint(url: &str) -> String {
+    let url = url.split(['?', '#']).next().unwrap_or_default();
+    // Leave the scheme and host alone
+    let path_start = match url.find("://") {
+        Some(i) => url[i + 3..].find('/').map_or(url.len(), |j| i + 3 + j),
+        None => 0,
+    };
+    let (origin, path) = url.split_at(path_start);
+
+    let path = path
+        .split('/')
+        .map(|segment| {
+            if is_id_segment(segment) {
+                ":id"
+            } else {
+                segment
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/");
+    format!("{}{}", origin, path)
+}
+
+fn is_id_segment(segment: &str) -> bool {
+    if segment.is_empty() {
+        return false;
+    }
+    if segment.bytes().all(|b| b.is_ascii_digit()) {
+        return true;
+    }
+    let is_uuid = segment.len() == 36
+        && segment.char_indices().all(|(i, c)| match i {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_hexdigit(),
+        });
+    // e.g. MongoDB ObjectIds and hashes
+    let is_hex_id = segment.len() >= 16
+        && segment.chars().all(|c| c.is_ascii_hexdigit())
+        && segment.chars().any(|c| c.is_ascii_digit());
+    is_uuid || is_hex_id
+}
+
+/// Map a row selected with `ENTRY_COLUMNS` to a history entry, reading an
+/// offloaded response body from its blob file
+fn full_entry_from_row(blob_dir: &Path, row: &rusqlite::Row) -> SqliteResult<HistoryEntry> {
+    let mut entry = entry_from_row(row)?;
+    if let Some(hash) = row.get::<_, Option<String>>(16)? {
+        entry.response_body = read_blob(blob_dir, &hash)
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(16, Type::Text, Box::new(e)))?;
+    }
+    Ok(entry)
+}
+
+/// Store a body under its SHA-256 hash, returning the hash
+fn write_blob(blob_dir: &Path, body: &str) -> std::io::Result<String> {
+    let hash = content_hash(body);
+    let path = blob_dir.join(&hash);
+    if !path.exists() {
+        std::fs::create_dir_all(blob_dir)?;
+        // Write then rename so a crash never leaves a partial blob behind
+        let partial = blob_dir.join(format!("{}.partial", hash));
+        std::fs::write(&partial, body)?;
+        std::fs::rename(&partial, &path)?;
+    }
+    Ok(hash)
+}
+
+fn read_blob(blob_dir: &Path, hash: &str) -> std::io::Result<String> {
+    std::fs::read_to_string(blob_dir.join(hash))
+}
+
+/// Delete blob files no longer referenced by any entry, returning how many
+fn collect_blob_garbage(conn: &Connection, blob_dir: &Path) -> SqliteResult<usize> {
+    let Ok(files) = std::fs::read_dir(blob_dir) else {
+        return Ok(0);
+    };
+
+    let mut stmt = conn.prepare(
+        "SELECT DISTINCT response_body_blob FROM history WHERE response_body_blob IS NOT NULL",
+    )?;
+    let referenced: HashSet<String> = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<SqliteResult<_>>()?;
+
+    let mut removed = 0;
+    for file in files.flatten() {
+        let name = file.file_name().to_string_lossy().to_string();
+        if !referenced.contains(&name) && std::fs::remove_file(file.path()).is_ok() {
+            removed += 1;
+        }
+    }
+
+    Ok(removed)
+}
+
+/// Move response bodies stored inline before blob files existed out of the
+/// table. Runs once per database.
+fn offload_inline_bodies(conn: &Connection, blob_dir: &Path) -> SqliteResult<()> {
+    let migrated = conn
+        .prepare("SELECT 1 FROM settings WHERE key = ?1")?
+        .exists([BLOBS_MIGRATED_KEY])?;
+    if migrated {
+        return Ok(());
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT id, response_body FROM history
+         WHERE response_body_blob IS NULL AND length(CAST(response_body AS BLOB)) > ?1",
+    )?;
+    let oversized: Vec<(i64, String)> = stmt
+        .query_map([INLINE_BODY_LIMIT as i64], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?
+        .collect::<SqliteResult<_>>()?;
+
+    for (id, body) in oversized {
+        let hash = write_blob(blob_dir, &body)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        conn.execute(
+            "UPDATE history SET response_body = '', response_body_blob = ?1 WHERE id = ?2",
+            rusqlite::params![hash, id],
+        )?;
+    }
+
+    conn.execute(
+        "INSERT INTO settings (key, value) VALUES (?1, '1')",
+        [BLOBS_MIGRATED_KEY],
+    )?;
+    Ok(())
+}
+
+/// Move response bodies kept in blob files into the database
+fn inline_blobs(conn: &Connection, blob_dir: &Path) -> SqliteResult<()> {
+    let offloaded: Vec<(i64, String)> = conn
+        .prepare("SELECT id, response_body_blob FROM history WHERE response_body_blob IS NOT NULL")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<SqliteResult<_>>()?;
+
+    for (id, blob) in offloaded {
+        // A blob removed since the copy was taken leaves an empty body
+        let body = read_blob(blob_dir, &blob).unwrap_or_default();
+        let tx = conn.unchecked_transaction()?;
+        let hash = store_body(&tx, &body)?;
+        tx.execute(
+            "UPDATE history SET response_body_blob = NULL, response_body_hash = ?1 WHERE id = ?2",
+            rusqlite::params![hash, id],
+        )?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+/// Insert an entry with the given timestamp, keeping a large response body in
+/// a blob file and others in the shared `bodies` table. Returns the new id.
+fn insert_entry(
+    conn: &Connection,
+    blob_dir: &Path,
+    entry: &NewHistoryEntry,
+    timestamp: &str,
+) -> SqliteResult<i64> {
+    let (blob, body_hash) = if entry.response_body.len() > INLINE_BODY_LIMIT {
+        let hash = write_blob(blob_dir, &entry.response_body)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        (Some(hash), None)
+    } else {
+        // Identical bodies, as from polling an endpoint, are stored once
+        (None, Some(store_body(conn, &entry.response_body)?))
+    };
+
+    conn.execute(
+        "INSERT INTO history (
+            timestamp, workspace, file_path, request_name,
+            method, url, request_headers, request_body,
+            status, status_text, response_headers, response_body,
+            duration_ms, response_size, replayed_from, response_body_blob,
+            environment, variables_snapshot, response_body_hash, response_body_base64
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, '', ?12, ?13, ?14, ?15,
+            ?16, ?17, ?18, ?19)",
+        rusqlite::params![
+            timestamp,
+            entry.workspace,
+            entry.file_path,
+            entry.request_name,
+            entry.method,
+            entry.url,
+            entry.request_headers,
+            entry.request_body,
+            entry.status,
+            entry.status_text,
+            entry.response_headers,
+            entry.duration_ms,
+            entry.response_size,
+            entry.replayed_from,
+            blob,
+            entry.environment,
+            entry.variables_snapshot,
+            body_hash,
+            entry.response_body_base64,
+        ],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// SHA-256 of a body, naming its blob file or `bodies` row
+fn content_hash(body: &str) -> String {
+    format!("{:x}", Sha256::digest(body.as_bytes()))
+}
+
+/// Store a body in the shared `bodies` table, or take another reference to
+/// an identical one, and return its hash
+fn store_body(conn: &Connection, body: &str) -> SqliteResult<String> {
+    let hash = content_hash(body);
+    conn.execute(
+        "INSERT INTO bodies (hash, content, refcount) VALUES (?1, ?2, 1)
+         ON CONFLICT(hash) DO UPDATE SET refcount = refcount + 1",
+        rusqlite::params![hash, body],
+    )?;
+    Ok(hash)
+}
+
+/// Write entries as a JSON array
+fn write_json<W: Write>(
+    entries: impl Iterator<Item = SqliteResult<HistoryEntry>>,
+    writer: W,
+) -> Result<usize, String> {
+    let mut writer = BufWriter::new(writer);
+    let io_err = |e: std::io::Error| format!("Failed to write export: {}", e);
+
+    writer.write_all(b"[").map_err(io_err)?;
+    let mut count = 0;
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("Failed to read history: {}", e))?;
+        if count > 0 {
+            writer.write_all(b",").map_err(io_err)?;
+        }
+        writer.write_all(b"\n  ").map_err(io_err)?;
+        serde_json::to_writer(&mut writer, &entry)
+            .map_err(|e| format!("Failed to write export: {}", e))?;
+        count += 1;
+    }
+    writer
+        .write_all(if count > 0 { b"\n]\n" } else { b"]\n" })
+        .map_err(io_err)?;
+    writer.flush().map_err(io_err)?;
+
+    Ok(count)
+}
+
+/// Write entries as CSV rows with bodies cut to `body_limit` characters
+fn write_csv<W: Write>(
+    entries: impl Iterator<Item = SqliteResult<HistoryEntry>>,
+    body_limit: usize,
+    writer: W,
+) -> Result<usize, String> {
+    let mut csv = csv::Writer::from_writer(writer);
+    let csv_err = |e: csv::Error| format!("Failed to write export: {}", e);
+    let truncate = |body: &str| match body.char_indices().nth(body_limit) {
+        Some((end, _)) => (body[..end].to_string(), "true"),
+        None => (body.to_string(), "false"),
+    };
+
+    csv.write_record(CSV_HEADER).map_err(csv_err)?;
+    let mut count = 0;
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("Failed to read history: {}", e))?;
+        let (request_body, request_truncated) =
+            truncate(entry.request_body.as_deref().unwrap_or_default());
+        let (response_body, response_truncated) = truncate(&entry.response_body);
+        csv.write_record([
+            entry.id.to_string(),
+            entry.timestamp.to_rfc3339(),
+            entry.workspace,
+            entry.file_path.unwrap_or_default(),
+            entry.request_name.unwrap_or_default(),
+            entry.method,
+            entry.url,
+            entry.status.to_string(),
+            entry.status_text,
+            entry.duration_ms.to_string(),
+            entry.response_size.to_string(),
+            entry
+                .replayed_from
+                .map(|id| id.to_string())
+                .unwrap_or_default(),
+            entry.environment.unwrap_or_default(),
+            entry.request_headers,
+            request_body,
+            request_truncated.to_string(),
+            entry.response_headers,
+            response_body,
+            response_truncated.to_string(),
+        ])
+        .map_err(csv_err)?;
+        count += 1;
+    }
+    csv.flush()
+        .map_err(|e| format!("Failed to write export: {}", e))?;
+
+    Ok(count)
+}
+
+/// Turn free text into an FTS5 query matching every term, with each term
+/// quoted so punctuation and FTS5 syntax in it are taken literally
+fn fts_query(query: &str) -> Option<String> {
+    let terms: Vec<String> = query
+        .split_whitespace()
+        // A term of only punctuation has no tokens to match
+        .filter(|term| term.chars().any(|c| c.is_alphanumeric() || c == '_'))
+        .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+/// Escape `%`, `_` and the escape character itself for `LIKE ... ESCAPE '\'`
+fn escape_like(term: &str) -> String {
+    let mut escaped = String::with_capacity(term.len());
+    for c in term.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Get the database file path
+fn get_database_path() -> PathBuf {
+    let data_dir = dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("kvile");
+
+    data_dir.join("history.db")
+}
+
+/// Get the directory for response bodies too large to keep in the database
+fn get_blob_dir() -> PathBuf {
+    get_database_path().with_file_name("history-blobs")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_database_path() {
+        let path = get_database_path();
+        assert!(path.to_string_lossy().contains("kvile"));
+        assert!(path.to_string_lossy().ends_with("history.db"));
+    }
+
+    fn seed(db: &HistoryDb) {
+        let entries = [
+            (
+                "GET",
+                "https://api.example.com/users",
+                Some("List users"),
+                None,
+                200,
+                "[]",
+            ),
+            (
+                "POST",
+                "https://api.example.com/users",
+                Some("Create user"),
+                Some(r#"{"name":"Ada"}"#),
+                201,
+                "{}",
+            ),
+            (
+                "POST",
+                "https://api.example.com/orders",
+                None,
+                Some(r#"{"sku":"100%_cotton"}"#),
+                500,
+                "boom",
+            ),
+            (
+                "DELETE",
+                "https://api.example.com/users/1",
+                None,
+                None,
+                404,
+                "not found",
+            ),
+        ];
+        for (i, (method, url, name, body, status, response)) in entries.into_iter().enumerate() {
+            let id = db
+                .add_entry(NewHistoryEntry {
+                    workspace: "/ws".to_string(),
+                    file_path: None,
+                    request_name: name.map(str::to_string),
+                    method: method.to_string(),
+                    url: url.to_string(),
+                    request_headers: "{}".to_string(),
+                    request_body: body.map(str::to_string),
+                    status,
+                    status_text: String::new(),
+                    response_headers: "{}".to_string(),
+                    response_body: response.to_string(),
+                    duration_ms: 10,
+                    response_size: response.len() as i64,
+                    replayed_from: None,
+                    environment: None,
+                    variables_snapshot: None,
+                    response_body_base64: false,
+                })
+                .unwrap();
+            // Deterministic, increasing timestamps
+            db.conn
+                .lock()
+                .unwrap()
+                .execute(
+                    "UPDATE history SET timestamp = ?1 WHERE id = ?2",
+                    rusqlite::params![format!("2024-01-01T00:00:0{}+00:00", i), id],
+                )
+                .unwrap();
+        }
+        db.add_entry(NewHistoryEntry {
+            workspace: "/other".to_string(),
+            file_path: None,
+            request_name: None,
+            method: "GET".to_string(),
+            url: "https://api.example.com/users".to_string(),
+            request_headers: "{}".to_string(),
+            request_body: None,
+            status: 200,
+            status_text: String::new(),
+            response_headers: "{}".to_string(),
+            response_body: String::new(),
+            duration_ms: 1,
+            response_size: 0,
+            replayed_from: None,
+            environment: None,
+            variables_snapshot: None,
+            response_body_base64: false,
+        })
+        .unwrap();
+    }
+
+    fn urls(entries: &[HistoryEntry]) -> Vec<String> {
+        entries
+            .iter()
+            .map(|e| format!("{} {}", e.method, e.url))
+            .collect()
+    }
+
+    #[test]
+    fn test_search_free_text_and_prefixes() {
+        let db = HistoryDb::in_memory().unwrap();
+        seed(&db);
+
+        assert_eq!(
+            urls(&db.search("/ws", "users", 100, false).unwrap()),
+            vec![
+                "DELETE https://api.example.com/users/1",
+                "POST https://api.example.com/users",
+                "GET https://api.example.com/users",
+            ]
+        );
+        assert_eq!(
+            urls(&db.search("/ws", "method:post users", 100, false).unwrap()),
+            vec!["POST https://api.example.com/users"]
+        );
+        assert_eq!(
+            urls(&db.search("/ws", "status:500", 100, false).unwrap()),
+            vec!["POST https://api.example.com/orders"]
+        );
+        assert_eq!(
+            urls(&db.search("/ws", "status:4xx", 100, false).unwrap()),
+            vec!["DELETE https://api.example.com/users/1"]
+        );
+        assert_eq!(
+            urls(&db.search("/ws", "create", 100, false).unwrap()),
+            vec!["POST https://api.example.com/users"]
+        );
+        assert_eq!(db.search("/ws", "", 2, false).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_search_bodies_and_escaping() {
+        let db = HistoryDb::in_memory().unwrap();
+        seed(&db);
+
+        assert!(db.search("/ws", "Ada", 100, false).unwrap().is_empty());
+        assert_eq!(
+            urls(&db.search("/ws", "Ada", 100, true).unwrap()),
+            vec!["POST https://api.example.com/users"]
+        );
+        // % and _ match literally
+        assert_eq!(
+            urls(&db.search("/ws", "100%_cotton", 100, true).unwrap()),
+            vec!["POST https://api.example.com/orders"]
+        );
+        assert!(db
+            .search("/ws", "100%cotton", 100, true)
+            .unwrap()
+            .is_empty());
+        assert!(db.search("/ws", "a_i", 100, false).unwrap().is_empty());
+    }
+
+    /// Insert an entry into `/ws` with a fixed timestamp
+    fn insert_at(
+        db: &HistoryDb,
+        timestamp: &str,
+        method: &str,
+        status: i32,
+        file_path: Option<&str>,
+        request_name: Option<&str>,
+    ) -> i64 {
+        let id = db
+            .add_entry(NewHistoryEntry {
+                workspace: "/ws".to_string(),
+                file_path: file_path.map(str::to_string),
+                request_name: request_name.map(str::to_string),
+                method: method.to_string(),
+                url: format!("https://api.example.com/{}", status),
+                request_headers: "{}".to_string(),
+                request_body: None,
+                status,
+                status_text: String::new(),
+                response_headers: "{}".to_string(),
+                response_body: String::new(),
+                duration_ms: 1,
+                response_size: 0,
+                replayed_from: None,
+                environment: None,
+                variables_snapshot: None,
+                response_body_base64: false,
+            })
+            .unwrap();
+        db.conn
+            .lock()
+            .unwrap()
+            .execute(
+                "UPDATE history SET timestamp = ?1 WHERE id = ?2",
+                rusqlite::params![timestamp, id],
+            )
+            .unwrap();
+        id
+    }
+
+    fn seed_days(db: &HistoryDb) -> Vec<i64> {
+        vec![
+            insert_at(
+                db,
+                "2024-03-01T09:00:00+00:00",
+                "POST",
+                500,
+                Some("users.http"),
+                Some("create"),
+            ),
+            insert_at(
+                db,
+                "2024-03-01T10:00:00+00:00",
+                "GET",
+                200,
+                Some("users.http"),
+                Some("list"),
+            ),
+            insert_at(
+                db,
+                "2024-03-02T08:30:00+00:00",
+                "POST",
+                422,
+                Some("users.http"),
+                Some("create"),
+            ),
+            insert_at(
+                db,
+                "2024-03-02T12:00:00.5+00:00",
+                "post",
+                503,
+                Some("orders.http"),
+                None,
+            ),
+            insert_at(
+                db,
+                "2024-03-02T18:00:00+00:00",
+                "DELETE",
+                404,
+                Some("orders.http"),
+                Some("cancel"),
+            ),
+            insert_at(
+                db,
+                "2024-03-02T19:00:00+00:00",
+                "POST",
+                201,
+                Some("orders.http"),
+                None,
+            ),
+            insert_at(db, "2024-03-03T07:00:00+00:00", "POST", 500, None, None),
+        ]
+    }
+
+    fn filtered(db: &HistoryDb, filter: HistoryFilter) -> Vec<i64> {
+        db.get_entries_filtered("/ws", &filter, 100)
+            .unwrap()
+            .iter()
+            .map(|e| e.id)
+            .collect()
+    }
+
+    fn at(timestamp: &str) -> Option<DateTime<Utc>> {
+        Some(
+            DateTime::parse_from_rfc3339(timestamp)
+                .unwrap()
+                .with_timezone(&Utc),
+        )
+    }
+
+    #[test]
+    fn test_filters_individually() {
+        let db = HistoryDb::in_memory().unwrap();
+        let ids = seed_days(&db);
+
+        assert_eq!(filtered(&db, HistoryFilter::default()).len(), ids.len());
+        assert_eq!(
+            filtered(
+                &db,
+                HistoryFilter {
+                    methods: vec!["delete".into(), "GET".into()],
+                    ..Default::default()
+                }
+            ),
+            vec![ids[4], ids[1]]
+        );
+        assert_eq!(
+            filtered(
+                &db,
+                HistoryFilter {
+                    status_class: Some(4),
+                    ..Default::default()
+                }
+            ),
+            vec![ids[4], ids[2]]
+        );
+        assert_eq!(
+            filtered(
+                &db,
+                HistoryFilter {
+                    status_min: Some(500),
+                    status_max: Some(502),
+                    ..Default::default()
+                }
+            ),
+            vec![ids[6], ids[0]]
+        );
+        assert_eq!(
+            filtered(
+                &db,
+                HistoryFilter {
+                    file_path: Some("orders.http".into()),
+                    ..Default::default()
+                }
+            ),
+            vec![ids[5], ids[4], ids[3]]
+        );
+        assert_eq!(
+            filtered(
+                &db,
+                HistoryFilter {
+                    request_name: Some("create".into()),
+                    ..Default::default()
+                }
+            ),
+            vec![ids[2], ids[0]]
+        );
+        assert_eq!(
+            filtered(
+                &db,
+                HistoryFilter {
+                    from: at("2024-03-02T12:00:00Z"),
+                    to: at("2024-03-02T18:00:00Z"),
+                    ..Default::default()
+                }
+            ),
+            vec![ids[4], ids[3]]
+        );
+    }
+
+    #[test]
+    fn test_filters_combined() {
+        let db = HistoryDb::in_memory().unwrap();
+        let ids = seed_days(&db);
+
+        // Failed POSTs from March 2nd
+        let filter = HistoryFilter {
+            methods: vec!["POST".into()],
+            status_min: Some(400),
+            from: at("2024-03-02T00:00:00Z"),
+            to: at("2024-03-02T23:59:59Z"),
+            ..Default::default()
+        };
+        assert_eq!(filtered(&db, filter.clone()), vec![ids[3], ids[2]]);
+        assert!(db
+            .get_entries_filtered("/other", &filter, 100)
+            .unwrap()
+            .is_empty());
+
+        let filter: HistoryFilter =
+            serde_json::from_str(r#"{"status_class": 5, "file_path": "users.http"}"#).unwrap();
+        assert_eq!(filtered(&db, filter), vec![ids[0]]);
+    }
+
+    #[test]
+    fn test_filtered_query_uses_workspace_index() {
+        let db = HistoryDb::in_memory().unwrap();
+        let mut clause = WhereClause::new("/ws");
+        HistoryFilter {
+            methods: vec!["POST".into()],
+            status_class: Some(5),
+            from: at("2024-03-02T00:00:00Z"),
+            ..Default::default()
+        }
+        .apply(&mut clause);
+
+        let conn = db.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare(&format!(
+                "EXPLAIN QUERY PLAN SELECT id FROM history WHERE {} ORDER BY timestamp DESC",
+                clause.sql()
+            ))
+            .unwrap();
+        let plan: Vec<String> = stmt
+            .query_map(rusqlite::params_from_iter(clause.params.iter()), |row| {
+                row.get(3)
+            })
+            .unwrap()
+            .collect::<SqliteResult<_>>()
+            .unwrap();
+        assert!(
+            plan.iter()
+                .any(|step| step.contains("idx_history_workspace_timestamp")),
+            "{:?}",
+            plan
+        );
+    }
+
+    #[test]
+    fn test_pagination() {
+        let db = HistoryDb::in_memory().unwrap();
+        let start = DateTime::parse_from_rfc3339("2024-05-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        // Ten entries share each timestamp
+        for i in 0..350 {
+            let timestamp = (start + chrono::Duration::seconds(i / 10)).to_rfc3339();
+            let method = if i % 2 == 0 { "GET" } else { "POST" };
+            insert_at(&db, &timestamp, method, 200, None, None);
+        }
+
+        let mut seen = Vec::new();
+        let mut cursor = None;
+        let mut sizes = Vec::new();
+        loop {
+            let page = db
+                .get_page("/ws", &HistoryFilter::default(), cursor.as_ref(), 100)
+                .unwrap();
+            assert_eq!(page.total, 350);
+            sizes.push(page.entries.len());
+            seen.extend(page.entries.iter().map(|e| (e.timestamp, e.id)));
+            assert_eq!(page.has_more, page.next_cursor.is_some());
+            if !page.has_more {
+                break;
+            }
+            cursor = page.next_cursor;
+        }
+        assert_eq!(sizes, vec![100, 100, 100, 50]);
+
+        // Every entry exactly once, newest first with ties broken by id
+        let mut expected = seen.clone();
+        expected.sort_by(|a, b| b.cmp(a));
+        expected.dedup();
+        assert_eq!(seen, expected);
+        assert_eq!(seen.len(), 350);
+
+        // Adding a newer entry does not shift later pages
+        let first = db
+            .get_page("/ws", &HistoryFilter::default(), None, 100)
+            .unwrap();
+        insert_at(&db, "2024-06-01T00:00:00+00:00", "GET", 200, None, None);
+        let second = db
+            .get_page(
+                "/ws",
+                &HistoryFilter::default(),
+                first.next_cursor.as_ref(),
+                100,
+            )
+            .unwrap();
+        assert_eq!(second.entries[0].id, seen[100].1);
+        assert_eq!(second.total, 351);
+    }
+
+    #[test]
+    fn test_pagination_total_under_filter() {
+        let db = HistoryDb::in_memory().unwrap();
+        for i in 0..350 {
+            let method = if i % 2 == 0 { "GET" } else { "POST" };
+            let status = if i % 7 == 0 { 500 } else { 200 };
+            insert_at(&db, "2024-05-01T00:00:00+00:00", method, status, None, None);
+        }
+        let filter = HistoryFilter {
+            methods: vec!["POST".into()],
+            ..Default::default()
+        };
+        assert_eq!(db.count_entries("/ws", &filter).unwrap(), 175);
+
+        let page = db.get_page("/ws", &filter, None, 100).unwrap();
+        assert_eq!(
+            (page.entries.len(), page.total, page.has_more),
+            (100, 175, true)
+        );
+        let page = db
+            .get_page("/ws", &filter, page.next_cursor.as_ref(), 100)
+            .unwrap();
+        assert_eq!((page.entries.len(), page.has_more), (75, false));
+        assert!(page.entries.iter().all(|e| e.method == "POST"));
+
+        // Odd multiples of 7 below 350
+        let failed = HistoryFilter {
+            status_class: Some(5),
+            ..filter
+        };
+        assert_eq!(db.count_entries("/ws", &failed).unwrap(), 25);
+        assert_eq!(db.count_entries("/other", &failed).unwrap(), 0);
+    }
+
+    /// An entry for `url` as it would be recorded, with `authorization` as
+    /// its stored Authorization header
+    fn sent_entry(url: &str, authorization: &str) -> NewHistoryEntry {
+        NewHistoryEntry {
+            workspace: "/ws".to_string(),
+            file_path: Some("users.http".to_string()),
+            request_name: Some("create".to_string()),
+            method: "POST".to_string(),
+            url: url.to_string(),
+            request_headers: serde_json::json!({
+                "Content-Type": "application/json",
+                "X-Trace": "a,b",
+                "Authorization": authorization,
+            })
+            .to_string(),
+            request_body: Some(r#"{"name":"Ada"}"#.to_string()),
+            status: 201,
+            status_text: "Created".to_string(),
+            response_headers: "{}".to_string(),
+            response_body: String::new(),
+            duration_ms: 5,
+            response_size: 0,
+            replayed_from: None,
+            environment: Some("dev".to_string()),
+            variables_snapshot: Some(r#"{"token":"********"}"#.to_string()),
+            response_body_base64: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replay_reconstructs_request_and_links_entry() {
+        let (url, received) = crate::oidc::mock::serve_once("409 Conflict", "exists").await;
+        let db = HistoryDb::in_memory().unwrap();
+        let id = db
+            .add_entry(sent_entry(&url, "Basic dXNlcjpwYXNz"))
+            .unwrap();
+        let original = db.get_entry(id).unwrap().unwrap();
+        assert_eq!(original.replayed_from, None);
+
+        let request = original.to_request().unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.url, url);
+        assert_eq!(request.headers.len(), 3);
+        assert_eq!(request.headers["Content-Type"], "application/json");
+        assert_eq!(request.headers["X-Trace"], "a,b");
+        assert_eq!(request.body.as_deref(), Some(r#"{"name":"Ada"}"#));
+
+        let replay = resend(&db, id).await.unwrap();
+        let received = received.await.unwrap();
+        assert!(received.contains("authorization: Basic dXNlcjpwYXNz"));
+        assert!(received.ends_with(r#"{"name":"Ada"}"#));
+        assert_eq!(replay.replayed_from, Some(id));
+        assert_eq!(replay.status, 409);
+        assert_eq!(replay.response_body, "exists");
+        assert_eq!(replay.file_path.as_deref(), Some("users.http"));
+        assert_eq!(replay.environment.as_deref(), Some("dev"));
+        assert_eq!(replay.variables_snapshot, original.variables_snapshot);
+        assert_eq!(replay.to_request().unwrap().headers, request.headers);
+    }
+
+    #[tokio::test]
+    async fn test_replay_of_masked_token_fails() {
+        let db = HistoryDb::in_memory().unwrap();
+        let id = db
+            .add_entry(sent_entry("http://127.0.0.1:9/users", "Bearer ********"))
+            .unwrap();
+
+        let err = resend(&db, id).await.unwrap_err();
+        assert!(err.contains("OIDC token"), "{}", err);
+        assert_eq!(db.get_entries("/ws", 10).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_replay_rejects_unreadable_headers() {
+        let db = HistoryDb::in_memory().unwrap();
+        let id = insert_at(&db, "2024-01-01T00:00:00+00:00", "GET", 200, None, None);
+        db.conn
+            .lock()
+            .unwrap()
+            .execute(
+                "UPDATE history SET request_headers = 'Accept: */*' WHERE id = ?1",
+                [id],
+            )
+            .unwrap();
+
+        let err = db.get_entry(id).unwrap().unwrap().to_request().unwrap_err();
+        assert!(err.contains("unreadable request headers"), "{}", err);
+    }
+
+    fn seed_export(db: &HistoryDb) {
+        seed(db);
+        db.add_entry(NewHistoryEntry {
+            workspace: "/ws".to_string(),
+            file_path: Some("notes, \"draft\".http".to_string()),
+            request_name: None,
+            method: "PUT".to_string(),
+            url: "https://api.example.com/notes/1".to_string(),
+            request_headers: r#"{"Content-Type":"text/plain"}"#.to_string(),
+            request_body: Some("line one\nline \"two\", ünïcode".to_string()),
+            status: 200,
+            status_text: "OK".to_string(),
+            response_headers: "{}".to_string(),
+            response_body: "short".to_string(),
+            duration_ms: 3,
+            response_size: 5,
+            replayed_from: Some(1),
+            environment: None,
+            variables_snapshot: None,
+            response_body_base64: false,
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_export_json() {
+        let db = HistoryDb::in_memory().unwrap();
+        seed_export(&db);
+
+        let mut out = Vec::new();
+        let count = db
+            .export(
+                "/ws",
+                &HistoryFilter::default(),
+                HistoryExportFormat::Json,
+                10,
+                &mut out,
+            )
+            .unwrap();
+        let entries: Vec<HistoryEntry> = serde_json::from_slice(&out).unwrap();
+        assert_eq!((count, entries.len()), (5, 5));
+
+        // Oldest first, bodies untruncated
+        assert_eq!(entries[0].method, "GET");
+        let last = &entries[4];
+        assert_eq!(
+            last.request_body.as_deref(),
+            Some("line one\nline \"two\", ünïcode")
+        );
+        assert_eq!(last.replayed_from, Some(1));
+        let raw: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        let timestamp = raw[4]["timestamp"].as_str().unwrap();
+        assert!(DateTime::parse_from_rfc3339(timestamp).is_ok());
+
+        let mut out = Vec::new();
+        let filter = HistoryFilter {
+            methods: vec!["POST".into()],
+            ..Default::default()
+        };
+        assert_eq!(
+            db.export("/ws", &filter, HistoryExportFormat::Json, 10, &mut out)
+                .unwrap(),
+            2
+        );
+        let mut out = Vec::new();
+        db.export("/none", &filter, HistoryExportFormat::Json, 10, &mut out)
+            .unwrap();
+        assert_eq!(
+            serde_json::from_slice::<Vec<HistoryEntry>>(&out)
+                .unwrap()
+                .len(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_export_to_file() {
+        let db = HistoryDb::in_memory().unwrap();
+        seed_export(&db);
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("history.json");
+        std::fs::write(&dest, "previous").unwrap();
+
+        let count = db
+            .export_to_file(
+                "/ws",
+                &HistoryFilter::default(),
+                HistoryExportFormat::Json,
+                10,
+                &dest,
+            )
+            .unwrap();
+        let entries: Vec<HistoryEntry> =
+            serde_json::from_slice(&std::fs::read(&dest).unwrap()).unwrap();
+        assert_eq!((count, entries.len()), (5, 5));
+        // Nothing left behind from the atomic write
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        // A failed export leaves the file as it was
+        db.conn
+            .lock()
+            .unwrap()
+            .execute_batch("DROP TABLE tags")
+            .unwrap();
+        let filter = HistoryFilter {
+            tags: vec!["smoke".to_string()],
+            ..Default::default()
+        };
+        assert!(db
+            .export_to_file("/ws", &filter, HistoryExportFormat::Json, 10, &dest)
+            .is_err());
+        assert_eq!(
+            serde_json::from_slice::<Vec<HistoryEntry>>(&std::fs::read(&dest).unwrap())
+                .unwrap()
+                .len(),
+            5
+        );
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_export_csv() {
+        let db = HistoryDb::in_memory().unwrap();
+        seed_export(&db);
+
+        let mut out = Vec::new();
+        let count = db
+            .export(
+                "/ws",
+                &HistoryFilter::default(),
+                HistoryExportFormat::Csv,
+                12,
+                &mut out,
+            )
+            .unwrap();
+        let mut reader = csv::Reader::from_reader(out.as_slice());
+        assert_eq!(reader.headers().unwrap(), CSV_HEADER.as_slice());
+        let rows: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+        assert_eq!((count, rows.len()), (5, 5));
+
+        let row = &rows[4];
+        let field = |name: &str| &row[CSV_HEADER.iter().position(|h| *h == name).unwrap()];
+        assert_eq!(field("method"), "PUT");
+        assert_eq!(field("file_path"), "notes, \"draft\".http");
+        assert_eq!(field("request_body"), "line one\nlin");
+        assert_eq!(field("request_body_truncated"), "true");
+        assert_eq!(field("response_body"), "short");
+        assert_eq!(field("response_body_truncated"), "false");
+        assert_eq!(field("replayed_from"), "1");
+        assert!(DateTime::parse_from_rfc3339(field("timestamp")).is_ok());
+        assert_eq!(&rows[0][1], "2024-01-01T00:00:00+00:00");
+    }
+
+    fn set_retention(db: &HistoryDb, settings: RetentionSettings) {
+        db.set_retention(&settings).unwrap();
+        assert_eq!(db.get_retention().unwrap(), settings);
+    }
+
+    #[test]
+    fn test_retention_defaults_and_storage() {
+        let db = HistoryDb::in_memory().unwrap();
+        assert_eq!(db.get_retention().unwrap(), RetentionSettings::default());
+        set_retention(
+            &db,
+            RetentionSettings {
+                max_entries_per_workspace: None,
+                max_age_days: Some(7),
+                max_db_size_bytes: Some(1 << 20),
+            },
+        );
+        // Saving again replaces the stored value
+        set_retention(&db, RetentionSettings::default());
+    }
+
+    #[test]
+    fn test_workspace_settings_storage() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.db");
+        let db = HistoryDb::open(&path, dir.path().join("blobs")).unwrap();
+        assert!(db.get_workspace_settings("/ws").unwrap().is_empty());
+
+        db.set_workspace_setting("/ws", SELECTED_ENVIRONMENT_KEY, Some("dev"))
+            .unwrap();
+        db.set_workspace_setting("/ws", SELECTED_ENVIRONMENT_KEY, Some("staging"))
+            .unwrap();
+        db.set_workspace_setting("/ws", "layout", Some("split"))
+            .unwrap();
+        db.set_workspace_setting("/other", SELECTED_ENVIRONMENT_KEY, Some("prod"))
+            .unwrap();
+        drop(db);
+
+        // Settings survive reopening and stay per workspace
+        let db = HistoryDb::open(&path, dir.path().join("blobs")).unwrap();
+        assert_eq!(
+            db.get_workspace_settings("/ws").unwrap(),
+            HashMap::from([
+                (SELECTED_ENVIRONMENT_KEY.to_string(), "staging".to_string()),
+                ("layout".to_string(), "split".to_string()),
+            ])
+        );
+        assert_eq!(
+            db.get_workspace_setting("/other", SELECTED_ENVIRONMENT_KEY)
+                .unwrap()
+                .as_deref(),
+            Some("prod")
+        );
+
+        db.set_workspace_setting("/ws", SELECTED_ENVIRONMENT_KEY, None)
+            .unwrap();
+        assert_eq!(
+            db.get_workspace_setting("/ws", SELECTED_ENVIRONMENT_KEY)
+                .unwrap(),
+            None
+        );
+        assert_eq!(db.get_workspace_settings("/ws").unwrap().len(), 1);
+        assert_eq!(
+            db.workspaces_with_setting(SELECTED_ENVIRONMENT_KEY)
+                .unwrap(),
+            vec!["/other".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_recent_workspaces_by_recency() {
+        let db = HistoryDb::in_memory().unwrap();
+        let dirs: Vec<_> = (0..3).map(|_| tempfile::tempdir().unwrap()).collect();
+        let paths: Vec<String> = dirs
+            .iter()
+            .map(|d| d.path().to_string_lossy().to_string())
+            .collect();
+        let start = Utc::now() - chrono::Duration::hours(1);
+        let at = |minutes| start + chrono::Duration::minutes(minutes);
+        db.touch_workspace_at(&paths[0], at(0)).unwrap();
+        db.touch_workspace_at(&paths[1], at(1)).unwrap();
+        db.touch_workspace_at(&paths[2], at(2)).unwrap();
+        // Reopening moves a workspace to the front
+        db.touch_workspace_at(&paths[0], at(3)).unwrap();
+
+        let recent = db.get_recent_workspaces().unwrap();
+        let order: Vec<&str> = recent.iter().map(|w| w.path.as_str()).collect();
+        assert_eq!(order, vec![&paths[0], &paths[2], &paths[1]]);
+        assert_eq!(recent[0].last_opened, at(3));
+
+        // Pinned workspaces come first
+        assert!(db.pin_workspace(&paths[1], true).unwrap());
+        let recent = db.get_recent_workspaces().unwrap();
+        assert_eq!(recent[0].path, paths[1]);
+        assert!(recent[0].pinned);
+
+        assert!(db.remove_recent_workspace(&paths[2]).unwrap());
+        assert!(!db.remove_recent_workspace(&paths[2]).unwrap());
+        assert_eq!(db.get_recent_workspaces().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_recent_workspaces_capped_and_flag_missing() {
+        let db = HistoryDb::in_memory().unwrap();
+        let start = Utc::now() - chrono::Duration::hours(1);
+        db.touch_workspace_at("/gone/pinned", start).unwrap();
+        db.pin_workspace("/gone/pinned", true).unwrap();
+        for i in 0..=MAX_RECENT_WORKSPACES {
+            db.touch_workspace_at(
+                &format!("/gone/{}", i),
+                start + chrono::Duration::minutes(i + 1),
+            )
+            .unwrap();
+        }
+
+        let recent = db.get_recent_workspaces().unwrap();
+        assert_eq!(recent.len() as i64, MAX_RECENT_WORKSPACES + 1);
+        // The oldest unpinned workspace was dropped, the pinned one kept
+        assert_eq!(recent[0].path, "/gone/pinned");
+        assert!(!recent.iter().any(|w| w.path == "/gone/0"));
+        // Directories that no longer exist stay in the list, flagged
+        assert!(recent.iter().all(|w| w.missing));
+
+        let dir = tempfile::tempdir().unwrap();
+        db.touch_workspace(&dir.path().to_string_lossy()).unwrap();
+        let recent = db.get_recent_workspaces().unwrap();
+        let existing = recent
+            .iter()
+            .find(|w| Path::new(&w.path) == dir.path())
+            .unwrap();
+        assert!(!existing.missing);
+    }
+
+    #[test]
+    fn test_prune_max_entries_per_workspace() {
+        let db = HistoryDb::in_memory().unwrap();
+        seed(&db);
+        set_retention(
+            &db,
+            RetentionSettings {
+                max_entries_per_workspace: Some(2),
+                max_age_days: None,
+                max_db_size_bytes: None,
+            },
+        );
+
+        assert_eq!(db.prune_now().unwrap().deleted, 2);
+        assert_eq!(
+            urls(&db.get_entries("/ws", 100).unwrap()),
+            vec![
+                "DELETE https://api.example.com/users/1",
+                "POST https://api.example.com/orders",
+            ]
+        );
+        assert_eq!(db.get_entries("/other", 100).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_prune_max_age() {
+        let db = HistoryDb::in_memory().unwrap();
+        let old = (Utc::now() - chrono::Duration::days(10)).to_rfc3339();
+        let recent = (Utc::now() - chrono::Duration::days(2)).to_rfc3339();
+        insert_at(&db, &old, "GET", 200, None, None);
+        let kept = insert_at(&db, &recent, "GET", 200, None, None);
+        set_retention(
+            &db,
+            RetentionSettings {
+                max_entries_per_workspace: None,
+                max_age_days: Some(7),
+                max_db_size_bytes: None,
+            },
+        );
+
+        assert_eq!(db.prune_now().unwrap().deleted, 1);
+        let stats = db.stats().unwrap();
+        assert_eq!(stats.entry_count, 1);
+        assert_eq!(
+            stats.oldest_entry.map(|t| t.to_rfc3339()),
+            Some(recent.clone())
+        );
+        assert!(db.get_entry(kept).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_prune_max_db_size() {
+        let db = HistoryDb::in_memory().unwrap();
+        let body = "x".repeat(10_000);
+        let mut ids = Vec::new();
+        for i in 0..60 {
+            let id = insert_at(
+                &db,
+                &format!("2024-01-01T00:{:02}:00+00:00", i),
+                "GET",
+                200,
+                None,
+                None,
+            );
+            db.conn
+                .lock()
+                .unwrap()
+                .execute(
+                    "UPDATE history SET response_body = ?1 WHERE id = ?2",
+                    rusqlite::params![body, id],
+                )
+                .unwrap();
+            ids.push(id);
+        }
+        let before = db.stats().unwrap();
+        assert!(before.db_size_bytes > 600_000);
+
+        set_retention(
+            &db,
+            RetentionSettings {
+                max_entries_per_workspace: None,
+                max_age_days: None,
+                max_db_size_bytes: Some(200_000),
+            },
+        );
+        let report = db.prune_now().unwrap();
+        assert!(report.deleted > 0);
+        assert!(report.vacuumed);
+
+        let after = db.stats().unwrap();
+        assert!(after.db_size_bytes <= 200_000, "{}", after.db_size_bytes);
+        assert_eq!(after.entry_count, 60 - report.deleted as i64);
+        // The newest entries survive
+        assert!(db.get_entry(*ids.last().unwrap()).unwrap().is_some());
+        assert!(db.get_entry(ids[0]).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_prunes_after_interval_of_inserts() {
+        let db = HistoryDb::in_memory().unwrap();
+        set_retention(
+            &db,
+            RetentionSettings {
+                max_entries_per_workspace: Some(10),
+                max_age_days: None,
+                max_db_size_bytes: None,
+            },
+        );
+        for _ in 0..PRUNE_INTERVAL - 1 {
+            insert_at(&db, "2024-01-01T00:00:00+00:00", "GET", 200, None, None);
+        }
+        assert_eq!(db.stats().unwrap().entry_count, PRUNE_INTERVAL as i64 - 1);
+        insert_at(&db, "2024-01-01T00:00:00+00:00", "GET", 200, None, None);
+        // The prune ran before this entry's timestamp was rewritten
+        assert_eq!(db.stats().unwrap().entry_count, 10);
+    }
+
+    fn entry_with_body(response_body: String) -> NewHistoryEntry {
+        NewHistoryEntry {
+            workspace: "/ws".to_string(),
+            file_path: None,
+            request_name: None,
+            method: "GET".to_string(),
+            url: "https://api.example.com/report".to_string(),
+            request_headers: "{}".to_string(),
+            request_body: Some("query".to_string()),
+            status: 200,
+            status_text: "OK".to_string(),
+            response_headers: "{}".to_string(),
+            response_size: response_body.len() as i64,
+            response_body,
+            duration_ms: 1,
+            replayed_from: None,
+            environment: None,
+            variables_snapshot: None,
+            response_body_base64: false,
+        }
+    }
+
+    fn blob_count(dir: &Path) -> usize {
+        std::fs::read_dir(dir).map(|d| d.count()).unwrap_or(0)
+    }
+
+    #[test]
+    fn test_large_bodies_offloaded_to_blobs() {
+        let dir = tempfile::tempdir().unwrap();
+        let db =
+            HistoryDb::from_connection(Connection::open_in_memory().unwrap(), dir.path().into())
+                .unwrap();
+
+        let at_limit = "a".repeat(INLINE_BODY_LIMIT);
+        let over_limit = "b".repeat(INLINE_BODY_LIMIT + 1);
+        let inline = db.add_entry(entry_with_body(at_limit.clone())).unwrap();
+        let offloaded = db.add_entry(entry_with_body(over_limit.clone())).unwrap();
+        // Identical bodies share one blob
+        let duplicate = db.add_entry(entry_with_body(over_limit.clone())).unwrap();
+        assert_eq!(blob_count(dir.path()), 1);
+
+        let stored: Option<String> = db
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT response_body FROM history WHERE id = ?1",
+                [offloaded],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(stored.as_deref(), Some(""));
+
+        assert_eq!(
+            db.get_entry(inline).unwrap().unwrap().response_body,
+            at_limit
+        );
+        assert_eq!(
+            db.get_entry(offloaded).unwrap().unwrap().response_body,
+            over_limit
+        );
+        let bodies = db.get_entry_body(duplicate).unwrap().unwrap();
+        assert_eq!(bodies.response_body, over_limit);
+        assert_eq!(bodies.request_body.as_deref(), Some("query"));
+        assert!(db.get_entry_body(999).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_list_queries_leave_out_bodies() {
+        let db = HistoryDb::in_memory().unwrap();
+        db.add_entry(entry_with_body("small".to_string())).unwrap();
+
+        let page = db
+            .get_page("/ws", &HistoryFilter::default(), None, 10)
+            .unwrap();
+        let lists = [
+            db.get_entries("/ws", 10).unwrap(),
+            db.get_entries_filtered("/ws", &HistoryFilter::default(), 10)
+                .unwrap(),
+            db.search("/ws", "report", 10, true).unwrap(),
+            page.entries,
+        ];
+        for entries in lists {
+            assert_eq!(entries.len(), 1);
+            assert_eq!(entries[0].response_body, "");
+            assert_eq!(entries[0].request_body, None);
+        }
+        let id = db.get_entries("/ws", 1).unwrap()[0].id;
+        assert_eq!(
+            db.get_entry_body(id).unwrap().unwrap().response_body,
+            "small"
+        );
+    }
+
+    #[test]
+    fn test_blob_garbage_collection() {
+        let dir = tempfile::tempdir().unwrap();
+        let db =
+            HistoryDb::from_connection(Connection::open_in_memory().unwrap(), dir.path().into())
+                .unwrap();
+
+        let shared = "s".repeat(INLINE_BODY_LIMIT + 1);
+        let first = db.add_entry(entry_with_body(shared.clone())).unwrap();
+        let second = db.add_entry(entry_with_body(shared)).unwrap();
+        db.add_entry(entry_with_body("u".repeat(INLINE_BODY_LIMIT + 1)))
+            .unwrap();
+        std::fs::write(dir.path().join("stray"), "left over").unwrap();
+        assert_eq!(blob_count(dir.path()), 3);
+
+        // Still referenced by the second entry
+        db.delete_entry(first).unwrap();
+        assert_eq!(blob_count(dir.path()), 2);
+        assert_eq!(
+            db.get_entry(second).unwrap().unwrap().response_body.len(),
+            INLINE_BODY_LIMIT + 1
+        );
+
+        db.delete_entry(second).unwrap();
+        assert_eq!(blob_count(dir.path()), 1);
+
+        db.set_retention(&RetentionSettings {
+            max_entries_per_workspace: Some(0),
+            max_age_days: None,
+            max_db_size_bytes: None,
+        })
+        .unwrap();
+        assert_eq!(db.prune_now().unwrap().deleted, 1);
+        assert_eq!(blob_count(dir.path()), 0);
+    }
+
+    #[test]
+    fn test_existing_inline_bodies_moved_to_blobs() {
+        let dir = tempfile::tempdir().unwrap();
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            "CREATE TABLE history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                timestamp TEXT NOT NULL,
+                workspace TEXT NOT NULL,
+                file_path TEXT,
+                request_name TEXT,
+                method TEXT NOT NULL,
+                url TEXT NOT NULL,
+                request_headers TEXT NOT NULL,
+                request_body TEXT,
+                status INTEGER NOT NULL,
+                status_text TEXT NOT NULL,
+                response_headers TEXT NOT NULL,
+                response_body TEXT NOT NULL,
+                duration_ms INTEGER NOT NULL,
+                response_size INTEGER NOT NULL
+            )",
+            [],
+        )
+        .unwrap();
+        let large = "ü".repeat(INLINE_BODY_LIMIT / 2 + 1);
+        for body in ["small", large.as_str()] {
+            conn.execute(
+                "INSERT INTO history (timestamp, workspace, method, url, request_headers,
+                    status, status_text, response_headers, response_body,
+                    duration_ms, response_size)
+                 VALUES ('2024-01-01T00:00:00+00:00', '/ws', 'GET', 'https://a.test', '{}',
+                    200, 'OK', '{}', ?1, 1, 1)",
+                [body],
+            )
+            .unwrap();
+        }
+
+        let db = HistoryDb::from_connection(conn, dir.path().into()).unwrap();
+        assert_eq!(blob_count(dir.path()), 1);
+        let blobs: Vec<Option<String>> = db
+            .conn
+            .lock()
+            .unwrap()
+            .prepare("SELECT response_body_blob FROM history ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<SqliteResult<_>>()
+            .unwrap();
+        assert!(blobs[0].is_none() && blobs[1].is_some());
+        assert_eq!(db.get_entry(1).unwrap().unwrap().response_body, "small");
+        assert_eq!(db.get_entry(2).unwrap().unwrap().response_body, large);
+    }
+
+    #[test]
+    fn test_normalize_endpoint() {
+        let cases = [
+            (
+                "https://api.example.com/users/123",
+                "https://api.example.com/users/:id",
+            ),
+            (
+                "https://api.example.com/users/123/orders/9?expand=items#top",
+                "https://api.example.com/users/:id/orders/:id",
+            ),
+            (
+                "http://localhost:8080/items/550e8400-e29b-41d4-a716-446655440000",
+                "http://localhost:8080/items/:id",
+            ),
+            (
+                "https://api.example.com/docs/507f1f77bcf86cd799439011/",
+                "https://api.example.com/docs/:id/",
+            ),
+            // Words, versions and short hex-looking words stay
+            (
+                "https://api.example.com/v2/users/me",
+                "https://api.example.com/v2/users/me",
+            ),
+            (
+                "https://api.example.com/feed/cafe",
+                "https://api.example.com/feed/cafe",
+            ),
+            (
+                "https://api.example.com/abcdefabcdefabcdef",
+                "https://api.example.com/abcdefabcdefabcdef",
+            ),
+            // Ports and hosts are not path segments
+            ("http://127.0.0.1:3000", "http://127.0.0.1:3000"),
+            ("/relative/42", "/relative/:id"),
+        ];
+        for (url, expected) in cases {
+            assert_eq!(normalize_endpoint(url), expected, "{}", url);
+        }
+    }
+
+    #[test]
+    fn test_endpoint_stats() {
+        let db = HistoryDb::in_memory().unwrap();
+        let recent = (Utc::now() - chrono::Duration::hours(1)).to_rfc3339();
+        let old = (Utc::now() - chrono::Duration::days(10)).to_rfc3339();
+        let add = |timestamp: &str, method: &str, url: &str, status: i32, duration: i64| {
+            let id = insert_at(&db, timestamp, method, status, None, None);
+            db.conn
+                .lock()
+                .unwrap()
+                .execute(
+                    "UPDATE history SET url = ?1, duration_ms = ?2, response_size = 100
+                     WHERE id = ?3",
+                    rusqlite::params![url, duration, id],
+                )
+                .unwrap();
+        };
+        // Durations 10..=200 over 20 order lookups, one in ten failing
+        for i in 1..=20 {
+            let status = if i % 10 == 0 { 500 } else { 200 };
+            add(
+                &recent,
+                "GET",
+                &format!("https://a.test/orders/{}?v={}", i, i),
+                status,
+                i * 10,
+            );
+        }
+        add(&recent, "POST", "https://a.test/orders", 201, 300);
+        add(&old, "GET", "https://a.test/orders/1", 200, 5000);
+
+        let stats = db.endpoint_stats("/ws", StatsPeriod::Week, true).unwrap();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(
+            stats[0],
+            EndpointStats {
+                method: "GET".to_string(),
+                endpoint: "https://a.test/orders/:id".to_string(),
+                count: 20,
+                error_rate: 0.1,
+                avg_duration_ms: 105.0,
+                p50_duration_ms: 100,
+                p95_duration_ms: 190,
+                total_bytes: 2000,
+            }
+        );
+        assert_eq!(stats[1].endpoint, "https://a.test/orders");
+
+        // The old entry counts over all of history
+        let all = db.endpoint_stats("/ws", StatsPeriod::All, true).unwrap();
+        assert_eq!((all[0].count, all[0].p95_duration_ms), (21, 200));
+
+        // Without normalization every URL is its own endpoint
+        let raw = db.endpoint_stats("/ws", StatsPeriod::Week, false).unwrap();
+        assert_eq!(raw.len(), 21);
+        assert!(db
+            .endpoint_stats("/other", StatsPeriod::All, true)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_inserts_and_reads() {
+        let db = HistoryDb::in_memory().unwrap();
+        let mut tasks = Vec::new();
+        for i in 0..50 {
+            let db = db.clone();
+            tasks.push(tokio::spawn(async move {
+                let id = db
+                    .run(move |db| {
+                        db.add_entry(NewHistoryEntry {
+                            workspace: "/ws".to_string(),
+                            file_path: None,
+                            request_name: Some(format!("request {}", i)),
+                            method: "GET".to_string(),
+                            url: format!("https://a.test/{}", i),
+                            request_headers: "{}".to_string(),
+                            request_body: None,
+                            status: 200,
+                            status_text: "OK".to_string(),
+                            response_headers: "{}".to_string(),
+                            response_body: String::new(),
+                            duration_ms: 1,
+                            response_size: 0,
+                            replayed_from: None,
+                            environment: None,
+                            variables_snapshot: None,
+                            response_body_base64: false,
+                        })
+                    })
+                    .await
+                    .unwrap();
+                let entry = db.run(move |db| db.get_entry(id)).await.unwrap();
+                assert_eq!(entry.unwrap().url, format!("https://a.test/{}", i));
+                db.run(|db| db.get_entries("/ws", 100)).await.unwrap();
+            }));
+        }
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        let count = db
+            .run(|db| db.count_entries("/ws", &HistoryFilter::default()))
+            .await
+            .unwrap();
+        assert_eq!(count, 50);
+    }
+
+    #[test]
+    fn test_tags() {
+        let db = HistoryDb::in_memory().unwrap();
+        let ids = seed_days(&db);
+
+        assert!(db.add_tag(ids[0], "bug-1234").unwrap());
+        assert!(!db.add_tag(ids[0], "bug-1234").unwrap());
+        db.add_tag(ids[0], "slow").unwrap();
+        db.add_tag(ids[2], "bug-1234").unwrap();
+        db.add_tag(ids[3], "slow").unwrap();
+        db.add_tag(ids[4], "bug-1234").unwrap();
+        assert!(db.add_tag(999, "orphan").is_err());
+
+        assert_eq!(db.entry_tags(ids[0]).unwrap(), vec!["bug-1234", "slow"]);
+        assert_eq!(
+            db.list_tags("/ws").unwrap(),
+            vec![
+                TagCount {
+                    tag: "bug-1234".to_string(),
+                    count: 3
+                },
+                TagCount {
+                    tag: "slow".to_string(),
+                    count: 2
+                },
+            ]
+        );
+        assert!(db.list_tags("/other").unwrap().is_empty());
+
+        assert!(db.remove_tag(ids[4], "bug-1234").unwrap());
+        assert!(!db.remove_tag(ids[4], "bug-1234").unwrap());
+        assert!(db.entry_tags(ids[4]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_filter_by_tags() {
+        let db = HistoryDb::in_memory().unwrap();
+        let ids = seed_days(&db);
+        db.add_tag(ids[0], "bug-1234").unwrap();
+        db.add_tag(ids[0], "slow").unwrap();
+        db.add_tag(ids[2], "bug-1234").unwrap();
+        db.add_tag(ids[3], "slow").unwrap();
+
+        let tagged = |tags: &[&str]| {
+            filtered(
+                &db,
+                HistoryFilter {
+                    tags: tags.iter().map(|t| t.to_string()).collect(),
+                    ..Default::default()
+                },
+            )
+        };
+        assert_eq!(tagged(&["bug-1234"]), vec![ids[2], ids[0]]);
+        // All tags must match
+        assert_eq!(tagged(&["bug-1234", "slow"]), vec![ids[0]]);
+        assert_eq!(tagged(&["slow", "slow"]), vec![ids[3], ids[0]]);
+        assert!(tagged(&["bug-1234", "missing"]).is_empty());
+
+        // Tags combine with the other filters
+        let filter = HistoryFilter {
+            tags: vec!["slow".into()],
+            status_class: Some(5),
+            ..Default::default()
+        };
+        assert_eq!(filtered(&db, filter.clone()), vec![ids[3], ids[0]]);
+        assert_eq!(db.count_entries("/ws", &filter).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_deleting_entries_cascades_tags() {
+        let db = HistoryDb::in_memory().unwrap();
+        let ids = seed_days(&db);
+        db.add_tag(ids[0], "bug-1234").unwrap();
+        db.add_tag(ids[1], "bug-1234").unwrap();
+
+        db.delete_entry(ids[0]).unwrap();
+        let count = |db: &HistoryDb| -> i64 {
+            db.conn
+                .lock()
+                .unwrap()
+                .query_row("SELECT COUNT(*) FROM tags", [], |row| row.get(0))
+                .unwrap()
+        };
+        assert_eq!(count(&db), 1);
+
+        db.clear_workspace("/ws").unwrap();
+        assert_eq!(count(&db), 0);
+        assert!(db.list_tags("/ws").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_environment_and_variables_snapshot() {
+        let db = HistoryDb::in_memory().unwrap();
+        let variables = HashMap::from([
+            (
+                "baseUrl".to_string(),
+                "https://staging.example.com".to_string(),
+            ),
+            ("apiVersion".to_string(), "v2".to_string()),
+            ("token".to_string(), "s3cr3t".to_string()),
+        ]);
+        let snapshot = variables_snapshot(&variables, &["token".to_string()]);
+        assert!(!snapshot.contains("s3cr3t"));
+
+        let mut entry = entry_with_body("{}".to_string());
+        entry.environment = Some("staging".to_string());
+        entry.variables_snapshot = Some(snapshot);
+        let id = db.add_entry(entry).unwrap();
+
+        let stored = db.get_entry(id).unwrap().unwrap();
+        assert_eq!(stored.environment.as_deref(), Some("staging"));
+        let restored: HashMap<String, String> =
+            serde_json::from_str(stored.variables_snapshot.as_deref().unwrap()).unwrap();
+        assert_eq!(restored.len(), 3);
+        assert_eq!(restored["apiVersion"], "v2");
+        assert_eq!(restored["baseUrl"], "https://staging.example.com");
+        assert_eq!(restored["token"], MASKED_VALUE);
+
+        // Lists carry them too, and serialization keeps them
+        let listed = &db.get_entries("/ws", 1).unwrap()[0];
+        assert_eq!(listed.environment.as_deref(), Some("staging"));
+        let json = serde_json::to_value(&stored).unwrap();
+        assert_eq!(json["environment"], "staging");
+        let deserialized: HistoryEntry = serde_json::from_value(json).unwrap();
+        assert_eq!(deserialized.variables_snapshot, stored.variables_snapshot);
+
+        // Entries sent without an environment
+        let old = insert_at(&db, "2024-01-01T00:00:00+00:00", "GET", 200, None, None);
+        let old = db.get_entry(old).unwrap().unwrap();
+        assert_eq!((old.environment, old.variables_snapshot), (None, None));
+    }
+
+    #[test]
+    fn test_two_connections_write_concurrently() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.db");
+        let first = HistoryDb::open(&path, dir.path().join("blobs")).unwrap();
+        let second = HistoryDb::open(&path, dir.path().join("blobs")).unwrap();
+
+        let journal_mode: String = first
+            .conn
+            .lock()
+            .unwrap()
+            .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(journal_mode, "wal");
+
+        let writers: Vec<_> = [first.clone(), second]
+            .into_iter()
+            .enumerate()
+            .map(|(n, db)| {
+                std::thread::spawn(move || {
+                    for i in 0..100 {
+                        let mut entry = entry_with_body(format!("{}-{}", n, i));
+                        entry.request_name = Some(format!("writer {}", n));
+                        db.add_entry(entry).unwrap();
+                        db.get_entries("/ws", 10).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        assert_eq!(
+            first
+                .count_entries("/ws", &HistoryFilter::default())
+                .unwrap(),
+            200
+        );
+    }
+
+    #[test]
+    fn test_reopens_with_leftover_wal() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.db");
+        let db = HistoryDb::open(&path, dir.path().join("blobs")).unwrap();
+        let id = db.add_entry(entry_with_body("kept".to_string())).unwrap();
+        // Simulate a crash: the WAL and shared-memory files stay behind
+        std::mem::forget(db);
+        assert!(dir.path().join("history.db-wal").exists());
+
+        let reopened = HistoryDb::open(&path, dir.path().join("blobs")).unwrap();
+        assert_eq!(
+            reopened.get_entry(id).unwrap().unwrap().response_body,
+            "kept"
+        );
+    }
+
+    #[test]
+    fn test_corrupt_database_falls_back_and_repairs() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.db");
+        std::fs::write(&path, "this is not a database, just some bytes".repeat(200)).unwrap();
+
+        let db = HistoryDb::open_or_fallback(path.clone(), dir.path().join("blobs"));
+        assert!(db.warning().unwrap().contains("history.db"));
+        // The in-memory fallback still records history
+        db.add_entry(entry_with_body("in memory".to_string()))
+            .unwrap();
+        assert_eq!(db.get_entries("/ws", 10).unwrap().len(), 1);
+
+        let backup = db.repair().unwrap().unwrap();
+        assert!(db.warning().is_none());
+        assert!(std::fs::read_to_string(&backup)
+            .unwrap()
+            .starts_with("this is not a database"));
+
+        let id = db
+            .add_entry(entry_with_body("on disk".to_string()))
+            .unwrap();
+        drop(db);
+        let reopened = HistoryDb::open(&path, dir.path().join("blobs")).unwrap();
+        assert_eq!(
+            reopened.get_entry(id).unwrap().unwrap().response_body,
+            "on disk"
+        );
+    }
+
+    #[test]
+    fn test_integrity_check_rejects_damaged_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.db");
+        {
+            let db = HistoryDb::open(&path, dir.path().join("blobs")).unwrap();
+            for i in 0..200 {
+                db.add_entry(entry_with_body(format!("body {}", i).repeat(50)))
+                    .unwrap();
+            }
+        }
+        // Overwrite a page in the middle of the file, keeping the header valid
+        let mut bytes = std::fs::read(&path).unwrap();
+        let middle = bytes.len() / 2;
+        bytes[middle..middle + 4096].fill(0xAB);
+        std::fs::write(&path, bytes).unwrap();
+
+        let db = HistoryDb::open_or_fallback(path, dir.path().join("blobs"));
+        assert!(db.warning().is_some());
+    }
+
+    fn body_rows(db: &HistoryDb) -> Vec<(String, i64)> {
+        db.conn
+            .lock()
+            .unwrap()
+            .prepare("SELECT content, refcount FROM bodies ORDER BY content")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<SqliteResult<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_identical_bodies_are_stored_once() {
+        let db = HistoryDb::in_memory().unwrap();
+        let polled = r#"{"status":"up"}"#.repeat(1000);
+        let ids: Vec<i64> = (0..5)
+            .map(|_| db.add_entry(entry_with_body(polled.clone())).unwrap())
+            .collect();
+        let other = db.add_entry(entry_with_body("other".to_string())).unwrap();
+
+        assert_eq!(
+            body_rows(&db),
+            vec![("other".to_string(), 1), (polled.clone(), 5)]
+        );
+        for id in &ids {
+            assert_eq!(db.get_entry(*id).unwrap().unwrap().response_body, polled);
+        }
+        assert_eq!(
+            db.get_entry_body(other).unwrap().unwrap().response_body,
+            "other"
+        );
+
+        db.delete_entry(ids[0]).unwrap();
+        assert_eq!(body_rows(&db)[1].1, 4);
+        db.delete_entry(other).unwrap();
+        db.clear_workspace("/ws").unwrap();
+        assert!(body_rows(&db).is_empty());
+    }
+
+    #[test]
+    fn test_latest_per_request() {
+        let db = HistoryDb::in_memory().unwrap();
+        let a = Some("/ws/a.http");
+        let b = Some("/ws/b.http");
+        insert_at(
+            &db,
+            "2024-01-01T10:00:00+00:00",
+            "GET",
+            200,
+            a,
+            Some("list"),
+        );
+        let create = insert_at(
+            &db,
+            "2024-01-01T10:01:00+00:00",
+            "POST",
+            500,
+            a,
+            Some("create"),
+        );
+        let list = insert_at(
+            &db,
+            "2024-01-01T10:02:00+00:00",
+            "GET",
+            404,
+            a,
+            Some("list"),
+        );
+        insert_at(
+            &db,
+            "2024-01-01T09:00:00+00:00",
+            "POST",
+            201,
+            a,
+            Some("create"),
+        );
+        let other = insert_at(
+            &db,
+            "2024-01-01T10:03:00+00:00",
+            "GET",
+            200,
+            b,
+            Some("list"),
+        );
+        insert_at(&db, "2024-01-01T11:00:00+00:00", "GET", 200, None, None);
+
+        let latest = db.latest_per_request("/ws", a).unwrap();
+        assert_eq!(
+            latest
+                .iter()
+                .map(|r| (r.id, r.request_name.as_deref(), r.status))
+                .collect::<Vec<_>>(),
+            vec![(create, Some("create"), 500), (list, Some("list"), 404)]
+        );
+        assert_eq!(
+            latest[1].timestamp.to_rfc3339(),
+            "2024-01-01T10:02:00+00:00"
+        );
+
+        let all: Vec<i64> = db
+            .latest_per_request("/ws", None)
+            .unwrap()
+            .iter()
+            .map(|r| r.id)
+            .collect();
+        assert_eq!(all, vec![create, list, other]);
+        assert!(db
+            .latest_per_request("/ws", Some("/ws/never.http"))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_delete_before_is_exclusive_and_keeps_pinned() {
+        let db = HistoryDb::in_memory().unwrap();
+        let old = insert_at(&db, "2024-01-01T09:59:59+00:00", "GET", 200, None, None);
+        let pinned = insert_at(&db, "2024-01-01T09:00:00+00:00", "GET", 200, None, None);
+        let boundary = insert_at(&db, "2024-01-01T10:00:00+00:00", "GET", 200, None, None);
+        let newer = insert_at(&db, "2024-01-02T00:00:00+00:00", "GET", 200, None, None);
+        db.add_tag(pinned, PINNED_TAG).unwrap();
+        db.add_tag(old, "smoke").unwrap();
+
+        let boundary_time = at("2024-01-01T10:00:00+00:00").unwrap();
+        let deleted = db.delete_before("/ws", boundary_time).unwrap();
+        assert_eq!(deleted, 1);
+        assert!(db.get_entry(old).unwrap().is_none());
+        for id in [pinned, boundary, newer] {
+            assert!(db.get_entry(id).unwrap().is_some());
+        }
+        // The deleted entry's tags went with it
+        assert!(db
+            .list_tags("/ws")
+            .unwrap()
+            .iter()
+            .all(|t| t.tag != "smoke"));
+
+        assert!(db
+            .delete_before("/ws", Utc::now() + chrono::Duration::hours(1))
+            .is_err());
+    }
+
+    fn body_search(db: &HistoryDb, query: &str) -> Vec<(i64, MatchedBody, String)> {
+        db.search_bodies("/ws", query, 10)
+            .unwrap()
+            .into_iter()
+            .map(|hit| (hit.entry.id, hit.matched, hit.snippet))
+            .collect()
+    }
+
+    #[test]
+    fn test_search_bodies_tokenizes_json() {
+        let db = HistoryDb::in_memory().unwrap();
+        let order = db
+            .add_entry(entry_with_body(
+                r#"{"order_id":98231,"customer-name":"Ada Lovelace"}"#.to_string(),
+            ))
+            .unwrap();
+        let mut request = entry_with_body("{}".to_string());
+        request.request_body = Some(r#"{"sku":"KV-42","qty":3}"#.to_string());
+        let posted = db.add_entry(request).unwrap();
+
+        assert_eq!(
+            body_search(&db, "order_id: 98231"),
+            vec![(
+                order,
+                MatchedBody::Response,
+                r#"{"<mark>order_id</mark>":<mark>98231</mark>,"customer-name":"Ada Lovelace"}"#
+                    .to_string()
+            )]
+        );
+        // `_` keeps snake_case keys whole; `-` splits, but matches as a phrase
+        assert!(body_search(&db, "order").is_empty());
+        assert_eq!(body_search(&db, "customer-name")[0].0, order);
+        assert!(body_search(&db, "name-customer").is_empty());
+        // Matching is case-insensitive
+        assert_eq!(body_search(&db, "LOVELACE")[0].0, order);
+
+        let hits = body_search(&db, "KV-42");
+        assert_eq!(hits.len(), 1);
+        assert_eq!((hits[0].0, hits[0].1), (posted, MatchedBody::Request));
+
+        // FTS5 syntax is taken literally
+        assert!(body_search(&db, r#"order_id" OR "x"#).is_empty());
+        assert!(body_search(&db, "NEAR( * :").is_empty());
+        assert!(db.search_bodies("/other", "98231", 10).unwrap().is_empty());
+
+        db.delete_entry(order).unwrap();
+        assert!(body_search(&db, "98231").is_empty());
+    }
+
+    #[test]
+    fn test_search_index_backfill() {
+        let db = HistoryDb::in_memory().unwrap();
+        let ids: Vec<i64> = (0..5)
+            .map(|i| {
+                let mut entry = entry_with_body(format!("{{\"page\":{}}}", i));
+                entry.request_body = Some(format!("cursor={}", i));
+                db.add_entry(entry).unwrap()
+            })
+            .collect();
+        // As if the entries predate the index
+        db.conn
+            .lock()
+            .unwrap()
+            .execute_batch(
+                "DELETE FROM response_fts;
+                DELETE FROM request_fts;
+                INSERT INTO search_backfill SELECT 'bodies', 1, MAX(id) FROM bodies;
+                INSERT INTO search_backfill SELECT 'history', 1, MAX(id) FROM history;",
+            )
+            .unwrap();
+        assert!(body_search(&db, "page").is_empty());
+
+        // Deleting an entry that isn't indexed yet is fine
+        db.delete_entry(ids[1]).unwrap();
+        let new = db
+            .add_entry(entry_with_body("{\"page\":9}".to_string()))
+            .unwrap();
+        assert_eq!(body_search(&db, "page").len(), 1);
+
+        assert!(!db.backfill_search_index(2).unwrap());
+        assert!(!db.backfill_search_index(2).unwrap());
+        assert!(db.backfill_search_index(2).unwrap());
+        assert!(db.backfill_search_index(2).unwrap());
+
+        let found: Vec<i64> = body_search(&db, "page").iter().map(|hit| hit.0).collect();
+        assert_eq!(found, vec![new, ids[4], ids[3], ids[2], ids[0]]);
+        assert_eq!(body_search(&db, "cursor 3")[0].1, MatchedBody::Request);
+    }
+
+    fn recorded(db: &HistoryDb) -> Vec<(i32, String)> {
+        db.get_entries("/ws", 10)
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.status, entry.status_text))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_send_recorded_records_success_and_failure() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            // Read the whole request, which ends with its `{}` body
+            let mut received = Vec::new();
+            let mut buf = [0; 1024];
+            while !received.ends_with(b"{}") {
+                let n = socket.read(&mut buf).await.unwrap();
+                if n == 0 {
+                    break;
+                }
+                received.extend_from_slice(&buf[..n]);
+            }
+            socket
+                .write_all(
+                    b"HTTP/1.1 201 Created\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok",
+                )
+                .await
+                .unwrap();
+        });
+
+        let db = HistoryDb::in_memory().unwrap();
+        let context = HistoryContext {
+            workspace: "/ws".to_string(),
+            file_path: Some("/ws/api.http".to_string()),
+            request_name: Some("create".to_string()),
+            environment: Some("dev".to_string()),
+            variables: None,
+            env_glob: None,
+            replayed_from: None,
+        };
+        let request = |url: String| HttpRequest {
+            method: "POST".to_string(),
+            url,
+            headers: HashMap::new(),
+            body: Some("{}".to_string()),
+        };
+
+        let response = send_recorded(
+            &db,
+            request(format!("http://{}/items", addr)),
+            &context,
+            None,
+            &[],
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status, 201);
+        let entry = db.get_entry(response.history_id.unwrap()).unwrap().unwrap();
+        assert_eq!(entry.response_body, "ok");
+        assert_eq!(entry.request_name.as_deref(), Some("create"));
+        assert_eq!(entry.environment.as_deref(), Some("dev"));
+        assert_eq!(recorded(&db).len(), 1);
+
+        // Nothing listens on the port any more
+        let error = send_recorded(
+            &db,
+            request(format!("http://{}/items", addr)),
+            &context,
+            None,
+            &[],
+        )
+        .await
+        .unwrap_err();
+        let entries = recorded(&db);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0], (0, error));
+    }
+
+    #[test]
+    fn test_export_and_import_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = HistoryDb::from_connection(
+            Connection::open_in_memory().unwrap(),
+            dir.path().join("source-blobs"),
+        )
+        .unwrap();
+        let large = "l".repeat(INLINE_BODY_LIMIT + 1);
+        let original = insert_at(&source, "2024-01-01T10:00:00+00:00", "GET", 200, None, None);
+        let replayed = source.add_entry(entry_with_body(large.clone())).unwrap();
+        source
+            .conn
+            .lock()
+            .unwrap()
+            .execute(
+                "UPDATE history SET replayed_from = ?1 WHERE id = ?2",
+                [original, replayed],
+            )
+            .unwrap();
+        source.add_tag(replayed, "smoke").unwrap();
+
+        let exported = dir.path().join("export.db");
+        source.export_db(&exported).unwrap();
+
+        // A different machine: no blob files, and some history of its own
+        let target = HistoryDb::from_connection(
+            Connection::open_in_memory().unwrap(),
+            dir.path().join("target-blobs"),
+        )
+        .unwrap();
+        let local = insert_at(&target, "2023-06-01T00:00:00+00:00", "GET", 200, None, None);
+        insert_at(&target, "2024-01-01T10:00:00+00:00", "GET", 200, None, None);
+
+        let report = target
+            .import_db(&exported, HistoryImportMode::Merge)
+            .unwrap();
+        assert_eq!(
+            report,
+            HistoryImportReport {
+                imported: 1,
+                skipped: 1
+            }
+        );
+        let entries = target.get_entries("/ws", 10).unwrap();
+        assert_eq!(entries.len(), 3);
+        let imported = &entries[0];
+        assert_eq!(
+            target
+                .get_entry(imported.id)
+                .unwrap()
+                .unwrap()
+                .response_body,
+            large
+        );
+        // Linked to the entry the duplicate matched
+        let matched = entries[1].id;
+        assert_eq!(imported.replayed_from, Some(matched));
+        assert_eq!(target.entry_tags(imported.id).unwrap(), vec!["smoke"]);
+
+        // Importing again adds nothing
+        let again = target
+            .import_db(&exported, HistoryImportMode::Merge)
+            .unwrap();
+        assert_eq!((again.imported, again.skipped), (0, 2));
+
+        let replaced = target
+            .import_db(&exported, HistoryImportMode::Replace)
+            .unwrap();
+        assert_eq!((replaced.imported, replaced.skipped), (2, 0));
+        assert!(target.get_entry(local).unwrap().is_none());
+        assert_eq!(target.get_entries("/ws", 10).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_import_migrates_old_schema() {
+        let dir = tempfile::tempdir().unwrap();
+        let old = dir.path().join("old.db");
+        Connection::open(&old)
+            .unwrap()
+            .execute_batch(
+                "CREATE TABLE history (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    timestamp TEXT NOT NULL,
+                    workspace TEXT NOT NULL,
+                    file_path TEXT,
+                    request_name TEXT,
+                    method TEXT NOT NULL,
+                    url TEXT NOT NULL,
+                    request_headers TEXT NOT NULL,
+                    request_body TEXT,
+                    status INTEGER NOT NULL,
+                    status_text TEXT NOT NULL,
+                    response_headers TEXT NOT NULL,
+                    response_body TEXT NOT NULL,
+                    duration_ms INTEGER NOT NULL,
+                    response_size INTEGER NOT NULL
+                );
+                INSERT INTO history (timestamp, workspace, method, url, request_headers,
+                    status, status_text, response_headers, response_body,
+                    duration_ms, response_size)
+                VALUES ('2024-01-01T00:00:00+00:00', '/ws', 'GET', 'https://a.test', '{}',
+                    200, 'OK', '{}', 'old body', 12, 8);",
+            )
+            .unwrap();
+
+        let db = HistoryDb::in_memory().unwrap();
+        let report = db.import_db(&old, HistoryImportMode::Merge).unwrap();
+        assert_eq!(report.imported, 1);
+        let id = db.get_entries("/ws", 1).unwrap()[0].id;
+        assert_eq!(db.get_entry(id).unwrap().unwrap().response_body, "old body");
+        // The source file was not migrated
+        let version: i64 = Connection::open(&old)
+            .unwrap()
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, 0);
+
+        Connection::open(&old)
+            .unwrap()
+            .pragma_update(None, "user_version", 999)
+            .unwrap();
+        assert!(db.import_db(&old, HistoryImportMode::Merge).is_err());
+        assert!(db
+            .import_db(&dir.path().join("missing.db"), HistoryImportMode::Merge)
+            .is_err());
     }
 }
@@ -0,0 +1,207 @@
+use super::HistoryEntry;
+use crate::import::har::HOP_BY_HOP_HEADERS;
+use crate::import::{render_requests, RequestBlock};
+use rusqlite::Result as SqliteResult;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// Headers carrying credentials, replaced with [`TOKEN_PLACEHOLDER`] when
+/// asked to
+const AUTH_HEADERS: &[&str] = &["authorization", "x-api-key", "api-key", "x-auth-token"];
+
+const TOKEN_PLACEHOLDER: &str = "{{token}}";
+
+/// How history entries are turned into an .http file
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HttpExportOptions {
+    /// Keep one request per method and URL, the most recently sent
+    pub dedupe: bool,
+    /// Replace credentials in auth headers with `{{token}}`, keeping a
+    /// scheme such as `Bearer`
+    pub auth_placeholders: bool,
+}
+
+/// The requests of history entries as .http text, in the order given. Each
+/// is named after its request name, or its path, and when it was sent.
+pub fn write_http(
+    entries: impl Iterator<Item = SqliteResult<HistoryEntry>>,
+    options: &HttpExportOptions,
+) -> Result<String, String> {
+    let mut blocks: Vec<RequestBlock> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("Failed to read history: {}", e))?;
+        let block = request_block(&entry, options);
+        if !options.dedupe {
+            blocks.push(block);
+            continue;
+        }
+        let key = format!("{} {}", block.method, block.url);
+        match positions.get(&key) {
+            Some(&i) => blocks[i] = block,
+            None => {
+                positions.insert(key, blocks.len());
+                blocks.push(block);
+            }
+        }
+    }
+    Ok(render_requests(&blocks))
+}
+
+fn request_block(entry: &HistoryEntry, options: &HttpExportOptions) -> RequestBlock {
+    let label = entry.request_name.clone().unwrap_or_else(|| {
+        url::Url::parse(&entry.url)
+            .map(|url| url.path().to_string())
+            .unwrap_or_else(|_| entry.url.clone())
+    });
+    let mut headers: Vec<(String, String)> =
+        serde_json::from_str::<Map<String, Value>>(&entry.request_headers)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|(name, value)| Some((name, value.as_str()?.to_string())))
+            .filter(|(name, _)| !HOP_BY_HOP_HEADERS.contains(&name.to_ascii_lowercase().as_str()))
+            .collect();
+    if options.auth_placeholders {
+        for (name, value) in headers.iter_mut() {
+            if AUTH_HEADERS.contains(&name.to_ascii_lowercase().as_str()) {
+                *value = match value.split_once(' ') {
+                    Some((scheme, _)) => format!("{} {}", scheme, TOKEN_PLACEHOLDER),
+                    None => TOKEN_PLACEHOLDER.to_string(),
+                };
+            }
+        }
+    }
+
+    RequestBlock {
+        name: format!("{} {}", label, entry.timestamp.format("%Y-%m-%d %H:%M:%S")),
+        method: entry.method.clone(),
+        url: entry.url.clone(),
+        headers,
+        body: entry
+            .request_body
+            .clone()
+            .filter(|body| !body.trim().is_empty()),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_http_content;
+    use chrono::{TimeZone, Utc};
+
+    fn entry(
+        id: i64,
+        method: &str,
+        url: &str,
+        name: Option<&str>,
+        headers: Value,
+        body: Option<&str>,
+    ) -> SqliteResult<HistoryEntry> {
+        Ok(HistoryEntry {
+            id,
+            timestamp: Utc.with_ymd_and_hms(2026, 3, 1, 12, 0, id as u32).unwrap(),
+            workspace: "/ws".to_string(),
+            file_path: None,
+            request_name: name.map(str::to_string),
+            method: method.to_string(),
+            url: url.to_string(),
+            request_headers: headers.to_string(),
+            request_body: body.map(str::to_string),
+            status: 200,
+            status_text: "OK".to_string(),
+            response_headers: "{}".to_string(),
+            response_body: String::new(),
+            duration_ms: 1,
+            response_size: 0,
+            replayed_from: None,
+            environment: None,
+            variables_snapshot: None,
+            response_body_base64: false,
+        })
+    }
+
+    fn entries() -> Vec<SqliteResult<HistoryEntry>> {
+        vec![
+            entry(
+                1,
+                "GET",
+                "https://api.test/users?page=1",
+                None,
+                serde_json::json!({"Accept": "application/json", "Authorization": "Bearer abc.def", "Content-Length": "0"}),
+                None,
+            ),
+            entry(
+                2,
+                "POST",
+                "https://api.test/users",
+                Some("create-user"),
+                serde_json::json!({"Content-Type": "application/json", "X-Api-Key": "secret"}),
+                Some("{\n  \"name\": \"Ada\"\n}"),
+            ),
+            entry(
+                3,
+                "GET",
+                "https://api.test/users?page=1",
+                None,
+                serde_json::json!({"Accept": "text/csv"}),
+                Some("  "),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_history_round_trips_through_parser() {
+        let options = HttpExportOptions::default();
+        let http = write_http(entries().into_iter(), &options).unwrap();
+        let requests = parse_http_content(&http).unwrap();
+
+        assert_eq!(requests.len(), 3);
+        let names: Vec<&str> = requests.iter().filter_map(|r| r.name.as_deref()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "/users 2026-03-01 12:00:01",
+                "create-user 2026-03-01 12:00:02",
+                "/users 2026-03-01 12:00:03"
+            ]
+        );
+        assert_eq!(requests[0].method, "GET");
+        assert_eq!(requests[0].url, "https://api.test/users?page=1");
+        assert_eq!(requests[0].headers["Authorization"], "Bearer abc.def");
+        assert!(!requests[0].headers.contains_key("Content-Length"));
+        assert_eq!(requests[0].body, None);
+        assert_eq!(requests[1].method, "POST");
+        assert_eq!(requests[1].headers["Content-Type"], "application/json");
+        assert_eq!(
+            requests[1].body.as_deref(),
+            Some("{\n  \"name\": \"Ada\"\n}")
+        );
+        assert_eq!(requests[2].body, None);
+    }
+
+    #[test]
+    fn test_dedupe_and_auth_placeholders() {
+        let options = HttpExportOptions {
+            dedupe: true,
+            auth_placeholders: true,
+        };
+        let http = write_http(entries().into_iter(), &options).unwrap();
+        let requests = parse_http_content(&http).unwrap();
+
+        // The repeated GET keeps its first position with the latest request
+        assert_eq!(requests.len(), 2);
+        assert_eq!(
+            requests[0].name.as_deref(),
+            Some("/users 2026-03-01 12:00:03")
+        );
+        assert_eq!(requests[0].headers["Accept"], "text/csv");
+        assert_eq!(requests[1].headers["X-Api-Key"], "{{token}}");
+
+        let http = write_http(entries().into_iter().take(1), &options).unwrap();
+        let requests = parse_http_content(&http).unwrap();
+        assert_eq!(requests[0].headers["Authorization"], "Bearer {{token}}");
+        assert!(!http.contains("abc.def"));
+    }
+}
@@ -0,0 +1,418 @@
+use super::{store_body, INLINE_BODY_LIMIT};
+use rusqlite::{Connection, Result as SqliteResult, Transaction};
+
+/// A schema change, applied once and in order
+type Migration = fn(&Transaction) -> SqliteResult<()>;
+
+/// Every migration in order; a database's `user_version` is the number applied
+const MIGRATIONS: &[Migration] = &[
+    create_history,
+    // Link replayed entries to their original
+    |tx| add_column(tx, "history", "replayed_from", "INTEGER"),
+    |tx| {
+        tx.execute(
+            "CREATE TABLE IF NOT EXISTS settings (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL
+            )",
+            [],
+        )?;
+        Ok(())
+    },
+    // Response bodies stored in blob files
+    |tx| add_column(tx, "history", "response_body_blob", "TEXT"),
+    |tx| {
+        tx.execute_batch(
+            "CREATE TABLE tags (
+                entry_id INTEGER NOT NULL REFERENCES history(id) ON DELETE CASCADE,
+                tag TEXT NOT NULL,
+                PRIMARY KEY (entry_id, tag)
+            );
+            CREATE INDEX idx_tags_tag ON tags(tag);",
+        )
+    },
+    // Environment and variables a request was sent with
+    |tx| {
+        add_column(tx, "history", "environment", "TEXT")?;
+        add_column(tx, "history", "variables_snapshot", "TEXT")
+    },
+    share_bodies,
+    index_bodies,
+    // Per-workspace settings such as the selected environment
+    |tx| {
+        tx.execute(
+            "CREATE TABLE workspace_settings (
+                workspace TEXT NOT NULL,
+                key TEXT NOT NULL,
+                value TEXT NOT NULL,
+                PRIMARY KEY (workspace, key)
+            )",
+            [],
+        )?;
+        Ok(())
+    }, // Cached auth tokens; a NULL token is kept in the keychain
+    |tx| {
+        tx.execute(
+            "CREATE TABLE auth_tokens (
+                workspace TEXT NOT NULL,
+                auth_id TEXT NOT NULL,
+                token TEXT,
+                PRIMARY KEY (workspace, auth_id)
+            )",
+            [],
+        )?;
+        Ok(())
+    },
+    // Tokens moved to the keychain, so none are left in the database
+    |tx| tx.execute_batch("DROP TABLE auth_tokens"),
+    // Workspaces opened recently, offered when the app starts
+    |tx| {
+        tx.execute(
+            "CREATE TABLE recent_workspaces (
+                path TEXT PRIMARY KEY,
+                last_opened TEXT NOT NULL,
+                pinned INTEGER NOT NULL DEFAULT 0
+            )",
+            [],
+        )?;
+        Ok(())
+    },
+    // Binary response bodies stored base64-encoded
+    |tx| {
+        add_column(
+            tx,
+            "history",
+            "response_body_base64",
+            "INTEGER NOT NULL DEFAULT 0",
+        )
+    },
+];
+
+/// Bring the database up to date with `MIGRATIONS`
+pub(super) fn migrate(conn: &mut Connection) -> SqliteResult<()> {
+    migrate_with(conn, MIGRATIONS)
+}
+
+/// Whether a newer version of the app has migrated the database further
+pub(super) fn is_newer(conn: &Connection) -> SqliteResult<bool> {
+    let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    Ok(version > MIGRATIONS.len())
+}
+
+/// Apply the migrations the database has not seen yet in a single
+/// transaction, so a failure leaves it as it was
+fn migrate_with(conn: &mut Connection, migrations: &[Migration]) -> SqliteResult<()> {
+    let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    // A newer version of the app may have migrated further; leave it be
+    if version >= migrations.len() {
+        return Ok(());
+    }
+
+    let tx = conn.transaction()?;
+    for migration in &migrations[version..] {
+        migration(&tx)?;
+    }
+    tx.pragma_update(None, "user_version", migrations.len())?;
+    tx.commit()
+}
+
+/// The original schema. Databases from before migrations already have it,
+/// hence `IF NOT EXISTS`.
+fn create_history(tx: &Transaction) -> SqliteResult<()> {
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS history (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timestamp TEXT NOT NULL,
+            workspace TEXT NOT NULL,
+            file_path TEXT,
+            request_name TEXT,
+            method TEXT NOT NULL,
+            url TEXT NOT NULL,
+            request_headers TEXT NOT NULL,
+            request_body TEXT,
+            status INTEGER NOT NULL,
+            status_text TEXT NOT NULL,
+            response_headers TEXT NOT NULL,
+            response_body TEXT NOT NULL,
+            duration_ms INTEGER NOT NULL,
+            response_size INTEGER NOT NULL
+        )",
+        [],
+    )?;
+
+    // Create indexes for faster queries
+    tx.execute(
+        "CREATE INDEX IF NOT EXISTS idx_history_workspace_timestamp
+         ON history(workspace, timestamp DESC)",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// Move inline response bodies to a `bodies` table holding each distinct body
+/// once. Deleting an entry releases its reference; unreferenced bodies go.
+fn share_bodies(tx: &Transaction) -> SqliteResult<()> {
+    add_column(tx, "history", "response_body_hash", "TEXT")?;
+    tx.execute_batch(
+        "CREATE TABLE bodies (
+            hash TEXT PRIMARY KEY,
+            content TEXT NOT NULL,
+            refcount INTEGER NOT NULL
+        );
+        CREATE TRIGGER history_release_body AFTER DELETE ON history
+        WHEN old.response_body_hash IS NOT NULL
+        BEGIN
+            UPDATE bodies SET refcount = refcount - 1 WHERE hash = old.response_body_hash;
+            DELETE FROM bodies WHERE hash = old.response_body_hash AND refcount <= 0;
+        END;",
+    )?;
+
+    // Bodies over the inline limit are left for the move to blob files.
+    // Rows are read one at a time so a large history isn't held in memory.
+    let ids: Vec<i64> = tx
+        .prepare(
+            "SELECT id FROM history
+             WHERE response_body_blob IS NULL AND length(CAST(response_body AS BLOB)) <= ?1",
+        )?
+        .query_map([INLINE_BODY_LIMIT as i64], |row| row.get(0))?
+        .collect::<SqliteResult<_>>()?;
+    for id in ids {
+        let body: String = tx.query_row(
+            "SELECT response_body FROM history WHERE id = ?1",
+            [id],
+            |row| row.get(0),
+        )?;
+        let hash = store_body(tx, &body)?;
+        tx.execute(
+            "UPDATE history SET response_body = '', response_body_hash = ?1 WHERE id = ?2",
+            rusqlite::params![hash, id],
+        )?;
+    }
+    Ok(())
+}
+
+/// Full-text indexes over response bodies (one row per distinct body) and
+/// request bodies (one row per entry). Rows that existed before are indexed
+/// later in batches, tracked in `search_backfill`.
+///
+/// `bodies` is rebuilt with an integer key, as its implicit rowid (which the
+/// index refers to) could change on VACUUM.
+///
+/// The unicode61 tokenizer splits on punctuation, so JSON like
+/// `{"order_id":98231}` yields the tokens `order_id` and `98231`; `_` is a
+/// token character so snake_case keys stay whole.
+fn index_bodies(tx: &Transaction) -> SqliteResult<()> {
+    tx.execute_batch(
+        "DROP TRIGGER history_release_body;
+        CREATE TABLE bodies_keyed (
+            id INTEGER PRIMARY KEY,
+            hash TEXT NOT NULL UNIQUE,
+            content TEXT NOT NULL,
+            refcount INTEGER NOT NULL
+        );
+        INSERT INTO bodies_keyed (hash, content, refcount)
+            SELECT hash, content, refcount FROM bodies;
+        DROP TABLE bodies;
+        ALTER TABLE bodies_keyed RENAME TO bodies;
+        CREATE TRIGGER history_release_body AFTER DELETE ON history
+        WHEN old.response_body_hash IS NOT NULL
+        BEGIN
+            UPDATE bodies SET refcount = refcount - 1 WHERE hash = old.response_body_hash;
+            DELETE FROM bodies WHERE hash = old.response_body_hash AND refcount <= 0;
+        END;
+        CREATE INDEX idx_history_body_hash ON history(response_body_hash);
+
+        CREATE VIRTUAL TABLE response_fts USING fts5(
+            content, tokenize = \"unicode61 tokenchars '_'\"
+        );
+        CREATE VIRTUAL TABLE request_fts USING fts5(
+            content, tokenize = \"unicode61 tokenchars '_'\"
+        );
+        CREATE TRIGGER bodies_index AFTER INSERT ON bodies
+        BEGIN
+            INSERT INTO response_fts (rowid, content) VALUES (new.id, new.content);
+        END;
+        CREATE TRIGGER bodies_unindex AFTER DELETE ON bodies
+        BEGIN
+            DELETE FROM response_fts WHERE rowid = old.id;
+        END;
+        CREATE TRIGGER history_index_request AFTER INSERT ON history
+        WHEN new.request_body <> ''
+        BEGIN
+            INSERT INTO request_fts (rowid, content) VALUES (new.id, new.request_body);
+        END;
+        CREATE TRIGGER history_unindex_request AFTER DELETE ON history
+        BEGIN
+            DELETE FROM request_fts WHERE rowid = old.id;
+        END;
+
+        CREATE TABLE search_backfill (
+            source TEXT PRIMARY KEY,
+            next_id INTEGER NOT NULL,
+            last_id INTEGER NOT NULL
+        );
+        INSERT INTO search_backfill (source, next_id, last_id)
+            SELECT 'bodies', 1, MAX(id) FROM bodies HAVING COUNT(*) > 0;
+        INSERT INTO search_backfill (source, next_id, last_id)
+            SELECT 'history', 1, MAX(id) FROM history HAVING COUNT(*) > 0;",
+    )
+}
+
+/// Add a column unless it exists, as it may in databases that added columns
+/// before migrations were tracked
+fn add_column(tx: &Transaction, table: &str, column: &str, definition: &str) -> SqliteResult<()> {
+    let exists = tx
+        .prepare("SELECT 1 FROM pragma_table_info(?1) WHERE name = ?2")?
+        .exists([table, column])?;
+    if !exists {
+        tx.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            [],
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user_version(conn: &Connection) -> usize {
+        conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    fn columns(conn: &Connection, table: &str) -> Vec<String> {
+        conn.prepare("SELECT name FROM pragma_table_info(?1)")
+            .unwrap()
+            .query_map([table], |row| row.get(0))
+            .unwrap()
+            .collect::<SqliteResult<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_migrates_database_from_before_migrations() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        // What the old init_database created
+        conn.execute_batch(
+            "CREATE TABLE history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                timestamp TEXT NOT NULL,
+                workspace TEXT NOT NULL,
+                file_path TEXT,
+                request_name TEXT,
+                method TEXT NOT NULL,
+                url TEXT NOT NULL,
+                request_headers TEXT NOT NULL,
+                request_body TEXT,
+                status INTEGER NOT NULL,
+                status_text TEXT NOT NULL,
+                response_headers TEXT NOT NULL,
+                response_body TEXT NOT NULL,
+                duration_ms INTEGER NOT NULL,
+                response_size INTEGER NOT NULL
+            );
+            CREATE INDEX idx_history_workspace_timestamp
+                ON history(workspace, timestamp DESC);
+            INSERT INTO history (timestamp, workspace, method, url, request_headers,
+                status, status_text, response_headers, response_body,
+                duration_ms, response_size)
+            VALUES ('2024-01-01T00:00:00+00:00', '/ws', 'GET', 'https://a.test', '{}',
+                200, 'OK', '{}', 'kept', 12, 4),
+                ('2024-01-01T00:00:05+00:00', '/ws', 'GET', 'https://a.test', '{}',
+                200, 'OK', '{}', 'kept', 10, 4);",
+        )
+        .unwrap();
+        assert_eq!(user_version(&conn), 0);
+
+        migrate(&mut conn).unwrap();
+        assert_eq!(user_version(&conn), MIGRATIONS.len());
+        let history = columns(&conn, "history");
+        assert!(history.contains(&"replayed_from".to_string()));
+        assert!(history.contains(&"response_body_blob".to_string()));
+        assert!(history.contains(&"variables_snapshot".to_string()));
+        assert_eq!(columns(&conn, "settings"), vec!["key", "value"]);
+        assert_eq!(
+            columns(&conn, "workspace_settings"),
+            vec!["workspace", "key", "value"]
+        );
+        assert!(columns(&conn, "auth_tokens").is_empty());
+        assert_eq!(columns(&conn, "tags"), vec!["entry_id", "tag"]);
+        assert_eq!(
+            columns(&conn, "bodies"),
+            vec!["id", "hash", "content", "refcount"]
+        );
+
+        let (url, body, replayed_from): (String, String, Option<i64>) = conn
+            .query_row(
+                "SELECT url, content, replayed_from
+                 FROM history JOIN bodies ON hash = response_body_hash WHERE history.id = 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(
+            (url.as_str(), body.as_str(), replayed_from),
+            ("https://a.test", "kept", None)
+        );
+
+        // The duplicate bodies were folded into one shared row
+        let refcounts: Vec<i64> = conn
+            .prepare("SELECT refcount FROM bodies")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<SqliteResult<_>>()
+            .unwrap();
+        assert_eq!(refcounts, vec![2]);
+
+        // Existing rows wait for the search index backfill
+        let backfill: Vec<(String, i64)> = conn
+            .prepare("SELECT source, last_id FROM search_backfill ORDER BY source")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<SqliteResult<_>>()
+            .unwrap();
+        assert_eq!(
+            backfill,
+            vec![("bodies".to_string(), 1), ("history".to_string(), 2)]
+        );
+
+        // Running again is a no-op
+        migrate(&mut conn).unwrap();
+        assert_eq!(user_version(&conn), MIGRATIONS.len());
+    }
+
+    #[test]
+    fn test_migrates_new_database() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+        assert_eq!(user_version(&conn), MIGRATIONS.len());
+        assert_eq!(columns(&conn, "history").len(), 21);
+    }
+
+    #[test]
+    fn test_failed_migration_leaves_database_untouched() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate_with(&mut conn, &MIGRATIONS[..1]).unwrap();
+        assert_eq!(user_version(&conn), 1);
+
+        let failing: [Migration; 3] = [
+            create_history,
+            |tx| {
+                tx.execute("CREATE TABLE extra (id INTEGER)", [])
+                    .map(|_| ())
+            },
+            |tx| {
+                tx.execute("ALTER TABLE missing ADD COLUMN x", [])
+                    .map(|_| ())
+            },
+        ];
+        assert!(migrate_with(&mut conn, &failing).is_err());
+        assert_eq!(user_version(&conn), 1);
+        assert!(columns(&conn, "extra").is_empty());
+    }
+}
@@ -0,0 +1,299 @@
+use super::HistoryEntry;
+use crate::http_client::is_binary_mime;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+
+/// Extensions for the response types worth naming; anything else binary is
+/// `.bin` and anything else textual `.txt`
+const EXTENSIONS: &[(&str, &str)] = &[
+    ("application/json", "json"),
+    ("application/xml", "xml"),
+    ("text/xml", "xml"),
+    ("text/html", "html"),
+    ("text/plain", "txt"),
+    ("text/csv", "csv"),
+    ("text/css", "css"),
+    ("text/javascript", "js"),
+    ("application/javascript", "js"),
+    ("application/yaml", "yaml"),
+    ("application/pdf", "pdf"),
+    ("application/zip", "zip"),
+    ("application/gzip", "gz"),
+    ("image/png", "png"),
+    ("image/jpeg", "jpg"),
+    ("image/gif", "gif"),
+    ("image/webp", "webp"),
+    ("image/svg+xml", "svg"),
+];
+
+/// Write an entry's response body to `dest`, decoding it when it was stored
+/// base64-encoded. When `dest` is a directory, the file is named after the
+/// URL and Content-Type. The file is replaced atomically, and only with
+/// `overwrite`. Returns the number of bytes written.
+pub fn save_response_body(
+    entry: &HistoryEntry,
+    dest: &Path,
+    overwrite: bool,
+) -> Result<u64, String> {
+    let dest = if dest.is_dir() {
+        dest.join(file_name(entry))
+    } else {
+        dest.to_path_buf()
+    };
+    if !overwrite && dest.symlink_metadata().is_ok() {
+        return Err(format!("{} already exists", dest.display()));
+    }
+    let bytes = body_bytes(entry)?;
+
+    let temp = temp_path(&dest);
+    std::fs::write(&temp, &bytes)
+        .and_then(|_| std::fs::rename(&temp, &dest))
+        .map_err(|e| {
+            let _ = std::fs::remove_file(&temp);
+            format!("Failed to write {}: {}", dest.display(), e)
+        })?;
+    Ok(bytes.len() as u64)
+}
+
+/// The response body as it was received
+fn body_bytes(entry: &HistoryEntry) -> Result<Vec<u8>, String> {
+    if !entry.response_body_base64 {
+        return Ok(entry.response_body.clone().into_bytes());
+    }
+    STANDARD.decode(&entry.response_body).map_err(|e| {
+        format!(
+            "History entry {} has an unreadable response body: {}",
+            entry.id, e
+        )
+    })
+}
+
+/// The response's Content-Type without parameters, lowercased
+fn content_type(entry: &HistoryEntry) -> String {
+    serde_json::from_str::<Map<String, Value>>(&entry.response_headers)
+        .unwrap_or_default()
+        .into_iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+        .and_then(|(_, value)| value.as_str().map(str::to_string))
+        .and_then(|value| {
+            value
+                .split(';')
+                .next()
+                .map(|t| t.trim().to_ascii_lowercase())
+        })
+        .unwrap_or_default()
+}
+
+/// A file name for the body: the URL's last path segment, with an extension
+/// from the Content-Type when it has none
+fn file_name(entry: &HistoryEntry) -> String {
+    let segment = url::Url::parse(&entry.url)
+        .ok()
+        .and_then(|url| {
+            url.path_segments()
+                .and_then(|mut segments| segments.next_back().map(str::to_string))
+        })
+        .map(|segment| {
+            urlencoding::decode(&segment)
+                .map(|s| s.into_owned())
+                .unwrap_or(segment)
+        })
+        .unwrap_or_default();
+    let stem: String = segment
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let stem = stem.trim_matches('.');
+    let stem = if stem.is_empty() { "response" } else { stem };
+    if Path::new(stem).extension().is_some() {
+        return stem.to_string();
+    }
+
+    let content_type = content_type(entry);
+    let extension = EXTENSIONS
+        .iter()
+        .find(|(mime, _)| *mime == content_type)
+        .map(|(_, extension)| *extension)
+        .unwrap_or_else(|| {
+            if content_type.ends_with("+json") {
+                "json"
+            } else if content_type.ends_with("+xml") {
+                "xml"
+            } else if is_binary_mime(&content_type) {
+                "bin"
+            } else {
+                "txt"
+            }
+        });
+    format!("{}.{}", stem, extension)
+}
+
+/// A hidden file next to `dest`, so the rename stays on one file system
+pub(super) fn temp_path(dest: &Path) -> PathBuf {
+    let name = dest
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    dest.with_file_name(format!(".{}.{}.tmp", name, std::process::id()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn entry(url: &str, content_type: &str, body: &str) -> HistoryEntry {
+        HistoryEntry {
+            id: 1,
+            timestamp: Utc::now(),
+            workspace: "/ws".to_string(),
+            file_path: None,
+            request_name: None,
+            method: "GET".to_string(),
+            url: url.to_string(),
+            request_headers: "{}".to_string(),
+            request_body: None,
+            status: 200,
+            status_text: "OK".to_string(),
+            response_headers: serde_json::json!({ "Content-Type": content_type }).to_string(),
+            response_body: body.to_string(),
+            duration_ms: 10,
+            response_size: body.len() as i64,
+            replayed_from: None,
+            environment: None,
+            variables_snapshot: None,
+            response_body_base64: false,
+        }
+    }
+
+    #[test]
+    fn test_save_text_body() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("users.json");
+        let entry = entry("http://localhost/users", "application/json", r#"{"id":1}"#);
+
+        assert_eq!(save_response_body(&entry, &dest, false), Ok(8));
+        assert_eq!(std::fs::read_to_string(&dest).unwrap(), r#"{"id":1}"#);
+
+        assert_eq!(
+            save_response_body(&entry, &dest, false),
+            Err(format!("{} already exists", dest.display()))
+        );
+        let other = HistoryEntry {
+            response_body: "[]".to_string(),
+            ..entry
+        };
+        assert_eq!(save_response_body(&other, &dest, true), Ok(2));
+        assert_eq!(std::fs::read_to_string(&dest).unwrap(), "[]");
+        // Nothing left behind from the atomic writes
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_save_binary_body_received() {
+        use crate::history::{HistoryContext, HistoryDb, NewHistoryEntry};
+        use crate::http_client::{execute_request, HttpRequest};
+        use crate::workspace_settings::WorkspaceSettings;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        const PNG: &[u8] = &[
+            0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0xff, 0xfe,
+        ];
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!(
+            "http://{}/avatars/42?size=64",
+            listener.local_addr().unwrap()
+        );
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut received = Vec::new();
+            let mut buf = [0; 1024];
+            while !received.ends_with(b"\r\n\r\n") {
+                let n = socket.read(&mut buf).await.unwrap();
+                if n == 0 {
+                    break;
+                }
+                received.extend_from_slice(&buf[..n]);
+            }
+            let head = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                PNG.len()
+            );
+            socket.write_all(head.as_bytes()).await.unwrap();
+            socket.write_all(PNG).await.unwrap();
+        });
+
+        let request = HttpRequest {
+            method: "GET".to_string(),
+            url,
+            headers: Default::default(),
+            body: None,
+        };
+        let response = execute_request(request.clone(), &WorkspaceSettings::default())
+            .await
+            .map_err(|e| e.to_string());
+        assert!(response.as_ref().unwrap().body_base64);
+        assert_eq!(response.as_ref().unwrap().size, PNG.len());
+
+        let db = HistoryDb::in_memory().unwrap();
+        let context = HistoryContext {
+            workspace: "/ws".to_string(),
+            ..Default::default()
+        };
+        let id = db
+            .add_entry(NewHistoryEntry::sent(
+                &context, None, &request, &response, 0,
+            ))
+            .unwrap();
+        let entry = db.get_entry(id).unwrap().unwrap();
+        assert!(entry.response_body_base64);
+
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(
+            save_response_body(&entry, dir.path(), false),
+            Ok(PNG.len() as u64)
+        );
+        assert_eq!(std::fs::read(dir.path().join("42.png")).unwrap(), PNG);
+    }
+
+    #[test]
+    fn test_save_text_that_looks_like_base64() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("token");
+        // Only the flag says a body is base64, not its content type
+        let entry = entry("http://localhost/token", "application/octet-stream", "aGk=");
+
+        assert_eq!(save_response_body(&entry, &dest, false), Ok(4));
+        assert_eq!(std::fs::read_to_string(&dest).unwrap(), "aGk=");
+    }
+
+    #[test]
+    fn test_file_name_from_url_and_content_type() {
+        let name = |url: &str, content_type: &str| file_name(&entry(url, content_type, ""));
+
+        assert_eq!(
+            name("http://localhost/users", "application/json; charset=utf-8"),
+            "users.json"
+        );
+        assert_eq!(
+            name("http://localhost/report.pdf", "application/octet-stream"),
+            "report.pdf"
+        );
+        assert_eq!(name("http://localhost/", "text/html"), "response.html");
+        assert_eq!(
+            name("http://localhost/feed", "application/atom+xml"),
+            "feed.xml"
+        );
+        assert_eq!(
+            name("http://localhost/blob", "application/octet-stream"),
+            "blob.bin"
+        );
+        assert_eq!(name("http://localhost/a%3Fb", ""), "a_b.txt");
+        assert_eq!(name("not a url", "image/jpeg"), "response.jpg");
+    }
+}
@@ -1,7 +1,13 @@
-use reqwest::{header::HeaderMap, Client, Method};
+use crate::parser::ParsedRequest;
+use crate::workspace_settings::WorkspaceSettings;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::{
+    header::{HeaderMap, CONTENT_TYPE},
+    Client, Method, Proxy,
+};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HttpRequest {
@@ -11,6 +17,17 @@ pub struct HttpRequest {
     pub body: Option<String>,
 }
 
+impl From<ParsedRequest> for HttpRequest {
+    fn from(request: ParsedRequest) -> Self {
+        Self {
+            method: request.method,
+            url: request.url,
+            headers: request.headers,
+            body: request.body,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HttpResponse {
     pub status: u16,
@@ -19,6 +36,13 @@ pub struct HttpResponse {
     pub body: String,
     pub time: u64,
     pub size: usize,
+    /// Whether `body` holds the bytes received base64-encoded, as it does
+    /// for binary content types and bodies that aren't UTF-8
+    #[serde(default)]
+    pub body_base64: bool,
+    /// The history entry recording this response, if it was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history_id: Option<i64>,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -30,12 +54,38 @@ pub enum HttpError {
     #[error("Invalid URL: {0}")]
     #[allow(dead_code)]
     InvalidUrl(String),
+    #[error("Invalid proxy: {0}")]
+    InvalidProxy(String),
+}
+
+/// Add the workspace's default headers that a request doesn't set itself
+pub fn add_default_headers(request: &mut HttpRequest, settings: &WorkspaceSettings) {
+    for (name, value) in &settings.default_headers {
+        if !request
+            .headers
+            .keys()
+            .any(|key| key.eq_ignore_ascii_case(name))
+        {
+            request.headers.insert(name.clone(), value.clone());
+        }
+    }
 }
 
-pub async fn execute_request(request: HttpRequest) -> Result<HttpResponse, HttpError> {
-    let client = Client::builder()
-        .danger_accept_invalid_certs(false)
-        .build()?;
+/// Send a request with a workspace's default headers, timeout and proxy
+pub async fn execute_request(
+    mut request: HttpRequest,
+    settings: &WorkspaceSettings,
+) -> Result<HttpResponse, HttpError> {
+    add_default_headers(&mut request, settings);
+    let mut builder = Client::builder().danger_accept_invalid_certs(false);
+    if let Some(timeout) = settings.timeout_ms {
+        builder = builder.timeout(Duration::from_millis(timeout));
+    }
+    if let Some(proxy) = &settings.proxy {
+        let proxy = Proxy::all(proxy).map_err(|e| HttpError::InvalidProxy(e.to_string()))?;
+        builder = builder.proxy(proxy);
+    }
+    let client = builder.build()?;
 
     let method = match request.method.to_uppercase().as_str() {
         "GET" => Method::GET,
@@ -62,13 +112,22 @@ pub async fn execute_request(request: HttpRequest) -> Result<HttpResponse, HttpE
 
     let start = Instant::now();
 
-    let mut req_builder = client.request(method, &request.url).headers(headers);
+    let mut req_builder = client
+        .request(method.clone(), &request.url)
+        .headers(headers);
 
     if let Some(body) = request.body {
         req_builder = req_builder.body(body);
     }
 
-    let response = req_builder.send().await?;
+    tracing::debug!(url = %request.url, "Sending {} request", method);
+    let response = match req_builder.send().await {
+        Ok(response) => response,
+        Err(e) => {
+            tracing::warn!(url = %request.url, "{} request failed: {}", method, e);
+            return Err(e.into());
+        }
+    };
     let elapsed = start.elapsed().as_millis() as u64;
 
     let status = response.status().as_u16();
@@ -84,8 +143,26 @@ pub async fn execute_request(request: HttpRequest) -> Result<HttpResponse, HttpE
         .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
         .collect();
 
-    let body = response.text().await?;
-    let size = body.len();
+    let content_type = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let bytes = response.bytes().await?;
+    let size = bytes.len();
+    let (body, body_base64) = match std::str::from_utf8(&bytes) {
+        Ok(text) if !is_binary_mime(&content_type) => (text.to_string(), false),
+        _ => (STANDARD.encode(&bytes), true),
+    };
+    tracing::info!(
+        url = %request.url,
+        status,
+        time_ms = elapsed,
+        size,
+        "{} request answered",
+        method
+    );
 
     Ok(HttpResponse {
         status,
@@ -94,5 +171,99 @@ pub async fn execute_request(request: HttpRequest) -> Result<HttpResponse, HttpE
         body,
         time: elapsed,
         size,
+        body_base64,
+        history_id: None,
     })
 }
+
+/// Response mime types whose bodies are kept base64-encoded
+pub fn is_binary_mime(mime_type: &str) -> bool {
+    let mime_type = mime_type.to_ascii_lowercase();
+    !(mime_type.starts_with("text/")
+        || mime_type.contains("json")
+        || mime_type.contains("xml")
+        || mime_type.contains("javascript")
+        || mime_type.contains("x-www-form-urlencoded")
+        || mime_type.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// Answer one request after `delay`, echoing its head as the body
+    async fn serve_once(delay: Duration) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut received = Vec::new();
+            let mut buf = [0; 1024];
+            while !received.ends_with(b"\r\n\r\n") {
+                let n = socket.read(&mut buf).await.unwrap();
+                if n == 0 {
+                    break;
+                }
+                received.extend_from_slice(&buf[..n]);
+            }
+            tokio::time::sleep(delay).await;
+            let head = String::from_utf8_lossy(&received).to_lowercase();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                head.len(),
+                head
+            );
+            let _ = socket.write_all(response.as_bytes()).await;
+        });
+        format!("http://{}/", addr)
+    }
+
+    fn get(url: String, headers: &[(&str, &str)]) -> HttpRequest {
+        HttpRequest {
+            method: "GET".to_string(),
+            url,
+            headers: headers
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            body: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_workspace_default_headers_and_timeout() {
+        let settings = WorkspaceSettings {
+            default_headers: [
+                ("X-Team".to_string(), "payments".to_string()),
+                ("Accept".to_string(), "application/json".to_string()),
+            ]
+            .into(),
+            timeout_ms: Some(200),
+            ..Default::default()
+        };
+
+        let url = serve_once(Duration::ZERO).await;
+        let response = execute_request(get(url, &[("accept", "text/plain")]), &settings)
+            .await
+            .unwrap();
+        assert!(response.body.contains("x-team: payments\r\n"));
+        // The request's own header wins, whatever its case
+        assert!(response.body.contains("accept: text/plain\r\n"));
+        assert!(!response.body.contains("application/json"));
+
+        let url = serve_once(Duration::from_secs(5)).await;
+        let error = execute_request(get(url, &[]), &settings).await.unwrap_err();
+        assert!(matches!(error, HttpError::RequestFailed(e) if e.is_timeout()));
+
+        let settings = WorkspaceSettings {
+            proxy: Some("not a proxy".to_string()),
+            ..Default::default()
+        };
+        let url = serve_once(Duration::ZERO).await;
+        assert!(matches!(
+            execute_request(get(url, &[]), &settings).await,
+            Err(HttpError::InvalidProxy(_))
+        ));
+    }
+}
@@ -0,0 +1,427 @@
+//! Which workspace paths the file list and watcher skip: those excluded by
+//! the workspace's .gitignore, .ignore and .kvileignore files, as git would
+//! exclude them. Workspaces without ignore files skip node_modules and target
+//! directories instead. Workspace settings add patterns of their own, limit
+//! the depth and decide whether symlinks are followed.
+
+use crate::history::{
+    HistoryDb, FOLLOW_SYMLINKS_KEY, IGNORE_PATTERNS_KEY, INCLUDE_IGNORED_KEY, MAX_DEPTH_KEY,
+};
+use crate::workspace_settings::{WorkspaceSettings, SETTINGS_FILE};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::WalkBuilder;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+/// Kvile's own ignore file, in .gitignore syntax. Its patterns take
+/// precedence, so `!build/` there lists a directory .gitignore excludes.
+pub const KVILE_IGNORE_FILE: &str = ".kvileignore";
+
+/// Ignore files a directory may hold, lowest precedence first
+const IGNORE_FILES: [&str; 3] = [".gitignore", ".ignore", KVILE_IGNORE_FILE];
+
+/// Directories skipped in workspaces without ignore files
+const FALLBACK_IGNORED_DIRS: [&str; 2] = ["node_modules", "target"];
+
+/// How many directories deep files are looked for without a
+/// [`MAX_DEPTH_KEY`] setting
+pub const DEFAULT_MAX_DEPTH: usize = 32;
+
+/// The ignore rules of a workspace
+#[derive(Debug, Clone)]
+pub struct WorkspaceIgnore {
+    root: PathBuf,
+    /// Rules of each directory with ignore files, deepest first. Empty when
+    /// there are none, and the fallback list applies.
+    rules: Vec<(PathBuf, Gitignore)>,
+    /// Patterns from the [`IGNORE_PATTERNS_KEY`] setting, which take
+    /// precedence over ignore files
+    patterns: Option<Gitignore>,
+    /// Paths more components below the root than this are skipped, so a
+    /// file directly in the root is at depth 1
+    pub max_depth: usize,
+    /// Whether symlinks are listed and watched through. Without, they are
+    /// skipped.
+    pub follow_symlinks: bool,
+}
+
+impl Default for WorkspaceIgnore {
+    fn default() -> Self {
+        WorkspaceIgnore {
+            root: PathBuf::new(),
+            rules: Vec::new(),
+            patterns: None,
+            max_depth: DEFAULT_MAX_DEPTH,
+            follow_symlinks: false,
+        }
+    }
+}
+
+impl WorkspaceIgnore {
+    /// The rules for a workspace with its settings applied. Extra patterns
+    /// are the `ignore` ones of its settings file or, when it has none, its
+    /// [`IGNORE_PATTERNS_KEY`] setting's; see
+    /// [`WorkspaceSettings::ignore_patterns`]. Ignore files are left out, for
+    /// the fallback list, when its [`INCLUDE_IGNORED_KEY`] setting includes
+    /// ignored paths.
+    pub fn for_workspace(history_db: &HistoryDb, workspace: &str) -> Self {
+        let settings = history_db
+            .get_workspace_settings(workspace)
+            .unwrap_or_default();
+        let enabled = |key| settings.get(key).is_some_and(|value| value == "true");
+        let ignore = if enabled(INCLUDE_IGNORED_KEY) {
+            WorkspaceIgnore {
+                root: PathBuf::from(workspace),
+                ..Default::default()
+            }
+        } else {
+            Self::load(Path::new(workspace))
+        };
+        let shared = WorkspaceSettings::load(Path::new(workspace)).settings;
+        let patterns =
+            shared.ignore_patterns(settings.get(IGNORE_PATTERNS_KEY).map(String::as_str));
+        WorkspaceIgnore {
+            max_depth: settings
+                .get(MAX_DEPTH_KEY)
+                .and_then(|depth| depth.trim().parse().ok())
+                .unwrap_or(DEFAULT_MAX_DEPTH),
+            follow_symlinks: enabled(FOLLOW_SYMLINKS_KEY),
+            ..ignore.with_patterns(&patterns)
+        }
+    }
+
+    /// Skip paths matching `patterns` too, written as in .gitignore relative
+    /// to the workspace root
+    pub fn with_patterns(self, patterns: &[&str]) -> Self {
+        let mut builder = GitignoreBuilder::new(&self.root);
+        for pattern in patterns {
+            // Invalid patterns are skipped, as in ignore files
+            let _ = builder.add_line(None, pattern);
+        }
+        WorkspaceIgnore {
+            patterns: builder.build().ok().filter(|patterns| !patterns.is_empty()),
+            ..self
+        }
+    }
+
+    /// Read the ignore files in a workspace. Directories they exclude and
+    /// hidden directories are not searched for more.
+    pub fn load(root: &Path) -> Self {
+        let walker = WalkBuilder::new(root)
+            .hidden(false)
+            .parents(false)
+            .git_global(false)
+            .git_exclude(false)
+            .require_git(false)
+            .add_custom_ignore_filename(KVILE_IGNORE_FILE)
+            .filter_entry(|entry| {
+                entry.depth() == 0
+                    || !entry.file_type().is_some_and(|t| t.is_dir())
+                    || !entry.file_name().to_string_lossy().starts_with('.')
+            })
+            .build();
+        let dirs: BTreeSet<PathBuf> = walker
+            .flatten()
+            .filter(|entry| {
+                entry.file_type().is_some_and(|t| t.is_file())
+                    && IGNORE_FILES.iter().any(|name| entry.file_name() == *name)
+            })
+            .filter_map(|entry| entry.path().parent().map(Path::to_path_buf))
+            .collect();
+
+        let mut rules: Vec<(PathBuf, Gitignore)> = dirs
+            .into_iter()
+            .filter_map(|dir| {
+                let mut builder = GitignoreBuilder::new(&dir);
+                for name in IGNORE_FILES {
+                    let path = dir.join(name);
+                    if path.is_file() {
+                        // Unparseable lines are skipped, as git does
+                        let _ = builder.add(path);
+                    }
+                }
+                builder.build().ok().map(|rules| (dir, rules))
+            })
+            .collect();
+        rules.sort_by_key(|(dir, _)| std::cmp::Reverse(dir.components().count()));
+        WorkspaceIgnore {
+            root: root.to_path_buf(),
+            rules,
+            ..Default::default()
+        }
+    }
+
+    /// Whether a path in the workspace is excluded. Setting patterns decide
+    /// first, then the nearest ignore file with a matching pattern, so a
+    /// nested file can re-include what a parent's excludes.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.is_through_symlink(path) || self.is_excluded(path, is_dir)
+    }
+
+    /// [`Self::is_ignored`] without the symlink check, for walkers that only
+    /// follow symlinks when [`Self::follow_symlinks`] says so
+    pub fn is_excluded(&self, path: &Path, is_dir: bool) -> bool {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        if relative.components().count() > self.max_depth {
+            return true;
+        }
+        if let Some(patterns) = self
+            .patterns
+            .as_ref()
+            .filter(|_| path.starts_with(&self.root))
+        {
+            let matched = patterns.matched_path_or_any_parents(path, is_dir);
+            if matched.is_ignore() {
+                return true;
+            }
+            if matched.is_whitelist() {
+                return false;
+            }
+        }
+        if self.rules.is_empty() {
+            return relative.components().any(|c| {
+                FALLBACK_IGNORED_DIRS
+                    .iter()
+                    .any(|dir| c.as_os_str() == *dir)
+            });
+        }
+        for (dir, rules) in &self.rules {
+            if path == dir || !path.starts_with(dir) {
+                continue;
+            }
+            let matched = rules.matched_path_or_any_parents(path, is_dir);
+            if matched.is_ignore() {
+                return true;
+            }
+            if matched.is_whitelist() {
+                return false;
+            }
+        }
+        false
+    }
+
+    /// Whether a path is, or is inside, a symlink below the root while
+    /// symlinks aren't followed
+    fn is_through_symlink(&self, path: &Path) -> bool {
+        if self.follow_symlinks || self.root.as_os_str().is_empty() {
+            return false;
+        }
+        path.ancestors()
+            .take_while(|ancestor| *ancestor != self.root && ancestor.starts_with(&self.root))
+            .any(|ancestor| ancestor.is_symlink())
+    }
+
+    /// Whether a path is an ignore file or the settings file, whose changes
+    /// change the rules
+    pub fn is_ignore_file(path: &Path) -> bool {
+        path.file_name().is_some_and(|name| {
+            name == SETTINGS_FILE || IGNORE_FILES.iter().any(|file| name == *file)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(path: &Path, content: &str) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn test_gitignore_rules() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        write(&root.join(".gitignore"), "build/\n*.log\n");
+        write(&root.join("build/generated.http"), "GET /");
+        write(&root.join("api/.ignore"), "scratch.http\n");
+        write(&root.join(".kvileignore"), "!build/keep/\n");
+        // Ignore files in excluded directories don't count
+        write(&root.join("build/.gitignore"), "!*.http\n");
+
+        let ignore = WorkspaceIgnore::load(root);
+        assert!(ignore.is_ignored(&root.join("build"), true));
+        assert!(ignore.is_ignored(&root.join("build/generated.http"), false));
+        assert!(ignore.is_ignored(&root.join("app.log"), false));
+        assert!(ignore.is_ignored(&root.join("api/scratch.http"), false));
+        assert!(!ignore.is_ignored(&root.join("api/users.http"), false));
+        assert!(!ignore.is_ignored(&root.join("scratch.http"), false));
+        assert!(!ignore.is_ignored(&root.join("build/keep"), true));
+        // Ignore files replace the fallback list
+        assert!(!ignore.is_ignored(&root.join("target/x.http"), false));
+    }
+
+    #[tokio::test]
+    async fn test_list_workspace_files_skips_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        write(&root.join(".gitignore"), "build/\n");
+        write(&root.join("api/users.http"), "GET /users");
+        write(&root.join("build/generated.http"), "GET /");
+        write(&root.join("build/nested/more.http"), "GET /");
+        let workspace = root.to_string_lossy().to_string();
+        let names = |files: Vec<crate::files::FileInfo>| {
+            let mut names: Vec<_> = files
+                .into_iter()
+                .filter(|file| file.is_http_file)
+                .map(|file| file.name)
+                .collect();
+            names.sort();
+            names
+        };
+
+        let list = |ignore: WorkspaceIgnore| {
+            let workspace = workspace.clone();
+            async move {
+                crate::files::list_workspace_files(&workspace, &ignore, &Default::default())
+                    .await
+                    .unwrap()
+                    .files
+            }
+        };
+
+        let files = list(WorkspaceIgnore::load(root)).await;
+        assert_eq!(names(files), vec!["users.http"]);
+
+        let files = list(WorkspaceIgnore::default()).await;
+        assert_eq!(
+            names(files),
+            vec!["generated.http", "more.http", "users.http"]
+        );
+    }
+
+    async fn http_files(root: &Path, ignore: &WorkspaceIgnore) -> Vec<String> {
+        let files = crate::files::list_workspace_files(
+            &root.to_string_lossy(),
+            ignore,
+            &Default::default(),
+        )
+        .await
+        .unwrap()
+        .files;
+        let mut paths: Vec<_> = files
+            .into_iter()
+            .filter(|file| file.is_http_file)
+            .map(|file| {
+                Path::new(&file.path)
+                    .strip_prefix(root)
+                    .unwrap()
+                    .to_string_lossy()
+                    .replace('\\', "/")
+            })
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    #[tokio::test]
+    async fn test_max_depth_and_patterns() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        write(&root.join("top.http"), "GET /");
+        write(&root.join("api/users.http"), "GET /users");
+        write(&root.join("api/v1/old.http"), "GET /old");
+        write(&root.join("api/draft.http"), "GET /draft");
+        let workspace = root.to_string_lossy().to_string();
+        let db = HistoryDb::in_memory().unwrap();
+
+        let ignore = WorkspaceIgnore::for_workspace(&db, &workspace);
+        assert_eq!(ignore.max_depth, DEFAULT_MAX_DEPTH);
+        assert_eq!(
+            http_files(root, &ignore).await,
+            vec![
+                "api/draft.http",
+                "api/users.http",
+                "api/v1/old.http",
+                "top.http"
+            ]
+        );
+
+        db.set_workspace_setting(&workspace, MAX_DEPTH_KEY, Some("2"))
+            .unwrap();
+        db.set_workspace_setting(&workspace, IGNORE_PATTERNS_KEY, Some("draft*\n# note\n"))
+            .unwrap();
+        let ignore = WorkspaceIgnore::for_workspace(&db, &workspace);
+        assert_eq!(
+            http_files(root, &ignore).await,
+            vec!["api/users.http", "top.http"]
+        );
+        assert!(!ignore.is_ignored(&root.join("api/v1"), true));
+        assert!(ignore.is_ignored(&root.join("api/v1/old.http"), false));
+
+        // Setting patterns can re-include what ignore files exclude
+        write(&root.join(".gitignore"), "api/\n");
+        db.set_workspace_setting(&workspace, IGNORE_PATTERNS_KEY, Some("!api/"))
+            .unwrap();
+        let ignore = WorkspaceIgnore::for_workspace(&db, &workspace);
+        assert_eq!(
+            http_files(root, &ignore).await,
+            vec!["api/draft.http", "api/users.http", "top.http"]
+        );
+
+        // The settings file's patterns replace the setting's
+        write(
+            &root.join(SETTINGS_FILE),
+            r#"{"ignore": ["!api/", "users*"]}"#,
+        );
+        let ignore = WorkspaceIgnore::for_workspace(&db, &workspace);
+        assert_eq!(
+            http_files(root, &ignore).await,
+            vec!["api/draft.http", "top.http"]
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_symlink_loop() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        write(&root.join("api/users.http"), "GET /users");
+        std::os::unix::fs::symlink(root, root.join("api/loop")).unwrap();
+        std::os::unix::fs::symlink(root.join("api/users.http"), root.join("linked.http")).unwrap();
+
+        let ignore = WorkspaceIgnore::load(root);
+        assert_eq!(http_files(root, &ignore).await, vec!["api/users.http"]);
+        assert!(ignore.is_ignored(&root.join("api/loop/api/users.http"), false));
+
+        // Followed, each directory is still listed once
+        let ignore = WorkspaceIgnore {
+            follow_symlinks: true,
+            ..WorkspaceIgnore::load(root)
+        };
+        assert_eq!(
+            http_files(root, &ignore).await,
+            vec!["api/users.http", "linked.http"]
+        );
+        assert!(!ignore.is_ignored(&root.join("linked.http"), false));
+    }
+
+    #[test]
+    fn test_fallback_without_ignore_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("target").join("ws");
+        write(&root.join("api.http"), "GET /");
+
+        let ignore = WorkspaceIgnore::load(&root);
+        // Only the workspace's own directories count
+        assert!(!ignore.is_ignored(&root.join("api.http"), false));
+        assert!(ignore.is_ignored(&root.join("node_modules/pkg/x.http"), false));
+        assert!(ignore.is_ignored(&root.join("sub/target"), true));
+    }
+
+    #[test]
+    fn test_include_ignored_setting() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        write(&root.join(".gitignore"), "build/\n");
+        let workspace = root.to_string_lossy().to_string();
+        let db = HistoryDb::in_memory().unwrap();
+
+        let path = root.join("build/generated.http");
+        assert!(WorkspaceIgnore::for_workspace(&db, &workspace).is_ignored(&path, false));
+        db.set_workspace_setting(&workspace, INCLUDE_IGNORED_KEY, Some("true"))
+            .unwrap();
+        assert!(!WorkspaceIgnore::for_workspace(&db, &workspace).is_ignored(&path, false));
+    }
+}
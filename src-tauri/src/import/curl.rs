@@ -0,0 +1,2000 @@
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use std::collections::HashMap;
+use std::path::Path;
+
+use super::ImportResult;
+use crate::parser::ParsedRequest;
+use crate::variables::substitute_request;
+
+#[derive(Debug, Clone)]
+pub struct CurlCommand {
+    pub method: String,
+    pub url: String,
+    /// Headers in command-line order; duplicates are kept like curl sends them
+    pub headers: Vec<(String, String)>,
+    pub body: Option<String>,
+    pub auth: Option<(String, String)>,
+    pub tls: TlsOptions,
+    pub retry: RetryOptions,
+    /// Protocol forced with --http1.0/--http1.1/--http2/--http3, e.g. `HTTP/2`
+    pub http_version: Option<String>,
+    /// `--http2-prior-knowledge`: speak HTTP/2 without upgrading from HTTP/1.1
+    pub http2_prior_knowledge: bool,
+    /// Cookie jar file that curl would write response cookies to (`-c`)
+    pub cookie_jar: Option<String>,
+    /// Extra `# @key value` lines from importers, e.g. `auth basic`
+    pub metadata: Vec<(String, String)>,
+    pub flags: Vec<String>,
+    /// Caveats about partially translated options, emitted as comments
+    pub notes: Vec<String>,
+}
+
+/// TLS settings gathered from --cert/--key/--cacert/--insecure and friends
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TlsOptions {
+    pub client_cert: Option<String>,
+    pub cert_type: Option<String>,
+    pub cert_passphrase: Option<String>,
+    pub client_key: Option<String>,
+    pub key_type: Option<String>,
+    pub ca_cert: Option<String>,
+    pub insecure: bool,
+}
+
+impl TlsOptions {
+    fn is_empty(&self) -> bool {
+        *self == TlsOptions::default()
+    }
+}
+
+/// Retry settings from --retry/--retry-delay/--retry-all-errors
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RetryOptions {
+    pub count: Option<u32>,
+    /// Delay between attempts in seconds
+    pub delay: Option<u64>,
+    pub all_errors: bool,
+}
+
+impl Default for CurlCommand {
+    fn default() -> Self {
+        Self {
+            method: "GET".to_string(),
+            url: String::new(),
+            headers: Vec::new(),
+            body: None,
+            auth: None,
+            tls: TlsOptions::default(),
+            retry: RetryOptions::default(),
+            http_version: None,
+            http2_prior_knowledge: false,
+            cookie_jar: None,
+            metadata: Vec::new(),
+            flags: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+}
+
+impl CurlCommand {
+    /// First value of a header, matched case-insensitively
+    pub fn header(&self, name: &str) -> Option<&String> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v)
+    }
+
+    /// Add a header from a `-H` argument or a line of a header file
+    pub fn add_header_line(&mut self, line: &str) {
+        match parse_header(line) {
+            // "Name:" with nothing after it tells curl to drop one of its own headers
+            Some((name, value)) if value.is_empty() && line.contains(':') => {
+                self.notes.push(format!(
+                    "-H \"{}:\" only removes a curl default header and was skipped",
+                    name
+                ));
+            }
+            Some(header) => self.headers.push(header),
+            None => {}
+        }
+    }
+
+    /// Load headers from an `-H @file` argument, one header per line
+    fn add_header_file(&mut self, path: &str, base_dir: Option<&Path>) {
+        match read_file_arg(path, base_dir) {
+            Ok(content) => {
+                for line in content.lines().filter(|l| !l.trim().is_empty()) {
+                    self.add_header_line(line);
+                }
+            }
+            Err(e) => self.notes.push(format!(
+                "headers from {} were not imported: {}",
+                portable_path(path),
+                e
+            )),
+        }
+    }
+}
+
+/// Read a file named on the command line, relative to `base_dir`
+fn read_file_arg(path: &str, base_dir: Option<&Path>) -> Result<String, String> {
+    let resolved = match base_dir {
+        Some(dir) if Path::new(path).is_relative() => dir.join(path),
+        None if Path::new(path).is_relative() => {
+            return Err("no base directory to resolve it".to_string());
+        }
+        _ => Path::new(path).to_path_buf(),
+    };
+
+    std::fs::read_to_string(&resolved).map_err(|e| e.to_string())
+}
+
+/// Parse a Netscape cookie file and return the `name=value` pairs curl would
+/// send to `url`
+fn netscape_cookies_for(content: &str, url: &str) -> Vec<String> {
+    let Ok(url) = url::Url::parse(url) else {
+        return Vec::new();
+    };
+    let host = url.host_str().unwrap_or_default().to_lowercase();
+    let https = url.scheme() == "https";
+
+    content
+        .lines()
+        .filter_map(|line| {
+            // curl marks HttpOnly cookies with a prefix on an otherwise commented line
+            let line = line.strip_prefix("#HttpOnly_").unwrap_or(line);
+            if line.trim().is_empty() || line.starts_with('#') {
+                return None;
+            }
+
+            let fields: Vec<&str> = line.split('\t').collect();
+            let [domain, subdomains, path, secure, _expires, name, value] = fields[..] else {
+                return None;
+            };
+
+            let domain = domain.trim_start_matches('.').to_lowercase();
+            let domain_matches = host == domain
+                || (subdomains.eq_ignore_ascii_case("TRUE")
+                    && host.ends_with(&format!(".{}", domain)));
+            let path_matches = url.path().starts_with(path);
+            let scheme_matches = https || !secure.eq_ignore_ascii_case("TRUE");
+
+            (domain_matches && path_matches && scheme_matches)
+                .then(|| format!("{}={}", name, value.trim_end()))
+        })
+        .collect()
+}
+
+/// How curl treats the contents of a `@file` data argument
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DataMode {
+    /// `-d`/`--data`: newlines are stripped from the file
+    Ascii,
+    /// `--data-binary`: sent exactly as stored
+    Binary,
+    /// `--data-urlencode`: contents are URL-encoded
+    UrlEncode,
+}
+
+/// One `-d`/`--data-*` argument, kept in command-line order
+#[derive(Debug, Clone, PartialEq)]
+enum DataPart {
+    Text(String),
+    File {
+        name: Option<String>,
+        path: String,
+        mode: DataMode,
+    },
+    Stdin,
+}
+
+/// Interpret a data flag's argument the way curl does
+fn parse_data_arg(flag: &str, value: &str) -> DataPart {
+    let file_part = |name: Option<String>, path: &str, mode: DataMode| {
+        if path == "-" {
+            DataPart::Stdin
+        } else {
+            DataPart::File {
+                name,
+                path: path.to_string(),
+                mode,
+            }
+        }
+    };
+
+    match flag {
+        "--data-raw" => DataPart::Text(value.to_string()),
+        "--data-binary" => match value.strip_prefix('@') {
+            Some(path) => file_part(None, path, DataMode::Binary),
+            None => DataPart::Text(value.to_string()),
+        },
+        "--data-urlencode" => {
+            // curl looks at whichever of '=' or '@' comes first
+            match value.find(['=', '@']) {
+                Some(pos) if value.as_bytes()[pos] == b'=' => {
+                    let (name, content) = (&value[..pos], &value[pos + 1..]);
+                    let encoded = urlencoding::encode(content);
+                    if name.is_empty() {
+                        DataPart::Text(encoded.into_owned())
+                    } else {
+                        DataPart::Text(format!("{}={}", name, encoded))
+                    }
+                }
+                Some(pos) => {
+                    let name = (pos > 0).then(|| value[..pos].to_string());
+                    file_part(name, &value[pos + 1..], DataMode::UrlEncode)
+                }
+                None => DataPart::Text(urlencoding::encode(value).into_owned()),
+            }
+        }
+        _ => match value.strip_prefix('@') {
+            Some(path) => file_part(None, path, DataMode::Ascii),
+            None => DataPart::Text(value.to_string()),
+        },
+    }
+}
+
+/// Variable name used as a placeholder for file contents that can't be inlined
+fn placeholder_for(path: &str) -> String {
+    let stem = std::path::Path::new(path)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let name: String = stem
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect();
+    if name.is_empty() {
+        "file".to_string()
+    } else {
+        name
+    }
+}
+
+/// Build the request body from the collected data parts.
+///
+/// A lone file becomes a `< ./file` include; files mixed with other parts and
+/// stdin can't be expressed in .http syntax, so they turn into `{{variables}}`
+/// with a note explaining what to fill in.
+fn render_data(parts: &[DataPart], notes: &mut Vec<String>) -> Option<String> {
+    if let [DataPart::File {
+        name: None,
+        path,
+        mode,
+    }] = parts
+    {
+        let path = portable_path(path);
+        match mode {
+            DataMode::Ascii => notes.push(format!(
+                "curl strips newlines from files sent with -d; {} is sent as-is",
+                path
+            )),
+            DataMode::UrlEncode => notes.push(format!(
+                "curl URL-encodes {} for --data-urlencode; make sure its contents are encoded",
+                path
+            )),
+            DataMode::Binary => {}
+        }
+        return Some(format!("< {}", path));
+    }
+
+    let rendered: Vec<String> = parts
+        .iter()
+        .map(|part| match part {
+            DataPart::Text(text) => text.clone(),
+            DataPart::File { name, path, mode } => {
+                let var = name.clone().unwrap_or_else(|| placeholder_for(path));
+                let encoding = if *mode == DataMode::UrlEncode {
+                    "URL-encoded "
+                } else {
+                    ""
+                };
+                notes.push(format!(
+                    "set {{{{{}}}}} to the {}contents of {}",
+                    var,
+                    encoding,
+                    portable_path(path)
+                ));
+                match name {
+                    Some(name) => format!("{}={{{{{}}}}}", name, var),
+                    None => format!("{{{{{}}}}}", var),
+                }
+            }
+            DataPart::Stdin => {
+                notes.push(
+                    "request body was read from stdin (@-); replace {{stdin}} with the data"
+                        .to_string(),
+                );
+                "{{stdin}}".to_string()
+            }
+        })
+        .collect();
+
+    (!rendered.is_empty()).then(|| rendered.join("&"))
+}
+
+/// Split a pasted shell command into words, handling line continuations and
+/// quoting for whichever shell it was copied from
+pub fn shell_words(input: &str) -> Result<Vec<String>, String> {
+    let dialect = detect_dialect(input);
+    let normalized = normalize_curl_input(input, dialect);
+    tokenize(&normalized, dialect)
+}
+
+/// Parse a cURL command string into structured components.
+///
+/// `@file` references are resolved relative to `base_dir`.
+pub fn parse_curl(input: &str, base_dir: Option<&Path>) -> Result<CurlCommand, String> {
+    let tokens = expand_short_flags(shell_words(input)?);
+
+    if tokens.is_empty() {
+        return Err("Empty cURL command".to_string());
+    }
+
+    let mut cmd = CurlCommand::default();
+    let mut data_parts: Vec<DataPart> = Vec::new();
+    let mut cookie_args: Vec<String> = Vec::new();
+    let mut i = 0;
+
+    while i < tokens.len() {
+        let token = &tokens[i];
+
+        match token.as_str() {
+            "curl" | "curl.exe" => {} // Skip command name
+            "-X" | "--request" => {
+                i += 1;
+                if i < tokens.len() {
+                    cmd.method = tokens[i].to_uppercase();
+                }
+            }
+            "-H" | "--header" => {
+                i += 1;
+                if i < tokens.len() {
+                    match tokens[i].strip_prefix('@') {
+                        Some(path) => cmd.add_header_file(path, base_dir),
+                        None => cmd.add_header_line(&tokens[i]),
+                    }
+                }
+            }
+            "-d" | "--data" | "--data-ascii" | "--data-raw" | "--data-binary"
+            | "--data-urlencode" => {
+                i += 1;
+                if i < tokens.len() {
+                    // curl joins repeated data flags with '&' in order
+                    data_parts.push(parse_data_arg(token, &tokens[i]));
+                    // Default to POST if no method specified
+                    if cmd.method == "GET" {
+                        cmd.method = "POST".to_string();
+                    }
+                }
+            }
+            "-u" | "--user" => {
+                i += 1;
+                if i < tokens.len() {
+                    let parts: Vec<_> = tokens[i].splitn(2, ':').collect();
+                    if parts.len() == 2 {
+                        cmd.auth = Some((parts[0].to_string(), parts[1].to_string()));
+                    } else if !parts.is_empty() {
+                        // Password might be empty
+                        cmd.auth = Some((parts[0].to_string(), String::new()));
+                    }
+                }
+            }
+            "-A" | "--user-agent" => {
+                i += 1;
+                if i < tokens.len() {
+                    cmd.headers
+                        .push(("User-Agent".to_string(), tokens[i].clone()));
+                }
+            }
+            "-b" | "--cookie" => {
+                i += 1;
+                if i < tokens.len() {
+                    // Resolved after the loop, once the URL is known
+                    cookie_args.push(tokens[i].clone());
+                }
+            }
+            "-c" | "--cookie-jar" => {
+                i += 1;
+                if i < tokens.len() {
+                    cmd.cookie_jar = Some(tokens[i].clone());
+                }
+            }
+            "-e" | "--referer" => {
+                i += 1;
+                if i < tokens.len() {
+                    cmd.headers.push(("Referer".to_string(), tokens[i].clone()));
+                }
+            }
+            "-L" | "--location" => {
+                cmd.flags.push("follow-redirects".to_string());
+            }
+            "-k" | "--insecure" => {
+                cmd.tls.insecure = true;
+            }
+            "-E" | "--cert" => {
+                i += 1;
+                if i < tokens.len() {
+                    let (path, passphrase) = split_cert_passphrase(&tokens[i]);
+                    cmd.tls.client_cert = Some(path);
+                    if passphrase.is_some() {
+                        cmd.tls.cert_passphrase = passphrase;
+                    }
+                }
+            }
+            "--cert-type" => {
+                i += 1;
+                if i < tokens.len() {
+                    cmd.tls.cert_type = Some(tokens[i].to_uppercase());
+                }
+            }
+            "--key" => {
+                i += 1;
+                if i < tokens.len() {
+                    cmd.tls.client_key = Some(tokens[i].clone());
+                }
+            }
+            "--key-type" => {
+                i += 1;
+                if i < tokens.len() {
+                    cmd.tls.key_type = Some(tokens[i].to_uppercase());
+                }
+            }
+            "--pass" => {
+                i += 1;
+                if i < tokens.len() {
+                    cmd.tls.cert_passphrase = Some(tokens[i].clone());
+                }
+            }
+            "--cacert" => {
+                i += 1;
+                if i < tokens.len() {
+                    cmd.tls.ca_cert = Some(tokens[i].clone());
+                }
+            }
+            "--http1.0" | "-0" => cmd.http_version = Some("HTTP/1.0".to_string()),
+            "--http1.1" => cmd.http_version = Some("HTTP/1.1".to_string()),
+            "--http2" => cmd.http_version = Some("HTTP/2".to_string()),
+            "--http2-prior-knowledge" => {
+                cmd.http_version = Some("HTTP/2".to_string());
+                cmd.http2_prior_knowledge = true;
+            }
+            "--http3" | "--http3-only" => cmd.http_version = Some("HTTP/3".to_string()),
+            "--retry" => {
+                i += 1;
+                if i < tokens.len() {
+                    match tokens[i].parse::<u32>() {
+                        Ok(count) => cmd.retry.count = Some(count),
+                        Err(_) => cmd.notes.push(format!(
+                            "--retry {} is not a non-negative integer and was ignored",
+                            tokens[i]
+                        )),
+                    }
+                }
+            }
+            "--retry-delay" => {
+                i += 1;
+                if i < tokens.len() {
+                    match parse_seconds(&tokens[i]) {
+                        Some(delay) => cmd.retry.delay = Some(delay),
+                        None => cmd.notes.push(format!(
+                            "--retry-delay {} is not a valid number of seconds and was ignored",
+                            tokens[i]
+                        )),
+                    }
+                }
+            }
+            "--retry-all-errors" => {
+                cmd.retry.all_errors = true;
+            }
+            "--compressed" => {
+                cmd.flags.push("compressed".to_string());
+                // Add Accept-Encoding if not present
+                if cmd.header("Accept-Encoding").is_none() {
+                    cmd.headers
+                        .push(("Accept-Encoding".to_string(), "gzip, deflate".to_string()));
+                }
+            }
+            flag if IGNORED_FLAGS.contains(&flag) => {
+                // Only affects curl's own console output
+            }
+            "-o" | "--output" => {
+                // Skip output file argument
+                i += 1;
+            }
+            _ if !token.starts_with('-') && cmd.url.is_empty() => {
+                cmd.url = token.clone();
+            }
+            _ if token.starts_with("http://") || token.starts_with("https://") => {
+                // URL might come before options
+                cmd.url = token.clone();
+            }
+            flag if FLAGS_WITH_ARGUMENT.contains(&flag) => {
+                i += 1;
+                match tokens.get(i) {
+                    Some(arg) => cmd
+                        .notes
+                        .push(format!("{} {} is not supported and was skipped", flag, arg)),
+                    None => cmd
+                        .notes
+                        .push(format!("{} is not supported and was skipped", flag)),
+                }
+            }
+            flag => {
+                cmd.notes
+                    .push(format!("{} is not supported and was skipped", flag));
+            }
+        }
+
+        i += 1;
+    }
+
+    if cmd.url.is_empty() {
+        return Err("No URL found in cURL command".to_string());
+    }
+
+    cmd.body = render_data(&data_parts, &mut cmd.notes);
+
+    // An argument without '=' is a cookie file rather than literal cookies
+    let mut cookies: Vec<String> = Vec::new();
+    for arg in &cookie_args {
+        if arg.contains('=') {
+            cookies.push(arg.clone());
+            continue;
+        }
+        match read_file_arg(arg, base_dir) {
+            Ok(content) => cookies.extend(netscape_cookies_for(&content, &cmd.url)),
+            Err(e) => cmd.notes.push(format!(
+                "cookies from {} were not imported: {}",
+                portable_path(arg),
+                e
+            )),
+        }
+    }
+    if !cookies.is_empty() {
+        cmd.headers.push(("Cookie".to_string(), cookies.join("; ")));
+    }
+
+    Ok(cmd)
+}
+
+/// Flags that only change curl's terminal output and are safe to drop
+const IGNORED_FLAGS: &[&str] = &[
+    "-v",
+    "--verbose",
+    "-s",
+    "--silent",
+    "-S",
+    "--show-error",
+    "-i",
+    "--include",
+    "-#",
+    "--progress-bar",
+    "--no-progress-meter",
+];
+
+/// Unsupported curl options that consume the following token as their value
+const FLAGS_WITH_ARGUMENT: &[&str] = &[
+    "--ciphers",
+    "--connect-timeout",
+    "--connect-to",
+    "--curves",
+    "--dns-servers",
+    "-D",
+    "--dump-header",
+    "--interface",
+    "-K",
+    "--config",
+    "--limit-rate",
+    "--local-port",
+    "-m",
+    "--max-time",
+    "--max-redirs",
+    "--noproxy",
+    "--pinnedpubkey",
+    "--proxy-header",
+    "-r",
+    "--range",
+    "--resolve",
+    "--stderr",
+    "--tls-max",
+    "--trace",
+    "--trace-ascii",
+    "-U",
+    "--proxy-user",
+    "--unix-socket",
+    "-w",
+    "--write-out",
+    "-x",
+    "--proxy",
+    "-z",
+    "--time-cond",
+];
+
+/// Split bundled short flags such as `-sSL` into `-s -S -L`.
+///
+/// Only flags that take no argument are split, so `-XPOST` is left alone.
+fn expand_short_flags(tokens: Vec<String>) -> Vec<String> {
+    const BUNDLEABLE: &str = "sSLkvi#";
+
+    tokens
+        .into_iter()
+        .flat_map(|token| match token.strip_prefix('-') {
+            Some(flags) if flags.len() > 1 && flags.chars().all(|c| BUNDLEABLE.contains(c)) => {
+                flags.chars().map(|c| format!("-{}", c)).collect()
+            }
+            _ => vec![token],
+        })
+        .collect()
+}
+
+/// Parse a delay given in whole seconds, optionally suffixed with `s`
+pub fn parse_seconds(value: &str) -> Option<u64> {
+    value.strip_suffix('s').unwrap_or(value).parse().ok()
+}
+
+/// Shell a command was copied from, which decides continuation and quoting rules
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ShellDialect {
+    /// bash/zsh: `\` continuations, single quotes are literal
+    Posix,
+    /// Windows cmd.exe: `^` continuations and `^` escapes, only double quotes
+    Cmd,
+    /// PowerShell: backtick continuations and backtick escapes
+    PowerShell,
+}
+
+impl ShellDialect {
+    fn continuation(self) -> char {
+        match self {
+            ShellDialect::Posix => '\\',
+            ShellDialect::Cmd => '^',
+            ShellDialect::PowerShell => '`',
+        }
+    }
+}
+
+/// Guess the shell dialect from continuation characters and escape sequences
+fn detect_dialect(input: &str) -> ShellDialect {
+    let line_ends_with = |c: char| input.lines().any(|l| l.trim_end().ends_with(c));
+
+    if line_ends_with('^') || input.contains("^\"") {
+        ShellDialect::Cmd
+    } else if line_ends_with('`')
+        || input.contains("`\"")
+        || input.trim_start().to_lowercase().starts_with("curl.exe")
+    {
+        ShellDialect::PowerShell
+    } else {
+        ShellDialect::Posix
+    }
+}
+
+/// Normalize cURL input by joining continuation lines for the given dialect.
+///
+/// Only a continuation character at the very end of a line is stripped, so
+/// literal backslashes, carets and backticks elsewhere survive. For cmd.exe
+/// the remaining `^` escapes are removed as well.
+fn normalize_curl_input(input: &str, dialect: ShellDialect) -> String {
+    let continuation = dialect.continuation();
+    let lines: Vec<&str> = input.lines().collect();
+    let mut output = String::new();
+    let mut idx = 0;
+
+    while idx < lines.len() {
+        let line = lines[idx].trim_end();
+        match line.strip_suffix(continuation) {
+            // cmd encodes a newline inside a quoted value as `^` + blank line
+            Some(rest)
+                if dialect == ShellDialect::Cmd
+                    && lines.get(idx + 1).is_some_and(|l| l.trim().is_empty()) =>
+            {
+                output.push_str(rest);
+                output.push('\n');
+                idx += 1;
+            }
+            Some(rest) => {
+                output.push_str(rest);
+                output.push(' ');
+            }
+            None => {
+                output.push_str(line);
+                output.push('\n');
+            }
+        }
+        idx += 1;
+    }
+
+    if dialect == ShellDialect::Cmd {
+        output = unescape_cmd_carets(&output);
+    }
+
+    output.trim().to_string()
+}
+
+/// Remove cmd.exe caret escapes (`^"` -> `"`, `^^` -> `^`)
+fn unescape_cmd_carets(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut chars = input.chars();
+    while let Some(ch) = chars.next() {
+        if ch == '^' {
+            if let Some(next) = chars.next() {
+                output.push(next);
+            }
+        } else {
+            output.push(ch);
+        }
+    }
+    output
+}
+
+/// Tokenize the cURL command respecting the quoting rules of its shell
+fn tokenize(input: &str, dialect: ShellDialect) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    // Tracks whether a (possibly empty) quoted argument was started
+    let mut has_token = false;
+    let mut chars = input.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            c if c.is_whitespace() => {
+                if has_token {
+                    tokens.push(std::mem::take(&mut current));
+                    has_token = false;
+                }
+            }
+            '\'' if dialect != ShellDialect::Cmd => {
+                has_token = true;
+                loop {
+                    match chars.next() {
+                        // PowerShell escapes a single quote by doubling it
+                        Some('\'')
+                            if dialect == ShellDialect::PowerShell
+                                && chars.peek() == Some(&'\'') =>
+                        {
+                            chars.next();
+                            current.push('\'');
+                        }
+                        Some('\'') => break,
+                        Some(c) => current.push(c),
+                        None => return Err("Unclosed quote in cURL command".to_string()),
+                    }
+                }
+            }
+            '"' => {
+                has_token = true;
+                loop {
+                    match chars.next() {
+                        Some('"')
+                            if dialect == ShellDialect::PowerShell
+                                && chars.peek() == Some(&'"') =>
+                        {
+                            chars.next();
+                            current.push('"');
+                        }
+                        Some('"') => break,
+                        Some('`') if dialect == ShellDialect::PowerShell => match chars.next() {
+                            Some('n') => current.push('\n'),
+                            Some('t') => current.push('\t'),
+                            Some('r') => current.push('\r'),
+                            Some(c) => current.push(c),
+                            None => return Err("Unclosed quote in cURL command".to_string()),
+                        },
+                        Some('\\') if dialect != ShellDialect::PowerShell => {
+                            let escapable: &[char] = match dialect {
+                                ShellDialect::Posix => &['"', '\\', '$', '`'],
+                                _ => &['"', '\\'],
+                            };
+                            match chars.peek() {
+                                Some(c) if escapable.contains(c) => {
+                                    current.push(*c);
+                                    chars.next();
+                                }
+                                _ => current.push('\\'),
+                            }
+                        }
+                        Some(c) => current.push(c),
+                        None => return Err("Unclosed quote in cURL command".to_string()),
+                    }
+                }
+            }
+            '$' if dialect == ShellDialect::Posix && chars.peek() == Some(&'\'') => {
+                chars.next();
+                has_token = true;
+                current.push_str(&read_ansi_c_quoted(&mut chars)?);
+            }
+            '\\' if dialect == ShellDialect::Posix => {
+                has_token = true;
+                if let Some(c) = chars.next() {
+                    current.push(c);
+                }
+            }
+            '`' if dialect == ShellDialect::PowerShell => {
+                has_token = true;
+                if let Some(c) = chars.next() {
+                    current.push(c);
+                }
+            }
+            c => {
+                has_token = true;
+                current.push(c);
+            }
+        }
+    }
+
+    if has_token {
+        tokens.push(current);
+    }
+
+    Ok(tokens)
+}
+
+/// Read the body of a bash ANSI-C quoted string (`$'...'`) up to the closing quote.
+///
+/// Escapes are decoded into bytes first so `\xHH` sequences forming UTF-8 and
+/// `\uXXXX` surrogate pairs (as emitted by Chrome) come out as real characters.
+fn read_ansi_c_quoted(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<String, String> {
+    let mut bytes: Vec<u8> = Vec::new();
+    let push_char = |bytes: &mut Vec<u8>, c: char| {
+        let mut buf = [0u8; 4];
+        bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+    };
+    let read_hex = |chars: &mut std::iter::Peekable<std::str::Chars>, max: usize| {
+        let mut value = 0u32;
+        let mut digits = 0;
+        while digits < max {
+            match chars.peek().and_then(|c| c.to_digit(16)) {
+                Some(d) => {
+                    value = value * 16 + d;
+                    chars.next();
+                    digits += 1;
+                }
+                None => break,
+            }
+        }
+        (digits > 0).then_some(value)
+    };
+
+    loop {
+        let ch = chars
+            .next()
+            .ok_or_else(|| "Unclosed quote in cURL command".to_string())?;
+        match ch {
+            '\'' => break,
+            '\\' => {
+                let escaped = chars
+                    .next()
+                    .ok_or_else(|| "Unclosed quote in cURL command".to_string())?;
+                match escaped {
+                    'n' => bytes.push(b'\n'),
+                    't' => bytes.push(b'\t'),
+                    'r' => bytes.push(b'\r'),
+                    'a' => bytes.push(0x07),
+                    'b' => bytes.push(0x08),
+                    'e' | 'E' => bytes.push(0x1b),
+                    'f' => bytes.push(0x0c),
+                    'v' => bytes.push(0x0b),
+                    'x' => match read_hex(chars, 2) {
+                        Some(byte) => bytes.push(byte as u8),
+                        None => bytes.extend_from_slice(b"\\x"),
+                    },
+                    'u' | 'U' => {
+                        let max = if escaped == 'u' { 4 } else { 8 };
+                        let Some(mut code) = read_hex(chars, max) else {
+                            bytes.push(b'\\');
+                            push_char(&mut bytes, escaped);
+                            continue;
+                        };
+                        // Combine a UTF-16 surrogate pair written as two \u escapes
+                        if (0xD800..0xDC00).contains(&code) {
+                            let mut lookahead = chars.clone();
+                            if lookahead.next() == Some('\\') && lookahead.next() == Some('u') {
+                                if let Some(low) = read_hex(&mut lookahead, 4) {
+                                    if (0xDC00..0xE000).contains(&low) {
+                                        code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                                        *chars = lookahead;
+                                    }
+                                }
+                            }
+                        }
+                        push_char(&mut bytes, char::from_u32(code).unwrap_or('\u{FFFD}'));
+                    }
+                    '0'..='7' => {
+                        let mut value = escaped.to_digit(8).unwrap_or(0);
+                        for _ in 0..2 {
+                            match chars.peek().and_then(|c| c.to_digit(8)) {
+                                Some(d) => {
+                                    value = value * 8 + d;
+                                    chars.next();
+                                }
+                                None => break,
+                            }
+                        }
+                        bytes.push(value as u8);
+                    }
+                    '\\' | '\'' | '"' | '?' => push_char(&mut bytes, escaped),
+                    other => {
+                        bytes.push(b'\\');
+                        push_char(&mut bytes, other);
+                    }
+                }
+            }
+            c => push_char(&mut bytes, c),
+        }
+    }
+
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Parse a header string like "Content-Type: application/json".
+///
+/// `X-Custom;` is curl's syntax for sending a header with an empty value.
+fn parse_header(header: &str) -> Option<(String, String)> {
+    if let Some((name, value)) = header.split_once(':') {
+        return Some((name.trim().to_string(), value.trim().to_string()));
+    }
+    let name = header.trim().strip_suffix(';')?.trim();
+    (!name.is_empty() && !name.contains(char::is_whitespace))
+        .then(|| (name.to_string(), String::new()))
+}
+
+/// Split a `--cert` argument into the certificate path and optional passphrase.
+///
+/// curl uses the first unescaped colon as the separator (`file.p12:secret`),
+/// except for the colon of a Windows drive letter (`C:\certs\client.pem`).
+fn split_cert_passphrase(arg: &str) -> (String, Option<String>) {
+    let chars: Vec<char> = arg.chars().collect();
+    let mut path = String::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let ch = chars[i];
+        if ch == '\\' && chars.get(i + 1) == Some(&':') {
+            path.push(':');
+            i += 2;
+            continue;
+        }
+        let is_drive_letter = i == 1
+            && chars[0].is_ascii_alphabetic()
+            && matches!(chars.get(2), Some('\\') | Some('/'));
+        if ch == ':' && !is_drive_letter {
+            let passphrase: String = chars[i + 1..].iter().collect();
+            return (path, Some(passphrase));
+        }
+        path.push(ch);
+        i += 1;
+    }
+
+    (path, None)
+}
+
+/// Keep relative paths relative but make them explicit so the .http file stays portable
+pub fn portable_path(path: &str) -> String {
+    let is_explicit = path.starts_with('/')
+        || path.starts_with('~')
+        || path.starts_with("./")
+        || path.starts_with("../")
+        || path.starts_with('\\')
+        || (path.len() > 2 && path.as_bytes()[1] == b':');
+    if is_explicit {
+        path.to_string()
+    } else {
+        format!("./{}", path)
+    }
+}
+
+/// Emit TLS metadata lines, with notes that sending doesn't use them yet.
+/// A passphrase becomes `{{certPassphrase}}` rather than landing in the file.
+fn tls_metadata(tls: &TlsOptions) -> String {
+    let mut output = String::new();
+    if tls.is_empty() {
+        return output;
+    }
+
+    if tls.client_cert.is_some() || tls.client_key.is_some() {
+        output.push_str("# Note: client certificates are not supported yet and won't be sent\n");
+    }
+    if tls.cert_passphrase.is_some() {
+        output.push_str(
+            "# Note: set certPassphrase in http-client.private.env.json to the certificate passphrase\n",
+        );
+    }
+    if tls.ca_cert.is_some() {
+        output.push_str(
+            "# Note: custom CA certificates are not supported yet; the system ones are used\n",
+        );
+    }
+    if tls.insecure {
+        output.push_str(
+            "# Note: certificate checks were turned off, but kvile still verifies certificates\n",
+        );
+    }
+
+    if let Some(cert) = &tls.client_cert {
+        output.push_str(&format!("# @client-cert {}\n", portable_path(cert)));
+    }
+    if let Some(cert_type) = &tls.cert_type {
+        output.push_str(&format!("# @cert-type {}\n", cert_type));
+    }
+    if tls.cert_passphrase.is_some() {
+        output.push_str("# @cert-passphrase {{certPassphrase}}\n");
+    }
+    if let Some(key) = &tls.client_key {
+        output.push_str(&format!("# @client-key {}\n", portable_path(key)));
+    }
+    if let Some(key_type) = &tls.key_type {
+        output.push_str(&format!("# @key-type {}\n", key_type));
+    }
+    if let Some(ca) = &tls.ca_cert {
+        output.push_str(&format!("# @ca-cert {}\n", portable_path(ca)));
+    }
+    if tls.insecure {
+        output.push_str("# @insecure true\n");
+    }
+
+    output
+}
+
+/// Render retry settings as `# @retry` metadata lines
+fn retry_metadata(retry: &RetryOptions) -> String {
+    let mut output = String::new();
+
+    if let Some(count) = retry.count {
+        output.push_str(&format!("# @retry {}\n", count));
+    }
+    if let Some(delay) = retry.delay {
+        output.push_str(&format!("# @retry-delay {}s\n", delay));
+    }
+    if retry.all_errors {
+        // Without this curl only retries transient errors, never a
+        // failed non-idempotent request
+        output.push_str("# Note: --retry-all-errors also retries non-idempotent failures\n");
+        output.push_str("# @retry-all-errors true\n");
+    }
+    if !output.is_empty() {
+        output.insert_str(
+            0,
+            "# Note: retries are not supported yet; the request is sent once\n",
+        );
+    }
+
+    output
+}
+
+/// Check whether a body is `key=value&key=value` form data
+fn looks_like_form_data(body: &str) -> bool {
+    !body.is_empty()
+        && !body.contains(char::is_whitespace)
+        && body.split('&').all(|pair| {
+            pair.split_once('=')
+                .is_some_and(|(key, _)| !key.is_empty() && !key.starts_with(['{', '[', '"']))
+        })
+}
+
+/// Convert a parsed cURL command to HTTP file format
+pub fn curl_to_http(cmd: &CurlCommand) -> String {
+    let mut output = String::new();
+
+    // Comments go above the request line; anything after the body would be
+    // read back as part of it
+    for note in &cmd.notes {
+        output.push_str(&format!("# Note: {}\n", note));
+    }
+    for flag in &cmd.flags {
+        output.push_str(&format!("# Note: {} flag was set in cURL\n", flag));
+    }
+
+    // TLS metadata must precede the request line
+    output.push_str(&tls_metadata(&cmd.tls));
+    output.push_str(&retry_metadata(&cmd.retry));
+    if cmd.http2_prior_knowledge {
+        output.push_str("# @http2-prior-knowledge true\n");
+    }
+    if let Some(jar) = &cmd.cookie_jar {
+        // curl -c saves response cookies; keep that intent on the request
+        output.push_str(&format!("# @cookie-jar {}\n", portable_path(jar)));
+    }
+    for (key, value) in &cmd.metadata {
+        output.push_str(&format!("# @{} {}\n", key, value));
+    }
+
+    // Method, URL and optional protocol version
+    match &cmd.http_version {
+        Some(version) => output.push_str(&format!("{} {} {}\n", cmd.method, cmd.url, version)),
+        None => output.push_str(&format!("{} {}\n", cmd.method, cmd.url)),
+    }
+
+    // Auth header if present
+    if let Some((user, pass)) = &cmd.auth {
+        let credentials = format!("{}:{}", user, pass);
+        let encoded = STANDARD.encode(credentials.as_bytes());
+        output.push_str(&format!("Authorization: Basic {}\n", encoded));
+    }
+
+    // Headers, in the order and multiplicity they were given
+    let mut headers: Vec<(&String, &String)> = cmd.headers.iter().map(|(k, v)| (k, v)).collect();
+
+    // curl sends -d data as a form unless told otherwise
+    let form_content_type = (
+        "Content-Type".to_string(),
+        "application/x-www-form-urlencoded".to_string(),
+    );
+    let has_content_type = cmd.header("Content-Type").is_some();
+    if !has_content_type && cmd.body.as_deref().is_some_and(looks_like_form_data) {
+        headers.push((&form_content_type.0, &form_content_type.1));
+    }
+    for (key, value) in headers {
+        output.push_str(&format!("{}: {}\n", key, value));
+    }
+
+    // Body
+    if let Some(body) = &cmd.body {
+        output.push('\n');
+        // Try to format JSON body
+        if let Ok(json) = serde_json::from_str::<serde_json::Value>(body) {
+            if let Ok(formatted) = serde_json::to_string_pretty(&json) {
+                output.push_str(&formatted);
+            } else {
+                output.push_str(body);
+            }
+        } else {
+            output.push_str(body);
+        }
+        output.push('\n');
+    }
+
+    output
+}
+
+/// Parse and convert a single cURL command, collecting import warnings
+pub fn import_curl(input: &str, base_dir: Option<&Path>) -> Result<ImportResult, String> {
+    let cmd = parse_curl(input, base_dir)?;
+    Ok(ImportResult {
+        http: curl_to_http(&cmd),
+        warnings: cmd.notes,
+    })
+}
+
+/// Strip leading whitespace and a shell prompt (`$ ` or `> `) from a line
+fn strip_prompt(line: &str) -> &str {
+    let line = line.trim_start();
+    line.strip_prefix("$ ")
+        .or_else(|| line.strip_prefix("> "))
+        .unwrap_or(line)
+        .trim_start()
+}
+
+/// Check whether a line starts a curl invocation
+fn starts_curl_command(line: &str) -> bool {
+    let lower = strip_prompt(line).to_lowercase();
+    lower == "curl"
+        || lower.starts_with("curl ")
+        || lower == "curl.exe"
+        || lower.starts_with("curl.exe ")
+}
+
+/// Split text containing several curl invocations (a shell script, a chat
+/// message) into `(line_number, command)` pairs.
+///
+/// A command continues while its lines end with a continuation character or a
+/// quoted argument is still open; anything between commands is ignored.
+pub fn split_curl_commands(input: &str) -> Vec<(usize, String)> {
+    let mut commands: Vec<(usize, String)> = Vec::new();
+    let mut current: Option<(usize, Vec<&str>)> = None;
+
+    for (idx, line) in input.lines().enumerate() {
+        if let Some((_, lines)) = current.as_mut() {
+            let block = lines.join("\n");
+            let dialect = detect_dialect(&block);
+            let continued = lines
+                .last()
+                .is_some_and(|l| l.trim_end().ends_with(dialect.continuation()));
+            let open_quote = tokenize(&normalize_curl_input(&block, dialect), dialect).is_err();
+
+            if continued || (open_quote && !starts_curl_command(line)) {
+                lines.push(line);
+                continue;
+            }
+            if let Some((start, lines)) = current.take() {
+                commands.push((start, lines.join("\n")));
+            }
+        }
+
+        if starts_curl_command(line) {
+            current = Some((idx + 1, vec![strip_prompt(line)]));
+        }
+    }
+
+    if let Some((start, lines)) = current {
+        commands.push((start, lines.join("\n")));
+    }
+
+    commands
+}
+
+/// Derive a request name from the URL path, falling back to the host
+fn request_name_from_url(url: &str) -> String {
+    let without_scheme = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+    let (host, path) = match without_scheme.find('/') {
+        Some(pos) => without_scheme.split_at(pos),
+        None => (without_scheme, ""),
+    };
+    let path = path.split(['?', '#']).next().unwrap_or("");
+
+    if path.is_empty() || path == "/" {
+        host.to_string()
+    } else {
+        path.to_string()
+    }
+}
+
+/// Convert every curl command found in the input into one .http document.
+///
+/// Commands that fail to parse are kept as a commented-out block with the
+/// error so nothing is silently lost.
+pub fn curls_to_http(input: &str, base_dir: Option<&Path>) -> String {
+    let blocks: Vec<String> = split_curl_commands(input)
+        .into_iter()
+        .map(|(line, command)| match parse_curl(&command, base_dir) {
+            Ok(cmd) => format!(
+                "### {}\n{}",
+                request_name_from_url(&cmd.url),
+                curl_to_http(&cmd)
+            ),
+            Err(e) => {
+                let mut block = format!("### Failed to import curl command (line {})\n", line);
+                block.push_str(&format!("# Error: {}\n", e));
+                for command_line in command.lines() {
+                    block.push_str(&format!("# {}\n", command_line));
+                }
+                block
+            }
+        })
+        .collect();
+
+    blocks.join("\n")
+}
+
+/// Marker for a shell variable reference inside an otherwise literal value
+fn shell_env_ref(name: &str) -> String {
+    format!("\0{}\0", name)
+}
+
+/// Shell-safe variable name for a kvile variable, e.g. `api-token` -> `API_TOKEN`
+fn shell_var_name(name: &str) -> String {
+    let mut var: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if var.starts_with(|c: char| c.is_ascii_digit()) {
+        var.insert(0, '_');
+    }
+    var
+}
+
+/// Quote a value for POSIX shells; `shell_env_ref` markers become `"${NAME}"`
+fn shell_quote(value: &str) -> String {
+    let quoted: String = value
+        .split('\0')
+        .enumerate()
+        .filter(|(_, part)| !part.is_empty())
+        .map(|(i, part)| {
+            if i % 2 == 1 {
+                format!("\"${{{}}}\"", part)
+            } else {
+                format!("'{}'", part.replace('\'', "'\\''"))
+            }
+        })
+        .collect();
+
+    if quoted.is_empty() {
+        "''".to_string()
+    } else {
+        quoted
+    }
+}
+
+/// curl arguments (already shell-quoted) that reproduce a parsed request
+fn curl_args(request: &ParsedRequest) -> Vec<String> {
+    let mut args = Vec::new();
+
+    // Method and URL share the first line
+    let url = shell_quote(&request.url);
+    match request.method.as_str() {
+        "GET" if request.body.is_none() => args.push(url),
+        // -X HEAD makes curl wait for a body that never comes
+        "HEAD" => args.push(format!("--head {}", url)),
+        method => args.push(format!("-X {} {}", method, url)),
+    }
+
+    match request.http_version.as_deref() {
+        Some("HTTP/1.0") => args.push("--http1.0".to_string()),
+        Some("HTTP/1.1") => args.push("--http1.1".to_string()),
+        Some("HTTP/2") if request.metadata.contains_key("http2-prior-knowledge") => {
+            args.push("--http2-prior-knowledge".to_string())
+        }
+        Some("HTTP/2") => args.push("--http2".to_string()),
+        Some("HTTP/3") => args.push("--http3".to_string()),
+        _ => {}
+    }
+    if request
+        .metadata
+        .get("insecure")
+        .is_some_and(|v| v == "true")
+    {
+        args.push("--insecure".to_string());
+    }
+
+    let mut headers: Vec<_> = request.headers.iter().collect();
+    headers.sort_by_key(|(k, _)| k.to_lowercase());
+    for (key, value) in headers {
+        args.push(format!(
+            "-H {}",
+            shell_quote(&format!("{}: {}", key, value))
+        ));
+    }
+
+    if let Some(body) = &request.body {
+        // A lone `< ./file` line is a JetBrains file include
+        match body
+            .trim()
+            .strip_prefix("< ")
+            .filter(|path| !path.contains('\n'))
+        {
+            Some(path) => args.push(format!(
+                "--data-binary {}",
+                shell_quote(&format!("@{}", path.trim()))
+            )),
+            None => args.push(format!("--data-raw {}", shell_quote(body))),
+        }
+    }
+
+    args
+}
+
+/// Render a parsed request as a multi-line curl command
+pub fn http_to_curl(request: &ParsedRequest) -> String {
+    format!("curl {}", curl_args(request).join(" \\\n  "))
+}
+
+/// Build a bash script running every request with curl.
+///
+/// Variables are substituted from `vars`; values of `secrets` are emitted as
+/// `$VAR` references when `secrets_as_env` is set.
+pub fn requests_to_script(
+    requests: &[ParsedRequest],
+    vars: &HashMap<String, String>,
+    secrets: &[String],
+    secrets_as_env: bool,
+) -> String {
+    let mut vars = vars.clone();
+    let mut required_env: Vec<String> = Vec::new();
+    if secrets_as_env {
+        for name in secrets {
+            let var = shell_var_name(name);
+            vars.insert(name.clone(), shell_env_ref(&var));
+            required_env.push(var);
+        }
+        required_env.sort();
+    }
+
+    let mut output = String::from("#!/usr/bin/env bash\nset -euo pipefail\n");
+    if !required_env.is_empty() {
+        output.push('\n');
+        for var in &required_env {
+            output.push_str(&format!(": \"${{{0}:?{0} must be set}}\"\n", var));
+        }
+    }
+
+    for request in requests {
+        let resolved = substitute_request(request, &vars);
+        let title = request
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("{} {}", request.method, request.url));
+
+        output.push_str(&format!("\n# {}\n", title));
+        if request.pre_script.is_some() || request.post_script.is_some() {
+            output.push_str("# Warning: request scripts and assertions were not translated\n");
+        }
+
+        let curl = http_to_curl(&resolved);
+        output.push_str(&curl.replacen("curl ", "curl --fail-with-body ", 1));
+        output.push('\n');
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_curl(input: &str) -> Result<CurlCommand, String> {
+        super::parse_curl(input, None)
+    }
+
+    #[test]
+    fn test_simple_get() {
+        let curl = "curl https://api.example.com/users";
+        let cmd = parse_curl(curl).unwrap();
+        assert_eq!(cmd.method, "GET");
+        assert_eq!(cmd.url, "https://api.example.com/users");
+    }
+
+    #[test]
+    fn test_post_with_data() {
+        let curl = r#"curl -X POST https://api.example.com/users -d '{"name":"test"}'"#;
+        let cmd = parse_curl(curl).unwrap();
+        assert_eq!(cmd.method, "POST");
+        assert_eq!(cmd.body, Some(r#"{"name":"test"}"#.to_string()));
+    }
+
+    #[test]
+    fn test_headers() {
+        let curl = r#"curl -H "Content-Type: application/json" -H "Accept: application/json" https://api.example.com"#;
+        let cmd = parse_curl(curl).unwrap();
+        assert_eq!(
+            cmd.header("Content-Type"),
+            Some(&"application/json".to_string())
+        );
+        assert_eq!(cmd.header("Accept"), Some(&"application/json".to_string()));
+    }
+
+    #[test]
+    fn test_basic_auth() {
+        let curl = "curl -u user:password https://api.example.com";
+        let cmd = parse_curl(curl).unwrap();
+        assert_eq!(cmd.auth, Some(("user".to_string(), "password".to_string())));
+    }
+
+    #[test]
+    fn test_multiline() {
+        let curl = r#"curl \
+          -X POST \
+          -H "Content-Type: application/json" \
+          https://api.example.com/users"#;
+        let cmd = parse_curl(curl).unwrap();
+        assert_eq!(cmd.method, "POST");
+        assert_eq!(cmd.url, "https://api.example.com/users");
+    }
+
+    #[test]
+    fn test_convert_to_http() {
+        let cmd = CurlCommand {
+            method: "POST".to_string(),
+            url: "https://api.example.com/users".to_string(),
+            headers: [("Content-Type".to_string(), "application/json".to_string())]
+                .into_iter()
+                .collect(),
+            body: Some(r#"{"name":"test"}"#.to_string()),
+            auth: None,
+            tls: TlsOptions::default(),
+            retry: RetryOptions::default(),
+            http_version: None,
+            http2_prior_knowledge: false,
+            cookie_jar: None,
+            metadata: Vec::new(),
+            flags: vec![],
+            notes: vec![],
+        };
+
+        let http = curl_to_http(&cmd);
+        assert!(http.contains("POST https://api.example.com/users"));
+        assert!(http.contains("Content-Type: application/json"));
+        assert!(http.contains(r#""name": "test""#)); // Formatted JSON
+    }
+
+    #[test]
+    fn test_client_cert_and_key() {
+        let curl =
+            "curl --cert client.pem --key client.key --cacert certs/ca.pem https://api.example.com";
+        let cmd = parse_curl(curl).unwrap();
+        assert_eq!(cmd.tls.client_cert, Some("client.pem".to_string()));
+        assert_eq!(cmd.tls.client_key, Some("client.key".to_string()));
+        assert_eq!(cmd.tls.ca_cert, Some("certs/ca.pem".to_string()));
+        assert_eq!(cmd.tls.cert_passphrase, None);
+
+        let http = curl_to_http(&cmd);
+        assert!(http.starts_with(
+            "# Note: client certificates are not supported yet and won't be sent\n\
+             # Note: custom CA certificates are not supported yet; the system ones are used\n\
+             # @client-cert ./client.pem\n"
+        ));
+        assert!(http.contains("# @client-key ./client.key\n"));
+        assert!(http.contains("# @ca-cert ./certs/ca.pem\n"));
+
+        let requests = crate::parser::parse_http_content(&http).unwrap();
+        assert_eq!(
+            requests[0].metadata.get("client-cert"),
+            Some(&"./client.pem".to_string())
+        );
+    }
+
+    #[test]
+    fn test_p12_cert_with_passphrase() {
+        let curl = "curl --cert-type p12 --cert ./file.p12:s3cret -k https://api.example.com";
+        let cmd = parse_curl(curl).unwrap();
+        assert_eq!(cmd.tls.client_cert, Some("./file.p12".to_string()));
+        assert_eq!(cmd.tls.cert_passphrase, Some("s3cret".to_string()));
+        assert_eq!(cmd.tls.cert_type, Some("P12".to_string()));
+        assert!(cmd.tls.insecure);
+
+        let http = curl_to_http(&cmd);
+        assert!(http.contains("# @client-cert ./file.p12\n"));
+        assert!(http.contains("# @cert-type P12\n"));
+        assert!(http.contains("# @cert-passphrase {{certPassphrase}}\n"));
+        assert!(!http.contains("s3cret"));
+        assert!(http.contains("# Note: set certPassphrase in http-client.private.env.json"));
+        assert!(http.contains("# @insecure true\n"));
+        assert!(http.contains(
+            "# Note: certificate checks were turned off, but kvile still verifies certificates\n"
+        ));
+    }
+
+    #[test]
+    fn test_absolute_cert_paths_are_preserved() {
+        assert_eq!(
+            split_cert_passphrase("/etc/ssl/client.pem"),
+            ("/etc/ssl/client.pem".to_string(), None)
+        );
+        assert_eq!(
+            split_cert_passphrase("C:/certs/client.p12:pw"),
+            ("C:/certs/client.p12".to_string(), Some("pw".to_string()))
+        );
+        assert_eq!(portable_path("/etc/ssl/client.pem"), "/etc/ssl/client.pem");
+        assert_eq!(portable_path("C:/certs/client.p12"), "C:/certs/client.p12");
+        assert_eq!(portable_path("../ca.pem"), "../ca.pem");
+    }
+
+    #[test]
+    fn test_windows_cmd_caret_continuations() {
+        // Copied from Edge devtools: "Copy as cURL (cmd)"
+        let curl = r#"curl ^"https://api.example.com/v1/users^" ^
+  -H ^"accept: application/json, text/plain, */*^" ^
+  -H ^"content-type: application/json^" ^
+  -H ^"user-agent: Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36 Edg/120.0.0.0^" ^
+  --data-raw ^"^{^\^"name^\^":^\^"Ada^\^",^\^"path^\^":^\^"C:^\^\^\^\temp^\^",^\^"tags^\^":^[^\^"a^\^"^]^}^" ^
+  --compressed"#;
+        let cmd = parse_curl(curl).unwrap();
+        assert_eq!(cmd.method, "POST");
+        assert_eq!(cmd.url, "https://api.example.com/v1/users");
+        assert_eq!(
+            cmd.header("content-type"),
+            Some(&"application/json".to_string())
+        );
+        assert_eq!(
+            cmd.header("accept"),
+            Some(&"application/json, text/plain, */*".to_string())
+        );
+        assert_eq!(
+            cmd.body,
+            Some(r#"{"name":"Ada","path":"C:\\temp","tags":["a"]}"#.to_string())
+        );
+        assert!(cmd.flags.contains(&"compressed".to_string()));
+    }
+
+    #[test]
+    fn test_powershell_backtick_continuations() {
+        let curl = r#"curl.exe -X POST "https://api.example.com/v1/users" `
+  -H "Content-Type: application/json" `
+  -H "Authorization: Bearer abc123" `
+  -d "{`"name`":`"Ada`"}""#;
+        let cmd = parse_curl(curl).unwrap();
+        assert_eq!(cmd.method, "POST");
+        assert_eq!(cmd.url, "https://api.example.com/v1/users");
+        assert_eq!(
+            cmd.header("Authorization"),
+            Some(&"Bearer abc123".to_string())
+        );
+        assert_eq!(cmd.body, Some(r#"{"name":"Ada"}"#.to_string()));
+    }
+
+    #[test]
+    fn test_powershell_single_quoted_body() {
+        let curl = "curl.exe https://api.example.com -H 'X-Note: it''s fine' -d '{\"a\":1}'";
+        let cmd = parse_curl(curl).unwrap();
+        assert_eq!(cmd.header("X-Note"), Some(&"it's fine".to_string()));
+        assert_eq!(cmd.body, Some(r#"{"a":1}"#.to_string()));
+    }
+
+    #[test]
+    fn test_posix_quoting_keeps_literal_backslashes() {
+        let curl =
+            r#"curl https://api.example.com -d '{"msg":"line\nbreak"}' -H "X-Quote: say \"hi\"""#;
+        let cmd = parse_curl(curl).unwrap();
+        assert_eq!(cmd.body, Some(r#"{"msg":"line\nbreak"}"#.to_string()));
+        assert_eq!(cmd.header("X-Quote"), Some(&r#"say "hi""#.to_string()));
+    }
+
+    #[test]
+    fn test_ansi_c_quoted_body() {
+        // Copied from Chrome: "Copy as cURL (bash)"
+        let curl = r#"curl 'https://api.example.com/v1/orders' \
+  -H 'accept: */*' \
+  -H 'content-type: text/plain;charset=UTF-8' \
+  --data-raw $'caf\u00e9 \u2615 \ud83d\ude00\nsecond line\twith tab, it\'s \\ done \xc3\xa6' \
+  --compressed"#;
+        let cmd = parse_curl(curl).unwrap();
+        assert_eq!(cmd.method, "POST");
+        assert_eq!(
+            cmd.body,
+            Some("café ☕ 😀\nsecond line\twith tab, it's \\ done æ".to_string())
+        );
+
+        let http = curl_to_http(&cmd);
+        assert!(http.contains("café ☕ 😀\nsecond line\twith tab"));
+    }
+
+    #[test]
+    fn test_ansi_c_quoted_json_keeps_escaped_backslashes() {
+        let curl = r#"curl https://api.example.com --data-raw $'{"msg":"a\\nb","name":"José"}'"#;
+        let cmd = parse_curl(curl).unwrap();
+        assert_eq!(
+            cmd.body,
+            Some(r#"{"msg":"a\nb","name":"José"}"#.to_string())
+        );
+    }
+
+    #[test]
+    fn test_split_multiple_commands() {
+        let script = r#"#!/bin/bash
+set -e
+
+# Create a user
+curl -X POST https://api.example.com/v1/users \
+  -H 'Content-Type: application/json' \
+  -d '{
+    "name": "Ada"
+  }'
+
+echo "created"
+$ curl https://api.example.com/v1/users?page=2
+curl -X DELETE \
+  -H 'Authorization: Bearer abc'
+"#;
+        let commands = split_curl_commands(script);
+        assert_eq!(commands.len(), 3);
+        assert_eq!(commands[0].0, 5);
+        assert!(commands[0].1.contains("\"name\": \"Ada\""));
+        assert_eq!(
+            commands[1].1,
+            "curl https://api.example.com/v1/users?page=2"
+        );
+        assert_eq!(commands[2].0, 13);
+
+        let http = curls_to_http(script, None);
+        assert!(http.contains("### /v1/users\nPOST https://api.example.com/v1/users\n"));
+        assert!(http.contains("### /v1/users\nGET https://api.example.com/v1/users?page=2\n"));
+        assert!(http.contains("### Failed to import curl command (line 13)\n"));
+        assert!(http.contains("# Error: No URL found in cURL command\n"));
+        assert!(http.contains("# curl -X DELETE \\\n"));
+
+        let requests = crate::parser::parse_http_content(&http).unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].method, "POST");
+        assert_eq!(requests[1].method, "GET");
+    }
+
+    #[test]
+    fn test_request_name_from_url() {
+        assert_eq!(
+            request_name_from_url("https://api.example.com"),
+            "api.example.com"
+        );
+        assert_eq!(
+            request_name_from_url("https://api.example.com/"),
+            "api.example.com"
+        );
+        assert_eq!(
+            request_name_from_url("{{host}}/orders/1?expand=true"),
+            "/orders/1"
+        );
+    }
+
+    #[test]
+    fn test_data_file_reference() {
+        let cmd = parse_curl("curl https://api.example.com -d @payload.json").unwrap();
+        assert_eq!(cmd.method, "POST");
+        assert_eq!(cmd.body, Some("< ./payload.json".to_string()));
+        assert!(cmd.notes[0].contains("strips newlines"));
+
+        let http = curl_to_http(&cmd);
+        assert!(http.starts_with("# Note: curl strips newlines"));
+        assert!(http.ends_with("\n< ./payload.json\n"));
+
+        let cmd = parse_curl("curl https://api.example.com --data-binary @/tmp/blob.bin").unwrap();
+        assert_eq!(cmd.body, Some("< /tmp/blob.bin".to_string()));
+        assert!(cmd.notes.is_empty());
+    }
+
+    #[test]
+    fn test_data_urlencode_forms() {
+        let parse = |arg: &str| parse_data_arg("--data-urlencode", arg);
+        assert_eq!(
+            parse("hello world"),
+            DataPart::Text("hello%20world".to_string())
+        );
+        assert_eq!(parse("=a&b"), DataPart::Text("a%26b".to_string()));
+        assert_eq!(
+            parse("q=rust lang"),
+            DataPart::Text("q=rust%20lang".to_string())
+        );
+        assert_eq!(
+            parse("@query.txt"),
+            DataPart::File {
+                name: None,
+                path: "query.txt".to_string(),
+                mode: DataMode::UrlEncode
+            }
+        );
+        assert_eq!(
+            parse("msg@message.txt"),
+            DataPart::File {
+                name: Some("msg".to_string()),
+                path: "message.txt".to_string(),
+                mode: DataMode::UrlEncode
+            }
+        );
+
+        let cmd = parse_curl(
+            "curl https://api.example.com --data-urlencode 'q=a b' --data-urlencode msg@message.txt",
+        )
+        .unwrap();
+        assert_eq!(cmd.body, Some("q=a%20b&msg={{msg}}".to_string()));
+        assert!(cmd.notes[0].contains("{{msg}}"));
+        assert!(cmd.notes[0].contains("./message.txt"));
+    }
+
+    #[test]
+    fn test_data_from_stdin() {
+        let cmd = parse_curl("curl https://api.example.com -d @-").unwrap();
+        assert_eq!(cmd.body, Some("{{stdin}}".to_string()));
+        assert!(cmd.notes[0].contains("stdin"));
+    }
+
+    #[test]
+    fn test_repeated_data_flags_are_joined() {
+        let cmd =
+            parse_curl("curl https://api.example.com/login -d a=1 -d b=2 --data c=3").unwrap();
+        assert_eq!(cmd.body, Some("a=1&b=2&c=3".to_string()));
+
+        let http = curl_to_http(&cmd);
+        assert!(http.contains("Content-Type: application/x-www-form-urlencoded\n"));
+        assert!(http.ends_with("\na=1&b=2&c=3\n"));
+    }
+
+    #[test]
+    fn test_mixed_data_and_urlencode() {
+        let curl = "curl https://api.example.com -H 'Content-Type: text/plain' -d name=ada --data-urlencode 'bio=likes tea & cake' -d x=1";
+        let cmd = parse_curl(curl).unwrap();
+        assert_eq!(
+            cmd.body,
+            Some("name=ada&bio=likes%20tea%20%26%20cake&x=1".to_string())
+        );
+
+        // Explicit content type wins over the form default
+        let http = curl_to_http(&cmd);
+        assert!(http.contains("Content-Type: text/plain\n"));
+        assert!(!http.contains("x-www-form-urlencoded"));
+    }
+
+    #[test]
+    fn test_json_body_is_not_form_data() {
+        assert!(!looks_like_form_data(r#"{"a":"b=c"}"#));
+        assert!(!looks_like_form_data("< ./payload.json"));
+        assert!(looks_like_form_data("q={{query}}&page=2"));
+    }
+
+    #[test]
+    fn test_header_file() {
+        let dir = std::env::temp_dir().join(format!("kvile-curl-headers-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("headers.txt"),
+            "Authorization: Bearer abc\r\nX-Trace: 1\r\n\r\nX-Empty;\r\n",
+        )
+        .unwrap();
+
+        let cmd = super::parse_curl(
+            "curl https://api.example.com -H 'Accept: */*' -H @headers.txt",
+            Some(&dir),
+        )
+        .unwrap();
+        assert_eq!(
+            cmd.headers,
+            vec![
+                ("Accept".to_string(), "*/*".to_string()),
+                ("Authorization".to_string(), "Bearer abc".to_string()),
+                ("X-Trace".to_string(), "1".to_string()),
+                ("X-Empty".to_string(), String::new()),
+            ]
+        );
+
+        let missing = parse_curl("curl https://api.example.com -H @headers.txt").unwrap();
+        assert!(missing.headers.is_empty());
+        assert!(missing.notes[0].contains("./headers.txt"));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_duplicate_headers_keep_order() {
+        let curl = r#"curl https://api.example.com -H "Accept: text/html" -H "X-Id: 1" -H "Accept: application/json""#;
+        let cmd = parse_curl(curl).unwrap();
+        assert_eq!(cmd.headers.len(), 3);
+
+        let http = curl_to_http(&cmd);
+        assert!(http.contains("Accept: text/html\nX-Id: 1\nAccept: application/json\n"));
+    }
+
+    #[test]
+    fn test_empty_header_syntax() {
+        let cmd =
+            parse_curl(r#"curl https://api.example.com -H "X-Custom;" -H "User-Agent:""#).unwrap();
+        assert_eq!(cmd.headers, vec![("X-Custom".to_string(), String::new())]);
+        assert!(cmd.notes[0].contains("User-Agent"));
+        assert!(curl_to_http(&cmd).contains("X-Custom: \n"));
+    }
+
+    #[test]
+    fn test_retry_flags() {
+        let cmd = parse_curl(
+            "curl --retry 3 --retry-delay 2 --retry-all-errors -X POST https://api.example.com/jobs",
+        )
+        .unwrap();
+        assert_eq!(
+            cmd.retry,
+            RetryOptions {
+                count: Some(3),
+                delay: Some(2),
+                all_errors: true,
+            }
+        );
+
+        let http = curl_to_http(&cmd);
+        assert!(http.contains(
+            "# Note: retries are not supported yet; the request is sent once\n# @retry 3\n# @retry-delay 2s\n# Note: --retry-all-errors also retries non-idempotent failures\n# @retry-all-errors true\nPOST https://api.example.com/jobs\n"
+        ));
+
+        let invalid =
+            parse_curl("curl --retry -1 --retry-delay soon https://api.example.com").unwrap();
+        assert_eq!(invalid.retry, RetryOptions::default());
+        assert_eq!(invalid.notes.len(), 2);
+        assert!(invalid.notes[0].contains("--retry -1"));
+        assert!(invalid.notes[1].contains("--retry-delay soon"));
+    }
+
+    #[test]
+    fn test_unsupported_flags_warn() {
+        let result = import_curl(
+            "curl -sS --ciphers ECDHE-RSA-AES128-GCM-SHA256 --tcp-fastopen -w '%{http_code}' -v https://api.example.com",
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            result.warnings,
+            vec![
+                "--ciphers ECDHE-RSA-AES128-GCM-SHA256 is not supported and was skipped",
+                "--tcp-fastopen is not supported and was skipped",
+                "-w %{http_code} is not supported and was skipped",
+            ]
+        );
+        assert!(result.http.contains("GET https://api.example.com\n"));
+
+        let quiet = import_curl("curl -sSL --silent https://api.example.com", None).unwrap();
+        assert!(quiet.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_inline_cookies() {
+        let cmd = parse_curl(r#"curl https://api.example.com -b "a=1; b=2" -b c=3"#).unwrap();
+        assert_eq!(cmd.header("Cookie").unwrap(), "a=1; b=2; c=3");
+        assert!(cmd.cookie_jar.is_none());
+    }
+
+    #[test]
+    fn test_cookie_file_and_jar() {
+        let dir = std::env::temp_dir().join(format!("kvile-curl-cookies-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("cookies.txt"),
+            "# Netscape HTTP Cookie File\n\
+             .example.com\tTRUE\t/\tFALSE\t0\tsession\tabc123\n\
+             #HttpOnly_api.example.com\tFALSE\t/v1\tTRUE\t2147483647\ttoken\txyz\n\
+             api.example.com\tFALSE\t/admin\tFALSE\t0\tadmin\tno\n\
+             other.org\tFALSE\t/\tFALSE\t0\tforeign\tno\n",
+        )
+        .unwrap();
+
+        let cmd = super::parse_curl(
+            "curl -b cookies.txt -c cookies.txt https://api.example.com/v1/users",
+            Some(&dir),
+        )
+        .unwrap();
+        assert_eq!(cmd.header("Cookie").unwrap(), "session=abc123; token=xyz");
+        assert_eq!(cmd.cookie_jar.as_deref(), Some("cookies.txt"));
+
+        let http = curl_to_http(&cmd);
+        assert!(
+            http.contains("# @cookie-jar ./cookies.txt\nGET https://api.example.com/v1/users\n")
+        );
+        assert!(http.contains("Cookie: session=abc123; token=xyz\n"));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_http_version_flags() {
+        let cases = [
+            ("--http1.0", "HTTP/1.0"),
+            ("--http1.1", "HTTP/1.1"),
+            ("--http2", "HTTP/2"),
+            ("--http2-prior-knowledge", "HTTP/2"),
+            ("--http3", "HTTP/3"),
+        ];
+
+        for (flag, version) in cases {
+            let cmd = parse_curl(&format!("curl {} https://api.example.com/users", flag)).unwrap();
+            assert_eq!(cmd.http_version.as_deref(), Some(version), "{}", flag);
+            assert_eq!(cmd.http2_prior_knowledge, flag == "--http2-prior-knowledge");
+
+            let http = curl_to_http(&cmd);
+            assert!(http.contains(&format!("GET https://api.example.com/users {}\n", version)));
+
+            let parsed = crate::parser::parse_http_content(&http).unwrap();
+            assert_eq!(parsed[0].url, "https://api.example.com/users");
+            assert_eq!(parsed[0].http_version.as_deref(), Some(version));
+            assert_eq!(
+                parsed[0].metadata.contains_key("http2-prior-knowledge"),
+                cmd.http2_prior_knowledge
+            );
+        }
+    }
+
+    #[test]
+    fn test_http_to_curl() {
+        let requests = crate::parser::parse_http_content(
+            "POST https://api.example.com/users HTTP/2\nContent-Type: application/json\nX-Note: it's fine\n\n{\"name\": \"test\"}\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            http_to_curl(&requests[0]),
+            "curl -X POST 'https://api.example.com/users' \\\n  --http2 \\\n  -H 'Content-Type: application/json' \\\n  -H 'X-Note: it'\\''s fine' \\\n  --data-raw '{\"name\": \"test\"}'"
+        );
+    }
+
+    #[test]
+    fn test_requests_to_script() {
+        let content = "### List users\nGET {{host}}/users\nAuthorization: Bearer {{token}}\n\n> {%\nclient.assert(response.status === 200);\n%}\n";
+        let requests = crate::parser::parse_http_content(content).unwrap();
+        let vars: HashMap<String, String> = [
+            ("host".to_string(), "https://api.example.com".to_string()),
+            ("token".to_string(), "s3cret".to_string()),
+        ]
+        .into_iter()
+        .collect();
+        let secrets = vec!["token".to_string()];
+
+        let literal = requests_to_script(&requests, &vars, &secrets, false);
+        assert!(literal.starts_with("#!/usr/bin/env bash\nset -euo pipefail\n"));
+        assert!(literal.contains(
+            "\n# List users\n# Warning: request scripts and assertions were not translated\n"
+        ));
+        assert!(literal.contains("curl --fail-with-body 'https://api.example.com/users' \\\n  -H 'Authorization: Bearer s3cret'\n"));
+
+        let referenced = requests_to_script(&requests, &vars, &secrets, true);
+        assert!(referenced.contains(": \"${TOKEN:?TOKEN must be set}\"\n"));
+        assert!(referenced.contains("-H 'Authorization: Bearer '\"${TOKEN}\"\n"));
+        assert!(!referenced.contains("s3cret"));
+    }
+}
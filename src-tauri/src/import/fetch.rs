@@ -0,0 +1,402 @@
+use serde_json::{Map, Number, Value};
+
+use super::curl::{curl_to_http, CurlCommand};
+
+/// Convert a devtools "Copy as fetch" / "Copy as Node.js fetch" snippet to
+/// HTTP file format
+pub fn fetch_to_http(snippet: &str) -> Result<String, String> {
+    let start = snippet
+        .find("fetch(")
+        .ok_or_else(|| "No fetch( call found in snippet".to_string())?;
+
+    let mut parser = JsParser::new(&snippet[start + "fetch(".len()..]);
+    let url = match parser.parse_value()? {
+        Value::String(url) => url,
+        _ => return Err("fetch() URL must be a string literal".to_string()),
+    };
+
+    parser.skip_whitespace();
+    let options = if parser.eat(',') {
+        parser.skip_whitespace();
+        if parser.peek() == Some(')') {
+            Map::new()
+        } else {
+            match parser.parse_value()? {
+                Value::Object(options) => options,
+                _ => return Err("fetch() options must be an object literal".to_string()),
+            }
+        }
+    } else {
+        Map::new()
+    };
+
+    let mut cmd = CurlCommand {
+        url,
+        ..Default::default()
+    };
+
+    if let Some(method) = options.get("method").and_then(Value::as_str) {
+        cmd.method = method.to_uppercase();
+    }
+
+    if let Some(Value::Object(headers)) = options.get("headers") {
+        for (name, value) in headers {
+            let value = match value {
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            cmd.headers.push((name.clone(), value));
+        }
+    }
+
+    // Chrome puts the referrer in the options rather than the headers
+    if let Some(referrer) = options.get("referrer").and_then(Value::as_str) {
+        if cmd.header("Referer").is_none() {
+            cmd.headers
+                .push(("Referer".to_string(), referrer.to_string()));
+        }
+    }
+
+    cmd.body = match options.get("body") {
+        Some(Value::String(body)) => Some(body.clone()),
+        Some(Value::Null) | None => None,
+        Some(other) => Some(other.to_string()),
+    };
+
+    if let Some(credentials) = options.get("credentials").and_then(Value::as_str) {
+        if credentials != "omit" && cmd.header("Cookie").is_none() {
+            cmd.notes.push(format!(
+                "credentials \"{}\" sends browser cookies, which are not part of the snippet",
+                credentials
+            ));
+        }
+    }
+
+    Ok(curl_to_http(&cmd))
+}
+
+/// Tolerant parser for the subset of JavaScript literals devtools emits:
+/// objects, arrays, strings, numbers, booleans and null
+struct JsParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl JsParser {
+    fn new(input: &str) -> Self {
+        Self {
+            chars: input.chars().collect(),
+            pos: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("{} at character {}", message, self.pos)
+    }
+
+    /// Skip whitespace and `//` / `/* */` comments
+    fn skip_whitespace(&mut self) {
+        loop {
+            while self.peek().is_some_and(char::is_whitespace) {
+                self.pos += 1;
+            }
+            let rest = &self.chars[self.pos..];
+            if rest.starts_with(&['/', '/']) {
+                while self.peek().is_some_and(|c| c != '\n') {
+                    self.pos += 1;
+                }
+            } else if rest.starts_with(&['/', '*']) {
+                self.pos += 2;
+                while self.pos < self.chars.len()
+                    && !self.chars[self.pos..].starts_with(&['*', '/'])
+                {
+                    self.pos += 1;
+                }
+                self.pos = (self.pos + 2).min(self.chars.len());
+            } else {
+                return;
+            }
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.parse_object(),
+            Some('[') => self.parse_array(),
+            Some('"' | '\'' | '`') => self.parse_string().map(Value::String),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.parse_number(),
+            Some(c) if c.is_alphabetic() || c == '_' || c == '$' => {
+                match self.parse_identifier().as_str() {
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    "null" | "undefined" => Ok(Value::Null),
+                    other => Err(self.error(&format!("Unsupported expression '{}'", other))),
+                }
+            }
+            Some(_) => Err(self.error("Unexpected character")),
+            None => Err(self.error("Unexpected end of snippet")),
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<Value, String> {
+        self.pos += 1; // '{'
+        let mut map = Map::new();
+
+        loop {
+            self.skip_whitespace();
+            if self.eat('}') {
+                return Ok(Value::Object(map));
+            }
+
+            let key = match self.peek() {
+                Some('"' | '\'' | '`') => self.parse_string()?,
+                Some(c) if c.is_alphanumeric() || c == '_' || c == '$' => self.parse_identifier(),
+                _ => return Err(self.error("Expected object key")),
+            };
+
+            self.skip_whitespace();
+            if !self.eat(':') {
+                return Err(self.error("Expected ':' after object key"));
+            }
+            let value = self.parse_value()?;
+            map.insert(key, value);
+
+            self.skip_whitespace();
+            // Trailing commas are allowed
+            if !self.eat(',') {
+                self.skip_whitespace();
+                if !self.eat('}') {
+                    return Err(self.error("Expected ',' or '}' in object"));
+                }
+                return Ok(Value::Object(map));
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Value, String> {
+        self.pos += 1; // '['
+        let mut items = Vec::new();
+
+        loop {
+            self.skip_whitespace();
+            if self.eat(']') {
+                return Ok(Value::Array(items));
+            }
+            items.push(self.parse_value()?);
+
+            self.skip_whitespace();
+            if !self.eat(',') {
+                self.skip_whitespace();
+                if !self.eat(']') {
+                    return Err(self.error("Expected ',' or ']' in array"));
+                }
+                return Ok(Value::Array(items));
+            }
+        }
+    }
+
+    fn parse_identifier(&mut self) -> String {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '$')
+        {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    fn parse_number(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))
+        {
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        text.parse::<f64>()
+            .ok()
+            .and_then(Number::from_f64)
+            .map(|n| match text.parse::<i64>() {
+                Ok(i) => Value::Number(i.into()),
+                Err(_) => Value::Number(n),
+            })
+            .ok_or_else(|| self.error(&format!("Invalid number '{}'", text)))
+    }
+
+    /// Parse a single, double or backtick quoted string
+    fn parse_string(&mut self) -> Result<String, String> {
+        let quote = self.chars[self.pos];
+        self.pos += 1;
+        let mut out = String::new();
+
+        loop {
+            let c = self
+                .peek()
+                .ok_or_else(|| self.error("Unterminated string"))?;
+            self.pos += 1;
+
+            match c {
+                c if c == quote => return Ok(out),
+                '\\' => {
+                    let escaped = self
+                        .peek()
+                        .ok_or_else(|| self.error("Unterminated string"))?;
+                    self.pos += 1;
+                    match escaped {
+                        'n' => out.push('\n'),
+                        't' => out.push('\t'),
+                        'r' => out.push('\r'),
+                        'b' => out.push('\u{8}'),
+                        'f' => out.push('\u{c}'),
+                        'v' => out.push('\u{b}'),
+                        '0' => out.push('\0'),
+                        '\n' => {} // Line continuation
+                        'x' => out.push(self.parse_hex_escape(2)?),
+                        'u' => out.push(self.parse_unicode_escape()?),
+                        other => out.push(other),
+                    }
+                }
+                c => out.push(c),
+            }
+        }
+    }
+
+    fn parse_hex_escape(&mut self, len: usize) -> Result<char, String> {
+        let code = self.read_hex(len)?;
+        char::from_u32(code).ok_or_else(|| self.error("Invalid escape"))
+    }
+
+    fn read_hex(&mut self, len: usize) -> Result<u32, String> {
+        let end = self.pos + len;
+        let hex: String = self
+            .chars
+            .get(self.pos..end)
+            .ok_or_else(|| self.error("Truncated escape"))?
+            .iter()
+            .collect();
+        self.pos = end;
+        u32::from_str_radix(&hex, 16).map_err(|_| self.error("Invalid escape"))
+    }
+
+    /// `\uXXXX`, `\u{X...}` and UTF-16 surrogate pairs. An unpaired
+    /// surrogate becomes U+FFFD, leaving any escape after it to be read alone.
+    fn parse_unicode_escape(&mut self) -> Result<char, String> {
+        if self.eat('{') {
+            let start = self.pos;
+            while self.peek().is_some_and(|c| c != '}') {
+                self.pos += 1;
+            }
+            let hex: String = self.chars[start..self.pos].iter().collect();
+            self.pos += 1;
+            return u32::from_str_radix(&hex, 16)
+                .ok()
+                .and_then(char::from_u32)
+                .ok_or_else(|| self.error("Invalid escape"));
+        }
+
+        let high = self.read_hex(4)?;
+        if (0xD800..0xDC00).contains(&high) && self.chars[self.pos..].starts_with(&['\\', 'u']) {
+            let start = self.pos;
+            self.pos += 2;
+            match self.read_hex(4) {
+                Ok(low) if (0xDC00..=0xDFFF).contains(&low) => {
+                    let code = 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00);
+                    return char::from_u32(code).ok_or_else(|| self.error("Invalid escape"));
+                }
+                _ => self.pos = start,
+            }
+        }
+        Ok(char::from_u32(high).unwrap_or(char::REPLACEMENT_CHARACTER))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chrome_fetch() {
+        let snippet = r#"fetch("https://api.example.com/v1/users", {
+  "headers": {
+    "accept": "application/json, text/plain, */*",
+    "accept-language": "en-US,en;q=0.9",
+    "content-type": "application/json",
+    "sec-fetch-mode": "cors"
+  },
+  "referrer": "https://app.example.com/",
+  "referrerPolicy": "strict-origin-when-cross-origin",
+  "body": "{\"name\":\"Ada\",\"tags\":[\"a\",\"b\"]}",
+  "method": "POST",
+  "mode": "cors",
+  "credentials": "include"
+});"#;
+
+        let http = fetch_to_http(snippet).unwrap();
+        assert!(http.starts_with(
+            "# Note: credentials \"include\" sends browser cookies, which are not part of the snippet\nPOST https://api.example.com/v1/users\n"
+        ));
+        assert!(http.contains("accept: application/json, text/plain, */*\n"));
+        assert!(http.contains("content-type: application/json\n"));
+        assert!(http.contains("Referer: https://app.example.com/\n"));
+        assert!(http.contains("\"name\": \"Ada\""));
+    }
+
+    #[test]
+    fn test_node_fetch() {
+        let snippet = r#"fetch("https://api.example.com/v1/session", {
+  "headers": {
+    "cookie": "sid=abc",
+    "Referer": "https://app.example.com/",
+    "Referrer-Policy": "strict-origin-when-cross-origin"
+  },
+  "body": null,
+  "method": "GET"
+});"#;
+
+        let http = fetch_to_http(snippet).unwrap();
+        assert!(http.starts_with("GET https://api.example.com/v1/session\n"));
+        assert!(http.contains("cookie: sid=abc\n"));
+        assert_eq!(http.matches("Referer").count(), 1);
+        assert!(!http.contains("# Note"));
+    }
+
+    #[test]
+    fn test_fetch_without_options() {
+        let http = fetch_to_http("await fetch('https://api.example.com/health');").unwrap();
+        assert_eq!(http, "GET https://api.example.com/health\n");
+
+        assert!(fetch_to_http("axios.get('https://api.example.com')").is_err());
+    }
+
+    #[test]
+    fn test_unicode_escapes() {
+        let body = |escaped: &str| {
+            let http = fetch_to_http(&format!(
+                "fetch('https://api.example.com', {{method: 'POST', body: '{}'}})",
+                escaped
+            ))
+            .unwrap();
+            http.split_once("\n\n").unwrap().1.trim_end().to_string()
+        };
+        assert_eq!(body(r"\ud83d\ude00 \u{1F600}"), "\u{1F600} \u{1F600}");
+        // A high surrogate without its low half doesn't swallow the next escape
+        assert_eq!(body(r"\ud800\u0041"), "\u{FFFD}A");
+        assert_eq!(body(r"\ud800x\udc00"), "\u{FFFD}x\u{FFFD}");
+    }
+}
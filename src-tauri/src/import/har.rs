@@ -0,0 +1,565 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::SecondsFormat;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::HashSet;
+
+use crate::history::HistoryEntry;
+
+use super::{multipart_body, render_requests, FormPart, RequestBlock, MULTIPART_BOUNDARY};
+
+/// Directory, relative to the .http file, that large or binary bodies are written to
+pub const ASSET_DIR: &str = "har-assets";
+
+/// Bodies larger than this are written to an asset file instead of inlined
+const MAX_INLINE_BODY: usize = 16 * 1024;
+
+/// Headers that describe a single connection, plus HTTP/2 pseudo headers
+pub const HOP_BY_HOP_HEADERS: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+    "host",
+    "content-length",
+];
+
+/// Which HAR entries to import
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct HarImportOptions {
+    /// Response mime type prefixes to keep, e.g. `application/json` (all when empty)
+    pub mime_types: Vec<String>,
+    /// Hosts to keep (all when empty)
+    pub hosts: Vec<String>,
+    /// Keep Cookie headers, which are dropped by default
+    pub include_cookies: bool,
+    /// Directory to write `har-assets` into; defaults to the HAR file's directory
+    pub output_dir: Option<String>,
+}
+
+/// A converted HAR file
+#[derive(Debug, Clone, Serialize)]
+pub struct HarImportResult {
+    pub http: String,
+    /// Entries filtered out, duplicated, or not convertible
+    pub skipped: usize,
+    pub warnings: Vec<String>,
+    /// Asset files written next to the .http file
+    pub assets: Vec<String>,
+}
+
+/// A HAR file converted in memory, with the asset contents still to be written
+pub struct HarConversion {
+    pub http: String,
+    pub skipped: usize,
+    pub warnings: Vec<String>,
+    /// `(file name inside ASSET_DIR, content)`
+    pub assets: Vec<(String, Vec<u8>)>,
+}
+
+/// Convert the entries of a HAR log to .http requests.
+///
+/// Asset files are numbered from `first_asset`, so an import never reuses the
+/// name of an asset written by an earlier one.
+pub fn har_to_http(
+    content: &str,
+    options: &HarImportOptions,
+    first_asset: usize,
+) -> Result<HarConversion, String> {
+    let har: Value =
+        serde_json::from_str(content).map_err(|e| format!("Failed to parse HAR: {}", e))?;
+    let entries = har
+        .pointer("/log/entries")
+        .and_then(Value::as_array)
+        .ok_or_else(|| "Not a HAR file: missing log.entries".to_string())?;
+
+    let mut conversion = HarConversion {
+        http: String::new(),
+        skipped: 0,
+        warnings: Vec::new(),
+        assets: Vec::new(),
+    };
+    let mut seen = HashSet::new();
+    let mut blocks = Vec::new();
+
+    for entry in entries {
+        let Some(request) = entry.get("request") else {
+            conversion.skipped += 1;
+            continue;
+        };
+        let method = str_of(request, "method").to_uppercase();
+        let url = str_of(request, "url");
+        let Ok(parsed) = url::Url::parse(url) else {
+            conversion.skipped += 1;
+            continue;
+        };
+        if !matches!(parsed.scheme(), "http" | "https") || !keep(entry, &parsed, options) {
+            conversion.skipped += 1;
+            continue;
+        }
+        if !seen.insert(format!("{} {}", method, url)) {
+            conversion.skipped += 1;
+            continue;
+        }
+
+        let mut block = RequestBlock {
+            name: format!("{} {}", method, parsed.path()),
+            method,
+            url: url.to_string(),
+            ..Default::default()
+        };
+        for header in request
+            .get("headers")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            let name = str_of(header, "name");
+            let lower = name.to_ascii_lowercase();
+            if name.is_empty()
+                || name.starts_with(':')
+                || HOP_BY_HOP_HEADERS.contains(&lower.as_str())
+                || (lower == "cookie" && !options.include_cookies)
+                || block.header(name).is_some()
+            {
+                continue;
+            }
+            block
+                .headers
+                .push((name.to_string(), str_of(header, "value").to_string()));
+        }
+
+        if let Some(post_data) = request.get("postData") {
+            let mut next_asset = |bytes: Vec<u8>| {
+                let name = (first_asset + conversion.assets.len()).to_string();
+                conversion.assets.push((name.clone(), bytes));
+                format!("< ./{}/{}", ASSET_DIR, name)
+            };
+            post_body(post_data, &mut block, &mut next_asset);
+        }
+        blocks.push(block);
+    }
+
+    if blocks.is_empty() {
+        conversion
+            .warnings
+            .push("No requests left to import after filtering".to_string());
+    }
+    conversion.http = render_requests(&blocks);
+    Ok(conversion)
+}
+
+/// Whether an entry passes the mime type and host filters
+fn keep(entry: &Value, url: &url::Url, options: &HarImportOptions) -> bool {
+    if !options.hosts.is_empty() {
+        let host = url.host_str().unwrap_or_default();
+        if !options.hosts.iter().any(|h| h.eq_ignore_ascii_case(host)) {
+            return false;
+        }
+    }
+    if !options.mime_types.is_empty() {
+        let mime = entry
+            .pointer("/response/content/mimeType")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        if !options
+            .mime_types
+            .iter()
+            .any(|m| mime.starts_with(&m.to_ascii_lowercase()))
+        {
+            return false;
+        }
+    }
+    true
+}
+
+/// Set the request body from HAR postData, moving large or binary content to assets
+fn post_body(
+    post_data: &Value,
+    block: &mut RequestBlock,
+    next_asset: &mut dyn FnMut(Vec<u8>) -> String,
+) {
+    let mime_type = str_of(post_data, "mimeType");
+    let params: Vec<&Value> = post_data
+        .get("params")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .collect();
+
+    if mime_type.starts_with("multipart/form-data") && !params.is_empty() {
+        let parts: Vec<FormPart> = params
+            .iter()
+            .map(|param| {
+                let name = str_of(param, "name").to_string();
+                match param.get("fileName").and_then(Value::as_str) {
+                    Some(file_name) => {
+                        let value = str_of(param, "value");
+                        let path = if value.is_empty() {
+                            file_name.to_string()
+                        } else {
+                            next_asset(value.as_bytes().to_vec())
+                                .trim_start_matches("< ")
+                                .to_string()
+                        };
+                        FormPart::File { name, path }
+                    }
+                    None => FormPart::Text {
+                        name,
+                        value: str_of(param, "value").to_string(),
+                    },
+                }
+            })
+            .collect();
+        set_content_type(
+            block,
+            format!("multipart/form-data; boundary={}", MULTIPART_BOUNDARY),
+        );
+        block.body = Some(multipart_body(&parts));
+        return;
+    }
+
+    if !params.is_empty() && post_data.get("text").is_none() {
+        let pairs: Vec<String> = params
+            .iter()
+            .map(|p| format!("{}={}", str_of(p, "name"), str_of(p, "value")))
+            .collect();
+        set_content_type(block, mime_type.to_string());
+        block.body = Some(pairs.join("&"));
+        return;
+    }
+
+    let text = str_of(post_data, "text");
+    if text.is_empty() {
+        return;
+    }
+    if !mime_type.is_empty() && block.header("Content-Type").is_none() {
+        block
+            .headers
+            .push(("Content-Type".to_string(), mime_type.to_string()));
+    }
+
+    let bytes = if post_data.get("encoding").and_then(Value::as_str) == Some("base64") {
+        STANDARD.decode(text).ok()
+    } else {
+        None
+    };
+    let binary = bytes.is_some() || text.chars().any(|c| c.is_control() && !c.is_whitespace());
+    block.body = Some(if binary || text.len() > MAX_INLINE_BODY {
+        next_asset(bytes.unwrap_or_else(|| text.as_bytes().to_vec()))
+    } else {
+        text.to_string()
+    });
+}
+
+/// Use the Content-Type implied by the body, replacing the captured one
+fn set_content_type(block: &mut RequestBlock, content_type: String) {
+    block
+        .headers
+        .retain(|(k, _)| !k.eq_ignore_ascii_case("Content-Type"));
+    if !content_type.is_empty() {
+        block
+            .headers
+            .push(("Content-Type".to_string(), content_type));
+    }
+}
+
+fn str_of<'v>(value: &'v Value, key: &str) -> &'v str {
+    value.get(key).and_then(Value::as_str).unwrap_or_default()
+}
+
+/// Build a HAR 1.2 log from history entries, oldest first
+pub fn entries_to_har(entries: &[HistoryEntry]) -> Value {
+    let mut entries: Vec<&HistoryEntry> = entries.iter().collect();
+    entries.sort_by_key(|e| (e.timestamp, e.id));
+
+    json!({
+        "log": {
+            "version": "1.2",
+            "creator": { "name": "kvile", "version": env!("CARGO_PKG_VERSION") },
+            "entries": entries.into_iter().map(har_entry).collect::<Vec<_>>(),
+        }
+    })
+}
+
+fn har_entry(entry: &HistoryEntry) -> Value {
+    let request_headers = header_list(&entry.request_headers);
+    let response_headers = header_list(&entry.response_headers);
+    let header = |headers: &[(String, String)], name: &str| {
+        headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.clone())
+    };
+
+    let query_string: Vec<Value> = url::Url::parse(&entry.url)
+        .map(|url| {
+            url.query_pairs()
+                .map(|(name, value)| json!({ "name": name, "value": value }))
+                .collect()
+        })
+        .unwrap_or_default();
+    let request_cookies: Vec<Value> = header(&request_headers, "Cookie")
+        .map(|cookies| {
+            cookies
+                .split(';')
+                .filter_map(|c| c.trim().split_once('='))
+                .map(|(name, value)| json!({ "name": name, "value": value }))
+                .collect()
+        })
+        .unwrap_or_default();
+    let response_cookies: Vec<Value> = response_headers
+        .iter()
+        .filter(|(k, _)| k.eq_ignore_ascii_case("Set-Cookie"))
+        .filter_map(|(_, v)| v.split(';').next()?.trim().split_once('='))
+        .map(|(name, value)| json!({ "name": name, "value": value }))
+        .collect();
+
+    let mut request = json!({
+        "method": entry.method,
+        "url": entry.url,
+        "httpVersion": "HTTP/1.1",
+        "cookies": request_cookies,
+        "headers": har_headers(&request_headers),
+        "queryString": query_string,
+        "headersSize": -1,
+        "bodySize": entry.request_body.as_ref().map_or(0, |b| b.len()),
+    });
+    if let Some(body) = &entry.request_body {
+        request["postData"] = json!({
+            "mimeType": header(&request_headers, "Content-Type").unwrap_or_default(),
+            "text": body,
+        });
+    }
+
+    let mime_type = header(&response_headers, "Content-Type").unwrap_or_default();
+    let mut content = json!({
+        "size": entry.response_size,
+        "mimeType": mime_type,
+        "text": entry.response_body,
+    });
+    if entry.response_body_base64 {
+        content["encoding"] = json!("base64");
+    }
+
+    json!({
+        "startedDateTime": entry.timestamp.to_rfc3339_opts(SecondsFormat::Millis, true),
+        "time": entry.duration_ms,
+        "request": request,
+        "response": {
+            "status": entry.status,
+            "statusText": entry.status_text,
+            "httpVersion": "HTTP/1.1",
+            "cookies": response_cookies,
+            "headers": har_headers(&response_headers),
+            "content": content,
+            "redirectURL": header(&response_headers, "Location").unwrap_or_default(),
+            "headersSize": -1,
+            "bodySize": entry.response_size,
+        },
+        "cache": {},
+        "timings": { "send": 0, "wait": entry.duration_ms, "receive": 0 },
+    })
+}
+
+/// Headers stored in history as a JSON object, sorted by name
+fn header_list(json: &str) -> Vec<(String, String)> {
+    let mut headers: Vec<(String, String)> = serde_json::from_str::<Map<String, Value>>(json)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(k, v)| {
+            let value = match v {
+                Value::String(s) => s,
+                other => other.to_string(),
+            };
+            (k, value)
+        })
+        .collect();
+    headers.sort();
+    headers
+}
+
+fn har_headers(headers: &[(String, String)]) -> Vec<Value> {
+    headers
+        .iter()
+        .map(|(name, value)| json!({ "name": name, "value": value }))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = include_str!("../../tests/fixtures/sample.har");
+
+    #[test]
+    fn test_converts_entries() {
+        let conversion = har_to_http(SAMPLE, &HarImportOptions::default(), 1).unwrap();
+
+        assert!(conversion.http.starts_with(
+            "### GET /users\nGET https://api.example.com/users?page=2\nAccept: application/json\nAuthorization: Bearer abc\n"
+        ));
+        assert!(conversion.http.contains(
+            "### POST /users\nPOST https://api.example.com/users\nContent-Type: application/json\nAccept: application/json\n\n{\"name\":\"Ada\"}\n"
+        ));
+        assert!(conversion.http.contains(
+            "### POST /upload\nPOST https://api.example.com/upload\nContent-Type: multipart/form-data; boundary=WebAppBoundary\n\n--WebAppBoundary\nContent-Disposition: form-data; name=\"title\"\n\nHoliday\n--WebAppBoundary\nContent-Disposition: form-data; name=\"photo\"; filename=\"1\"\n\n< ./har-assets/1\n--WebAppBoundary--\n"
+        ));
+        assert!(!conversion.http.contains("Cookie"));
+        assert!(!conversion.http.contains(":authority"));
+        assert!(!conversion.http.contains("Connection"));
+
+        // The duplicate GET and the data: URL
+        assert_eq!(conversion.skipped, 2);
+        assert_eq!(
+            conversion.assets,
+            vec![("1".to_string(), b"PNGDATA".to_vec())]
+        );
+        crate::parser::parse_http_content(&conversion.http).unwrap();
+    }
+
+    #[test]
+    fn test_filters_and_cookies() {
+        let options = HarImportOptions {
+            mime_types: vec!["application/json".to_string()],
+            hosts: vec!["api.example.com".to_string()],
+            include_cookies: true,
+            ..Default::default()
+        };
+        let conversion = har_to_http(SAMPLE, &options, 1).unwrap();
+
+        assert!(conversion.http.contains("Cookie: session=xyz\n"));
+        assert!(!conversion.http.contains("/upload"));
+        assert!(!conversion.http.contains("cdn.example.com"));
+        assert_eq!(conversion.skipped, 4);
+    }
+
+    #[test]
+    fn test_binary_body_becomes_asset() {
+        let har = r#"{"log": {"entries": [{
+            "request": {
+                "method": "PUT",
+                "url": "https://api.example.com/blob",
+                "headers": [],
+                "postData": {"mimeType": "application/octet-stream", "text": "AAEC", "encoding": "base64"}
+            },
+            "response": {"content": {"mimeType": "text/plain"}}
+        }]}}"#;
+        let conversion = har_to_http(har, &HarImportOptions::default(), 3).unwrap();
+
+        assert_eq!(
+            conversion.http,
+            "### PUT /blob\nPUT https://api.example.com/blob\nContent-Type: application/octet-stream\n\n< ./har-assets/3\n"
+        );
+        assert_eq!(conversion.assets, vec![("3".to_string(), vec![0, 1, 2])]);
+    }
+
+    const HAR_SCHEMA: &str = include_str!("../../tests/fixtures/har-1.2.schema.json");
+
+    fn history_entry(id: i64, timestamp: &str, content_type: &str, body: &str) -> HistoryEntry {
+        HistoryEntry {
+            id,
+            timestamp: timestamp.parse().unwrap(),
+            workspace: "/ws".to_string(),
+            file_path: None,
+            request_name: None,
+            method: "POST".to_string(),
+            url: "https://api.example.com/items?tag=a&tag=b".to_string(),
+            request_headers: r#"{"Content-Type": "application/json", "Cookie": "session=xyz"}"#
+                .to_string(),
+            request_body: Some(r#"{"name": "Ada"}"#.to_string()),
+            status: 201,
+            status_text: "Created".to_string(),
+            response_headers: format!(
+                r#"{{"Content-Type": "{}", "Location": "/items/1"}}"#,
+                content_type
+            ),
+            response_body: body.to_string(),
+            duration_ms: 42,
+            response_size: body.len() as i64,
+            replayed_from: None,
+            environment: None,
+            variables_snapshot: None,
+            response_body_base64: false,
+        }
+    }
+
+    #[test]
+    fn test_export_har_matches_schema() {
+        let har = entries_to_har(&[
+            HistoryEntry {
+                response_body_base64: true,
+                ..history_entry(2, "2024-05-02T10:15:03Z", "image/png", "iVBORw0KGgo=")
+            },
+            history_entry(
+                1,
+                "2024-05-02T10:15:01.250Z",
+                "application/json",
+                r#"{"id":1}"#,
+            ),
+        ]);
+
+        let schema: Value = serde_json::from_str(HAR_SCHEMA).unwrap();
+        let compiled = jsonschema::JSONSchema::compile(&schema).unwrap();
+        if let Err(errors) = compiled.validate(&har) {
+            let errors: Vec<String> = errors.map(|e| e.to_string()).collect();
+            panic!("HAR does not match the schema: {:?}", errors);
+        }
+
+        let entries = har["log"]["entries"].as_array().unwrap();
+        assert_eq!(entries[0]["startedDateTime"], "2024-05-02T10:15:01.250Z");
+        assert_eq!(entries[1]["startedDateTime"], "2024-05-02T10:15:03.000Z");
+        assert_eq!(entries[0]["time"], 42);
+        assert_eq!(
+            entries[0]["request"]["queryString"][1],
+            json!({"name": "tag", "value": "b"})
+        );
+        assert_eq!(
+            entries[0]["request"]["cookies"],
+            json!([{"name": "session", "value": "xyz"}])
+        );
+        assert_eq!(
+            entries[0]["request"]["postData"]["mimeType"],
+            "application/json"
+        );
+        assert_eq!(entries[0]["response"]["redirectURL"], "/items/1");
+        assert!(entries[0]["response"]["content"].get("encoding").is_none());
+        assert_eq!(entries[1]["response"]["content"]["encoding"], "base64");
+    }
+
+    #[test]
+    fn test_export_har_encoding_follows_stored_body() {
+        // Text that happens to decode as base64 is still text
+        let har = entries_to_har(&[history_entry(
+            1,
+            "2024-05-02T10:15:01Z",
+            "application/octet-stream",
+            "abcd",
+        )]);
+        let content = &har["log"]["entries"][0]["response"]["content"];
+        assert_eq!(content["text"], "abcd");
+        assert!(content.get("encoding").is_none());
+    }
+
+    #[test]
+    fn test_export_har_round_trips_through_import() {
+        let har = entries_to_har(&[history_entry(
+            1,
+            "2024-05-02T10:15:01Z",
+            "application/json",
+            "{}",
+        )]);
+        let conversion = har_to_http(&har.to_string(), &HarImportOptions::default(), 1).unwrap();
+        assert_eq!(
+            conversion.http,
+            "### POST /items\nPOST https://api.example.com/items?tag=a&tag=b\nContent-Type: application/json\n\n{\"name\": \"Ada\"}\n"
+        );
+    }
+}
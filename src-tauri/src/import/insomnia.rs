@@ -0,0 +1,481 @@
+use regex::Regex;
+use serde_json::{Map, Value};
+use std::path::PathBuf;
+
+use super::{
+    multipart_body, render_requests, sanitize_file_name, ConvertedCollection, FormPart,
+    RequestBlock, MULTIPART_BOUNDARY,
+};
+
+/// Resource types that are converted or intentionally have no .http equivalent
+const HANDLED_TYPES: &[&str] = &[
+    "workspace",
+    "request_group",
+    "request",
+    "environment",
+    "cookie_jar",
+];
+
+/// Convert an Insomnia v4 export (JSON or YAML) to .http files.
+///
+/// Each workspace and request group becomes an .http file in a directory tree
+/// mirroring the export. The base environment becomes `$shared` and its sub
+/// environments become named environments.
+pub fn insomnia_to_http(content: &str) -> Result<ConvertedCollection, String> {
+    let export: Value = match serde_json::from_str(content) {
+        Ok(value) => value,
+        Err(_) => serde_yaml::from_str(content)
+            .map_err(|e| format!("Failed to parse Insomnia export: {}", e))?,
+    };
+
+    if export.get("__export_format").and_then(Value::as_u64) != Some(4) {
+        return Err("Not an Insomnia v4 export".to_string());
+    }
+    let resources = export
+        .get("resources")
+        .and_then(Value::as_array)
+        .ok_or_else(|| "Insomnia export has no resources".to_string())?;
+
+    let importer = Importer::new(resources);
+    let mut converted = ConvertedCollection::default();
+
+    for resource in resources {
+        let kind = resource
+            .get("_type")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        if !HANDLED_TYPES.contains(&kind) {
+            converted.warnings.push(format!(
+                "Skipped {} '{}': not supported",
+                kind.replace('_', " "),
+                name_of(resource)
+            ));
+        }
+    }
+
+    for workspace in importer.of_type("workspace") {
+        importer.walk(
+            workspace,
+            PathBuf::new(),
+            &sanitize_file_name(name_of(workspace)),
+            &mut converted,
+        );
+        importer.environments(workspace, &mut converted);
+    }
+
+    Ok(converted)
+}
+
+struct Importer<'a> {
+    resources: &'a [Value],
+    /// `{{ _.var }}` from Insomnia's Nunjucks templating
+    context_var_re: Regex,
+    /// `{{ var }}` with spaces or older syntax without the `_.` prefix
+    plain_var_re: Regex,
+    /// `{% tag ... %}` template tags
+    tag_re: Regex,
+}
+
+impl<'a> Importer<'a> {
+    fn new(resources: &'a [Value]) -> Self {
+        Self {
+            resources,
+            context_var_re: Regex::new(r"\{\{\s*_\.([\w.-]+)\s*\}\}").unwrap(),
+            plain_var_re: Regex::new(r"\{\{\s*([\w.-]+)\s*\}\}").unwrap(),
+            tag_re: Regex::new(r"\{%\s*(\w+)[^%]*%\}").unwrap(),
+        }
+    }
+
+    fn of_type(&self, kind: &'a str) -> impl Iterator<Item = &'a Value> + 'a {
+        self.resources
+            .iter()
+            .filter(move |r| r.get("_type").and_then(Value::as_str) == Some(kind))
+    }
+
+    fn children(&self, parent: &Value) -> Vec<&'a Value> {
+        let id = parent.get("_id").and_then(Value::as_str);
+        let mut children: Vec<&Value> = self
+            .resources
+            .iter()
+            .filter(|r| id.is_some() && r.get("parentId").and_then(Value::as_str) == id)
+            .collect();
+        // Insomnia orders siblings by metaSortKey
+        children.sort_by(|a, b| {
+            let key = |r: &Value| r.get("metaSortKey").and_then(Value::as_f64).unwrap_or(0.0);
+            key(a).total_cmp(&key(b))
+        });
+        children
+    }
+
+    /// Rewrite Insomnia templating to kvile variables
+    fn template(&self, text: &str, warnings: &mut Vec<String>) -> String {
+        let text = self.context_var_re.replace_all(text, "{{$1}}");
+        let text = self.plain_var_re.replace_all(&text, "{{$1}}");
+        self.tag_re
+            .replace_all(&text, |caps: &regex::Captures| match &caps[1] {
+                "uuid" => "{{$uuid}}".to_string(),
+                "now" if caps[0].contains("millis") => "{{$timestampMs}}".to_string(),
+                "now" if caps[0].contains("unix") => "{{$timestamp}}".to_string(),
+                "now" => "{{$isoTimestamp}}".to_string(),
+                tag => {
+                    warnings.push(format!("Template tag '{}' has no kvile equivalent", tag));
+                    caps[0].to_string()
+                }
+            })
+            .into_owned()
+    }
+
+    /// Convert the requests under a workspace or request group, recursing into groups
+    fn walk(
+        &self,
+        parent: &Value,
+        dir: PathBuf,
+        file_stem: &str,
+        converted: &mut ConvertedCollection,
+    ) {
+        let mut requests = Vec::new();
+
+        for child in self.children(parent) {
+            match child.get("_type").and_then(Value::as_str) {
+                Some("request_group") => {
+                    if let Some(Value::Object(data)) = child.get("environment") {
+                        let shared = converted
+                            .environments
+                            .entry("$shared".to_string())
+                            .or_default();
+                        for (key, value) in data {
+                            shared.entry(key.clone()).or_insert(value.clone());
+                        }
+                    }
+                    let folder = sanitize_file_name(name_of(child));
+                    self.walk(child, dir.join(file_stem), &folder, converted);
+                }
+                Some("request") => {
+                    let mut warnings = Vec::new();
+                    let block = self.convert_request(child, &mut warnings);
+                    converted.warnings.extend(warnings);
+                    requests.push(block);
+                }
+                _ => {}
+            }
+        }
+
+        if !requests.is_empty() {
+            let path = if dir.as_os_str().is_empty() {
+                PathBuf::from(format!("{}.http", file_stem))
+            } else {
+                dir.join(format!("{}.http", file_stem))
+            };
+            converted.files.push((path, render_requests(&requests)));
+        }
+    }
+
+    fn convert_request(&self, request: &Value, warnings: &mut Vec<String>) -> RequestBlock {
+        let name = name_of(request);
+        let mut block = RequestBlock {
+            name: name.to_string(),
+            method: request
+                .get("method")
+                .and_then(Value::as_str)
+                .unwrap_or("GET")
+                .to_uppercase(),
+            ..Default::default()
+        };
+
+        let mut url = self.template(str_of(request, "url"), warnings);
+        let params: Vec<String> = enabled(request.get("parameters"))
+            .map(|p| {
+                format!(
+                    "{}={}",
+                    self.template(str_of(p, "name"), warnings),
+                    self.template(str_of(p, "value"), warnings)
+                )
+            })
+            .collect();
+        if !params.is_empty() {
+            url.push(if url.contains('?') { '&' } else { '?' });
+            url.push_str(&params.join("&"));
+        }
+        block.url = url;
+
+        for header in enabled(request.get("headers")) {
+            let key = str_of(header, "name");
+            if !key.is_empty() {
+                let value = self.template(str_of(header, "value"), warnings);
+                block.headers.push((key.to_string(), value));
+            }
+        }
+
+        if let Some(body) = request.get("body") {
+            self.convert_body(name, body, &mut block, warnings);
+        }
+        if let Some(auth) = request.get("authentication") {
+            self.convert_auth(name, auth, &mut block, warnings);
+        }
+
+        block
+    }
+
+    fn convert_body(
+        &self,
+        name: &str,
+        body: &Value,
+        block: &mut RequestBlock,
+        warnings: &mut Vec<String>,
+    ) {
+        let mime = str_of(body, "mimeType");
+
+        match mime {
+            "application/x-www-form-urlencoded" => {
+                let pairs: Vec<String> = enabled(body.get("params"))
+                    .map(|p| {
+                        format!(
+                            "{}={}",
+                            self.template(str_of(p, "name"), warnings),
+                            self.template(str_of(p, "value"), warnings)
+                        )
+                    })
+                    .collect();
+                set_content_type(block, mime);
+                block.body = Some(pairs.join("&"));
+            }
+            "multipart/form-data" => {
+                let parts: Vec<FormPart> = enabled(body.get("params"))
+                    .map(|p| {
+                        let name = str_of(p, "name").to_string();
+                        if str_of(p, "type") == "file" {
+                            FormPart::File {
+                                name,
+                                path: str_of(p, "fileName").to_string(),
+                            }
+                        } else {
+                            FormPart::Text {
+                                name,
+                                value: self.template(str_of(p, "value"), warnings),
+                            }
+                        }
+                    })
+                    .collect();
+                // Insomnia's own header has no boundary, so replace it
+                block
+                    .headers
+                    .retain(|(k, _)| !k.eq_ignore_ascii_case("Content-Type"));
+                block.headers.push((
+                    "Content-Type".to_string(),
+                    format!("multipart/form-data; boundary={}", MULTIPART_BOUNDARY),
+                ));
+                block.body = Some(multipart_body(&parts));
+            }
+            "application/octet-stream" if body.get("fileName").is_some() => {
+                let file = str_of(body, "fileName");
+                if file.is_empty() {
+                    warnings.push(format!("'{}': binary body has no file selected", name));
+                } else {
+                    block.body = Some(format!("< {}", super::curl::portable_path(file)));
+                }
+            }
+            "application/graphql" => {
+                // Stored as a JSON document of query and variables already
+                let text = str_of(body, "text");
+                set_content_type(block, "application/json");
+                block.body = Some(self.template(text, warnings));
+                block
+                    .headers
+                    .iter_mut()
+                    .filter(|(k, _)| k.eq_ignore_ascii_case("Content-Type"))
+                    .for_each(|(_, v)| *v = "application/json".to_string());
+            }
+            _ => {
+                let text = str_of(body, "text");
+                if !text.is_empty() {
+                    if !mime.is_empty() {
+                        set_content_type(block, mime);
+                    }
+                    block.body = Some(self.template(text, warnings));
+                }
+            }
+        }
+    }
+
+    /// Map Insomnia auth to a header or kvile's `# @auth` metadata
+    fn convert_auth(
+        &self,
+        name: &str,
+        auth: &Value,
+        block: &mut RequestBlock,
+        warnings: &mut Vec<String>,
+    ) {
+        if auth.get("disabled").and_then(Value::as_bool) == Some(true) {
+            return;
+        }
+        let kind = str_of(auth, "type");
+        let mut field = |key: &str| self.template(str_of(auth, key), warnings);
+
+        match kind {
+            "" | "none" => {}
+            "bearer" => {
+                let prefix = match field("prefix") {
+                    prefix if prefix.is_empty() => "Bearer".to_string(),
+                    prefix => prefix,
+                };
+                let token = field("token");
+                block
+                    .headers
+                    .push(("Authorization".to_string(), format!("{} {}", prefix, token)));
+            }
+            "basic" => {
+                let username = field("username");
+                let password = field("password");
+                block
+                    .metadata
+                    .push(("auth".to_string(), "basic".to_string()));
+                block.metadata.push(("auth.username".to_string(), username));
+                block.metadata.push(("auth.password".to_string(), password));
+            }
+            "apikey" => {
+                let key = field("key");
+                let value = field("value");
+                let location = match str_of(auth, "addTo") {
+                    "queryParams" => "query",
+                    _ => "header",
+                };
+                block
+                    .metadata
+                    .push(("auth".to_string(), "apiKey".to_string()));
+                block.metadata.push(("auth.header".to_string(), key));
+                block.metadata.push(("auth.value".to_string(), value));
+                block
+                    .metadata
+                    .push(("auth.in".to_string(), location.to_string()));
+            }
+            other => warnings.push(format!("'{}': unsupported auth type '{}'", name, other)),
+        }
+    }
+
+    /// Base environment to `$shared`, sub environments to named environments
+    fn environments(&self, workspace: &Value, converted: &mut ConvertedCollection) {
+        for base in self
+            .children(workspace)
+            .into_iter()
+            .filter(|r| r.get("_type").and_then(Value::as_str) == Some("environment"))
+        {
+            merge_env(converted, "$shared", base.get("data"));
+
+            for sub in self
+                .children(base)
+                .into_iter()
+                .filter(|r| r.get("_type").and_then(Value::as_str) == Some("environment"))
+            {
+                merge_env(converted, name_of(sub), sub.get("data"));
+            }
+        }
+    }
+}
+
+fn merge_env(converted: &mut ConvertedCollection, name: &str, data: Option<&Value>) {
+    let Some(Value::Object(data)) = data else {
+        return;
+    };
+    let env: &mut Map<String, Value> = converted.environments.entry(name.to_string()).or_default();
+    for (key, value) in data {
+        env.entry(key.clone()).or_insert(value.clone());
+    }
+}
+
+fn set_content_type(block: &mut RequestBlock, content_type: &str) {
+    if block.header("Content-Type").is_none() {
+        block
+            .headers
+            .push(("Content-Type".to_string(), content_type.to_string()));
+    }
+}
+
+fn name_of(resource: &Value) -> &str {
+    resource
+        .get("name")
+        .and_then(Value::as_str)
+        .unwrap_or("Untitled")
+}
+
+fn str_of<'v>(value: &'v Value, key: &str) -> &'v str {
+    value.get(key).and_then(Value::as_str).unwrap_or_default()
+}
+
+/// Entries of a name/value list that aren't disabled
+fn enabled(list: Option<&Value>) -> impl Iterator<Item = &Value> {
+    list.and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter(|entry| entry.get("disabled").and_then(Value::as_bool) != Some(true))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPORT: &str = include_str!("../../tests/fixtures/insomnia-export.json");
+
+    #[test]
+    fn test_insomnia_export() {
+        let converted = insomnia_to_http(EXPORT).unwrap();
+
+        let paths: Vec<_> = converted
+            .files
+            .iter()
+            .map(|(p, _)| p.to_string_lossy().replace('\\', "/"))
+            .collect();
+        assert_eq!(paths, vec!["Kvile Demo/Users.http", "Kvile Demo.http"]);
+
+        let users = &converted.files[0].1;
+        assert!(users.contains(
+            "### List users\nGET {{baseUrl}}/users?page=1\nAccept: application/json\nAuthorization: Bearer {{token}}\n"
+        ));
+        assert!(users.contains(
+            "### Create user\n# @auth basic\n# @auth.username admin\n# @auth.password {{password}}\nPOST {{baseUrl}}/users\nContent-Type: application/json\n\n{\"id\": \"{{$uuid}}\", \"name\": \"Ada\"}\n"
+        ));
+        assert!(users.contains(
+            "Content-Type: multipart/form-data; boundary=WebAppBoundary\n\n--WebAppBoundary\nContent-Disposition: form-data; name=\"note\"\n\nhello\n--WebAppBoundary\nContent-Disposition: form-data; name=\"file\"; filename=\"avatar.png\"\n\n< /tmp/avatar.png\n--WebAppBoundary--\n"
+        ));
+        for (_, content) in &converted.files {
+            crate::parser::parse_http_content(content).unwrap();
+        }
+
+        assert_eq!(
+            converted.environments["$shared"]["baseUrl"],
+            "https://api.example.com"
+        );
+        assert_eq!(converted.environments["$shared"]["pageSize"], 20);
+        assert_eq!(converted.environments["Production"]["token"], "prod-token");
+
+        assert_eq!(
+            converted.warnings,
+            vec![
+                "Skipped grpc request 'Greeter': not supported",
+                "Skipped websocket request 'Live feed': not supported",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_yaml_export() {
+        let yaml = r#"
+_type: export
+__export_format: 4
+resources:
+  - _id: wrk_1
+    _type: workspace
+    parentId: null
+    name: YAML
+  - _id: req_1
+    _type: request
+    parentId: wrk_1
+    name: Ping
+    method: get
+    url: "{{ _.host }}/ping"
+"#;
+        let converted = insomnia_to_http(yaml).unwrap();
+        assert_eq!(converted.files[0].1, "### Ping\nGET {{host}}/ping\n");
+
+        assert!(insomnia_to_http(r#"{"resources": []}"#).is_err());
+    }
+}
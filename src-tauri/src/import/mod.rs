@@ -0,0 +1,246 @@
+//! Converters from other tools' request formats to .http files
+
+pub mod curl;
+pub mod fetch;
+pub mod har;
+pub mod insomnia;
+pub mod openapi;
+pub mod postman;
+pub mod powershell;
+pub mod raw;
+pub mod thunder;
+pub mod wget;
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Result of an import: the .http text plus everything that could not be
+/// carried over exactly
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportResult {
+    pub http: String,
+    pub warnings: Vec<String>,
+}
+
+/// Files written by a collection import
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportSummary {
+    pub files_created: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+/// A converted collection, before anything is written to disk
+#[derive(Debug, Default)]
+pub struct ConvertedCollection {
+    /// .http files relative to the output directory
+    pub files: Vec<(PathBuf, String)>,
+    /// Variables for http-client.env.json, keyed by environment (`$shared` for shared)
+    pub environments: BTreeMap<String, Map<String, Value>>,
+    pub warnings: Vec<String>,
+}
+
+/// One request in an .http file
+#[derive(Debug, Default)]
+pub struct RequestBlock {
+    pub name: String,
+    /// Comment lines placed above the request line
+    pub comments: Vec<String>,
+    /// `# @key value` lines
+    pub metadata: Vec<(String, String)>,
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<String>,
+}
+
+impl RequestBlock {
+    pub fn header(&self, name: &str) -> Option<&String> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v)
+    }
+
+    /// Render as `### name` followed by the request
+    pub fn render(&self) -> String {
+        let mut output = format!("### {}\n", self.name);
+        for comment in &self.comments {
+            for line in comment.lines() {
+                if line.trim().is_empty() {
+                    output.push_str("#\n");
+                } else {
+                    output.push_str(&format!("# {}\n", line));
+                }
+            }
+        }
+        for (key, value) in &self.metadata {
+            output.push_str(&format!("# @{} {}\n", key, value));
+        }
+        output.push_str(&format!("{} {}\n", self.method, self.url));
+        for (key, value) in &self.headers {
+            output.push_str(&format!("{}: {}\n", key, value));
+        }
+        if let Some(body) = &self.body {
+            output.push('\n');
+            output.push_str(body.trim_end());
+            output.push('\n');
+        }
+        output
+    }
+}
+
+/// Render requests as the contents of one .http file
+pub fn render_requests(requests: &[RequestBlock]) -> String {
+    requests
+        .iter()
+        .map(RequestBlock::render)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Boundary used for multipart bodies generated by importers
+pub const MULTIPART_BOUNDARY: &str = "WebAppBoundary";
+
+/// A part of a multipart/form-data body
+pub enum FormPart {
+    Text { name: String, value: String },
+    File { name: String, path: String },
+}
+
+/// Render a multipart/form-data body; file parts become `< path` includes
+pub fn multipart_body(parts: &[FormPart]) -> String {
+    let mut body = String::new();
+    for part in parts {
+        body.push_str(&format!("--{}\n", MULTIPART_BOUNDARY));
+        match part {
+            FormPart::Text { name, value } => {
+                body.push_str(&format!(
+                    "Content-Disposition: form-data; name=\"{}\"\n\n{}\n",
+                    name, value
+                ));
+            }
+            FormPart::File { name, path } => {
+                let filename = Path::new(path)
+                    .file_name()
+                    .map(|f| f.to_string_lossy().to_string())
+                    .unwrap_or_else(|| path.clone());
+                body.push_str(&format!(
+                    "Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\n\n< {}\n",
+                    name,
+                    filename,
+                    curl::portable_path(path)
+                ));
+            }
+        }
+    }
+    body.push_str(&format!("--{}--", MULTIPART_BOUNDARY));
+    body
+}
+
+/// Turn a collection or folder name into a safe file name
+pub fn sanitize_file_name(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '-',
+            c if c.is_control() => '-',
+            c => c,
+        })
+        .collect();
+    let cleaned = cleaned.trim().trim_matches('.').to_string();
+    if cleaned.is_empty() {
+        "Untitled".to_string()
+    } else {
+        cleaned
+    }
+}
+
+/// Pick a path that is neither taken on disk nor already used by this import
+fn unique_path(output_dir: &Path, relative: &Path, used: &mut HashSet<PathBuf>) -> PathBuf {
+    let stem = relative
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let extension = relative
+        .extension()
+        .map(|e| e.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let mut candidate = output_dir.join(relative);
+    let mut n = 2;
+    while used.contains(&candidate) || candidate.exists() {
+        candidate = output_dir
+            .join(relative)
+            .with_file_name(format!("{} ({}).{}", stem, n, extension));
+        n += 1;
+    }
+    used.insert(candidate.clone());
+    candidate
+}
+
+/// Write a converted collection below `output_dir`.
+///
+/// Existing .http files are never overwritten, and variables are merged into
+/// an existing http-client.env.json without replacing values already there.
+pub async fn write_collection(
+    output_dir: &Path,
+    collection: ConvertedCollection,
+) -> Result<ImportSummary, String> {
+    let mut files_created = Vec::new();
+    let mut used = HashSet::new();
+
+    for (relative, content) in collection.files {
+        let path = unique_path(output_dir, &relative, &mut used);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| format!("Failed to create directory: {}", e))?;
+        }
+        tokio::fs::write(&path, content)
+            .await
+            .map_err(|e| format!("Failed to write file: {}", e))?;
+        files_created.push(path.to_string_lossy().to_string());
+    }
+
+    if collection
+        .environments
+        .values()
+        .any(|vars| !vars.is_empty())
+    {
+        let env_path = output_dir.join("http-client.env.json");
+        let mut existing: Map<String, Value> = if env_path.exists() {
+            let content = tokio::fs::read_to_string(&env_path)
+                .await
+                .map_err(|e| format!("Failed to read env file: {}", e))?;
+            serde_json::from_str(&content)
+                .map_err(|e| format!("Failed to parse env file: {}", e))?
+        } else {
+            Map::new()
+        };
+
+        for (env_name, vars) in collection.environments {
+            let entry = existing
+                .entry(env_name)
+                .or_insert_with(|| Value::Object(Map::new()));
+            if let Value::Object(entry) = entry {
+                for (key, value) in vars {
+                    entry.entry(key).or_insert(value);
+                }
+            }
+        }
+
+        let content = serde_json::to_string_pretty(&existing)
+            .map_err(|e| format!("Failed to serialize env file: {}", e))?;
+        tokio::fs::write(&env_path, content)
+            .await
+            .map_err(|e| format!("Failed to write env file: {}", e))?;
+        files_created.push(env_path.to_string_lossy().to_string());
+    }
+
+    Ok(ImportSummary {
+        files_created,
+        warnings: collection.warnings,
+    })
+}
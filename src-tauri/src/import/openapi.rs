@@ -0,0 +1,724 @@
+use serde_json::{json, Map, Value};
+use std::path::PathBuf;
+
+use super::{
+    multipart_body, render_requests, sanitize_file_name, ConvertedCollection, FormPart,
+    RequestBlock, MULTIPART_BOUNDARY,
+};
+
+const METHODS: &[&str] = &[
+    "get", "put", "post", "delete", "options", "head", "patch", "trace",
+];
+
+/// How deep example generation follows nested and recursive schemas
+const MAX_SCHEMA_DEPTH: usize = 8;
+
+/// Generate .http files from an OpenAPI 3.x or Swagger 2.0 spec (JSON or YAML).
+///
+/// Operations are grouped by their first tag into `<tag>.http`; untagged
+/// operations go into a file named after the API. Each file defines the
+/// first server URL as `@<base_url_var>` so requests read `{{baseUrl}}/path`.
+pub fn openapi_to_http(content: &str, base_url_var: &str) -> Result<ConvertedCollection, String> {
+    let spec: Value = match serde_json::from_str(content) {
+        Ok(value) => value,
+        Err(_) => serde_yaml::from_str(content)
+            .map_err(|e| format!("Failed to parse OpenAPI spec: {}", e))?,
+    };
+
+    let swagger = match (spec.get("openapi"), spec.get("swagger")) {
+        (Some(Value::String(version)), _) if version.starts_with('3') => false,
+        (_, Some(Value::String(version))) if version.starts_with('2') => true,
+        (Some(version), _) | (_, Some(version)) => {
+            return Err(format!("Unsupported OpenAPI version: {}", version))
+        }
+        (None, None) => return Err("Not an OpenAPI or Swagger spec".to_string()),
+    };
+    let paths = spec
+        .get("paths")
+        .and_then(Value::as_object)
+        .ok_or_else(|| "OpenAPI spec has no paths".to_string())?;
+
+    let title = spec
+        .pointer("/info/title")
+        .and_then(Value::as_str)
+        .unwrap_or("OpenAPI");
+    let importer = Importer {
+        spec: &spec,
+        swagger,
+    };
+    let mut converted = ConvertedCollection::default();
+
+    // Tags listed at the top level keep their declared order
+    let mut groups: Vec<(String, Vec<RequestBlock>)> = spec
+        .get("tags")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|tag| tag.get("name").and_then(Value::as_str))
+        .map(|name| (name.to_string(), Vec::new()))
+        .collect();
+
+    for (path, item) in paths {
+        let item = importer.resolve(item);
+        for method in METHODS {
+            let Some(operation) = item.get(*method) else {
+                continue;
+            };
+            let block = importer.convert_operation(
+                path,
+                method,
+                item,
+                operation,
+                base_url_var,
+                &mut converted.warnings,
+            );
+            let group = operation
+                .pointer("/tags/0")
+                .and_then(Value::as_str)
+                .unwrap_or(title);
+            match groups.iter_mut().find(|(name, _)| name == group) {
+                Some((_, blocks)) => blocks.push(block),
+                None => groups.push((group.to_string(), vec![block])),
+            }
+        }
+    }
+
+    let header = format!("@{} = {}\n\n", base_url_var, importer.server_url());
+    for (group, blocks) in groups {
+        if blocks.is_empty() {
+            continue;
+        }
+        converted.files.push((
+            PathBuf::from(format!("{}.http", sanitize_file_name(&group))),
+            format!("{}{}", header, render_requests(&blocks)),
+        ));
+    }
+
+    if converted.files.is_empty() {
+        return Err("OpenAPI spec has no operations".to_string());
+    }
+    Ok(converted)
+}
+
+struct Importer<'a> {
+    spec: &'a Value,
+    swagger: bool,
+}
+
+impl<'a> Importer<'a> {
+    /// Follow a local `$ref` (`#/components/...` or `#/definitions/...`)
+    fn resolve(&self, value: &'a Value) -> &'a Value {
+        let mut value = value;
+        // A handful of hops covers refs to refs without looping forever
+        for _ in 0..MAX_SCHEMA_DEPTH {
+            match value.get("$ref").and_then(Value::as_str) {
+                Some(reference) => match reference
+                    .strip_prefix('#')
+                    .and_then(|pointer| self.spec.pointer(pointer))
+                {
+                    Some(target) => value = target,
+                    None => break,
+                },
+                None => break,
+            }
+        }
+        value
+    }
+
+    /// The first server URL, with server variables set to their defaults
+    fn server_url(&self) -> String {
+        let url = if self.swagger {
+            let host = self.spec.get("host").and_then(Value::as_str);
+            let base_path = self
+                .spec
+                .get("basePath")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            let scheme = self
+                .spec
+                .pointer("/schemes/0")
+                .and_then(Value::as_str)
+                .unwrap_or("https");
+            match host {
+                Some(host) => format!("{}://{}{}", scheme, host, base_path),
+                None => base_path.to_string(),
+            }
+        } else {
+            match self.spec.pointer("/servers/0") {
+                Some(server) => {
+                    let mut url = server
+                        .get("url")
+                        .and_then(Value::as_str)
+                        .unwrap_or_default()
+                        .to_string();
+                    if let Some(Value::Object(variables)) = server.get("variables") {
+                        for (name, variable) in variables {
+                            let default =
+                                variable.get("default").map(scalar_text).unwrap_or_default();
+                            url = url.replace(&format!("{{{}}}", name), &default);
+                        }
+                    }
+                    url
+                }
+                None => String::new(),
+            }
+        };
+        let url = url.trim_end_matches('/').to_string();
+        if url.is_empty() {
+            "http://localhost".to_string()
+        } else {
+            url
+        }
+    }
+
+    fn convert_operation(
+        &self,
+        path: &str,
+        method: &str,
+        item: &Value,
+        operation: &Value,
+        base_url_var: &str,
+        warnings: &mut Vec<String>,
+    ) -> RequestBlock {
+        let name = operation
+            .get("operationId")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("{} {}", method.to_uppercase(), path));
+        let mut block = RequestBlock {
+            name,
+            method: method.to_uppercase(),
+            ..Default::default()
+        };
+        if let Some(summary) = operation.get("summary").and_then(Value::as_str) {
+            block.comments.push(summary.trim().to_string());
+        }
+        if operation.get("deprecated") == Some(&Value::Bool(true)) {
+            block.comments.push("Deprecated".to_string());
+        }
+
+        // Operation parameters override path-level ones with the same name and location
+        let mut parameters: Vec<&Value> = Vec::new();
+        for parameter in [item.get("parameters"), operation.get("parameters")]
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_array)
+            .flatten()
+        {
+            let parameter = self.resolve(parameter);
+            let key = |p: &Value| (str_of(p, "name").to_string(), str_of(p, "in").to_string());
+            parameters.retain(|existing| key(existing) != key(parameter));
+            parameters.push(parameter);
+        }
+
+        let mut query = Vec::new();
+        let mut form = Vec::new();
+        let mut body_schema = None;
+        for parameter in &parameters {
+            let name = str_of(parameter, "name");
+            let required = parameter.get("required") == Some(&Value::Bool(true));
+            match str_of(parameter, "in") {
+                "query" if required => {
+                    query.push(format!("{}={}", name, self.parameter_value(parameter)))
+                }
+                "header" if required => block
+                    .headers
+                    .push((name.to_string(), format!("{{{{{}}}}}", name))),
+                "body" => body_schema = parameter.get("schema"),
+                "formData" => form.push(*parameter),
+                _ => {}
+            }
+        }
+
+        let mut url = format!("{{{{{}}}}}{}", base_url_var, path_variables(path));
+        if !query.is_empty() {
+            url.push('?');
+            url.push_str(&query.join("&"));
+        }
+        block.url = url;
+
+        let security = operation.get("security").or(self.spec.get("security"));
+        self.apply_security(security, &mut block, warnings);
+
+        if self.swagger {
+            let consumes = operation
+                .get("consumes")
+                .or(self.spec.get("consumes"))
+                .and_then(|c| c.get(0))
+                .and_then(Value::as_str);
+            if let Some(schema) = body_schema {
+                let example = self.example(schema, 0);
+                set_json_body(&mut block, &example);
+            } else if !form.is_empty() {
+                let multipart = consumes == Some("multipart/form-data")
+                    || form.iter().any(|p| str_of(p, "type") == "file");
+                let fields = form
+                    .iter()
+                    .map(|p| {
+                        let value = if str_of(p, "type") == "file" {
+                            None
+                        } else {
+                            Some(self.example(p, 0))
+                        };
+                        (str_of(p, "name").to_string(), value)
+                    })
+                    .collect();
+                set_form_body(&mut block, fields, multipart);
+            }
+        } else if let Some(body) = operation.get("requestBody") {
+            self.request_body(self.resolve(body), &mut block, warnings);
+        }
+
+        block
+    }
+
+    /// The example value of a query parameter, or a `{{name}}` variable
+    fn parameter_value(&self, parameter: &Value) -> String {
+        let schema = parameter.get("schema").map(|s| self.resolve(s));
+        let example = parameter
+            .get("example")
+            .or_else(|| schema.and_then(|s| s.get("example")))
+            .or_else(|| parameter.get("default"))
+            .or_else(|| schema.and_then(|s| s.get("default")));
+        match example {
+            Some(value) if !value.is_array() && !value.is_object() => scalar_text(value),
+            _ => format!("{{{{{}}}}}", str_of(parameter, "name")),
+        }
+    }
+
+    /// Add an OpenAPI 3 request body, preferring JSON over other media types
+    fn request_body(&self, body: &Value, block: &mut RequestBlock, warnings: &mut Vec<String>) {
+        let Some(Value::Object(content)) = body.get("content") else {
+            return;
+        };
+        let media_type = [
+            "application/json",
+            "application/x-www-form-urlencoded",
+            "multipart/form-data",
+        ]
+        .iter()
+        .find(|m| content.contains_key(**m))
+        .map(|m| m.to_string())
+        .or_else(|| content.keys().find(|m| m.contains("json")).cloned())
+        .or_else(|| content.keys().next().cloned());
+        let Some(media_type) = media_type else {
+            return;
+        };
+        let media = &content[&media_type];
+        let schema = media.get("schema").map(|s| self.resolve(s));
+        let example = media
+            .get("example")
+            .cloned()
+            .or_else(|| {
+                media
+                    .get("examples")
+                    .and_then(Value::as_object)
+                    .and_then(|examples| examples.values().next())
+                    .map(|example| self.resolve(example))
+                    .and_then(|example| example.get("value"))
+                    .cloned()
+            })
+            .or_else(|| schema.map(|s| self.example(s, 0)))
+            .unwrap_or(Value::Null);
+
+        match media_type.as_str() {
+            "application/x-www-form-urlencoded" | "multipart/form-data" => {
+                let fields = match (&example, schema) {
+                    (Value::Object(fields), _) => fields
+                        .iter()
+                        .map(|(name, value)| {
+                            let binary = schema
+                                .and_then(|s| s.get("properties"))
+                                .and_then(|p| p.get(name))
+                                .map(|p| self.resolve(p))
+                                .is_some_and(|p| {
+                                    matches!(str_of(p, "format"), "binary" | "base64")
+                                });
+                            (name.clone(), (!binary).then(|| value.clone()))
+                        })
+                        .collect(),
+                    _ => Vec::new(),
+                };
+                set_form_body(block, fields, media_type == "multipart/form-data");
+            }
+            media_type if media_type.contains("json") => {
+                set_json_body(block, &example);
+                if let Some((_, value)) = block
+                    .headers
+                    .iter_mut()
+                    .find(|(k, _)| k.eq_ignore_ascii_case("Content-Type"))
+                {
+                    *value = media_type.to_string();
+                }
+            }
+            media_type => {
+                block
+                    .headers
+                    .push(("Content-Type".to_string(), media_type.to_string()));
+                match example {
+                    Value::String(text) => block.body = Some(text),
+                    _ => warnings.push(format!(
+                        "No example body generated for '{}' ({})",
+                        block.name, media_type
+                    )),
+                }
+            }
+        }
+    }
+
+    /// Add auth header lines for the first security requirement
+    fn apply_security(
+        &self,
+        security: Option<&Value>,
+        block: &mut RequestBlock,
+        warnings: &mut Vec<String>,
+    ) {
+        let Some(requirement) = security
+            .and_then(Value::as_array)
+            .and_then(|requirements| requirements.first())
+            .and_then(Value::as_object)
+        else {
+            return;
+        };
+        let schemes = if self.swagger {
+            self.spec.get("securityDefinitions")
+        } else {
+            self.spec.pointer("/components/securitySchemes")
+        };
+
+        for scheme_name in requirement.keys() {
+            let Some(scheme) = schemes.and_then(|s| s.get(scheme_name)) else {
+                warnings.push(format!(
+                    "Security scheme '{}' used by '{}' is not defined",
+                    scheme_name, block.name
+                ));
+                continue;
+            };
+            let scheme = self.resolve(scheme);
+            let variable = format!("{{{{{}}}}}", scheme_name);
+            match (str_of(scheme, "type"), str_of(scheme, "scheme")) {
+                ("http", "bearer") | ("oauth2" | "openIdConnect", _) => block
+                    .headers
+                    .push(("Authorization".to_string(), format!("Bearer {}", variable))),
+                ("http", "basic") | ("basic", _) => block
+                    .headers
+                    .push(("Authorization".to_string(), format!("Basic {}", variable))),
+                ("apiKey", _) => {
+                    let name = str_of(scheme, "name");
+                    match str_of(scheme, "in") {
+                        "query" => {
+                            let separator = if block.url.contains('?') { '&' } else { '?' };
+                            block.url = format!("{}{}{}={}", block.url, separator, name, variable);
+                        }
+                        "cookie" => block
+                            .headers
+                            .push(("Cookie".to_string(), format!("{}={}", name, variable))),
+                        _ => block.headers.push((name.to_string(), variable)),
+                    }
+                }
+                (kind, _) => warnings.push(format!(
+                    "Security scheme '{}' ({}) of '{}' was not converted",
+                    scheme_name, kind, block.name
+                )),
+            }
+        }
+    }
+
+    /// Build an example value from a schema: `example`, then `default`, then
+    /// a placeholder for the type
+    fn example(&self, schema: &Value, depth: usize) -> Value {
+        let schema = self.resolve(schema);
+        if depth > MAX_SCHEMA_DEPTH {
+            return Value::Null;
+        }
+        if let Some(value) = schema.get("example").or(schema.get("default")) {
+            return value.clone();
+        }
+        if let Some(first) = schema.pointer("/enum/0") {
+            return first.clone();
+        }
+        if let Some(Value::Array(parts)) = schema.get("allOf") {
+            let mut merged = Map::new();
+            for part in parts {
+                if let Value::Object(fields) = self.example(part, depth + 1) {
+                    merged.extend(fields);
+                }
+            }
+            return Value::Object(merged);
+        }
+        if let Some(first) = schema
+            .pointer("/oneOf/0")
+            .or_else(|| schema.pointer("/anyOf/0"))
+        {
+            return self.example(first, depth + 1);
+        }
+
+        let kind = match schema.get("type") {
+            Some(Value::String(kind)) => kind.as_str(),
+            // OpenAPI 3.1 allows a list of types such as ["string", "null"]
+            Some(Value::Array(kinds)) => kinds
+                .iter()
+                .filter_map(Value::as_str)
+                .find(|k| *k != "null")
+                .unwrap_or("null"),
+            _ if schema.get("properties").is_some() => "object",
+            _ => "",
+        };
+        match kind {
+            "object" => {
+                let mut object = Map::new();
+                if let Some(Value::Object(properties)) = schema.get("properties") {
+                    for (name, property) in properties {
+                        let property = self.resolve(property);
+                        if property.get("readOnly") == Some(&Value::Bool(true)) {
+                            continue;
+                        }
+                        object.insert(name.clone(), self.example(property, depth + 1));
+                    }
+                }
+                Value::Object(object)
+            }
+            "array" => match schema.get("items") {
+                Some(items) => json!([self.example(items, depth + 1)]),
+                None => json!([]),
+            },
+            "integer" => json!(0),
+            "number" => json!(0.0),
+            "boolean" => json!(true),
+            "string" => Value::String(
+                match str_of(schema, "format") {
+                    "date" => "2024-01-01",
+                    "date-time" => "2024-01-01T00:00:00Z",
+                    "email" => "user@example.com",
+                    "uuid" => "00000000-0000-0000-0000-000000000000",
+                    "uri" | "url" => "https://example.com",
+                    _ => "string",
+                }
+                .to_string(),
+            ),
+            _ => Value::Null,
+        }
+    }
+}
+
+/// Turn `{param}` path templates into `{{param}}` variables
+fn path_variables(path: &str) -> String {
+    let mut output = String::with_capacity(path.len());
+    let mut rest = path;
+    while let Some(start) = rest.find('{') {
+        output.push_str(&rest[..start]);
+        match rest[start..].find('}') {
+            Some(end) => {
+                output.push_str(&format!("{{{{{}}}}}", &rest[start + 1..start + end]));
+                rest = &rest[start + end + 1..];
+            }
+            None => {
+                output.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+fn set_json_body(block: &mut RequestBlock, example: &Value) {
+    block
+        .headers
+        .push(("Content-Type".to_string(), "application/json".to_string()));
+    block.body = Some(serde_json::to_string_pretty(example).unwrap_or_default());
+}
+
+/// Set a form body; fields without a value are file uploads
+fn set_form_body(block: &mut RequestBlock, fields: Vec<(String, Option<Value>)>, multipart: bool) {
+    if multipart {
+        let parts: Vec<FormPart> = fields
+            .into_iter()
+            .map(|(name, value)| match value {
+                Some(value) => FormPart::Text {
+                    value: scalar_text(&value),
+                    name,
+                },
+                None => FormPart::File {
+                    path: format!("./{}", name),
+                    name,
+                },
+            })
+            .collect();
+        block.headers.push((
+            "Content-Type".to_string(),
+            format!("multipart/form-data; boundary={}", MULTIPART_BOUNDARY),
+        ));
+        block.body = Some(multipart_body(&parts));
+    } else {
+        let pairs: Vec<String> = fields
+            .into_iter()
+            .map(|(name, value)| {
+                let value = value.map(|v| scalar_text(&v)).unwrap_or_default();
+                format!("{}={}", name, urlencoding::encode(&value))
+            })
+            .collect();
+        block.headers.push((
+            "Content-Type".to_string(),
+            "application/x-www-form-urlencoded".to_string(),
+        ));
+        block.body = Some(pairs.join("&"));
+    }
+}
+
+/// A JSON value as plain text, without quotes around strings
+fn scalar_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+fn str_of<'v>(value: &'v Value, key: &str) -> &'v str {
+    value.get(key).and_then(Value::as_str).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_http_content;
+
+    const PETSTORE: &str = include_str!("../../tests/fixtures/petstore.yaml");
+
+    fn file<'c>(converted: &'c ConvertedCollection, name: &str) -> &'c str {
+        &converted
+            .files
+            .iter()
+            .find(|(path, _)| path == &PathBuf::from(name))
+            .unwrap_or_else(|| panic!("{} was not generated", name))
+            .1
+    }
+
+    #[test]
+    fn test_petstore_groups_by_tag() {
+        let converted = openapi_to_http(PETSTORE, "baseUrl").unwrap();
+        let names: Vec<_> = converted
+            .files
+            .iter()
+            .map(|(path, _)| path.to_string_lossy().to_string())
+            .collect();
+        assert_eq!(
+            names,
+            vec!["pets.http", "store.http", "Swagger Petstore.http"]
+        );
+        assert!(converted.warnings.is_empty(), "{:?}", converted.warnings);
+
+        let pets = file(&converted, "pets.http");
+        assert!(pets.starts_with("@baseUrl = https://petstore.example.com/v1\n\n### listPets\n"));
+        assert!(pets.contains(
+            "### showPetById\n# Info for a specific pet\nGET {{baseUrl}}/pets/{{petId}}\nAuthorization: Bearer {{bearerAuth}}\n"
+        ));
+        assert!(pets.contains(
+            "### createPets\n# Create a pet\nPOST {{baseUrl}}/pets\nX-Request-Id: {{X-Request-Id}}\nAuthorization: Bearer {{bearerAuth}}\nContent-Type: application/json\n\n{\n  \"name\": \"Rex\",\n  \"tag\": \"string\"\n}\n"
+        ));
+    }
+
+    #[test]
+    fn test_petstore_round_trips_through_parser() {
+        let converted = openapi_to_http(PETSTORE, "baseUrl").unwrap();
+        let mut operations = Vec::new();
+        for (path, content) in &converted.files {
+            let requests = parse_http_content(content)
+                .unwrap_or_else(|e| panic!("{} did not parse: {}", path.display(), e));
+            for request in requests {
+                assert_eq!(
+                    request.variables.get("baseUrl").map(String::as_str),
+                    Some("https://petstore.example.com/v1")
+                );
+                operations.push((request.name.unwrap(), request.method, request.url));
+            }
+        }
+
+        assert_eq!(
+            operations,
+            vec![
+                (
+                    "listPets".to_string(),
+                    "GET".to_string(),
+                    "{{baseUrl}}/pets?limit=20".to_string()
+                ),
+                (
+                    "createPets".to_string(),
+                    "POST".to_string(),
+                    "{{baseUrl}}/pets".to_string()
+                ),
+                (
+                    "showPetById".to_string(),
+                    "GET".to_string(),
+                    "{{baseUrl}}/pets/{{petId}}".to_string()
+                ),
+                (
+                    "deletePet".to_string(),
+                    "DELETE".to_string(),
+                    "{{baseUrl}}/pets/{{petId}}".to_string()
+                ),
+                (
+                    "uploadPetPhoto".to_string(),
+                    "POST".to_string(),
+                    "{{baseUrl}}/pets/{{petId}}/photo".to_string()
+                ),
+                (
+                    "getInventory".to_string(),
+                    "GET".to_string(),
+                    "{{baseUrl}}/store/inventory?api_key={{apiKey}}".to_string()
+                ),
+                (
+                    "GET /health".to_string(),
+                    "GET".to_string(),
+                    "{{baseUrl}}/health".to_string()
+                ),
+            ]
+        );
+
+        let requests = parse_http_content(file(&converted, "pets.http")).unwrap();
+        let upload = requests
+            .iter()
+            .find(|r| r.name.as_deref() == Some("uploadPetPhoto"))
+            .unwrap();
+        assert_eq!(
+            upload.body.as_deref(),
+            Some("--WebAppBoundary\nContent-Disposition: form-data; name=\"caption\"\n\nstring\n--WebAppBoundary\nContent-Disposition: form-data; name=\"photo\"; filename=\"photo\"\n\n< ./photo\n--WebAppBoundary--")
+        );
+    }
+
+    #[test]
+    fn test_swagger_2() {
+        let spec = r##"{
+            "swagger": "2.0",
+            "info": {"title": "Legacy", "version": "1"},
+            "host": "legacy.example.com",
+            "basePath": "/api",
+            "schemes": ["http"],
+            "securityDefinitions": {"basic": {"type": "basic"}},
+            "security": [{"basic": []}],
+            "definitions": {
+                "Order": {"type": "object", "properties": {"qty": {"type": "integer", "default": 1}}}
+            },
+            "paths": {
+                "/orders/{id}": {
+                    "put": {
+                        "operationId": "updateOrder",
+                        "parameters": [
+                            {"name": "id", "in": "path", "required": true, "type": "string"},
+                            {"name": "body", "in": "body", "schema": {"$ref": "#/definitions/Order"}}
+                        ]
+                    }
+                }
+            }
+        }"##;
+        let converted = openapi_to_http(spec, "host").unwrap();
+        assert_eq!(
+            converted.files[0].1,
+            "@host = http://legacy.example.com/api\n\n### updateOrder\nPUT {{host}}/orders/{{id}}\nAuthorization: Basic {{basic}}\nContent-Type: application/json\n\n{\n  \"qty\": 1\n}\n"
+        );
+
+        assert!(openapi_to_http(r#"{"openapi": "4.0.0", "paths": {}}"#, "baseUrl").is_err());
+        assert!(openapi_to_http("info: {}", "baseUrl").is_err());
+    }
+}
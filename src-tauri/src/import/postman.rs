@@ -0,0 +1,1001 @@
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::env::EnvironmentConfig;
+use crate::parser::ParsedRequest;
+use crate::variables::{environment_variables, secret_names};
+
+use super::{
+    multipart_body, render_requests, sanitize_file_name, ConvertedCollection, FormPart,
+    RequestBlock, MULTIPART_BOUNDARY,
+};
+
+/// Convert a Postman v2.1 collection to .http files, one per folder.
+///
+/// Requests at the top level go into a file named after the collection;
+/// each folder's requests go into `<folder>.http` inside a directory tree
+/// mirroring the collection.
+pub fn postman_to_http(json: &str) -> Result<ConvertedCollection, String> {
+    let collection: Value =
+        serde_json::from_str(json).map_err(|e| format!("Failed to parse collection: {}", e))?;
+
+    let info = collection
+        .get("info")
+        .ok_or_else(|| "Not a Postman collection: missing info".to_string())?;
+    if let Some(schema) = info.get("schema").and_then(Value::as_str) {
+        if !schema.contains("v2.1") && !schema.contains("v2.0") {
+            return Err(format!("Unsupported Postman collection schema: {}", schema));
+        }
+    }
+    let name = info
+        .get("name")
+        .and_then(Value::as_str)
+        .unwrap_or("Postman Collection");
+
+    let mut converted = ConvertedCollection::default();
+
+    let mut shared = Map::new();
+    collect_variables(&collection, &mut shared);
+    if has_scripts(&collection) {
+        converted.warnings.push(format!(
+            "Collection-level scripts of '{}' were not translated",
+            name
+        ));
+    }
+
+    let items = collection.get("item").and_then(Value::as_array);
+    walk_folder(
+        items.map(Vec::as_slice).unwrap_or_default(),
+        PathBuf::new(),
+        &sanitize_file_name(name),
+        collection.get("auth"),
+        &mut shared,
+        &mut converted,
+    );
+
+    converted.environments.insert("$shared".to_string(), shared);
+    Ok(converted)
+}
+
+/// Convert the requests of one folder and recurse into its subfolders
+fn walk_folder(
+    items: &[Value],
+    dir: PathBuf,
+    file_stem: &str,
+    inherited_auth: Option<&Value>,
+    variables: &mut Map<String, Value>,
+    converted: &mut ConvertedCollection,
+) {
+    let mut requests = Vec::new();
+
+    for item in items {
+        let item_name = item
+            .get("name")
+            .and_then(Value::as_str)
+            .unwrap_or("Untitled");
+
+        if let Some(children) = item.get("item").and_then(Value::as_array) {
+            collect_variables(item, variables);
+            if has_scripts(item) {
+                converted.warnings.push(format!(
+                    "Folder-level scripts of '{}' were not translated",
+                    item_name
+                ));
+            }
+            let auth = item.get("auth").or(inherited_auth);
+            let folder = sanitize_file_name(item_name);
+            walk_folder(
+                children,
+                dir.join(file_stem),
+                &folder,
+                auth,
+                variables,
+                converted,
+            );
+        } else if let Some(request) = item.get("request") {
+            match convert_request(item_name, item, request, inherited_auth) {
+                Ok((block, warnings)) => {
+                    requests.push(block);
+                    converted.warnings.extend(warnings);
+                }
+                Err(e) => converted
+                    .warnings
+                    .push(format!("Skipped request '{}': {}", item_name, e)),
+            }
+        } else {
+            converted.warnings.push(format!(
+                "Skipped item '{}': neither a request nor a folder",
+                item_name
+            ));
+        }
+    }
+
+    if !requests.is_empty() {
+        let path = if dir.as_os_str().is_empty() {
+            PathBuf::from(format!("{}.http", file_stem))
+        } else {
+            dir.join(format!("{}.http", file_stem))
+        };
+        converted.files.push((path, render_requests(&requests)));
+    }
+}
+
+/// Map a Postman request item to a request block, with any warnings
+fn convert_request(
+    name: &str,
+    item: &Value,
+    request: &Value,
+    inherited_auth: Option<&Value>,
+) -> Result<(RequestBlock, Vec<String>), String> {
+    let mut warnings = Vec::new();
+    let mut block = RequestBlock {
+        name: name.to_string(),
+        method: "GET".to_string(),
+        ..Default::default()
+    };
+
+    // A request may be just a URL string
+    let request = match request {
+        Value::String(url) => {
+            block.url = url.clone();
+            return Ok((block, warnings));
+        }
+        Value::Object(request) => request,
+        _ => return Err("request is not an object".to_string()),
+    };
+
+    if let Some(method) = request.get("method").and_then(Value::as_str) {
+        block.method = method.to_uppercase();
+    }
+    block.url = request
+        .get("url")
+        .map(url_of)
+        .filter(|url| !url.is_empty())
+        .ok_or_else(|| "request has no URL".to_string())?;
+
+    for header in enabled(request.get("header")) {
+        if let Some(key) = header.get("key").and_then(Value::as_str) {
+            let value = header
+                .get("value")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            block.headers.push((key.to_string(), value.to_string()));
+        }
+    }
+
+    if let Some(body) = request.get("body") {
+        convert_body(name, body, &mut block, &mut warnings);
+    }
+
+    if let Some(auth) = request.get("auth").or(inherited_auth) {
+        convert_auth(name, auth, &mut block, &mut warnings);
+    }
+
+    for (listen, label) in [
+        ("prerequest", "Pre-request script"),
+        ("test", "Test script"),
+    ] {
+        if let Some(script) = script_of(item, listen) {
+            block.comments.push(format!(
+                "{} from Postman (not translated):\n{}",
+                label, script
+            ));
+        }
+    }
+
+    Ok((block, warnings))
+}
+
+/// The URL of a request, as either a plain string or a URL object
+fn url_of(url: &Value) -> String {
+    match url {
+        Value::String(raw) => path_variables(raw, None),
+        Value::Object(url) => {
+            let raw = url.get("raw").and_then(Value::as_str).unwrap_or_default();
+            path_variables(raw, url.get("variable"))
+        }
+        _ => String::new(),
+    }
+}
+
+/// Replace Postman's `:name` path segments with their value or a `{{name}}` variable
+fn path_variables(raw: &str, variables: Option<&Value>) -> String {
+    let (path, query) = match raw.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (raw, None),
+    };
+
+    let lookup = |name: &str| -> Option<String> {
+        variables?
+            .as_array()?
+            .iter()
+            .find(|v| v.get("key").and_then(Value::as_str) == Some(name))?
+            .get("value")
+            .and_then(Value::as_str)
+            .filter(|v| !v.is_empty())
+            .map(str::to_string)
+    };
+
+    let path = path
+        .split('/')
+        .map(|segment| match segment.strip_prefix(':') {
+            Some(name) if !name.is_empty() => {
+                lookup(name).unwrap_or_else(|| format!("{{{{{}}}}}", name))
+            }
+            _ => segment.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("/");
+
+    match query {
+        Some(query) => format!("{}?{}", path, query),
+        None => path,
+    }
+}
+
+fn convert_body(name: &str, body: &Value, block: &mut RequestBlock, warnings: &mut Vec<String>) {
+    let mode = body.get("mode").and_then(Value::as_str).unwrap_or("raw");
+
+    match mode {
+        "raw" => {
+            let raw = body.get("raw").and_then(Value::as_str).unwrap_or_default();
+            if raw.is_empty() {
+                return;
+            }
+            let language = body
+                .pointer("/options/raw/language")
+                .and_then(Value::as_str);
+            let content_type = match language {
+                Some("json") => Some("application/json"),
+                Some("xml") => Some("application/xml"),
+                Some("html") => Some("text/html"),
+                Some("javascript") => Some("application/javascript"),
+                _ => None,
+            };
+            if let Some(content_type) = content_type {
+                set_content_type(block, content_type);
+            }
+            block.body = Some(raw.to_string());
+        }
+        "urlencoded" => {
+            let pairs: Vec<String> = enabled(body.get("urlencoded"))
+                .filter_map(|p| {
+                    let key = p.get("key").and_then(Value::as_str)?;
+                    let value = p.get("value").and_then(Value::as_str).unwrap_or_default();
+                    Some(format!("{}={}", key, value))
+                })
+                .collect();
+            set_content_type(block, "application/x-www-form-urlencoded");
+            block.body = Some(pairs.join("&"));
+        }
+        "formdata" => {
+            let parts: Vec<FormPart> = enabled(body.get("formdata"))
+                .filter_map(|p| {
+                    let key = p.get("key").and_then(Value::as_str)?.to_string();
+                    if p.get("type").and_then(Value::as_str) == Some("file") {
+                        let path = match p.get("src") {
+                            Some(Value::String(src)) => src.clone(),
+                            Some(Value::Array(srcs)) => srcs.first()?.as_str()?.to_string(),
+                            _ => return None,
+                        };
+                        Some(FormPart::File { name: key, path })
+                    } else {
+                        let value = p.get("value").and_then(Value::as_str).unwrap_or_default();
+                        Some(FormPart::Text {
+                            name: key,
+                            value: value.to_string(),
+                        })
+                    }
+                })
+                .collect();
+            set_content_type(
+                block,
+                &format!("multipart/form-data; boundary={}", MULTIPART_BOUNDARY),
+            );
+            block.body = Some(multipart_body(&parts));
+        }
+        "graphql" => {
+            let query = body
+                .pointer("/graphql/query")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            let variables = body
+                .pointer("/graphql/variables")
+                .and_then(Value::as_str)
+                .filter(|v| !v.trim().is_empty())
+                .and_then(|v| serde_json::from_str::<Value>(v).ok())
+                .unwrap_or(Value::Object(Map::new()));
+            let payload = serde_json::json!({ "query": query, "variables": variables });
+            set_content_type(block, "application/json");
+            block.body = serde_json::to_string_pretty(&payload).ok();
+        }
+        "file" => match body.pointer("/file/src").and_then(Value::as_str) {
+            Some(src) => block.body = Some(format!("< {}", super::curl::portable_path(src))),
+            None => warnings.push(format!("'{}': binary body has no file selected", name)),
+        },
+        other => warnings.push(format!("'{}': unsupported body mode '{}'", name, other)),
+    }
+}
+
+fn set_content_type(block: &mut RequestBlock, content_type: &str) {
+    if block.header("Content-Type").is_none() {
+        block
+            .headers
+            .push(("Content-Type".to_string(), content_type.to_string()));
+    }
+}
+
+/// Map Postman auth helpers to kvile's `# @auth` metadata
+fn convert_auth(name: &str, auth: &Value, block: &mut RequestBlock, warnings: &mut Vec<String>) {
+    let kind = auth.get("type").and_then(Value::as_str).unwrap_or("noauth");
+    let param = |key: &str| -> String {
+        auth.get(kind)
+            .and_then(Value::as_array)
+            .and_then(|params| {
+                params
+                    .iter()
+                    .find(|p| p.get("key").and_then(Value::as_str) == Some(key))
+            })
+            .and_then(|p| p.get("value"))
+            .map(|v| match v {
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            })
+            .unwrap_or_default()
+    };
+
+    let mut meta = |key: &str, value: String| {
+        if !value.is_empty() {
+            block.metadata.push((key.to_string(), value));
+        }
+    };
+
+    match kind {
+        "noauth" => {}
+        "bearer" => {
+            meta("auth", "bearer".to_string());
+            meta("auth.token", param("token"));
+        }
+        "basic" => {
+            meta("auth", "basic".to_string());
+            meta("auth.username", param("username"));
+            meta("auth.password", param("password"));
+        }
+        "apikey" => {
+            meta("auth", "apiKey".to_string());
+            meta("auth.header", param("key"));
+            meta("auth.value", param("value"));
+            meta("auth.in", param("in"));
+        }
+        "oauth2" => {
+            meta("auth", "oauth2".to_string());
+            meta("oauth.tokenUrl", param("accessTokenUrl"));
+            meta("oauth.clientId", param("clientId"));
+            meta("oauth.clientSecret", param("clientSecret"));
+            meta("oauth.scopes", param("scope"));
+            let grant = param("grant_type");
+            let grant = match grant.as_str() {
+                "password_credentials" => "password".to_string(),
+                _ => grant,
+            };
+            meta("oauth.grantType", grant);
+        }
+        other => warnings.push(format!("'{}': unsupported auth type '{}'", name, other)),
+    }
+}
+
+/// Entries of a key/value list that aren't disabled
+fn enabled(list: Option<&Value>) -> impl Iterator<Item = &Value> {
+    list.and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter(|entry| entry.get("disabled").and_then(Value::as_bool) != Some(true))
+}
+
+fn collect_variables(item: &Value, variables: &mut Map<String, Value>) {
+    for var in enabled(item.get("variable")) {
+        if let Some(key) = var.get("key").and_then(Value::as_str) {
+            let value = var
+                .get("value")
+                .cloned()
+                .unwrap_or(Value::String(String::new()));
+            variables.entry(key.to_string()).or_insert(value);
+        }
+    }
+}
+
+/// The source of an event script (`prerequest` or `test`), if non-empty
+fn script_of(item: &Value, listen: &str) -> Option<String> {
+    let event = item
+        .get("event")?
+        .as_array()?
+        .iter()
+        .find(|e| e.get("listen").and_then(Value::as_str) == Some(listen))?;
+    let exec = event.pointer("/script/exec")?;
+    let source = match exec {
+        Value::String(s) => s.clone(),
+        Value::Array(lines) => lines
+            .iter()
+            .filter_map(Value::as_str)
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => return None,
+    };
+    (!source.trim().is_empty()).then_some(source)
+}
+
+fn has_scripts(item: &Value) -> bool {
+    script_of(item, "prerequest").is_some() || script_of(item, "test").is_some()
+}
+
+/// Banner prepended to exported scripts, which run against the JetBrains API
+const JETBRAINS_SCRIPT_NOTE: &str =
+    "// Exported from kvile: this script uses the JetBrains HTTP Client API (client, request, response), not pm.*";
+
+/// Build a Postman v2.1 collection with one folder per .http file
+pub fn http_to_postman(name: &str, folders: &[(String, Vec<ParsedRequest>)]) -> Value {
+    let mut variables: Vec<Value> = Vec::new();
+    let mut items = Vec::new();
+
+    for (folder, requests) in folders {
+        for request in requests {
+            let mut keys: Vec<_> = request.variables.iter().collect();
+            keys.sort();
+            for (key, value) in keys {
+                if !variables.iter().any(|v| v["key"] == key.as_str()) {
+                    variables.push(json!({ "key": key, "value": value }));
+                }
+            }
+        }
+        items.push(json!({
+            "name": folder,
+            "item": requests.iter().map(request_item).collect::<Vec<_>>(),
+        }));
+    }
+
+    let mut collection = json!({
+        "info": {
+            "name": name,
+            "schema": "https://schema.getpostman.com/json/collection/v2.1.0/collection.json",
+        },
+        "item": items,
+    });
+    if !variables.is_empty() {
+        collection["variable"] = Value::Array(variables);
+    }
+    collection
+}
+
+/// Map a parsed request to a Postman request item
+fn request_item(request: &ParsedRequest) -> Value {
+    let name = request
+        .metadata
+        .get("name")
+        .or(request.name.as_ref())
+        .cloned()
+        .unwrap_or_else(|| format!("{} {}", request.method, request.url));
+
+    let mut headers: Vec<_> = request.headers.iter().collect();
+    headers.sort();
+    let mut postman_request = json!({
+        "method": request.method,
+        "header": headers
+            .iter()
+            .map(|(key, value)| json!({ "key": key, "value": value }))
+            .collect::<Vec<_>>(),
+        "url": postman_url(&request.url),
+    });
+
+    if let Some(body) = request.body.as_deref().filter(|b| !b.trim().is_empty()) {
+        let content_type = request
+            .headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case("Content-Type"))
+            .map(|(_, v)| v.as_str())
+            .unwrap_or_default();
+        postman_request["body"] = postman_body(body, content_type);
+    }
+    if let Some(auth) = postman_auth(&request.metadata) {
+        postman_request["auth"] = auth;
+    }
+
+    let mut item = json!({ "name": name, "request": postman_request });
+    let events: Vec<Value> = [
+        ("prerequest", &request.pre_script),
+        ("test", &request.post_script),
+    ]
+    .into_iter()
+    .filter_map(|(listen, script)| {
+        let script = script.as_deref()?;
+        let exec: Vec<&str> = std::iter::once(JETBRAINS_SCRIPT_NOTE)
+            .chain(script.lines())
+            .collect();
+        Some(json!({ "listen": listen, "script": { "type": "text/javascript", "exec": exec } }))
+    })
+    .collect();
+    if !events.is_empty() {
+        item["event"] = Value::Array(events);
+    }
+    item
+}
+
+/// A Postman URL object; `{{variables}}` stay as they are
+fn postman_url(raw: &str) -> Value {
+    let (rest, query) = match raw.split_once('?') {
+        Some((rest, query)) => (rest, Some(query)),
+        None => (raw, None),
+    };
+    let (protocol, rest) = match rest.split_once("://") {
+        Some((protocol, rest)) => (Some(protocol), rest),
+        None => (None, rest),
+    };
+    let (host, path) = match rest.split_once('/') {
+        Some((host, path)) => (host, Some(path)),
+        None => (rest, None),
+    };
+
+    let mut url = json!({ "raw": raw });
+    if let Some(protocol) = protocol {
+        url["protocol"] = json!(protocol);
+    }
+    let (host, port) = match host.rsplit_once(':') {
+        Some((host, port)) if port.chars().all(|c| c.is_ascii_digit()) => (host, Some(port)),
+        _ => (host, None),
+    };
+    url["host"] = json!(host.split('.').collect::<Vec<_>>());
+    if let Some(port) = port {
+        url["port"] = json!(port);
+    }
+    if let Some(path) = path {
+        url["path"] = json!(path.split('/').collect::<Vec<_>>());
+    }
+    if let Some(query) = query {
+        url["query"] = query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| match pair.split_once('=') {
+                Some((key, value)) => json!({ "key": key, "value": value }),
+                None => json!({ "key": pair, "value": null }),
+            })
+            .collect();
+    }
+    url
+}
+
+/// A Postman body, with the mode inferred from the Content-Type
+fn postman_body(body: &str, content_type: &str) -> Value {
+    let content_type = content_type.to_ascii_lowercase();
+
+    if let Some(path) = body.trim().strip_prefix("< ").filter(|p| !p.contains('\n')) {
+        return json!({ "mode": "file", "file": { "src": path.trim() } });
+    }
+    if content_type.starts_with("application/x-www-form-urlencoded") {
+        let pairs: Vec<Value> = body
+            .trim()
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                json!({ "key": key, "value": value })
+            })
+            .collect();
+        return json!({ "mode": "urlencoded", "urlencoded": pairs });
+    }
+    if content_type.starts_with("multipart/form-data") {
+        if let Some(parts) = multipart_parts(body, &content_type) {
+            return json!({ "mode": "formdata", "formdata": parts });
+        }
+    }
+
+    let language = if content_type.contains("json") {
+        "json"
+    } else if content_type.contains("xml") {
+        "xml"
+    } else if content_type.contains("html") {
+        "html"
+    } else if content_type.contains("javascript") {
+        "javascript"
+    } else {
+        "text"
+    };
+    json!({
+        "mode": "raw",
+        "raw": body,
+        "options": { "raw": { "language": language } },
+    })
+}
+
+/// Split a multipart body into Postman formdata entries
+fn multipart_parts(body: &str, content_type: &str) -> Option<Vec<Value>> {
+    let boundary = content_type
+        .split(';')
+        .find_map(|p| p.trim().strip_prefix("boundary="))?
+        .trim_matches('"');
+    // The header value was lowercased; find the boundary as written in the body
+    let start = body.to_ascii_lowercase().find(&format!("--{}", boundary))?;
+    let delimiter = format!("--{}", &body[start + 2..start + 2 + boundary.len()]);
+
+    let mut parts = Vec::new();
+    for section in body.split(delimiter.as_str()).skip(1) {
+        let section = section.trim_start_matches(['\r', '\n']);
+        if section.starts_with("--") {
+            break;
+        }
+        let (head, content) = section
+            .split_once("\n\n")
+            .or_else(|| section.split_once("\r\n\r\n"))?;
+        let disposition = head
+            .lines()
+            .find(|l| l.to_ascii_lowercase().starts_with("content-disposition"))?;
+        let attribute = |name: &str| {
+            disposition
+                .split(';')
+                .find_map(|p| p.trim().strip_prefix(&format!("{}=", name)))
+                .map(|v| v.trim_matches('"').to_string())
+        };
+        let key = attribute("name")?;
+        let content = content.trim_end_matches(['\r', '\n']);
+        match content.strip_prefix("< ") {
+            Some(path) => parts.push(json!({ "key": key, "type": "file", "src": path.trim() })),
+            None => parts.push(json!({ "key": key, "type": "text", "value": content })),
+        }
+    }
+    Some(parts)
+}
+
+/// Map kvile's `# @auth` metadata to a Postman auth helper
+fn postman_auth(metadata: &HashMap<String, String>) -> Option<Value> {
+    let params = |pairs: &[(&str, &str)]| -> Vec<Value> {
+        pairs
+            .iter()
+            .filter_map(|(key, meta)| {
+                let value = metadata.get(*meta)?;
+                Some(json!({ "key": key, "value": value, "type": "string" }))
+            })
+            .collect()
+    };
+    match metadata.get("auth")?.as_str() {
+        "bearer" => Some(json!({ "type": "bearer", "bearer": params(&[("token", "auth.token")]) })),
+        "basic" => Some(json!({
+            "type": "basic",
+            "basic": params(&[("username", "auth.username"), ("password", "auth.password")]),
+        })),
+        "apiKey" => Some(json!({
+            "type": "apikey",
+            "apikey": params(&[("key", "auth.header"), ("value", "auth.value"), ("in", "auth.in")]),
+        })),
+        _ => None,
+    }
+}
+
+/// Build a Postman environment from a kvile environment with `$shared` folded in.
+///
+/// Variables from http-client.private.env.json are marked `secret`.
+pub fn environment_to_postman(config: &EnvironmentConfig, name: &str) -> Result<Value, String> {
+    let variables = environment_variables(config, Some(name))?;
+    let secrets = secret_names(config, Some(name));
+
+    let mut keys: Vec<&String> = variables.keys().collect();
+    keys.sort();
+    let values: Vec<Value> = keys
+        .into_iter()
+        .map(|key| {
+            let kind = if secrets.contains(key) {
+                "secret"
+            } else {
+                "default"
+            };
+            json!({ "key": key, "value": variables[key], "enabled": true, "type": kind })
+        })
+        .collect();
+
+    Ok(json!({
+        "name": name,
+        "values": values,
+        "_postman_variable_scope": "environment",
+        "_postman_exported_using": format!("kvile/{}", env!("CARGO_PKG_VERSION")),
+    }))
+}
+
+/// Enabled variables of a Postman environment export
+#[derive(Debug, Default)]
+pub struct PostmanEnvironment {
+    pub name: String,
+    pub variables: Map<String, Value>,
+    /// `secret`-typed values, which belong in http-client.private.env.json
+    pub secrets: Map<String, Value>,
+}
+
+/// Parse a Postman environment export
+pub fn parse_postman_environment(json: &str) -> Result<PostmanEnvironment, String> {
+    let export: Value =
+        serde_json::from_str(json).map_err(|e| format!("Failed to parse environment: {}", e))?;
+    let values = export
+        .get("values")
+        .and_then(Value::as_array)
+        .ok_or_else(|| "Not a Postman environment: missing values".to_string())?;
+
+    let mut environment = PostmanEnvironment {
+        name: export
+            .get("name")
+            .and_then(Value::as_str)
+            .unwrap_or("Postman")
+            .to_string(),
+        ..Default::default()
+    };
+    for value in values {
+        if value.get("enabled").and_then(Value::as_bool) == Some(false) {
+            continue;
+        }
+        let Some(key) = value.get("key").and_then(Value::as_str) else {
+            continue;
+        };
+        let target = if value.get("type").and_then(Value::as_str) == Some("secret") {
+            &mut environment.secrets
+        } else {
+            &mut environment.variables
+        };
+        target.insert(
+            key.to_string(),
+            value
+                .get("value")
+                .cloned()
+                .unwrap_or(Value::String(String::new())),
+        );
+    }
+    Ok(environment)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ECHO: &str = include_str!("../../tests/fixtures/postman-echo.postman_collection.json");
+
+    fn file<'a>(converted: &'a ConvertedCollection, path: &str) -> &'a str {
+        converted
+            .files
+            .iter()
+            .find(|(p, _)| p == &PathBuf::from(path))
+            .map(|(_, content)| content.as_str())
+            .unwrap_or_else(|| panic!("{} was not created", path))
+    }
+
+    #[test]
+    fn test_folders_become_files() {
+        let converted = postman_to_http(ECHO).unwrap();
+        let mut paths: Vec<_> = converted
+            .files
+            .iter()
+            .map(|(p, _)| p.to_string_lossy().replace('\\', "/"))
+            .collect();
+        paths.sort();
+        assert_eq!(
+            paths,
+            vec![
+                "Postman Echo.http",
+                "Postman Echo/Auth.http",
+                "Postman Echo/Auth/Digest.http",
+                "Postman Echo/Request Methods.http",
+            ]
+        );
+
+        for (_, content) in &converted.files {
+            crate::parser::parse_http_content(content).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_request_conversion() {
+        let converted = postman_to_http(ECHO).unwrap();
+        let methods = file(&converted, "Postman Echo/Request Methods.http");
+
+        assert!(methods.contains(
+            "### GET Request\n# Test script from Postman (not translated):\n# pm.test(\"Status code is 200\", function () {\n#     pm.response.to.have.status(200);\n# });\nGET https://postman-echo.com/get?foo1=bar1&foo2=bar2\n"
+        ));
+        assert!(methods.contains(
+            "### POST Raw Text\nPOST {{baseUrl}}/post\nContent-Type: application/json\n\n{\"hello\": \"{{name}}\"}\n"
+        ));
+        assert!(methods.contains(
+            "### POST Form Data\nPOST {{baseUrl}}/post\nContent-Type: application/x-www-form-urlencoded\n\nfoo1=bar1&foo2=bar2\n"
+        ));
+        assert!(methods.contains(
+            "--WebAppBoundary\nContent-Disposition: form-data; name=\"avatar\"; filename=\"avatar.png\"\n\n< ./images/avatar.png\n"
+        ));
+        assert!(methods.contains("### Get user\nGET {{baseUrl}}/users/{{id}}/posts/7\n"));
+        assert!(methods.contains("\"query\": \"{ user(id: 1) { name } }\""));
+        assert!(!methods.contains("X-Disabled"));
+
+        let auth = file(&converted, "Postman Echo/Auth.http");
+        assert!(auth.contains("# @auth basic\n# @auth.username postman\n# @auth.password password\nGET {{baseUrl}}/basic-auth\n"));
+        assert!(auth.contains("# @auth bearer\n# @auth.token {{token}}\nGET {{baseUrl}}/bearer\n"));
+
+        let root = file(&converted, "Postman Echo.http");
+        assert!(root.starts_with("### Health\nGET {{baseUrl}}/status/200\n"));
+    }
+
+    #[test]
+    fn test_variables_and_warnings() {
+        let converted = postman_to_http(ECHO).unwrap();
+        let shared = &converted.environments["$shared"];
+        assert_eq!(shared["baseUrl"], "https://postman-echo.com");
+        assert_eq!(shared["name"], "kvile");
+        assert_eq!(shared["token"], "abc123");
+
+        assert_eq!(
+            converted.warnings,
+            vec![
+                "'Digest Auth': unsupported auth type 'digest'".to_string(),
+                "Skipped request 'Broken': request has no URL".to_string(),
+            ]
+        );
+    }
+
+    const SCHEMA: &str = include_str!("../../tests/fixtures/postman-collection-v2.1.schema.json");
+
+    fn export(content: &str) -> Value {
+        let requests = crate::parser::parse_http_content(content).unwrap();
+        http_to_postman("Exported", &[("users".to_string(), requests)])
+    }
+
+    #[test]
+    fn test_export_matches_schema() {
+        let collection = export(
+            "### List users\n# @name listUsers\n# @auth bearer\n# @auth.token {{token}}\nGET {{baseUrl}}/users?page=1&active\nAccept: application/json\n\n> {%\nclient.test(\"ok\", function() {\n  client.assert(response.status === 200);\n});\n%}\n\n### Create\nPOST https://api.example.com:8443/users\nContent-Type: application/json\n\n{\"name\": \"{{name}}\"}\n\n### Login\nPOST {{baseUrl}}/login\nContent-Type: application/x-www-form-urlencoded\n\nuser=ada&pass={{password}}\n\n### Upload\nPOST {{baseUrl}}/upload\nContent-Type: multipart/form-data; boundary=WebAppBoundary\n\n--WebAppBoundary\nContent-Disposition: form-data; name=\"note\"\n\nhello\n--WebAppBoundary\nContent-Disposition: form-data; name=\"file\"; filename=\"a.png\"\n\n< ./a.png\n--WebAppBoundary--\n",
+        );
+
+        let schema: Value = serde_json::from_str(SCHEMA).unwrap();
+        let compiled = jsonschema::JSONSchema::compile(&schema).unwrap();
+        if let Err(errors) = compiled.validate(&collection) {
+            let errors: Vec<String> = errors.map(|e| e.to_string()).collect();
+            panic!("collection does not match the schema: {:?}", errors);
+        }
+
+        let items = collection["item"][0]["item"].as_array().unwrap();
+        assert_eq!(collection["item"][0]["name"], "users");
+        assert_eq!(items.len(), 4);
+
+        let list = &items[0];
+        assert_eq!(list["name"], "listUsers");
+        assert_eq!(
+            list["request"]["url"]["raw"],
+            "{{baseUrl}}/users?page=1&active"
+        );
+        assert_eq!(list["request"]["url"]["host"], json!(["{{baseUrl}}"]));
+        assert_eq!(
+            list["request"]["url"]["query"][1],
+            json!({"key": "active", "value": null})
+        );
+        assert_eq!(list["request"]["auth"]["type"], "bearer");
+        assert_eq!(list["event"][0]["listen"], "test");
+        assert_eq!(list["event"][0]["script"]["exec"][0], JETBRAINS_SCRIPT_NOTE);
+        assert_eq!(
+            list["event"][0]["script"]["exec"][1],
+            "client.test(\"ok\", function() {"
+        );
+
+        let create = &items[1];
+        assert_eq!(create["request"]["url"]["port"], "8443");
+        assert_eq!(create["request"]["body"]["mode"], "raw");
+        assert_eq!(
+            create["request"]["body"]["options"]["raw"]["language"],
+            "json"
+        );
+
+        assert_eq!(
+            items[2]["request"]["body"]["urlencoded"],
+            json!([{"key": "user", "value": "ada"}, {"key": "pass", "value": "{{password}}"}])
+        );
+        assert_eq!(
+            items[3]["request"]["body"]["formdata"],
+            json!([
+                {"key": "note", "type": "text", "value": "hello"},
+                {"key": "file", "type": "file", "src": "./a.png"}
+            ])
+        );
+    }
+
+    #[test]
+    fn test_export_round_trips_through_import() {
+        let collection = export(
+            "### Create\nPOST {{baseUrl}}/users\nContent-Type: application/json\nX-Trace: 1\n\n{\"name\": \"Ada\"}\n",
+        );
+        let converted = postman_to_http(&collection.to_string()).unwrap();
+        let users = file(&converted, "Exported/users.http");
+        assert_eq!(
+            users,
+            "### Create\nPOST {{baseUrl}}/users\nContent-Type: application/json\nX-Trace: 1\n\n{\"name\": \"Ada\"}\n"
+        );
+    }
+
+    fn environment_config() -> EnvironmentConfig {
+        use crate::env::Environment;
+
+        let vars = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        EnvironmentConfig {
+            environments: vec![Environment {
+                name: "dev".to_string(),
+                variables: vars(&[("baseUrl", "http://localhost:3000"), ("user", "ada")]),
+                private_variables: vars(&[("password", "hunter2")]),
+                source_file: "http-client.env.json".to_string(),
+                variable_sources: HashMap::new(),
+                auth: HashMap::new(),
+            }],
+            shared: vars(&[("apiVersion", "v1"), ("user", "shared-user")]),
+            private_shared: vars(&[("clientSecret", "s3cret")]),
+            warnings: vec![],
+            locked: vec![],
+            files: vec![],
+            command_variables: HashMap::new(),
+            untrusted_commands: vec![],
+        }
+    }
+
+    #[test]
+    fn test_export_environment() {
+        let environment = environment_to_postman(&environment_config(), "dev").unwrap();
+
+        assert_eq!(environment["name"], "dev");
+        assert_eq!(
+            environment["values"],
+            json!([
+                {"key": "apiVersion", "value": "v1", "enabled": true, "type": "default"},
+                {"key": "baseUrl", "value": "http://localhost:3000", "enabled": true, "type": "default"},
+                {"key": "clientSecret", "value": "s3cret", "enabled": true, "type": "secret"},
+                {"key": "password", "value": "hunter2", "enabled": true, "type": "secret"},
+                {"key": "user", "value": "ada", "enabled": true, "type": "default"},
+            ])
+        );
+        assert!(environment_to_postman(&environment_config(), "prod").is_err());
+    }
+
+    #[test]
+    fn test_import_environment() {
+        let environment = parse_postman_environment(
+            r#"{
+                "id": "5c1d0e1f-1111-4c3b-9d58-000000000000",
+                "name": "Staging",
+                "values": [
+                    {"key": "baseUrl", "value": "https://staging.example.com", "type": "default", "enabled": true},
+                    {"key": "apiKey", "value": "k-123", "type": "secret", "enabled": true},
+                    {"key": "legacy", "value": "old", "type": "default", "enabled": false}
+                ],
+                "_postman_variable_scope": "environment"
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(environment.name, "Staging");
+        assert_eq!(
+            Value::Object(environment.variables),
+            json!({"baseUrl": "https://staging.example.com"})
+        );
+        assert_eq!(
+            Value::Object(environment.secrets),
+            json!({"apiKey": "k-123"})
+        );
+        assert!(parse_postman_environment(r#"{"name": "x"}"#).is_err());
+    }
+
+    #[test]
+    fn test_environment_round_trip() {
+        let exported = environment_to_postman(&environment_config(), "dev").unwrap();
+        let imported = parse_postman_environment(&exported.to_string()).unwrap();
+
+        assert_eq!(imported.name, "dev");
+        assert_eq!(
+            Value::Object(imported.variables),
+            json!({"apiVersion": "v1", "baseUrl": "http://localhost:3000", "user": "ada"})
+        );
+        assert_eq!(
+            Value::Object(imported.secrets),
+            json!({"clientSecret": "s3cret", "password": "hunter2"})
+        );
+    }
+}
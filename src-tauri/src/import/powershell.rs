@@ -0,0 +1,651 @@
+use std::collections::HashMap;
+
+use super::curl::{curl_to_http, CurlCommand};
+use super::ImportResult;
+use crate::variables::substitute;
+
+/// A PowerShell value as written in the snippet
+#[derive(Debug, Clone, PartialEq)]
+enum PsValue {
+    /// String or bare word; `$variables` are already `{{variable}}` placeholders
+    Text(String),
+    /// `@{ key = value; ... }` in source order
+    Table(Vec<(String, PsValue)>),
+    /// Expression we don't evaluate, such as `(Get-Credential)`
+    Raw(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Param(String),
+    Variable(String),
+    Value(PsValue),
+    Assign,
+    Pipe,
+    Separator,
+}
+
+/// Parameters that take no argument
+const SWITCHES: &[&str] = &[
+    "skipcertificatecheck",
+    "usebasicparsing",
+    "usedefaultcredentials",
+    "disablekeepalive",
+    "skipheadervalidation",
+    "skiphttperrorcheck",
+    "allowunencryptedauthentication",
+    "passthru",
+    "resume",
+];
+
+/// Convert an `Invoke-RestMethod` / `Invoke-WebRequest` snippet to HTTP file
+/// format. `$variables` assigned earlier in the snippet are inlined; others
+/// become `{{variable}}` placeholders.
+pub fn powershell_to_http(input: &str) -> Result<ImportResult, String> {
+    let tokens = Tokenizer::new(input).tokenize()?;
+
+    let mut vars: HashMap<String, PsValue> = HashMap::new();
+    let mut command: Option<Vec<Token>> = None;
+
+    for statement in tokens.split(|t| *t == Token::Separator) {
+        let invoke = statement.iter().position(
+            |t| matches!(t, Token::Value(PsValue::Text(word)) if is_invoke_command(word)),
+        );
+
+        match (invoke, statement) {
+            (Some(pos), _) => {
+                let args = statement[pos + 1..]
+                    .iter()
+                    .take_while(|t| **t != Token::Pipe)
+                    .cloned()
+                    .collect();
+                command = Some(args);
+                break;
+            }
+            (None, [Token::Variable(name), Token::Assign, Token::Value(value)]) => {
+                vars.insert(name.to_lowercase(), value.clone());
+            }
+            _ => {}
+        }
+    }
+
+    let args = command
+        .ok_or_else(|| "No Invoke-RestMethod or Invoke-WebRequest command found".to_string())?;
+    let mut cmd = build_command(&args, &vars)?;
+
+    // Inline string variables that were assigned in the snippet
+    let text_vars: HashMap<String, String> = vars
+        .iter()
+        .filter_map(|(name, value)| match value {
+            PsValue::Text(text) => Some((name.clone(), text.clone())),
+            _ => None,
+        })
+        .collect();
+    cmd.url = substitute(&cmd.url, &text_vars);
+    for (_, value) in cmd.headers.iter_mut() {
+        *value = substitute(value, &text_vars);
+    }
+    cmd.body = cmd.body.map(|body| substitute(&body, &text_vars));
+
+    Ok(ImportResult {
+        http: curl_to_http(&cmd),
+        warnings: cmd.notes,
+    })
+}
+
+fn is_invoke_command(word: &str) -> bool {
+    ["invoke-restmethod", "invoke-webrequest", "irm", "iwr"].contains(&word.to_lowercase().as_str())
+}
+
+/// Map cmdlet parameters onto a request
+fn build_command(args: &[Token], vars: &HashMap<String, PsValue>) -> Result<CurlCommand, String> {
+    let mut cmd = CurlCommand::default();
+    let mut content_type: Option<String> = None;
+    let mut auth_scheme: Option<String> = None;
+    let mut token: Option<String> = None;
+    let mut credential = false;
+    let mut i = 0;
+
+    let resolve = |token: &Token| -> Option<PsValue> {
+        match token {
+            Token::Value(value) => Some(value.clone()),
+            Token::Variable(name) => Some(
+                vars.get(&name.to_lowercase())
+                    .cloned()
+                    .unwrap_or_else(|| PsValue::Text(format!("{{{{{}}}}}", name))),
+            ),
+            _ => None,
+        }
+    };
+
+    while i < args.len() {
+        let Token::Param(param) = &args[i] else {
+            // First positional argument is the URI
+            if let Some(PsValue::Text(url)) = resolve(&args[i]) {
+                if cmd.url.is_empty() {
+                    cmd.url = url;
+                }
+            }
+            i += 1;
+            continue;
+        };
+
+        let name = param.to_lowercase();
+        if SWITCHES.contains(&name.as_str()) {
+            match name.as_str() {
+                "skipcertificatecheck" => cmd.tls.insecure = true,
+                "usebasicparsing" | "passthru" => {}
+                _ => cmd
+                    .notes
+                    .push(format!("-{} is not supported and was skipped", param)),
+            }
+            i += 1;
+            continue;
+        }
+
+        let value = args.get(i + 1).and_then(&resolve);
+        if value.is_some() {
+            i += 1;
+        }
+        i += 1;
+
+        match (name.as_str(), value) {
+            ("uri", Some(PsValue::Text(url))) => cmd.url = url,
+            ("method", Some(PsValue::Text(method))) => cmd.method = method.to_uppercase(),
+            ("headers", Some(PsValue::Table(entries))) => {
+                for (key, value) in entries {
+                    cmd.headers.push((key, text_of(&value)));
+                }
+            }
+            ("body", Some(PsValue::Table(entries))) => {
+                // PowerShell form-encodes a hashtable body
+                let pairs: Vec<String> = entries
+                    .iter()
+                    .map(|(k, v)| format!("{}={}", k, text_of(v)))
+                    .collect();
+                cmd.body = Some(pairs.join("&"));
+            }
+            ("body", Some(value)) => cmd.body = Some(text_of(&value)),
+            ("infile", Some(value)) => cmd.body = Some(format!("< {}", text_of(&value))),
+            ("contenttype", Some(value)) => content_type = Some(text_of(&value)),
+            ("useragent", Some(value)) => cmd
+                .headers
+                .push(("User-Agent".to_string(), text_of(&value))),
+            ("credential", Some(_)) => credential = true,
+            ("authentication", Some(value)) => auth_scheme = Some(text_of(&value).to_lowercase()),
+            ("token", Some(value)) => token = Some(text_of(&value)),
+            ("outfile", _) => {}
+            (_, Some(value)) => cmd.notes.push(format!(
+                "-{} {} is not supported and was skipped",
+                param,
+                text_of(&value)
+            )),
+            (_, None) => cmd
+                .notes
+                .push(format!("-{} is not supported and was skipped", param)),
+        }
+    }
+
+    if cmd.url.is_empty() {
+        return Err("No -Uri found in PowerShell command".to_string());
+    }
+
+    if let Some(content_type) = content_type {
+        if cmd.header("Content-Type").is_none() {
+            cmd.headers.push(("Content-Type".to_string(), content_type));
+        }
+    }
+
+    if auth_scheme.as_deref() == Some("bearer") || (token.is_some() && !credential) {
+        let token = token.unwrap_or_else(|| "{{token}}".to_string());
+        cmd.headers
+            .push(("Authorization".to_string(), format!("Bearer {}", token)));
+    } else if credential {
+        // A PSCredential can't be read from the snippet; ask for the values
+        cmd.metadata.push(("auth".to_string(), "basic".to_string()));
+        cmd.metadata
+            .push(("auth.username".to_string(), "{{username}}".to_string()));
+        cmd.metadata
+            .push(("auth.password".to_string(), "{{password}}".to_string()));
+    }
+
+    Ok(cmd)
+}
+
+fn text_of(value: &PsValue) -> String {
+    match value {
+        PsValue::Text(text) | PsValue::Raw(text) => text.clone(),
+        PsValue::Table(entries) => entries
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, text_of(v)))
+            .collect::<Vec<_>>()
+            .join("; "),
+    }
+}
+
+struct Tokenizer {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Tokenizer {
+    fn new(input: &str) -> Self {
+        Self {
+            chars: input.chars().collect(),
+            pos: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn tokenize(mut self) -> Result<Vec<Token>, String> {
+        let mut tokens = Vec::new();
+
+        loop {
+            self.skip_blanks();
+            let Some(c) = self.peek() else {
+                return Ok(tokens);
+            };
+
+            match c {
+                '\n' | ';' => {
+                    self.pos += 1;
+                    tokens.push(Token::Separator);
+                }
+                '|' => {
+                    self.pos += 1;
+                    // What the response is piped into, such as
+                    // `ForEach-Object { $_.name }`, isn't part of the request
+                    let statement = tokens
+                        .rsplit(|t| *t == Token::Separator)
+                        .next()
+                        .unwrap_or_default();
+                    if statement.iter().any(
+                        |t| matches!(t, Token::Value(PsValue::Text(word)) if is_invoke_command(word)),
+                    ) {
+                        self.skip_statement();
+                    }
+                    tokens.push(Token::Pipe);
+                }
+                '}' | ')' => {
+                    return Err(format!("Unmatched '{}' in PowerShell command", c));
+                }
+                '=' => {
+                    self.pos += 1;
+                    tokens.push(Token::Assign);
+                }
+                '-' if self.peek_at(1).is_some_and(char::is_alphabetic) => {
+                    self.pos += 1;
+                    let name = self.read_word();
+                    // -Name:value binds the argument directly
+                    tokens.push(Token::Param(name.trim_end_matches(':').to_string()));
+                }
+                '$' => {
+                    self.pos += 1;
+                    tokens.push(Token::Variable(self.read_variable_name()));
+                }
+                _ => tokens.push(Token::Value(self.read_value()?)),
+            }
+        }
+    }
+
+    /// Skip spaces, backtick line continuations and comments
+    fn skip_blanks(&mut self) {
+        loop {
+            match self.peek() {
+                Some(' ' | '\t' | '\r') => self.pos += 1,
+                Some('`') if matches!(self.peek_at(1), Some('\n' | '\r')) => {
+                    self.pos += 1;
+                    while matches!(self.peek(), Some('\r' | '\n')) {
+                        self.pos += 1;
+                        if self.chars[self.pos - 1] == '\n' {
+                            break;
+                        }
+                    }
+                }
+                Some('<') if self.peek_at(1) == Some('#') => {
+                    while self.pos < self.chars.len()
+                        && !self.chars[self.pos..].starts_with(&['#', '>'])
+                    {
+                        self.pos += 1;
+                    }
+                    self.pos = (self.pos + 2).min(self.chars.len());
+                }
+                Some('#') => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.pos += 1;
+                    }
+                }
+                _ => return,
+            }
+        }
+    }
+
+    /// Skip to the newline or `;` ending the statement, past any blocks,
+    /// parentheses and strings on the way
+    fn skip_statement(&mut self) {
+        let mut depth = 0usize;
+        let mut quote: Option<char> = None;
+        while let Some(c) = self.peek() {
+            match (quote, c) {
+                (Some(q), _) if c == q => quote = None,
+                (Some(_), _) => {}
+                (None, '"' | '\'') => quote = Some(c),
+                (None, '{' | '(') => depth += 1,
+                (None, '}' | ')') => depth = depth.saturating_sub(1),
+                (None, '\n' | ';') if depth == 0 => return,
+                _ => {}
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn skip_whitespace_and_newlines(&mut self) {
+        loop {
+            self.skip_blanks();
+            if self.peek() == Some('\n') {
+                self.pos += 1;
+            } else {
+                return;
+            }
+        }
+    }
+
+    fn read_value(&mut self) -> Result<PsValue, String> {
+        match (self.peek(), self.peek_at(1)) {
+            (Some('@'), Some('{')) => {
+                self.pos += 2;
+                self.read_table()
+            }
+            (Some('@'), Some(quote @ ('"' | '\''))) => {
+                self.pos += 2;
+                self.read_here_string(quote).map(PsValue::Text)
+            }
+            (Some('"'), _) => {
+                self.pos += 1;
+                self.read_expandable_string().map(PsValue::Text)
+            }
+            (Some('\''), _) => {
+                self.pos += 1;
+                self.read_literal_string().map(PsValue::Text)
+            }
+            (Some('$'), _) => {
+                self.pos += 1;
+                let name = self.read_variable_name();
+                Ok(PsValue::Text(format!("{{{{{}}}}}", name)))
+            }
+            (Some('('), _) => Ok(PsValue::Raw(self.read_parenthesized())),
+            _ => Ok(PsValue::Text(self.read_word())),
+        }
+    }
+
+    /// Read a bare word up to whitespace or a delimiter
+    fn read_word(&mut self) -> String {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| !c.is_whitespace() && !matches!(c, ';' | '|' | '}' | ')' | '='))
+        {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    /// `$name`, `${name}` or `$env:NAME`
+    fn read_variable_name(&mut self) -> String {
+        if self.peek() == Some('{') {
+            self.pos += 1;
+            let start = self.pos;
+            while self.peek().is_some_and(|c| c != '}') {
+                self.pos += 1;
+            }
+            let name: String = self.chars[start..self.pos].iter().collect();
+            self.pos += 1;
+            return name;
+        }
+
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_alphanumeric() || c == '_' || c == ':')
+        {
+            self.pos += 1;
+        }
+        let name: String = self.chars[start..self.pos].iter().collect();
+        match name.split_once(':') {
+            Some((_scope, name)) => name.to_string(),
+            None => name,
+        }
+    }
+
+    fn read_parenthesized(&mut self) -> String {
+        let start = self.pos;
+        let mut depth = 0;
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                '(' => depth += 1,
+                ')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    fn read_literal_string(&mut self) -> Result<String, String> {
+        let mut out = String::new();
+        loop {
+            match self.peek() {
+                None => return Err("Unterminated string in PowerShell command".to_string()),
+                Some('\'') if self.peek_at(1) == Some('\'') => {
+                    out.push('\'');
+                    self.pos += 2;
+                }
+                Some('\'') => {
+                    self.pos += 1;
+                    return Ok(out);
+                }
+                Some(c) => {
+                    out.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    fn read_expandable_string(&mut self) -> Result<String, String> {
+        let mut out = String::new();
+        loop {
+            match self.peek() {
+                None => return Err("Unterminated string in PowerShell command".to_string()),
+                Some('"') if self.peek_at(1) == Some('"') => {
+                    out.push('"');
+                    self.pos += 2;
+                }
+                Some('"') => {
+                    self.pos += 1;
+                    return Ok(out);
+                }
+                Some(_) => self.read_expandable_char(&mut out),
+            }
+        }
+    }
+
+    /// One character of an expandable string: a backtick escape, a
+    /// `$variable` (as a placeholder) or a literal character
+    fn read_expandable_char(&mut self, out: &mut String) {
+        let c = self.chars[self.pos];
+        self.pos += 1;
+
+        match c {
+            '`' => {
+                if let Some(escaped) = self.peek() {
+                    self.pos += 1;
+                    out.push(match escaped {
+                        'n' => '\n',
+                        't' => '\t',
+                        'r' => '\r',
+                        '0' => '\0',
+                        other => other,
+                    });
+                }
+            }
+            '$' if self
+                .peek()
+                .is_some_and(|c| c.is_alphabetic() || c == '_' || c == '{') =>
+            {
+                let name = self.read_variable_name();
+                out.push_str(&format!("{{{{{}}}}}", name));
+            }
+            c => out.push(c),
+        }
+    }
+
+    /// `@"..."@` / `@'...'@`; the content starts on the line after the opener
+    fn read_here_string(&mut self, quote: char) -> Result<String, String> {
+        while self.peek().is_some_and(|c| c != '\n') {
+            self.pos += 1;
+        }
+        self.pos += 1;
+
+        let mut out = String::new();
+        loop {
+            let at_line_start = self.pos == 0 || self.chars[self.pos - 1] == '\n';
+            match self.peek() {
+                None => return Err("Unterminated here-string in PowerShell command".to_string()),
+                Some(c) if at_line_start && c == quote && self.peek_at(1) == Some('@') => {
+                    self.pos += 2;
+                    // The newline before the terminator isn't part of the value
+                    if out.ends_with('\n') {
+                        out.pop();
+                        if out.ends_with('\r') {
+                            out.pop();
+                        }
+                    }
+                    return Ok(out);
+                }
+                Some(_) if quote == '"' => self.read_expandable_char(&mut out),
+                Some(c) => {
+                    out.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    /// Parse the body of `@{ ... }`
+    fn read_table(&mut self) -> Result<PsValue, String> {
+        let mut entries = Vec::new();
+
+        loop {
+            self.skip_whitespace_and_newlines();
+            match self.peek() {
+                None => return Err("Unterminated hashtable in PowerShell command".to_string()),
+                Some('}') => {
+                    self.pos += 1;
+                    return Ok(PsValue::Table(entries));
+                }
+                Some(';') => {
+                    self.pos += 1;
+                    continue;
+                }
+                _ => {}
+            }
+
+            let key = match self.read_value()? {
+                PsValue::Text(key) | PsValue::Raw(key) => key,
+                PsValue::Table(_) => return Err("Hashtable keys must be strings".to_string()),
+            };
+
+            self.skip_blanks();
+            if self.peek() != Some('=') {
+                return Err(format!("Expected '=' after hashtable key '{}'", key));
+            }
+            self.pos += 1;
+            self.skip_blanks();
+
+            entries.push((key, self.read_value()?));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_piped_response_and_unmatched_braces() {
+        let result =
+            powershell_to_http("irm https://api/users | ForEach-Object { $_.name }").unwrap();
+        assert!(result.http.contains("GET https://api/users"));
+
+        // Piping into the command still works, and later statements are read
+        let snippet =
+            "$u = 'https://api/items'; @{a=1} | ConvertTo-Json | irm $u -Method Post | Out-Null\n";
+        assert!(powershell_to_http(snippet)
+            .unwrap()
+            .http
+            .contains("POST https://api/items"));
+
+        assert_eq!(
+            powershell_to_http("irm https://api/users }").unwrap_err(),
+            "Unmatched '}' in PowerShell command"
+        );
+        assert!(powershell_to_http("irm https://api/users)").is_err());
+    }
+
+    #[test]
+    fn test_get_with_headers() {
+        let snippet = r#"Invoke-RestMethod -Uri "https://api.example.com/users?page=1" `
+    -Headers @{"Authorization"="Bearer $token"; 'Accept' = 'application/json'
+        "X-Request-Id" = 42 }"#;
+
+        let result = powershell_to_http(snippet).unwrap();
+        assert_eq!(
+            result.http,
+            "GET https://api.example.com/users?page=1\nAuthorization: Bearer {{token}}\nAccept: application/json\nX-Request-Id: 42\n"
+        );
+        assert!(result.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_post_with_here_string_body() {
+        let snippet = r#"$base = "https://api.example.com"
+$json = @"
+{
+  "name": "Ada",
+  "team": "$team"
+}
+"@
+$response = Invoke-WebRequest -Uri "$base/users" -Method Post -ContentType "application/json" `
+    -Body $json -Credential (Get-Credential) -TimeoutSec 30 -UseBasicParsing | ConvertFrom-Json"#;
+
+        let result = powershell_to_http(snippet).unwrap();
+        assert_eq!(
+            result.http,
+            "# Note: -TimeoutSec 30 is not supported and was skipped\n\
+             # @auth basic\n\
+             # @auth.username {{username}}\n\
+             # @auth.password {{password}}\n\
+             POST https://api.example.com/users\n\
+             Content-Type: application/json\n\
+             \n\
+             {\n  \"name\": \"Ada\",\n  \"team\": \"{{team}}\"\n}\n"
+        );
+        assert_eq!(
+            result.warnings,
+            vec!["-TimeoutSec 30 is not supported and was skipped"]
+        );
+    }
+}
@@ -0,0 +1,171 @@
+/// Headers that only describe the captured message's framing
+const FRAMING_HEADERS: &[&str] = &["host", "content-length", "transfer-encoding"];
+
+/// Convert a raw HTTP/1.x request message (as captured in logs or by a proxy)
+/// to HTTP file format.
+///
+/// The URL is rebuilt from the Host header and request target, using https
+/// unless `use_http` is set. Chunked bodies are de-chunked.
+pub fn raw_http_to_http(text: &str, use_http: bool) -> Result<String, String> {
+    let text = text.trim_start_matches(['\r', '\n', ' ', '\t']);
+    let (head, body) = split_message(text);
+    let mut lines = head.lines().map(|line| line.trim_end_matches('\r'));
+
+    let request_line = lines
+        .next()
+        .filter(|line| !line.trim().is_empty())
+        .ok_or_else(|| "No request line found".to_string())?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts
+        .next()
+        .ok_or_else(|| "No request line found".to_string())?
+        .to_uppercase();
+    let target = parts
+        .next()
+        .ok_or_else(|| format!("Request line '{}' has no target", request_line))?;
+    if let Some(version) = parts.next() {
+        if !version.starts_with("HTTP/") {
+            return Err(format!("'{}' is not an HTTP request line", request_line));
+        }
+    }
+
+    // Lines starting with whitespace continue the previous header (obs-fold)
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for line in lines {
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+                continue;
+            }
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    };
+
+    let url = if target.starts_with("http://") || target.starts_with("https://") {
+        target.to_string()
+    } else {
+        let host =
+            header("Host").ok_or_else(|| "No Host header to build the URL from".to_string())?;
+        let scheme = if use_http { "http" } else { "https" };
+        format!("{}://{}{}", scheme, host, target)
+    };
+
+    let chunked =
+        header("Transfer-Encoding").is_some_and(|te| te.to_ascii_lowercase().contains("chunked"));
+    let body = if chunked {
+        dechunk(body)?
+    } else {
+        body.to_string()
+    };
+
+    let mut output = format!("{} {}\n", method, url);
+    for (name, value) in &headers {
+        if !FRAMING_HEADERS.contains(&name.to_ascii_lowercase().as_str()) {
+            output.push_str(&format!("{}: {}\n", name, value));
+        }
+    }
+
+    let body = body.trim_end_matches(['\r', '\n']);
+    if !body.is_empty() {
+        output.push('\n');
+        match serde_json::from_str::<serde_json::Value>(body)
+            .ok()
+            .and_then(|json| serde_json::to_string_pretty(&json).ok())
+        {
+            Some(formatted) => output.push_str(&formatted),
+            None => output.push_str(&body.replace("\r\n", "\n")),
+        }
+        output.push('\n');
+    }
+
+    Ok(output)
+}
+
+/// Split a message into its head and body at the first empty line
+fn split_message(text: &str) -> (&str, &str) {
+    let crlf = text.find("\r\n\r\n").map(|i| (i, 4));
+    let lf = text.find("\n\n").map(|i| (i, 2));
+    match (crlf, lf) {
+        (Some(a), Some(b)) => {
+            let (i, len) = if a.0 < b.0 { a } else { b };
+            (&text[..i], &text[i + len..])
+        }
+        (Some((i, len)), None) | (None, Some((i, len))) => (&text[..i], &text[i + len..]),
+        (None, None) => (text, ""),
+    }
+}
+
+/// Decode a chunked transfer-encoding body
+fn dechunk(body: &str) -> Result<String, String> {
+    let mut rest = body;
+    let mut decoded = String::new();
+
+    loop {
+        let (size_line, after) = rest
+            .split_once('\n')
+            .ok_or_else(|| "Chunked body ends without a final chunk".to_string())?;
+        let size_text = size_line.trim_end_matches('\r');
+        // Chunk extensions follow a ';'
+        let size_text = size_text.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size_text, 16)
+            .map_err(|_| format!("Invalid chunk size '{}'", size_text))?;
+        if size == 0 {
+            return Ok(decoded);
+        }
+        let chunk = after
+            .get(..size)
+            .ok_or_else(|| "Chunk is shorter than its declared size".to_string())?;
+        decoded.push_str(chunk);
+        rest = after[size..]
+            .strip_prefix("\r\n")
+            .or_else(|| after[size..].strip_prefix('\n'))
+            .unwrap_or(&after[size..]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_captured_request_with_folded_header() {
+        let captured = "POST /api/v1/users?notify=true HTTP/1.1\r\nHost: api.example.com\r\nContent-Type: application/json\r\nX-Trace: abc\r\n\tdef\r\nContent-Length: 27\r\n\r\n{\"name\":\"Ada\",\"admin\":true}";
+
+        assert_eq!(
+            raw_http_to_http(captured, false).unwrap(),
+            "POST https://api.example.com/api/v1/users?notify=true\nContent-Type: application/json\nX-Trace: abc def\n\n{\n  \"name\": \"Ada\",\n  \"admin\": true\n}\n"
+        );
+        assert!(raw_http_to_http(captured, true)
+            .unwrap()
+            .starts_with("POST http://api.example.com/api/v1/users"));
+    }
+
+    #[test]
+    fn test_chunked_body() {
+        let captured = "PUT /notes/1 HTTP/1.1\r\nHost: localhost:8080\r\nTransfer-Encoding: chunked\r\nContent-Type: text/plain\r\n\r\n7\r\nhello, \r\n6;ext=1\r\nworld!\r\n0\r\n\r\n";
+
+        assert_eq!(
+            raw_http_to_http(captured, true).unwrap(),
+            "PUT http://localhost:8080/notes/1\nContent-Type: text/plain\n\nhello, world!\n"
+        );
+    }
+
+    #[test]
+    fn test_absolute_target_and_errors() {
+        assert_eq!(
+            raw_http_to_http("GET http://proxy.test/a HTTP/1.1\nAccept: */*\n", false).unwrap(),
+            "GET http://proxy.test/a\nAccept: */*\n"
+        );
+        assert!(raw_http_to_http("GET /a HTTP/1.1\nAccept: */*\n", false).is_err());
+        assert!(raw_http_to_http("hello world again", false).is_err());
+    }
+}
@@ -0,0 +1,389 @@
+use serde_json::{Map, Value};
+use std::path::PathBuf;
+
+use super::{
+    multipart_body, render_requests, sanitize_file_name, ConvertedCollection, FormPart,
+    RequestBlock, MULTIPART_BOUNDARY,
+};
+
+/// Convert a Thunder Client collection or environment export.
+///
+/// A collection becomes `<collection>.http` for its top-level requests plus
+/// `<collection>/<folder>.http` per folder. An environment export becomes an
+/// environment in http-client.env.json. Thunder's `{{var}}` syntax is kvile's
+/// too, so values are copied verbatim.
+pub fn thunder_to_http(json: &str) -> Result<ConvertedCollection, String> {
+    let export: Value = serde_json::from_str(json)
+        .map_err(|e| format!("Failed to parse Thunder Client export: {}", e))?;
+    let mut converted = ConvertedCollection::default();
+
+    if let Some(env_name) = export.get("environmentName").and_then(Value::as_str) {
+        let mut variables = Map::new();
+        for variable in export
+            .get("data")
+            .or(export.get("variables"))
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            let name = str_of(variable, "name");
+            if !name.is_empty() {
+                let value = variable.get("value").cloned().unwrap_or(Value::Null);
+                variables.insert(name.to_string(), value);
+            }
+        }
+        converted
+            .environments
+            .insert(env_name.to_string(), variables);
+        return Ok(converted);
+    }
+
+    let collection_name = export
+        .get("collectionName")
+        .and_then(Value::as_str)
+        .ok_or_else(|| "Not a Thunder Client collection or environment export".to_string())?;
+    let folders = list(&export, "folders");
+    let requests = list(&export, "requests");
+
+    let importer = Importer {
+        folders: &folders,
+        requests: &requests,
+        collection_auth: export.pointer("/settings/auth"),
+    };
+    importer.walk(
+        "",
+        PathBuf::new(),
+        &sanitize_file_name(collection_name),
+        &mut converted,
+    );
+    Ok(converted)
+}
+
+struct Importer<'a> {
+    folders: &'a [&'a Value],
+    requests: &'a [&'a Value],
+    collection_auth: Option<&'a Value>,
+}
+
+impl Importer<'_> {
+    /// Convert the requests in one container (`""` for the collection root) and its subfolders
+    fn walk(
+        &self,
+        container: &str,
+        dir: PathBuf,
+        file_stem: &str,
+        converted: &mut ConvertedCollection,
+    ) {
+        let mut requests: Vec<&Value> = self
+            .requests
+            .iter()
+            .copied()
+            .filter(|r| str_of(r, "containerId") == container)
+            .collect();
+        requests.sort_by(|a, b| sort_num(a).total_cmp(&sort_num(b)));
+
+        let blocks: Vec<RequestBlock> = requests
+            .into_iter()
+            .map(|request| self.convert_request(request, &mut converted.warnings))
+            .collect();
+        if !blocks.is_empty() {
+            let path = if dir.as_os_str().is_empty() {
+                PathBuf::from(format!("{}.http", file_stem))
+            } else {
+                dir.join(format!("{}.http", file_stem))
+            };
+            converted.files.push((path, render_requests(&blocks)));
+        }
+
+        let mut folders: Vec<&Value> = self
+            .folders
+            .iter()
+            .copied()
+            .filter(|f| str_of(f, "containerId") == container)
+            .collect();
+        folders.sort_by(|a, b| sort_num(a).total_cmp(&sort_num(b)));
+        for folder in folders {
+            self.walk(
+                str_of(folder, "_id"),
+                dir.join(file_stem),
+                &sanitize_file_name(name_of(folder)),
+                converted,
+            );
+        }
+    }
+
+    fn convert_request(&self, request: &Value, warnings: &mut Vec<String>) -> RequestBlock {
+        let name = name_of(request);
+        let mut block = RequestBlock {
+            name: name.to_string(),
+            method: str_of(request, "method").to_uppercase(),
+            url: str_of(request, "url").to_string(),
+            ..Default::default()
+        };
+        if block.method.is_empty() {
+            block.method = "GET".to_string();
+        }
+
+        for header in enabled(request.get("headers")) {
+            let key = str_of(header, "name");
+            if !key.is_empty() {
+                block
+                    .headers
+                    .push((key.to_string(), str_of(header, "value").to_string()));
+            }
+        }
+
+        if let Some(body) = request.get("body") {
+            convert_body(name, body, &mut block, warnings);
+        }
+        if let Some(auth) = self.auth_for(request) {
+            convert_auth(name, auth, &mut block, warnings);
+        }
+
+        if request
+            .get("tests")
+            .and_then(Value::as_array)
+            .is_some_and(|tests| !tests.is_empty())
+        {
+            warnings.push(format!("'{}': tests were not converted", name));
+        }
+        if !str_of(request, "docs").trim().is_empty() {
+            warnings.push(format!("'{}': docs were not converted", name));
+        }
+        if request.get("preReq").is_some_and(|p| !p.is_null()) {
+            warnings.push(format!(
+                "'{}': pre-request settings were not converted",
+                name
+            ));
+        }
+
+        block
+    }
+
+    /// The request's own auth, or the closest folder's or collection's for `inherit`
+    fn auth_for<'v>(&'v self, request: &'v Value) -> Option<&'v Value> {
+        let auth = request.get("auth");
+        if auth.map(|a| str_of(a, "type")) != Some("inherit") {
+            return auth;
+        }
+        let mut container = str_of(request, "containerId");
+        while let Some(folder) = self
+            .folders
+            .iter()
+            .find(|f| !container.is_empty() && str_of(f, "_id") == container)
+        {
+            match folder.pointer("/settings/auth") {
+                Some(auth) if str_of(auth, "type") != "inherit" => return Some(auth),
+                _ => container = str_of(folder, "containerId"),
+            }
+        }
+        self.collection_auth
+    }
+}
+
+fn convert_body(name: &str, body: &Value, block: &mut RequestBlock, warnings: &mut Vec<String>) {
+    let raw = str_of(body, "raw");
+    match str_of(body, "type") {
+        "" | "none" => {}
+        "json" => set_raw_body(block, "application/json", raw),
+        "xml" => set_raw_body(block, "application/xml", raw),
+        "text" => set_raw_body(block, "text/plain", raw),
+        "formencoded" => {
+            let pairs: Vec<String> = enabled(body.get("form"))
+                .map(|p| format!("{}={}", str_of(p, "name"), str_of(p, "value")))
+                .collect();
+            set_content_type(block, "application/x-www-form-urlencoded");
+            block.body = Some(pairs.join("&"));
+        }
+        "multipartform" => {
+            let mut parts: Vec<FormPart> = enabled(body.get("form"))
+                .map(|p| FormPart::Text {
+                    name: str_of(p, "name").to_string(),
+                    value: str_of(p, "value").to_string(),
+                })
+                .collect();
+            parts.extend(enabled(body.get("files")).map(|p| FormPart::File {
+                name: str_of(p, "name").to_string(),
+                path: str_of(p, "value").to_string(),
+            }));
+            block
+                .headers
+                .retain(|(k, _)| !k.eq_ignore_ascii_case("Content-Type"));
+            block.headers.push((
+                "Content-Type".to_string(),
+                format!("multipart/form-data; boundary={}", MULTIPART_BOUNDARY),
+            ));
+            block.body = Some(multipart_body(&parts));
+        }
+        "graphql" => {
+            let query = body
+                .pointer("/graphql/query")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            let variables = body
+                .pointer("/graphql/variables")
+                .and_then(Value::as_str)
+                .filter(|v| !v.trim().is_empty())
+                .and_then(|v| serde_json::from_str::<Value>(v).ok())
+                .unwrap_or(Value::Object(Map::new()));
+            let payload = serde_json::json!({ "query": query, "variables": variables });
+            set_content_type(block, "application/json");
+            block.body = serde_json::to_string_pretty(&payload).ok();
+        }
+        "binary" => match body.get("binary").and_then(Value::as_str) {
+            Some(path) if !path.is_empty() => {
+                block.body = Some(format!("< {}", super::curl::portable_path(path)))
+            }
+            _ => warnings.push(format!("'{}': binary body has no file selected", name)),
+        },
+        other => warnings.push(format!("'{}': unsupported body type '{}'", name, other)),
+    }
+}
+
+/// Map Thunder Client auth to kvile's `# @auth` metadata
+fn convert_auth(name: &str, auth: &Value, block: &mut RequestBlock, warnings: &mut Vec<String>) {
+    let mut meta = |key: &str, value: &str| {
+        block.metadata.push((key.to_string(), value.to_string()));
+    };
+    match str_of(auth, "type") {
+        "" | "none" | "inherit" => {}
+        "bearer" => {
+            meta("auth", "bearer");
+            meta("auth.token", str_of(auth, "bearer"));
+        }
+        "basic" => {
+            let basic = auth.get("basic").unwrap_or(&Value::Null);
+            meta("auth", "basic");
+            meta("auth.username", str_of(basic, "username"));
+            meta("auth.password", str_of(basic, "password"));
+        }
+        other => warnings.push(format!("'{}': unsupported auth type '{}'", name, other)),
+    }
+}
+
+fn set_raw_body(block: &mut RequestBlock, content_type: &str, raw: &str) {
+    if raw.trim().is_empty() {
+        return;
+    }
+    set_content_type(block, content_type);
+    block.body = Some(raw.to_string());
+}
+
+fn set_content_type(block: &mut RequestBlock, content_type: &str) {
+    if block.header("Content-Type").is_none() {
+        block
+            .headers
+            .push(("Content-Type".to_string(), content_type.to_string()));
+    }
+}
+
+fn list<'v>(value: &'v Value, key: &str) -> Vec<&'v Value> {
+    value
+        .get(key)
+        .and_then(Value::as_array)
+        .map(|items| items.iter().collect())
+        .unwrap_or_default()
+}
+
+fn sort_num(value: &Value) -> f64 {
+    value.get("sortNum").and_then(Value::as_f64).unwrap_or(0.0)
+}
+
+fn name_of(value: &Value) -> &str {
+    value
+        .get("name")
+        .and_then(Value::as_str)
+        .unwrap_or("Untitled")
+}
+
+fn str_of<'v>(value: &'v Value, key: &str) -> &'v str {
+    value.get(key).and_then(Value::as_str).unwrap_or_default()
+}
+
+/// Entries of a name/value list that aren't disabled
+fn enabled(list: Option<&Value>) -> impl Iterator<Item = &Value> {
+    list.and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter(|entry| entry.get("isDisabled").and_then(Value::as_bool) != Some(true))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLLECTION: &str = include_str!("../../tests/fixtures/thunder-collection_Demo.json");
+
+    fn file<'a>(converted: &'a ConvertedCollection, path: &str) -> &'a str {
+        converted
+            .files
+            .iter()
+            .find(|(p, _)| p == &PathBuf::from(path))
+            .map(|(_, content)| content.as_str())
+            .unwrap_or_else(|| panic!("{} was not created", path))
+    }
+
+    #[test]
+    fn test_collection() {
+        let converted = thunder_to_http(COLLECTION).unwrap();
+        let paths: Vec<_> = converted
+            .files
+            .iter()
+            .map(|(p, _)| p.to_string_lossy().replace('\\', "/"))
+            .collect();
+        assert_eq!(
+            paths,
+            vec!["Demo.http", "Demo/Users.http", "Demo/Users/Admin.http"]
+        );
+
+        assert_eq!(
+            file(&converted, "Demo.http"),
+            "### Health\nGET {{baseUrl}}/health\n"
+        );
+        let users = file(&converted, "Demo/Users.http");
+        assert!(users.contains(
+            "### List users\n# @auth bearer\n# @auth.token {{token}}\nGET {{baseUrl}}/users?page=1\nAccept: application/json\n"
+        ));
+        assert!(users.contains(
+            "### Create user\n# @auth basic\n# @auth.username admin\n# @auth.password {{password}}\nPOST {{baseUrl}}/users\nContent-Type: application/json\n\n{\"name\": \"Ada\"}\n"
+        ));
+        assert!(users.contains(
+            "### Login\nPOST {{baseUrl}}/login\nContent-Type: application/x-www-form-urlencoded\n\nuser=ada&pass={{password}}\n"
+        ));
+        assert!(users.contains(
+            "--WebAppBoundary\nContent-Disposition: form-data; name=\"avatar\"; filename=\"avatar.png\"\n\n< ./avatar.png\n"
+        ));
+        assert!(!users.contains("X-Disabled"));
+
+        // Inherits the folder's bearer auth
+        let admin = file(&converted, "Demo/Users/Admin.http");
+        assert!(admin.contains("### Search\n# @auth bearer\n# @auth.token {{adminToken}}\nPOST {{baseUrl}}/graphql\nContent-Type: application/json\n\n{\n  \"query\": \"{ users { id } }\",\n  \"variables\": {\n    \"limit\": 5\n  }\n}\n"));
+
+        for (_, content) in &converted.files {
+            crate::parser::parse_http_content(content).unwrap();
+        }
+        assert_eq!(
+            converted.warnings,
+            vec![
+                "'List users': tests were not converted".to_string(),
+                "'Create user': docs were not converted".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_environment() {
+        let converted = thunder_to_http(
+            r#"{"client": "Thunder Client", "environmentName": "Local", "dateExported": "2024-01-10T10:00:00.000Z", "version": "1.1", "folder": "", "data": [{"name": "baseUrl", "value": "http://localhost:3000"}, {"name": "token", "value": "dev"}]}"#,
+        )
+        .unwrap();
+        assert!(converted.files.is_empty());
+        assert_eq!(
+            converted.environments["Local"]["baseUrl"],
+            "http://localhost:3000"
+        );
+        assert_eq!(converted.environments["Local"]["token"], "dev");
+
+        assert!(thunder_to_http(r#"{"info": {}}"#).is_err());
+    }
+}
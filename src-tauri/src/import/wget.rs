@@ -0,0 +1,202 @@
+use super::curl::{curl_to_http, parse_seconds, portable_path, shell_words, CurlCommand};
+use super::ImportResult;
+
+/// Flags that only change wget's console output
+const IGNORED_SWITCHES: &[&str] = &[
+    "-q",
+    "--quiet",
+    "-v",
+    "--verbose",
+    "-nv",
+    "--no-verbose",
+    "-S",
+    "--server-response",
+];
+
+/// Options naming local files wget writes, which don't affect the request
+const IGNORED_WITH_ARGUMENT: &[&str] = &[
+    "-O",
+    "--output-document",
+    "-o",
+    "--output-file",
+    "-a",
+    "--append-output",
+    "-P",
+    "--directory-prefix",
+];
+
+/// Unsupported wget options that consume the following word as their value
+const FLAGS_WITH_ARGUMENT: &[&str] = &[
+    "-l",
+    "--level",
+    "-A",
+    "--accept",
+    "-R",
+    "--reject",
+    "-w",
+    "--wait",
+    "--waitretry",
+    "-e",
+    "--execute",
+    "--limit-rate",
+    "--load-cookies",
+    "--save-cookies",
+    "--certificate",
+    "--private-key",
+    "--ca-certificate",
+];
+
+/// Convert a wget command to HTTP file format
+pub fn wget_to_http(input: &str) -> Result<ImportResult, String> {
+    let tokens = shell_words(input)?;
+    let mut cmd = CurlCommand::default();
+    let mut method: Option<String> = None;
+    let mut user: Option<String> = None;
+    let mut password: Option<String> = None;
+    let mut i = 0;
+
+    while i < tokens.len() {
+        // Long options may carry their value as --flag=value
+        let (flag, inline_value) = match tokens[i].split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_string())),
+            _ => (tokens[i].as_str(), None),
+        };
+        let takes_value = |flag: &str| {
+            matches!(
+                flag,
+                "--header"
+                    | "--post-data"
+                    | "--post-file"
+                    | "--body-data"
+                    | "--body-file"
+                    | "--method"
+                    | "-T"
+                    | "--timeout"
+                    | "-t"
+                    | "--tries"
+                    | "-U"
+                    | "--user-agent"
+                    | "--user"
+                    | "--http-user"
+                    | "--password"
+                    | "--http-password"
+            ) || IGNORED_WITH_ARGUMENT.contains(&flag)
+                || FLAGS_WITH_ARGUMENT.contains(&flag)
+        };
+
+        let value = if inline_value.is_some() {
+            inline_value
+        } else if takes_value(flag) && i + 1 < tokens.len() {
+            i += 1;
+            Some(tokens[i].clone())
+        } else {
+            None
+        };
+        i += 1;
+
+        match (flag, value) {
+            ("wget" | "wget.exe", _) => {}
+            ("--header", Some(header)) => cmd.add_header_line(&header),
+            ("--post-data" | "--body-data", Some(data)) => cmd.body = Some(data),
+            ("--post-file" | "--body-file", Some(path)) => {
+                cmd.body = Some(format!("< {}", portable_path(&path)))
+            }
+            ("--method", Some(value)) => method = Some(value.to_uppercase()),
+            ("--no-check-certificate", None) => cmd.tls.insecure = true,
+            ("-T" | "--timeout", Some(value)) => match parse_seconds(&value) {
+                Some(seconds) => cmd
+                    .metadata
+                    .push(("timeout".to_string(), format!("{}s", seconds))),
+                None => cmd.notes.push(format!(
+                    "{} {} is not a valid number of seconds and was ignored",
+                    flag, value
+                )),
+            },
+            // wget counts the first attempt, curl's --retry does not
+            ("-t" | "--tries", Some(value)) => match value.parse::<u32>() {
+                Ok(tries) if tries > 0 => cmd.retry.count = Some(tries - 1),
+                _ => cmd.notes.push(format!(
+                    "{} {} can't be expressed as a retry count and was ignored",
+                    flag, value
+                )),
+            },
+            ("-U" | "--user-agent", Some(agent)) => {
+                cmd.headers.push(("User-Agent".to_string(), agent))
+            }
+            ("--user" | "--http-user", Some(value)) => user = Some(value),
+            ("--password" | "--http-password", Some(value)) => password = Some(value),
+            (flag, _) if IGNORED_SWITCHES.contains(&flag) => {}
+            (flag, _) if IGNORED_WITH_ARGUMENT.contains(&flag) => {}
+            (flag, None) if !flag.starts_with('-') => {
+                if cmd.url.is_empty() {
+                    cmd.url = flag.to_string();
+                }
+            }
+            (flag, Some(value)) => cmd.notes.push(format!(
+                "{} {} is not supported and was skipped",
+                flag, value
+            )),
+            (flag, None) => cmd
+                .notes
+                .push(format!("{} is not supported and was skipped", flag)),
+        }
+    }
+
+    if cmd.url.is_empty() {
+        return Err("No URL found in wget command".to_string());
+    }
+
+    cmd.method = match method {
+        Some(method) => method,
+        None if cmd.body.is_some() => "POST".to_string(),
+        None => "GET".to_string(),
+    };
+    if let Some(user) = user {
+        cmd.auth = Some((user, password.unwrap_or_default()));
+    }
+
+    Ok(ImportResult {
+        http: curl_to_http(&cmd),
+        warnings: cmd.notes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_post_with_header_and_body() {
+        let result = wget_to_http(
+            r#"wget --header="Authorization: Bearer x" --header 'Content-Type: application/json' --post-data='{"a":1}' --timeout=30 https://api.example.com/endpoint"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            result.http,
+            "# @timeout 30s\nPOST https://api.example.com/endpoint\nAuthorization: Bearer x\nContent-Type: application/json\n\n{\n  \"a\": 1\n}\n"
+        );
+        assert!(result.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_get_ignores_output_document() {
+        let result =
+            wget_to_http("wget -q -O - --no-check-certificate https://api.example.com/health")
+                .unwrap();
+        assert_eq!(
+            result.http,
+            "# Note: certificate checks were turned off, but kvile still verifies certificates\n\
+             # @insecure true\nGET https://api.example.com/health\n"
+        );
+
+        let recursive = wget_to_http("wget -r --level=2 https://example.com/docs/").unwrap();
+        assert_eq!(
+            recursive.warnings,
+            vec![
+                "-r is not supported and was skipped",
+                "--level 2 is not supported and was skipped",
+            ]
+        );
+    }
+}
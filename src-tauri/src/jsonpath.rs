@@ -0,0 +1,131 @@
+//! JSONPath (RFC 9535) queries over JSON values, such as `$.items[0].id`,
+//! `$..id` or `$.items[?(@.id > 3)]`. Extracting from response bodies and
+//! `{{login.response.body.$.token}}` references both go through here.
+
+use serde::Serialize;
+use serde_json::Value;
+use serde_json_path::JsonPath;
+
+/// A value a query matched
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct JsonMatch {
+    /// Where the value is, in normalized bracket notation such as
+    /// `$['items'][0]['id']`
+    pub path: String,
+    pub value: Value,
+}
+
+/// A query that isn't valid JSONPath
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("Invalid JSONPath at position {position}: {message}")]
+pub struct JsonPathError {
+    pub message: String,
+    /// 1-based character in the query
+    pub position: usize,
+}
+
+fn parse(path: &str) -> Result<JsonPath, JsonPathError> {
+    JsonPath::parse(path).map_err(|e| JsonPathError {
+        message: e.message().to_string(),
+        position: e.position(),
+    })
+}
+
+/// Every value `path` matches in `value`, in document order
+pub fn query(value: &Value, path: &str) -> Result<Vec<JsonMatch>, JsonPathError> {
+    Ok(parse(path)?
+        .query_located(value)
+        .into_iter()
+        .map(|node| JsonMatch {
+            path: node.location().to_string(),
+            value: node.node().clone(),
+        })
+        .collect())
+}
+
+/// The value `path` selects: the one match, an array of several, or `None`
+/// when nothing matches
+pub fn select(value: &Value, path: &str) -> Result<Option<Value>, JsonPathError> {
+    let mut matches = parse(path)?.query(value).all();
+    Ok(match matches.len() {
+        0 => None,
+        1 => matches.pop().cloned(),
+        _ => Some(Value::Array(matches.into_iter().cloned().collect())),
+    })
+}
+
+/// [`query`] over a JSON document, such as a response body
+pub fn extract(body: &str, path: &str) -> Result<Vec<JsonMatch>, String> {
+    let value: Value =
+        serde_json::from_str(body).map_err(|e| format!("Body is not JSON: {}", e))?;
+    query(&value, path).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn body() -> Value {
+        json!({
+            "total": 5,
+            "items": [
+                {"id": 2, "name": "bolt", "tags": [{"id": 10}]},
+                {"id": 4, "name": "nut"},
+                {"id": 7, "name": "gear", "price": 3.5}
+            ]
+        })
+    }
+
+    fn paths(matches: &[JsonMatch]) -> Vec<&str> {
+        matches.iter().map(|m| m.path.as_str()).collect()
+    }
+
+    #[test]
+    fn test_filter() {
+        let matches = query(&body(), "$.items[?(@.id>3)]").unwrap();
+        assert_eq!(paths(&matches), vec!["$['items'][1]", "$['items'][2]"]);
+        assert_eq!(matches[1].value["name"], "gear");
+
+        let matches = query(&body(), "$.items[?@.price].name").unwrap();
+        assert_eq!(
+            matches,
+            vec![JsonMatch {
+                path: "$['items'][2]['name']".to_string(),
+                value: json!("gear"),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_recursive_descent() {
+        let matches = query(&body(), "$..id").unwrap();
+        let ids: Vec<&Value> = matches.iter().map(|m| &m.value).collect();
+        assert_eq!(ids.len(), 4);
+        assert!(paths(&matches).contains(&"$['items'][0]['tags'][0]['id']"));
+        assert_eq!(select(&body(), "$..tags[*].id").unwrap(), Some(json!(10)));
+        assert_eq!(
+            select(&body(), "$.items[*].name").unwrap(),
+            Some(json!(["bolt", "nut", "gear"]))
+        );
+    }
+
+    #[test]
+    fn test_no_match_and_invalid_paths() {
+        assert!(query(&body(), "$.items[?(@.id>100)]").unwrap().is_empty());
+        assert!(query(&body(), "$.missing..id").unwrap().is_empty());
+        assert_eq!(select(&body(), "$.items[9]").unwrap(), None);
+
+        let error = query(&body(), "$.items[?(@.id>]").unwrap_err();
+        assert!(error.position > 1, "{:?}", error);
+        assert!(error
+            .to_string()
+            .starts_with(&format!("Invalid JSONPath at position {}", error.position)));
+        assert!(query(&body(), "items").is_err());
+
+        assert_eq!(
+            extract("not json", "$").unwrap_err().split(':').next(),
+            Some("Body is not JSON")
+        );
+    }
+}
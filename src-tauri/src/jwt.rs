@@ -0,0 +1,167 @@
+//! Local JWT inspection, so tokens never have to be pasted into a website.
+//!
+//! Decoding only: signatures are not verified.
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+
+/// A decoded JWT with its common claims pulled out
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DecodedJwt {
+    pub header: Value,
+    pub payload: Value,
+    /// The signature segment as it appeared in the token
+    pub signature: String,
+    /// `exp` as RFC 3339
+    pub expires_at: Option<String>,
+    /// `iat` as RFC 3339
+    pub issued_at: Option<String>,
+    /// `nbf` as RFC 3339
+    pub not_before: Option<String>,
+    /// Seconds until `exp`; negative once expired
+    pub expires_in: Option<i64>,
+    pub issuer: Option<String>,
+    /// `aud`, which may be a single string or a list
+    pub audience: Vec<String>,
+}
+
+/// Decode a JWT's header and payload without verifying its signature. A
+/// `Bearer ` prefix and surrounding whitespace are ignored; a malformed token
+/// fails with an error naming the part that is wrong.
+#[cfg_attr(feature = "app", tauri::command)]
+pub fn decode_jwt(token: String) -> Result<DecodedJwt, String> {
+    decode_jwt_at(&token, Utc::now().timestamp())
+}
+
+fn decode_jwt_at(token: &str, now: i64) -> Result<DecodedJwt, String> {
+    let token = token.trim();
+    let token = token
+        .strip_prefix("Bearer ")
+        .or_else(|| token.strip_prefix("bearer "))
+        .unwrap_or(token)
+        .trim();
+
+    let parts: Vec<&str> = token.split('.').collect();
+    let [header, payload, signature] = parts[..] else {
+        return Err(format!(
+            "Invalid JWT: expected 3 parts separated by '.', found {}",
+            parts.len()
+        ));
+    };
+    let header = decode_part(header, "header")?;
+    let payload = decode_part(payload, "payload")?;
+    base64url(signature)
+        .map_err(|e| format!("Invalid JWT signature: not valid base64url ({})", e))?;
+
+    let claim = |name: &str| payload.get(name).and_then(Value::as_f64).map(|t| t as i64);
+    let exp = claim("exp");
+    let audience = match payload.get("aud") {
+        Some(Value::String(aud)) => vec![aud.clone()],
+        Some(Value::Array(auds)) => auds
+            .iter()
+            .filter_map(|aud| aud.as_str().map(str::to_string))
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    Ok(DecodedJwt {
+        expires_at: exp.and_then(rfc3339),
+        issued_at: claim("iat").and_then(rfc3339),
+        not_before: claim("nbf").and_then(rfc3339),
+        expires_in: exp.map(|exp| exp - now),
+        issuer: payload
+            .get("iss")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        audience,
+        header,
+        payload,
+        signature: signature.to_string(),
+    })
+}
+
+/// Decode the header or payload, which must be a JSON object
+fn decode_part(part: &str, name: &str) -> Result<Value, String> {
+    let bytes = base64url(part)
+        .map_err(|e| format!("Invalid JWT {}: not valid base64url ({})", name, e))?;
+    let text =
+        String::from_utf8(bytes).map_err(|_| format!("Invalid JWT {}: not valid UTF-8", name))?;
+    match serde_json::from_str(&text) {
+        Ok(value @ Value::Object(_)) => Ok(value),
+        Ok(_) => Err(format!("Invalid JWT {}: not a JSON object", name)),
+        Err(e) => Err(format!("Invalid JWT {}: not valid JSON ({})", name, e)),
+    }
+}
+
+/// Base64url, tolerating the padding some issuers leave in
+fn base64url(part: &str) -> Result<Vec<u8>, base64::DecodeError> {
+    URL_SAFE_NO_PAD.decode(part.trim_end_matches('='))
+}
+
+fn rfc3339(timestamp: i64) -> Option<String> {
+    DateTime::<Utc>::from_timestamp(timestamp, 0).map(|t| t.to_rfc3339())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// RS256 token issued at 2023-11-14T22:13:20Z, valid for an hour
+    const TOKEN: &str = "eyJhbGciOiJSUzI1NiIsInR5cCI6IkpXVCIsImtpZCI6ImsxIn0.eyJpc3MiOiJodHRwczovL2F1dGguZXhhbXBsZS5jb20iLCJzdWIiOiJ1c2VyLTEiLCJhdWQiOlsiYXBpIiwid2ViIl0sImlhdCI6MTcwMDAwMDAwMCwibmJmIjoxNzAwMDAwMDAwLCJleHAiOjE3MDAwMDM2MDB9.c2ln";
+
+    /// Expired in 2020, with non-ASCII name and greeting claims
+    const EXPIRED_TOKEN: &str = "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9.eyJpc3MiOiJodHRwczovL2lkcC50ZXN0IiwiYXVkIjoiYXBpIiwibmFtZSI6IkJqw7hybiDDmGRlZ8OlcmQiLCJncmVldGluZyI6IuOBk-OCk-OBq-OBoeOBryIsImV4cCI6MTYwMDAwMDAwMH0.c2ln";
+
+    #[test]
+    fn test_decode_jwt() {
+        let jwt = decode_jwt_at(TOKEN, 1_700_000_600).unwrap();
+        assert_eq!(jwt.header["alg"], "RS256");
+        assert_eq!(jwt.header["kid"], "k1");
+        assert_eq!(jwt.payload["sub"], "user-1");
+        assert_eq!(jwt.signature, "c2ln");
+        assert_eq!(jwt.issued_at.as_deref(), Some("2023-11-14T22:13:20+00:00"));
+        assert_eq!(jwt.not_before.as_deref(), Some("2023-11-14T22:13:20+00:00"));
+        assert_eq!(jwt.expires_at.as_deref(), Some("2023-11-14T23:13:20+00:00"));
+        assert_eq!(jwt.expires_in, Some(3000));
+        assert_eq!(jwt.issuer.as_deref(), Some("https://auth.example.com"));
+        assert_eq!(jwt.audience, vec!["api", "web"]);
+
+        // As copied from an Authorization header
+        let header = format!("  Bearer {}\n", TOKEN);
+        assert_eq!(decode_jwt_at(&header, 1_700_000_600).unwrap(), jwt);
+    }
+
+    #[test]
+    fn test_decode_expired_jwt_with_non_ascii_claims() {
+        let jwt = decode_jwt_at(EXPIRED_TOKEN, 1_700_000_000).unwrap();
+        assert_eq!(jwt.payload["name"], "Bjørn Ødegård");
+        assert_eq!(jwt.payload["greeting"], "こんにちは");
+        assert_eq!(jwt.expires_at.as_deref(), Some("2020-09-13T12:26:40+00:00"));
+        assert_eq!(jwt.expires_in, Some(-100_000_000));
+        assert_eq!(jwt.issued_at, None);
+        assert_eq!(jwt.audience, vec!["api"]);
+    }
+
+    #[test]
+    fn test_decode_invalid_jwt() {
+        let err = |token: &str| decode_jwt_at(token, 0).unwrap_err();
+        assert_eq!(
+            err("abc.def"),
+            "Invalid JWT: expected 3 parts separated by '.', found 2"
+        );
+        assert!(err("e30!.e30.").starts_with("Invalid JWT header: not valid base64url"));
+        // `{"alg":"HS256"` is cut short
+        assert!(err("eyJhbGciOiJIUzI1NiI.e30.").starts_with("Invalid JWT header: not valid JSON"));
+        assert_eq!(
+            err("e30.bm90IGpzb24.x"),
+            "Invalid JWT payload: not valid JSON (expected ident at line 1 column 2)"
+        );
+        assert_eq!(err("e30.WzFd.x"), "Invalid JWT payload: not a JSON object");
+        assert_eq!(err("e30._w.x"), "Invalid JWT payload: not valid UTF-8");
+        assert!(err("e30.e30.a*b").starts_with("Invalid JWT signature"));
+        // Unsigned tokens have an empty signature
+        assert!(decode_jwt_at("e30.e30.", 0).is_ok());
+    }
+}
@@ -1,52 +1,208 @@
+// Without the `app` feature only what kvile-cli runs on is built, and the
+// helpers just the commands call go unused
+#![cfg_attr(not(feature = "app"), allow(dead_code))]
+
+mod auth;
+#[cfg(feature = "app")]
 mod commands;
-mod curl;
+mod compare;
+mod encryption;
 mod env;
+mod exec;
+mod files;
 mod history;
 mod http_client;
+mod ignores;
+mod import;
+mod jsonpath;
+mod jwt;
+mod lint;
+mod logging;
 mod oidc;
 mod parser;
+pub mod runner;
+mod search;
+mod secrets;
+mod templates;
+mod tokens;
+mod variables;
+#[cfg(feature = "app")]
 mod watcher;
+mod workspace_settings;
 
+#[cfg(feature = "app")]
 use commands::*;
+#[cfg(feature = "app")]
 use env::*;
+#[cfg(feature = "app")]
 use history::HistoryDb;
+#[cfg(feature = "app")]
+use jwt::*;
+#[cfg(feature = "app")]
+use lint::*;
+#[cfg(feature = "app")]
+use tauri_plugin_deep_link::DeepLinkExt;
+#[cfg(feature = "app")]
 use watcher::*;
 
+#[cfg(feature = "app")]
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    // Initialize history database
-    let history_db = HistoryDb::new().expect("Failed to initialize history database");
+    // Kept until the app exits, so buffered log lines reach the file
+    let _log_guard = logging::init();
+    // Initialize history database; falls back to memory rather than failing
+    let history_db = HistoryDb::new();
+    // Workspaces trusted to run env file commands in earlier sessions
+    for workspace in history_db
+        .workspaces_with_setting(history::EXEC_TRUSTED_KEY)
+        .unwrap_or_default()
+    {
+        exec::set_trusted(&workspace, true);
+    }
 
     tauri::Builder::default()
+        // Deep links that launch a second instance are passed to this one
+        .plugin(tauri_plugin_single_instance::init(|_app, _argv, _cwd| {}))
+        .plugin(tauri_plugin_deep_link::init())
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_shell::init())
         .manage(history_db)
+        .manage(tokens::TokenStore::new())
+        .manage(WatcherManager::default())
+        .setup(|app| {
+            // macOS registers the scheme from the app bundle; elsewhere it is
+            // registered at runtime, which can fail on some desktops
+            #[cfg(any(target_os = "linux", target_os = "windows"))]
+            if let Err(e) = app.deep_link().register_all() {
+                tracing::warn!(
+                    "Failed to register the {}:// scheme: {}",
+                    oidc::DEEP_LINK_SCHEME,
+                    e
+                );
+                oidc::set_deep_link_error(e.to_string());
+            }
+            app.deep_link().on_open_url(|event| {
+                for url in event.urls() {
+                    oidc::deliver_deep_link(&url);
+                }
+            });
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             send_request,
             parse_http_file,
+            order_requests,
             read_file,
             write_file,
+            create_file,
+            create_directory,
+            list_templates,
+            create_http_file_from_template,
+            rename_path,
+            delete_path,
             list_http_files,
+            search_workspace,
+            cancel_search,
             start_watching,
             stop_watching,
-            get_watched_path,
+            stop_all_watching,
+            pause_watching,
+            resume_watching,
+            get_watched_paths,
             load_environment_config,
             save_environment,
+            save_environment_variable,
+            delete_environment_variable,
+            move_private_values_to_keychain,
+            validate_environment_config,
+            lint_workspace_variables,
+            validate_http_file,
+            lint_http_file,
+            create_private_env,
+            encrypt_private_env,
+            unlock_private_env,
             // History commands
             get_history,
             get_history_entry,
+            get_history_entry_body,
+            extract_json,
+            get_history_filtered,
+            get_history_page,
+            count_history,
+            resend_from_history,
+            export_history,
+            export_history_as_http,
+            save_response_body,
+            get_recent_logs,
+            open_log_directory,
+            get_recent_workspaces,
+            touch_workspace,
+            pin_workspace,
+            remove_recent_workspace,
+            export_history_db,
+            import_history_db,
+            get_history_stats,
+            get_endpoint_stats,
+            get_latest_per_request,
+            add_history_tag,
+            remove_history_tag,
+            get_history_entry_tags,
+            list_history_tags,
+            get_local_workspace_settings,
+            set_local_workspace_setting,
+            get_workspace_settings,
+            update_workspace_settings,
+            set_exec_trust,
+            get_history_retention,
+            set_history_retention,
+            run_prune_now,
+            get_history_db_warning,
+            repair_history_db,
+            search_history,
+            search_history_bodies,
             add_history_entry,
             delete_history_entry,
             clear_history,
+            delete_history_before,
             // Import commands
             convert_curl_to_http,
+            convert_curls_to_http,
+            convert_fetch_to_http,
+            convert_powershell_to_http,
+            convert_wget_to_http,
+            import_raw_http,
+            import_postman_collection,
+            import_thunder_client,
+            import_postman_environment,
+            import_insomnia_export,
+            import_openapi,
+            import_har,
+            export_http_as_script,
+            resolve_request,
+            compare_across_environments,
+            export_postman_collection,
+            export_postman_environment,
+            export_har,
             // OIDC commands
             oidc_discover,
             oidc_start_auth,
             oidc_wait_for_callback,
+            oidc_cancel_callback,
+            oidc_introspect_token,
+            oidc_logout,
+            oidc_wait_for_logout,
             oidc_exchange_code,
             oidc_refresh_token,
+            oidc_client_credentials,
+            oidc_password_grant,
+            oidc_token_exchange,
+            decode_jwt,
+            get_oidc_auth_config,
+            store_tokens,
+            load_tokens,
+            get_valid_token,
+            delete_tokens,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
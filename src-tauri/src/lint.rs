@@ -0,0 +1,364 @@
+//! Cross-checks `{{name}}` references in a workspace's .http files against
+//! the variables that could define them, and checks the requests of one file.
+
+mod requests;
+
+pub use requests::HttpDiagnostic;
+
+use crate::env::EnvironmentConfig;
+use crate::ignores::WorkspaceIgnore;
+use crate::parser::parse_http_content;
+use crate::workspace_settings::WorkspaceSettings;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// A `{{name}}` reference nothing defines
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UndefinedReference {
+    pub name: String,
+    /// 1-based line of the reference
+    pub line: usize,
+}
+
+/// Undefined references in one .http file
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FileLint {
+    pub file: String,
+    pub undefined: Vec<UndefinedReference>,
+}
+
+/// Result of linting a workspace's variables
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VariableLint {
+    /// Files with undefined references, by path
+    pub files: Vec<FileLint>,
+    /// Env and shared variables no .http file or env value references
+    pub unused: Vec<String>,
+}
+
+/// Report `{{name}}` references in the workspace's .http files that no file
+/// variable, prompt, script global, named request or env value defines, and
+/// env variables never referenced. With `environment`, only that environment
+/// and shared values count; without, any environment does.
+#[cfg_attr(feature = "app", tauri::command)]
+pub async fn lint_workspace_variables(
+    workspace: String,
+    environment: Option<String>,
+    env_glob: Option<String>,
+) -> Result<VariableLint, String> {
+    let root = PathBuf::from(&workspace);
+    let ignore = tokio::task::spawn_blocking(move || WorkspaceIgnore::load(&root))
+        .await
+        .map_err(|e| e.to_string())?;
+    let files: Vec<_> =
+        crate::files::list_workspace_files(&workspace, &ignore, &Default::default())
+            .await?
+            .files
+            .into_iter()
+            .filter(|file| file.is_http_file)
+            .collect();
+
+    let mut contents = Vec::new();
+    for file in files {
+        let content = tokio::fs::read_to_string(&file.path)
+            .await
+            .map_err(|e| format!("Failed to read {}: {}", file.path, e))?;
+        contents.push((file.path, content));
+    }
+
+    // Env files depend on the directory, so load each directory's config once
+    let mut configs: HashMap<String, EnvironmentConfig> = HashMap::new();
+    let root =
+        crate::env::load_environment_config(workspace.clone(), None, env_glob.clone()).await?;
+    for (path, _) in &contents {
+        let dir = Path::new(path)
+            .parent()
+            .unwrap_or(Path::new(&workspace))
+            .to_string_lossy()
+            .to_string();
+        if let Entry::Vacant(entry) = configs.entry(dir) {
+            let config = crate::env::load_environment_config(
+                workspace.clone(),
+                Some(path.clone()),
+                env_glob.clone(),
+            )
+            .await?;
+            entry.insert(config);
+        }
+    }
+
+    let inputs: Vec<LintInput> = contents
+        .iter()
+        .map(|(path, content)| {
+            let dir = Path::new(path).parent().unwrap_or(Path::new(&workspace));
+            LintInput {
+                file: path,
+                content,
+                config: &configs[&*dir.to_string_lossy()],
+            }
+        })
+        .collect();
+    Ok(lint_variables(&inputs, &root, environment.as_deref()))
+}
+
+/// Problems in a .http file that make part of it be skipped or misread, such
+/// as a block without a request line or a script never closed
+#[cfg_attr(feature = "app", tauri::command)]
+pub fn validate_http_file(content: String) -> Vec<HttpDiagnostic> {
+    requests::validate(&content)
+}
+
+/// Check the requests of a .http file against the lint rules (see
+/// [`requests::LINT_RULES`]) the workspace's settings leave on. The workspace's env
+/// config, for `environment` or without one any environment, gives the
+/// `baseUrl` plain http:// URLs are flagged against.
+#[cfg_attr(feature = "app", tauri::command)]
+pub async fn lint_http_file(
+    content: String,
+    workspace: String,
+    environment: Option<String>,
+) -> Result<Vec<HttpDiagnostic>, String> {
+    let root = PathBuf::from(&workspace);
+    let settings = tokio::task::spawn_blocking(move || WorkspaceSettings::load(&root).settings)
+        .await
+        .map_err(|e| e.to_string())?;
+    // validate_environment_config reports env files that don't load
+    let config = crate::env::load_environment_config(workspace, None, None)
+        .await
+        .ok();
+    Ok(requests::lint(
+        &content,
+        &settings,
+        config.as_ref(),
+        environment.as_deref(),
+    ))
+}
+
+/// One .http file and the env config it loads
+pub struct LintInput<'a> {
+    pub file: &'a str,
+    pub content: &'a str,
+    pub config: &'a EnvironmentConfig,
+}
+
+/// [`lint_workspace_variables`] over files already read. `root` is the
+/// workspace's own env config, whose variables count for unused checks even
+/// when no file loads them.
+pub fn lint_variables(
+    files: &[LintInput],
+    root: &EnvironmentConfig,
+    environment: Option<&str>,
+) -> VariableLint {
+    let reference_re = Regex::new(r"\{\{\s*([^{}\s]+)[^{}]*\}\}").unwrap();
+    let prompt_re = Regex::new(r"^#\s*@prompt\s+([\w.-]+)").unwrap();
+    let global_re = Regex::new(r#"client\.global\.set\(\s*["']([\w.-]+)["']"#).unwrap();
+
+    // Names defined for every file: script globals and named requests
+    let mut workspace_names = HashSet::new();
+    for input in files {
+        workspace_names.extend(
+            global_re
+                .captures_iter(input.content)
+                .map(|caps| caps[1].to_string()),
+        );
+        for request in parse_http_content(input.content).unwrap_or_default() {
+            workspace_names.extend(request.name);
+            workspace_names.extend(request.metadata.get("name").cloned());
+        }
+    }
+
+    let mut referenced = HashSet::new();
+    let mut lints = Vec::new();
+    for input in files {
+        let mut defined = workspace_names.clone();
+        for request in parse_http_content(input.content).unwrap_or_default() {
+            defined.extend(request.variables.into_keys());
+        }
+        defined.extend(
+            input
+                .content
+                .lines()
+                .filter_map(|line| prompt_re.captures(line.trim()))
+                .map(|caps| caps[1].to_string()),
+        );
+        defined.extend(env_names(input.config, environment));
+
+        let mut undefined = Vec::new();
+        for (index, line) in input.content.lines().enumerate() {
+            for caps in reference_re.captures_iter(line) {
+                let name = &caps[1];
+                // Dynamic built-ins such as {{$uuid}}
+                if name.starts_with('$') {
+                    continue;
+                }
+                referenced.insert(name.to_string());
+                if !is_defined(&defined, name) {
+                    undefined.push(UndefinedReference {
+                        name: name.to_string(),
+                        line: index + 1,
+                    });
+                }
+            }
+        }
+        if !undefined.is_empty() {
+            lints.push(FileLint {
+                file: input.file.to_string(),
+                undefined,
+            });
+        }
+    }
+    lints.sort_by(|a, b| a.file.cmp(&b.file));
+
+    // Env values may reference other env variables
+    let configs = std::iter::once(root).chain(files.iter().map(|input| input.config));
+    let mut env_variables = BTreeSet::new();
+    for config in configs {
+        for vars in env_maps(config, environment) {
+            for value in vars.values() {
+                referenced.extend(
+                    reference_re
+                        .captures_iter(value)
+                        .map(|caps| caps[1].to_string()),
+                );
+            }
+            env_variables.extend(vars.keys().cloned());
+        }
+    }
+    let unused = env_variables
+        .into_iter()
+        .filter(|name| {
+            !referenced
+                .iter()
+                .any(|reference| refers_to(reference, name))
+        })
+        .collect();
+
+    VariableLint {
+        files: lints,
+        unused,
+    }
+}
+
+/// The variable maps of a config that count for `environment`
+fn env_maps<'a>(
+    config: &'a EnvironmentConfig,
+    environment: Option<&'a str>,
+) -> Vec<&'a HashMap<String, String>> {
+    let mut maps = vec![&config.shared, &config.private_shared];
+    for env in &config.environments {
+        if environment.is_none_or(|name| name == env.name) {
+            maps.push(&env.variables);
+            maps.push(&env.private_variables);
+        }
+    }
+    maps
+}
+
+fn env_names(config: &EnvironmentConfig, environment: Option<&str>) -> Vec<String> {
+    env_maps(config, environment)
+        .into_iter()
+        .flat_map(|vars| vars.keys().cloned())
+        .collect()
+}
+
+/// Whether a reference is defined itself or reaches into a defined value,
+/// as `{{auth.clientId}}` or `{{login.response.body.token}}` do
+fn is_defined(defined: &HashSet<String>, name: &str) -> bool {
+    defined.contains(name)
+        || name
+            .match_indices('.')
+            .any(|(i, _)| defined.contains(&name[..i]))
+}
+
+/// Whether a reference uses the variable `name`
+fn refers_to(reference: &str, name: &str) -> bool {
+    reference == name
+        || reference
+            .strip_prefix(name)
+            .is_some_and(|rest| rest.starts_with('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(path: &Path, content: &str) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_lint_workspace_variables() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        write(
+            &root.join("http-client.env.json"),
+            r#"{
+                "$shared": {"apiVersion": "v1", "unusedShared": "x"},
+                "dev": {"host": "dev.test", "auth": "{\"clientId\": \"abc\"}", "baseUrl": "https://{{host}}", "oldToken": "t"},
+                "prod": {"host": "prod.test", "prodOnly": "p"}
+            }"#,
+        );
+        write(
+            &root.join("users.http"),
+            "@path = /users\n\
+             # @prompt username Enter username\n\
+             ### Login\n\
+             # @name login\n\
+             POST {{baseUrl}}/{{apiVersion}}/login?id={{$uuid}}&n={{ $randomInt 1 10 }}\n\
+             \n\
+             {\"user\": \"{{username}}\", \"client\": \"{{auth.clientId}}\"}\n\
+             \n\
+             > {%\n\
+             client.global.set(\"sessionId\", response.body.id);\n\
+             %}\n\
+             \n\
+             ### List\n\
+             GET {{baseUrl}}{{path}}\n\
+             Authorization: Bearer {{token}}\n",
+        );
+        write(
+            &root.join("api").join("orders.http"),
+            "### Orders\n\
+             GET {{baseUrl}}/orders?session={{sessionId}}\n\
+             X-Token: {{login.response.body.token}}\n\
+             X-Missing: {{ordersKey}}\n",
+        );
+        let workspace = root.to_string_lossy().to_string();
+
+        let lint = lint_workspace_variables(workspace.clone(), Some("dev".to_string()), None)
+            .await
+            .unwrap();
+        let path = |p: &Path| p.to_string_lossy().to_string();
+        assert_eq!(
+            lint.files,
+            vec![
+                FileLint {
+                    file: path(&root.join("api").join("orders.http")),
+                    undefined: vec![UndefinedReference {
+                        name: "ordersKey".to_string(),
+                        line: 4,
+                    }],
+                },
+                FileLint {
+                    file: path(&root.join("users.http")),
+                    undefined: vec![UndefinedReference {
+                        name: "token".to_string(),
+                        line: 15,
+                    }],
+                },
+            ]
+        );
+        // host is referenced from baseUrl's value
+        assert_eq!(lint.unused, vec!["oldToken", "unusedShared"]);
+
+        // Without an environment, every environment's variables count
+        let lint = lint_workspace_variables(workspace, None, None)
+            .await
+            .unwrap();
+        assert_eq!(lint.unused, vec!["oldToken", "prodOnly", "unusedShared"]);
+    }
+}
@@ -2,13 +2,20 @@
 //!
 //! Implements the Authorization Code flow with PKCE for desktop applications.
 
-use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
 use rand::Rng;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::net::TcpListener;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot};
 use url::Url;
 
 /// OIDC Discovery Document (OpenID Provider Configuration)
@@ -23,12 +30,31 @@ pub struct OidcDiscovery {
     pub jwks_uri: Option<String>,
     #[serde(default)]
     pub end_session_endpoint: Option<String>,
+    /// RFC 7662 token introspection, which not every provider offers
+    #[serde(default)]
+    pub introspection_endpoint: Option<String>,
     #[serde(default)]
     pub scopes_supported: Vec<String>,
     #[serde(default)]
     pub response_types_supported: Vec<String>,
     #[serde(default)]
     pub grant_types_supported: Vec<String>,
+    #[serde(default)]
+    pub token_endpoint_auth_methods_supported: Vec<String>,
+    #[serde(default)]
+    pub code_challenge_methods_supported: Vec<String>,
+}
+
+/// How the client authenticates to the token endpoint (RFC 7591 section 2)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenEndpointAuthMethod {
+    /// Client id and secret in the form body
+    ClientSecretPost,
+    /// Client id and secret in a Basic Authorization header
+    ClientSecretBasic,
+    /// Public client: only the client id, in the form body
+    None,
 }
 
 /// OIDC Configuration for a request
@@ -44,13 +70,28 @@ pub struct OidcConfig {
     pub client_id: String,
     /// Client Secret (optional for public clients)
     pub client_secret: Option<String>,
+    /// How to send the client credentials; by default what discovery lists,
+    /// preferring the form body
+    #[serde(default)]
+    pub token_endpoint_auth_method: Option<TokenEndpointAuthMethod>,
     /// Redirect URL (must be registered with IdP)
     pub redirect_url: String,
+    /// Listen on a port the OS picks instead of the redirect URL's, as
+    /// loopback redirects allow; port 0 in the redirect URL does the same
+    #[serde(default)]
+    pub use_random_port: bool,
+    /// Send PKCE parameters; some old providers reject them outright
+    #[serde(default = "default_use_pkce")]
+    pub use_pkce: bool,
     /// Scopes to request
     pub scopes: Vec<String>,
     /// Additional parameters to include in auth request
     #[serde(default)]
     pub extra_params: HashMap<String, String>,
+    /// Page shown in the browser after the callback; unset parts fall back
+    /// to the workspace's settings, then the defaults
+    #[serde(default)]
+    pub callback_page: CallbackPage,
 }
 
 /// Token response from the token endpoint
@@ -67,6 +108,46 @@ pub struct TokenResponse {
     pub id_token: Option<String>,
     #[serde(default)]
     pub scope: Option<String>,
+    /// Type of the token a token exchange issued, such as
+    /// [`ACCESS_TOKEN_TYPE`]
+    #[serde(default)]
+    pub issued_token_type: Option<String>,
+}
+
+fn default_use_pkce() -> bool {
+    true
+}
+
+/// PKCE code challenge method (RFC 7636), or none where PKCE is off
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PkceMethod {
+    S256,
+    #[serde(rename = "plain")]
+    Plain,
+    #[serde(rename = "none")]
+    None,
+}
+
+/// The PKCE method for a config: none when it turns PKCE off, else the best
+/// one discovery lists, else S256. A provider listing neither method gets
+/// none.
+pub fn pkce_method(config: &OidcConfig, discovery: Option<&OidcDiscovery>) -> PkceMethod {
+    if !config.use_pkce {
+        return PkceMethod::None;
+    }
+    let supported = match discovery {
+        Some(d) if !d.code_challenge_methods_supported.is_empty() => {
+            &d.code_challenge_methods_supported
+        }
+        _ => return PkceMethod::S256,
+    };
+    if supported.iter().any(|m| m == "S256") {
+        PkceMethod::S256
+    } else if supported.iter().any(|m| m == "plain") {
+        PkceMethod::Plain
+    } else {
+        PkceMethod::None
+    }
 }
 
 /// PKCE (Proof Key for Code Exchange) parameters
@@ -96,28 +177,45 @@ pub struct CallbackResult {
     pub error_description: Option<String>,
 }
 
-/// Generate PKCE code verifier and challenge
-pub fn generate_pkce() -> PkceParams {
+/// Generate PKCE code verifier and challenge for `method`; None when it is
+/// [`PkceMethod::None`]
+pub fn generate_pkce(method: PkceMethod) -> Option<PkceParams> {
     // Generate 32 random bytes for code verifier
     let mut rng = rand::thread_rng();
     let random_bytes: Vec<u8> = (0..32).map(|_| rng.gen()).collect();
     let code_verifier = URL_SAFE_NO_PAD.encode(&random_bytes);
 
-    // Generate code challenge using SHA256
-    let mut hasher = Sha256::new();
-    hasher.update(code_verifier.as_bytes());
-    let hash = hasher.finalize();
-    let code_challenge = URL_SAFE_NO_PAD.encode(hash);
+    let (code_challenge, code_challenge_method) = match method {
+        PkceMethod::S256 => {
+            // Generate code challenge using SHA256
+            let mut hasher = Sha256::new();
+            hasher.update(code_verifier.as_bytes());
+            let hash = hasher.finalize();
+            (URL_SAFE_NO_PAD.encode(hash), "S256")
+        }
+        PkceMethod::Plain => (code_verifier.clone(), "plain"),
+        PkceMethod::None => return None,
+    };
 
-    PkceParams {
+    Some(PkceParams {
         code_verifier,
         code_challenge,
-        code_challenge_method: "S256".to_string(),
-    }
+        code_challenge_method: code_challenge_method.to_string(),
+    })
 }
 
 /// Generate a random state parameter
 pub fn generate_state() -> String {
+    random_token()
+}
+
+/// Generate a random nonce, which the IdP echoes in the ID token so a
+/// replayed token can be detected
+pub fn generate_nonce() -> String {
+    random_token()
+}
+
+fn random_token() -> String {
     let mut rng = rand::thread_rng();
     let random_bytes: Vec<u8> = (0..16).map(|_| rng.gen()).collect();
     URL_SAFE_NO_PAD.encode(&random_bytes)
@@ -130,14 +228,15 @@ pub async fn fetch_discovery(issuer: &str) -> Result<OidcDiscovery, String> {
         issuer.trim_end_matches('/')
     );
 
+    tracing::debug!(url = %discovery_url, "Fetching discovery document");
     let client = reqwest::Client::new();
-    let response = client
-        .get(&discovery_url)
-        .send()
-        .await
-        .map_err(|e| format!("Failed to fetch discovery document: {}", e))?;
+    let response = client.get(&discovery_url).send().await.map_err(|e| {
+        tracing::warn!(url = %discovery_url, "Discovery request failed: {}", e);
+        format!("Failed to fetch discovery document: {}", e)
+    })?;
 
     if !response.status().is_success() {
+        tracing::warn!(url = %discovery_url, status = %response.status(), "Discovery request failed");
         return Err(format!(
             "Discovery request failed with status: {}",
             response.status()
@@ -155,7 +254,8 @@ pub fn build_auth_url(
     config: &OidcConfig,
     discovery: Option<&OidcDiscovery>,
     state: &str,
-    pkce: &PkceParams,
+    nonce: &str,
+    pkce: Option<&PkceParams>,
 ) -> Result<String, String> {
     let auth_endpoint = config
         .authorization_endpoint
@@ -173,8 +273,11 @@ pub fn build_auth_url(
         params.append_pair("redirect_uri", &config.redirect_url);
         params.append_pair("scope", &config.scopes.join(" "));
         params.append_pair("state", state);
-        params.append_pair("code_challenge", &pkce.code_challenge);
-        params.append_pair("code_challenge_method", &pkce.code_challenge_method);
+        params.append_pair("nonce", nonce);
+        if let Some(pkce) = pkce {
+            params.append_pair("code_challenge", &pkce.code_challenge);
+            params.append_pair("code_challenge_method", &pkce.code_challenge_method);
+        }
 
         // Add any extra parameters
         for (key, value) in &config.extra_params {
@@ -185,17 +288,202 @@ pub fn build_auth_url(
     Ok(url.to_string())
 }
 
-/// Start a local HTTP server to listen for the OAuth callback
-pub async fn start_callback_server(
+/// Build the IdP's RP-initiated logout URL (OpenID Connect RP-Initiated
+/// Logout 1.0) from the discovery document's end_session_endpoint
+pub fn build_logout_url(
+    config: &OidcConfig,
+    discovery: Option<&OidcDiscovery>,
+    id_token_hint: Option<&str>,
+    post_logout_redirect_uri: Option<&str>,
+    state: &str,
+) -> Result<String, String> {
+    let end_session_endpoint = discovery
+        .and_then(|d| d.end_session_endpoint.as_ref())
+        .ok_or("The provider does not advertise an end_session_endpoint")?;
+
+    let mut url = Url::parse(end_session_endpoint)
+        .map_err(|e| format!("Invalid end session endpoint: {}", e))?;
+
+    {
+        let mut params = url.query_pairs_mut();
+        if let Some(hint) = id_token_hint {
+            params.append_pair("id_token_hint", hint);
+        }
+        params.append_pair("client_id", &config.client_id);
+        if let Some(redirect) = post_logout_redirect_uri {
+            params.append_pair("post_logout_redirect_uri", redirect);
+        }
+        params.append_pair("state", state);
+    }
+
+    Ok(url.to_string())
+}
+
+/// How long to wait for the OAuth callback when no timeout is given
+pub const DEFAULT_CALLBACK_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Cancels the callback servers currently waiting, by flow state
+static PENDING_CALLBACKS: Mutex<Option<HashMap<String, oneshot::Sender<()>>>> = Mutex::new(None);
+
+/// Listeners bound when a flow started, by flow state, until it is awaited
+static HELD_LISTENERS: Mutex<Option<HashMap<String, CallbackListener>>> = Mutex::new(None);
+
+/// Scheme kvile registers for deep-link redirects such as
+/// `kvile://oauth/callback`
+pub const DEEP_LINK_SCHEME: &str = "kvile";
+
+/// Why the deep-link scheme could not be registered, if it couldn't
+static DEEP_LINK_ERROR: Mutex<Option<String>> = Mutex::new(None);
+
+/// Where deep links go for flows with a custom scheme redirect, by flow state
+static DEEP_LINK_SENDERS: Mutex<Option<HashMap<String, mpsc::UnboundedSender<Url>>>> =
+    Mutex::new(None);
+
+/// Deep links received for flows not yet awaited, by flow state
+static HELD_DEEP_LINKS: Mutex<Option<HashMap<String, mpsc::UnboundedReceiver<Url>>>> =
+    Mutex::new(None);
+
+const DEFAULT_SUCCESS_HTML: &str = r#"<!DOCTYPE html>
+<html>
+<head><title>Authentication Successful</title></head>
+<body style="font-family: system-ui; display: flex; justify-content: center; align-items: center; height: 100vh; margin: 0;">
+<div style="text-align: center;">
+<h1 style="color: #22c55e;">Authentication Successful</h1>
+<p>You can close this window and return to Kvile.</p>
+</div>
+</body>
+</html>"#;
+
+const DEFAULT_ERROR_HTML: &str = r#"<!DOCTYPE html>
+<html>
+<head><title>Authentication Failed</title></head>
+<body style="font-family: system-ui; display: flex; justify-content: center; align-items: center; height: 100vh; margin: 0;">
+<div style="text-align: center;">
+<h1 style="color: #ef4444;">Authentication Failed</h1>
+<p>{{error_description}}</p>
+<p>You can close this window.</p>
+</div>
+</body>
+</html>"#;
+
+/// Shown after the IdP redirects back from a logout
+pub const LOGGED_OUT_HTML: &str = r#"<!DOCTYPE html>
+<html>
+<head><title>Logged Out</title></head>
+<body style="font-family: system-ui; display: flex; justify-content: center; align-items: center; height: 100vh; margin: 0;">
+<div style="text-align: center;">
+<h1>Logged Out</h1>
+<p>You can close this window.</p>
+</div>
+</body>
+</html>"#;
+
+/// Closes the tab, where the browser allows it
+const AUTO_CLOSE_SCRIPT: &str = "<script>window.close()</script>";
+
+/// The page the browser shows after the OAuth callback
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CallbackPage {
+    /// HTML shown after a successful login
+    #[serde(default)]
+    pub success_html: Option<String>,
+    /// HTML shown when the IdP reports an error. `{{error}}` and
+    /// `{{error_description}}` are replaced with the HTML-escaped values.
+    #[serde(default)]
+    pub error_html: Option<String>,
+    /// Try to close the tab once the page is shown
+    #[serde(default)]
+    pub auto_close: bool,
+}
+
+impl CallbackPage {
+    /// The HTML for a callback, from the templates or the defaults
+    pub fn render(&self, result: &CallbackResult) -> String {
+        let mut html = match result.error {
+            Some(ref error) => {
+                let template = self.error_html.as_deref().unwrap_or(DEFAULT_ERROR_HTML);
+                let description = result
+                    .error_description
+                    .as_deref()
+                    .unwrap_or("Unknown error");
+                // One pass, so values containing placeholders stay as they are
+                let placeholder = Regex::new(r"\{\{(error|error_description)\}\}").unwrap();
+                placeholder
+                    .replace_all(template, |caps: &regex::Captures| {
+                        escape_html(if &caps[1] == "error" {
+                            error
+                        } else {
+                            description
+                        })
+                    })
+                    .into_owned()
+            }
+            None => self
+                .success_html
+                .clone()
+                .unwrap_or_else(|| DEFAULT_SUCCESS_HTML.to_string()),
+        };
+        if self.auto_close {
+            match html.rfind("</body>") {
+                Some(end) => html.insert_str(end, AUTO_CLOSE_SCRIPT),
+                None => html.push_str(AUTO_CLOSE_SCRIPT),
+            }
+        }
+        html
+    }
+}
+
+fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// How waiting for the OAuth callback ended
+#[derive(Debug, Clone)]
+pub enum CallbackOutcome {
+    Received(CallbackResult),
+    TimedOut,
+    Cancelled,
+}
+
+/// A bound callback server, with the redirect URL that reaches it
+#[derive(Debug)]
+pub struct CallbackListener {
+    listener: TcpListener,
+    /// The redirect URL as configured, which may have port 0
+    requested_url: String,
+    /// The redirect URL with the port actually bound
+    pub redirect_url: String,
+    /// What the browser is shown once the callback arrives
+    pub page: CallbackPage,
+}
+
+/// Bind the callback server for a redirect URL. With port 0 or
+/// `random_port`, the OS picks a free loopback port (RFC 8252 section 7.3)
+/// and the returned redirect URL has it filled in.
+pub async fn bind_callback_listener(
     redirect_url: &str,
-    expected_state: &str,
-) -> Result<CallbackResult, String> {
+    random_port: bool,
+) -> Result<CallbackListener, String> {
     // Parse the redirect URL to get host and port
-    let url = Url::parse(redirect_url).map_err(|e| format!("Invalid redirect URL: {}", e))?;
+    let mut url = Url::parse(redirect_url).map_err(|e| format!("Invalid redirect URL: {}", e))?;
 
-    let host = url.host_str().unwrap_or("127.0.0.1");
-    let port = url.port().unwrap_or(8080);
-    let path = url.path();
+    let host = url.host_str().unwrap_or("127.0.0.1").to_string();
+    let port = if random_port {
+        0
+    } else {
+        url.port().unwrap_or(8080)
+    };
 
     let bind_addr = format!("{}:{}", host, port);
 
@@ -203,41 +491,175 @@ pub async fn start_callback_server(
     let listener = TcpListener::bind(&bind_addr)
         .await
         .map_err(|e| format!("Failed to bind to {}: {}", bind_addr, e))?;
+    if port == 0 {
+        let bound = listener
+            .local_addr()
+            .map_err(|e| format!("Failed to bind to {}: {}", bind_addr, e))?;
+        url.set_port(Some(bound.port()))
+            .map_err(|_| format!("Invalid redirect URL: {}", redirect_url))?;
+    }
 
-    // Wait for a single connection
-    let (mut socket, _) = listener
-        .accept()
-        .await
-        .map_err(|e| format!("Failed to accept connection: {}", e))?;
+    Ok(CallbackListener {
+        listener,
+        requested_url: redirect_url.to_string(),
+        redirect_url: url.to_string(),
+        page: CallbackPage::default(),
+    })
+}
+
+/// Keep a flow's listener until [`take_listener`] is called with its state
+pub fn hold_listener(state: &str, listener: CallbackListener) {
+    HELD_LISTENERS
+        .lock()
+        .unwrap()
+        .get_or_insert_with(HashMap::new)
+        .insert(state.to_string(), listener);
+}
+
+/// Release listeners held by abandoned flows for a redirect URL, so a
+/// restarted flow can bind its port again
+pub fn release_listeners_for(redirect_url: &str) {
+    if let Some(held) = HELD_LISTENERS.lock().unwrap().as_mut() {
+        held.retain(|_, listener| listener.requested_url != redirect_url);
+    }
+}
+
+/// The listener held for a flow, if it was bound when the flow started
+pub fn take_listener(state: &str) -> Option<CallbackListener> {
+    HELD_LISTENERS
+        .lock()
+        .unwrap()
+        .as_mut()
+        .and_then(|held| held.remove(state))
+}
+
+/// A receiver that fires when [`cancel_callback`] is called for the flow
+pub fn register_callback_cancel(state: &str) -> oneshot::Receiver<()> {
+    let (tx, rx) = oneshot::channel();
+    PENDING_CALLBACKS
+        .lock()
+        .unwrap()
+        .get_or_insert_with(HashMap::new)
+        .insert(state.to_string(), tx);
+    rx
+}
+
+/// Stop every pending callback server and release listeners not yet
+/// awaited. Returns whether a login was waiting.
+pub fn cancel_callback() -> bool {
+    HELD_LISTENERS.lock().unwrap().take();
+    HELD_DEEP_LINKS.lock().unwrap().take();
+    if let Some(senders) = DEEP_LINK_SENDERS.lock().unwrap().as_mut() {
+        senders.retain(|_, tx| !tx.is_closed());
+    }
+    PENDING_CALLBACKS
+        .lock()
+        .unwrap()
+        .take()
+        .unwrap_or_default()
+        .into_values()
+        .map(|tx| tx.send(()).is_ok())
+        .filter(|sent| *sent)
+        .count()
+        > 0
+}
+
+/// Wait on a bound callback server. Requests for other paths, such as the
+/// browser's favicon, get a 404 and the server keeps listening until the
+/// callback arrives, `timeout` passes or `cancel` fires. The port is released
+/// whichever way it ends.
+pub async fn wait_for_callback(
+    listener: CallbackListener,
+    expected_state: &str,
+    timeout: Duration,
+    cancel: oneshot::Receiver<()>,
+) -> Result<CallbackOutcome, String> {
+    let url =
+        Url::parse(&listener.redirect_url).map_err(|e| format!("Invalid redirect URL: {}", e))?;
+    let path = url.path();
+
+    let wait = async {
+        loop {
+            let (socket, _) = listener
+                .listener
+                .accept()
+                .await
+                .map_err(|e| format!("Failed to accept connection: {}", e))?;
+            if let Some(result) =
+                handle_callback(socket, path, expected_state, &listener.page).await?
+            {
+                return Ok(result);
+            }
+        }
+    };
+
+    let outcome = tokio::select! {
+        result = wait => result.map(CallbackOutcome::Received),
+        _ = tokio::time::sleep(timeout) => Ok(CallbackOutcome::TimedOut),
+        _ = cancel => Ok(CallbackOutcome::Cancelled),
+    };
+    if let Some(pending) = PENDING_CALLBACKS.lock().unwrap().as_mut() {
+        pending.remove(expected_state);
+    }
+    outcome
+}
 
+/// Answer one connection to the callback server, returning the callback's
+/// parameters if it was the callback
+async fn handle_callback(
+    mut socket: TcpStream,
+    path: &str,
+    expected_state: &str,
+    page: &CallbackPage,
+) -> Result<Option<CallbackResult>, String> {
     // Read the HTTP request
     let mut reader = BufReader::new(&mut socket);
     let mut request_line = String::new();
-    reader
-        .read_line(&mut request_line)
-        .await
-        .map_err(|e| format!("Failed to read request: {}", e))?;
+    if reader.read_line(&mut request_line).await.is_err() {
+        return Ok(None);
+    }
 
     // Parse the request to extract query parameters
     // Request line format: "GET /callback?code=xxx&state=xxx HTTP/1.1"
     let parts: Vec<&str> = request_line.split_whitespace().collect();
-    if parts.len() < 2 {
-        return Err("Invalid HTTP request".to_string());
-    }
-
-    let request_path = parts[1];
+    let request_path = parts.get(1).copied().unwrap_or_default();
 
-    // Check if the path matches expected
-    if !request_path.starts_with(path) {
-        return Err(format!("Unexpected callback path: {}", request_path));
+    // Anything else the browser asks for, such as /favicon.ico
+    if request_path.split('?').next() != Some(path) {
+        let response = "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+        let _ = reader.into_inner().write_all(response.as_bytes()).await;
+        return Ok(None);
     }
 
     // Parse query parameters
     let callback_url = format!("http://localhost{}", request_path);
     let parsed =
         Url::parse(&callback_url).map_err(|e| format!("Failed to parse callback: {}", e))?;
+    let result = callback_result(&parsed, expected_state)?;
+
+    // Send HTTP response (success page)
+    let response_body = page.render(&result);
+
+    let response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response_body.len(),
+        response_body
+    );
+
+    // Get inner socket back and write response
+    let socket = reader.into_inner();
+    socket
+        .write_all(response.as_bytes())
+        .await
+        .map_err(|e| format!("Failed to send response: {}", e))?;
+
+    Ok(Some(result))
+}
 
-    let params: HashMap<String, String> = parsed.query_pairs().into_owned().collect();
+/// The callback's parameters from the URL the IdP redirected to, checking
+/// its state
+fn callback_result(url: &Url, expected_state: &str) -> Result<CallbackResult, String> {
+    let params: HashMap<String, String> = url.query_pairs().into_owned().collect();
 
     let result = CallbackResult {
         code: params.get("code").cloned(),
@@ -252,54 +674,151 @@ pub async fn start_callback_server(
             return Err("State mismatch - possible CSRF attack".to_string());
         }
     }
+    Ok(result)
+}
 
-    // Send HTTP response (success page)
-    let response_body = if result.error.is_some() {
-        format!(
-            r#"<!DOCTYPE html>
-<html>
-<head><title>Authentication Failed</title></head>
-<body style="font-family: system-ui; display: flex; justify-content: center; align-items: center; height: 100vh; margin: 0;">
-<div style="text-align: center;">
-<h1 style="color: #ef4444;">Authentication Failed</h1>
-<p>{}</p>
-<p>You can close this window.</p>
-</div>
-</body>
-</html>"#,
-            result
-                .error_description
-                .as_ref()
-                .unwrap_or(&"Unknown error".to_string())
-        )
-    } else {
-        r#"<!DOCTYPE html>
-<html>
-<head><title>Authentication Successful</title></head>
-<body style="font-family: system-ui; display: flex; justify-content: center; align-items: center; height: 100vh; margin: 0;">
-<div style="text-align: center;">
-<h1 style="color: #22c55e;">Authentication Successful</h1>
-<p>You can close this window and return to Kvile.</p>
-</div>
-</body>
-</html>"#
-            .to_string()
+/// Whether a redirect URL uses a custom scheme, so the callback arrives as a
+/// deep link rather than at a loopback server
+pub fn is_deep_link_redirect(redirect_url: &str) -> bool {
+    Url::parse(redirect_url).is_ok_and(|url| !matches!(url.scheme(), "http" | "https"))
+}
+
+/// Record that registering [`DEEP_LINK_SCHEME`] failed, so flows with a
+/// deep-link redirect fail up front instead of waiting for a callback that
+/// can't arrive
+pub fn set_deep_link_error(error: String) {
+    *DEEP_LINK_ERROR.lock().unwrap() = Some(error);
+}
+
+/// Start receiving deep links for a flow, until [`wait_for_deep_link`] is
+/// called with its state. Fails when the redirect URL isn't for kvile's
+/// scheme or the scheme could not be registered.
+pub fn expect_deep_link(state: &str, redirect_url: &str) -> Result<(), String> {
+    let links = deep_link_receiver(state, redirect_url)?;
+    HELD_DEEP_LINKS
+        .lock()
+        .unwrap()
+        .get_or_insert_with(HashMap::new)
+        .insert(state.to_string(), links);
+    Ok(())
+}
+
+/// Register a flow for deep links, returning where they arrive
+fn deep_link_receiver(
+    state: &str,
+    redirect_url: &str,
+) -> Result<mpsc::UnboundedReceiver<Url>, String> {
+    let url = Url::parse(redirect_url).map_err(|e| format!("Invalid redirect URL: {}", e))?;
+    if url.scheme() != DEEP_LINK_SCHEME {
+        return Err(format!(
+            "Redirect URL {} uses the '{}' scheme; only {}:// redirects reach kvile",
+            redirect_url,
+            url.scheme(),
+            DEEP_LINK_SCHEME
+        ));
+    }
+    if let Some(error) = DEEP_LINK_ERROR.lock().unwrap().as_ref() {
+        return Err(format!(
+            "The {}:// scheme could not be registered on this system ({}); use a loopback redirect URL such as http://127.0.0.1:0/callback instead",
+            DEEP_LINK_SCHEME, error
+        ));
+    }
+    let (tx, rx) = mpsc::unbounded_channel();
+    DEEP_LINK_SENDERS
+        .lock()
+        .unwrap()
+        .get_or_insert_with(HashMap::new)
+        .insert(state.to_string(), tx);
+    Ok(rx)
+}
+
+/// Pass a deep link the app was opened with to the flows waiting for one.
+/// Returns whether any flow was.
+pub fn deliver_deep_link(url: &Url) -> bool {
+    DEEP_LINK_SENDERS
+        .lock()
+        .unwrap()
+        .as_ref()
+        .is_some_and(|senders| {
+            senders
+                .values()
+                .map(|tx| tx.send(url.clone()).is_ok())
+                .filter(|sent| *sent)
+                .count()
+                > 0
+        })
+}
+
+/// The callback a deep link carries, if it is for `redirect_url`; links to
+/// other paths are ignored as stray requests to the callback server are
+pub fn deep_link_callback(
+    url: &Url,
+    redirect_url: &str,
+    expected_state: &str,
+) -> Result<Option<CallbackResult>, String> {
+    let redirect = Url::parse(redirect_url).map_err(|e| format!("Invalid redirect URL: {}", e))?;
+    // `kvile://oauth/callback` has host `oauth`; `kvile:/callback` has none
+    let same_target = url.scheme().eq_ignore_ascii_case(redirect.scheme())
+        && url.host_str() == redirect.host_str()
+        && url.path().trim_end_matches('/') == redirect.path().trim_end_matches('/');
+    if !same_target {
+        return Ok(None);
+    }
+    callback_result(url, expected_state).map(Some)
+}
+
+/// Wait for a flow's deep-link callback, with the callback server's timeout
+/// and cancel semantics. Flows not started with [`expect_deep_link`] start
+/// receiving now.
+pub async fn wait_for_deep_link(
+    redirect_url: &str,
+    expected_state: &str,
+    timeout: Duration,
+    cancel: oneshot::Receiver<()>,
+) -> Result<CallbackOutcome, String> {
+    let held = HELD_DEEP_LINKS
+        .lock()
+        .unwrap()
+        .as_mut()
+        .and_then(|held| held.remove(expected_state));
+    let mut links = match held {
+        Some(links) => links,
+        None => deep_link_receiver(expected_state, redirect_url)?,
     };
 
-    let response = format!(
-        "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
-        response_body.len(),
-        response_body
-    );
+    let wait = async {
+        while let Some(url) = links.recv().await {
+            if let Some(result) = deep_link_callback(&url, redirect_url, expected_state)? {
+                return Ok(CallbackOutcome::Received(result));
+            }
+        }
+        // The flow's sender is only dropped once it stops waiting
+        Ok(CallbackOutcome::Cancelled)
+    };
 
-    // Get inner socket back and write response
-    let socket = reader.into_inner();
-    socket
-        .write_all(response.as_bytes())
-        .await
-        .map_err(|e| format!("Failed to send response: {}", e))?;
+    let outcome = tokio::select! {
+        result = wait => result,
+        _ = tokio::time::sleep(timeout) => Ok(CallbackOutcome::TimedOut),
+        _ = cancel => Ok(CallbackOutcome::Cancelled),
+    };
+    if let Some(senders) = DEEP_LINK_SENDERS.lock().unwrap().as_mut() {
+        senders.remove(expected_state);
+    }
+    if let Some(pending) = PENDING_CALLBACKS.lock().unwrap().as_mut() {
+        pending.remove(expected_state);
+    }
+    outcome
+}
 
-    Ok(result)
+/// Check that an ID token carries the nonce sent with the auth request.
+/// Only the claim is checked; the signature is not verified.
+pub fn validate_id_token_nonce(id_token: &str, expected_nonce: &str) -> Result<(), String> {
+    let jwt = crate::jwt::decode_jwt(id_token.to_string())?;
+    match jwt.payload.get("nonce").and_then(|nonce| nonce.as_str()) {
+        Some(nonce) if nonce == expected_nonce => Ok(()),
+        Some(_) => Err("ID token nonce does not match the auth request".to_string()),
+        None => Err("ID token is missing the nonce claim".to_string()),
+    }
 }
 
 /// Exchange authorization code for tokens
@@ -307,7 +826,7 @@ pub async fn exchange_code_for_tokens(
     config: &OidcConfig,
     discovery: Option<&OidcDiscovery>,
     code: &str,
-    code_verifier: &str,
+    code_verifier: Option<&str>,
 ) -> Result<TokenResponse, String> {
     let token_endpoint = config
         .token_endpoint
@@ -319,30 +838,18 @@ pub async fn exchange_code_for_tokens(
     params.insert("grant_type", "authorization_code");
     params.insert("code", code);
     params.insert("redirect_uri", &config.redirect_url);
-    params.insert("client_id", &config.client_id);
-    params.insert("code_verifier", code_verifier);
-
-    let client = reqwest::Client::new();
-    let mut request = client.post(token_endpoint).form(&params);
-
-    // Add client secret if provided (confidential client)
-    if let Some(ref secret) = config.client_secret {
-        // Can either use client_secret in body or Basic auth header
-        // Using body is more common
-        request = client.post(token_endpoint).form(&{
-            let mut p = params.clone();
-            p.insert("client_secret", secret.as_str());
-            p
-        });
+    if let Some(code_verifier) = code_verifier {
+        params.insert("code_verifier", code_verifier);
     }
 
-    let response = request
+    let response = token_request(token_endpoint, config, discovery, params)
         .send()
         .await
         .map_err(|e| format!("Token request failed: {}", e))?;
 
     if !response.status().is_success() {
         let error_text = response.text().await.unwrap_or_default();
+        tracing::warn!("Token exchange failed: {}", error_text);
         return Err(format!("Token exchange failed: {}", error_text));
     }
 
@@ -367,22 +874,15 @@ pub async fn refresh_access_token(
     let mut params = HashMap::new();
     params.insert("grant_type", "refresh_token");
     params.insert("refresh_token", refresh_token);
-    params.insert("client_id", &config.client_id);
-
-    if let Some(ref secret) = config.client_secret {
-        params.insert("client_secret", secret.as_str());
-    }
 
-    let client = reqwest::Client::new();
-    let response = client
-        .post(token_endpoint)
-        .form(&params)
+    let response = token_request(token_endpoint, config, discovery, params)
         .send()
         .await
         .map_err(|e| format!("Refresh request failed: {}", e))?;
 
     if !response.status().is_success() {
         let error_text = response.text().await.unwrap_or_default();
+        tracing::warn!("Token refresh failed: {}", error_text);
         return Err(format!("Token refresh failed: {}", error_text));
     }
 
@@ -392,26 +892,1123 @@ pub async fn refresh_access_token(
         .map_err(|e| format!("Failed to parse refresh response: {}", e))
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Get tokens for the client itself with the client credentials grant
+pub async fn client_credentials_grant(
+    config: &OidcConfig,
+    discovery: Option<&OidcDiscovery>,
+) -> Result<TokenResponse, String> {
+    let token_endpoint = config
+        .token_endpoint
+        .as_ref()
+        .or(discovery.map(|d| &d.token_endpoint))
+        .ok_or("No token endpoint configured")?;
 
-    #[test]
-    fn test_generate_pkce() {
-        let pkce = generate_pkce();
-        assert!(!pkce.code_verifier.is_empty());
-        assert!(!pkce.code_challenge.is_empty());
-        assert_eq!(pkce.code_challenge_method, "S256");
-        // Verifier should be ~43 chars (32 bytes base64 encoded)
-        assert!(pkce.code_verifier.len() >= 40);
+    let scope = config.scopes.join(" ");
+    let mut params = HashMap::new();
+    params.insert("grant_type", "client_credentials");
+    if !scope.is_empty() {
+        params.insert("scope", &scope);
     }
 
-    #[test]
-    fn test_generate_state() {
-        let state = generate_state();
-        assert!(!state.is_empty());
-        // State should be ~22 chars (16 bytes base64 encoded)
-        assert!(state.len() >= 20);
+    let response = token_request(token_endpoint, config, discovery, params)
+        .send()
+        .await
+        .map_err(|e| format!("Token request failed: {}", e))?;
+
+    if !response.status().is_success() {
+        let error_text = response.text().await.unwrap_or_default();
+        tracing::warn!("Client credentials grant failed: {}", error_text);
+        return Err(format!("Client credentials grant failed: {}", error_text));
+    }
+
+    response
+        .json::<TokenResponse>()
+        .await
+        .map_err(|e| format!("Failed to parse token response: {}", e))
+}
+
+/// What the IdP says about a token (RFC 7662 section 2.2). Claims other than
+/// these are in `claims`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenIntrospection {
+    /// Whether the token is currently active; inactive tokens carry no claims
+    pub active: bool,
+    /// Space-separated scopes
+    #[serde(default)]
+    pub scope: Option<String>,
+    /// Unix time in seconds
+    #[serde(default)]
+    pub exp: Option<i64>,
+    #[serde(default)]
+    pub sub: Option<String>,
+    #[serde(default)]
+    pub client_id: Option<String>,
+    #[serde(flatten)]
+    pub claims: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Ask the IdP whether a token is active, authenticating as the client the
+/// same way as at the token endpoint
+pub async fn introspect_token(
+    config: &OidcConfig,
+    discovery: Option<&OidcDiscovery>,
+    token: &str,
+) -> Result<TokenIntrospection, String> {
+    let endpoint = discovery
+        .and_then(|d| d.introspection_endpoint.as_ref())
+        .ok_or("The provider does not advertise an introspection_endpoint")?;
+
+    let mut params = HashMap::new();
+    params.insert("token", token);
+    let response = token_request(endpoint, config, discovery, params)
+        .send()
+        .await
+        .map_err(|e| format!("Introspection request failed: {}", e))?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(format!("Introspection failed ({}): {}", status, error_text));
+    }
+
+    response
+        .json::<TokenIntrospection>()
+        .await
+        .map_err(|e| format!("Failed to parse introspection response: {}", e))
+}
+
+/// The auth method a config uses at the token endpoint: the configured one,
+/// else the form body unless discovery lists only the Basic header. Clients
+/// without a secret are public.
+pub fn token_endpoint_auth_method(
+    config: &OidcConfig,
+    discovery: Option<&OidcDiscovery>,
+) -> TokenEndpointAuthMethod {
+    if let Some(method) = config.token_endpoint_auth_method {
+        return method;
+    }
+    if config.client_secret.is_none() {
+        return TokenEndpointAuthMethod::None;
+    }
+    let supported = discovery
+        .map(|d| d.token_endpoint_auth_methods_supported.as_slice())
+        .unwrap_or_default();
+    let supports = |method: &str| supported.iter().any(|m| m == method);
+    if !supports("client_secret_post") && supports("client_secret_basic") {
+        TokenEndpointAuthMethod::ClientSecretBasic
+    } else {
+        TokenEndpointAuthMethod::ClientSecretPost
+    }
+}
+
+/// A token endpoint request with the client authenticated as the config says
+fn token_request<'a>(
+    token_endpoint: &str,
+    config: &'a OidcConfig,
+    discovery: Option<&OidcDiscovery>,
+    mut params: HashMap<&str, &'a str>,
+) -> reqwest::RequestBuilder {
+    let client = reqwest::Client::new();
+    let auth_method = token_endpoint_auth_method(config, discovery);
+    if let Some(secret) = config.client_secret.as_deref() {
+        crate::logging::add_secret(secret);
+    }
+    tracing::info!(
+        endpoint = token_endpoint,
+        grant_type = params.get("grant_type").copied().unwrap_or("none"),
+        ?auth_method,
+        "Token endpoint request"
+    );
+    match auth_method {
+        TokenEndpointAuthMethod::ClientSecretPost => {
+            params.insert("client_id", &config.client_id);
+            if let Some(secret) = config.client_secret.as_deref() {
+                params.insert("client_secret", secret);
+            }
+        }
+        TokenEndpointAuthMethod::ClientSecretBasic => {
+            // Both parts are form-encoded first (RFC 6749 appendix B)
+            let encode = |value: &str| {
+                url::form_urlencoded::byte_serialize(value.as_bytes()).collect::<String>()
+            };
+            let secret = config.client_secret.as_deref().unwrap_or_default();
+            let credentials = format!("{}:{}", encode(&config.client_id), encode(secret));
+            return client
+                .post(token_endpoint)
+                .header(
+                    reqwest::header::AUTHORIZATION,
+                    format!("Basic {}", STANDARD.encode(credentials)),
+                )
+                .form(&params);
+        }
+        TokenEndpointAuthMethod::None => {
+            params.insert("client_id", &config.client_id);
+        }
+    }
+    client.post(token_endpoint).form(&params)
+}
+
+/// Error prefix for an `invalid_grant` password grant response, which means
+/// the username or password was wrong
+pub const INVALID_GRANT_ERROR: &str = "invalid_grant";
+
+/// Get tokens with the resource owner password credentials grant.
+///
+/// Legacy: only for identity providers that offer nothing else, such as test
+/// realms. The username and password are sent once and never stored, logged
+/// or included in errors.
+pub async fn password_grant(
+    config: &OidcConfig,
+    discovery: Option<&OidcDiscovery>,
+    username: &str,
+    password: &str,
+) -> Result<TokenResponse, String> {
+    let token_endpoint = config
+        .token_endpoint
+        .as_ref()
+        .or(discovery.map(|d| &d.token_endpoint))
+        .ok_or("No token endpoint configured")?;
+
+    let scope = config.scopes.join(" ");
+    let mut params = HashMap::new();
+    params.insert("grant_type", "password");
+    params.insert("username", username);
+    params.insert("password", password);
+    if !scope.is_empty() {
+        params.insert("scope", &scope);
+    }
+
+    let mask = |text: String| mask_credentials(text, &[username, password]);
+    let response = token_request(token_endpoint, config, discovery, params)
+        .send()
+        .await
+        .map_err(|e| mask(format!("Token request failed: {}", e)))?;
+
+    if !response.status().is_success() {
+        let error_text = response.text().await.unwrap_or_default();
+        let error: Option<TokenError> = serde_json::from_str(&error_text).ok();
+        return Err(mask(match error {
+            Some(error) if error.error == INVALID_GRANT_ERROR => format!(
+                "{}: Wrong username or password{}",
+                INVALID_GRANT_ERROR,
+                error
+                    .error_description
+                    .map(|desc| format!(" ({})", desc))
+                    .unwrap_or_default()
+            ),
+            _ => format!("Password grant failed: {}", error_text),
+        }));
+    }
+
+    response
+        .json::<TokenResponse>()
+        .await
+        .map_err(|e| mask(format!("Failed to parse token response: {}", e)))
+}
+
+/// Grant type of a token exchange (RFC 8693)
+pub const TOKEN_EXCHANGE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:token-exchange";
+
+/// Token type of an OAuth access token (RFC 8693 section 3)
+pub const ACCESS_TOKEN_TYPE: &str = "urn:ietf:params:oauth:token-type:access_token";
+
+/// Exchange a token for one for another audience or of another type
+/// (RFC 8693), authenticating as the client. An error response fails with
+/// its OAuth error code first, such as `invalid_target: ...`.
+pub async fn token_exchange(
+    config: &OidcConfig,
+    discovery: Option<&OidcDiscovery>,
+    subject_token: &str,
+    subject_token_type: &str,
+    audience: Option<&str>,
+    requested_token_type: Option<&str>,
+) -> Result<TokenResponse, String> {
+    let token_endpoint = config
+        .token_endpoint
+        .as_ref()
+        .or(discovery.map(|d| &d.token_endpoint))
+        .ok_or("No token endpoint configured")?;
+
+    let mut params = HashMap::new();
+    params.insert("grant_type", TOKEN_EXCHANGE_GRANT_TYPE);
+    params.insert("subject_token", subject_token);
+    params.insert("subject_token_type", subject_token_type);
+    if let Some(audience) = audience {
+        params.insert("audience", audience);
+    }
+    if let Some(requested_token_type) = requested_token_type {
+        params.insert("requested_token_type", requested_token_type);
+    }
+
+    let response = token_request(token_endpoint, config, discovery, params)
+        .send()
+        .await
+        .map_err(|e| format!("Token exchange request failed: {}", e))?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let error_text = response.text().await.unwrap_or_default();
+        let error: Option<TokenError> = serde_json::from_str(&error_text).ok();
+        return Err(match error {
+            Some(error) => format!(
+                "{}: Token exchange failed{}",
+                error.error,
+                error
+                    .error_description
+                    .map(|desc| format!(" ({})", desc))
+                    .unwrap_or_default()
+            ),
+            None => format!("Token exchange failed ({}): {}", status, error_text),
+        });
+    }
+
+    response
+        .json::<TokenResponse>()
+        .await
+        .map_err(|e| format!("Failed to parse token exchange response: {}", e))
+}
+
+/// Error body of a failed token request (RFC 6749 section 5.2)
+#[derive(Debug, Deserialize)]
+struct TokenError {
+    error: String,
+    #[serde(default)]
+    error_description: Option<String>,
+}
+
+/// Replace credentials an identity provider may echo back in its errors
+fn mask_credentials(mut text: String, credentials: &[&str]) -> String {
+    for credential in credentials.iter().filter(|c| !c.is_empty()) {
+        text = text.replace(credential, "****");
+    }
+    text
+}
+
+/// Token endpoint stand-in for tests
+#[cfg(test)]
+pub mod mock {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serve one response and return the URL and the request received
+    pub async fn serve_once(
+        status: &'static str,
+        body: &'static str,
+    ) -> (String, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/token", listener.local_addr().unwrap());
+        let handle = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut received = Vec::new();
+            let mut buf = [0; 1024];
+            loop {
+                let n = socket.read(&mut buf).await.unwrap();
+                if n == 0 {
+                    break;
+                }
+                received.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&received).to_string();
+                if let Some((head, form)) = text.split_once("\r\n\r\n") {
+                    let length = head
+                        .lines()
+                        .find_map(|line| {
+                            line.to_lowercase()
+                                .strip_prefix("content-length: ")
+                                .map(|l| l.parse::<usize>().unwrap())
+                        })
+                        .unwrap_or(0);
+                    if form.len() >= length {
+                        break;
+                    }
+                }
+            }
+            let response = format!(
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+            String::from_utf8_lossy(&received).to_string()
+        });
+        (url, handle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mock::serve_once;
+    use super::*;
+
+    fn callback(error: Option<&str>, description: Option<&str>) -> CallbackResult {
+        CallbackResult {
+            code: error.is_none().then(|| "abc".to_string()),
+            state: Some("state".to_string()),
+            error: error.map(str::to_string),
+            error_description: description.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_callback_page_templates() {
+        let page = CallbackPage {
+            success_html: Some("<body><p>Signed in</p></body>".to_string()),
+            error_html: Some("<p>{{error}}: {{error_description}}</p>".to_string()),
+            auto_close: false,
+        };
+        assert_eq!(
+            page.render(&callback(None, None)),
+            "<body><p>Signed in</p></body>"
+        );
+        assert_eq!(
+            page.render(&callback(Some("access_denied"), Some("User declined"))),
+            "<p>access_denied: User declined</p>"
+        );
+        assert_eq!(
+            page.render(&callback(Some("server_error"), None)),
+            "<p>server_error: Unknown error</p>"
+        );
+
+        // The script goes before </body>, or at the end without one
+        let closing = CallbackPage {
+            auto_close: true,
+            ..page
+        };
+        assert_eq!(
+            closing.render(&callback(None, None)),
+            "<body><p>Signed in</p><script>window.close()</script></body>"
+        );
+        assert_eq!(
+            closing.render(&callback(Some("e"), Some("d"))),
+            "<p>e: d</p><script>window.close()</script>"
+        );
+
+        // Without templates, the built-in pages
+        let default = CallbackPage::default();
+        assert!(default
+            .render(&callback(None, None))
+            .contains("Authentication Successful"));
+        assert!(default
+            .render(&callback(Some("access_denied"), Some("User declined")))
+            .contains("<p>User declined</p>"));
+    }
+
+    #[test]
+    fn test_callback_page_escapes_error_params() {
+        let page = CallbackPage {
+            error_html: Some("<p title=\"{{error}}\">{{error_description}}</p>".to_string()),
+            ..CallbackPage::default()
+        };
+        let html = page.render(&callback(
+            Some("x\" onmouseover=\"alert(1)"),
+            Some("<script>alert('xss')</script> & {{error}}"),
+        ));
+        assert_eq!(
+            html,
+            "<p title=\"x&quot; onmouseover=&quot;alert(1)\">\
+             &lt;script&gt;alert(&#39;xss&#39;)&lt;/script&gt; &amp; {{error}}</p>"
+        );
+        // The default page escapes too
+        let html = CallbackPage::default()
+            .render(&callback(Some("e"), Some("<img src=x onerror=alert(1)>")));
+        assert!(html.contains("&lt;img src=x onerror=alert(1)&gt;"));
+        assert!(!html.contains("<img"));
+    }
+
+    /// A redirect URL on a port nothing is listening on
+    async fn free_redirect_url() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        format!(
+            "http://127.0.0.1:{}/callback",
+            listener.local_addr().unwrap().port()
+        )
+    }
+
+    /// Whether the redirect URL's port can be bound again
+    async fn port_released(redirect_url: &str) -> bool {
+        let port = Url::parse(redirect_url).unwrap().port().unwrap();
+        TcpListener::bind(("127.0.0.1", port)).await.is_ok()
+    }
+
+    async fn get(redirect_url: &str, path: &str) -> String {
+        use tokio::io::AsyncReadExt;
+
+        let port = Url::parse(redirect_url).unwrap().port().unwrap();
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn test_callback_server_times_out() {
+        let redirect_url = free_redirect_url().await;
+        let (_tx, cancel) = oneshot::channel();
+        let listener = bind_callback_listener(&redirect_url, false).await.unwrap();
+        let outcome = wait_for_callback(listener, "state", Duration::from_millis(100), cancel)
+            .await
+            .unwrap();
+        assert!(matches!(outcome, CallbackOutcome::TimedOut));
+        assert!(port_released(&redirect_url).await);
+    }
+
+    #[tokio::test]
+    async fn test_callback_server_cancel() {
+        let redirect_url = free_redirect_url().await;
+        let (tx, cancel) = oneshot::channel();
+        let listener = bind_callback_listener(&redirect_url, false).await.unwrap();
+        let server = tokio::spawn(async move {
+            wait_for_callback(listener, "state", DEFAULT_CALLBACK_TIMEOUT, cancel).await
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        tx.send(()).unwrap();
+
+        let outcome = server.await.unwrap().unwrap();
+        assert!(matches!(outcome, CallbackOutcome::Cancelled));
+        assert!(port_released(&redirect_url).await);
+
+        // Cancelling stops every flow waiting
+        let first = register_callback_cancel("cancel-first");
+        let second = register_callback_cancel("cancel-second");
+        assert!(cancel_callback());
+        assert!(first.await.is_ok());
+        assert!(second.await.is_ok());
+        assert!(!cancel_callback());
+    }
+
+    #[tokio::test]
+    async fn test_callback_server_ignores_stray_requests() {
+        let redirect_url = free_redirect_url().await;
+        let (_tx, cancel) = oneshot::channel();
+        let listener = bind_callback_listener(&redirect_url, false).await.unwrap();
+        let server = tokio::spawn(async move {
+            wait_for_callback(listener, "state-1", DEFAULT_CALLBACK_TIMEOUT, cancel).await
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let response = get(&redirect_url, "/favicon.ico").await;
+        assert!(response.starts_with("HTTP/1.1 404"));
+        let response = get(&redirect_url, "/callback?code=abc&state=state-1").await;
+        assert!(response.contains("Authentication Successful"));
+
+        match server.await.unwrap().unwrap() {
+            CallbackOutcome::Received(result) => {
+                assert_eq!(result.code.as_deref(), Some("abc"));
+                assert_eq!(result.state.as_deref(), Some("state-1"));
+            }
+            outcome => panic!("unexpected outcome: {:?}", outcome),
+        }
+        assert!(port_released(&redirect_url).await);
+    }
+
+    #[test]
+    fn test_deep_link_callback() {
+        let redirect = "kvile://oauth/callback";
+        let parse = |link: &str| deep_link_callback(&Url::parse(link).unwrap(), redirect, "s1");
+
+        let result = parse("kvile://oauth/callback?code=abc&state=s1")
+            .unwrap()
+            .unwrap();
+        assert_eq!(result.code.as_deref(), Some("abc"));
+        assert_eq!(result.state.as_deref(), Some("s1"));
+
+        let result = parse(
+            "kvile://oauth/callback/?error=access_denied&error_description=User%20denied&state=s1",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(result.code, None);
+        assert_eq!(result.error.as_deref(), Some("access_denied"));
+        assert_eq!(result.error_description.as_deref(), Some("User denied"));
+
+        // Links elsewhere in the app are not the callback
+        assert!(parse("kvile://open?file=api.http").unwrap().is_none());
+        assert!(parse("kvile://oauth/logout?state=s1").unwrap().is_none());
+        assert_eq!(
+            parse("kvile://oauth/callback?code=abc&state=s2").unwrap_err(),
+            "State mismatch - possible CSRF attack"
+        );
+
+        assert!(is_deep_link_redirect(redirect));
+        assert!(!is_deep_link_redirect("http://127.0.0.1:0/callback"));
+        assert!(expect_deep_link("s1", "myapp://oauth/callback")
+            .unwrap_err()
+            .contains("only kvile:// redirects reach kvile"));
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_deep_link() {
+        let redirect = "kvile://oauth/callback";
+        expect_deep_link("deep-1", redirect).unwrap();
+        let (_tx, cancel) = oneshot::channel();
+        let waiter = tokio::spawn(async move {
+            wait_for_deep_link(redirect, "deep-1", DEFAULT_CALLBACK_TIMEOUT, cancel).await
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        assert!(deliver_deep_link(
+            &Url::parse("kvile://open?file=a.http").unwrap()
+        ));
+        assert!(deliver_deep_link(
+            &Url::parse("kvile://oauth/callback?code=xyz&state=deep-1").unwrap()
+        ));
+        match waiter.await.unwrap().unwrap() {
+            CallbackOutcome::Received(result) => assert_eq!(result.code.as_deref(), Some("xyz")),
+            outcome => panic!("unexpected outcome: {:?}", outcome),
+        }
+
+        let (_tx, cancel) = oneshot::channel();
+        let outcome = wait_for_deep_link(redirect, "deep-2", Duration::from_millis(50), cancel)
+            .await
+            .unwrap();
+        assert!(matches!(outcome, CallbackOutcome::TimedOut));
+
+        let (tx, cancel) = oneshot::channel();
+        tx.send(()).unwrap();
+        let outcome = wait_for_deep_link(redirect, "deep-3", DEFAULT_CALLBACK_TIMEOUT, cancel)
+            .await
+            .unwrap();
+        assert!(matches!(outcome, CallbackOutcome::Cancelled));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_flows_on_random_ports() {
+        let first = bind_callback_listener("http://127.0.0.1:0/callback", false)
+            .await
+            .unwrap();
+        let second = bind_callback_listener("http://127.0.0.1:8080/callback", true)
+            .await
+            .unwrap();
+        assert_ne!(first.redirect_url, second.redirect_url);
+        assert!(!first.redirect_url.contains(":0/"));
+        assert!(!second.redirect_url.contains(":8080/"));
+
+        // The auth URL sends the IdP to the bound port
+        let config = OidcConfig {
+            issuer: None,
+            authorization_endpoint: Some("https://auth.example.com/authorize".to_string()),
+            token_endpoint: None,
+            client_id: "my-client".to_string(),
+            client_secret: None,
+            token_endpoint_auth_method: None,
+            redirect_url: first.redirect_url.clone(),
+            use_random_port: false,
+            use_pkce: true,
+            scopes: vec!["openid".to_string()],
+            extra_params: HashMap::new(),
+            callback_page: CallbackPage::default(),
+        };
+        let auth_url = build_auth_url(&config, None, "state-a", "nonce-a", None).unwrap();
+        let redirect_uri = Url::parse(&auth_url)
+            .unwrap()
+            .query_pairs()
+            .find(|(key, _)| key == "redirect_uri")
+            .unwrap()
+            .1
+            .to_string();
+        assert_eq!(redirect_uri, first.redirect_url);
+
+        // Held by state between starting the flow and waiting on it
+        let (first_url, second_url) = (first.redirect_url.clone(), second.redirect_url.clone());
+        hold_listener("state-a", first);
+        hold_listener("state-b", second);
+        let flows: Vec<_> = ["state-a", "state-b"]
+            .into_iter()
+            .map(|state| {
+                let listener = take_listener(state).unwrap();
+                let cancel = register_callback_cancel(state);
+                tokio::spawn(async move {
+                    wait_for_callback(listener, state, DEFAULT_CALLBACK_TIMEOUT, cancel).await
+                })
+            })
+            .collect();
+        assert!(take_listener("state-a").is_none());
+
+        get(&second_url, "/callback?code=code-b&state=state-b").await;
+        get(&first_url, "/callback?code=code-a&state=state-a").await;
+        let mut codes = Vec::new();
+        for flow in flows {
+            match flow.await.unwrap().unwrap() {
+                CallbackOutcome::Received(result) => codes.push(result.code.unwrap()),
+                outcome => panic!("unexpected outcome: {:?}", outcome),
+            }
+        }
+        assert_eq!(codes, vec!["code-a", "code-b"]);
+    }
+
+    /// The form fields and Authorization header of a received request
+    fn form_and_auth(request: &str) -> (HashMap<String, String>, Option<String>) {
+        let (head, body) = request.split_once("\r\n\r\n").unwrap();
+        let auth = head.lines().find_map(|line| {
+            let (name, value) = line.split_once(": ")?;
+            name.eq_ignore_ascii_case("authorization")
+                .then(|| value.to_string())
+        });
+        let form = url::form_urlencoded::parse(body.as_bytes())
+            .into_owned()
+            .collect();
+        (form, auth)
+    }
+
+    fn client_config(
+        token_endpoint: String,
+        method: Option<TokenEndpointAuthMethod>,
+    ) -> OidcConfig {
+        OidcConfig {
+            client_id: "my:client".to_string(),
+            client_secret: Some("s&cret/é".to_string()),
+            token_endpoint_auth_method: method,
+            ..password_config(token_endpoint)
+        }
+    }
+
+    const TOKEN_BODY: &str = r#"{"access_token":"at-1","token_type":"Bearer"}"#;
+
+    #[test]
+    fn test_build_logout_url() {
+        let discovery = OidcDiscovery {
+            issuer: "https://auth.example.com".to_string(),
+            authorization_endpoint: "https://auth.example.com/authorize".to_string(),
+            token_endpoint: "https://auth.example.com/token".to_string(),
+            userinfo_endpoint: None,
+            jwks_uri: None,
+            end_session_endpoint: Some("https://auth.example.com/logout?realm=test".to_string()),
+            introspection_endpoint: None,
+            scopes_supported: Vec::new(),
+            response_types_supported: Vec::new(),
+            grant_types_supported: Vec::new(),
+            token_endpoint_auth_methods_supported: Vec::new(),
+            code_challenge_methods_supported: Vec::new(),
+        };
+        let config = password_config(String::new());
+
+        let url = build_logout_url(
+            &config,
+            Some(&discovery),
+            Some("id.token.value"),
+            Some("http://127.0.0.1:8080/logged-out"),
+            "state-1",
+        )
+        .unwrap();
+        assert_eq!(
+            url,
+            "https://auth.example.com/logout?realm=test&id_token_hint=id.token.value\
+             &client_id=test-client\
+             &post_logout_redirect_uri=http%3A%2F%2F127.0.0.1%3A8080%2Flogged-out&state=state-1"
+        );
+
+        let url = build_logout_url(&config, Some(&discovery), None, None, "state-2").unwrap();
+        assert_eq!(
+            url,
+            "https://auth.example.com/logout?realm=test&client_id=test-client&state=state-2"
+        );
+
+        let without_endpoint = OidcDiscovery {
+            end_session_endpoint: None,
+            ..discovery
+        };
+        assert_eq!(
+            build_logout_url(&config, Some(&without_endpoint), None, None, "s").unwrap_err(),
+            "The provider does not advertise an end_session_endpoint"
+        );
+        assert!(build_logout_url(&config, None, None, None, "s").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_client_secret_post() {
+        let (url, server) = serve_once("200 OK", TOKEN_BODY).await;
+        let config = client_config(url, Some(TokenEndpointAuthMethod::ClientSecretPost));
+        exchange_code_for_tokens(&config, None, "code-1", Some("verifier"))
+            .await
+            .unwrap();
+
+        let (form, auth) = form_and_auth(&server.await.unwrap());
+        assert_eq!(auth, None);
+        assert_eq!(form["client_id"], "my:client");
+        assert_eq!(form["client_secret"], "s&cret/é");
+        assert_eq!(form["code"], "code-1");
+    }
+
+    #[tokio::test]
+    async fn test_client_secret_basic() {
+        let (url, server) = serve_once("200 OK", TOKEN_BODY).await;
+        let config = client_config(url, Some(TokenEndpointAuthMethod::ClientSecretBasic));
+        refresh_access_token(&config, None, "rt-1").await.unwrap();
+
+        let (form, auth) = form_and_auth(&server.await.unwrap());
+        // base64("my%3Aclient:s%26cret%2F%C3%A9")
+        assert_eq!(
+            auth.as_deref(),
+            Some("Basic bXklM0FjbGllbnQ6cyUyNmNyZXQlMkYlQzMlQTk=")
+        );
+        assert!(!form.contains_key("client_id"));
+        assert!(!form.contains_key("client_secret"));
+        assert_eq!(form["refresh_token"], "rt-1");
+    }
+
+    #[tokio::test]
+    async fn test_public_client_auth() {
+        let (url, server) = serve_once("200 OK", TOKEN_BODY).await;
+        let config = client_config(url, Some(TokenEndpointAuthMethod::None));
+        client_credentials_grant(&config, None).await.unwrap();
+
+        let (form, auth) = form_and_auth(&server.await.unwrap());
+        assert_eq!(auth, None);
+        assert_eq!(form["client_id"], "my:client");
+        assert!(!form.contains_key("client_secret"));
+        assert_eq!(form["grant_type"], "client_credentials");
+        assert_eq!(form["scope"], "openid profile");
+    }
+
+    fn introspection_discovery(endpoint: Option<String>) -> OidcDiscovery {
+        OidcDiscovery {
+            issuer: "https://auth.example.com".to_string(),
+            authorization_endpoint: "https://auth.example.com/authorize".to_string(),
+            token_endpoint: "https://auth.example.com/token".to_string(),
+            userinfo_endpoint: None,
+            jwks_uri: None,
+            end_session_endpoint: None,
+            introspection_endpoint: endpoint,
+            scopes_supported: Vec::new(),
+            response_types_supported: Vec::new(),
+            grant_types_supported: Vec::new(),
+            token_endpoint_auth_methods_supported: Vec::new(),
+            code_challenge_methods_supported: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_introspect_active_token() {
+        let (url, server) = serve_once(
+            "200 OK",
+            r#"{"active":true,"scope":"openid orders:read","exp":1700003600,"sub":"user-1","client_id":"my:client","username":"alice"}"#,
+        )
+        .await;
+        let config = client_config(
+            String::new(),
+            Some(TokenEndpointAuthMethod::ClientSecretBasic),
+        );
+        let discovery = introspection_discovery(Some(url));
+
+        let introspection = introspect_token(&config, Some(&discovery), "at-1")
+            .await
+            .unwrap();
+        assert!(introspection.active);
+        assert_eq!(introspection.scope.as_deref(), Some("openid orders:read"));
+        assert_eq!(introspection.exp, Some(1_700_003_600));
+        assert_eq!(introspection.sub.as_deref(), Some("user-1"));
+        assert_eq!(introspection.client_id.as_deref(), Some("my:client"));
+        assert_eq!(introspection.claims["username"], "alice");
+
+        let (form, auth) = form_and_auth(&server.await.unwrap());
+        assert!(auth.unwrap().starts_with("Basic "));
+        assert_eq!(form["token"], "at-1");
+    }
+
+    #[tokio::test]
+    async fn test_introspect_inactive_token() {
+        let (url, _server) = serve_once("200 OK", r#"{"active":false}"#).await;
+        let discovery = introspection_discovery(Some(url));
+
+        let introspection =
+            introspect_token(&password_config(String::new()), Some(&discovery), "at-1")
+                .await
+                .unwrap();
+        assert!(!introspection.active);
+        assert_eq!(introspection.scope, None);
+        assert_eq!(introspection.sub, None);
+        assert!(introspection.claims.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_introspect_errors() {
+        let (url, _server) = serve_once("401 Unauthorized", r#"{"error":"invalid_client"}"#).await;
+        let config = password_config(String::new());
+        let err = introspect_token(&config, Some(&introspection_discovery(Some(url))), "at-1")
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            r#"Introspection failed (401 Unauthorized): {"error":"invalid_client"}"#
+        );
+
+        let err = introspect_token(&config, Some(&introspection_discovery(None)), "at-1")
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            "The provider does not advertise an introspection_endpoint"
+        );
+        assert!(introspect_token(&config, None, "at-1").await.is_err());
+    }
+
+    #[test]
+    fn test_auth_method_defaults_from_discovery() {
+        let discovery = |methods: &[&str]| OidcDiscovery {
+            issuer: "https://auth.example.com".to_string(),
+            authorization_endpoint: "https://auth.example.com/authorize".to_string(),
+            token_endpoint: "https://auth.example.com/token".to_string(),
+            userinfo_endpoint: None,
+            jwks_uri: None,
+            end_session_endpoint: None,
+            introspection_endpoint: None,
+            scopes_supported: Vec::new(),
+            response_types_supported: Vec::new(),
+            grant_types_supported: Vec::new(),
+            token_endpoint_auth_methods_supported: methods.iter().map(|m| m.to_string()).collect(),
+            code_challenge_methods_supported: Vec::new(),
+        };
+        let config = client_config(String::new(), None);
+        let method =
+            |methods: &[&str]| token_endpoint_auth_method(&config, Some(&discovery(methods)));
+
+        assert_eq!(
+            token_endpoint_auth_method(&config, None),
+            TokenEndpointAuthMethod::ClientSecretPost
+        );
+        assert_eq!(
+            method(&["client_secret_basic"]),
+            TokenEndpointAuthMethod::ClientSecretBasic
+        );
+        assert_eq!(
+            method(&["client_secret_basic", "client_secret_post"]),
+            TokenEndpointAuthMethod::ClientSecretPost
+        );
+
+        // An explicit method wins; clients without a secret are public
+        let basic = client_config(
+            String::new(),
+            Some(TokenEndpointAuthMethod::ClientSecretBasic),
+        );
+        assert_eq!(
+            token_endpoint_auth_method(&basic, Some(&discovery(&["client_secret_post"]))),
+            TokenEndpointAuthMethod::ClientSecretBasic
+        );
+        let public = OidcConfig {
+            client_secret: None,
+            ..client_config(String::new(), None)
+        };
+        assert_eq!(
+            token_endpoint_auth_method(&public, Some(&discovery(&["client_secret_basic"]))),
+            TokenEndpointAuthMethod::None
+        );
+    }
+
+    fn password_config(token_endpoint: String) -> OidcConfig {
+        OidcConfig {
+            issuer: None,
+            authorization_endpoint: None,
+            token_endpoint: Some(token_endpoint),
+            client_id: "test-client".to_string(),
+            client_secret: Some("client-secret".to_string()),
+            token_endpoint_auth_method: None,
+            redirect_url: String::new(),
+            use_random_port: false,
+            use_pkce: true,
+            scopes: vec!["openid".to_string(), "profile".to_string()],
+            extra_params: HashMap::new(),
+            callback_page: CallbackPage::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_password_grant() {
+        let (url, server) = serve_once(
+            "200 OK",
+            r#"{"access_token":"at-1","token_type":"Bearer","expires_in":300}"#,
+        )
+        .await;
+
+        let tokens = password_grant(&password_config(url), None, "alice", "s3cret!")
+            .await
+            .unwrap();
+        assert_eq!(tokens.access_token, "at-1");
+        assert_eq!(tokens.expires_in, Some(300));
+
+        let request = server.await.unwrap();
+        let form: HashMap<String, String> =
+            url::form_urlencoded::parse(request.split_once("\r\n\r\n").unwrap().1.as_bytes())
+                .into_owned()
+                .collect();
+        assert_eq!(form["grant_type"], "password");
+        assert_eq!(form["username"], "alice");
+        assert_eq!(form["password"], "s3cret!");
+        assert_eq!(form["scope"], "openid profile");
+        assert_eq!(form["client_id"], "test-client");
+        assert_eq!(form["client_secret"], "client-secret");
+    }
+
+    #[tokio::test]
+    async fn test_password_grant_masks_credentials_in_errors() {
+        // Some IdPs echo the username back
+        let (url, _server) = serve_once(
+            "400 Bad Request",
+            r#"{"error":"invalid_grant","error_description":"Invalid user credentials for alice"}"#,
+        )
+        .await;
+        let err = password_grant(&password_config(url), None, "alice", "s3cret!")
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            "invalid_grant: Wrong username or password (Invalid user credentials for ****)"
+        );
+
+        let (url, _server) = serve_once(
+            "401 Unauthorized",
+            r#"{"error":"unauthorized_client","error_description":"s3cret! rejected"}"#,
+        )
+        .await;
+        let err = password_grant(&password_config(url), None, "alice", "s3cret!")
+            .await
+            .unwrap_err();
+        assert!(err.starts_with("Password grant failed: "));
+        assert!(!err.contains(INVALID_GRANT_ERROR));
+        assert!(!err.contains("alice") && !err.contains("s3cret!"));
+    }
+
+    #[tokio::test]
+    async fn test_token_exchange() {
+        let (url, server) = serve_once(
+            "200 OK",
+            r#"{"access_token":"downstream-token","issued_token_type":"urn:ietf:params:oauth:token-type:access_token","token_type":"Bearer","expires_in":300}"#,
+        )
+        .await;
+        let config = client_config(url, Some(TokenEndpointAuthMethod::ClientSecretBasic));
+        let tokens = token_exchange(
+            &config,
+            None,
+            "user-token",
+            ACCESS_TOKEN_TYPE,
+            Some("orders-api"),
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(tokens.access_token, "downstream-token");
+        assert_eq!(tokens.issued_token_type.as_deref(), Some(ACCESS_TOKEN_TYPE));
+        assert_eq!(tokens.expires_in, Some(300));
+
+        let (form, auth) = form_and_auth(&server.await.unwrap());
+        assert!(auth.unwrap().starts_with("Basic "));
+        assert_eq!(form["grant_type"], TOKEN_EXCHANGE_GRANT_TYPE);
+        assert_eq!(form["subject_token"], "user-token");
+        assert_eq!(form["subject_token_type"], ACCESS_TOKEN_TYPE);
+        assert_eq!(form["audience"], "orders-api");
+        assert!(!form.contains_key("requested_token_type"));
+    }
+
+    #[tokio::test]
+    async fn test_token_exchange_invalid_target() {
+        let (url, _server) = serve_once(
+            "400 Bad Request",
+            r#"{"error":"invalid_target","error_description":"Audience billing-api is not allowed"}"#,
+        )
+        .await;
+        let config = client_config(url, None);
+        let err = token_exchange(
+            &config,
+            None,
+            "user-token",
+            ACCESS_TOKEN_TYPE,
+            Some("billing-api"),
+            Some("urn:ietf:params:oauth:token-type:jwt"),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            err,
+            "invalid_target: Token exchange failed (Audience billing-api is not allowed)"
+        );
+
+        let (url, _server) = serve_once("502 Bad Gateway", "upstream down").await;
+        let err = token_exchange(
+            &client_config(url, None),
+            None,
+            "t",
+            ACCESS_TOKEN_TYPE,
+            None,
+            None,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            err,
+            "Token exchange failed (502 Bad Gateway): upstream down"
+        );
+    }
+
+    #[test]
+    fn test_generate_pkce() {
+        let pkce = generate_pkce(PkceMethod::S256).unwrap();
+        assert!(!pkce.code_verifier.is_empty());
+        assert!(!pkce.code_challenge.is_empty());
+        assert_ne!(pkce.code_challenge, pkce.code_verifier);
+        assert_eq!(pkce.code_challenge_method, "S256");
+        // Verifier should be ~43 chars (32 bytes base64 encoded)
+        assert!(pkce.code_verifier.len() >= 40);
+
+        let plain = generate_pkce(PkceMethod::Plain).unwrap();
+        assert_eq!(plain.code_challenge, plain.code_verifier);
+        assert_eq!(plain.code_challenge_method, "plain");
+
+        assert!(generate_pkce(PkceMethod::None).is_none());
+    }
+
+    #[test]
+    fn test_pkce_method() {
+        let mut config = password_config(String::new());
+        let discovery = |methods: &[&str]| OidcDiscovery {
+            code_challenge_methods_supported: methods.iter().map(|m| m.to_string()).collect(),
+            ..introspection_discovery(None)
+        };
+
+        assert_eq!(pkce_method(&config, None), PkceMethod::S256);
+        assert_eq!(
+            pkce_method(&config, Some(&discovery(&[]))),
+            PkceMethod::S256
+        );
+        assert_eq!(
+            pkce_method(&config, Some(&discovery(&["plain", "S256"]))),
+            PkceMethod::S256
+        );
+        assert_eq!(
+            pkce_method(&config, Some(&discovery(&["plain"]))),
+            PkceMethod::Plain
+        );
+        assert_eq!(
+            pkce_method(&config, Some(&discovery(&["S512"]))),
+            PkceMethod::None
+        );
+
+        config.use_pkce = false;
+        assert_eq!(
+            pkce_method(&config, Some(&discovery(&["S256"]))),
+            PkceMethod::None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_code_exchange_without_pkce() {
+        let (url, server) = serve_once("200 OK", TOKEN_BODY).await;
+        let config = password_config(url);
+        exchange_code_for_tokens(&config, None, "code-1", None)
+            .await
+            .unwrap();
+
+        let (form, _) = form_and_auth(&server.await.unwrap());
+        assert_eq!(form["code"], "code-1");
+        assert!(!form.contains_key("code_verifier"));
+    }
+
+    #[test]
+    fn test_generate_state() {
+        let state = generate_state();
+        assert!(!state.is_empty());
+        // State should be ~22 chars (16 bytes base64 encoded)
+        assert!(state.len() >= 20);
     }
 
     #[test]
@@ -422,21 +2019,62 @@ mod tests {
             token_endpoint: Some("https://auth.example.com/token".to_string()),
             client_id: "my-client".to_string(),
             client_secret: None,
+            token_endpoint_auth_method: None,
             redirect_url: "http://localhost:8080/callback".to_string(),
+            use_random_port: false,
+            use_pkce: true,
             scopes: vec!["openid".to_string(), "profile".to_string()],
             extra_params: HashMap::new(),
+            callback_page: CallbackPage::default(),
         };
 
-        let pkce = generate_pkce();
+        let pkce = generate_pkce(PkceMethod::S256).unwrap();
         let state = generate_state();
+        let nonce = generate_nonce();
+        assert_ne!(nonce, state);
 
-        let url = build_auth_url(&config, None, &state, &pkce).unwrap();
+        let url = build_auth_url(&config, None, &state, &nonce, Some(&pkce)).unwrap();
 
         assert!(url.contains("response_type=code"));
         assert!(url.contains("client_id=my-client"));
         assert!(url.contains("redirect_uri="));
         assert!(url.contains("scope=openid+profile"));
-        assert!(url.contains("code_challenge="));
+        assert!(url.contains(&format!("code_challenge={}", pkce.code_challenge)));
         assert!(url.contains("code_challenge_method=S256"));
+        assert!(url.contains(&format!("nonce={}", nonce)));
+
+        let plain = generate_pkce(PkceMethod::Plain).unwrap();
+        let url = build_auth_url(&config, None, &state, &nonce, Some(&plain)).unwrap();
+        assert!(url.contains(&format!("code_challenge={}", plain.code_verifier)));
+        assert!(url.contains("code_challenge_method=plain"));
+
+        let url = build_auth_url(&config, None, &state, &nonce, None).unwrap();
+        assert!(!url.contains("code_challenge"));
+        assert!(url.contains("response_type=code"));
+    }
+
+    #[test]
+    fn test_validate_id_token_nonce() {
+        let id_token = |payload: &str| {
+            format!(
+                "{}.{}.c2ln",
+                URL_SAFE_NO_PAD.encode(r#"{"alg":"RS256"}"#),
+                URL_SAFE_NO_PAD.encode(payload)
+            )
+        };
+
+        let token = id_token(r#"{"sub":"user-1","nonce":"n-123"}"#);
+        assert!(validate_id_token_nonce(&token, "n-123").is_ok());
+        assert_eq!(
+            validate_id_token_nonce(&token, "n-456").unwrap_err(),
+            "ID token nonce does not match the auth request"
+        );
+        assert_eq!(
+            validate_id_token_nonce(&id_token(r#"{"sub":"user-1"}"#), "n-123").unwrap_err(),
+            "ID token is missing the nonce claim"
+        );
+        assert!(validate_id_token_nonce("not-a-jwt", "n-123")
+            .unwrap_err()
+            .starts_with("Invalid JWT"));
     }
 }
@@ -0,0 +1,212 @@
+use super::types::ParsedRequest;
+use std::collections::HashMap;
+
+/// Request metadata (`# @depends-on login, create-user`) naming requests that
+/// must run before this one
+pub const DEPENDS_ON_METADATA: &str = "depends-on";
+
+/// The names in a request's `# @depends-on`, separated by commas or spaces
+fn dependencies(request: &ParsedRequest) -> Vec<&str> {
+    request
+        .metadata
+        .get(DEPENDS_ON_METADATA)
+        .map(|names| {
+            names
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|name| !name.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// The order to run `requests` in, as indexes into it. Each request's
+/// dependencies run before it, even when they come later in the file; apart
+/// from that, requests keep their document order. Fails on a dependency
+/// cycle, naming it, and on dependencies no request or several requests are
+/// named.
+pub fn execution_order(requests: &[ParsedRequest]) -> Result<Vec<usize>, String> {
+    let mut by_name: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (index, request) in requests.iter().enumerate() {
+        if let Some(name) = &request.name {
+            by_name.entry(name.as_str()).or_default().push(index);
+        }
+    }
+    let mut edges = Vec::with_capacity(requests.len());
+    for request in requests {
+        let mut deps = Vec::new();
+        for name in dependencies(request) {
+            match by_name.get(name).map(Vec::as_slice) {
+                Some([index]) => deps.push(*index),
+                Some(_) => {
+                    return Err(format!(
+                        "{} depends on '{}', which names more than one request",
+                        label(request),
+                        name
+                    ))
+                }
+                None => {
+                    return Err(format!(
+                        "{} depends on '{}', but no request has that name",
+                        label(request),
+                        name
+                    ))
+                }
+            }
+        }
+        edges.push(deps);
+    }
+
+    let mut order = Vec::with_capacity(requests.len());
+    let mut state = vec![Visit::New; requests.len()];
+    let mut path = Vec::new();
+    for index in 0..requests.len() {
+        visit(index, requests, &edges, &mut state, &mut path, &mut order)?;
+    }
+    Ok(order)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Visit {
+    New,
+    /// On the current path, so reaching it again is a cycle
+    InProgress,
+    Done,
+}
+
+fn visit(
+    index: usize,
+    requests: &[ParsedRequest],
+    edges: &[Vec<usize>],
+    state: &mut [Visit],
+    path: &mut Vec<usize>,
+    order: &mut Vec<usize>,
+) -> Result<(), String> {
+    match state[index] {
+        Visit::Done => return Ok(()),
+        Visit::InProgress => {
+            let start = path.iter().position(|&i| i == index).unwrap_or(0);
+            let cycle: Vec<_> = path[start..]
+                .iter()
+                .chain([&index])
+                .map(|&i| label(&requests[i]))
+                .collect();
+            return Err(format!("Dependency cycle: {}", cycle.join(" -> ")));
+        }
+        Visit::New => {}
+    }
+    state[index] = Visit::InProgress;
+    path.push(index);
+    for &dep in &edges[index] {
+        visit(dep, requests, edges, state, path, order)?;
+    }
+    path.pop();
+    state[index] = Visit::Done;
+    order.push(index);
+    Ok(())
+}
+
+/// How errors refer to a request: its name, or the line it starts on
+fn label(request: &ParsedRequest) -> String {
+    match &request.name {
+        Some(name) => format!("'{}'", name),
+        None => format!("The request on line {}", request.line_number),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_http_content;
+
+    fn order(content: &str) -> Result<Vec<String>, String> {
+        let requests = parse_http_content(content).unwrap();
+        let order = execution_order(&requests)?;
+        Ok(order
+            .into_iter()
+            .map(|i| requests[i].name.clone().unwrap_or_default())
+            .collect())
+    }
+
+    #[test]
+    fn test_execution_order_diamond() {
+        let content = r#"### cleanup
+# @depends-on report
+DELETE http://localhost/users
+
+### report
+# @depends-on create-user, create-order
+GET http://localhost/report
+
+### list
+GET http://localhost/users
+
+### create-order
+# @depends-on login
+POST http://localhost/orders
+
+### create-user
+# @depends-on login
+POST http://localhost/users
+
+### login
+POST http://localhost/login
+"#;
+        assert_eq!(
+            order(content).unwrap(),
+            vec![
+                "login",
+                "create-user",
+                "create-order",
+                "report",
+                "cleanup",
+                "list"
+            ]
+        );
+        // Without dependencies, document order
+        assert_eq!(
+            order("### a\nGET http://a\n\n### b\nGET http://b\n").unwrap(),
+            vec!["a", "b"]
+        );
+    }
+
+    #[test]
+    fn test_execution_order_cycle() {
+        let content = r#"### list
+GET http://localhost/users
+
+### a
+# @depends-on b
+GET http://localhost/a
+
+### b
+# @depends-on c
+GET http://localhost/b
+
+### c
+# @depends-on a
+GET http://localhost/c
+"#;
+        assert_eq!(
+            order(content).unwrap_err(),
+            "Dependency cycle: 'a' -> 'b' -> 'c' -> 'a'"
+        );
+        assert_eq!(
+            order("### self\n# @depends-on self\nGET http://a\n").unwrap_err(),
+            "Dependency cycle: 'self' -> 'self'"
+        );
+    }
+
+    #[test]
+    fn test_execution_order_unknown_name() {
+        assert_eq!(
+            order("### a\n# @depends-on setup\nGET http://a\n").unwrap_err(),
+            "'a' depends on 'setup', but no request has that name"
+        );
+        let content =
+            "### a\nGET http://a\n\n### a\nGET http://b\n\n### c\n# @depends-on a\nGET http://c\n";
+        assert_eq!(
+            order(content).unwrap_err(),
+            "'c' depends on 'a', which names more than one request"
+        );
+    }
+}
@@ -35,10 +35,15 @@ pub fn detect_format(content: &str) -> HttpFileFormat {
 pub fn parse_http_content(content: &str) -> Result<Vec<ParsedRequest>, ParseError> {
     let format = detect_format(content);
 
-    match format {
+    let result = match format {
         HttpFileFormat::VsCode => vscode::parse_vscode(content),
         HttpFileFormat::JetBrains | HttpFileFormat::Unknown => jetbrains::parse_jetbrains(content),
+    };
+    match &result {
+        Ok(requests) => tracing::debug!(?format, requests = requests.len(), "Parsed .http content"),
+        Err(e) => tracing::warn!(?format, "Failed to parse .http content: {}", e),
     }
+    result
 }
 
 /// Substitute variables in a string with their values
@@ -52,9 +57,7 @@ pub fn substitute_variables(
     var_re
         .replace_all(input, |caps: &regex::Captures| {
             let var_name = &caps[1];
-            variables
-                .get(var_name)
-                .cloned()
+            crate::variables::lookup(variables, var_name)
                 .unwrap_or_else(|| format!("{{{{{}}}}}", var_name))
         })
         .to_string()
@@ -1,7 +1,9 @@
+mod dependencies;
 mod detect;
 mod jetbrains;
 mod types;
 mod vscode;
 
+pub use dependencies::execution_order;
 pub use detect::parse_http_content;
 pub use types::*;
@@ -0,0 +1,557 @@
+//! Running .http files without the app, for `kvile-cli run` in CI.
+//!
+//! Only the parser, env loader, variable resolution and HTTP client are used
+//! here; nothing reaches Tauri state, history or the watcher. Pre-request and
+//! response handler scripts need the app's script runtime, so a request with
+//! any fails unless scripts are skipped, and responses with a non-2xx status
+//! fail unless error statuses are allowed. A named request's
+//! response body is available to the requests after it in its file as
+//! `{{name.response.body}}`, so `{{login.response.body.$.token}}` chains
+//! requests without a script.
+
+use crate::env::{load_environment_config, EnvironmentConfig};
+use crate::files::list_workspace_files;
+use crate::http_client::{execute_request, HttpRequest};
+use crate::ignores::WorkspaceIgnore;
+use crate::parser::{execution_order, parse_http_content, ParsedRequest};
+use crate::variables::{resolve_request_with_commands, ResolveOptions};
+use crate::workspace_settings::WorkspaceSettings;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// What to run and how to resolve its variables
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    /// .http files, or directories whose .http files are all run
+    pub paths: Vec<PathBuf>,
+    /// Root env files and the settings file are looked up from
+    pub workspace: PathBuf,
+    /// Environment to resolve variables in; without one, the workspace
+    /// settings' `environment`
+    pub environment: Option<String>,
+    pub env_glob: Option<String>,
+    /// Fall back to the OS environment for variables nothing else defines
+    pub process_env: bool,
+    /// Count responses with a non-2xx status as passing
+    pub allow_error_status: bool,
+    /// Send requests that have scripts without running them, rather than
+    /// failing them
+    pub skip_scripts: bool,
+}
+
+/// The outcome of one request
+#[derive(Debug, Clone, Serialize)]
+pub struct RequestResult {
+    /// File the request is in, relative to the workspace where possible
+    pub file: String,
+    /// The request's name, or its method and URL as written
+    pub name: String,
+    pub line: usize,
+    pub method: String,
+    /// URL with variables substituted, once resolved
+    pub url: Option<String>,
+    pub status: Option<u16>,
+    /// Milliseconds the request took
+    pub time: u64,
+    /// Why the request failed: it could not be resolved or sent, has scripts
+    /// that can't run, or got a non-2xx status
+    pub error: Option<String>,
+    /// The request was sent without running its scripts
+    pub scripts_skipped: bool,
+}
+
+impl RequestResult {
+    pub fn passed(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Every request run, in order
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RunReport {
+    pub passed: usize,
+    pub failed: usize,
+    /// Env config warnings, such as `$exec` commands that were not run
+    pub warnings: Vec<String>,
+    pub results: Vec<RequestResult>,
+}
+
+impl RunReport {
+    pub fn success(&self) -> bool {
+        self.failed == 0
+    }
+
+    fn push(&mut self, result: RequestResult) {
+        if result.passed() {
+            self.passed += 1;
+        } else {
+            self.failed += 1;
+        }
+        self.results.push(result);
+    }
+}
+
+/// Run the requests in every file `options` names, one at a time, a file's
+/// `# @depends-on` dependencies before the requests needing them. Errors are
+/// for files that can't be found or read; failing requests are in the report.
+pub async fn run(options: &RunOptions) -> Result<RunReport, String> {
+    let workspace = options.workspace.to_string_lossy().to_string();
+    let loaded = WorkspaceSettings::load(&options.workspace);
+    let mut report = RunReport {
+        warnings: loaded.warnings,
+        ..Default::default()
+    };
+    let settings = loaded.settings;
+    let options = &RunOptions {
+        environment: options
+            .environment
+            .clone()
+            .or_else(|| settings.default_environment(None)),
+        ..options.clone()
+    };
+    for file in http_files(options, &settings).await? {
+        let content = tokio::fs::read_to_string(&file)
+            .await
+            .map_err(|e| format!("Failed to read {}: {}", file.display(), e))?;
+        let display = display_path(&options.workspace, &file);
+        let parsed = parse_http_content(&content).map_err(|e| e.to_string());
+        let ordered = parsed.and_then(|requests| {
+            let order = execution_order(&requests)?;
+            Ok(order
+                .into_iter()
+                .map(|i| requests[i].clone())
+                .collect::<Vec<_>>())
+        });
+        let requests = match ordered {
+            Ok(requests) => requests,
+            Err(e) => {
+                report.push(RequestResult {
+                    file: display,
+                    name: "parse".to_string(),
+                    line: 0,
+                    method: String::new(),
+                    url: None,
+                    status: None,
+                    time: 0,
+                    error: Some(e),
+                    scripts_skipped: false,
+                });
+                continue;
+            }
+        };
+        let mut config = load_environment_config(
+            workspace.clone(),
+            Some(file.to_string_lossy().to_string()),
+            options.env_glob.clone(),
+        )
+        .await?;
+        // Response bodies of the file's named requests, as variables
+        let mut responses = HashMap::new();
+        for request in &requests {
+            let result = run_request(
+                request,
+                &display,
+                &workspace,
+                &mut config,
+                &settings,
+                options,
+                &mut responses,
+            )
+            .await;
+            report.push(result);
+        }
+        // After the requests, for the commands they ran
+        for warning in config.warnings.iter().chain(&config.untrusted_commands) {
+            if !report.warnings.contains(warning) {
+                report.warnings.push(warning.clone());
+            }
+        }
+    }
+    Ok(report)
+}
+
+async fn run_request(
+    request: &ParsedRequest,
+    file: &str,
+    workspace: &str,
+    config: &mut EnvironmentConfig,
+    settings: &WorkspaceSettings,
+    options: &RunOptions,
+    responses: &mut HashMap<String, String>,
+) -> RequestResult {
+    let mut result = RequestResult {
+        file: file.to_string(),
+        name: request
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("{} {}", request.method, request.url)),
+        line: request.line_number,
+        method: request.method.clone(),
+        url: None,
+        status: None,
+        time: 0,
+        error: None,
+        scripts_skipped: false,
+    };
+    if request.pre_script.is_some() || request.post_script.is_some() {
+        if !options.skip_scripts {
+            result.error = Some(
+                "Scripts can't run outside the kvile app; pass --skip-scripts to send without them"
+                    .to_string(),
+            );
+            return result;
+        }
+        result.scripts_skipped = true;
+    }
+    if let Some(auth) = request.metadata.get("auth") {
+        if crate::auth::oidc_config_name(auth).is_some() {
+            result.error = Some("OIDC auth needs the kvile app to sign in".to_string());
+            return result;
+        }
+    }
+
+    let resolve_options = ResolveOptions {
+        globals: responses.clone(),
+        process_env: options.process_env,
+        ..Default::default()
+    };
+    let resolved = match resolve_request_with_commands(
+        workspace,
+        request,
+        config,
+        options.environment.as_deref(),
+        &resolve_options,
+    )
+    .await
+    {
+        Ok(resolved) => resolved,
+        Err(e) => {
+            result.error = Some(e);
+            return result;
+        }
+    };
+    result.url = Some(resolved.request.url.clone());
+    if !resolved.missing.is_empty() {
+        result.error = Some(format!(
+            "Undefined variables: {}",
+            resolved.missing.join(", ")
+        ));
+        return result;
+    }
+
+    match execute_request(HttpRequest::from(resolved.request), settings).await {
+        Ok(response) => {
+            result.status = Some(response.status);
+            result.time = response.time;
+            if !options.allow_error_status && !(200..300).contains(&response.status) {
+                result.error = Some(format!("HTTP {}", response.status));
+            }
+            if let Some(name) = request.name.as_ref().or(request.metadata.get("name")) {
+                responses.insert(format!("{}.response.body", name), response.body);
+            }
+        }
+        Err(e) => result.error = Some(e.to_string()),
+    }
+    result
+}
+
+/// The .http files to run, in the order given; a directory's files are
+/// sorted by path and exclude what its ignore files and the settings'
+/// `ignore` patterns do
+async fn http_files(
+    options: &RunOptions,
+    settings: &WorkspaceSettings,
+) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    for path in &options.paths {
+        let metadata = tokio::fs::metadata(path)
+            .await
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        if !metadata.is_dir() {
+            files.push(path.clone());
+            continue;
+        }
+        let root = path.clone();
+        let settings = settings.clone();
+        let ignore = tokio::task::spawn_blocking(move || {
+            WorkspaceIgnore::load(&root).with_patterns(&settings.ignore_patterns(None))
+        })
+        .await
+        .map_err(|e| e.to_string())?;
+        let listing =
+            list_workspace_files(&path.to_string_lossy(), &ignore, &Default::default()).await?;
+        files.extend(
+            listing
+                .files
+                .into_iter()
+                .filter(|file| file.is_http_file)
+                .map(|file| PathBuf::from(file.path)),
+        );
+    }
+    Ok(files)
+}
+
+fn display_path(workspace: &Path, file: &Path) -> String {
+    let workspace = std::fs::canonicalize(workspace).unwrap_or_else(|_| workspace.to_path_buf());
+    let canonical = std::fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf());
+    canonical
+        .strip_prefix(&workspace)
+        .unwrap_or(file)
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+/// The report as a person reads it: one line per request, then totals
+pub fn to_human(report: &RunReport) -> String {
+    let mut out = String::new();
+    let mut file = None;
+    for result in &report.results {
+        if file != Some(&result.file) {
+            out.push_str(&format!("{}\n", result.file));
+            file = Some(&result.file);
+        }
+        match (&result.error, result.status) {
+            (Some(error), _) => out.push_str(&format!("  FAIL {}: {}\n", result.name, error)),
+            (None, status) => out.push_str(&format!(
+                "  ok   {} ({}, {} ms)\n",
+                result.name,
+                status.unwrap_or_default(),
+                result.time
+            )),
+        }
+        if result.scripts_skipped {
+            out.push_str("       scripts not run\n");
+        }
+    }
+    for warning in &report.warnings {
+        out.push_str(&format!("warning: {}\n", warning));
+    }
+    out.push_str(&format!(
+        "{} passed, {} failed\n",
+        report.passed, report.failed
+    ));
+    out
+}
+
+/// The report as JUnit XML: a test suite per file, a test case per request
+pub fn to_junit(report: &RunReport) -> String {
+    let mut suites: Vec<(&str, Vec<&RequestResult>)> = Vec::new();
+    for result in &report.results {
+        match suites.last_mut() {
+            Some((file, results)) if *file == result.file => results.push(result),
+            _ => suites.push((&result.file, vec![result])),
+        }
+    }
+
+    let seconds = |ms: u64| format!("{:.3}", ms as f64 / 1000.0);
+    let total: u64 = report.results.iter().map(|r| r.time).sum();
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str(&format!(
+        "<testsuites name=\"kvile\" tests=\"{}\" failures=\"{}\" time=\"{}\">\n",
+        report.results.len(),
+        report.failed,
+        seconds(total)
+    ));
+    for (file, results) in suites {
+        let failures = results.iter().filter(|r| !r.passed()).count();
+        let time: u64 = results.iter().map(|r| r.time).sum();
+        out.push_str(&format!(
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" time=\"{}\">\n",
+            xml_escape(file),
+            results.len(),
+            failures,
+            seconds(time)
+        ));
+        for result in results {
+            out.push_str(&format!(
+                "    <testcase name=\"{}\" classname=\"{}\" time=\"{}\"",
+                xml_escape(&result.name),
+                xml_escape(file),
+                seconds(result.time)
+            ));
+            match &result.error {
+                Some(error) => out.push_str(&format!(
+                    ">\n      <failure message=\"{}\"/>\n    </testcase>\n",
+                    xml_escape(error)
+                )),
+                None => out.push_str("/>\n"),
+            }
+        }
+        out.push_str("  </testsuite>\n");
+    }
+    out.push_str("</testsuites>\n");
+    out
+}
+
+fn xml_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            // Not allowed in XML 1.0 at all
+            c if c.is_control() && !matches!(c, '\n' | '\r' | '\t') => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(file: &str, name: &str, error: Option<&str>) -> RequestResult {
+        RequestResult {
+            file: file.to_string(),
+            name: name.to_string(),
+            line: 1,
+            method: "GET".to_string(),
+            url: None,
+            status: error.is_none().then_some(200),
+            time: 1500,
+            error: error.map(str::to_string),
+            scripts_skipped: false,
+        }
+    }
+
+    #[test]
+    fn test_to_junit() {
+        let mut report = RunReport::default();
+        report.push(result("api/users.http", "list", None));
+        report.push(result(
+            "api/users.http",
+            "GET {{host}}/a&b",
+            Some("Bad <url>"),
+        ));
+        report.push(result("orders.http", "create", None));
+
+        assert_eq!(
+            to_junit(&report),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites name="kvile" tests="3" failures="1" time="4.500">
+  <testsuite name="api/users.http" tests="2" failures="1" time="3.000">
+    <testcase name="list" classname="api/users.http" time="1.500"/>
+    <testcase name="GET {{host}}/a&amp;b" classname="api/users.http" time="1.500">
+      <failure message="Bad &lt;url&gt;"/>
+    </testcase>
+  </testsuite>
+  <testsuite name="orders.http" tests="1" failures="0" time="1.500">
+    <testcase name="create" classname="orders.http" time="1.500"/>
+  </testsuite>
+</testsuites>
+"#
+        );
+        assert!(!report.success());
+    }
+
+    #[tokio::test]
+    async fn test_run_reports_unresolved_requests() {
+        let dir = tempfile::Builder::new().prefix("ws").tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir(root.join("api")).unwrap();
+        std::fs::write(
+            root.join("api/users.http"),
+            "### list\nGET {{host}}/users\n\n### auth\n# @auth oidc corp\nGET http://localhost/\n",
+        )
+        .unwrap();
+        std::fs::write(root.join("notes.txt"), "not run").unwrap();
+
+        let report = run(&RunOptions {
+            paths: vec![root.to_path_buf()],
+            workspace: root.to_path_buf(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let errors: Vec<_> = report
+            .results
+            .iter()
+            .map(|r| {
+                (
+                    r.file.as_str(),
+                    r.name.as_str(),
+                    r.error.as_deref().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                ("api/users.http", "list", "Undefined variables: host"),
+                (
+                    "api/users.http",
+                    "auth",
+                    "OIDC auth needs the kvile app to sign in"
+                ),
+            ]
+        );
+        assert_eq!((report.passed, report.failed), (0, 2));
+    }
+
+    #[tokio::test]
+    async fn test_run_chains_named_responses() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // Answers a login, then echoes the request line of the next request
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            for body in [
+                r#"{"tokens":[{"kind":"refresh","id":1},{"kind":"access","id":42}]}"#,
+                "",
+            ] {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut received = Vec::new();
+                let mut buf = [0; 1024];
+                while !received.ends_with(b"\r\n\r\n") {
+                    let n = socket.read(&mut buf).await.unwrap();
+                    if n == 0 {
+                        break;
+                    }
+                    received.extend_from_slice(&buf[..n]);
+                }
+                let status = match String::from_utf8_lossy(&received).contains("/items/42 ") {
+                    true => "200 OK",
+                    false if body.is_empty() => "404 Not Found",
+                    false => "200 OK",
+                };
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(
+            root.join("chain.http"),
+            format!(
+                "### item\n# @depends-on login\nGET http://{addr}/items/{{{{login.response.body.$.tokens[?@.kind == 'access'].id}}}}\n\n\
+                 ### login\nPOST http://{addr}/login\n"
+            ),
+        )
+        .unwrap();
+        let report = run(&RunOptions {
+            paths: vec![root.join("chain.http")],
+            workspace: root.to_path_buf(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let item = &report.results[1];
+        assert_eq!(item.name, "item");
+        assert_eq!(item.url, Some(format!("http://{}/items/42", addr)));
+        assert_eq!(item.status, Some(200));
+        assert!(report.success());
+    }
+}
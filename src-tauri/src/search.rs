@@ -0,0 +1,356 @@
+//! Searching the .http and .rest files of a workspace, like grep, with each
+//! match placed in the request it falls in
+
+use crate::files::{is_http_file_name, list_workspace_files, ListOptions};
+use crate::ignores::WorkspaceIgnore;
+use crate::parser::parse_http_content;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Most matching lines returned when the options don't say
+pub const DEFAULT_MAX_RESULTS: usize = 1000;
+
+/// Cancel flags of running searches, by search id
+static RUNNING: Mutex<Option<HashMap<String, Arc<AtomicBool>>>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SearchOptions {
+    /// Treat the query as a regular expression rather than literal text
+    pub regex: bool,
+    pub case_sensitive: bool,
+    /// Most matching lines to return, [`DEFAULT_MAX_RESULTS`] by default
+    pub max_results: Option<usize>,
+}
+
+/// A line matching the query
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SearchMatch {
+    pub path: String,
+    /// 1-based
+    pub line: usize,
+    /// The whole line
+    pub text: String,
+    /// Character offsets of each match in `text`, end exclusive
+    pub ranges: Vec<[usize; 2]>,
+    /// The request the line belongs to, from its `### title` line up to the
+    /// next request. `None` for lines before the first request.
+    pub request_index: Option<usize>,
+    pub request_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SearchResults {
+    /// By path, then line
+    pub matches: Vec<SearchMatch>,
+    /// More lines matched than the result cap
+    pub truncated: bool,
+    /// The search was cancelled; `matches` holds what was found until then
+    pub cancelled: bool,
+    pub files_searched: usize,
+    /// Files and directories that could not be read
+    pub warnings: Vec<String>,
+}
+
+/// Start tracking a search so [`cancel_search`] can stop it. A running
+/// search with the same id is cancelled, as when the query is retyped.
+pub fn register_search(id: &str) -> Arc<AtomicBool> {
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let previous = RUNNING
+        .lock()
+        .unwrap()
+        .get_or_insert_with(HashMap::new)
+        .insert(id.to_string(), cancelled.clone());
+    if let Some(previous) = previous {
+        previous.store(true, Ordering::Relaxed);
+    }
+    cancelled
+}
+
+/// Stop tracking a finished search, unless a newer one took its id
+pub fn finish_search(id: &str, cancelled: &Arc<AtomicBool>) {
+    if let Some(running) = RUNNING.lock().unwrap().as_mut() {
+        if running.get(id).is_some_and(|c| Arc::ptr_eq(c, cancelled)) {
+            running.remove(id);
+        }
+    }
+}
+
+/// Cancel a running search. Returns whether one was running.
+pub fn cancel_search(id: &str) -> bool {
+    let cancelled = RUNNING
+        .lock()
+        .unwrap()
+        .as_mut()
+        .and_then(|running| running.remove(id));
+    match cancelled {
+        Some(cancelled) => {
+            cancelled.store(true, Ordering::Relaxed);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Search the .http and .rest files in `workspace` that `ignore` doesn't
+/// exclude, on a blocking thread, until `cancelled` is set
+pub async fn search_workspace(
+    workspace: &str,
+    ignore: &WorkspaceIgnore,
+    query: &str,
+    options: &SearchOptions,
+    cancelled: Arc<AtomicBool>,
+) -> Result<SearchResults, String> {
+    let pattern = RegexBuilder::new(&if options.regex {
+        query.to_string()
+    } else {
+        regex::escape(query)
+    })
+    .case_insensitive(!options.case_sensitive)
+    // Files are checked whole before their lines are, so `^` and `$` have
+    // to match at line boundaries for both
+    .multi_line(true)
+    .build()
+    .map_err(|e| format!("Invalid search pattern: {}", e))?;
+    if query.is_empty() {
+        return Ok(SearchResults::default());
+    }
+
+    let listing = list_workspace_files(workspace, ignore, &ListOptions::default()).await?;
+    let files: Vec<String> = listing
+        .files
+        .into_iter()
+        .filter(|file| is_http_file_name(&file.name))
+        .map(|file| file.path)
+        .collect();
+    let max_results = options.max_results.unwrap_or(DEFAULT_MAX_RESULTS);
+    let mut results = tokio::task::spawn_blocking(move || {
+        search_files(&files, &pattern, max_results, &cancelled)
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+    results.warnings.splice(0..0, listing.warnings);
+    Ok(results)
+}
+
+fn search_files(
+    files: &[String],
+    pattern: &Regex,
+    max_results: usize,
+    cancelled: &AtomicBool,
+) -> SearchResults {
+    let mut results = SearchResults::default();
+    for path in files {
+        if cancelled.load(Ordering::Relaxed) {
+            results.cancelled = true;
+            break;
+        }
+        let content = match std::fs::read_to_string(Path::new(path)) {
+            Ok(content) => content,
+            Err(e) => {
+                results
+                    .warnings
+                    .push(format!("Failed to read {}: {}", path, e));
+                continue;
+            }
+        };
+        results.files_searched += 1;
+        if !pattern.is_match(&content) {
+            continue;
+        }
+
+        // Requests by the line their block starts on
+        let requests = parse_http_content(&content).unwrap_or_default();
+        for (i, line) in content.lines().enumerate() {
+            let ranges: Vec<[usize; 2]> = pattern
+                .find_iter(line)
+                .filter(|m| !m.is_empty())
+                .map(|m| {
+                    let start = line[..m.start()].chars().count();
+                    [start, start + m.as_str().chars().count()]
+                })
+                .collect();
+            if ranges.is_empty() {
+                continue;
+            }
+            if results.matches.len() == max_results {
+                results.truncated = true;
+                return results;
+            }
+            let request_index = requests
+                .iter()
+                .rposition(|request| request.line_number <= i + 1);
+            results.matches.push(SearchMatch {
+                path: path.clone(),
+                line: i + 1,
+                text: line.to_string(),
+                ranges,
+                request_index,
+                request_name: request_index.and_then(|index| requests[index].name.clone()),
+            });
+        }
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("billing")).unwrap();
+        std::fs::create_dir_all(root.join("build")).unwrap();
+        std::fs::write(
+            root.join("billing/invoices.http"),
+            "@host = https://api.test\n\n### list-invoices\nGET {{host}}/v2/invoices\n\n### create-invoice\nPOST {{host}}/v2/Invoices\nContent-Type: application/json\n\n{\"amount\": 10}\n",
+        )
+        .unwrap();
+        std::fs::write(
+            root.join("users.rest"),
+            "GET https://api.test/v1/users\n###\nGET https://api.test/v2/invoices?user=1\n",
+        )
+        .unwrap();
+        std::fs::write(root.join("notes.txt"), "/v2/invoices").unwrap();
+        std::fs::write(
+            root.join("build/old.http"),
+            "GET https://api.test/v2/invoices\n",
+        )
+        .unwrap();
+        std::fs::write(root.join(".gitignore"), "build/\n").unwrap();
+        dir
+    }
+
+    async fn search(dir: &Path, query: &str, options: SearchOptions) -> SearchResults {
+        let workspace = dir.to_string_lossy().to_string();
+        search_workspace(
+            &workspace,
+            &WorkspaceIgnore::load(dir),
+            query,
+            &options,
+            Arc::new(AtomicBool::new(false)),
+        )
+        .await
+        .unwrap()
+    }
+
+    fn lines(results: &SearchResults, dir: &Path) -> Vec<(String, usize)> {
+        results
+            .matches
+            .iter()
+            .map(|m| {
+                let relative = Path::new(&m.path).strip_prefix(dir).unwrap();
+                (relative.to_string_lossy().replace('\\', "/"), m.line)
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_search_literal() {
+        let dir = fixture();
+        let options = SearchOptions {
+            case_sensitive: true,
+            ..Default::default()
+        };
+        let results = search(dir.path(), "/v2/invoices", options).await;
+
+        // Only .http and .rest files the ignore files don't exclude
+        assert_eq!(results.files_searched, 2);
+        assert_eq!(
+            lines(&results, dir.path()),
+            vec![
+                ("billing/invoices.http".to_string(), 4),
+                ("users.rest".to_string(), 3)
+            ]
+        );
+        let first = &results.matches[0];
+        assert_eq!(first.text, "GET {{host}}/v2/invoices");
+        assert_eq!(first.ranges, vec![[12, 24]]);
+        assert_eq!(first.request_index, Some(0));
+        assert_eq!(first.request_name.as_deref(), Some("list-invoices"));
+        let second = &results.matches[1];
+        assert_eq!(second.request_index, Some(1));
+        assert_eq!(second.request_name, None);
+
+        // Regex characters are literal
+        let results = search(dir.path(), "{{host}}", Default::default()).await;
+        assert_eq!(results.matches.len(), 2);
+        let results = search(dir.path(), "@host =", Default::default()).await;
+        assert_eq!(results.matches[0].request_index, None);
+    }
+
+    #[tokio::test]
+    async fn test_search_case_insensitive_and_regex() {
+        let dir = fixture();
+        let results = search(dir.path(), "/V2/INVOICES", Default::default()).await;
+        assert_eq!(
+            lines(&results, dir.path()),
+            vec![
+                ("billing/invoices.http".to_string(), 4),
+                ("billing/invoices.http".to_string(), 7),
+                ("users.rest".to_string(), 3)
+            ]
+        );
+        assert_eq!(
+            results.matches[1].request_name.as_deref(),
+            Some("create-invoice")
+        );
+
+        let regex = SearchOptions {
+            regex: true,
+            case_sensitive: true,
+            ..Default::default()
+        };
+        let results = search(dir.path(), r"^(GET|POST) \S+/v\d/[A-Z]", regex.clone()).await;
+        assert_eq!(
+            lines(&results, dir.path()),
+            vec![("billing/invoices.http".to_string(), 7)]
+        );
+
+        let capped = SearchOptions {
+            max_results: Some(2),
+            ..regex.clone()
+        };
+        let results = search(dir.path(), r"https?://", capped).await;
+        assert_eq!(results.matches.len(), 2);
+        assert!(results.truncated);
+
+        let workspace = dir.path().to_string_lossy().to_string();
+        let error = search_workspace(
+            &workspace,
+            &WorkspaceIgnore::load(dir.path()),
+            "(",
+            &regex,
+            Arc::new(AtomicBool::new(false)),
+        )
+        .await
+        .unwrap_err();
+        assert!(error.starts_with("Invalid search pattern"));
+    }
+
+    #[tokio::test]
+    async fn test_search_cancellation() {
+        let dir = fixture();
+        let first = register_search("test-search");
+        let second = register_search("test-search");
+        // A new search under the same id cancels the old one
+        assert!(first.load(Ordering::Relaxed));
+
+        let workspace = dir.path().to_string_lossy().to_string();
+        let ignore = WorkspaceIgnore::load(dir.path());
+        let results = search_workspace(&workspace, &ignore, "GET", &Default::default(), first)
+            .await
+            .unwrap();
+        assert!(results.cancelled);
+        assert!(results.matches.is_empty());
+
+        assert!(cancel_search("test-search"));
+        assert!(second.load(Ordering::Relaxed));
+        assert!(!cancel_search("test-search"));
+    }
+}
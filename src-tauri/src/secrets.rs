@@ -0,0 +1,176 @@
+//! Secret environment values kept in the platform keychain.
+//!
+//! Env files only hold a `{"$secret": true}` marker for these variables; the
+//! value lives in the keychain under `kvile/<workspace>/<env>/<name>`.
+
+use keyring::Entry;
+
+/// Keychain service all kvile secrets are stored under
+const SERVICE: &str = "kvile";
+
+/// Key of the marker object written to env files in place of a secret
+pub const SECRET_MARKER_KEY: &str = "$secret";
+
+/// The `{"$secret": true}` marker recorded in env files
+pub fn marker() -> serde_json::Value {
+    serde_json::json!({ SECRET_MARKER_KEY: true })
+}
+
+/// Whether an env file value is a secret marker
+pub fn is_marker(value: &serde_json::Value) -> bool {
+    value
+        .as_object()
+        .is_some_and(|obj| obj.len() == 1 && obj.get(SECRET_MARKER_KEY) == Some(&true.into()))
+}
+
+fn entry(workspace: &str, environment: &str, name: &str) -> keyring::Result<Entry> {
+    Entry::new(SERVICE, &format!("{}/{}/{}", workspace, environment, name))
+}
+
+/// Read a secret from the keychain
+pub fn get_secret(workspace: &str, environment: &str, name: &str) -> Result<String, String> {
+    entry(workspace, environment, name)
+        .and_then(|entry| entry.get_password())
+        .map_err(|e| format!("Failed to read '{}' from the keychain: {}", name, e))
+}
+
+/// Read a secret from the keychain, or None when it has no entry
+pub fn find_secret(
+    workspace: &str,
+    environment: &str,
+    name: &str,
+) -> Result<Option<String>, String> {
+    match entry(workspace, environment, name).and_then(|entry| entry.get_password()) {
+        Ok(value) => Ok(Some(value)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(format!(
+            "Failed to read '{}' from the keychain: {}",
+            name, e
+        )),
+    }
+}
+
+/// Store a secret in the keychain, replacing any previous value
+pub fn set_secret(
+    workspace: &str,
+    environment: &str,
+    name: &str,
+    value: &str,
+) -> Result<(), String> {
+    entry(workspace, environment, name)
+        .and_then(|entry| entry.set_password(value))
+        .map_err(|e| format!("Failed to store '{}' in the keychain: {}", name, e))
+}
+
+/// Remove a secret from the keychain. A missing entry is not an error.
+pub fn delete_secret(workspace: &str, environment: &str, name: &str) -> Result<(), String> {
+    match entry(workspace, environment, name).and_then(|entry| entry.delete_credential()) {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(format!(
+            "Failed to remove '{}' from the keychain: {}",
+            name, e
+        )),
+    }
+}
+
+/// In-memory keychain for tests. Secrets for workspaces passed to [`lock`]
+/// fail as if no keychain were available.
+#[cfg(test)]
+pub mod memory {
+    use keyring::credential::{Credential, CredentialApi, CredentialBuilderApi};
+    use std::any::Any;
+    use std::collections::{HashMap, HashSet};
+    use std::sync::{Mutex, Once};
+
+    static SECRETS: Mutex<Option<HashMap<String, Vec<u8>>>> = Mutex::new(None);
+    static LOCKED: Mutex<Option<HashSet<String>>> = Mutex::new(None);
+
+    /// Route keychain access to the in-memory store
+    pub fn install() {
+        static INSTALL: Once = Once::new();
+        INSTALL.call_once(|| keyring::set_default_credential_builder(Box::new(Builder)));
+    }
+
+    /// Make the keychain unavailable for one workspace
+    pub fn lock(workspace: &str) {
+        LOCKED
+            .lock()
+            .unwrap()
+            .get_or_insert_with(HashSet::new)
+            .insert(workspace.to_string());
+    }
+
+    /// Raw keychain value, bypassing the secrets API
+    pub fn stored(workspace: &str, environment: &str, name: &str) -> Option<String> {
+        let key = format!("{}/{}/{}", workspace, environment, name);
+        SECRETS
+            .lock()
+            .unwrap()
+            .as_ref()
+            .and_then(|secrets| secrets.get(&key))
+            .map(|bytes| String::from_utf8(bytes.clone()).unwrap())
+    }
+
+    #[derive(Debug)]
+    struct Builder;
+
+    impl CredentialBuilderApi for Builder {
+        fn build(
+            &self,
+            _target: Option<&str>,
+            _service: &str,
+            user: &str,
+        ) -> keyring::Result<Box<Credential>> {
+            let locked = LOCKED.lock().unwrap().as_ref().is_some_and(|locked| {
+                locked
+                    .iter()
+                    .any(|ws| user.starts_with(&format!("{}/", ws)))
+            });
+            if locked {
+                return Err(keyring::Error::NoStorageAccess("keychain locked".into()));
+            }
+            Ok(Box::new(Memory(user.to_string())))
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    #[derive(Debug)]
+    struct Memory(String);
+
+    impl CredentialApi for Memory {
+        fn set_secret(&self, secret: &[u8]) -> keyring::Result<()> {
+            SECRETS
+                .lock()
+                .unwrap()
+                .get_or_insert_with(HashMap::new)
+                .insert(self.0.clone(), secret.to_vec());
+            Ok(())
+        }
+
+        fn get_secret(&self) -> keyring::Result<Vec<u8>> {
+            SECRETS
+                .lock()
+                .unwrap()
+                .as_ref()
+                .and_then(|secrets| secrets.get(&self.0).cloned())
+                .ok_or(keyring::Error::NoEntry)
+        }
+
+        fn delete_credential(&self) -> keyring::Result<()> {
+            SECRETS
+                .lock()
+                .unwrap()
+                .as_mut()
+                .and_then(|secrets| secrets.remove(&self.0))
+                .map(|_| ())
+                .ok_or(keyring::Error::NoEntry)
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+}
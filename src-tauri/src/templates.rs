@@ -0,0 +1,306 @@
+//! Templates for new .http files: built-in ones embedded in the app, and a
+//! workspace's own in `.kvile/templates/*.http`.
+//!
+//! A template is an .http file whose leading comments name it and declare
+//! the variables it needs:
+//!
+//! ```text
+//! # @template REST CRUD for a resource
+//! # @template-var resource Collection path segment, e.g. users
+//! ```
+//!
+//! Declared variables are filled in wherever the template has
+//! `{{resource}}`. Other placeholders, such as `{{host}}`, are left for the
+//! environment to provide when the request is sent.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Directory, relative to the workspace, holding the workspace's templates
+pub const WORKSPACE_TEMPLATES_DIR: &str = ".kvile/templates";
+
+const NAME_METADATA: &str = "# @template ";
+const VARIABLE_METADATA: &str = "# @template-var ";
+
+const BUILTIN: [(&str, &str); 4] = [
+    ("rest-crud", include_str!("templates/rest-crud.http")),
+    (
+        "graphql-query",
+        include_str!("templates/graphql-query.http"),
+    ),
+    (
+        "oauth-request",
+        include_str!("templates/oauth-request.http"),
+    ),
+    (
+        "multipart-upload",
+        include_str!("templates/multipart-upload.http"),
+    ),
+];
+
+/// A variable a template needs filled in
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TemplateVariable {
+    pub name: String,
+    pub description: String,
+}
+
+/// A template that new files can be created from
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TemplateInfo {
+    pub id: String,
+    pub name: String,
+    /// Variables that must all be given to create a file from it
+    pub variables: Vec<TemplateVariable>,
+    /// Embedded in the app rather than from the workspace
+    pub builtin: bool,
+}
+
+struct Template {
+    info: TemplateInfo,
+    /// The content without its template comments
+    body: String,
+}
+
+impl Template {
+    fn parse(id: &str, content: &str, builtin: bool) -> Self {
+        let mut info = TemplateInfo {
+            id: id.to_string(),
+            name: id.to_string(),
+            variables: Vec::new(),
+            builtin,
+        };
+        let mut lines = content.lines().peekable();
+        while let Some(line) = lines.peek() {
+            let line = line.trim();
+            if let Some(name) = line.strip_prefix(NAME_METADATA) {
+                info.name = name.trim().to_string();
+            } else if let Some(variable) = line.strip_prefix(VARIABLE_METADATA) {
+                let (name, description) = variable.trim().split_once(' ').unwrap_or((variable, ""));
+                info.variables.push(TemplateVariable {
+                    name: name.trim().to_string(),
+                    description: description.trim().to_string(),
+                });
+            } else if !line.is_empty() {
+                break;
+            }
+            lines.next();
+        }
+        let body: Vec<&str> = lines.collect();
+        Template {
+            info,
+            body: body.join("\n") + "\n",
+        }
+    }
+
+    /// The template's content with its variables filled in from `vars`.
+    /// Fails naming the variables `vars` has no value for.
+    fn render(&self, vars: &HashMap<String, String>) -> Result<String, String> {
+        let missing: Vec<&str> = self
+            .info
+            .variables
+            .iter()
+            .filter(|v| vars.get(&v.name).is_none_or(|value| value.is_empty()))
+            .map(|v| v.name.as_str())
+            .collect();
+        if !missing.is_empty() {
+            return Err(format!(
+                "Missing template variables: {}",
+                missing.join(", ")
+            ));
+        }
+        let mut content = self.body.clone();
+        for variable in &self.info.variables {
+            content = content.replace(&format!("{{{{{}}}}}", variable.name), &vars[&variable.name]);
+        }
+        Ok(content)
+    }
+}
+
+/// The built-in templates and the workspace's, sorted by id. A workspace
+/// template with a built-in's id replaces it.
+fn load(workspace: Option<&Path>) -> Result<Vec<Template>, String> {
+    let mut templates: Vec<Template> = BUILTIN
+        .iter()
+        .map(|(id, content)| Template::parse(id, content, true))
+        .collect();
+    if let Some(workspace) = workspace {
+        let dir = workspace.join(WORKSPACE_TEMPLATES_DIR);
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(sorted(templates)),
+            Err(e) => return Err(format!("Failed to read {}: {}", dir.display(), e)),
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("http") {
+                continue;
+            }
+            let Some(id) = path.file_stem().map(|s| s.to_string_lossy().to_string()) else {
+                continue;
+            };
+            let content = std::fs::read_to_string(&path)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            templates.retain(|t| t.info.id != id);
+            templates.push(Template::parse(&id, &content, false));
+        }
+    }
+    Ok(sorted(templates))
+}
+
+fn sorted(mut templates: Vec<Template>) -> Vec<Template> {
+    templates.sort_by(|a, b| a.info.id.cmp(&b.info.id));
+    templates
+}
+
+/// Templates new files can be created from in `workspace`, sorted by id
+pub fn list_templates(workspace: Option<&Path>) -> Result<Vec<TemplateInfo>, String> {
+    Ok(load(workspace)?.into_iter().map(|t| t.info).collect())
+}
+
+/// The content of a new file from a template, with its variables filled in
+pub fn render_template(
+    workspace: &Path,
+    template_id: &str,
+    vars: &HashMap<String, String>,
+) -> Result<String, String> {
+    load(Some(workspace))?
+        .into_iter()
+        .find(|t| t.info.id == template_id)
+        .ok_or_else(|| format!("Unknown template: {}", template_id))?
+        .render(vars)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{execution_order, parse_http_content};
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_builtin_templates_parse() {
+        let dir = tempfile::tempdir().unwrap();
+        for info in list_templates(None).unwrap() {
+            let values: HashMap<String, String> = info
+                .variables
+                .iter()
+                .map(|v| (v.name.clone(), format!("sample-{}", v.name)))
+                .collect();
+            let content = render_template(dir.path(), &info.id, &values).unwrap();
+            assert!(!content.contains("@template"), "{}", info.id);
+            for variable in &info.variables {
+                assert!(
+                    !content.contains(&format!("{{{{{}}}}}", variable.name)),
+                    "{}: {{{{{}}}}} left in",
+                    info.id,
+                    variable.name
+                );
+            }
+
+            let requests = parse_http_content(&content)
+                .unwrap_or_else(|e| panic!("{} doesn't parse: {}", info.id, e));
+            assert!(!requests.is_empty(), "{}", info.id);
+            execution_order(&requests).unwrap_or_else(|e| panic!("{}: {}", info.id, e));
+        }
+    }
+
+    #[test]
+    fn test_builtin_template_contents() {
+        let templates = list_templates(None).unwrap();
+        let ids: Vec<&str> = templates.iter().map(|t| t.id.as_str()).collect();
+        assert_eq!(
+            ids,
+            vec![
+                "graphql-query",
+                "multipart-upload",
+                "oauth-request",
+                "rest-crud"
+            ]
+        );
+        let crud = &templates[3];
+        assert_eq!(crud.name, "REST CRUD for a resource");
+        assert_eq!(crud.variables[0].name, "resource");
+        assert_eq!(
+            crud.variables[0].description,
+            "Collection path segment, e.g. users"
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let content = render_template(
+            dir.path(),
+            "rest-crud",
+            &vars(&[("resource", "users"), ("base_url", "https://api.test")]),
+        )
+        .unwrap();
+        let requests = parse_http_content(&content).unwrap();
+        let names: Vec<&str> = requests.iter().filter_map(|r| r.name.as_deref()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "list-users",
+                "create-users",
+                "get-users",
+                "update-users",
+                "delete-users"
+            ]
+        );
+        assert_eq!(requests[2].url, "https://api.test/users/{{id}}");
+        assert!(requests[1].post_script.is_some());
+
+        let content = render_template(
+            dir.path(),
+            "oauth-request",
+            &vars(&[("url", "https://api.test/me"), ("auth_config", "keycloak")]),
+        )
+        .unwrap();
+        let requests = parse_http_content(&content).unwrap();
+        assert_eq!(requests[0].metadata["auth"], "oidc keycloak");
+    }
+
+    #[test]
+    fn test_workspace_templates() {
+        let dir = tempfile::tempdir().unwrap();
+        let templates_dir = dir.path().join(WORKSPACE_TEMPLATES_DIR);
+        std::fs::create_dir_all(&templates_dir).unwrap();
+        std::fs::write(
+            templates_dir.join("health.http"),
+            "# @template Health check\n# @template-var service Service name\n\n### {{service}}\nGET {{host}}/{{service}}/health\n",
+        )
+        .unwrap();
+        std::fs::write(
+            templates_dir.join("graphql-query.http"),
+            "### Ours\nPOST {{host}}/graphql\n",
+        )
+        .unwrap();
+        std::fs::write(templates_dir.join("notes.txt"), "not a template").unwrap();
+
+        let templates = list_templates(Some(dir.path())).unwrap();
+        let graphql = templates.iter().find(|t| t.id == "graphql-query").unwrap();
+        assert!(!graphql.builtin);
+        assert_eq!(graphql.name, "graphql-query");
+        let health = templates.iter().find(|t| t.id == "health").unwrap();
+        assert_eq!(health.name, "Health check");
+        assert_eq!(templates.len(), 5);
+
+        // Only declared variables are filled in
+        assert_eq!(
+            render_template(dir.path(), "health", &vars(&[("service", "orders")])).unwrap(),
+            "### orders\nGET {{host}}/orders/health\n"
+        );
+        assert_eq!(
+            render_template(dir.path(), "health", &vars(&[("service", "")])),
+            Err("Missing template variables: service".to_string())
+        );
+        assert_eq!(
+            render_template(dir.path(), "missing", &HashMap::new()),
+            Err("Unknown template: missing".to_string())
+        );
+    }
+}
@@ -0,0 +1,492 @@
+//! Cached OAuth 2.0 / OIDC tokens, refreshed before they expire.
+//!
+//! Tokens are kept per workspace under the id of the auth config that
+//! obtained them, in the keychain under `kvile/<workspace>/$auth/<auth id>`.
+//! Where no keychain is available they go to a file encrypted with a key
+//! derived from this machine instead. They are never written to the history
+//! database or logged. A token close to expiry is refreshed with its refresh
+//! token; only when that is not possible does the user have to log in again.
+
+use crate::oidc::{fetch_discovery, refresh_access_token, OidcConfig, TokenResponse};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+
+/// Error prefix when no usable token is cached and the user must log in again
+pub const REAUTH_REQUIRED_ERROR: &str = "reauth_required";
+
+/// Seconds before expiry a token is refreshed when no skew is given
+pub const DEFAULT_SKEW_SECS: i64 = 60;
+
+/// Warning returned when tokens had to be stored outside the keychain
+pub const KEYCHAIN_FALLBACK_WARNING: &str =
+    "No keychain is available; tokens are stored in a file encrypted with a key derived from this machine";
+
+/// Keychain environment cached tokens are stored under
+const KEYCHAIN_ENVIRONMENT: &str = "$auth";
+
+/// A token response with when its access token expires
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedToken {
+    #[serde(flatten)]
+    pub token: TokenResponse,
+    /// Unix time in seconds; None when the provider did not say
+    pub expires_at: Option<i64>,
+}
+
+impl CachedToken {
+    fn new(token: TokenResponse, now: i64) -> Self {
+        let expires_at = token.expires_in.map(|secs| now + secs as i64);
+        Self { token, expires_at }
+    }
+
+    /// Whether the access token expires within `skew` seconds of `now`
+    fn expires_within(&self, now: i64, skew: i64) -> bool {
+        self.expires_at.is_some_and(|at| at - skew <= now)
+    }
+}
+
+/// Where cached tokens live: the keychain, or encrypted files in
+/// `fallback_dir` on machines without one
+#[derive(Debug, Clone)]
+pub struct TokenStore {
+    fallback_dir: PathBuf,
+}
+
+impl Default for TokenStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TokenStore {
+    /// Store falling back to the app data directory
+    pub fn new() -> Self {
+        let data_dir = dirs::data_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("kvile");
+        Self::with_fallback_dir(data_dir.join("tokens"))
+    }
+
+    pub fn with_fallback_dir(fallback_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            fallback_dir: fallback_dir.into(),
+        }
+    }
+
+    /// Cache a token obtained for an auth config. Returns
+    /// [`KEYCHAIN_FALLBACK_WARNING`] alongside it when the keychain could
+    /// not be used.
+    pub fn store_tokens(
+        &self,
+        workspace: &str,
+        auth_id: &str,
+        token: TokenResponse,
+    ) -> Result<(CachedToken, Option<&'static str>), String> {
+        let cached = CachedToken::new(token, Utc::now().timestamp());
+        let warning = self.save(workspace, auth_id, &cached)?;
+        Ok((cached, warning))
+    }
+
+    /// The token cached for an auth config, whether or not it has expired
+    pub fn load_tokens(
+        &self,
+        workspace: &str,
+        auth_id: &str,
+    ) -> Result<Option<CachedToken>, String> {
+        let json = match crate::secrets::find_secret(workspace, KEYCHAIN_ENVIRONMENT, auth_id) {
+            Ok(Some(json)) => json,
+            Ok(None) => match self.read_fallback(workspace, auth_id)? {
+                Some(json) => json,
+                None => return Ok(None),
+            },
+            // Without a keychain, the fallback file is all there is
+            Err(e) => match self.read_fallback(workspace, auth_id)? {
+                Some(json) => json,
+                None => return Err(e),
+            },
+        };
+        serde_json::from_str(&json)
+            .map(Some)
+            .map_err(|e| format!("Failed to parse cached token: {}", e))
+    }
+
+    /// Forget the token cached for an auth config
+    pub fn delete_tokens(&self, workspace: &str, auth_id: &str) -> Result<(), String> {
+        let removed_file = self.remove_fallback(workspace, auth_id)?;
+        match crate::secrets::delete_secret(workspace, KEYCHAIN_ENVIRONMENT, auth_id) {
+            // A token only ever in the file is gone even without a keychain
+            Err(_) if removed_file => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Forget the cached token before logging out at the IdP, returning the
+    /// id token to send as the hint: `id_token_hint` if given, else the
+    /// cached one
+    pub fn end_session(
+        &self,
+        workspace: &str,
+        auth_id: &str,
+        id_token_hint: Option<String>,
+    ) -> Result<Option<String>, String> {
+        let cached_id_token = self
+            .load_tokens(workspace, auth_id)
+            .ok()
+            .flatten()
+            .and_then(|cached| cached.token.id_token);
+        self.delete_tokens(workspace, auth_id)?;
+        Ok(id_token_hint.or(cached_id_token))
+    }
+
+    /// The cached token for an auth config, refreshed first when it expires
+    /// within `skew` seconds. Fails with an error starting with
+    /// [`REAUTH_REQUIRED_ERROR`] when there is no token or it can't be
+    /// refreshed.
+    pub async fn get_valid_token(
+        &self,
+        workspace: &str,
+        auth_id: &str,
+        config: &OidcConfig,
+        skew: i64,
+    ) -> Result<CachedToken, String> {
+        let cached = self.load_tokens(workspace, auth_id)?.ok_or_else(|| {
+            format!(
+                "{}: No token cached for '{}'",
+                REAUTH_REQUIRED_ERROR, auth_id
+            )
+        })?;
+        let now = Utc::now().timestamp();
+        if !cached.expires_within(now, skew) {
+            return Ok(cached);
+        }
+
+        let refresh_token = cached.token.refresh_token.clone().ok_or_else(|| {
+            format!(
+                "{}: Token for '{}' expired and has no refresh token",
+                REAUTH_REQUIRED_ERROR, auth_id
+            )
+        })?;
+        let discovery = match config.issuer {
+            Some(ref issuer) => Some(fetch_discovery(issuer).await?),
+            None => None,
+        };
+        let mut token = refresh_access_token(config, discovery.as_ref(), &refresh_token)
+            .await
+            .map_err(|e| format!("{}: {}", REAUTH_REQUIRED_ERROR, e))?;
+        // Providers that don't rotate refresh tokens leave them out
+        token.refresh_token = token.refresh_token.or(Some(refresh_token));
+        token.id_token = token.id_token.or(cached.token.id_token);
+
+        let refreshed = CachedToken::new(token, now);
+        self.save(workspace, auth_id, &refreshed)?;
+        Ok(refreshed)
+    }
+
+    /// Save to the keychain, or the fallback file when it is unavailable
+    fn save(
+        &self,
+        workspace: &str,
+        auth_id: &str,
+        cached: &CachedToken,
+    ) -> Result<Option<&'static str>, String> {
+        let json = serde_json::to_string(cached)
+            .map_err(|e| format!("Failed to serialize token: {}", e))?;
+        if crate::secrets::set_secret(workspace, KEYCHAIN_ENVIRONMENT, auth_id, &json).is_ok() {
+            // Don't leave an older copy behind from when there was no keychain
+            self.remove_fallback(workspace, auth_id)?;
+            return Ok(None);
+        }
+
+        let content = crate::encryption::encrypt(&json, &machine_passphrase())
+            .map_err(|e| format!("Failed to encrypt token: {}", e))?;
+        std::fs::create_dir_all(&self.fallback_dir)
+            .map_err(|e| format!("Failed to create token directory: {}", e))?;
+        std::fs::write(self.fallback_path(workspace, auth_id), content)
+            .map_err(|e| format!("Failed to save token: {}", e))?;
+        tracing::warn!("{}", KEYCHAIN_FALLBACK_WARNING);
+        Ok(Some(KEYCHAIN_FALLBACK_WARNING))
+    }
+
+    fn read_fallback(&self, workspace: &str, auth_id: &str) -> Result<Option<String>, String> {
+        let content = match std::fs::read_to_string(self.fallback_path(workspace, auth_id)) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("Failed to read cached token: {}", e)),
+        };
+        crate::encryption::decrypt(&content, &machine_passphrase())
+            .map(Some)
+            .map_err(|_| {
+                "Failed to decrypt cached token; it was saved on another machine or modified"
+                    .to_string()
+            })
+    }
+
+    /// Remove the fallback file, returning whether there was one
+    fn remove_fallback(&self, workspace: &str, auth_id: &str) -> Result<bool, String> {
+        match std::fs::remove_file(self.fallback_path(workspace, auth_id)) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(format!("Failed to remove cached token: {}", e)),
+        }
+    }
+
+    /// Named by a hash, so the directory doesn't reveal workspace paths
+    fn fallback_path(&self, workspace: &str, auth_id: &str) -> PathBuf {
+        let hash = Sha256::digest(format!("{}/{}", workspace, auth_id).as_bytes());
+        let name: String = hash.iter().map(|b| format!("{:02x}", b)).collect();
+        self.fallback_dir.join(format!("{}.json", name))
+    }
+}
+
+/// Passphrase for fallback files, derived from this machine and account so a
+/// copied file is useless elsewhere. It keeps tokens out of plain sight, not
+/// from someone who can run code as this user.
+fn machine_passphrase() -> String {
+    let machine_id = ["/etc/machine-id", "/var/lib/dbus/machine-id"]
+        .iter()
+        .find_map(|path| std::fs::read_to_string(path).ok())
+        .unwrap_or_default();
+    let host = std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .unwrap_or_default();
+    let home = dirs::home_dir().unwrap_or_default();
+    format!(
+        "kvile-tokens/{}/{}/{}",
+        machine_id.trim(),
+        host,
+        home.to_string_lossy()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oidc::mock::serve_once;
+    use std::collections::HashMap;
+
+    fn config(token_endpoint: &str) -> OidcConfig {
+        OidcConfig {
+            issuer: None,
+            authorization_endpoint: None,
+            token_endpoint: Some(token_endpoint.to_string()),
+            client_id: "test-client".to_string(),
+            client_secret: None,
+            token_endpoint_auth_method: None,
+            redirect_url: "http://localhost:8080/callback".to_string(),
+            use_random_port: false,
+            use_pkce: true,
+            scopes: vec!["openid".to_string()],
+            extra_params: HashMap::new(),
+            callback_page: Default::default(),
+        }
+    }
+
+    fn token(access_token: &str, expires_in: u64, refresh_token: Option<&str>) -> TokenResponse {
+        TokenResponse {
+            access_token: access_token.to_string(),
+            token_type: Some("Bearer".to_string()),
+            expires_in: Some(expires_in),
+            refresh_token: refresh_token.map(str::to_string),
+            id_token: Some("id-1".to_string()),
+            scope: None,
+            issued_token_type: None,
+        }
+    }
+
+    /// A store on the in-memory keychain, with its fallback directory
+    fn store() -> (TokenStore, tempfile::TempDir) {
+        crate::secrets::memory::install();
+        let dir = tempfile::tempdir().unwrap();
+        (TokenStore::with_fallback_dir(dir.path()), dir)
+    }
+
+    #[tokio::test]
+    async fn test_fresh_token_is_returned_from_cache() {
+        let (store, _dir) = store();
+        store
+            .store_tokens("/ws-fresh", "api", token("at-1", 3600, Some("rt-1")))
+            .unwrap();
+
+        // Nothing listens on the endpoint, so a refresh would fail
+        let cached = store
+            .get_valid_token("/ws-fresh", "api", &config("http://127.0.0.1:9/token"), 60)
+            .await
+            .unwrap();
+        assert_eq!(cached.token.access_token, "at-1");
+        let expires_in = cached.expires_at.unwrap() - Utc::now().timestamp();
+        assert!((3590..=3600).contains(&expires_in));
+
+        // Tokens are per workspace
+        let err = store
+            .get_valid_token("/other", "api", &config("http://127.0.0.1:9/token"), 60)
+            .await
+            .unwrap_err();
+        assert_eq!(err, "reauth_required: No token cached for 'api'");
+    }
+
+    #[tokio::test]
+    async fn test_token_near_expiry_is_refreshed() {
+        let (store, _dir) = store();
+        store
+            .store_tokens("/ws-refresh", "api", token("at-1", 30, Some("rt-1")))
+            .unwrap();
+        let (url, server) = serve_once(
+            "200 OK",
+            r#"{"access_token":"at-2","token_type":"Bearer","expires_in":3600}"#,
+        )
+        .await;
+
+        let cached = store
+            .get_valid_token("/ws-refresh", "api", &config(&url), 60)
+            .await
+            .unwrap();
+        assert_eq!(cached.token.access_token, "at-2");
+        // Kept from the original response when the refresh leaves them out
+        assert_eq!(cached.token.refresh_token.as_deref(), Some("rt-1"));
+        assert_eq!(cached.token.id_token.as_deref(), Some("id-1"));
+
+        let request = server.await.unwrap();
+        assert!(request.contains("grant_type=refresh_token"));
+        assert!(request.contains("refresh_token=rt-1"));
+
+        // The refreshed token replaced the cached one
+        let stored = store.load_tokens("/ws-refresh", "api").unwrap().unwrap();
+        assert_eq!(stored.token.access_token, "at-2");
+    }
+
+    #[tokio::test]
+    async fn test_failed_refresh_requires_reauth() {
+        let (store, _dir) = store();
+        store
+            .store_tokens("/ws-reauth", "api", token("at-1", 30, Some("rt-1")))
+            .unwrap();
+        let (url, _server) = serve_once(
+            "400 Bad Request",
+            r#"{"error":"invalid_grant","error_description":"Token is not active"}"#,
+        )
+        .await;
+
+        let err = store
+            .get_valid_token("/ws-reauth", "api", &config(&url), 60)
+            .await
+            .unwrap_err();
+        assert!(err.starts_with("reauth_required: Token refresh failed"));
+
+        store
+            .store_tokens("/ws-reauth", "api", token("at-1", 30, None))
+            .unwrap();
+        let err = store
+            .get_valid_token("/ws-reauth", "api", &config(&url), 60)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            "reauth_required: Token for 'api' expired and has no refresh token"
+        );
+    }
+
+    #[test]
+    fn test_end_session_clears_cache() {
+        let (store, _dir) = store();
+        store
+            .store_tokens("/ws-logout", "api", token("at-1", 3600, None))
+            .unwrap();
+        store
+            .store_tokens("/ws-logout", "other", token("at-2", 3600, None))
+            .unwrap();
+
+        // The cached id token is the hint unless one is given
+        assert_eq!(
+            store
+                .end_session("/ws-logout", "api", None)
+                .unwrap()
+                .as_deref(),
+            Some("id-1")
+        );
+        assert_eq!(
+            crate::secrets::memory::stored("/ws-logout", "$auth", "api"),
+            None
+        );
+        // Other auth configs keep their tokens
+        assert!(store.load_tokens("/ws-logout", "other").unwrap().is_some());
+
+        assert_eq!(
+            store
+                .end_session("/ws-logout", "other", Some("given".to_string()))
+                .unwrap()
+                .as_deref(),
+            Some("given")
+        );
+        assert_eq!(store.end_session("/ws-logout", "api", None).unwrap(), None);
+    }
+
+    #[test]
+    fn test_tokens_kept_in_keychain() {
+        let (store, dir) = store();
+        let (_, warning) = store
+            .store_tokens("/ws-keychain", "api", token("at-1", 3600, Some("rt-1")))
+            .unwrap();
+        assert_eq!(warning, None);
+
+        let stored = crate::secrets::memory::stored("/ws-keychain", "$auth", "api").unwrap();
+        assert!(stored.contains("\"access_token\":\"at-1\""));
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+        let cached = store.load_tokens("/ws-keychain", "api").unwrap().unwrap();
+        assert_eq!(cached.token.refresh_token.as_deref(), Some("rt-1"));
+
+        store.delete_tokens("/ws-keychain", "api").unwrap();
+        assert_eq!(
+            crate::secrets::memory::stored("/ws-keychain", "$auth", "api"),
+            None
+        );
+        assert!(store.load_tokens("/ws-keychain", "api").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_tokens_fall_back_to_encrypted_file() {
+        let (store, dir) = store();
+        crate::secrets::memory::lock("/ws-no-keychain");
+
+        let (_, warning) = store
+            .store_tokens("/ws-no-keychain", "api", token("at-1", 3600, Some("rt-1")))
+            .unwrap();
+        assert_eq!(warning, Some(KEYCHAIN_FALLBACK_WARNING));
+
+        // Neither the token nor the workspace path is readable on disk
+        let files: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(files.len(), 1);
+        let name = files[0].file_name().unwrap().to_string_lossy().to_string();
+        assert!(!name.contains("ws-no-keychain"));
+        let content = std::fs::read_to_string(&files[0]).unwrap();
+        assert!(crate::encryption::is_encrypted(&content));
+        assert!(!content.contains("at-1") && !content.contains("rt-1"));
+
+        let cached = store
+            .load_tokens("/ws-no-keychain", "api")
+            .unwrap()
+            .unwrap();
+        assert_eq!(cached.token.access_token, "at-1");
+        assert_eq!(cached.token.refresh_token.as_deref(), Some("rt-1"));
+
+        // A file that was tampered with is not trusted
+        std::fs::write(
+            &files[0],
+            content.replace("\"ciphertext\": \"", "\"ciphertext\": \"AAAA"),
+        )
+        .unwrap();
+        assert!(store
+            .load_tokens("/ws-no-keychain", "api")
+            .unwrap_err()
+            .starts_with("Failed to decrypt cached token"));
+
+        store.delete_tokens("/ws-no-keychain", "api").unwrap();
+        assert!(!files[0].exists());
+        // With no keychain and no file, the keychain's error is reported
+        assert!(store.load_tokens("/ws-no-keychain", "api").is_err());
+    }
+}
@@ -0,0 +1,752 @@
+mod dynamic;
+
+use regex::Regex;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::env::EnvironmentConfig;
+use crate::parser::ParsedRequest;
+
+/// How many levels of `{{var}}` inside variable values are expanded
+const MAX_DEPTH: usize = 10;
+
+/// Collect variables for an environment in the same order the frontend merges
+/// them: shared, private shared, environment, private environment
+pub fn environment_variables(
+    config: &EnvironmentConfig,
+    environment: Option<&str>,
+) -> Result<HashMap<String, String>, String> {
+    let mut vars = config.shared.clone();
+    vars.extend(config.private_shared.clone());
+
+    if let Some(name) = environment {
+        let env = config
+            .environments
+            .iter()
+            .find(|e| e.name == name)
+            .ok_or_else(|| format!("Environment '{}' not found", name))?;
+        vars.extend(env.variables.clone());
+        vars.extend(env.private_variables.clone());
+    }
+
+    Ok(vars)
+}
+
+/// Names of variables that come from private env files or `$exec` commands
+pub fn secret_names(config: &EnvironmentConfig, environment: Option<&str>) -> Vec<String> {
+    let mut names: Vec<String> = config.private_shared.keys().cloned().collect();
+    if let Some(env) =
+        environment.and_then(|name| config.environments.iter().find(|e| e.name == name))
+    {
+        names.extend(env.private_variables.keys().cloned());
+    }
+    for scope in std::iter::once("$shared").chain(environment) {
+        if let Some(command_names) = config.command_variables.get(scope) {
+            names.extend(command_names.iter().cloned());
+        }
+    }
+    names
+}
+
+/// Replace `{{name}}` placeholders with values, leaving unknown and dynamic
+/// (`{{$uuid}}`) variables untouched
+pub fn substitute(input: &str, vars: &HashMap<String, String>) -> String {
+    substitute_with(input, |name| lookup(vars, name))
+}
+
+/// Look up a variable. A dotted name that isn't defined itself is resolved
+/// into the JSON value of its longest defined prefix, so `{{auth.clientId}}`
+/// and `{{hosts.0}}` reach into objects and arrays. A JSONPath after `.$`
+/// queries the value instead, as in `{{login.response.body.$.items[0].id}}`;
+/// several matches give a JSON array of them.
+pub fn lookup(vars: &HashMap<String, String>, name: &str) -> Option<String> {
+    lookup_path(name, |key| vars.get(key).cloned())
+}
+
+/// [`lookup`] with values from a lookup function
+fn lookup_path(name: &str, mut get: impl FnMut(&str) -> Option<String>) -> Option<String> {
+    if let Some(value) = get(name) {
+        return Some(value);
+    }
+    if let Some(i) = name.find(".$") {
+        let root: serde_json::Value = serde_json::from_str(&get(&name[..i])?).ok()?;
+        let value = crate::jsonpath::select(&root, &name[i + 1..]).ok()??;
+        return Some(json_text(&value));
+    }
+    let (prefix, path) = name
+        .match_indices('.')
+        .rev()
+        .map(|(i, _)| (&name[..i], &name[i + 1..]))
+        .find_map(|(prefix, path)| Some((get(prefix)?, path)))?;
+
+    let root: serde_json::Value = serde_json::from_str(&prefix).ok()?;
+    let value = path
+        .split('.')
+        .try_fold(&root, |value, segment| match value {
+            serde_json::Value::Object(map) => map.get(segment),
+            serde_json::Value::Array(items) => items.get(segment.parse::<usize>().ok()?),
+            _ => None,
+        })?;
+    Some(json_text(value))
+}
+
+/// A JSON value as substituted: strings as they are, anything else, such as
+/// objects and arrays, as compact JSON
+fn json_text(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// `{{name}}` placeholders. `$name` placeholders of dynamic variables may
+/// take arguments, and a JSONPath may follow a name after `.$`.
+fn placeholder_re() -> Regex {
+    Regex::new(r"\{\{\s*(\$\w+(?:\s+[^{}]*?)?|[\w.-]+(?:\.\$[^{}]*?)?)\s*\}\}").unwrap()
+}
+
+/// Names the placeholders in `input` may read, with the prefixes [`lookup`]
+/// falls back to. Dynamic variables are left out.
+pub fn referenced_names(input: &str) -> Vec<String> {
+    placeholder_re()
+        .captures_iter(input)
+        .filter(|caps| !caps[1].starts_with('$'))
+        .flat_map(|caps| lookup_keys(caps[1].trim_end()))
+        .collect()
+}
+
+/// The names [`lookup`] may try for `name`: itself, the name a JSONPath
+/// follows and each dotted prefix
+fn lookup_keys(name: &str) -> Vec<String> {
+    let base = name.find(".$").map_or(name, |i| &name[..i]);
+    let mut keys = vec![name.to_string()];
+    keys.extend(
+        base.match_indices('.')
+            .map(|(i, _)| base[..i].to_string())
+            .chain((base != name).then(|| base.to_string())),
+    );
+    keys
+}
+
+/// [`substitute`] with values from a lookup function
+fn substitute_with(input: &str, mut lookup: impl FnMut(&str) -> Option<String>) -> String {
+    let var_re = placeholder_re();
+
+    let mut result = input.to_string();
+    for _ in 0..MAX_DEPTH {
+        let next = var_re
+            .replace_all(&result, |caps: &regex::Captures| {
+                lookup(caps[1].trim_end()).unwrap_or_else(|| caps[0].to_string())
+            })
+            .into_owned();
+        if next == result {
+            break;
+        }
+        result = next;
+    }
+    result
+}
+
+/// Substitute variables in a request's URL, headers and body. Request-level
+/// variables from the file override `vars`.
+pub fn substitute_request(
+    request: &ParsedRequest,
+    vars: &HashMap<String, String>,
+) -> ParsedRequest {
+    let mut scoped = vars.clone();
+    scoped.extend(request.variables.clone());
+
+    let mut resolved = request.clone();
+    resolved.url = substitute(&request.url, &scoped);
+    resolved.headers = request
+        .headers
+        .iter()
+        .map(|(k, v)| (k.clone(), substitute(v, &scoped)))
+        .collect();
+    resolved.body = request.body.as_ref().map(|b| substitute(b, &scoped));
+    resolved
+}
+
+/// Request metadata (`# @env name`) pinning a request to an environment
+pub const ENV_METADATA: &str = "env";
+
+/// Request metadata (`# @prompt otp Enter the one-time code`) naming a
+/// variable whose value is asked for on every send rather than looked up
+pub const PROMPT_METADATA: &str = "prompt";
+
+/// Where a variable's value was found when resolving a request, highest
+/// precedence first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VariableSource {
+    /// Answers to prompts. A name the request declares with `# @prompt`
+    /// comes only from here.
+    Prompt,
+    /// Runtime variables passed along with the request, e.g. set by its
+    /// pre-request script
+    Request,
+    /// Values scripts stored with `client.global.set`
+    Global,
+    /// `@name = value` definitions in the .http file
+    File,
+    /// The selected environment, public or private
+    Environment,
+    /// `$shared` values, public or private
+    Shared,
+    /// The OS environment of the kvile process. Opt-in, and only for names
+    /// nothing else defines.
+    Process,
+    /// `{{$uuid}}` and the other generated values
+    Dynamic,
+}
+
+/// Values a request is resolved with besides its own and the env config's
+#[derive(Debug, Clone, Default)]
+pub struct ResolveOptions {
+    /// Answers to prompts, by variable name
+    pub prompt_answers: HashMap<String, String>,
+    /// Runtime variables for this request
+    pub variables: HashMap<String, String>,
+    /// Values scripts stored with `client.global.set`
+    pub globals: HashMap<String, String>,
+    /// Fall back to the OS environment for names nothing else defines
+    pub process_env: bool,
+}
+
+/// A variable used while resolving a request
+#[derive(Debug, Clone, Serialize)]
+pub struct ResolvedVariable {
+    pub name: String,
+    pub source: VariableSource,
+    /// Env file an environment value came from
+    pub file: Option<String>,
+}
+
+/// A request with its variables substituted
+#[derive(Debug, Clone, Serialize)]
+pub struct ResolvedRequest {
+    pub request: ParsedRequest,
+    /// Environment the request was resolved against
+    pub environment: Option<String>,
+    /// Variables that were substituted, by name
+    pub variables: Vec<ResolvedVariable>,
+    /// Names no source defines
+    pub missing: Vec<String>,
+}
+
+/// [`resolve_request`], first running the `$exec` commands of the env values
+/// the request uses; see [`crate::env::run_commands`]. Commands of values it
+/// doesn't use never run.
+pub async fn resolve_request_with_commands(
+    workspace: &str,
+    request: &ParsedRequest,
+    config: &mut EnvironmentConfig,
+    environment: Option<&str>,
+    options: &ResolveOptions,
+) -> Result<ResolvedRequest, String> {
+    loop {
+        let resolved = resolve_request(request, Some(config), environment, options)?;
+        // Each pass replaces at least one marker, so this ends
+        let names: Vec<String> = resolved
+            .variables
+            .iter()
+            .filter(|v| {
+                matches!(
+                    v.source,
+                    VariableSource::Environment | VariableSource::Shared
+                )
+            })
+            .flat_map(|v| lookup_keys(&v.name))
+            .collect();
+        let environment = resolved.environment.as_deref();
+        if !crate::env::run_commands(workspace, config, environment, names).await {
+            return Ok(resolved);
+        }
+    }
+}
+
+/// Substitute variables in a request's URL, headers, body and metadata,
+/// recording where each value came from.
+///
+/// Each name is looked up in the order of [`VariableSource`]: prompt
+/// answers, the runtime `variables`, `globals`, the request's file
+/// variables, the environment, `$shared` values and finally, with
+/// `process_env`, the OS environment. `{{$name args}}` placeholders are
+/// generated fresh. A `# @env name` annotation on the request overrides
+/// `environment`.
+pub fn resolve_request(
+    request: &ParsedRequest,
+    config: Option<&EnvironmentConfig>,
+    environment: Option<&str>,
+    options: &ResolveOptions,
+) -> Result<ResolvedRequest, String> {
+    let environment = request
+        .metadata
+        .get(ENV_METADATA)
+        .map(String::as_str)
+        .or(environment);
+
+    // Lowest precedence first so later layers overwrite
+    let mut scope: HashMap<String, (String, VariableSource, Option<String>)> = HashMap::new();
+    let mut layer = |vars: &HashMap<String, String>,
+                     source: VariableSource,
+                     files: Option<&HashMap<String, String>>| {
+        for (name, value) in vars {
+            let file = files.and_then(|files| files.get(name)).cloned();
+            scope.insert(name.clone(), (value.clone(), source, file));
+        }
+    };
+    if let Some(config) = config {
+        layer(&config.shared, VariableSource::Shared, None);
+        layer(&config.private_shared, VariableSource::Shared, None);
+        if let Some(name) = environment {
+            let env = config
+                .environments
+                .iter()
+                .find(|e| e.name == name)
+                .ok_or_else(|| format!("Environment '{}' not found", name))?;
+            let files = Some(&env.variable_sources);
+            layer(&env.variables, VariableSource::Environment, files);
+            layer(&env.private_variables, VariableSource::Environment, files);
+        }
+    }
+    layer(&request.variables, VariableSource::File, None);
+    layer(&options.globals, VariableSource::Global, None);
+    layer(&options.variables, VariableSource::Request, None);
+    layer(&options.prompt_answers, VariableSource::Prompt, None);
+    if let Some(name) = prompt_name(request) {
+        if !options.prompt_answers.contains_key(name) {
+            scope.remove(name);
+        }
+    }
+
+    let mut used = BTreeMap::new();
+    let mut missing = BTreeMap::new();
+    let mut lookup = |name: &str| {
+        let found = if let Some(dynamic) = name.strip_prefix('$') {
+            let (name, args) = dynamic
+                .split_once(char::is_whitespace)
+                .unwrap_or((dynamic, ""));
+            dynamic::generate(name, args).map(|value| (value, VariableSource::Dynamic, None))
+        } else {
+            let mut key = None;
+            lookup_path(name, |prefix| {
+                let value = scope.get(prefix)?.0.clone();
+                key = Some(prefix.to_string());
+                Some(value)
+            })
+            .and_then(|value| {
+                let (_, source, file) = &scope[&key?];
+                Some((value, *source, file.clone()))
+            })
+            .or_else(|| {
+                options
+                    .process_env
+                    .then(|| std::env::var(name).ok())
+                    .flatten()
+                    .map(|value| (value, VariableSource::Process, None))
+            })
+        };
+        match found {
+            Some((value, source, file)) => {
+                used.insert(name.to_string(), (source, file));
+                Some(value)
+            }
+            None => {
+                missing.insert(name.to_string(), ());
+                None
+            }
+        }
+    };
+
+    let mut resolved = request.clone();
+    resolved.url = substitute_with(&request.url, &mut lookup);
+    resolved.headers = request
+        .headers
+        .iter()
+        .map(|(k, v)| (k.clone(), substitute_with(v, &mut lookup)))
+        .collect();
+    resolved.body = request
+        .body
+        .as_ref()
+        .map(|b| substitute_with(b, &mut lookup));
+    resolved.metadata = request
+        .metadata
+        .iter()
+        .map(|(k, v)| (k.clone(), substitute_with(v, &mut lookup)))
+        .collect();
+
+    Ok(ResolvedRequest {
+        request: resolved,
+        environment: environment.map(str::to_string),
+        variables: used
+            .into_iter()
+            .map(|(name, (source, file))| ResolvedVariable { name, source, file })
+            .collect(),
+        missing: missing.into_keys().collect(),
+    })
+}
+
+/// The variable a request declares with `# @prompt`, followed by its
+/// description
+pub fn prompt_name(request: &ParsedRequest) -> Option<&str> {
+    request
+        .metadata
+        .get(PROMPT_METADATA)?
+        .split_whitespace()
+        .next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_substitute() {
+        let vars: HashMap<String, String> = [
+            ("host".to_string(), "https://api.example.com".to_string()),
+            ("base".to_string(), "{{host}}/v1".to_string()),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(
+            substitute("{{base}}/users/{{ id }}?t={{$timestamp}}", &vars),
+            "https://api.example.com/v1/users/{{ id }}?t={{$timestamp}}"
+        );
+    }
+
+    #[test]
+    fn test_resolve_request_precedence() {
+        let config: EnvironmentConfig = serde_json::from_value(serde_json::json!({
+            "environments": [{
+                "name": "dev",
+                "variables": {"host": "env.test", "token": "env-token"},
+                "private_variables": {"key": "private"},
+                "source_file": "http-client.env.json",
+                "variable_sources": {
+                    "host": "http-client.env.json",
+                    "token": "http-client.env.json",
+                    "key": "http-client.private.env.json"
+                }
+            }],
+            "shared": {"host": "shared.test", "region": "eu", "KVILE_TEST_SHADOWED": "shared"},
+            "private_shared": {}
+        }))
+        .unwrap();
+        let mut request = ParsedRequest::new();
+        request.url =
+            "https://{{host}}/{{region}}/{{version}}?k={{key}}&s={{KVILE_TEST_SHADOWED}}".into();
+        request.headers.insert(
+            "Authorization".into(),
+            "Bearer {{token}} {{KVILE_TEST_PROCESS}}".into(),
+        );
+        request.body = Some("{{KVILE_TEST_UNSET}}".into());
+        request.variables.insert("version".into(), "v1".into());
+        request
+            .variables
+            .insert("token".into(), "file-token".into());
+        let runtime: HashMap<String, String> = [("version".to_string(), "v2".to_string())]
+            .into_iter()
+            .collect();
+
+        std::env::set_var("KVILE_TEST_PROCESS", "from-process");
+        std::env::set_var("KVILE_TEST_SHADOWED", "from-process");
+        std::env::remove_var("KVILE_TEST_UNSET");
+
+        let mut options = ResolveOptions {
+            variables: runtime,
+            process_env: true,
+            ..Default::default()
+        };
+        let resolved = resolve_request(&request, Some(&config), Some("dev"), &options).unwrap();
+        assert_eq!(
+            resolved.request.url,
+            "https://env.test/eu/v2?k=private&s=shared"
+        );
+        assert_eq!(
+            resolved.request.headers["Authorization"],
+            "Bearer file-token from-process"
+        );
+        assert_eq!(
+            resolved.request.body.as_deref(),
+            Some("{{KVILE_TEST_UNSET}}")
+        );
+        assert_eq!(resolved.missing, vec!["KVILE_TEST_UNSET"]);
+
+        let sources: Vec<_> = resolved
+            .variables
+            .iter()
+            .map(|v| (v.name.as_str(), v.source, v.file.as_deref()))
+            .collect();
+        assert_eq!(
+            sources,
+            vec![
+                ("KVILE_TEST_PROCESS", VariableSource::Process, None),
+                ("KVILE_TEST_SHADOWED", VariableSource::Shared, None),
+                (
+                    "host",
+                    VariableSource::Environment,
+                    Some("http-client.env.json")
+                ),
+                (
+                    "key",
+                    VariableSource::Environment,
+                    Some("http-client.private.env.json")
+                ),
+                ("region", VariableSource::Shared, None),
+                ("token", VariableSource::File, None),
+                ("version", VariableSource::Request, None),
+            ]
+        );
+
+        // Without the opt-in the process environment is never read
+        options.process_env = false;
+        let resolved = resolve_request(&request, Some(&config), Some("dev"), &options).unwrap();
+        assert!(resolved.request.headers["Authorization"].ends_with("{{KVILE_TEST_PROCESS}}"));
+        assert_eq!(
+            resolved.missing,
+            vec!["KVILE_TEST_PROCESS", "KVILE_TEST_UNSET"]
+        );
+    }
+
+    #[test]
+    fn test_substitute_json_paths() {
+        let vars: HashMap<String, String> = [
+            (
+                "auth".to_string(),
+                r#"{"clientId":"x","tenant":{"id":7},"scopes":["read","write"]}"#.to_string(),
+            ),
+            ("hosts".to_string(), r#"["a.test","b.test"]"#.to_string()),
+            ("auth.override".to_string(), "flat".to_string()),
+            ("plain".to_string(), "text".to_string()),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(substitute("{{auth.clientId}}", &vars), "x");
+        assert_eq!(substitute("{{auth.tenant.id}}", &vars), "7");
+        assert_eq!(substitute("{{auth.scopes.1}}", &vars), "write");
+        assert_eq!(substitute("{{hosts.0}}", &vars), "a.test");
+        assert_eq!(substitute("{{auth.tenant}}", &vars), r#"{"id":7}"#);
+        assert_eq!(substitute("{{hosts}}", &vars), r#"["a.test","b.test"]"#);
+        // A key containing dots wins over the path into its parent
+        assert_eq!(substitute("{{auth.override}}", &vars), "flat");
+
+        // Paths that don't exist are left alone
+        for missing in [
+            "{{auth.missing}}",
+            "{{hosts.5}}",
+            "{{hosts.first}}",
+            "{{plain.length}}",
+            "{{auth.clientId.x}}",
+        ] {
+            assert_eq!(substitute(missing, &vars), missing);
+        }
+
+        // JSONPath after `.$`, as request chaining references use
+        assert_eq!(substitute("{{auth.$.tenant.id}}", &vars), "7");
+        assert_eq!(substitute("{{ auth.$..id }}", &vars), "7");
+        assert_eq!(substitute("{{hosts.$[?@ == 'b.test']}}", &vars), "b.test");
+        assert_eq!(
+            substitute("{{hosts.$[*]}}", &vars),
+            r#"["a.test","b.test"]"#
+        );
+        for missing in ["{{auth.$.missing}}", "{{auth.$[}}", "{{plain.$.x}}"] {
+            assert_eq!(substitute(missing, &vars), missing);
+        }
+    }
+
+    #[test]
+    fn test_resolve_request_env_override() {
+        let config: EnvironmentConfig = serde_json::from_value(serde_json::json!({
+            "environments": [
+                {"name": "prod", "variables": {"host": "prod.test"}, "source_file": "env.json"},
+                {"name": "staging", "variables": {"host": "staging.test"}, "source_file": "env.json"}
+            ],
+            "shared": {}
+        }))
+        .unwrap();
+        let mut health = ParsedRequest::new();
+        health.url = "https://{{host}}/health".into();
+        health.metadata.insert("env".into(), "prod".into());
+        let mut update = ParsedRequest::new();
+        update.url = "https://{{host}}/update".into();
+        update.metadata.insert("env".into(), "staging".into());
+        let mut unpinned = ParsedRequest::new();
+        unpinned.url = "https://{{host}}/".into();
+
+        let options = ResolveOptions::default();
+        let resolve = |request: &ParsedRequest| {
+            resolve_request(request, Some(&config), Some("staging"), &options).unwrap()
+        };
+        let resolved = resolve(&health);
+        assert_eq!(resolved.request.url, "https://prod.test/health");
+        assert_eq!(resolved.environment.as_deref(), Some("prod"));
+        assert_eq!(resolve(&update).request.url, "https://staging.test/update");
+        assert_eq!(resolve(&unpinned).request.url, "https://staging.test/");
+
+        let mut unknown = ParsedRequest::new();
+        unknown.metadata.insert("env".into(), "qa".into());
+        assert_eq!(
+            resolve_request(&unknown, Some(&config), Some("staging"), &options).unwrap_err(),
+            "Environment 'qa' not found"
+        );
+    }
+
+    fn map(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_resolve_request_layer_order() {
+        const NAME: &str = "KVILE_TEST_LAYERED";
+        let layers = [
+            VariableSource::Prompt,
+            VariableSource::Request,
+            VariableSource::Global,
+            VariableSource::File,
+            VariableSource::Environment,
+            VariableSource::Shared,
+            VariableSource::Process,
+        ];
+        std::env::set_var(NAME, "process");
+
+        // Drop the highest layer each round; the next one down must win
+        for (skip, expected) in layers.iter().enumerate() {
+            let has = |source: VariableSource| layers[skip..].contains(&source);
+            let layer = |source: VariableSource| -> HashMap<String, String> {
+                let value = serde_json::to_value(source).unwrap();
+                match has(source) {
+                    true => map(&[(NAME, value.as_str().unwrap())]),
+                    false => HashMap::new(),
+                }
+            };
+            let config: EnvironmentConfig = serde_json::from_value(serde_json::json!({
+                "environments": [{
+                    "name": "dev",
+                    "variables": layer(VariableSource::Environment),
+                    "source_file": "http-client.env.json"
+                }],
+                "shared": layer(VariableSource::Shared)
+            }))
+            .unwrap();
+            let mut request = ParsedRequest::new();
+            request.url = format!("https://{{{{{}}}}}/", NAME);
+            request.variables = layer(VariableSource::File);
+            let options = ResolveOptions {
+                prompt_answers: layer(VariableSource::Prompt),
+                variables: layer(VariableSource::Request),
+                globals: layer(VariableSource::Global),
+                process_env: has(VariableSource::Process),
+            };
+
+            let resolved = resolve_request(&request, Some(&config), Some("dev"), &options).unwrap();
+            let winner = serde_json::to_value(expected).unwrap();
+            assert_eq!(
+                resolved.request.url,
+                format!("https://{}/", winner.as_str().unwrap())
+            );
+            assert_eq!(resolved.variables.len(), 1);
+            assert_eq!(resolved.variables[0].source, *expected);
+            assert!(resolved.missing.is_empty());
+        }
+
+        // Private values override public ones within a layer
+        let config: EnvironmentConfig = serde_json::from_value(serde_json::json!({
+            "environments": [{
+                "name": "dev",
+                "variables": {"a": "public"},
+                "private_variables": {"a": "private"},
+                "source_file": "http-client.env.json"
+            }],
+            "shared": {"b": "public"},
+            "private_shared": {"b": "private"}
+        }))
+        .unwrap();
+        let mut request = ParsedRequest::new();
+        request.url = "{{a}} {{b}}".into();
+        let resolved =
+            resolve_request(&request, Some(&config), Some("dev"), &Default::default()).unwrap();
+        assert_eq!(resolved.request.url, "private private");
+    }
+
+    #[test]
+    fn test_resolve_request_prompts() {
+        let mut request = ParsedRequest::new();
+        request.url = "https://api.test/?otp={{otp}}&token={{token}}&user={{user}}".into();
+        request
+            .metadata
+            .insert("prompt".into(), "otp Enter the one-time code".into());
+        request.variables = map(&[("otp", "file-otp"), ("user", "file-user")]);
+        let mut options = ResolveOptions {
+            variables: map(&[("user", "runtime-user"), ("token", "runtime-token")]),
+            ..Default::default()
+        };
+
+        // A declared prompt is never filled in from another source
+        let resolved = resolve_request(&request, None, None, &options).unwrap();
+        assert_eq!(
+            resolved.request.url,
+            "https://api.test/?otp={{otp}}&token=runtime-token&user=runtime-user"
+        );
+        assert_eq!(resolved.missing, vec!["otp"]);
+
+        // Answers win over everything, declared or not
+        options.prompt_answers = map(&[("otp", "123456"), ("token", "t"), ("user", "asked")]);
+        let resolved = resolve_request(&request, None, None, &options).unwrap();
+        assert_eq!(
+            resolved.request.url,
+            "https://api.test/?otp=123456&token=t&user=asked"
+        );
+        assert!(resolved.missing.is_empty());
+        assert!(resolved
+            .variables
+            .iter()
+            .all(|v| v.source == VariableSource::Prompt));
+    }
+
+    #[test]
+    fn test_resolve_request_dynamic_variables() {
+        let mut request = ParsedRequest::new();
+        request.url = "https://api.test/{{id}}/{{$randomInt 3 3}}?r={{$nope}}".into();
+        request
+            .headers
+            .insert("X-Request-Id".into(), "{{ $uuid }}".into());
+        request
+            .metadata
+            .insert("auth".into(), "oidc {{provider}}".into());
+        request.variables = map(&[("id", "{{$randomInt 4 4}}"), ("provider", "keycloak")]);
+
+        let resolved = resolve_request(&request, None, None, &Default::default()).unwrap();
+        assert_eq!(resolved.request.url, "https://api.test/4/3?r={{$nope}}");
+        assert_eq!(resolved.request.headers["X-Request-Id"].len(), 36);
+        assert_eq!(resolved.request.metadata["auth"], "oidc keycloak");
+        assert_eq!(resolved.missing, vec!["$nope"]);
+
+        let sources: Vec<_> = resolved
+            .variables
+            .iter()
+            .map(|v| (v.name.as_str(), v.source))
+            .collect();
+        assert_eq!(
+            sources,
+            vec![
+                ("$randomInt 3 3", VariableSource::Dynamic),
+                ("$randomInt 4 4", VariableSource::Dynamic),
+                ("$uuid", VariableSource::Dynamic),
+                ("id", VariableSource::File),
+                ("provider", VariableSource::File),
+            ]
+        );
+
+        // Plain substitution leaves dynamic variables for the caller
+        assert_eq!(
+            substitute("{{$randomInt 1 2}}", &HashMap::new()),
+            "{{$randomInt 1 2}}"
+        );
+    }
+}
@@ -0,0 +1,175 @@
+//! Dynamic variables such as `{{$uuid}}` and `{{$randomInt 1 10}}`, generated
+//! fresh for every placeholder. The same set the editor offers in
+//! `src/lib/dynamic-variables.ts`.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::Utc;
+use rand::Rng;
+
+const ALPHANUMERIC: &str = "abcdefghijklmnopqrstuvwxyz0123456789";
+const LETTERS: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const HEX: &str = "0123456789abcdef";
+const FIRST_NAMES: &[&str] = &[
+    "John", "Jane", "Alex", "Sarah", "Michael", "Emma", "David", "Lisa", "James", "Emily",
+    "Robert", "Anna",
+];
+const LAST_NAMES: &[&str] = &[
+    "Smith", "Johnson", "Williams", "Brown", "Jones", "Davis", "Miller", "Wilson", "Moore",
+    "Taylor", "Anderson", "Thomas",
+];
+const LOREM: &[&str] = &[
+    "lorem",
+    "ipsum",
+    "dolor",
+    "sit",
+    "amet",
+    "consectetur",
+    "adipiscing",
+    "elit",
+    "sed",
+    "do",
+    "eiusmod",
+    "tempor",
+    "incididunt",
+    "ut",
+    "labore",
+    "et",
+    "dolore",
+    "magna",
+    "aliqua",
+];
+
+/// The value of `{{$name args}}`, or `None` for names that aren't dynamic
+/// variables and for `$basicAuth` without a username and password
+pub fn generate(name: &str, args: &str) -> Option<String> {
+    let mut rng = rand::thread_rng();
+    let args = args.trim();
+    let numbers = || -> Option<(f64, f64)> {
+        let mut parts = args.split_whitespace().map(|p| p.parse::<f64>());
+        match (parts.next()?, parts.next()?) {
+            (Ok(min), Ok(max)) if max >= min => Some((min, max)),
+            _ => None,
+        }
+    };
+    let length = |default: usize| args.parse::<usize>().map_or(default, |n| n.min(100));
+
+    Some(match name {
+        "uuid" | "guid" => uuid(&mut rng),
+        "timestamp" => Utc::now().timestamp().to_string(),
+        "timestampMs" => Utc::now().timestamp_millis().to_string(),
+        "isoTimestamp" => Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string(),
+        "date" => Utc::now().format("%Y-%m-%d").to_string(),
+        "time" => Utc::now().format("%H:%M:%S").to_string(),
+        "randomInt" => match numbers() {
+            Some((min, max)) => rng.gen_range(min as i64..=max as i64).to_string(),
+            None => rng.gen_range(0..=1000).to_string(),
+        },
+        "randomFloat" => match numbers() {
+            Some((min, max)) => format!("{:.2}", rng.gen_range(min..=max)),
+            None => format!("{:.4}", rng.gen::<f64>()),
+        },
+        "randomString" => random_chars(&mut rng, ALPHANUMERIC, length(10)),
+        "randomAlpha" => random_chars(&mut rng, LETTERS, length(10)),
+        "randomHex" => random_chars(&mut rng, HEX, length(16)),
+        "randomEmail" => format!("{}@example.com", random_chars(&mut rng, ALPHANUMERIC, 10)),
+        "randomFirstName" => pick(&mut rng, FIRST_NAMES).to_string(),
+        "randomLastName" => pick(&mut rng, LAST_NAMES).to_string(),
+        "randomFullName" => format!(
+            "{} {}",
+            pick(&mut rng, &FIRST_NAMES[..6]),
+            pick(&mut rng, &LAST_NAMES[..6])
+        ),
+        "randomPhone" => format!("+1{}", rng.gen_range(1_000_000_000u64..10_000_000_000)),
+        "randomBoolean" => rng.gen::<bool>().to_string(),
+        "loremWord" => pick(&mut rng, &LOREM[..12]).to_string(),
+        "loremSentence" => {
+            let count = rng.gen_range(5..13);
+            let words: Vec<&str> = (0..count).map(|_| pick(&mut rng, LOREM)).collect();
+            let sentence = words.join(" ");
+            let mut chars = sentence.chars();
+            let first = chars.next().map(|c| c.to_uppercase().to_string());
+            format!("{}{}.", first.unwrap_or_default(), chars.as_str())
+        }
+        "basicAuth" => {
+            let (username, password) = args.split_once(char::is_whitespace)?;
+            let password = password.trim();
+            if password.is_empty() {
+                return None;
+            }
+            format!(
+                "Basic {}",
+                STANDARD.encode(format!("{}:{}", username, password))
+            )
+        }
+        "base64" => STANDARD.encode(args),
+        "base64Decode" => STANDARD
+            .decode(args)
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .unwrap_or_else(|| "[invalid base64]".to_string()),
+        "urlEncode" => urlencoding::encode(args).into_owned(),
+        "urlDecode" => urlencoding::decode(args)
+            .map(|s| s.into_owned())
+            .unwrap_or_else(|_| "[invalid url encoding]".to_string()),
+        _ => return None,
+    })
+}
+
+fn uuid(rng: &mut impl Rng) -> String {
+    let mut bytes: [u8; 16] = rng.gen();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+fn random_chars(rng: &mut impl Rng, chars: &str, length: usize) -> String {
+    let chars = chars.as_bytes();
+    (0..length)
+        .map(|_| chars[rng.gen_range(0..chars.len())] as char)
+        .collect()
+}
+
+fn pick<'a>(rng: &mut impl Rng, items: &[&'a str]) -> &'a str {
+    items[rng.gen_range(0..items.len())]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate() {
+        let uuid = generate("uuid", "").unwrap();
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(&uuid[14..15], "4");
+        assert_ne!(generate("guid", "").unwrap(), uuid);
+
+        for _ in 0..20 {
+            let n: i64 = generate("randomInt", "5 7").unwrap().parse().unwrap();
+            assert!((5..=7).contains(&n));
+        }
+        assert_eq!(generate("randomHex", "").unwrap().len(), 16);
+        assert_eq!(generate("randomString", " 4 ").unwrap().len(), 4);
+        assert_eq!(generate("randomAlpha", "500").unwrap().len(), 100);
+        assert_eq!(generate("date", "").unwrap().len(), 10);
+
+        assert_eq!(
+            generate("basicAuth", "user pass word").as_deref(),
+            Some("Basic dXNlcjpwYXNzIHdvcmQ=")
+        );
+        assert_eq!(generate("basicAuth", "user"), None);
+        assert_eq!(generate("base64", "hi").as_deref(), Some("aGk="));
+        assert_eq!(generate("base64Decode", "aGk=").as_deref(), Some("hi"));
+        assert_eq!(generate("urlEncode", "a b&c").as_deref(), Some("a%20b%26c"));
+        assert_eq!(generate("urlDecode", "a%20b").as_deref(), Some("a b"));
+        assert_eq!(generate("nope", ""), None);
+    }
+}
@@ -1,117 +1,704 @@
-use notify::{Config, Event, RecommendedWatcher, RecursiveMode, Watcher};
-use std::path::Path;
-use std::sync::mpsc::channel;
-use std::sync::Mutex;
-use std::thread;
-use std::time::Duration;
-use tauri::{AppHandle, Emitter};
+use crate::history::HistoryDb;
+use crate::ignores::WorkspaceIgnore;
+use notify::event::{MetadataKind, ModifyKind, RenameMode};
+use notify::{Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, State};
 
-/// Global watcher state
-static WATCHER: Mutex<Option<WatcherState>> = Mutex::new(None);
+/// The watched roots and what they share, kept in Tauri's managed state
+#[derive(Default)]
+pub struct WatcherManager {
+    /// Watched roots, by path
+    roots: Mutex<BTreeMap<String, WatcherState>>,
+    /// Files kvile wrote itself, whose changes are not reported
+    self_writes: Arc<Mutex<SelfWrites>>,
+    /// Whether events are dropped rather than reported, for every root
+    paused: Arc<AtomicBool>,
+}
 
 struct WatcherState {
-    _watcher: RecommendedWatcher,
-    watched_path: String,
+    watcher: RecommendedWatcher,
+    /// The event thread's channel, to tell it to finish
+    shutdown: Sender<Message>,
+    thread: JoinHandle<()>,
 }
 
-/// Start watching a directory for file changes
-#[tauri::command]
-pub fn start_watching(app: AppHandle, directory: String) -> Result<(), String> {
-    stop_watching()?;
+/// What a root's event thread receives
+enum Message {
+    Event(Event),
+    /// Finish, as the root is no longer watched
+    Shutdown,
+}
+
+impl WatcherState {
+    /// Stop watching, and wait for the event thread to finish so nothing is
+    /// emitted for the root afterwards
+    fn stop(self) {
+        drop(self.watcher);
+        let _ = self.shutdown.send(Message::Shutdown);
+        let _ = self.thread.join();
+    }
+}
+
+/// How long the directory must be quiet before pending changes are emitted
+const QUIET_PERIOD: Duration = Duration::from_millis(500);
+
+/// Longest a change waits while others keep arriving
+const MAX_LATENCY: Duration = Duration::from_secs(2);
+
+/// How long a write kvile made itself is kept from being reported
+const SELF_WRITE_TTL: Duration = Duration::from_secs(5);
+
+/// Event carrying a [`FilesChanged`] payload
+pub const FILES_CHANGED_EVENT: &str = "files-changed";
+
+/// Event whose payload is just the watched directory. Deprecated in favour of
+/// [`FILES_CHANGED_EVENT`]; still emitted for one release.
+pub const LEGACY_FILE_CHANGED_EVENT: &str = "file-changed";
+
+/// Event carrying a [`FileRenamed`] payload, once per rename whose old and
+/// new names were both seen
+pub const FILE_RENAMED_EVENT: &str = "file-renamed";
+
+/// Event carrying a [`FileDeleted`] payload for each deleted .http or .rest
+/// file, including those in a deleted directory
+pub const FILE_DELETED_EVENT: &str = "file-deleted";
+
+/// What happened to a path
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Created,
+    Modified,
+    Removed,
+    Renamed,
+}
+
+/// What a changed path is, for the frontend to decide what to reload
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileKind {
+    /// An .http or .rest file
+    Http,
+    /// An env file environments are loaded from
+    Env,
+    /// A directory or another file shown in the tree
+    Other,
+}
+
+/// One change to a watched path
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileChange {
+    pub path: String,
+    pub kind: ChangeKind,
+    pub file_kind: FileKind,
+    /// For renames, the path before, when the platform reports both
+    pub from: Option<String>,
+}
+
+/// Payload of [`FILES_CHANGED_EVENT`]: the changes in the watched directory
+/// since the last event, at most one per path
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FilesChanged {
+    /// The watched directory
+    pub root: String,
+    pub changes: Vec<FileChange>,
+}
 
-    let (tx, rx) = channel();
+/// Payload of [`FILE_RENAMED_EVENT`], so open tabs can follow their file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileRenamed {
+    /// The watched directory
+    pub root: String,
+    pub from: String,
+    pub to: String,
+}
+
+/// Payload of [`FILE_DELETED_EVENT`], so open tabs know their file is gone
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileDeleted {
+    /// The watched directory
+    pub root: String,
+    pub path: String,
+}
 
-    let mut watcher = RecommendedWatcher::new(
-        move |res: Result<Event, notify::Error>| {
-            if let Ok(event) = res {
-                let _ = tx.send(event);
+/// Start watching a directory for file changes, alongside any other watched
+/// roots. Watching a root again changes nothing. Paths its ignore files
+/// exclude are skipped, as in [`crate::commands::list_http_files`].
+#[tauri::command]
+pub fn start_watching(
+    app: AppHandle,
+    directory: String,
+    history_db: State<'_, HistoryDb>,
+    watchers: State<'_, WatcherManager>,
+) -> Result<(), String> {
+    let history_db = history_db.inner().clone();
+    let workspace = directory.clone();
+    let root = directory.clone();
+
+    // One "env-changed" per env file as well, so a tree change doesn't
+    // swallow an env change
+    watchers.watch(
+        &directory,
+        move || WorkspaceIgnore::for_workspace(&history_db, &workspace),
+        move |batch| {
+            for change in &batch.changes {
+                if let (ChangeKind::Renamed, Some(from)) = (change.kind, &change.from) {
+                    let payload = FileRenamed {
+                        root: root.clone(),
+                        from: from.clone(),
+                        to: change.path.clone(),
+                    };
+                    let _ = app.emit(FILE_RENAMED_EVENT, &payload);
+                }
+            }
+            for path in &batch.deleted {
+                let payload = FileDeleted {
+                    root: root.clone(),
+                    path: path.clone(),
+                };
+                let _ = app.emit(FILE_DELETED_EVENT, &payload);
+            }
+            if !batch.changes.is_empty() {
+                let payload = FilesChanged {
+                    root: root.clone(),
+                    changes: batch.changes,
+                };
+                let _ = app.emit(FILES_CHANGED_EVENT, &payload);
+            }
+            if batch.tree_changed {
+                let _ = app.emit(LEGACY_FILE_CHANGED_EVENT, &root);
+            }
+            for path in batch.env_changed {
+                let _ = app.emit("env-changed", &path);
             }
         },
-        Config::default().with_poll_interval(Duration::from_secs(2)),
     )
-    .map_err(|e| format!("Failed to create watcher: {}", e))?;
+}
+
+/// Stop watching one root. Roots not being watched are ignored.
+#[tauri::command]
+pub fn stop_watching(path: String, watchers: State<'_, WatcherManager>) -> Result<(), String> {
+    watchers.stop(&path);
+    Ok(())
+}
+
+/// Stop watching every root
+#[tauri::command]
+pub fn stop_all_watching(watchers: State<'_, WatcherManager>) -> Result<(), String> {
+    watchers.stop_all();
+    Ok(())
+}
+
+/// Get the watched roots
+#[tauri::command]
+pub fn get_watched_paths(watchers: State<'_, WatcherManager>) -> Vec<String> {
+    watchers.paths()
+}
 
-    watcher
-        .watch(Path::new(&directory), RecursiveMode::Recursive)
-        .map_err(|e| format!("Failed to watch directory: {}", e))?;
+/// Drop file events for every root until [`resume_watching`], such as while
+/// kvile rewrites many files itself. Changes made meanwhile are not reported.
+#[tauri::command]
+pub fn pause_watching(watchers: State<'_, WatcherManager>) -> Result<(), String> {
+    watchers.paused.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Report file events again after [`pause_watching`]
+#[tauri::command]
+pub fn resume_watching(watchers: State<'_, WatcherManager>) -> Result<(), String> {
+    watchers.paused.store(false, Ordering::Relaxed);
+    Ok(())
+}
 
-    let app_handle = app.clone();
-    let watched_dir = directory.clone();
+impl WatcherManager {
+    /// Watch a root unless it already is, passing each batch of changes to
+    /// `emit` on the root's event thread. `load_ignore` reads the root's
+    /// ignore rules, again whenever an ignore file changes.
+    fn watch<L, E>(&self, directory: &str, load_ignore: L, mut emit: E) -> Result<(), String>
+    where
+        L: Fn() -> WorkspaceIgnore + Send + 'static,
+        E: FnMut(ChangeBatch) + Send + 'static,
+    {
+        let mut roots = self.roots.lock().unwrap();
+        if roots.contains_key(directory) {
+            return Ok(());
+        }
 
-    // Spawn thread to handle file events
-    thread::spawn(move || {
-        // Debounce: collect events for a short period before emitting
-        let mut last_emit = std::time::Instant::now();
-        let debounce_duration = Duration::from_millis(500);
+        let (tx, rx) = channel();
+        let shutdown = tx.clone();
 
-        loop {
-            match rx.recv_timeout(Duration::from_secs(1)) {
+        let mut watcher = RecommendedWatcher::new(
+            move |res: Result<Event, notify::Error>| match res {
                 Ok(event) => {
-                    // Check if any relevant files changed
-                    let dominated_paths: Vec<String> = event
-                        .paths
-                        .iter()
-                        .filter(|p| is_relevant_path(p))
-                        .map(|p| p.to_string_lossy().to_string())
-                        .collect();
-
-                    if !dominated_paths.is_empty() && last_emit.elapsed() >= debounce_duration {
-                        let _ = app_handle.emit("file-changed", &watched_dir);
-                        last_emit = std::time::Instant::now();
+                    let _ = tx.send(Message::Event(event));
+                }
+                Err(e) => tracing::warn!("File watcher error: {}", e),
+            },
+            Config::default().with_poll_interval(Duration::from_secs(2)),
+        )
+        .map_err(|e| format!("Failed to create watcher: {}", e))?;
+
+        watcher
+            .watch(Path::new(directory), RecursiveMode::Recursive)
+            .map_err(|e| format!("Failed to watch directory: {}", e))?;
+
+        let self_writes = self.self_writes.clone();
+        let paused = self.paused.clone();
+        let root = PathBuf::from(directory);
+
+        // Spawn thread to handle file events
+        let span = tracing::info_span!("watcher", root = directory);
+        let thread = thread::spawn(move || {
+            let _span = span.entered();
+            let ignore = load_ignore();
+            let mut pending = PendingChanges {
+                known: http_files(&root, &ignore),
+                ignore,
+                ..Default::default()
+            };
+
+            loop {
+                // Wake for the next emit, or wait for the next message
+                let message = match pending.due_at() {
+                    Some(due) => rx.recv_timeout(due.saturating_duration_since(Instant::now())),
+                    None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                };
+                match message {
+                    Ok(Message::Shutdown) | Err(RecvTimeoutError::Disconnected) => break,
+                    Ok(Message::Event(_)) if paused.load(Ordering::Relaxed) => {}
+                    Ok(Message::Event(event)) => pending.add(&event, Instant::now()),
+                    Err(RecvTimeoutError::Timeout) => {}
+                }
+
+                if let Some(mut batch) = pending.take_due(Instant::now()) {
+                    self_writes
+                        .lock()
+                        .unwrap()
+                        .remove_from(&mut batch, Instant::now());
+                    if batch.ignore_changed {
+                        tracing::debug!("Reloading ignore rules");
+                        pending.ignore = load_ignore();
                     }
+                    tracing::debug!(
+                        changes = batch.changes.len(),
+                        deleted = batch.deleted.len(),
+                        "Emitting file changes"
+                    );
+                    emit(batch);
                 }
-                Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
-                    // Check if we should stop
-                    let guard = WATCHER.lock().unwrap();
-                    if guard.is_none() {
-                        break;
+            }
+        });
+
+        tracing::info!(root = directory, "Watching for file changes");
+        roots.insert(
+            directory.to_string(),
+            WatcherState {
+                watcher,
+                shutdown,
+                thread,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Stop watching a root. Its event thread has finished by the time this
+    /// returns, before the root can be watched again.
+    fn stop(&self, path: &str) {
+        let mut roots = self.roots.lock().unwrap();
+        if let Some(state) = roots.remove(path) {
+            state.stop();
+            tracing::info!(root = path, "Stopped watching");
+        }
+    }
+
+    fn stop_all(&self) {
+        let mut roots = self.roots.lock().unwrap();
+        for state in std::mem::take(&mut *roots).into_values() {
+            state.stop();
+        }
+    }
+
+    fn paths(&self) -> Vec<String> {
+        self.roots.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// Whether `path` is a watched root, as given to [`start_watching`]
+    pub fn is_watched(&self, path: &str) -> bool {
+        self.roots.lock().unwrap().contains_key(path)
+    }
+
+    /// Note that kvile is about to write `content` to a file, so the watcher
+    /// doesn't report the change back. Other edits to the file still are.
+    pub fn suppress_write(&self, path: &Path, content: &[u8]) {
+        self.self_writes
+            .lock()
+            .unwrap()
+            .insert(path, content, Instant::now());
+    }
+}
+
+/// The changes a notify event makes to paths the frontend cares about.
+/// Access events and metadata changes other than writes are ignored.
+fn classify_event(event: &Event, ignore: &WorkspaceIgnore) -> Vec<FileChange> {
+    let kind = match event.kind {
+        EventKind::Create(_) => ChangeKind::Created,
+        EventKind::Remove(_) => ChangeKind::Removed,
+        EventKind::Modify(ModifyKind::Name(RenameMode::From)) => ChangeKind::Removed,
+        EventKind::Modify(ModifyKind::Name(RenameMode::To)) => ChangeKind::Created,
+        EventKind::Modify(ModifyKind::Name(_)) => ChangeKind::Renamed,
+        EventKind::Modify(ModifyKind::Metadata(MetadataKind::WriteTime)) => ChangeKind::Modified,
+        EventKind::Modify(ModifyKind::Metadata(_)) => return Vec::new(),
+        EventKind::Modify(_) | EventKind::Any => ChangeKind::Modified,
+        EventKind::Access(_) | EventKind::Other => return Vec::new(),
+    };
+
+    // Both names of a rename come in one event, old first
+    if let (EventKind::Modify(ModifyKind::Name(RenameMode::Both)), [from, to]) =
+        (event.kind, event.paths.as_slice())
+    {
+        return rename_change(from, to, ignore).into_iter().collect();
+    }
+
+    event
+        .paths
+        .iter()
+        .filter_map(|path| {
+            Some(FileChange {
+                path: path.to_string_lossy().to_string(),
+                kind,
+                file_kind: file_kind(path, ignore)?,
+                from: None,
+            })
+        })
+        .collect()
+}
+
+/// The change renaming `from` to `to` makes. The new name decides whether
+/// the frontend cares; renamed to something not shown, such as an editor's
+/// backup, the file is removed.
+fn rename_change(from: &Path, to: &Path, ignore: &WorkspaceIgnore) -> Option<FileChange> {
+    match (file_kind(from, ignore), file_kind(to, ignore)) {
+        (_, Some(file_kind)) => Some(FileChange {
+            path: to.to_string_lossy().to_string(),
+            kind: ChangeKind::Renamed,
+            file_kind,
+            from: Some(from.to_string_lossy().to_string()),
+        }),
+        (Some(file_kind), None) => Some(FileChange {
+            path: from.to_string_lossy().to_string(),
+            kind: ChangeKind::Removed,
+            file_kind,
+            from: None,
+        }),
+        (None, None) => None,
+    }
+}
+
+/// What a path is, if the frontend cares about it
+fn file_kind(path: &Path, ignore: &WorkspaceIgnore) -> Option<FileKind> {
+    if is_env_file(path, ignore) {
+        return Some(FileKind::Env);
+    }
+    if !is_relevant_path(path, ignore) {
+        return None;
+    }
+    if is_http_file(path) {
+        Some(FileKind::Http)
+    } else {
+        Some(FileKind::Other)
+    }
+}
+
+fn is_http_file(path: &Path) -> bool {
+    let name = path.to_string_lossy().to_lowercase();
+    name.ends_with(".http") || name.ends_with(".rest")
+}
+
+/// The .http and .rest files under a root that the file list shows
+fn http_files(root: &Path, ignore: &WorkspaceIgnore) -> BTreeSet<PathBuf> {
+    crate::files::walk(root, ignore, false)
+        .files
+        .into_iter()
+        .filter(|file| file.is_http_file)
+        .map(|file| PathBuf::from(file.path))
+        .collect()
+}
+
+/// Changes collected since the last emit. They are emitted together once the
+/// directory has been quiet for [`QUIET_PERIOD`], or [`MAX_LATENCY`] after
+/// the first of them while changes keep arriving, so every change is emitted
+/// exactly once.
+#[derive(Debug, Default)]
+struct PendingChanges {
+    /// Paths to skip, reloaded after an ignore file changes
+    ignore: WorkspaceIgnore,
+    batch: ChangeBatch,
+    /// Old names of renames whose new name hasn't arrived yet. Any left when
+    /// the batch is emitted were removed, as far as the frontend knows.
+    renamed_from: Vec<RenameFrom>,
+    /// Renames paired from their halves, whose combined event is redundant
+    paired: BTreeSet<usize>,
+    /// The .http and .rest files known to exist, so deleting a directory
+    /// can report the files in it
+    known: BTreeSet<PathBuf>,
+    first_at: Option<Instant>,
+    last_at: Option<Instant>,
+}
+
+/// The old-name half of a rename. Linux pairs the halves with a tracker;
+/// platforms without one send the new name right after.
+#[derive(Debug)]
+struct RenameFrom {
+    tracker: Option<usize>,
+    path: PathBuf,
+}
+
+/// What one emit reports
+#[derive(Debug, Default, PartialEq)]
+struct ChangeBatch {
+    changes: Vec<FileChange>,
+    /// Whether anything in the tree changed, for the legacy event
+    tree_changed: bool,
+    env_changed: BTreeSet<String>,
+    /// Whether an ignore file changed, so the rules need reloading
+    ignore_changed: bool,
+    /// Known .http and .rest files that no longer exist
+    deleted: BTreeSet<String>,
+}
+
+impl PendingChanges {
+    fn add(&mut self, event: &Event, now: Instant) {
+        for path in &event.paths {
+            self.batch.tree_changed |= is_relevant_path(path, &self.ignore);
+            self.batch.ignore_changed |= WorkspaceIgnore::is_ignore_file(path);
+            if is_env_file(path, &self.ignore) {
+                self.batch
+                    .env_changed
+                    .insert(path.to_string_lossy().to_string());
+            }
+        }
+        let tracker = event.attrs.tracker();
+        let changes = match (event.kind, event.paths.as_slice()) {
+            (EventKind::Modify(ModifyKind::Name(RenameMode::From)), [path]) => {
+                self.renamed_from.push(RenameFrom {
+                    tracker,
+                    path: path.clone(),
+                });
+                Vec::new()
+            }
+            (EventKind::Modify(ModifyKind::Name(RenameMode::To)), [path]) => {
+                match self
+                    .renamed_from
+                    .iter()
+                    .rposition(|from| from.tracker == tracker)
+                {
+                    Some(index) => {
+                        let from = self.renamed_from.remove(index);
+                        self.paired.extend(tracker);
+                        self.moved(&from.path, path);
+                        rename_change(&from.path, path, &self.ignore)
+                            .into_iter()
+                            .collect()
                     }
+                    None => classify_event(event, &self.ignore),
                 }
-                Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => {
-                    break;
+            }
+            (EventKind::Modify(ModifyKind::Name(RenameMode::Both)), _)
+                if tracker.is_some_and(|tracker| self.paired.contains(&tracker)) =>
+            {
+                Vec::new()
+            }
+            (EventKind::Modify(ModifyKind::Name(RenameMode::Both)), [from, to]) => {
+                self.moved(from, to);
+                classify_event(event, &self.ignore)
+            }
+            _ => classify_event(event, &self.ignore),
+        };
+        if let EventKind::Remove(_) = event.kind {
+            for path in &event.paths {
+                self.forget(path);
+            }
+        }
+        for change in changes {
+            let path = Path::new(&change.path);
+            match change.kind {
+                ChangeKind::Removed => self.forget(path),
+                ChangeKind::Created if change.file_kind == FileKind::Http => {
+                    self.known.insert(path.to_path_buf());
                 }
+                _ => {}
             }
+            coalesce(&mut self.batch.changes, change);
         }
-    });
+        self.first_at.get_or_insert(now);
+        self.last_at = Some(now);
+    }
 
-    let mut guard = WATCHER.lock().unwrap();
-    *guard = Some(WatcherState {
-        _watcher: watcher,
-        watched_path: directory,
-    });
+    /// When the pending changes should be emitted, if there are any
+    fn due_at(&self) -> Option<Instant> {
+        let (first, last) = (self.first_at?, self.last_at?);
+        Some((last + QUIET_PERIOD).min(first + MAX_LATENCY))
+    }
 
-    Ok(())
+    /// The pending changes, if they are due
+    fn take_due(&mut self, now: Instant) -> Option<ChangeBatch> {
+        if self.due_at()? > now {
+            return None;
+        }
+        for from in std::mem::take(&mut self.renamed_from) {
+            self.forget(&from.path);
+            if let Some(file_kind) = file_kind(&from.path, &self.ignore) {
+                let change = FileChange {
+                    path: from.path.to_string_lossy().to_string(),
+                    kind: ChangeKind::Removed,
+                    file_kind,
+                    from: None,
+                };
+                coalesce(&mut self.batch.changes, change);
+            }
+        }
+        self.paired.clear();
+        self.first_at = None;
+        self.last_at = None;
+        // Replaced since, such as by a checkout rewriting it
+        self.batch.deleted.retain(|path| !Path::new(path).exists());
+        Some(std::mem::take(&mut self.batch))
+    }
+
+    /// Known files at or under a removed path, which are deleted
+    fn take_known(&mut self, path: &Path) -> Vec<PathBuf> {
+        let under: Vec<PathBuf> = self
+            .known
+            .range(path.to_path_buf()..)
+            .take_while(|known| known.starts_with(path))
+            .cloned()
+            .collect();
+        for known in &under {
+            self.known.remove(known);
+        }
+        under
+    }
+
+    fn forget(&mut self, path: &Path) {
+        for known in self.take_known(path) {
+            self.batch
+                .deleted
+                .insert(known.to_string_lossy().to_string());
+        }
+    }
+
+    /// Follow known files to a renamed path, or a renamed directory
+    fn moved(&mut self, from: &Path, to: &Path) {
+        for known in self.take_known(from) {
+            let moved = match known.strip_prefix(from) {
+                Ok(rest) if !rest.as_os_str().is_empty() => to.join(rest),
+                _ => to.to_path_buf(),
+            };
+            if is_http_file(&moved) {
+                self.known.insert(moved);
+            } else {
+                self.batch
+                    .deleted
+                    .insert(known.to_string_lossy().to_string());
+            }
+        }
+    }
 }
 
-/// Stop watching the current directory
-#[tauri::command]
-pub fn stop_watching() -> Result<(), String> {
-    let mut guard = WATCHER.lock().unwrap();
-    *guard = None;
-    Ok(())
+/// Recent writes kvile made itself: a hash of the content written, and when
+/// the entry expires
+#[derive(Debug, Default)]
+struct SelfWrites(BTreeMap<PathBuf, (u64, Instant)>);
+
+impl SelfWrites {
+    fn insert(&mut self, path: &Path, content: &[u8], now: Instant) {
+        self.0.retain(|_, (_, expires_at)| *expires_at > now);
+        self.0.insert(
+            path.to_path_buf(),
+            (content_hash(content), now + SELF_WRITE_TTL),
+        );
+    }
+
+    /// Whether a file still holds what kvile wrote to it. Once it holds
+    /// anything else, someone else edited it since.
+    fn is_self_write(&self, path: &Path, now: Instant) -> bool {
+        let Some((hash, expires_at)) = self.0.get(path) else {
+            return false;
+        };
+        *expires_at > now
+            && std::fs::read(path).is_ok_and(|content| content_hash(&content) == *hash)
+    }
+
+    /// Remove kvile's own writes from a batch. A batch left with nothing
+    /// else is not a tree change either.
+    fn remove_from(&self, batch: &mut ChangeBatch, now: Instant) {
+        if self.0.is_empty() {
+            return;
+        }
+        let is_self_write = |path: &str, kind: ChangeKind| {
+            matches!(kind, ChangeKind::Created | ChangeKind::Modified)
+                && self.is_self_write(Path::new(path), now)
+        };
+        let before = batch.changes.len();
+        batch
+            .changes
+            .retain(|change| !is_self_write(&change.path, change.kind));
+        batch
+            .env_changed
+            .retain(|path| !is_self_write(path, ChangeKind::Modified));
+        if batch.changes.len() < before && batch.changes.is_empty() {
+            batch.tree_changed = false;
+        }
+    }
 }
 
-/// Get the currently watched path
-#[tauri::command]
-pub fn get_watched_path() -> Option<String> {
-    let guard = WATCHER.lock().unwrap();
-    guard.as_ref().map(|s| s.watched_path.clone())
+fn content_hash(content: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Add a change to those waiting to be emitted, merging it with an earlier
+/// change to the same path: a file created then written is still created,
+/// and one created then removed never existed as far as the frontend knows
+fn coalesce(changes: &mut Vec<FileChange>, change: FileChange) {
+    let Some(index) = changes.iter().position(|c| c.path == change.path) else {
+        changes.push(change);
+        return;
+    };
+    let earlier = &mut changes[index];
+    match (earlier.kind, change.kind) {
+        (ChangeKind::Created, ChangeKind::Removed) => {
+            changes.remove(index);
+        }
+        (ChangeKind::Created | ChangeKind::Renamed, ChangeKind::Modified) => {}
+        (ChangeKind::Removed, ChangeKind::Created) => earlier.kind = ChangeKind::Modified,
+        _ => *earlier = change,
+    }
 }
 
 /// Check if a path is relevant for our file tree
-fn is_relevant_path(path: &Path) -> bool {
+fn is_relevant_path(path: &Path, ignore: &WorkspaceIgnore) -> bool {
     let path_str = path.to_string_lossy().to_lowercase();
+    let is_dir = path.is_dir();
 
-    // Skip hidden files and common non-relevant directories
-    if path_str.contains("/.")
-        || path_str.contains("/node_modules/")
-        || path_str.contains("/target/")
-    {
+    // Skip hidden files and ignored paths
+    if path_str.contains("/.") || ignore.is_ignored(path, is_dir) {
         return false;
     }
 
     // Check if it's a relevant file type or a directory
-    if path.is_dir() {
+    if is_dir {
         return true;
     }
 
@@ -122,3 +709,656 @@ fn is_relevant_path(path: &Path) -> bool {
 
     name.ends_with(".http") || name.ends_with(".rest") || name.contains(".env.json")
 }
+
+/// Check if a path is an env file that environments are loaded from. Private
+/// env files are usually ignored themselves, so only their directory counts.
+fn is_env_file(path: &Path, ignore: &WorkspaceIgnore) -> bool {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy())
+        .unwrap_or_default();
+    if !crate::files::is_env_file_name(&name) {
+        return false;
+    }
+
+    // The file itself may be hidden (.env), its directories may not
+    !path.parent().is_some_and(|dir| {
+        ignore.is_ignored(dir, true)
+            || dir.components().any(|c| {
+                let c = c.as_os_str().to_string_lossy();
+                c.starts_with('.') && c != "." && c != ".."
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_env_file() {
+        let ignore = WorkspaceIgnore::default();
+        assert!(is_env_file(Path::new("/ws/http-client.env.json"), &ignore));
+        assert!(is_env_file(
+            Path::new("/ws/api/http-client.private.env.json"),
+            &ignore
+        ));
+        assert!(is_env_file(Path::new("/ws/.env"), &ignore));
+        assert!(is_env_file(Path::new("/ws/HTTP-CLIENT.ENV.JSON"), &ignore));
+        assert!(is_env_file(
+            Path::new("/ws/envs/orders.http-client.private.env.json"),
+            &ignore
+        ));
+
+        assert!(!is_env_file(Path::new("/ws/api.http"), &ignore));
+        assert!(!is_env_file(Path::new("/ws/other.env.json"), &ignore));
+        assert!(!is_env_file(Path::new("/ws/.env.local"), &ignore));
+        assert!(!is_env_file(
+            Path::new("/ws/.git/http-client.env.json"),
+            &ignore
+        ));
+        assert!(!is_env_file(
+            Path::new("/ws/node_modules/pkg/.env"),
+            &ignore
+        ));
+    }
+
+    fn event(kind: EventKind, paths: &[&str]) -> Event {
+        paths
+            .iter()
+            .fold(Event::new(kind), |event, path| event.add_path(path.into()))
+    }
+
+    fn classify(event: &Event) -> Vec<FileChange> {
+        classify_event(event, &WorkspaceIgnore::default())
+    }
+
+    fn change(path: &str, kind: ChangeKind, file_kind: FileKind) -> FileChange {
+        FileChange {
+            path: path.to_string(),
+            kind,
+            file_kind,
+            from: None,
+        }
+    }
+
+    #[test]
+    fn test_classify_event() {
+        use notify::event::{AccessKind, CreateKind, DataChange, RemoveKind};
+
+        assert_eq!(
+            classify(&event(
+                EventKind::Create(CreateKind::File),
+                &["/ws/api/users.http", "/ws/notes.md"]
+            )),
+            vec![change(
+                "/ws/api/users.http",
+                ChangeKind::Created,
+                FileKind::Http
+            )]
+        );
+        assert_eq!(
+            classify(&event(
+                EventKind::Modify(ModifyKind::Data(DataChange::Content)),
+                &["/ws/http-client.private.env.json"]
+            )),
+            vec![change(
+                "/ws/http-client.private.env.json",
+                ChangeKind::Modified,
+                FileKind::Env
+            )]
+        );
+        assert_eq!(
+            classify(&event(
+                EventKind::Remove(RemoveKind::File),
+                &["/ws/.env", "/ws/users.rest"]
+            )),
+            vec![
+                change("/ws/.env", ChangeKind::Removed, FileKind::Env),
+                change("/ws/users.rest", ChangeKind::Removed, FileKind::Http),
+            ]
+        );
+        assert_eq!(
+            classify(&event(
+                EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+                &["/ws/old.http", "/ws/new.http"]
+            )),
+            vec![FileChange {
+                from: Some("/ws/old.http".to_string()),
+                ..change("/ws/new.http", ChangeKind::Renamed, FileKind::Http)
+            }]
+        );
+        // Saved through a temporary file, or renamed out of view
+        assert_eq!(
+            classify(&event(
+                EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+                &["/ws/users.http", "/ws/users.http~"]
+            )),
+            vec![change(
+                "/ws/users.http",
+                ChangeKind::Removed,
+                FileKind::Http
+            )]
+        );
+        assert_eq!(
+            classify(&event(
+                EventKind::Modify(ModifyKind::Name(RenameMode::To)),
+                &["/ws/users.http"]
+            )),
+            vec![change(
+                "/ws/users.http",
+                ChangeKind::Created,
+                FileKind::Http
+            )]
+        );
+
+        // Reads, permission changes and paths the tree hides are skipped
+        assert!(classify(&event(
+            EventKind::Access(AccessKind::Read),
+            &["/ws/users.http"]
+        ))
+        .is_empty());
+        assert!(classify(&event(
+            EventKind::Modify(ModifyKind::Metadata(MetadataKind::Permissions)),
+            &["/ws/users.http"]
+        ))
+        .is_empty());
+        assert!(classify(&event(
+            EventKind::Create(CreateKind::File),
+            &["/ws/.git/index", "/ws/target/debug/x.http"]
+        ))
+        .is_empty());
+    }
+
+    #[test]
+    fn test_pending_changes_emit_bursts_once() {
+        use notify::event::{CreateKind, DataChange};
+
+        let modify = |path| {
+            event(
+                EventKind::Modify(ModifyKind::Data(DataChange::Content)),
+                &[path],
+            )
+        };
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut pending = PendingChanges::default();
+        assert_eq!(pending.take_due(at(0)), None);
+
+        // A burst of saves is one emit, once the directory is quiet
+        pending.add(&modify("/ws/a.http"), at(0));
+        pending.add(&modify("/ws/a.http"), at(100));
+        pending.add(
+            &event(EventKind::Create(CreateKind::File), &["/ws/b.http"]),
+            at(200),
+        );
+        pending.add(&modify("/ws/http-client.env.json"), at(300));
+        assert_eq!(pending.take_due(at(700)), None);
+        let batch = pending.take_due(at(800)).unwrap();
+        assert_eq!(
+            batch.changes,
+            vec![
+                change("/ws/a.http", ChangeKind::Modified, FileKind::Http),
+                change("/ws/b.http", ChangeKind::Created, FileKind::Http),
+                change(
+                    "/ws/http-client.env.json",
+                    ChangeKind::Modified,
+                    FileKind::Env
+                ),
+            ]
+        );
+        assert!(batch.tree_changed);
+        assert_eq!(
+            batch.env_changed,
+            BTreeSet::from(["/ws/http-client.env.json".to_string()])
+        );
+        assert_eq!(pending.take_due(at(5000)), None);
+
+        // A change right after an emit still gets its own
+        pending.add(&modify("/ws/a.http"), at(810));
+        let batch = pending.take_due(at(1310)).unwrap();
+        assert_eq!(
+            batch.changes,
+            vec![change("/ws/a.http", ChangeKind::Modified, FileKind::Http)]
+        );
+
+        // Changes that never stop are emitted every MAX_LATENCY
+        let mut emits = Vec::new();
+        for ms in (2000..6000).step_by(50) {
+            pending.add(&modify("/ws/a.http"), at(ms));
+            emits.extend(pending.take_due(at(ms)).map(|_| ms));
+        }
+        assert_eq!(emits, vec![4000]);
+        assert!(pending.take_due(at(6500)).is_some());
+
+        // Nothing relevant changed, so nothing is emitted
+        pending.add(&modify("/ws/readme.md"), at(7000));
+        assert_eq!(pending.take_due(at(7500)), Some(ChangeBatch::default()));
+    }
+
+    #[test]
+    fn test_pending_changes_pair_renames() {
+        let start = Instant::now();
+        let rename = |mode, path: &str, tracker: Option<usize>| {
+            let event = event(EventKind::Modify(ModifyKind::Name(mode)), &[path]);
+            match tracker {
+                Some(tracker) => event.set_tracker(tracker),
+                None => event,
+            }
+        };
+        let take = |pending: &mut PendingChanges| {
+            pending
+                .take_due(start + Duration::from_secs(60))
+                .unwrap()
+                .changes
+        };
+        let renamed = |from: &str, to: &str| FileChange {
+            from: Some(from.to_string()),
+            ..change(to, ChangeKind::Renamed, FileKind::Http)
+        };
+        let mut pending = PendingChanges::default();
+
+        // Linux sends both halves, paired by a tracker, then both together
+        pending.add(&rename(RenameMode::From, "/ws/auth.http", Some(7)), start);
+        pending.add(&rename(RenameMode::From, "/ws/other.http", Some(8)), start);
+        pending.add(
+            &rename(RenameMode::To, "/ws/authentication.http", Some(7)),
+            start,
+        );
+        pending.add(
+            &event(
+                EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+                &["/ws/auth.http", "/ws/authentication.http"],
+            )
+            .set_tracker(7),
+            start,
+        );
+        // The other file was moved out of the workspace
+        assert_eq!(
+            take(&mut pending),
+            vec![
+                renamed("/ws/auth.http", "/ws/authentication.http"),
+                change("/ws/other.http", ChangeKind::Removed, FileKind::Http),
+            ]
+        );
+
+        // Windows sends the halves one after the other
+        pending.add(&rename(RenameMode::From, "/ws/a.http", None), start);
+        pending.add(&rename(RenameMode::To, "/ws/b.http", None), start);
+        assert_eq!(
+            take(&mut pending),
+            vec![renamed("/ws/a.http", "/ws/b.http")]
+        );
+
+        // Moved in from outside, or renamed to a name the tree hides
+        pending.add(&rename(RenameMode::To, "/ws/moved.http", Some(9)), start);
+        pending.add(&rename(RenameMode::From, "/ws/users.http", Some(10)), start);
+        pending.add(&rename(RenameMode::To, "/ws/users.http~", Some(10)), start);
+        assert_eq!(
+            take(&mut pending),
+            vec![
+                change("/ws/moved.http", ChangeKind::Created, FileKind::Http),
+                change("/ws/users.http", ChangeKind::Removed, FileKind::Http),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pending_changes_track_deleted_files() {
+        use notify::event::{CreateKind, RemoveKind};
+
+        let now = Instant::now();
+        let take = |pending: &mut PendingChanges| {
+            let deleted = pending.take_due(now + MAX_LATENCY).unwrap().deleted;
+            deleted.into_iter().collect::<Vec<_>>()
+        };
+        let mut pending = PendingChanges {
+            known: [
+                "/ws/a.http",
+                "/ws/api/b.http",
+                "/ws/api/v1/c.rest",
+                "/ws/api.http",
+            ]
+            .into_iter()
+            .map(PathBuf::from)
+            .collect(),
+            ..Default::default()
+        };
+
+        // A renamed directory takes its files along
+        pending.add(
+            &event(
+                EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+                &["/ws/api", "/ws/apis"],
+            ),
+            now,
+        );
+        pending.add(
+            &event(EventKind::Create(CreateKind::File), &["/ws/new.http"]),
+            now,
+        );
+        assert!(take(&mut pending).is_empty());
+
+        pending.add(
+            &event(EventKind::Remove(RemoveKind::Folder), &["/ws/apis"]),
+            now,
+        );
+        pending.add(
+            &event(EventKind::Remove(RemoveKind::File), &["/ws/new.http"]),
+            now,
+        );
+        assert_eq!(
+            take(&mut pending),
+            vec!["/ws/apis/b.http", "/ws/apis/v1/c.rest", "/ws/new.http"]
+        );
+        assert_eq!(
+            pending.known,
+            BTreeSet::from([PathBuf::from("/ws/a.http"), PathBuf::from("/ws/api.http")])
+        );
+    }
+
+    #[test]
+    fn test_deleted_files_while_watching() {
+        let dir = tempfile::Builder::new().prefix("ws").tempdir().unwrap();
+        let root = dir.path();
+        for file in [
+            "users.http",
+            "api/orders.http",
+            "api/v1/old.rest",
+            "api/notes.md",
+        ] {
+            let path = root.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "GET /").unwrap();
+        }
+        let watchers = WatcherManager::default();
+        let (tx, rx) = channel();
+        watchers
+            .watch(
+                &root.to_string_lossy(),
+                WorkspaceIgnore::default,
+                move |batch| {
+                    let _ = tx.send(batch.deleted);
+                },
+            )
+            .unwrap();
+        assert!(watchers.is_watched(&root.to_string_lossy()));
+        assert!(!watchers.is_watched(&root.join("api").to_string_lossy()));
+        // Give the event thread time to list the files
+        thread::sleep(QUIET_PERIOD);
+
+        std::fs::remove_file(root.join("users.http")).unwrap();
+        std::fs::remove_dir_all(root.join("api")).unwrap();
+        let mut deleted = BTreeSet::new();
+        while deleted.len() < 3 {
+            let batch = rx
+                .recv_timeout(Duration::from_secs(10))
+                .expect("no deletions in time");
+            deleted.extend(batch);
+        }
+        let path = |file: &str| root.join(file).to_string_lossy().to_string();
+        assert_eq!(
+            deleted,
+            BTreeSet::from([
+                path("api/orders.http"),
+                path("api/v1/old.rest"),
+                path("users.http"),
+            ])
+        );
+        watchers.stop_all();
+        assert!(!watchers.is_watched(&root.to_string_lossy()));
+    }
+
+    #[test]
+    fn test_coalesce_changes() {
+        let mut changes = Vec::new();
+        let http = |kind| change("/ws/a.http", kind, FileKind::Http);
+        coalesce(&mut changes, http(ChangeKind::Created));
+        coalesce(&mut changes, http(ChangeKind::Modified));
+        assert_eq!(changes, vec![http(ChangeKind::Created)]);
+        coalesce(&mut changes, http(ChangeKind::Removed));
+        assert!(changes.is_empty());
+
+        // Editors that save by replacing the file
+        coalesce(&mut changes, http(ChangeKind::Removed));
+        coalesce(&mut changes, http(ChangeKind::Created));
+        assert_eq!(changes, vec![http(ChangeKind::Modified)]);
+
+        let env = change("/ws/.env", ChangeKind::Modified, FileKind::Env);
+        coalesce(&mut changes, env.clone());
+        assert_eq!(changes, vec![http(ChangeKind::Modified), env]);
+    }
+
+    #[test]
+    fn test_is_relevant_path() {
+        let ignore = WorkspaceIgnore::default();
+        assert!(is_relevant_path(Path::new("/ws/api/users.http"), &ignore));
+        assert!(is_relevant_path(Path::new("/ws/users.rest"), &ignore));
+        assert!(is_relevant_path(
+            Path::new("/ws/http-client.env.json"),
+            &ignore
+        ));
+
+        assert!(!is_relevant_path(Path::new("/ws/.env"), &ignore));
+        assert!(!is_relevant_path(Path::new("/ws/readme.md"), &ignore));
+        assert!(!is_relevant_path(
+            Path::new("/ws/target/debug/x.http"),
+            &ignore
+        ));
+    }
+
+    #[test]
+    fn test_ignore_files_hide_paths() {
+        use notify::event::DataChange;
+
+        // Temporary directories are hidden by default
+        let dir = tempfile::Builder::new().prefix("ws").tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(
+            root.join(".gitignore"),
+            "build/\nhttp-client.private.env.json\n",
+        )
+        .unwrap();
+        std::fs::create_dir(root.join("build")).unwrap();
+        let ignore = WorkspaceIgnore::load(root);
+
+        assert!(is_relevant_path(&root.join("api.http"), &ignore));
+        assert!(!is_relevant_path(&root.join("build"), &ignore));
+        assert!(!is_relevant_path(
+            &root.join("build/generated.http"),
+            &ignore
+        ));
+        // Private env files are ignored but still load environments
+        assert!(is_env_file(
+            &root.join("http-client.private.env.json"),
+            &ignore
+        ));
+        assert!(!is_env_file(
+            &root.join("build/http-client.env.json"),
+            &ignore
+        ));
+        // Ignore files replace the node_modules and target defaults
+        assert!(is_relevant_path(&root.join("target/x.http"), &ignore));
+
+        let mut pending = PendingChanges {
+            ignore,
+            ..Default::default()
+        };
+        let gitignore = root.join(".gitignore").to_string_lossy().to_string();
+        pending.add(
+            &event(
+                EventKind::Modify(ModifyKind::Data(DataChange::Content)),
+                &[&gitignore],
+            ),
+            Instant::now(),
+        );
+        assert!(
+            pending
+                .take_due(Instant::now() + MAX_LATENCY)
+                .unwrap()
+                .ignore_changed
+        );
+    }
+
+    #[test]
+    fn test_self_writes_are_not_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("users.http");
+        let env = dir.path().join("http-client.env.json");
+        let other = dir.path().join("orders.http");
+        let path = |path: &Path| path.to_string_lossy().to_string();
+        let batch = || ChangeBatch {
+            changes: vec![
+                change(&path(&file), ChangeKind::Modified, FileKind::Http),
+                change(&path(&env), ChangeKind::Created, FileKind::Env),
+            ],
+            tree_changed: true,
+            env_changed: BTreeSet::from([path(&env)]),
+            ..ChangeBatch::default()
+        };
+        let now = Instant::now();
+        let mut writes = SelfWrites::default();
+        writes.insert(&file, b"GET /users", now);
+        writes.insert(&env, b"{}", now);
+        std::fs::write(&file, "GET /users").unwrap();
+        std::fs::write(&env, "{}").unwrap();
+        std::fs::write(&other, "GET /orders").unwrap();
+
+        let mut own = batch();
+        writes.remove_from(&mut own, now);
+        assert_eq!(
+            own,
+            ChangeBatch {
+                tree_changed: false,
+                ..ChangeBatch::default()
+            }
+        );
+
+        // Someone else's edits, and changes to other files, still count
+        std::fs::write(&file, "GET /users?page=2").unwrap();
+        let mut edited = batch();
+        edited
+            .changes
+            .push(change(&path(&other), ChangeKind::Created, FileKind::Http));
+        writes.remove_from(&mut edited, now);
+        assert_eq!(
+            edited.changes,
+            vec![
+                change(&path(&file), ChangeKind::Modified, FileKind::Http),
+                change(&path(&other), ChangeKind::Created, FileKind::Http),
+            ]
+        );
+        assert!(edited.tree_changed);
+        assert!(edited.env_changed.is_empty());
+
+        // Removing a file kvile wrote is reported
+        std::fs::write(&file, "GET /users").unwrap();
+        let mut removed = ChangeBatch {
+            changes: vec![change(&path(&file), ChangeKind::Removed, FileKind::Http)],
+            ..batch()
+        };
+        writes.remove_from(&mut removed, now);
+        assert_eq!(removed.changes.len(), 1);
+
+        // Entries expire, so a later edit back to the same content is reported
+        let mut later = batch();
+        writes.remove_from(&mut later, now + SELF_WRITE_TTL);
+        assert_eq!(later, batch());
+        writes.insert(&other, b"GET /orders", now + SELF_WRITE_TTL);
+        assert_eq!(writes.0.len(), 1);
+    }
+
+    #[test]
+    fn test_watch_roots_lifecycle() {
+        use std::sync::mpsc::{Receiver, TryRecvError};
+
+        let first = tempfile::Builder::new().prefix("ws").tempdir().unwrap();
+        let second = tempfile::Builder::new().prefix("ws").tempdir().unwrap();
+        let watchers = WatcherManager::default();
+        let watch = |dir: &Path| {
+            let (tx, rx) = channel();
+            watchers
+                .watch(
+                    &dir.to_string_lossy(),
+                    WorkspaceIgnore::default,
+                    move |batch| {
+                        let _ = tx.send(batch);
+                    },
+                )
+                .unwrap();
+            rx
+        };
+        let changes = |rx: &Receiver<ChangeBatch>| {
+            rx.recv_timeout(Duration::from_secs(10))
+                .expect("no batch in time")
+                .changes
+        };
+        // Stopping joins the event thread, which drops the sender
+        let finished = |rx: &Receiver<ChangeBatch>| {
+            rx.try_iter().for_each(drop);
+            rx.try_recv() == Err(TryRecvError::Disconnected)
+        };
+        let path = |dir: &Path| dir.to_string_lossy().to_string();
+
+        let first_rx = watch(first.path());
+        let second_rx = watch(second.path());
+        // Already watched, so its sender is dropped unused
+        let again_rx = watch(first.path());
+        assert!(again_rx.recv().is_err());
+        let mut roots = vec![path(first.path()), path(second.path())];
+        roots.sort();
+        assert_eq!(watchers.paths(), roots);
+
+        // Each root reports its own changes
+        let file = first.path().join("users.http");
+        std::fs::write(&file, "GET /users").unwrap();
+        assert!(changes(&first_rx)
+            .iter()
+            .any(|change| change.path == path(&file)));
+        let file = second.path().join("orders.http");
+        std::fs::write(&file, "GET /orders").unwrap();
+        assert!(changes(&second_rx)
+            .iter()
+            .any(|change| change.path == path(&file)));
+
+        // Removing a root ends its thread, leaving the other running
+        watchers.stop(&path(first.path()));
+        assert!(finished(&first_rx));
+        assert_eq!(watchers.paths(), vec![path(second.path())]);
+        watchers.stop(&path(first.path()));
+
+        watchers.stop_all();
+        assert!(finished(&second_rx));
+        assert!(watchers.paths().is_empty());
+    }
+
+    #[test]
+    fn test_no_events_after_stop() {
+        use std::sync::atomic::AtomicUsize;
+
+        let dir = tempfile::Builder::new().prefix("ws").tempdir().unwrap();
+        let root = dir.path().to_string_lossy().to_string();
+        let watchers = WatcherManager::default();
+        let late = Arc::new(AtomicUsize::new(0));
+
+        for i in 0..50 {
+            let stopped = Arc::new(AtomicBool::new(false));
+            let (stopped_flag, late) = (stopped.clone(), late.clone());
+            watchers
+                .watch(&root, WorkspaceIgnore::default, move |_| {
+                    if stopped_flag.load(Ordering::SeqCst) {
+                        late.fetch_add(1, Ordering::SeqCst);
+                    }
+                })
+                .unwrap();
+            std::fs::write(dir.path().join(format!("{}.http", i % 3)), "GET /").unwrap();
+            watchers.stop(&root);
+            stopped.store(true, Ordering::SeqCst);
+        }
+
+        // Long enough for any batch a leftover thread held to come due
+        thread::sleep(QUIET_PERIOD * 2);
+        assert_eq!(late.load(Ordering::SeqCst), 0);
+        assert!(watchers.paths().is_empty());
+    }
+}
@@ -0,0 +1,267 @@
+//! Workspace settings kept in `kvile.json` at the workspace root, so they can
+//! be committed with the .http files. Settings that only concern one machine,
+//! such as trusting `$exec` commands, stay in the history database.
+//!
+//! Where both have a setting, `kvile.json` wins: its `environment` is used
+//! over the one last picked on this machine, and its `ignore` patterns
+//! replace the machine's. The app and kvile-cli both go through
+//! [`WorkspaceSettings::default_environment`] and
+//! [`WorkspaceSettings::ignore_patterns`] for this.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// The settings file, at the workspace root
+pub const SETTINGS_FILE: &str = "kvile.json";
+
+/// Settings shared by everyone working in a workspace. Keys kvile doesn't
+/// know are kept in `other` and written back as they were.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorkspaceSettings {
+    /// Headers sent with every request that doesn't set them itself
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub default_headers: BTreeMap<String, String>,
+    /// Milliseconds before a request is given up on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    /// Proxy URL all requests go through
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    /// Environment requests use when they don't name one, over the one last
+    /// picked on this machine
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub environment: Option<String>,
+    /// Paths the file list and watcher skip, in .gitignore syntax, in place
+    /// of the patterns set on this machine
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ignore: Vec<String>,
+    /// Lint rules turned on or off by code, such as `"get-with-body": false`.
+    /// Rules not listed are on.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub lint: BTreeMap<String, bool>,
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+/// Keys of the fields above, which replace the file's on save
+const KNOWN_KEYS: [&str; 6] = [
+    "default_headers",
+    "timeout_ms",
+    "proxy",
+    "environment",
+    "ignore",
+    "lint",
+];
+
+/// Settings as loaded, with what was wrong in the file
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct LoadedSettings {
+    pub settings: WorkspaceSettings,
+    /// Values of the wrong type, which were left out, or a file that isn't
+    /// a JSON object
+    pub warnings: Vec<String>,
+}
+
+impl WorkspaceSettings {
+    /// A workspace's settings. Without a settings file they are all
+    /// defaults; a value of the wrong type is left out with a warning
+    /// rather than failing the rest.
+    pub fn load(workspace: &Path) -> LoadedSettings {
+        let path = settings_path(workspace);
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return LoadedSettings::default(),
+            Err(e) => {
+                return LoadedSettings {
+                    warnings: vec![format!("Failed to read {}: {}", SETTINGS_FILE, e)],
+                    ..Default::default()
+                }
+            }
+        };
+        let object = match serde_json::from_str(&content) {
+            Ok(Value::Object(object)) => object,
+            Ok(_) => {
+                return LoadedSettings {
+                    warnings: vec![format!("{} is not a JSON object", SETTINGS_FILE)],
+                    ..Default::default()
+                }
+            }
+            Err(e) => {
+                return LoadedSettings {
+                    warnings: vec![format!("{} is not valid JSON: {}", SETTINGS_FILE, e)],
+                    ..Default::default()
+                }
+            }
+        };
+        Self::from_object(object)
+    }
+
+    fn from_object(object: Map<String, Value>) -> LoadedSettings {
+        let mut valid = Map::new();
+        let mut warnings = Vec::new();
+        for (key, value) in object {
+            // Each value alone, so one bad value doesn't cost the others
+            let single = Map::from_iter([(key.clone(), value.clone())]);
+            match serde_json::from_value::<WorkspaceSettings>(Value::Object(single)) {
+                Ok(_) => {
+                    valid.insert(key, value);
+                }
+                Err(e) => warnings.push(format!("{}: '{}' is ignored: {}", SETTINGS_FILE, key, e)),
+            }
+        }
+        LoadedSettings {
+            settings: serde_json::from_value(Value::Object(valid)).unwrap_or_default(),
+            warnings,
+        }
+    }
+
+    /// The environment for requests that don't name one: this file's, else
+    /// `local`, the one last picked on this machine
+    pub fn default_environment(&self, local: Option<String>) -> Option<String> {
+        self.environment.clone().or(local)
+    }
+
+    /// Extra ignore patterns: this file's, else `local`, the machine's
+    /// [`crate::history::IGNORE_PATTERNS_KEY`] setting with one per line
+    pub fn ignore_patterns<'a>(&'a self, local: Option<&'a str>) -> Vec<&'a str> {
+        if self.ignore.is_empty() {
+            local.into_iter().flat_map(str::lines).collect()
+        } else {
+            self.ignore.iter().map(String::as_str).collect()
+        }
+    }
+
+    /// Write the settings to the workspace's settings file, replacing it
+    /// atomically. Keys the file has that kvile doesn't know are kept, even
+    /// when `other` doesn't hold them.
+    pub fn save(&self, workspace: &Path) -> Result<(), String> {
+        let path = settings_path(workspace);
+        let mut object = match std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+        {
+            Some(Value::Object(object)) => object,
+            _ => Map::new(),
+        };
+        object.retain(|key, _| !KNOWN_KEYS.contains(&key.as_str()));
+        match serde_json::to_value(self).map_err(|e| e.to_string())? {
+            Value::Object(settings) => object.extend(settings),
+            _ => unreachable!("settings serialize to an object"),
+        }
+        let content = serde_json::to_string_pretty(&object).map_err(|e| e.to_string())? + "\n";
+
+        let temp = workspace.join(format!(".{}.{}.tmp", SETTINGS_FILE, std::process::id()));
+        std::fs::write(&temp, content)
+            .and_then(|_| std::fs::rename(&temp, &path))
+            .map_err(|e| {
+                let _ = std::fs::remove_file(&temp);
+                format!("Failed to write {}: {}", SETTINGS_FILE, e)
+            })
+    }
+}
+
+fn settings_path(workspace: &Path) -> PathBuf {
+    workspace.join(SETTINGS_FILE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_file_is_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(
+            WorkspaceSettings::load(dir.path()),
+            LoadedSettings::default()
+        );
+
+        std::fs::write(dir.path().join(SETTINGS_FILE), r#"{"timeout_ms": 500}"#).unwrap();
+        let loaded = WorkspaceSettings::load(dir.path());
+        assert_eq!(loaded.settings.timeout_ms, Some(500));
+        assert_eq!(loaded.settings.proxy, None);
+        assert!(loaded.settings.default_headers.is_empty());
+    }
+
+    #[test]
+    fn test_wrong_types_warn() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join(SETTINGS_FILE),
+            r#"{"timeout_ms": "soon", "ignore": ["build/"], "default_headers": {"X-Team": 1}}"#,
+        )
+        .unwrap();
+
+        let loaded = WorkspaceSettings::load(dir.path());
+        assert_eq!(loaded.settings.ignore, vec!["build/"]);
+        assert_eq!(loaded.settings.timeout_ms, None);
+        assert!(loaded.settings.default_headers.is_empty());
+        assert_eq!(loaded.warnings.len(), 2);
+        assert!(loaded.warnings[0].starts_with("kvile.json: 'timeout_ms' is ignored: invalid type"));
+        assert!(loaded.warnings[1].starts_with("kvile.json: 'default_headers' is ignored"));
+
+        std::fs::write(dir.path().join(SETTINGS_FILE), "[1]").unwrap();
+        let loaded = WorkspaceSettings::load(dir.path());
+        assert_eq!(loaded.settings, WorkspaceSettings::default());
+        assert_eq!(loaded.warnings, vec!["kvile.json is not a JSON object"]);
+    }
+
+    #[test]
+    fn test_save_preserves_unknown_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SETTINGS_FILE);
+        std::fs::write(
+            &path,
+            r#"{"$schema": "./kvile.schema.json", "proxy": "http://old:8080", "plugins": {"a": [1, 2]}}"#,
+        )
+        .unwrap();
+
+        let mut settings = WorkspaceSettings::load(dir.path()).settings;
+        assert_eq!(settings.other["plugins"], serde_json::json!({"a": [1, 2]}));
+        settings.proxy = None;
+        settings.timeout_ms = Some(2000);
+        settings.save(dir.path()).unwrap();
+
+        let saved: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(
+            saved,
+            serde_json::json!({
+                "$schema": "./kvile.schema.json",
+                "plugins": {"a": [1, 2]},
+                "timeout_ms": 2000
+            })
+        );
+
+        // Unknown keys survive settings that were built without them
+        WorkspaceSettings {
+            environment: Some("dev".to_string()),
+            ..Default::default()
+        }
+        .save(dir.path())
+        .unwrap();
+        let loaded = WorkspaceSettings::load(dir.path());
+        assert_eq!(loaded.settings.environment.as_deref(), Some("dev"));
+        // The file's environment wins over the one picked on this machine
+        assert_eq!(
+            loaded
+                .settings
+                .default_environment(Some("staging".to_string()))
+                .as_deref(),
+            Some("dev")
+        );
+        assert_eq!(
+            WorkspaceSettings::default().default_environment(Some("staging".to_string())),
+            Some("staging".to_string())
+        );
+        assert_eq!(loaded.settings.timeout_ms, None);
+        assert_eq!(
+            loaded.settings.other["plugins"],
+            serde_json::json!({"a": [1, 2]})
+        );
+        // Nothing left behind from the atomic write
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
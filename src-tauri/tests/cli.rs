@@ -0,0 +1,166 @@
+//! Runs the kvile-cli binary against a local HTTP server
+
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::process::{Command, Output};
+
+/// Serve `/ok` with 200 and anything else with 404 on a random port
+fn serve() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let mut head = Vec::new();
+            let mut buf = [0; 1024];
+            while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+                match stream.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => head.extend_from_slice(&buf[..n]),
+                }
+            }
+            let head = String::from_utf8_lossy(&head);
+            let status = if head.starts_with("GET /ok ") {
+                "200 OK"
+            } else {
+                "404 Not Found"
+            };
+            let _ = write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{{}}",
+                status
+            );
+        }
+    });
+    port
+}
+
+fn kvile(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_kvile-cli"))
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn workspace(port: u16) -> tempfile::TempDir {
+    let dir = tempfile::Builder::new().prefix("ws").tempdir().unwrap();
+    let root = dir.path();
+    std::fs::write(
+        root.join("http-client.env.json"),
+        format!(r#"{{"staging": {{"host": "http://127.0.0.1:{}"}}}}"#, port),
+    )
+    .unwrap();
+    std::fs::create_dir(root.join("api")).unwrap();
+    std::fs::write(
+        root.join("api/health.http"),
+        "### health\nGET {{host}}/ok\n\n### status\nGET {{host}}/missing\n",
+    )
+    .unwrap();
+    dir
+}
+
+#[test]
+fn test_run_directory_passes() {
+    let port = serve();
+    let dir = workspace(port);
+
+    // A 404 fails the run unless error statuses are allowed
+    let output = kvile(dir.path(), &["run", "api", "--env", "staging"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(1), "{}", stdout);
+    assert!(stdout.contains("  FAIL status: HTTP 404\n"), "{}", stdout);
+
+    let output = kvile(
+        dir.path(),
+        &[
+            "run",
+            "api",
+            "--env",
+            "staging",
+            "--allow-error-status",
+            "--report",
+            "junit.xml",
+        ],
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("api/health.http\n"), "{}", stdout);
+    assert!(stdout.contains("  ok   health (200, "), "{}", stdout);
+    assert!(stdout.contains("  ok   status (404, "), "{}", stdout);
+    assert!(stdout.ends_with("2 passed, 0 failed\n"), "{}", stdout);
+
+    let junit = std::fs::read_to_string(dir.path().join("junit.xml")).unwrap();
+    assert!(junit.contains(r#"<testsuites name="kvile" tests="2" failures="0""#));
+    assert!(junit.contains(r#"<testcase name="health" classname="api/health.http""#));
+}
+
+#[test]
+fn test_run_fails_on_request_errors() {
+    // Nothing listens on a port once its listener is dropped
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let dir = workspace(port);
+    std::fs::write(
+        dir.path().join("api/undefined.http"),
+        "### undefined\nGET {{missing}}/ok\n",
+    )
+    .unwrap();
+
+    let output = kvile(
+        dir.path(),
+        &["run", "api", "--env", "staging", "--format", "json"],
+    );
+    assert_eq!(output.status.code(), Some(1));
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["passed"], 0);
+    assert_eq!(report["failed"], 3);
+    let results = report["results"].as_array().unwrap();
+    assert_eq!(results[0]["file"], "api/health.http");
+    assert!(results[0]["error"]
+        .as_str()
+        .unwrap()
+        .starts_with("Request failed"));
+    assert_eq!(results[2]["error"], "Undefined variables: missing");
+}
+
+#[test]
+fn test_run_fails_requests_with_scripts() {
+    let port = serve();
+    let dir = workspace(port);
+    std::fs::write(
+        dir.path().join("api/scripted.http"),
+        "### scripted\nGET {{host}}/ok\n\n> {%\nclient.assert(response.status === 201);\n%}\n",
+    )
+    .unwrap();
+
+    let args = ["run", "api/scripted.http", "--env", "staging"];
+    let output = kvile(dir.path(), &args);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(1), "{}", stdout);
+    assert!(
+        stdout.contains("FAIL scripted: Scripts can't run"),
+        "{}",
+        stdout
+    );
+
+    let output = kvile(dir.path(), &[&args[..], &["--skip-scripts"]].concat());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("scripts not run"), "{}", stdout);
+}
+
+#[test]
+fn test_usage_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = kvile(dir.path(), &["run"]);
+    assert_eq!(output.status.code(), Some(2));
+    let output = kvile(dir.path(), &["run", "missing.http"]);
+    assert_eq!(output.status.code(), Some(2));
+    let output = kvile(dir.path(), &["run", "x.http", "--format", "yaml"]);
+    assert_eq!(output.status.code(), Some(2));
+}
@@ -40,6 +40,8 @@ function App() {
     environmentConfig,
     historyEntries,
     isLoadingHistory,
+    historyWarning,
+    repairHistory,
     viewHistoryEntry,
     rerunHistoryEntry,
     deleteHistoryEntry,
@@ -47,6 +49,7 @@ function App() {
     openFiles,
     activeFileIndex,
     updateFileContent,
+    loadEnvironments,
     // Diff
     showDiffPanel,
     setShowDiffPanel,
@@ -86,6 +89,16 @@ function App() {
     [activeFileIndex, openFiles, updateFileContent]
   );
 
+  // Env files are resolved relative to the active file and the env file glob,
+  // so reload them when either changes
+  const activeFilePath = activeFileIndex >= 0 ? openFiles[activeFileIndex]?.path : undefined;
+  const envFileGlob = useSettingsStore((state) => state.envFileGlob);
+  useEffect(() => {
+    if (activeFilePath) {
+      loadEnvironments();
+    }
+  }, [activeFilePath, envFileGlob, loadEnvironments]);
+
   // Apply theme to document
   useEffect(() => {
     if (isDarkMode) {
@@ -178,6 +191,8 @@ function App() {
         onClose={() => setShowHistoryPanel(false)}
         entries={historyEntries}
         isLoading={isLoadingHistory}
+        warning={historyWarning}
+        onRepair={repairHistory}
         onViewEntry={viewHistoryEntry}
         onRerunEntry={rerunHistoryEntry}
         onDeleteEntry={deleteHistoryEntry}
@@ -1,9 +1,14 @@
 import { useState, useCallback } from "react";
-import { X, Trash2, Pencil, Plus, Save, XCircle, Lock, Unlock } from "lucide-react";
+import { X, Trash2, Pencil, Plus, Save, XCircle, Lock, Unlock, KeyRound, Terminal } from "lucide-react";
 import { useAppStore } from "@/stores/appStore";
 import { useScriptStore } from "@/stores/scriptStore";
 import { extractInlineVariables } from "@/lib/variables";
-import { saveEnvironment } from "@/lib/tauri";
+import {
+  saveEnvironment,
+  movePrivateValuesToKeychain,
+  unlockPrivateEnv,
+  setExecTrust,
+} from "@/lib/tauri";
 
 interface EnvironmentPanelProps {
   isOpen: boolean;
@@ -36,6 +41,41 @@ export function EnvironmentPanel({ isOpen, onClose }: EnvironmentPanelProps) {
   const [editedPrivateSharedVars, setEditedPrivateSharedVars] = useState<EditableVariable[]>([]);
   const [isSaving, setIsSaving] = useState(false);
   const [error, setError] = useState("");
+  const [passphrase, setPassphrase] = useState("");
+
+  const unlock = useCallback(async () => {
+    if (!workspacePath) return;
+    setError("");
+    try {
+      await unlockPrivateEnv(workspacePath, passphrase);
+      setPassphrase("");
+      await loadEnvironments();
+    } catch (e) {
+      setError(e instanceof Error ? e.message : String(e));
+    }
+  }, [workspacePath, passphrase, loadEnvironments]);
+
+  const trustCommands = useCallback(async () => {
+    if (!workspacePath) return;
+    setError("");
+    try {
+      await setExecTrust(workspacePath, true);
+      await loadEnvironments();
+    } catch (e) {
+      setError(e instanceof Error ? e.message : String(e));
+    }
+  }, [workspacePath, loadEnvironments]);
+
+  const moveToKeychain = useCallback(async () => {
+    if (!workspacePath) return;
+    setError("");
+    try {
+      await movePrivateValuesToKeychain(workspacePath);
+      await loadEnvironments();
+    } catch (e) {
+      setError(e instanceof Error ? e.message : String(e));
+    }
+  }, [workspacePath, loadEnvironments]);
 
   const startEditing = useCallback(() => {
     const env = environmentConfig?.environments.find(
@@ -159,6 +199,15 @@ export function EnvironmentPanel({ isOpen, onClose }: EnvironmentPanelProps) {
             Environment: {activeEnvironment || "None"}
           </h2>
           <div className="flex items-center gap-2">
+            {!isEditing && env && (
+              <button
+                onClick={moveToKeychain}
+                className="p-1.5 rounded hover:bg-accent transition-colors"
+                title="Move private values to keychain"
+              >
+                <KeyRound className="h-4 w-4" />
+              </button>
+            )}
             {!isEditing && env && (
               <button
                 onClick={startEditing}
@@ -191,6 +240,57 @@ export function EnvironmentPanel({ isOpen, onClose }: EnvironmentPanelProps) {
           </div>
         ) : null}
 
+        {(environmentConfig?.locked?.length ?? 0) > 0 && (
+          <form
+            className="flex items-center gap-2 mb-4"
+            onSubmit={(e) => {
+              e.preventDefault();
+              unlock();
+            }}
+          >
+            <Lock className="h-4 w-4 text-amber-500 flex-shrink-0" />
+            <input
+              type="password"
+              value={passphrase}
+              onChange={(e) => setPassphrase(e.target.value)}
+              placeholder="Passphrase for private variables"
+              className="flex-1 px-2 py-1 text-xs bg-background border border-border rounded"
+            />
+            <button
+              type="submit"
+              className="px-2 py-1 text-xs rounded bg-primary text-primary-foreground hover:bg-primary/90"
+            >
+              Unlock
+            </button>
+          </form>
+        )}
+
+        {(environmentConfig?.untrusted_commands?.length ?? 0) > 0 && (
+          <div className="text-xs border border-amber-500/50 rounded p-2 mb-4">
+            <div className="flex items-center gap-2 mb-1 text-amber-500">
+              <Terminal className="h-4 w-4 flex-shrink-0" />
+              This workspace's env files want to run commands:
+            </div>
+            <ul className="font-mono mb-2 ml-6 list-disc">
+              {environmentConfig?.untrusted_commands?.map((command) => (
+                <li key={command}>{command}</li>
+              ))}
+            </ul>
+            <button
+              onClick={trustCommands}
+              className="px-2 py-1 text-xs rounded bg-primary text-primary-foreground hover:bg-primary/90"
+            >
+              Trust this workspace
+            </button>
+          </div>
+        )}
+
+        {environmentConfig?.warnings?.map((warning) => (
+          <div key={warning} className="text-xs text-amber-500 mb-2">
+            {warning}
+          </div>
+        ))}
+
         {error && (
           <div className="text-xs text-destructive bg-destructive/10 p-2 rounded mb-4">
             {error}
@@ -7,6 +7,7 @@ import {
   ChevronRight,
   ChevronDown,
   ArrowLeftRight,
+  AlertTriangle,
 } from "lucide-react";
 import { cn } from "@/lib/utils";
 import type { HistoryEntry } from "@/lib/tauri";
@@ -16,6 +17,9 @@ interface HistoryPanelProps {
   onClose: () => void;
   entries: HistoryEntry[];
   isLoading: boolean;
+  /** Why history is only kept in memory, if the database file is unusable */
+  warning?: string | null;
+  onRepair?: () => void;
   onViewEntry: (entry: HistoryEntry) => void;
   onRerunEntry: (entry: HistoryEntry) => void;
   onDeleteEntry: (id: number) => void;
@@ -83,7 +87,8 @@ function HistoryEntryItem({
   canCompare?: boolean;
 }) {
   const [expanded, setExpanded] = useState(false);
-  const isError = entry.status >= 400;
+  // Status 0 means the request failed without a response
+  const isError = entry.status === 0 || entry.status >= 400;
 
   return (
     <div className="border-b border-border last:border-0">
@@ -219,6 +224,8 @@ export function HistoryPanel({
   onClose,
   entries,
   isLoading,
+  warning,
+  onRepair,
   onViewEntry,
   onRerunEntry,
   onDeleteEntry,
@@ -305,6 +312,21 @@ export function HistoryPanel({
           </div>
         </div>
 
+        {warning && (
+          <div className="flex items-start gap-2 px-4 py-2 border-b border-border bg-yellow-500/10 text-xs">
+            <AlertTriangle className="h-4 w-4 text-yellow-500 flex-shrink-0" />
+            <span className="flex-1">{warning}</span>
+            {onRepair && (
+              <button
+                onClick={onRepair}
+                className="px-2 py-1 border border-input rounded hover:bg-accent flex-shrink-0"
+              >
+                Repair
+              </button>
+            )}
+          </div>
+        )}
+
         {/* Content */}
         <div className="flex-1 overflow-auto">
           {isLoading ? (
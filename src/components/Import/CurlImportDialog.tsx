@@ -1,6 +1,14 @@
 import { useState, useCallback } from "react";
-import { X, FileDown, Copy, AlertCircle, Loader2 } from "lucide-react";
+import {
+  X,
+  FileDown,
+  Copy,
+  AlertCircle,
+  AlertTriangle,
+  Loader2,
+} from "lucide-react";
 import { convertCurlToHttp, isTauriAvailable } from "@/lib/tauri";
+import { useAppStore } from "@/stores/appStore";
 
 interface CurlImportDialogProps {
   onClose: () => void;
@@ -8,9 +16,11 @@ interface CurlImportDialogProps {
 }
 
 export function CurlImportDialog({ onClose, onInsert }: CurlImportDialogProps) {
+  const workspacePath = useAppStore((state) => state.workspacePath);
   const [curlCommand, setCurlCommand] = useState("");
   const [httpOutput, setHttpOutput] = useState("");
   const [error, setError] = useState("");
+  const [warnings, setWarnings] = useState<string[]>([]);
   const [isConverting, setIsConverting] = useState(false);
 
   const handleConvert = useCallback(async () => {
@@ -28,15 +38,20 @@ export function CurlImportDialog({ onClose, onInsert }: CurlImportDialogProps) {
     setError("");
 
     try {
-      const result = await convertCurlToHttp(curlCommand);
-      setHttpOutput(result);
+      const result = await convertCurlToHttp(
+        curlCommand,
+        workspacePath ?? undefined
+      );
+      setHttpOutput(result.http);
+      setWarnings(result.warnings);
     } catch (e) {
       setError(String(e));
       setHttpOutput("");
+      setWarnings([]);
     } finally {
       setIsConverting(false);
     }
-  }, [curlCommand]);
+  }, [curlCommand, workspacePath]);
 
   const handleInsert = useCallback(() => {
     if (httpOutput) {
@@ -133,6 +148,21 @@ export function CurlImportDialog({ onClose, onInsert }: CurlImportDialogProps) {
           </div>
         )}
 
+        {/* Warnings */}
+        {warnings.length > 0 && (
+          <div className="mt-4 p-3 bg-yellow-500/10 border border-yellow-500/20 rounded-md text-yellow-600 dark:text-yellow-400 text-sm">
+            <div className="flex items-center gap-2 font-medium mb-1">
+              <AlertTriangle className="h-4 w-4 flex-shrink-0" />
+              <span>Some options were not fully imported</span>
+            </div>
+            <ul className="list-disc pl-6 space-y-0.5 font-mono text-xs">
+              {warnings.map((warning, i) => (
+                <li key={i}>{warning}</li>
+              ))}
+            </ul>
+          </div>
+        )}
+
         {/* Help text */}
         <div className="mt-4 text-xs text-muted-foreground">
           <p>
@@ -1,6 +1,7 @@
 import { useState, useCallback, useEffect } from "react";
 import { X, Play, Loader2, Save, AlertCircle, PlayCircle, ChevronLeft, Undo2 } from "lucide-react";
 import { useAppStore } from "@/stores/appStore";
+import { useSettingsStore } from "@/stores/settingsStore";
 import { useScriptStore } from "@/stores/scriptStore";
 import { cn } from "@/lib/utils";
 import { HttpEditor } from "@/components/Editor/HttpEditor";
@@ -9,15 +10,20 @@ import { RequestEditor } from "@/components/Editor/RequestEditor";
 import { ResponsePanel } from "@/components/Response/ResponsePanel";
 import { RunAllResults } from "@/components/Response/RunAllResults";
 import { TestResultsPanel } from "@/components/Response/TestResultsPanel";
-import { isTauriAvailable, type ParsedRequest } from "@/lib/tauri";
+import { isTauriAvailable, resolveRequest, type ParsedRequest } from "@/lib/tauri";
 import { getRequestAtCursor } from "@/lib/http-parser";
-import { substituteVariables, extractInlineVariables } from "@/lib/variables";
+import {
+  substituteVariables,
+  extractInlineVariables,
+  hasCommandVariables,
+} from "@/lib/variables";
 import { executePreRequestScript, executePostRequestScript } from "@/lib/script-runtime";
 import { parseAuthFromMetadata, applyAuth, applyAuthToUrl } from "@/lib/auth-helpers";
 import { updateRequestInContent } from "@/lib/http-serializer";
 import { open } from "@tauri-apps/plugin-dialog";
 import { useParseDebounced } from "@/hooks/useParseDebounced";
 import { Logo } from "@/components/Logo";
+import { RecentWorkspaces } from "./RecentWorkspaces";
 
 export function MainContent() {
   const {
@@ -128,8 +134,10 @@ export function MainContent() {
         return;
       }
 
-      // Get all variables (environment + inline + response variables from scripts)
-      const envVariables = getCurrentVariables();
+      // Get all variables (environment + inline + response variables from scripts).
+      // `# @env name` pins the request to an environment other than the active one.
+      const environment = parsedRequest.metadata?.env || undefined;
+      const envVariables = getCurrentVariables(environment);
       const inlineVariables = extractInlineVariables(activeFile.content);
       const responseVariables = scriptStore.getAllVariables();
       let allVariables: Record<string, unknown> = {
@@ -181,13 +189,37 @@ export function MainContent() {
         stringVariables[key] = typeof value === 'string' ? value : JSON.stringify(value);
       }
 
+      // Names nothing else defines may come from the OS environment (opt-in),
+      // and values `$exec` commands provide are left to the backend, which
+      // runs only the commands this request uses. It also generates dynamic
+      // variables then.
+      let source = parsedRequest;
+      const { workspacePath, environmentConfig, activeEnvironment } = useAppStore.getState();
+      const commands =
+        workspacePath !== null &&
+        hasCommandVariables(environmentConfig, environment || activeEnvironment);
+      const { processEnvFallback, envFileGlob } = useSettingsStore.getState();
+      if (processEnvFallback || commands) {
+        const resolved = await resolveRequest({ parsed: parsedRequest }, {
+          variables: stringVariables,
+          processEnv: processEnvFallback,
+          ...(commands && {
+            workspace: workspacePath ?? undefined,
+            environment: environment || activeEnvironment || undefined,
+            filePath: activeFile.path,
+            envGlob: envFileGlob,
+          }),
+        });
+        source = resolved.request;
+      }
+
       // Substitute variables in URL, headers, and body
-      const substitutedUrl = substituteVariables(parsedRequest.url, stringVariables);
-      const substitutedBody = parsedRequest.body
-        ? substituteVariables(parsedRequest.body, stringVariables)
+      const substitutedUrl = substituteVariables(source.url, stringVariables);
+      const substitutedBody = source.body
+        ? substituteVariables(source.body, stringVariables)
         : undefined;
       const substitutedHeaders = Object.fromEntries(
-        Object.entries(parsedRequest.headers).map(([k, v]) => [
+        Object.entries(source.headers).map(([k, v]) => [
           k,
           substituteVariables(v, stringVariables).result,
         ])
@@ -232,7 +264,7 @@ export function MainContent() {
         body: substitutedBody?.result,
       };
 
-      await executeRequest(httpRequest);
+      await executeRequest(httpRequest, environment, substitutedMetadata.auth);
 
       // Execute post-request script if present
       if (parsedRequest.post_script) {
@@ -338,6 +370,7 @@ export function MainContent() {
               New File
             </button>
           </div>
+          <RecentWorkspaces onOpen={loadWorkspace} />
         </div>
       </div>
     );
@@ -359,7 +392,10 @@ export function MainContent() {
             )}
             onClick={() => setActiveFileIndex(index)}
           >
-            <span className="truncate text-sm max-w-[150px]">
+            <span
+              className={cn("truncate text-sm max-w-[150px]", file.deleted && "line-through")}
+              title={file.deleted ? "Deleted on disk" : undefined}
+            >
               {file.name}
               {file.modified && (
                 <span className="text-yellow-500 ml-1 font-bold" title="Unsaved changes">
@@ -0,0 +1,82 @@
+import { useEffect, useState } from "react";
+import { Folder, Pin, PinOff, X, AlertTriangle } from "lucide-react";
+import { cn } from "@/lib/utils";
+import {
+  getRecentWorkspaces,
+  isTauriAvailable,
+  pinWorkspace,
+  removeRecentWorkspace,
+  type RecentWorkspace,
+} from "@/lib/tauri";
+
+interface RecentWorkspacesProps {
+  onOpen: (path: string) => void;
+}
+
+/** Workspaces opened before, for reopening from the welcome screen */
+export function RecentWorkspaces({ onOpen }: RecentWorkspacesProps) {
+  const [workspaces, setWorkspaces] = useState<RecentWorkspace[]>([]);
+
+  const reload = () => {
+    getRecentWorkspaces()
+      .then(setWorkspaces)
+      .catch((error) => console.warn("Failed to load recent workspaces:", error));
+  };
+
+  useEffect(() => {
+    if (isTauriAvailable()) reload();
+  }, []);
+
+  if (workspaces.length === 0) return null;
+
+  return (
+    <div className="mt-6 w-80 mx-auto text-left">
+      <h3 className="text-xs font-semibold uppercase mb-2">Recent</h3>
+      <ul className="space-y-0.5">
+        {workspaces.map((workspace) => (
+          <li
+            key={workspace.path}
+            className="group flex items-center gap-2 px-2 py-1 rounded-md hover:bg-accent"
+          >
+            {workspace.missing ? (
+              <AlertTriangle className="h-4 w-4 shrink-0 text-yellow-500" />
+            ) : (
+              <Folder className="h-4 w-4 shrink-0" />
+            )}
+            <button
+              onClick={() => onOpen(workspace.path)}
+              disabled={workspace.missing}
+              className={cn(
+                "flex-1 min-w-0 text-left text-sm truncate",
+                workspace.missing ? "line-through opacity-60" : "text-foreground"
+              )}
+              title={workspace.missing ? `${workspace.path} no longer exists` : workspace.path}
+            >
+              {workspace.path.split("/").pop() || workspace.path}
+            </button>
+            <button
+              onClick={() => pinWorkspace(workspace.path, !workspace.pinned).then(reload)}
+              className={cn(
+                "p-0.5 rounded hover:bg-background",
+                !workspace.pinned && "opacity-0 group-hover:opacity-100"
+              )}
+              title={workspace.pinned ? "Unpin" : "Pin"}
+            >
+              {workspace.pinned ? <PinOff className="h-3.5 w-3.5" /> : <Pin className="h-3.5 w-3.5" />}
+            </button>
+            <button
+              onClick={() => removeRecentWorkspace(workspace.path).then(reload)}
+              className={cn(
+                "p-0.5 rounded hover:bg-background",
+                !workspace.missing && "opacity-0 group-hover:opacity-100"
+              )}
+              title="Remove from recent"
+            >
+              <X className="h-3.5 w-3.5" />
+            </button>
+          </li>
+        ))}
+      </ul>
+    </div>
+  );
+}
@@ -412,6 +412,7 @@ export function ResponsePanel({ response }: ResponsePanelProps) {
         {activeTab === "body" && (
           <ResponseBody
             body={formattedBody}
+            size={response.size}
             contentType={contentType}
             searchQuery={search.query}
             searchRegex={search.isRegex}
@@ -508,6 +509,8 @@ function HighlightedText({
 
 interface ResponseBodyProps {
   body: string;
+  /** Bytes received, which a base64-encoded binary body doesn't show */
+  size: number;
   contentType: ContentType;
   searchQuery: string;
   searchRegex: boolean;
@@ -518,6 +521,7 @@ interface ResponseBodyProps {
 
 function ResponseBody({
   body,
+  size,
   contentType,
   searchQuery,
   searchRegex,
@@ -531,7 +535,7 @@ function ResponseBody({
       <div className="flex flex-col items-center justify-center h-full text-muted-foreground">
         <FileText className="h-12 w-12 mb-2 opacity-50" />
         <p className="text-sm">Binary content</p>
-        <p className="text-xs opacity-70">({body.length} bytes)</p>
+        <p className="text-xs opacity-70">({size} bytes)</p>
       </div>
     );
   }
@@ -2,6 +2,7 @@ import { useState } from "react";
 import { X, RotateCcw } from "lucide-react";
 import { useSettingsStore, type Settings } from "@/stores/settingsStore";
 import { cn } from "@/lib/utils";
+import { getRecentLogs, isTauriAvailable, openLogDirectory } from "@/lib/tauri";
 
 interface SettingsPanelProps {
   isOpen: boolean;
@@ -187,10 +188,53 @@ function GeneralSettings() {
           onChange={(checked) => setSetting('showWelcome', checked)}
         />
       </SettingItem>
+
+      <LogsSetting />
     </div>
   );
 }
 
+// Logs to attach to bug reports, secrets already redacted
+function LogsSetting() {
+  const [copied, setCopied] = useState(false);
+
+  const copyLogs = async () => {
+    const records = await getRecentLogs("debug");
+    const lines = records.map((r) => {
+      const spans = r.spans.length > 0 ? `${r.spans.join(":")}: ` : "";
+      const fields = Object.entries(r.fields).map(([k, v]) => ` ${k}=${v}`).join("");
+      return `${r.timestamp} ${r.level} ${spans}${r.target}: ${r.message}${fields}`;
+    });
+    await navigator.clipboard.writeText(lines.join("\n"));
+    setCopied(true);
+    setTimeout(() => setCopied(false), 2000);
+  };
+
+  return (
+    <SettingItem
+      label="Logs"
+      description="Attach these to bug reports; tokens and secrets are redacted"
+    >
+      <div className="flex gap-2">
+        <button
+          onClick={() => copyLogs().catch((error) => console.error("Failed to copy logs:", error))}
+          disabled={!isTauriAvailable()}
+          className="px-2 py-1.5 text-sm border border-input rounded-md hover:bg-accent transition-colors"
+        >
+          {copied ? "Copied" : "Copy recent"}
+        </button>
+        <button
+          onClick={() => openLogDirectory().catch((error) => console.error("Failed to open logs:", error))}
+          disabled={!isTauriAvailable()}
+          className="px-2 py-1.5 text-sm border border-input rounded-md hover:bg-accent transition-colors"
+        >
+          Open folder
+        </button>
+      </div>
+    </SettingItem>
+  );
+}
+
 // Editor Settings Section
 function EditorSettings() {
   const {
@@ -295,6 +339,7 @@ function RequestSettings() {
     followRedirects,
     verifySsl,
     maxResponseSize,
+    tokenRefreshSkew,
     setSetting,
   } = useSettingsStore();
 
@@ -357,6 +402,23 @@ function RequestSettings() {
           <option value={50 * 1024 * 1024}>50 MB</option>
         </select>
       </SettingItem>
+
+      <SettingItem
+        label="Token Refresh Skew"
+        description="Refresh OAuth tokens this long before they expire"
+      >
+        <div className="flex items-center gap-2">
+          <input
+            type="number"
+            value={tokenRefreshSkew}
+            onChange={(e) => setSetting('tokenRefreshSkew', Math.max(0, parseInt(e.target.value) || 0))}
+            min={0}
+            step={30}
+            className="w-24 px-2 py-1.5 text-sm bg-background border border-input rounded-md focus:outline-none focus:ring-2 focus:ring-ring"
+          />
+          <span className="text-xs text-muted-foreground">s</span>
+        </div>
+      </SettingItem>
     </div>
   );
 }
@@ -367,6 +429,8 @@ function EnvironmentSettings() {
     autoLoadEnv,
     showVariableHints,
     maskSensitiveValues,
+    processEnvFallback,
+    envFileGlob,
     setSetting,
   } = useSettingsStore();
 
@@ -401,6 +465,29 @@ function EnvironmentSettings() {
           onChange={(checked) => setSetting('maskSensitiveValues', checked)}
         />
       </SettingItem>
+
+      <SettingItem
+        label="Fall Back to OS Environment"
+        description="Resolve variables no environment file defines from the process environment, e.g. API_TOKEN set in CI"
+      >
+        <Toggle
+          checked={processEnvFallback}
+          onChange={(checked) => setSetting('processEnvFallback', checked)}
+        />
+      </SettingItem>
+
+      <SettingItem
+        label="Env File Glob"
+        description="Env files to merge across the workspace, e.g. **/*http-client.env.json for envs/orders.http-client.env.json"
+      >
+        <input
+          type="text"
+          value={envFileGlob}
+          onChange={(e) => setSetting('envFileGlob', e.target.value)}
+          placeholder="**/http-client.env.json"
+          className="w-56 px-2 py-1.5 text-sm font-mono bg-background border border-input rounded-md focus:outline-none focus:ring-2 focus:ring-ring"
+        />
+      </SettingItem>
     </div>
   );
 }
@@ -1,26 +1,61 @@
 import { useEffect } from "react";
 import { listen } from "@tauri-apps/api/event";
 import { useAppStore } from "@/stores/appStore";
-import { isTauriAvailable } from "@/lib/tauri";
+import {
+  FILE_DELETED_EVENT,
+  FILE_RENAMED_EVENT,
+  FILES_CHANGED_EVENT,
+  isTauriAvailable,
+  type FileDeleted,
+  type FileRenamed,
+  type FilesChanged,
+} from "@/lib/tauri";
 
 /**
- * Hook that listens for file change events from the backend
- * and automatically refreshes the file tree when changes are detected
+ * Hook that listens for file change events from the backend and refreshes
+ * the file tree when files are added, removed or renamed; edits alone leave
+ * it as is. Edits to env files reload the environments, renamed files keep
+ * their open tabs, and deleted ones have their tabs flagged. Other watched roots are left to whoever opened them.
  */
 export function useFileWatcher() {
   const refreshWorkspace = useAppStore((state) => state.refreshWorkspace);
+  const loadEnvironments = useAppStore((state) => state.loadEnvironments);
+  const renameOpenFile = useAppStore((state) => state.renameOpenFile);
+  const markFileDeleted = useAppStore((state) => state.markFileDeleted);
   const workspacePath = useAppStore((state) => state.workspacePath);
 
   useEffect(() => {
     if (!isTauriAvailable() || !workspacePath) return;
 
-    const unlisten = listen<string>("file-changed", (event) => {
-      console.log("File change detected:", event.payload);
-      refreshWorkspace();
+    const unlisten = listen<FilesChanged>(FILES_CHANGED_EVENT, (event) => {
+      if (event.payload.root !== workspacePath) return;
+      console.log("File changes detected:", event.payload.changes);
+      if (event.payload.changes.some((change) => change.kind !== "modified")) {
+        refreshWorkspace();
+      }
+    });
+
+    const unlistenRename = listen<FileRenamed>(FILE_RENAMED_EVENT, (event) => {
+      if (event.payload.root !== workspacePath) return;
+      renameOpenFile(event.payload.from, event.payload.to);
+    });
+
+    const unlistenDelete = listen<FileDeleted>(FILE_DELETED_EVENT, (event) => {
+      if (event.payload.root !== workspacePath) return;
+      markFileDeleted(event.payload.path);
+    });
+
+    const unlistenEnv = listen<string>("env-changed", (event) => {
+      if (!event.payload.startsWith(workspacePath)) return;
+      console.log("Env file change detected:", event.payload);
+      loadEnvironments();
     });
 
     return () => {
       unlisten.then((fn) => fn());
+      unlistenRename.then((fn) => fn());
+      unlistenDelete.then((fn) => fn());
+      unlistenEnv.then((fn) => fn());
     };
-  }, [workspacePath, refreshWorkspace]);
+  }, [workspacePath, refreshWorkspace, loadEnvironments, renameOpenFile, markFileDeleted]);
 }
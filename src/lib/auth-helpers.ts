@@ -26,19 +26,29 @@ import { useAuthStore, type AuthConfig, type OAuth2Config, type OidcAuthConfig }
  * - # @oauth.grantType client_credentials|password - Grant type
  * - # @oauth.username xxx  - For password grant
  * - # @oauth.password xxx  - For password grant
+ * - # @oauth.authMethod client_secret_post|client_secret_basic|none - How the
+ *                            client authenticates to the token endpoint
+ * - # @auth oidc <name>    - Token for an OIDC config named in the env file's
+ *                            Security.Auth or workspace settings, added by
+ *                            the backend at send time
  * - # @auth oidc           - OpenID Connect (Authorization Code + PKCE)
  * - # @oidc.issuer xxx     - OIDC issuer URL (for discovery)
  * - # @oidc.authorizationEndpoint xxx - Explicit auth endpoint
  * - # @oidc.tokenEndpoint xxx - Explicit token endpoint
  * - # @oidc.clientId xxx   - OIDC client ID
  * - # @oidc.clientSecret xxx - OIDC client secret (optional)
- * - # @oidc.redirectUrl xxx - Redirect URL (must be registered with IdP)
+ * - # @oidc.redirectUrl xxx - Redirect URL (must be registered with IdP);
+ *                             kvile://oauth/callback is received as a deep link
+ * - # @oidc.randomPort true - Listen on a free port instead (port 0 in the
+ *                             redirect URL does the same)
  * - # @oidc.scopes x y z   - OIDC scopes (space-separated)
+ * - # @oidc.authMethod client_secret_post|client_secret_basic|none
+ * - # @oidc.pkce false     - Skip PKCE for providers that reject it
  */
 export function parseAuthFromMetadata(metadata: Record<string, string>): AuthConfig | null {
   const authType = metadata['auth'];
 
-  if (!authType || authType === 'none') {
+  if (!authType || authType === 'none' || parseOidcConfigName(authType)) {
     return null;
   }
 
@@ -105,6 +115,7 @@ export function parseAuthFromMetadata(metadata: Record<string, string>): AuthCon
         scopes: metadata['oauth.scopes']?.split(/\s+/).filter(Boolean),
         username: metadata['oauth.username'],
         password: metadata['oauth.password'],
+        tokenEndpointAuthMethod: (metadata['oauth.authMethod'] || metadata['oauth.auth-method']) as OAuth2Config['tokenEndpointAuthMethod'],
       };
 
       return {
@@ -145,6 +156,9 @@ export function parseAuthFromMetadata(metadata: Record<string, string>): AuthCon
         redirectUrl,
         scopes,
         clientSecret: metadata['oidc.clientSecret'] || metadata['oidc.client-secret'],
+        tokenEndpointAuthMethod: (metadata['oidc.authMethod'] || metadata['oidc.auth-method']) as OidcAuthConfig['tokenEndpointAuthMethod'],
+        useRandomPort: (metadata['oidc.randomPort'] || metadata['oidc.random-port']) === 'true',
+        usePkce: metadata['oidc.pkce'] !== 'false',
       };
 
       return {
@@ -159,6 +173,14 @@ export function parseAuthFromMetadata(metadata: Record<string, string>): AuthCon
   }
 }
 
+/**
+ * The config a `# @auth oidc <config-name>` directive names. The backend
+ * injects the token for these, so they need no auth applied here.
+ */
+export function parseOidcConfigName(auth?: string): string | undefined {
+  return auth?.trim().match(/^oidc\s+(\S+)$/i)?.[1];
+}
+
 /**
  * Check if request metadata contains auth directives
  */
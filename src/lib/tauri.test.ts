@@ -5,8 +5,13 @@ import {
   sendRequest,
   parseHttpFile,
   readFile,
+  FileNotFoundError,
   writeFile,
+  createFile,
+  deletePath,
+  FileOperationError,
   listHttpFiles,
+  listAllHttpFiles,
 } from "./tauri";
 
 vi.mock("@tauri-apps/api/core", () => ({
@@ -56,6 +61,21 @@ describe("tauri.ts", () => {
       expect(result).toEqual(mockResponse);
     });
 
+    it("passes the history context to record the request", async () => {
+      vi.mocked(invoke).mockResolvedValue({});
+
+      const request = {
+        method: "GET",
+        url: "https://example.com",
+        headers: {},
+      };
+      const history = { workspace: "/ws", file_path: "/ws/api.http" };
+
+      await sendRequest(request, history);
+
+      expect(invoke).toHaveBeenCalledWith("send_request", { request, history });
+    });
+
     it("throws error on failure", async () => {
       vi.mocked(invoke).mockRejectedValue("Network error");
 
@@ -106,6 +126,12 @@ describe("tauri.ts", () => {
 
       await expect(readFile("/nonexistent")).rejects.toThrow("File not found");
     });
+
+    it("throws FileNotFoundError for a missing file", async () => {
+      vi.mocked(invoke).mockRejectedValue({ kind: "not_found", path: "/gone.http" });
+
+      await expect(readFile("/gone.http")).rejects.toBeInstanceOf(FileNotFoundError);
+    });
   });
 
   describe("writeFile", () => {
@@ -121,17 +147,78 @@ describe("tauri.ts", () => {
     });
   });
 
+  describe("createFile", () => {
+    it("throws FileOperationError when the file exists", async () => {
+      vi.mocked(invoke).mockRejectedValue({ kind: "already_exists", path: "/ws/a.http" });
+
+      const error = await createFile("/ws", "/ws/a.http", "GET /").catch((e) => e);
+
+      expect(invoke).toHaveBeenCalledWith("create_file", {
+        workspace: "/ws",
+        path: "/ws/a.http",
+        content: "GET /",
+        overwrite: false,
+      });
+      expect(error).toBeInstanceOf(FileOperationError);
+      expect(error.error.kind).toBe("already_exists");
+      expect(error.message).toBe("Already exists: /ws/a.http");
+    });
+  });
+
+  describe("deletePath", () => {
+    it("moves to the trash by default", async () => {
+      vi.mocked(invoke).mockResolvedValue(["/ws/a.http"]);
+
+      const result = await deletePath("/ws", "/ws/a.http");
+
+      expect(invoke).toHaveBeenCalledWith("delete_path", {
+        workspace: "/ws",
+        path: "/ws/a.http",
+        toTrash: true,
+      });
+      expect(result).toEqual(["/ws/a.http"]);
+    });
+  });
+
   describe("listHttpFiles", () => {
     it("calls invoke with correct command and args", async () => {
-      const mockFiles = [
-        { path: "/dir/test.http", name: "test.http", is_http_file: true },
-      ];
-      vi.mocked(invoke).mockResolvedValue(mockFiles);
+      const mockListing = {
+        files: [
+          { path: "/dir/test.http", name: "test.http", is_http_file: true, size: 5, modified: null },
+        ],
+        next_cursor: null,
+        warnings: [],
+      };
+      vi.mocked(invoke).mockResolvedValue(mockListing);
 
       const result = await listHttpFiles("/dir");
 
       expect(invoke).toHaveBeenCalledWith("list_http_files", { directory: "/dir" });
-      expect(result).toEqual(mockFiles);
+      expect(result).toEqual(mockListing);
+    });
+  });
+
+  describe("listAllHttpFiles", () => {
+    it("follows the cursor through every page", async () => {
+      const file = (name: string) => ({
+        path: `/dir/${name}`,
+        name,
+        is_http_file: true,
+        size: 0,
+        modified: null,
+      });
+      vi.mocked(invoke)
+        .mockResolvedValueOnce({ files: [file("a.http")], next_cursor: "/dir/a.http", warnings: [] })
+        .mockResolvedValueOnce({ files: [file("b.http")], next_cursor: null, warnings: ["x"] });
+
+      const result = await listAllHttpFiles("/dir");
+
+      expect(invoke).toHaveBeenLastCalledWith("list_http_files", {
+        directory: "/dir",
+        cursor: "/dir/a.http",
+      });
+      expect(result.files.map((f) => f.name)).toEqual(["a.http", "b.http"]);
+      expect(result.warnings).toEqual(["x"]);
     });
   });
 });
@@ -39,6 +39,22 @@ export interface HttpResponse {
   body: string;
   time: number;
   size: number;
+  /** Whether body holds the bytes received base64-encoded */
+  body_base64?: boolean;
+  /** Id of the history entry recording this response, if recorded */
+  history_id?: number;
+}
+
+/** Where a request was sent from, for recording it in history */
+export interface HistoryContext {
+  workspace: string;
+  file_path?: string;
+  request_name?: string;
+  environment?: string;
+  /** Values the request was resolved with; secrets are masked when stored */
+  variables?: Record<string, string>;
+  /** Glob the env files were loaded with, for masking their secrets */
+  env_glob?: string;
 }
 
 export interface ParsedRequest {
@@ -59,6 +75,56 @@ export interface FileInfo {
   path: string;
   name: string;
   is_http_file: boolean;
+  /** Size in bytes */
+  size: number;
+  /** Last modified, as RFC 3339 */
+  modified: string | null;
+}
+
+/** A page of files, sorted by path */
+export interface FileListing {
+  files: FileInfo[];
+  /** Pass back as `cursor` to fetch the next page; null on the last page */
+  next_cursor: string | null;
+  /** Directories that could not be read, such as for lack of permission */
+  warnings: string[];
+}
+
+export interface ListFilesOptions {
+  /** List every file rather than only .http, .rest and env files */
+  includeAll?: boolean;
+  /** Most files per page; the backend defaults to 5000 */
+  limit?: number;
+  cursor?: string;
+}
+
+export interface SearchOptions {
+  /** Treat the query as a regular expression rather than literal text */
+  regex?: boolean;
+  case_sensitive?: boolean;
+  /** Most matching lines; the backend defaults to 1000 */
+  max_results?: number;
+}
+
+export interface SearchMatch {
+  path: string;
+  /** 1-based */
+  line: number;
+  text: string;
+  /** Character offsets of each match in `text`, end exclusive */
+  ranges: [number, number][];
+  /** The request the line belongs to, null before the first request */
+  request_index: number | null;
+  request_name: string | null;
+}
+
+export interface SearchResults {
+  matches: SearchMatch[];
+  /** More lines matched than `max_results` */
+  truncated: boolean;
+  cancelled: boolean;
+  files_searched: number;
+  warnings: string[];
 }
 
 export interface Environment {
@@ -66,19 +132,76 @@ export interface Environment {
   variables: Record<string, string>;
   private_variables: Record<string, string>;
   source_file: string;
+  /** Env file each variable was resolved from */
+  variable_sources: Record<string, string>;
+  /** Named auth configs from the `Security.Auth` section */
+  auth?: Record<string, AuthConfig>;
+}
+
+export type GrantType =
+  | "authorization_code"
+  | "client_credentials"
+  | "password"
+  | "implicit"
+  | "device_authorization";
+
+/**
+ * A named auth config from an env file's `Security.Auth` section, in the
+ * JetBrains HTTP client's format. Values may hold `{{variables}}`.
+ */
+export interface AuthConfig {
+  /** Only "OAuth2" is usable; assumed when unset */
+  auth_type?: string;
+  grant_type?: GrantType;
+  issuer?: string;
+  auth_url?: string;
+  token_url?: string;
+  client_id?: string;
+  client_secret?: string;
+  client_auth_method?: TokenEndpointAuthMethod;
+  redirect_url?: string;
+  scopes: string[];
+  username?: string;
+  password?: string;
+  /** Unset leaves it to kvile, which uses PKCE */
+  pkce?: boolean;
+  /** The config as written */
+  raw: Record<string, unknown>;
 }
 
 export interface EnvironmentConfig {
   environments: Environment[];
   shared: Record<string, string>;
   private_shared: Record<string, string>;
+  /** Secrets that could not be read from the keychain, and auth configs
+   * that can't be used */
+  warnings?: string[];
+  /** Encrypted private env files not unlocked this session */
+  locked?: string[];
+  /** Env files that contributed, lowest precedence first */
+  files?: string[];
+  /** Names of variables provided by `$exec` commands, by environment */
+  command_variables?: Record<string, string[]>;
+  /** `$exec` commands not run because the workspace isn't trusted */
+  untrusted_commands?: string[];
 }
 
 /**
- * Send an HTTP request via the Tauri backend
+ * Send an HTTP request via the Tauri backend. With a `history` context the
+ * outcome, including failures, is recorded in history by the backend.
+ * `auth` is the request's `# @auth` value; for `oidc <config-name>` the
+ * backend adds a bearer token, failing with REAUTH_REQUIRED without one.
+ *
+ * `request` is either ready to send, such as a `ResolvedRequest`'s request,
+ * or a request for the backend to resolve the way `resolveRequest` does. It
+ * then fails on undefined variables, and `auth` defaults to its `# @auth`.
  */
-export async function sendRequest(request: HttpRequest): Promise<HttpResponse> {
-  return invokeWithErrorHandling<HttpResponse>("send_request", { request });
+export async function sendRequest(
+  request: HttpRequest | ResolveArgs,
+  history?: HistoryContext,
+  auth?: string
+): Promise<HttpResponse> {
+  return invokeWithErrorHandling<HttpResponse>("send_request", { request, history, auth });
 }
 
 /**
@@ -89,48 +212,335 @@ export async function parseHttpFile(content: string): Promise<ParsedRequest[]> {
 }
 
 /**
- * Read a file from the filesystem
+ * The order to run requests in, as indexes into `requests`: `# @depends-on`
+ * dependencies first, otherwise document order. Rejects on a dependency
+ * cycle or an unknown request name.
+ */
+export async function orderRequests(requests: ParsedRequest[]): Promise<number[]> {
+  return invokeWithErrorHandling<number[]>("order_requests", { requests });
+}
+
+/** Why read_file failed */
+export type ReadFileError =
+  | { kind: "not_found"; path: string }
+  | { kind: "other"; message: string };
+
+/** Thrown by readFile when the file doesn't exist, such as after it was deleted */
+export class FileNotFoundError extends Error {
+  constructor(public path: string) {
+    super(`File not found: ${path}`);
+    this.name = "FileNotFoundError";
+  }
+}
+
+/**
+ * Read a file from the filesystem. A missing file throws FileNotFoundError.
  */
 export async function readFile(path: string): Promise<string> {
-  return invokeWithErrorHandling<string>("read_file", { path });
+  if (!isTauriAvailable()) {
+    throw new Error("Not running in Tauri context");
+  }
+  try {
+    return await invoke<string>("read_file", { path });
+  } catch (error) {
+    console.error(`Tauri command "read_file" failed:`, error);
+    if (typeof error === "string") {
+      throw new Error(error);
+    }
+    const readError = error as ReadFileError;
+    if (readError?.kind === "not_found") {
+      throw new FileNotFoundError(readError.path);
+    }
+    throw new Error(`Failed to read file: ${readError?.message ?? "unknown error"}`);
+  }
 }
 
 /**
- * Write content to a file
+ * Write content to a file. The file watcher doesn't report the write back.
  */
 export async function writeFile(path: string, content: string): Promise<void> {
   return invokeWithErrorHandling<void>("write_file", { path, content });
 }
 
+/** Why creating, renaming or deleting a path failed */
+export type FileOpErrorKind =
+  | { kind: "already_exists"; path: string }
+  | { kind: "not_found"; path: string }
+  | { kind: "outside_workspace"; path: string }
+  | { kind: "other"; message: string };
+
+/** Thrown by the file management commands, with the backend's error kind */
+export class FileOperationError extends Error {
+  constructor(public error: FileOpErrorKind) {
+    super(
+      error.kind === "already_exists"
+        ? `Already exists: ${error.path}`
+        : error.kind === "not_found"
+          ? `Not found: ${error.path}`
+          : error.kind === "outside_workspace"
+            ? `Not inside the workspace: ${error.path}`
+            : error.message
+    );
+    this.name = "FileOperationError";
+  }
+}
+
+/**
+ * Invoke a file management command; failures throw FileOperationError. The
+ * workspace has to be open, with its file watcher started.
+ */
+async function invokeFileOperation(
+  command: string,
+  args: Record<string, unknown>
+): Promise<string[]> {
+  if (!isTauriAvailable()) {
+    throw new Error("Not running in Tauri context");
+  }
+  try {
+    return await invoke<string[]>(command, args);
+  } catch (error) {
+    console.error(`Tauri command "${command}" failed:`, error);
+    if (typeof error === "string") {
+      throw new Error(error);
+    }
+    throw new FileOperationError(error as FileOpErrorKind);
+  }
+}
+
+/**
+ * Create a file inside the workspace, failing with `already_exists` unless
+ * `overwrite`. The file watcher doesn't report the write back.
+ * Returns the path created.
+ */
+export async function createFile(
+  workspace: string,
+  path: string,
+  content: string,
+  overwrite = false
+): Promise<string[]> {
+  return invokeFileOperation("create_file", { workspace, path, content, overwrite });
+}
+
+export interface TemplateVariable {
+  name: string;
+  description: string;
+}
+
+export interface TemplateInfo {
+  id: string;
+  name: string;
+  /** Variables that must all be given to create a file from it */
+  variables: TemplateVariable[];
+  /** Embedded in the app rather than from the workspace's .kvile/templates */
+  builtin: boolean;
+}
+
+/**
+ * Templates new .http files can be created from: the built-in ones and,
+ * with a workspace, those in its `.kvile/templates`
+ */
+export async function listTemplates(workspace?: string): Promise<TemplateInfo[]> {
+  return invokeWithErrorHandling<TemplateInfo[]>("list_templates", { workspace });
+}
+
+/**
+ * Create an .http file inside the workspace from a template, filling in its
+ * variables from `vars`. Fails with `already_exists` if the file exists.
+ * Returns the path created.
+ */
+export async function createHttpFileFromTemplate(
+  workspace: string,
+  path: string,
+  templateId: string,
+  vars: Record<string, string>
+): Promise<string[]> {
+  return invokeFileOperation("create_http_file_from_template", {
+    workspace,
+    path,
+    templateId,
+    vars,
+  });
+}
+
+/** Create a directory inside the workspace. Returns the path created. */
+export async function createDirectory(workspace: string, path: string): Promise<string[]> {
+  return invokeFileOperation("create_directory", { workspace, path });
+}
+
+/**
+ * Rename a file or directory inside the workspace, failing with
+ * `already_exists` if `to` exists. Returns both paths.
+ */
+export async function renamePath(workspace: string, from: string, to: string): Promise<string[]> {
+  return invokeFileOperation("rename_path", { workspace, from, to });
+}
+
+/**
+ * Delete a file or directory inside the workspace, to the system trash
+ * unless `toTrash` is false. Returns the path deleted.
+ */
+export async function deletePath(
+  workspace: string,
+  path: string,
+  toTrash = true
+): Promise<string[]> {
+  return invokeFileOperation("delete_path", { workspace, path, toTrash });
+}
+
+/**
+ * List a page of the .http, .rest and env files in a directory recursively
+ */
+export async function listHttpFiles(
+  directory: string,
+  options: ListFilesOptions = {}
+): Promise<FileListing> {
+  return invokeWithErrorHandling<FileListing>("list_http_files", { directory, ...options });
+}
+
+/**
+ * List every page of a directory's files, as one listing
+ */
+export async function listAllHttpFiles(
+  directory: string,
+  options: Omit<ListFilesOptions, "cursor"> = {}
+): Promise<FileListing> {
+  const all: FileListing = { files: [], next_cursor: null, warnings: [] };
+  let cursor: string | undefined;
+  do {
+    const page = await listHttpFiles(directory, { ...options, cursor });
+    all.files.push(...page.files);
+    all.warnings.push(...page.warnings);
+    cursor = page.next_cursor ?? undefined;
+  } while (cursor);
+  return all;
+}
+
 /**
- * List all HTTP files in a directory recursively
+ * Search a workspace's .http and .rest files. A newer search with the same
+ * `searchId` cancels the previous one.
  */
-export async function listHttpFiles(directory: string): Promise<FileInfo[]> {
-  return invokeWithErrorHandling<FileInfo[]>("list_http_files", { directory });
+export async function searchWorkspace(
+  workspace: string,
+  query: string,
+  options: SearchOptions = {},
+  searchId?: string
+): Promise<SearchResults> {
+  return invokeWithErrorHandling<SearchResults>("search_workspace", {
+    workspace,
+    query,
+    options,
+    searchId,
+  });
+}
+
+/**
+ * Cancel a running search. Resolves to whether one was running.
+ */
+export async function cancelSearch(searchId: string): Promise<boolean> {
+  return invokeWithErrorHandling<boolean>("cancel_search", { searchId });
 }
 
 /**
- * Start watching a directory for file changes
+ * Start watching a directory for file changes, alongside any other watched
+ * roots. Watching a root again changes nothing.
  */
 export async function startWatching(directory: string): Promise<void> {
   return invokeWithErrorHandling<void>("start_watching", { directory });
 }
 
+/** Event the watcher emits with a FilesChanged payload */
+export const FILES_CHANGED_EVENT = "files-changed";
+
+export type ChangeKind = "created" | "modified" | "removed" | "renamed";
+
+/** "other" is a directory or another file shown in the tree */
+export type FileKind = "http" | "env" | "other";
+
+export interface FileChange {
+  path: string;
+  kind: ChangeKind;
+  file_kind: FileKind;
+  /** For renames, the path before, when the platform reports both */
+  from?: string;
+}
+
+/** Event the watcher emits with a FileDeleted payload */
+export const FILE_DELETED_EVENT = "file-deleted";
+
+/** A deleted .http or .rest file, possibly in a deleted directory */
+export interface FileDeleted {
+  root: string;
+  path: string;
+}
+
+/** Event the watcher emits with a FileRenamed payload */
+export const FILE_RENAMED_EVENT = "file-renamed";
+
+/** A rename whose old and new names were both seen */
+export interface FileRenamed {
+  root: string;
+  from: string;
+  to: string;
+}
+
+/** The changes in a watched directory since the last event, one per path */
+export interface FilesChanged {
+  /** The watched root the changes belong to */
+  root: string;
+  changes: FileChange[];
+}
+
+/**
+ * Stop watching one root
+ */
+export async function stopWatching(path: string): Promise<void> {
+  return invokeWithErrorHandling<void>("stop_watching", { path });
+}
+
+/**
+ * Stop watching every root
+ */
+export async function stopAllWatching(): Promise<void> {
+  return invokeWithErrorHandling<void>("stop_all_watching", {});
+}
+
+/**
+ * Stop reporting file changes in every root until resumeWatching. Changes
+ * made meanwhile are not reported.
+ */
+export async function pauseWatching(): Promise<void> {
+  return invokeWithErrorHandling<void>("pause_watching", {});
+}
+
+/**
+ * Report file changes again after pauseWatching
+ */
+export async function resumeWatching(): Promise<void> {
+  return invokeWithErrorHandling<void>("resume_watching", {});
+}
+
 /**
- * Stop watching the current directory
+ * Get the watched roots
  */
-export async function stopWatching(): Promise<void> {
-  return invokeWithErrorHandling<void>("stop_watching", {});
+export async function getWatchedPaths(): Promise<string[]> {
+  return invokeWithErrorHandling<string[]>("get_watched_paths", {});
 }
 
 /**
- * Load environment configuration from workspace
+ * Load environment configuration from workspace. With a `filePath` the env
+ * files between the workspace root and that file's directory are merged,
+ * with the closest file winning. Other files matching `envGlob` are merged
+ * in too, deeper paths overriding shallower ones.
  */
 export async function loadEnvironmentConfig(
-  workspace: string
+  workspace: string,
+  filePath?: string,
+  envGlob?: string
 ): Promise<EnvironmentConfig> {
   return invokeWithErrorHandling<EnvironmentConfig>("load_environment_config", {
     workspace,
+    filePath,
+    envGlob,
   });
 }
 
@@ -151,6 +561,324 @@ export async function saveEnvironment(
   });
 }
 
+/**
+ * Set a single variable of an environment. Private variables are written to
+ * http-client.private.env.json; secret ones are kept in the OS keychain.
+ * Returns a warning when the keychain was unavailable and the value was
+ * written to the file instead.
+ */
+export async function saveEnvironmentVariable(
+  workspace: string,
+  environment: string,
+  key: string,
+  value: string,
+  isPrivate: boolean,
+  secret = false
+): Promise<string | null> {
+  return invokeWithErrorHandling<string | null>("save_environment_variable", {
+    workspace,
+    environment,
+    key,
+    value,
+    private: isPrivate,
+    secret,
+  });
+}
+
+/**
+ * Remove a single variable from an environment
+ */
+export async function deleteEnvironmentVariable(
+  workspace: string,
+  environment: string,
+  key: string,
+  isPrivate: boolean
+): Promise<void> {
+  return invokeWithErrorHandling<void>("delete_environment_variable", {
+    workspace,
+    environment,
+    key,
+    private: isPrivate,
+  });
+}
+
+export interface ResolvedVariable {
+  name: string;
+  source:
+    | "prompt"
+    | "request"
+    | "global"
+    | "file"
+    | "environment"
+    | "shared"
+    | "process"
+    | "dynamic";
+  /** Env file an environment value came from */
+  file: string | null;
+}
+
+export interface ResolvedRequest {
+  request: ParsedRequest;
+  /** Environment the request was resolved against, after any `# @env` */
+  environment: string | null;
+  variables: ResolvedVariable[];
+  missing: string[];
+}
+
+/** .http content or a file to take a request from, or a request already parsed */
+export type RequestSource = { content: string } | { path: string } | { parsed: ParsedRequest };
+
+export interface ResolveOptions {
+  /** Index or name of the source's request; the first by default */
+  request?: number | string;
+  environment?: string;
+  workspace?: string;
+  promptAnswers?: Record<string, string>;
+  filePath?: string;
+  variables?: Record<string, string>;
+  globals?: Record<string, string>;
+  processEnv?: boolean;
+  envGlob?: string;
+}
+
+/** A request for `sendRequest` to resolve before sending */
+export interface ResolveArgs {
+  source: RequestSource;
+  request?: number | string;
+  environment?: string;
+  workspace?: string;
+  prompt_answers?: Record<string, string>;
+  file_path?: string;
+  variables?: Record<string, string>;
+  globals?: Record<string, string>;
+  process_env?: boolean;
+  env_glob?: string;
+}
+
+/**
+ * Substitute variables in a request and report where each value came from.
+ * Lookup order is prompt answers, `variables`, `globals`, the file's own
+ * variables, the environment, shared values and, with `processEnv`, the OS
+ * environment. Names declared with `# @prompt` come only from prompt
+ * answers, and dynamic variables such as `{{$uuid}}` are generated.
+ */
+export async function resolveRequest(
+  source: RequestSource,
+  options: ResolveOptions = {}
+): Promise<ResolvedRequest> {
+  return invokeWithErrorHandling<ResolvedRequest>("resolve_request", {
+    source,
+    ...options,
+  });
+}
+
+export interface HeaderChange {
+  /** Lowercased */
+  name: string;
+  left: string | null;
+  right: string | null;
+}
+
+export interface JsonChange {
+  /** e.g. `$.items[0].id` */
+  path: string;
+  /** Absent on that side when null */
+  left: unknown | null;
+  right: unknown | null;
+}
+
+export interface LineChange {
+  left_line: number | null;
+  right_line: number | null;
+  text: string;
+}
+
+/** How two responses differ; Content-Length, timings and sizes aren't compared */
+export interface ResponseDiff {
+  /** Both statuses, when they differ */
+  status: [number, number] | null;
+  headers: HeaderChange[];
+  body: { kind: "json"; changes: JsonChange[] } | { kind: "text"; changes: LineChange[] };
+  identical: boolean;
+}
+
+export interface EnvironmentResponse {
+  environment: string;
+  url: string | null;
+  response: HttpResponse | null;
+  error: string | null;
+}
+
+export interface EnvironmentComparison {
+  responses: EnvironmentResponse[];
+  /** Every pair of environments that both responded */
+  diffs: { left: string; right: string; diff: ResponseDiff }[];
+}
+
+/**
+ * Send a named request once per environment and diff the responses of every
+ * pair. `ignoreHeaders` and `ignorePaths` (e.g. `$.links[*].href`) leave out
+ * values expected to differ, such as hostnames.
+ */
+export async function compareAcrossEnvironments(
+  workspace: string,
+  file: string,
+  requestName: string,
+  environments: string[],
+  options: { ignoreHeaders?: string[]; ignorePaths?: string[]; envGlob?: string } = {}
+): Promise<EnvironmentComparison> {
+  return invokeWithErrorHandling<EnvironmentComparison>("compare_across_environments", {
+    workspace,
+    file,
+    requestName,
+    environments,
+    ...options,
+  });
+}
+
+export interface EnvDiagnostic {
+  file: string;
+  line: number | null;
+  column: number | null;
+  severity: "error" | "warning";
+  message: string;
+}
+
+/**
+ * Check the env files a .http file would load for syntax errors and likely
+ * mistakes, including variables glob-matched files define differently
+ */
+export async function validateEnvironmentConfig(
+  workspace: string,
+  filePath?: string,
+  envGlob?: string
+): Promise<EnvDiagnostic[]> {
+  return invokeWithErrorHandling<EnvDiagnostic[]>("validate_environment_config", {
+    workspace,
+    filePath,
+    envGlob,
+  });
+}
+
+export interface UndefinedReference {
+  name: string;
+  line: number;
+}
+
+export interface FileLint {
+  file: string;
+  undefined: UndefinedReference[];
+}
+
+export interface VariableLint {
+  files: FileLint[];
+  unused: string[];
+}
+
+/**
+ * Find {{name}} references nothing defines across the workspace's .http
+ * files, and env variables never referenced. Without an environment, any
+ * environment's variables count as defined.
+ */
+export async function lintWorkspaceVariables(
+  workspace: string,
+  environment?: string,
+  envGlob?: string
+): Promise<VariableLint> {
+  return invokeWithErrorHandling<VariableLint>("lint_workspace_variables", {
+    workspace,
+    environment,
+    envGlob,
+  });
+}
+
+export interface HttpDiagnostic {
+  /** Which check found it, such as "get-with-body" */
+  code: string;
+  line: number;
+  severity: "error" | "warning";
+  message: string;
+}
+
+/**
+ * Find problems that make part of a .http file be skipped or misread, such
+ * as a block without a request line
+ */
+export async function validateHttpFile(content: string): Promise<HttpDiagnostic[]> {
+  return invokeWithErrorHandling<HttpDiagnostic[]>("validate_http_file", { content });
+}
+
+/**
+ * Check a .http file's requests against the lint rules the workspace's
+ * kvile.json leaves on
+ */
+export async function lintHttpFile(
+  content: string,
+  workspace: string,
+  environment?: string
+): Promise<HttpDiagnostic[]> {
+  return invokeWithErrorHandling<HttpDiagnostic[]>("lint_http_file", {
+    content,
+    workspace,
+    environment,
+  });
+}
+
+export interface PrivateEnvScaffold {
+  env_file: string;
+  created_env_file: boolean;
+  added_environment: boolean;
+  gitignore: string | null;
+  created_gitignore: boolean;
+  gitignore_entries: string[];
+}
+
+/**
+ * Create http-client.private.env.json with an empty environment and, in a git
+ * repo, add the private env files to .gitignore
+ */
+export async function createPrivateEnv(
+  workspace: string,
+  environment: string
+): Promise<PrivateEnvScaffold> {
+  return invokeWithErrorHandling<PrivateEnvScaffold>("create_private_env", {
+    workspace,
+    environment,
+  });
+}
+
+/**
+ * Encrypt the workspace's private env file with a passphrase
+ */
+export async function encryptPrivateEnv(workspace: string, passphrase: string): Promise<void> {
+  return invokeWithErrorHandling<void>("encrypt_private_env", { workspace, passphrase });
+}
+
+/**
+ * Unlock the workspace's encrypted private env file for this session
+ */
+export async function unlockPrivateEnv(workspace: string, passphrase: string): Promise<void> {
+  return invokeWithErrorHandling<void>("unlock_private_env", { workspace, passphrase });
+}
+
+/**
+ * Allow or forbid `$exec` env values to run commands in a workspace. The
+ * choice is remembered across restarts.
+ */
+export async function setExecTrust(workspace: string, trusted: boolean): Promise<void> {
+  return invokeWithErrorHandling<void>("set_exec_trust", { workspace, trusted });
+}
+
+/**
+ * Move every value in the workspace's private env file into the OS keychain.
+ * Returns how many values were moved.
+ */
+export async function movePrivateValuesToKeychain(workspace: string): Promise<number> {
+  return invokeWithErrorHandling<number>("move_private_values_to_keychain", {
+    workspace,
+  });
+}
+
 /**
  * Get file extension based on content type
  */
@@ -226,6 +954,10 @@ export interface HistoryEntry {
   response_body: string;
   duration_ms: number;
   response_size: number;
+  replayed_from: number | null; // ID of the entry this one re-sent
+  environment: string | null; // active environment when sent
+  variables_snapshot: string | null; // JSON object, secrets masked
+  response_body_base64: boolean; // response_body is base64-encoded
 }
 
 export interface NewHistoryEntry {
@@ -242,12 +974,53 @@ export interface NewHistoryEntry {
   response_body: string;
   duration_ms: number;
   response_size: number;
+  replayed_from?: number;
+  environment?: string;
+  response_body_base64?: boolean;
 }
 
-/**
- * Get history entries for a workspace
- */
-export async function getHistory(
+/** Optional history filters; unset fields match everything */
+export interface HistoryFilter {
+  methods?: string[];
+  status_class?: number; // e.g. 5 for 5xx
+  status_min?: number;
+  status_max?: number;
+  file_path?: string;
+  request_name?: string;
+  from?: string; // RFC 3339, inclusive
+  to?: string; // RFC 3339, inclusive
+  tags?: string[]; // entries must carry all of them
+}
+
+export interface TagCount {
+  tag: string;
+  count: number;
+}
+
+/** Position after the last entry of a history page */
+export interface HistoryCursor {
+  timestamp: string;
+  id: number;
+}
+
+export interface HistoryPage {
+  entries: HistoryEntry[];
+  total: number;
+  has_more: boolean;
+  next_cursor: HistoryCursor | null;
+}
+
+/** Request and response bodies of a history entry */
+export interface HistoryBodies {
+  request_body: string | null;
+  response_body: string;
+}
+
+/**
+ * Get history entries for a workspace.
+ * Bodies are left out; fetch them with `getHistoryEntryBody`.
+ */
+export async function getHistory(
   workspace: string,
   limit?: number
 ): Promise<HistoryEntry[]> {
@@ -258,7 +1031,76 @@ export async function getHistory(
 }
 
 /**
- * Get a single history entry by ID
+ * Get history entries for a workspace matching a filter (most recent first)
+ */
+export async function getHistoryFiltered(
+  workspace: string,
+  filter: HistoryFilter,
+  limit?: number
+): Promise<HistoryEntry[]> {
+  return invokeWithErrorHandling<HistoryEntry[]>("get_history_filtered", {
+    workspace,
+    filter,
+    limit,
+  });
+}
+
+/**
+ * Get a page of history entries (most recent first) with the total count.
+ * Pass the previous page's `next_cursor` to fetch the next one.
+ */
+export async function getHistoryPage(
+  workspace: string,
+  filter?: HistoryFilter,
+  cursor?: HistoryCursor,
+  limit?: number
+): Promise<HistoryPage> {
+  return invokeWithErrorHandling<HistoryPage>("get_history_page", {
+    workspace,
+    filter,
+    cursor,
+    limit,
+  });
+}
+
+/**
+ * Count the history entries for a workspace matching a filter
+ */
+export async function countHistory(
+  workspace: string,
+  filter?: HistoryFilter
+): Promise<number> {
+  return invokeWithErrorHandling<number>("count_history", { workspace, filter });
+}
+
+/**
+ * Get the request and response bodies of a history entry
+ */
+export async function getHistoryEntryBody(id: number): Promise<HistoryBodies | null> {
+  return invokeWithErrorHandling<HistoryBodies | null>("get_history_entry_body", {
+    id,
+  });
+}
+
+export interface JsonMatch {
+  /** Normalized path of the value, e.g. `$['items'][0]['id']` */
+  path: string;
+  value: unknown;
+}
+
+/**
+ * Query a JSON body, or the response body of the history entry with that ID,
+ * with a JSONPath such as `$.items[?(@.id > 3)].name`
+ */
+export async function extractJson(
+  bodyOrHistoryId: string | number,
+  jsonpath: string
+): Promise<JsonMatch[]> {
+  return invokeWithErrorHandling<JsonMatch[]>("extract_json", { bodyOrHistoryId, jsonpath });
+}
+
+/**
+ * Get a single history entry by ID, including its bodies
  */
 export async function getHistoryEntry(id: number): Promise<HistoryEntry | null> {
   return invokeWithErrorHandling<HistoryEntry | null>("get_history_entry", {
@@ -267,10 +1109,92 @@ export async function getHistoryEntry(id: number): Promise<HistoryEntry | null>
 }
 
 /**
- * Add a new history entry
+ * Re-send the request recorded in a history entry.
+ * Returns the new entry, linked to the original via `replayed_from`.
  */
-export async function addHistoryEntry(entry: NewHistoryEntry): Promise<number> {
-  return invokeWithErrorHandling<number>("add_history_entry", { entry });
+export async function resendFromHistory(id: number): Promise<HistoryEntry> {
+  return invokeWithErrorHandling<HistoryEntry>("resend_from_history", { id });
+}
+
+/**
+ * Tag a history entry. Returns false if it already had the tag.
+ */
+export async function addHistoryTag(id: number, tag: string): Promise<boolean> {
+  return invokeWithErrorHandling<boolean>("add_history_tag", { id, tag });
+}
+
+/**
+ * Remove a tag from a history entry
+ */
+export async function removeHistoryTag(id: number, tag: string): Promise<boolean> {
+  return invokeWithErrorHandling<boolean>("remove_history_tag", { id, tag });
+}
+
+/**
+ * Get the tags of a history entry
+ */
+export async function getHistoryEntryTags(id: number): Promise<string[]> {
+  return invokeWithErrorHandling<string[]>("get_history_entry_tags", { id });
+}
+
+/**
+ * List the tags used in a workspace with their counts, most used first
+ */
+export async function listHistoryTags(workspace: string): Promise<TagCount[]> {
+  return invokeWithErrorHandling<TagCount[]>("list_history_tags", { workspace });
+}
+
+/**
+ * Search a workspace's history, most recent first.
+ * Supports `method:POST` and `status:500` / `status:5xx` alongside free text.
+ */
+export async function searchHistory(
+  workspace: string,
+  query: string,
+  limit?: number,
+  includeBodies?: boolean
+): Promise<HistoryEntry[]> {
+  return invokeWithErrorHandling<HistoryEntry[]>("search_history", {
+    workspace,
+    query,
+    limit,
+    includeBodies,
+  });
+}
+
+/** An entry (without bodies) found by a body search */
+export interface BodySearchHit extends HistoryEntry {
+  matched: "request" | "response";
+  /** Excerpt of the matching body with matches wrapped in <mark> tags */
+  snippet: string;
+}
+
+/**
+ * Full-text search a workspace's request and response bodies, most recent
+ * first. Every term must match a whole token; JSON punctuation separates
+ * tokens while `_` does not.
+ */
+export async function searchHistoryBodies(
+  workspace: string,
+  query: string,
+  limit?: number
+): Promise<BodySearchHit[]> {
+  return invokeWithErrorHandling<BodySearchHit[]>("search_history_bodies", {
+    workspace,
+    query,
+    limit,
+  });
+}
+
+/**
+ * Add a new history entry. `variables` are stored as a snapshot with
+ * secrets from private env files masked.
+ */
+export async function addHistoryEntry(
+  entry: NewHistoryEntry,
+  variables?: Record<string, string>
+): Promise<number> {
+  return invokeWithErrorHandling<number>("add_history_entry", { entry, variables });
 }
 
 /**
@@ -287,14 +1211,620 @@ export async function clearHistory(workspace: string): Promise<number> {
   return invokeWithErrorHandling<number>("clear_history", { workspace });
 }
 
+/**
+ * Delete a workspace's history from before `timestamp` (exclusive), keeping
+ * entries tagged "pinned". Returns the number of entries deleted.
+ */
+export async function deleteHistoryBefore(
+  workspace: string,
+  timestamp: string
+): Promise<number> {
+  return invokeWithErrorHandling<number>("delete_history_before", {
+    workspace,
+    timestamp,
+  });
+}
+
+/** How much history to keep; null limits are not enforced */
+export interface RetentionSettings {
+  max_entries_per_workspace: number | null;
+  max_age_days: number | null;
+  max_db_size_bytes: number | null;
+}
+
+export interface HistoryStats {
+  entry_count: number;
+  db_size_bytes: number;
+  oldest_entry: string | null;
+}
+
+export interface PruneReport {
+  deleted: number;
+  vacuumed: boolean;
+}
+
+/**
+ * Get the history entry count, database size and oldest entry
+ */
+export async function getHistoryStats(): Promise<HistoryStats> {
+  return invokeWithErrorHandling<HistoryStats>("get_history_stats");
+}
+
+export interface EndpointStats {
+  method: string;
+  endpoint: string;
+  count: number;
+  error_rate: number; // share of 4xx/5xx responses
+  avg_duration_ms: number;
+  p50_duration_ms: number;
+  p95_duration_ms: number;
+  total_bytes: number;
+}
+
+/**
+ * Get per-endpoint statistics over a period, busiest endpoints first.
+ * With `normalize` (the default) IDs in paths collapse to `:id`.
+ */
+export async function getEndpointStats(
+  workspace: string,
+  period: "day" | "week" | "month" | "all",
+  normalize?: boolean
+): Promise<EndpointStats[]> {
+  return invokeWithErrorHandling<EndpointStats[]>("get_endpoint_stats", {
+    workspace,
+    period,
+    normalize,
+  });
+}
+
+export interface LatestResult {
+  id: number;
+  file_path: string;
+  request_name: string | null;
+  status: number;
+  duration_ms: number;
+  timestamp: string;
+}
+
+/**
+ * Get the newest result of each request sent from a file, or from any file
+ * in the workspace when `filePath` is omitted. Requests never sent are absent.
+ */
+export async function getLatestPerRequest(
+  workspace: string,
+  filePath?: string
+): Promise<LatestResult[]> {
+  return invokeWithErrorHandling<LatestResult[]>("get_latest_per_request", {
+    workspace,
+    filePath,
+  });
+}
+
+/** Workspace setting naming the environment last selected in the workspace */
+export const SELECTED_ENVIRONMENT_KEY = "selected_environment";
+
+/**
+ * Workspace setting that, when "true", lists and watches files the
+ * workspace's .gitignore, .ignore and .kvileignore files exclude
+ */
+export const INCLUDE_IGNORED_KEY = "include_ignored";
+
+/**
+ * Workspace settings for which files are listed and watched: extra ignore
+ * patterns in .gitignore syntax, one per line; how many directories deep to
+ * look (32 by default); and, when "true", following symlinks
+ */
+export const IGNORE_PATTERNS_KEY = "ignore_patterns";
+export const MAX_DEPTH_KEY = "max_depth";
+export const FOLLOW_SYMLINKS_KEY = "follow_symlinks";
+
+/** Workspace settings for the page shown after an OAuth login */
+export const CALLBACK_SUCCESS_HTML_KEY = "callback_success_html";
+export const CALLBACK_ERROR_HTML_KEY = "callback_error_html";
+export const CALLBACK_AUTO_CLOSE_KEY = "callback_auto_close";
+
+/**
+ * Get the settings stored for a workspace on this machine, such as the
+ * selected environment
+ */
+export async function getLocalWorkspaceSettings(
+  workspace: string
+): Promise<Record<string, string>> {
+  return invokeWithErrorHandling<Record<string, string>>("get_local_workspace_settings", {
+    workspace,
+  });
+}
+
+/**
+ * Store a setting for a workspace on this machine; `null` removes it
+ */
+export async function setLocalWorkspaceSetting(
+  workspace: string,
+  key: string,
+  value: string | null
+): Promise<void> {
+  return invokeWithErrorHandling<void>("set_local_workspace_setting", {
+    workspace,
+    key,
+    value,
+  });
+}
+
+/**
+ * Settings shared through kvile.json at the workspace root. Keys kvile
+ * doesn't know are kept as they are.
+ */
+export interface WorkspaceSettings {
+  default_headers?: Record<string, string>;
+  timeout_ms?: number;
+  proxy?: string;
+  /** Environment selected when none has been picked on this machine */
+  environment?: string;
+  /** Paths the file list and watcher skip, in .gitignore syntax */
+  ignore?: string[];
+  /** Lint rules turned on or off by code; rules not listed are on */
+  lint?: Record<string, boolean>;
+  [key: string]: unknown;
+}
+
+export interface LoadedSettings {
+  settings: WorkspaceSettings;
+  /** Values in kvile.json that were left out for having the wrong type */
+  warnings: string[];
+}
+
+/**
+ * Get a workspace's kvile.json settings, defaults when it has none
+ */
+export async function getWorkspaceSettings(workspace: string): Promise<LoadedSettings> {
+  return invokeWithErrorHandling<LoadedSettings>("get_workspace_settings", { workspace });
+}
+
+/**
+ * Write a workspace's kvile.json settings
+ */
+export async function updateWorkspaceSettings(
+  workspace: string,
+  settings: WorkspaceSettings
+): Promise<void> {
+  return invokeWithErrorHandling<void>("update_workspace_settings", { workspace, settings });
+}
+
+/**
+ * Get the history retention settings
+ */
+export async function getHistoryRetention(): Promise<RetentionSettings> {
+  return invokeWithErrorHandling<RetentionSettings>("get_history_retention");
+}
+
+/**
+ * Save the history retention settings (applied at startup and periodically)
+ */
+export async function setHistoryRetention(settings: RetentionSettings): Promise<void> {
+  return invokeWithErrorHandling<void>("set_history_retention", { settings });
+}
+
+/**
+ * Prune history according to the retention settings right away
+ */
+export async function runPruneNow(): Promise<PruneReport> {
+  return invokeWithErrorHandling<PruneReport>("run_prune_now");
+}
+
+/**
+ * Why history is kept in memory instead of on disk, if the database file
+ * could not be opened at startup
+ */
+export async function getHistoryDbWarning(): Promise<string | null> {
+  return invokeWithErrorHandling<string | null>("get_history_db_warning");
+}
+
+/**
+ * Back up the history database file and recreate it empty.
+ * Returns the backup path, if there was a file to back up.
+ */
+export async function repairHistoryDb(): Promise<string | null> {
+  return invokeWithErrorHandling<string | null>("repair_history_db");
+}
+
+/**
+ * Export a workspace's history (oldest first) to a JSON or CSV file.
+ * CSV bodies are truncated to `bodyLimit` characters (default 1024).
+ * Returns the number of entries written.
+ */
+export async function exportHistory(
+  workspace: string,
+  format: "json" | "csv",
+  path: string,
+  filter?: HistoryFilter,
+  bodyLimit?: number
+): Promise<number> {
+  return invokeWithErrorHandling<number>("export_history", {
+    workspace,
+    format,
+    path,
+    filter,
+    bodyLimit,
+  });
+}
+
+/**
+ * Rebuild requests from a workspace's history as .http text, oldest first.
+ * `dedupe` keeps the latest request per method and URL; `authPlaceholders`
+ * replaces credentials in auth headers with `{{token}}`. With `path` the
+ * text is also written there.
+ */
+export async function exportHistoryAsHttp(
+  workspace: string,
+  options: {
+    filter?: HistoryFilter;
+    dedupe?: boolean;
+    authPlaceholders?: boolean;
+    path?: string;
+  } = {}
+): Promise<string> {
+  return invokeWithErrorHandling<string>("export_history_as_http", { workspace, ...options });
+}
+
+export interface RecentWorkspace {
+  path: string;
+  last_opened: string;
+  /** Kept however many workspaces are opened after it */
+  pinned: boolean;
+  /** The directory no longer exists */
+  missing: boolean;
+}
+
+/**
+ * Workspaces opened before, pinned first and then most recent first
+ */
+export async function getRecentWorkspaces(): Promise<RecentWorkspace[]> {
+  return invokeWithErrorHandling<RecentWorkspace[]>("get_recent_workspaces");
+}
+
+/**
+ * Record that a workspace was opened
+ */
+export async function touchWorkspace(path: string): Promise<void> {
+  return invokeWithErrorHandling<void>("touch_workspace", { path });
+}
+
+/**
+ * Pin or unpin a recent workspace. Returns false if it isn't in the list.
+ */
+export async function pinWorkspace(path: string, pinned: boolean): Promise<boolean> {
+  return invokeWithErrorHandling<boolean>("pin_workspace", { path, pinned });
+}
+
+/**
+ * Remove a workspace from the recent list
+ */
+export async function removeRecentWorkspace(path: string): Promise<boolean> {
+  return invokeWithErrorHandling<boolean>("remove_recent_workspace", { path });
+}
+
+/**
+ * Save a history entry's response body to a file, decoded when it is
+ * binary. Given a directory, the file is named after the URL and
+ * Content-Type. Fails if the file exists unless `overwrite` is set. Returns
+ * the number of bytes written.
+ */
+export async function saveResponseBody(
+  historyId: number,
+  destPath: string,
+  overwrite = false
+): Promise<number> {
+  return invokeWithErrorHandling<number>("save_response_body", {
+    historyId,
+    destPath,
+    overwrite,
+  });
+}
+
+export interface LogRecord {
+  timestamp: string;
+  level: "ERROR" | "WARN" | "INFO" | "DEBUG" | "TRACE";
+  /** Module the event was logged from */
+  target: string;
+  message: string;
+  fields: Record<string, string>;
+  /** Spans the event happened in, outermost first, e.g. `send_request{request_id=12}` */
+  spans: string[];
+}
+
+export type LogLevel = "error" | "warn" | "info" | "debug" | "trace";
+
+/**
+ * The most recent log records at `level` or more severe, oldest first.
+ * Secrets are already redacted.
+ */
+export async function getRecentLogs(level?: LogLevel, limit?: number): Promise<LogRecord[]> {
+  return invokeWithErrorHandling<LogRecord[]>("get_recent_logs", { level, limit });
+}
+
+/**
+ * Open the log directory in the file manager. Returns its path.
+ */
+export async function openLogDirectory(): Promise<string> {
+  return invokeWithErrorHandling<string>("open_log_directory");
+}
+
+/**
+ * Back up the whole history database to a standalone file. Safe while the
+ * app runs.
+ */
+export async function exportHistoryDb(destPath: string): Promise<void> {
+  return invokeWithErrorHandling<void>("export_history_db", { destPath });
+}
+
+export interface HistoryImportReport {
+  imported: number;
+  /** Entries matching an existing one's workspace, timestamp, URL and method */
+  skipped: number;
+}
+
+/**
+ * Restore history from a backup. "merge" adds entries not already present;
+ * "replace" deletes all existing history first.
+ */
+export async function importHistoryDb(
+  srcPath: string,
+  mode: "merge" | "replace"
+): Promise<HistoryImportReport> {
+  return invokeWithErrorHandling<HistoryImportReport>("import_history_db", {
+    srcPath,
+    mode,
+  });
+}
+
+/**
+ * Export history entries as a HAR 1.2 document (JSON), oldest first
+ */
+export async function exportHar(entryIds: number[]): Promise<string> {
+  return invokeWithErrorHandling<string>("export_har", { entryIds });
+}
+
 // ===== IMPORT API =====
 
+export interface ImportResult {
+  http: string;
+  /** Flags that were skipped or only partially translated */
+  warnings: string[];
+}
+
 /**
  * Convert a cURL command to HTTP file format
+ * @param baseDir - Directory used to resolve `@file` references
  */
-export async function convertCurlToHttp(curlCommand: string): Promise<string> {
-  return invokeWithErrorHandling<string>("convert_curl_to_http", {
+export async function convertCurlToHttp(
+  curlCommand: string,
+  baseDir?: string
+): Promise<ImportResult> {
+  return invokeWithErrorHandling<ImportResult>("convert_curl_to_http", {
     curlCommand,
+    baseDir,
+  });
+}
+
+/**
+ * Convert text containing several cURL commands to a single HTTP file
+ */
+export async function convertCurlsToHttp(
+  text: string,
+  baseDir?: string
+): Promise<string> {
+  return invokeWithErrorHandling<string>("convert_curls_to_http", {
+    text,
+    baseDir,
+  });
+}
+
+/**
+ * Convert a browser "Copy as fetch" snippet to HTTP file format
+ */
+export async function convertFetchToHttp(snippet: string): Promise<string> {
+  return invokeWithErrorHandling<string>("convert_fetch_to_http", { snippet });
+}
+
+/**
+ * Convert an Invoke-RestMethod / Invoke-WebRequest command to HTTP file format
+ */
+export async function convertPowershellToHttp(
+  command: string
+): Promise<ImportResult> {
+  return invokeWithErrorHandling<ImportResult>("convert_powershell_to_http", {
+    command,
+  });
+}
+
+/**
+ * Convert a wget command to HTTP file format
+ */
+export async function convertWgetToHttp(command: string): Promise<ImportResult> {
+  return invokeWithErrorHandling<ImportResult>("convert_wget_to_http", {
+    command,
+  });
+}
+
+/**
+ * Convert a raw HTTP request message (from logs or a proxy) to HTTP file format
+ * @param useHttp - Build the URL with http:// instead of https://
+ */
+export async function importRawHttp(text: string, useHttp?: boolean): Promise<string> {
+  return invokeWithErrorHandling<string>("import_raw_http", { text, useHttp });
+}
+
+export interface ImportSummary {
+  files_created: string[];
+  /** Items that were skipped or only partially converted */
+  warnings: string[];
+}
+
+/**
+ * Import a Postman v2.1 collection into .http files
+ * @param outputDir - Directory the .http files are written to
+ */
+export async function importPostmanCollection(
+  json: string,
+  outputDir: string
+): Promise<ImportSummary> {
+  return invokeWithErrorHandling<ImportSummary>("import_postman_collection", {
+    json,
+    outputDir,
+  });
+}
+
+/**
+ * Import a Thunder Client collection or environment export into .http files
+ * and http-client.env.json under outputDir
+ */
+export async function importThunderClient(
+  json: string,
+  outputDir: string
+): Promise<ImportSummary> {
+  return invokeWithErrorHandling<ImportSummary>("import_thunder_client", {
+    json,
+    outputDir,
+  });
+}
+
+export interface EnvMergeDiff {
+  added: string[];
+  overwritten: string[];
+}
+
+export interface PostmanEnvironmentImport {
+  environment: string;
+  /** Changes to http-client.env.json */
+  public: EnvMergeDiff;
+  /** Changes to http-client.private.env.json (secret values) */
+  private: EnvMergeDiff;
+  dry_run: boolean;
+}
+
+/**
+ * Merge a Postman environment into the workspace env files
+ * @param environmentName - Target environment; defaults to the Postman environment's name
+ * @param dryRun - Only report the keys that would be added or overwritten
+ */
+export async function importPostmanEnvironment(
+  workspace: string,
+  json: string,
+  environmentName?: string,
+  dryRun?: boolean
+): Promise<PostmanEnvironmentImport> {
+  return invokeWithErrorHandling<PostmanEnvironmentImport>("import_postman_environment", {
+    workspace,
+    json,
+    environmentName,
+    dryRun,
+  });
+}
+
+/**
+ * Import an Insomnia v4 export (JSON or YAML) into .http files under outputDir
+ */
+export async function importInsomniaExport(
+  content: string,
+  outputDir: string
+): Promise<ImportSummary> {
+  return invokeWithErrorHandling<ImportSummary>("import_insomnia_export", {
+    content,
+    outputDir,
+  });
+}
+
+/**
+ * Generate .http files from an OpenAPI 3.x or Swagger 2.0 spec
+ * @param pathOrContent - Path to the spec, or the spec itself (JSON or YAML)
+ * @param baseUrlVar - Name of the server URL variable (defaults to baseUrl)
+ */
+export async function importOpenapi(
+  pathOrContent: string,
+  outputDir: string,
+  baseUrlVar?: string
+): Promise<ImportSummary> {
+  return invokeWithErrorHandling<ImportSummary>("import_openapi", {
+    pathOrContent,
+    outputDir,
+    baseUrlVar,
+  });
+}
+
+export interface HarImportOptions {
+  /** Response mime type prefixes to keep (all when empty) */
+  mime_types?: string[];
+  /** Hosts to keep (all when empty) */
+  hosts?: string[];
+  include_cookies?: boolean;
+  /** Where har-assets/ is written; defaults to the HAR file's directory */
+  output_dir?: string;
+}
+
+export interface HarImportResult {
+  http: string;
+  /** Entries filtered out, duplicated, or not convertible */
+  skipped: number;
+  warnings: string[];
+  assets: string[];
+}
+
+/**
+ * Convert a HAR capture into .http requests
+ */
+export async function importHar(
+  path: string,
+  options?: HarImportOptions
+): Promise<HarImportResult> {
+  return invokeWithErrorHandling<HarImportResult>("import_har", {
+    path,
+    options,
+  });
+}
+
+/**
+ * Export an .http file as a bash script of curl commands
+ * @param environment - Environment whose variables are substituted
+ * @param secretsAsEnv - Emit private env values as `$VAR` references
+ */
+export async function exportHttpAsScript(
+  content: string,
+  environment?: string,
+  workspace?: string,
+  secretsAsEnv?: boolean
+): Promise<string> {
+  return invokeWithErrorHandling<string>("export_http_as_script", {
+    content,
+    environment,
+    workspace,
+    secretsAsEnv,
+  });
+}
+
+/**
+ * Export .http files as a Postman v2.1 collection (JSON), one folder per file
+ */
+export async function exportPostmanCollection(
+  files: string[],
+  name: string
+): Promise<string> {
+  return invokeWithErrorHandling<string>("export_postman_collection", {
+    files,
+    name,
+  });
+}
+
+/**
+ * Export an environment, with $shared folded in, as a Postman environment (JSON).
+ * Variables from the private env file are marked as secrets.
+ */
+export async function exportPostmanEnvironment(
+  workspace: string,
+  environmentName: string
+): Promise<string> {
+  return invokeWithErrorHandling<string>("export_postman_environment", {
+    workspace,
+    environmentName,
   });
 }
 
@@ -307,26 +1837,58 @@ export interface OidcDiscovery {
   userinfo_endpoint?: string;
   jwks_uri?: string;
   end_session_endpoint?: string;
+  introspection_endpoint?: string;
   scopes_supported: string[];
   response_types_supported: string[];
   grant_types_supported: string[];
+  token_endpoint_auth_methods_supported?: string[];
+  code_challenge_methods_supported?: string[];
 }
 
+/** How the client authenticates to the token endpoint */
+export type TokenEndpointAuthMethod = "client_secret_post" | "client_secret_basic" | "none";
+
+/** PKCE method an auth request used; "none" when PKCE is off */
+export type PkceMethod = "S256" | "plain" | "none";
+
 export interface OidcConfig {
   issuer?: string;
   authorization_endpoint?: string;
   token_endpoint?: string;
   client_id: string;
   client_secret?: string;
+  /** Defaults to what discovery lists, preferring the form body */
+  token_endpoint_auth_method?: TokenEndpointAuthMethod;
   redirect_url: string;
+  /** Listen on a port the OS picks; port 0 in redirect_url does the same */
+  use_random_port?: boolean;
+  /** Send PKCE parameters (default true); some old providers reject them */
+  use_pkce?: boolean;
   scopes: string[];
   extra_params?: Record<string, string>;
+  /** Page shown in the browser after the callback */
+  callback_page?: OidcCallbackPage;
+}
+
+export interface OidcCallbackPage {
+  success_html?: string;
+  /** `{{error}}` and `{{error_description}}` are replaced, HTML-escaped */
+  error_html?: string;
+  /** Try to close the tab once the page is shown */
+  auto_close?: boolean;
 }
 
 export interface OidcAuthStartResult {
   auth_url: string;
   state: string;
-  code_verifier: string;
+  /** Nonce sent with the auth request, checked against the ID token */
+  nonce: string;
+  /** Absent when the flow runs without PKCE */
+  code_verifier?: string;
+  pkce_method: PkceMethod;
+  /** Redirect URL with the callback server's actual port, or the kvile://
+   * deep-link redirect as configured */
+  redirect_url: string;
 }
 
 export interface OidcTokenResponse {
@@ -336,6 +1898,8 @@ export interface OidcTokenResponse {
   refresh_token?: string;
   id_token?: string;
   scope?: string;
+  /** Type of the token a token exchange issued */
+  issued_token_type?: string;
 }
 
 /**
@@ -346,37 +1910,110 @@ export async function oidcDiscover(issuer: string): Promise<OidcDiscovery> {
 }
 
 /**
- * Start OIDC auth flow - returns auth URL to open in browser
+ * Start OIDC auth flow - returns auth URL to open in browser. Callback page
+ * templates the config leaves unset come from the workspace's settings.
  */
-export async function oidcStartAuth(config: OidcConfig): Promise<OidcAuthStartResult> {
-  return invokeWithErrorHandling<OidcAuthStartResult>("oidc_start_auth", { config });
+export async function oidcStartAuth(
+  config: OidcConfig,
+  workspace?: string
+): Promise<OidcAuthStartResult> {
+  return invokeWithErrorHandling<OidcAuthStartResult>("oidc_start_auth", { config, workspace });
 }
 
 /**
- * Wait for OIDC callback on localhost - returns authorization code
+ * Wait for OIDC callback on localhost - returns authorization code. Fails
+ * after `timeoutSecs` (five minutes by default) or when cancelled.
  */
 export async function oidcWaitForCallback(
   redirectUrl: string,
-  expectedState: string
+  expectedState: string,
+  timeoutSecs?: number
 ): Promise<string> {
   return invokeWithErrorHandling<string>("oidc_wait_for_callback", {
     redirectUrl,
     expectedState,
+    timeoutSecs,
+  });
+}
+
+/** What the IdP says about a token (RFC 7662); other claims are included as-is */
+export interface TokenIntrospection {
+  active: boolean;
+  scope?: string;
+  /** Unix time in seconds */
+  exp?: number;
+  sub?: string;
+  client_id?: string;
+  [claim: string]: unknown;
+}
+
+/**
+ * Ask the IdP whether a token is active and what it grants. Needs an issuer
+ * whose discovery document lists an introspection endpoint.
+ */
+export async function oidcIntrospectToken(
+  config: OidcConfig,
+  token: string
+): Promise<TokenIntrospection> {
+  return invokeWithErrorHandling<TokenIntrospection>("oidc_introspect_token", { config, token });
+}
+
+/**
+ * Stop waiting for the OIDC callback - returns whether a login was waiting
+ */
+export async function oidcCancelCallback(): Promise<boolean> {
+  return invokeWithErrorHandling<boolean>("oidc_cancel_callback", {});
+}
+
+export interface OidcLogoutResult {
+  /** The IdP's end-session URL to open in the browser */
+  logout_url: string;
+  state: string;
+}
+
+/**
+ * Log out of an auth config: clears its cached token and returns the IdP's
+ * end-session URL. Fails after clearing when the provider has no
+ * end_session_endpoint.
+ */
+export async function oidcLogout(
+  workspace: string,
+  authId: string,
+  config: OidcConfig,
+  idTokenHint?: string,
+  postLogoutRedirectUri?: string
+): Promise<OidcLogoutResult> {
+  return invokeWithErrorHandling<OidcLogoutResult>("oidc_logout", {
+    workspace,
+    authId,
+    config,
+    idTokenHint,
+    postLogoutRedirectUri,
   });
 }
 
 /**
- * Exchange authorization code for tokens
+ * Wait for the IdP to redirect back to a loopback post-logout redirect URI
+ */
+export async function oidcWaitForLogout(state: string, timeoutSecs?: number): Promise<void> {
+  return invokeWithErrorHandling<void>("oidc_wait_for_logout", { state, timeoutSecs });
+}
+
+/**
+ * Exchange authorization code for tokens. With the auth request's nonce, an
+ * ID token in the response must carry it.
  */
 export async function oidcExchangeCode(
   config: OidcConfig,
   code: string,
-  codeVerifier: string
+  codeVerifier: string | undefined,
+  nonce?: string
 ): Promise<OidcTokenResponse> {
   return invokeWithErrorHandling<OidcTokenResponse>("oidc_exchange_code", {
     config,
     code,
     codeVerifier,
+    nonce,
   });
 }
 
@@ -392,3 +2029,150 @@ export async function oidcRefreshToken(
     refreshToken,
   });
 }
+
+/**
+ * Get tokens for the client itself with the client credentials grant
+ */
+export async function oidcClientCredentials(config: OidcConfig): Promise<OidcTokenResponse> {
+  return invokeWithErrorHandling<OidcTokenResponse>("oidc_client_credentials", { config });
+}
+
+/** Error message prefix for a password grant with wrong credentials */
+export const OIDC_INVALID_GRANT = "invalid_grant";
+
+/**
+ * Get tokens with the legacy resource owner password credentials grant.
+ * Only for identity providers that offer nothing else; the credentials are
+ * never stored. Wrong credentials fail with a message starting with
+ * OIDC_INVALID_GRANT.
+ */
+export async function oidcPasswordGrant(
+  config: OidcConfig,
+  username: string,
+  password: string
+): Promise<OidcTokenResponse> {
+  return invokeWithErrorHandling<OidcTokenResponse>("oidc_password_grant", {
+    config,
+    username,
+    password,
+  });
+}
+
+/** Token type of an OAuth access token (RFC 8693) */
+export const ACCESS_TOKEN_TYPE = "urn:ietf:params:oauth:token-type:access_token";
+
+/**
+ * Exchange a token for one for another audience (RFC 8693). The subject
+ * token type defaults to an access token; error responses fail with their
+ * OAuth error code first, such as `invalid_target`.
+ */
+export async function oidcTokenExchange(
+  config: OidcConfig,
+  subjectToken: string,
+  subjectTokenType?: string,
+  audience?: string,
+  requestedTokenType?: string
+): Promise<OidcTokenResponse> {
+  return invokeWithErrorHandling<OidcTokenResponse>("oidc_token_exchange", {
+    config,
+    subjectToken,
+    subjectTokenType,
+    audience,
+    requestedTokenType,
+  });
+}
+
+/** Error message prefix when a cached token is missing or can't be refreshed */
+export const REAUTH_REQUIRED = "reauth_required";
+
+export interface CachedToken extends OidcTokenResponse {
+  /** Unix time in seconds the access token expires */
+  expires_at?: number;
+}
+
+/**
+ * Get the OIDC config a `# @auth oidc <config-name>` directive names, from
+ * the environment's `Security.Auth` configs or the workspace settings.
+ * Client credentials and password configs get tokens when sending, without
+ * a login.
+ */
+export async function getOidcAuthConfig(name: string, context: HistoryContext): Promise<OidcConfig> {
+  return invokeWithErrorHandling<OidcConfig>("get_oidc_auth_config", { name, context });
+}
+
+export interface StoreTokensResult {
+  token: CachedToken;
+  /** Set when no keychain was available and an encrypted file was used */
+  warning?: string;
+}
+
+/**
+ * Cache the tokens obtained for an auth config so later requests reuse them.
+ * They are kept in the OS keychain, or an encrypted file without one.
+ */
+export async function storeTokens(
+  workspace: string,
+  authId: string,
+  token: OidcTokenResponse
+): Promise<StoreTokensResult> {
+  return invokeWithErrorHandling<StoreTokensResult>("store_tokens", {
+    workspace,
+    authId,
+    token,
+  });
+}
+
+/**
+ * Get the tokens cached for an auth config, whether or not they have expired
+ */
+export async function loadTokens(workspace: string, authId: string): Promise<CachedToken | null> {
+  return invokeWithErrorHandling<CachedToken | null>("load_tokens", { workspace, authId });
+}
+
+/**
+ * Get the cached token for an auth config, refreshed when it expires within
+ * `skewSeconds`. Fails with a message starting with REAUTH_REQUIRED when the
+ * user has to log in again.
+ */
+export async function getValidToken(
+  workspace: string,
+  authId: string,
+  config: OidcConfig,
+  skewSeconds?: number
+): Promise<CachedToken> {
+  return invokeWithErrorHandling<CachedToken>("get_valid_token", {
+    workspace,
+    authId,
+    config,
+    skewSeconds,
+  });
+}
+
+/**
+ * Forget the tokens cached for an auth config
+ */
+export async function deleteTokens(workspace: string, authId: string): Promise<void> {
+  return invokeWithErrorHandling<void>("delete_tokens", { workspace, authId });
+}
+
+export interface DecodedJwt {
+  header: Record<string, unknown>;
+  payload: Record<string, unknown>;
+  signature: string;
+  /** `exp`, `iat` and `nbf` as RFC 3339 */
+  expires_at?: string;
+  issued_at?: string;
+  not_before?: string;
+  /** Seconds until `exp`; negative once expired */
+  expires_in?: number;
+  issuer?: string;
+  audience: string[];
+}
+
+/**
+ * Decode a JWT locally without verifying its signature. A `Bearer ` prefix
+ * is ignored; malformed tokens fail with a message naming the bad part.
+ */
+export async function decodeJwt(token: string): Promise<DecodedJwt> {
+  return invokeWithErrorHandling<DecodedJwt>("decode_jwt", { token });
+}
@@ -1,4 +1,5 @@
 import { resolveDynamicVariable } from "./dynamic-variables";
+import type { EnvironmentConfig } from "./tauri";
 
 export interface SubstitutionResult {
   result: string;
@@ -34,8 +35,9 @@ export function substituteVariables(
 
     // Regular variable lookup (without $ prefix)
     const cleanName = varContent.startsWith("$") ? varContent : varContent;
-    if (cleanName in variables) {
-      return variables[cleanName];
+    const value = lookupVariable(variables, cleanName);
+    if (value !== undefined) {
+      return value;
     }
 
     missing.push(varContent);
@@ -45,6 +47,72 @@ export function substituteVariables(
   return { result, missingVariables: missing };
 }
 
+/**
+ * Whether an env value is a `{"$exec": ...}` marker. The backend runs its
+ * command when a request it resolves uses the value.
+ */
+export function isExecMarker(value: string): boolean {
+  if (!value.startsWith("{") || !value.includes("$exec")) return false;
+  try {
+    const parsed = JSON.parse(value);
+    return typeof parsed === "object" && parsed !== null && "$exec" in parsed;
+  } catch {
+    return false;
+  }
+}
+
+/** Whether `$exec` commands provide values of `environment` or `$shared` */
+export function hasCommandVariables(
+  config: EnvironmentConfig | null,
+  environment?: string | null
+): boolean {
+  const commands = config?.command_variables ?? {};
+  return [commands["$shared"], environment ? commands[environment] : undefined].some(
+    (names) => (names?.length ?? 0) > 0
+  );
+}
+
+/**
+ * Look up a variable. A dotted name that isn't defined itself is resolved
+ * into the JSON value of its longest defined prefix, so `auth.clientId` and
+ * `hosts.0` reach into objects and arrays.
+ */
+export function lookupVariable(
+  variables: Record<string, string>,
+  name: string
+): string | undefined {
+  if (name in variables) {
+    return variables[name];
+  }
+
+  const segments = name.split(".");
+  for (let i = segments.length - 1; i > 0; i--) {
+    const prefix = segments.slice(0, i).join(".");
+    if (!(prefix in variables)) continue;
+
+    let value: unknown;
+    try {
+      value = JSON.parse(variables[prefix]);
+    } catch {
+      return undefined;
+    }
+    for (const segment of segments.slice(i)) {
+      if (Array.isArray(value) && /^\d+$/.test(segment)) {
+        value = value[Number(segment)];
+      } else if (value && typeof value === "object" && !Array.isArray(value)) {
+        value = Object.prototype.hasOwnProperty.call(value, segment)
+          ? (value as Record<string, unknown>)[segment]
+          : undefined;
+      } else {
+        return undefined;
+      }
+      if (value === undefined) return undefined;
+    }
+    return typeof value === "string" ? value : JSON.stringify(value);
+  }
+  return undefined;
+}
+
 /**
  * Extract inline variable definitions from .http file content
  * Supports VS Code REST Client style: @varName = value
@@ -4,14 +4,20 @@ import * as tauri from "@/lib/tauri";
 
 // Mock the tauri module
 vi.mock("@/lib/tauri", () => ({
-  listHttpFiles: vi.fn(),
+  listAllHttpFiles: vi.fn(),
   readFile: vi.fn(),
   writeFile: vi.fn(),
   sendRequest: vi.fn(),
   parseHttpFile: vi.fn().mockResolvedValue([]),
+  orderRequests: vi.fn(async (requests: unknown[]) => requests.map((_, i) => i)),
   startWatching: vi.fn().mockResolvedValue(undefined),
   stopWatching: vi.fn().mockResolvedValue(undefined),
   loadEnvironmentConfig: vi.fn().mockResolvedValue({ environments: [], shared: {} }),
+  SELECTED_ENVIRONMENT_KEY: "selected_environment",
+  getLocalWorkspaceSettings: vi.fn().mockResolvedValue({}),
+  getWorkspaceSettings: vi.fn().mockResolvedValue({ settings: {}, warnings: [] }),
+  touchWorkspace: vi.fn().mockResolvedValue(undefined),
+  setLocalWorkspaceSetting: vi.fn().mockResolvedValue(undefined),
 }));
 
 describe("appStore", () => {
@@ -37,10 +43,14 @@ describe("appStore", () => {
   describe("loadWorkspace", () => {
     it("loads files and builds tree on success", async () => {
       const mockFiles = [
-        { path: "/workspace/api/test.http", name: "test.http", is_http_file: true },
-        { path: "/workspace/readme.md", name: "readme.md", is_http_file: false },
+        { path: "/workspace/api/test.http", name: "test.http", is_http_file: true, size: 0, modified: null },
+        { path: "/workspace/readme.md", name: "readme.md", is_http_file: false, size: 0, modified: null },
       ];
-      vi.mocked(tauri.listHttpFiles).mockResolvedValue(mockFiles);
+      vi.mocked(tauri.listAllHttpFiles).mockResolvedValue({
+        files: mockFiles,
+        next_cursor: null,
+        warnings: [],
+      });
 
       await useAppStore.getState().loadWorkspace("/workspace");
 
@@ -52,8 +62,11 @@ describe("appStore", () => {
     });
 
     it("sets loading state while loading", async () => {
-      vi.mocked(tauri.listHttpFiles).mockImplementation(
-        () => new Promise((resolve) => setTimeout(() => resolve([]), 100))
+      vi.mocked(tauri.listAllHttpFiles).mockImplementation(
+        () =>
+          new Promise((resolve) =>
+            setTimeout(() => resolve({ files: [], next_cursor: null, warnings: [] }), 100)
+          )
       );
 
       const loadPromise = useAppStore.getState().loadWorkspace("/workspace");
@@ -65,8 +78,58 @@ describe("appStore", () => {
       expect(useAppStore.getState().isLoadingFiles).toBe(false);
     });
 
+    it("restores the environment last selected in the workspace", async () => {
+      vi.mocked(tauri.listAllHttpFiles).mockResolvedValue({
+        files: [],
+        next_cursor: null,
+        warnings: [],
+      });
+      vi.mocked(tauri.getLocalWorkspaceSettings).mockResolvedValueOnce({
+        selected_environment: "staging",
+      });
+      vi.mocked(tauri.loadEnvironmentConfig).mockResolvedValueOnce({
+        environments: [
+          { name: "dev", variables: {}, private_variables: {}, source_file: "", variable_sources: {} },
+          { name: "staging", variables: {}, private_variables: {}, source_file: "", variable_sources: {} },
+        ],
+        shared: {},
+        private_shared: {},
+      });
+
+      await useAppStore.getState().loadWorkspace("/workspace");
+
+      expect(tauri.getLocalWorkspaceSettings).toHaveBeenCalledWith("/workspace");
+      expect(useAppStore.getState().activeEnvironment).toBe("staging");
+
+      useAppStore.getState().setActiveEnvironment("dev");
+      expect(tauri.setLocalWorkspaceSetting).toHaveBeenCalledWith(
+        "/workspace",
+        "selected_environment",
+        "dev"
+      );
+    });
+
+    it("prefers the environment kvile.json sets", async () => {
+      vi.mocked(tauri.listAllHttpFiles).mockResolvedValue({
+        files: [],
+        next_cursor: null,
+        warnings: [],
+      });
+      vi.mocked(tauri.getLocalWorkspaceSettings).mockResolvedValueOnce({
+        selected_environment: "staging",
+      });
+      vi.mocked(tauri.getWorkspaceSettings).mockResolvedValueOnce({
+        settings: { environment: "dev" },
+        warnings: [],
+      });
+
+      await useAppStore.getState().loadWorkspace("/workspace");
+
+      expect(useAppStore.getState().activeEnvironment).toBe("dev");
+    });
+
     it("sets error on failure", async () => {
-      vi.mocked(tauri.listHttpFiles).mockRejectedValue(new Error("Permission denied"));
+      vi.mocked(tauri.listAllHttpFiles).mockRejectedValue(new Error("Permission denied"));
 
       await useAppStore.getState().loadWorkspace("/workspace");
 
@@ -143,6 +206,42 @@ describe("appStore", () => {
       expect(state.lastError).toBe("Network error");
       expect(state.isExecutingRequest).toBe(false);
     });
+
+    it("records a pinned environment instead of the active one", async () => {
+      vi.mocked(tauri.sendRequest).mockResolvedValue({
+        status: 200,
+        status_text: "OK",
+        headers: {},
+        body: "",
+        time: 1,
+        size: 0,
+      });
+      useAppStore.setState({
+        workspacePath: "/workspace",
+        activeEnvironment: "staging",
+        environmentConfig: {
+          environments: [
+            { name: "prod", variables: { host: "prod" }, private_variables: {}, source_file: "", variable_sources: {} },
+            { name: "staging", variables: { host: "staging" }, private_variables: {}, source_file: "", variable_sources: {} },
+          ],
+          shared: {},
+          private_shared: {},
+        },
+      });
+
+      expect(useAppStore.getState().getCurrentVariables("prod")).toEqual({ host: "prod" });
+      expect(useAppStore.getState().getCurrentVariables()).toEqual({ host: "staging" });
+
+      await useAppStore.getState().executeRequest(
+        { method: "GET", url: "https://prod", headers: {} },
+        "prod"
+      );
+
+      expect(tauri.sendRequest).toHaveBeenCalledWith(
+        expect.anything(),
+        expect.objectContaining({ environment: "prod", variables: { host: "prod" } })
+      );
+    });
   });
 
   describe("saveCurrentFile", () => {
@@ -216,6 +315,7 @@ describe("appStore", () => {
       expect(state.openFiles).toHaveLength(0);
       expect(state.activeFileIndex).toBe(-1);
       expect(state.currentResponse).toBeNull();
+      expect(tauri.stopWatching).toHaveBeenCalledWith("/some/path");
     });
   });
 });
@@ -1,7 +1,15 @@
 import { create } from "zustand";
 import { persist } from "zustand/middleware";
 import * as tauri from "@/lib/tauri";
-import { extractInlineVariables } from "@/lib/variables";
+import { useSettingsStore } from "@/stores/settingsStore";
+import { isReauthRequired, useAuthStore } from "@/stores/authStore";
+import { parseOidcConfigName } from "@/lib/auth-helpers";
+import {
+  extractInlineVariables,
+  hasCommandVariables,
+  isExecMarker,
+  lookupVariable,
+} from "@/lib/variables";
 import type {
   FileInfo,
   HttpRequest as TauriHttpRequest,
@@ -16,6 +24,8 @@ export interface HttpFile {
   content: string;
   modified: boolean;
   parsedRequests: ParsedRequest[];
+  /** Deleted on disk while open; saving it writes it back */
+  deleted?: boolean;
 }
 
 export interface HttpRequest {
@@ -80,6 +90,10 @@ interface AppState {
   closeFile: (index: number, force?: boolean) => void;
   updateFileContent: (index: number, content: string) => void;
   updateFileParsedRequests: (index: number, requests: ParsedRequest[]) => void;
+  /** Point the open tab for `from`, if any, at the file's new path */
+  renameOpenFile: (from: string, to: string) => void;
+  /** Flag the open tab for `path`, if any, as deleted on disk */
+  markFileDeleted: (path: string) => void;
 
   // Pending close (for unsaved changes confirmation)
   pendingCloseIndex: number | null;
@@ -107,7 +121,8 @@ interface AppState {
   activeEnvironment: string;
   setActiveEnvironment: (name: string) => void;
   loadEnvironments: () => Promise<void>;
-  getCurrentVariables: () => Record<string, string>;
+  /** Variables of `environment`, or of the active environment */
+  getCurrentVariables: (environment?: string) => Record<string, string>;
 
   // Workspace
   workspacePath: string | null;
@@ -132,7 +147,12 @@ interface AppState {
   loadWorkspace: (path: string) => Promise<void>;
   refreshWorkspace: () => Promise<void>;
   loadFileFromPath: (path: string, name: string) => Promise<void>;
-  executeRequest: (request: TauriHttpRequest) => Promise<void>;
+  /** Send a request; `environment` is recorded in history instead of the active one */
+  executeRequest: (
+    request: TauriHttpRequest,
+    environment?: string,
+    auth?: string
+  ) => Promise<void>;
   saveCurrentFile: () => Promise<void>;
   saveAllFiles: () => Promise<void>;
   discardFileChanges: (index?: number) => Promise<void>;
@@ -171,7 +191,7 @@ interface AppState {
   diffLeftResponse: HttpResponse | null;
   diffRightResponse: HttpResponse | null;
   setDiffResponses: (left: HttpResponse | null, right: HttpResponse | null) => void;
-  compareWithCurrent: (entry: HistoryEntry) => void;
+  compareWithCurrent: (entry: HistoryEntry) => Promise<void>;
 
   // Editor View (GUI vs Source)
   activeEditorView: 'gui' | 'source';
@@ -188,7 +208,10 @@ interface AppState {
   // History
   historyEntries: HistoryEntry[];
   isLoadingHistory: boolean;
+  /** Set when history is kept in memory because the database file is unusable */
+  historyWarning: string | null;
   loadHistory: () => Promise<void>;
+  repairHistory: () => Promise<void>;
   deleteHistoryEntry: (id: number) => Promise<void>;
   clearHistory: () => Promise<void>;
   viewHistoryEntry: (entry: HistoryEntry) => Promise<void>;
@@ -378,6 +401,21 @@ export const useAppStore = create<AppState>()(
           set({ parsedRequestsCache: newCache });
         }
       },
+      renameOpenFile: (from, to) => {
+        const { openFiles } = get();
+        if (!openFiles.some((f) => f.path === from)) return;
+        const name = to.split(/[\\/]/).pop() || to;
+        set({
+          openFiles: openFiles.map((f) => (f.path === from ? { ...f, path: to, name } : f)),
+        });
+      },
+      markFileDeleted: (path) => {
+        const { openFiles } = get();
+        if (!openFiles.some((f) => f.path === path)) return;
+        set({
+          openFiles: openFiles.map((f) => (f.path === path ? { ...f, deleted: true } : f)),
+        });
+      },
 
       // Pending close
       pendingCloseIndex: null,
@@ -430,13 +468,26 @@ export const useAppStore = create<AppState>()(
       // Environment
       environmentConfig: null,
       activeEnvironment: "",
-      setActiveEnvironment: (name) => set({ activeEnvironment: name }),
-      loadEnvironments: async () => {
+      setActiveEnvironment: (name) => {
+        set({ activeEnvironment: name });
+        // Remembered per workspace so reopening it keeps the environment
         const { workspacePath } = get();
+        if (workspacePath) {
+          tauri
+            .setLocalWorkspaceSetting(workspacePath, tauri.SELECTED_ENVIRONMENT_KEY, name || null)
+            .catch((error) => console.warn("Failed to save selected environment:", error));
+        }
+      },
+      loadEnvironments: async () => {
+        const { workspacePath, openFiles, activeFileIndex } = get();
         if (!workspacePath) return;
 
         try {
-          const config = await tauri.loadEnvironmentConfig(workspacePath);
+          const config = await tauri.loadEnvironmentConfig(
+            workspacePath,
+            openFiles[activeFileIndex]?.path,
+            useSettingsStore.getState().envFileGlob
+          );
           set({ environmentConfig: config });
 
           // Auto-select first environment if none selected or current doesn't exist
@@ -449,22 +500,26 @@ export const useAppStore = create<AppState>()(
           console.warn("Failed to load environments:", error);
         }
       },
-      getCurrentVariables: () => {
+      getCurrentVariables: (environment?: string) => {
         const { environmentConfig, activeEnvironment } = get();
         if (!environmentConfig) return {};
 
-        const env = environmentConfig.environments.find(
-          (e) => e.name === activeEnvironment
-        );
+        const name = environment || activeEnvironment;
+        const env = environmentConfig.environments.find((e) => e.name === name);
 
         // Merge: shared (public) -> shared (private) -> env vars (public) -> env vars (private)
         // Private variables override public ones with the same key
-        return {
+        const merged: Record<string, string> = {
           ...environmentConfig.shared,
           ...(environmentConfig.private_shared || {}),
           ...(env?.variables || {}),
           ...(env?.private_variables || {}),
         };
+        // `$exec` values are left to the backend, which runs only the
+        // commands a request uses
+        return Object.fromEntries(
+          Object.entries(merged).filter(([, value]) => !isExecMarker(value))
+        );
       },
 
       // Workspace
@@ -472,7 +527,10 @@ export const useAppStore = create<AppState>()(
       setWorkspacePath: (path) => set({ workspacePath: path }),
       closeWorkspace: () => {
         // Stop watching for file changes
-        tauri.stopWatching().catch(console.warn);
+        const { workspacePath } = get();
+        if (workspacePath) {
+          tauri.stopWatching(workspacePath).catch(console.warn);
+        }
         set({
           workspacePath: null,
           fileTree: [],
@@ -502,9 +560,30 @@ export const useAppStore = create<AppState>()(
       loadWorkspace: async (path: string) => {
         set({ isLoadingFiles: true, lastError: null });
         try {
-          const files = await tauri.listHttpFiles(path);
-          const tree = buildFileTree(files, path);
+          const listing = await tauri.listAllHttpFiles(path);
+          listing.warnings.forEach((warning) => console.warn(warning));
+          const tree = buildFileTree(listing.files, path);
           set({ workspacePath: path, fileTree: tree, isLoadingFiles: false });
+          tauri
+            .touchWorkspace(path)
+            .catch((error) => console.warn("Failed to record recent workspace:", error));
+
+          // Restore the environment last selected in this workspace
+          try {
+            const [local, shared] = await Promise.all([
+              tauri.getLocalWorkspaceSettings(path),
+              tauri.getWorkspaceSettings(path),
+            ]);
+            shared.warnings.forEach((warning) => console.warn(warning));
+            // kvile.json's environment wins over the one last picked here
+            const selected =
+              shared.settings.environment || local[tauri.SELECTED_ENVIRONMENT_KEY];
+            if (selected) {
+              set({ activeEnvironment: selected });
+            }
+          } catch (settingsError) {
+            console.warn("Failed to load workspace settings:", settingsError);
+          }
 
           // Load environment configuration
           await get().loadEnvironments();
@@ -527,8 +606,9 @@ export const useAppStore = create<AppState>()(
 
         set({ isLoadingFiles: true });
         try {
-          const files = await tauri.listHttpFiles(workspacePath);
-          const tree = buildFileTree(files, workspacePath);
+          const listing = await tauri.listAllHttpFiles(workspacePath);
+          listing.warnings.forEach((warning) => console.warn(warning));
+          const tree = buildFileTree(listing.files, workspacePath);
           set({ fileTree: tree, isLoadingFiles: false });
         } catch (error) {
           const message = error instanceof Error ? error.message : "Failed to refresh workspace";
@@ -566,13 +646,36 @@ export const useAppStore = create<AppState>()(
         }
       },
 
-      executeRequest: async (request: TauriHttpRequest) => {
-        const { workspacePath, openFiles, activeFileIndex } = get();
+      executeRequest: async (request: TauriHttpRequest, environment?: string, auth?: string) => {
+        const { workspacePath, openFiles, activeFileIndex, activeEnvironment, getCurrentVariables } =
+          get();
         const activeFile = activeFileIndex >= 0 ? openFiles[activeFileIndex] : null;
 
         set({ isExecutingRequest: true, isLoading: true, lastError: null });
         try {
-          const response = await tauri.sendRequest(request);
+          // The backend records the outcome in history, failures included
+          const history = workspacePath
+            ? {
+                workspace: workspacePath,
+                file_path: activeFile?.path,
+                environment: environment || activeEnvironment || undefined,
+                variables: getCurrentVariables(environment),
+                env_glob: useSettingsStore.getState().envFileGlob,
+              }
+            : undefined;
+          let response: tauri.HttpResponse;
+          try {
+            response = await tauri.sendRequest(request, history, auth);
+          } catch (error) {
+            // `# @auth oidc <name>` without a usable token: log in, then retry
+            const configName = parseOidcConfigName(auth);
+            if (!configName || !history || !isReauthRequired(error)) {
+              throw error;
+            }
+            const config = await tauri.getOidcAuthConfig(configName, history);
+            await useAuthStore.getState().loginNamedOidcConfig(configName, config);
+            response = await tauri.sendRequest(request, history, auth);
+          }
           set({
             currentResponse: {
               status: response.status,
@@ -595,30 +698,6 @@ export const useAppStore = create<AppState>()(
             isExecutingRequest: false,
             isLoading: false,
           });
-
-          // Auto-save to history if workspace is open
-          if (workspacePath) {
-            try {
-              await tauri.addHistoryEntry({
-                workspace: workspacePath,
-                file_path: activeFile?.path,
-                request_name: undefined, // Could extract from parsed request name
-                method: request.method,
-                url: request.url,
-                request_headers: JSON.stringify(request.headers),
-                request_body: request.body,
-                status: response.status,
-                status_text: response.status_text,
-                response_headers: JSON.stringify(response.headers),
-                response_body: response.body,
-                duration_ms: response.time,
-                response_size: response.size,
-              });
-            } catch (historyError) {
-              // Don't fail the request if history save fails
-              console.warn("Failed to save to history:", historyError);
-            }
-          }
         } catch (error) {
           const message = error instanceof Error ? error.message : "Request failed";
           set({ lastError: message, isExecutingRequest: false, isLoading: false });
@@ -634,7 +713,7 @@ export const useAppStore = create<AppState>()(
         try {
           await tauri.writeFile(activeFile.path, activeFile.content);
           const newFiles = [...openFiles];
-          newFiles[activeFileIndex] = { ...activeFile, modified: false };
+          newFiles[activeFileIndex] = { ...activeFile, modified: false, deleted: false };
           set({ openFiles: newFiles, isSavingFile: false });
         } catch (error) {
           const message = error instanceof Error ? error.message : "Failed to save file";
@@ -644,14 +723,15 @@ export const useAppStore = create<AppState>()(
 
       saveAllFiles: async () => {
         const { openFiles } = get();
-        const modifiedFiles = openFiles.filter((f) => f.modified);
+        // Files deleted on disk are only written back when saved one by one
+        const modifiedFiles = openFiles.filter((f) => f.modified && !f.deleted);
         if (modifiedFiles.length === 0) return;
 
         set({ isSavingFile: true, lastError: null });
         try {
           const newFiles = [...openFiles];
           for (let i = 0; i < openFiles.length; i++) {
-            if (openFiles[i].modified) {
+            if (openFiles[i].modified && !openFiles[i].deleted) {
               await tauri.writeFile(openFiles[i].path, openFiles[i].content);
               newFiles[i] = { ...openFiles[i], modified: false };
             }
@@ -715,7 +795,8 @@ export const useAppStore = create<AppState>()(
       runAllProgress: 0,
 
       executeAllRequests: async (stopOnError = false) => {
-        const { openFiles, activeFileIndex, getCurrentVariables, workspacePath } = get();
+        const { openFiles, activeFileIndex, getCurrentVariables, workspacePath, activeEnvironment } =
+          get();
         const file = openFiles[activeFileIndex];
 
         if (!file?.parsedRequests?.length) {
@@ -723,11 +804,17 @@ export const useAppStore = create<AppState>()(
           return;
         }
 
-        const requests = file.parsedRequests;
-        // Get all variables: environment + inline from file content
-        const envVariables = getCurrentVariables();
+        const parsed = file.parsedRequests;
+        let requests: ParsedRequest[];
+        try {
+          // `# @depends-on` dependencies run first
+          const order = await tauri.orderRequests(parsed);
+          requests = order.map((i) => parsed[i]);
+        } catch (error) {
+          set({ lastError: error instanceof Error ? error.message : String(error) });
+          return;
+        }
         const inlineVariables = extractInlineVariables(file.content);
-        const variables = { ...envVariables, ...inlineVariables };
         const results: RunAllResultItem[] = [];
 
         set({
@@ -743,27 +830,58 @@ export const useAppStore = create<AppState>()(
 
           const request = requests[i];
           const startTime = Date.now();
+          // `# @env name` pins a request to an environment
+          const environment = request.metadata?.env || activeEnvironment;
+          // Get all variables: environment + inline from file content
+          const variables = { ...getCurrentVariables(environment), ...inlineVariables };
 
           try {
+            // Values `$exec` commands provide are resolved by the backend
+            let source = request;
+            if (workspacePath && hasCommandVariables(get().environmentConfig, environment)) {
+              const resolved = await tauri.resolveRequest(
+                { parsed: request },
+                {
+                  workspace: workspacePath,
+                  environment: environment || undefined,
+                  filePath: file.path,
+                  variables,
+                  envGlob: useSettingsStore.getState().envFileGlob,
+                }
+              );
+              source = resolved.request;
+            }
+
             // Substitute variables
             const varRegex = /\{\{([\w.-]+)\}\}/g;
             const substitute = (str: string) =>
-              str.replace(varRegex, (match, varName) =>
-                varName in variables ? variables[varName] : match
-              );
+              str.replace(varRegex, (match, varName) => lookupVariable(variables, varName) ?? match);
 
-            const substitutedUrl = substitute(request.url);
+            const substitutedUrl = substitute(source.url);
             const substitutedHeaders = Object.fromEntries(
-              Object.entries(request.headers).map(([k, v]) => [k, substitute(v)])
+              Object.entries(source.headers).map(([k, v]) => [k, substitute(v)])
             );
-            const substitutedBody = request.body ? substitute(request.body) : undefined;
+            const substitutedBody = source.body ? substitute(source.body) : undefined;
 
-            const response = await tauri.sendRequest({
-              method: request.method,
-              url: substitutedUrl,
-              headers: substitutedHeaders,
-              body: substitutedBody,
-            });
+            const response = await tauri.sendRequest(
+              {
+                method: request.method,
+                url: substitutedUrl,
+                headers: substitutedHeaders,
+                body: substitutedBody,
+              },
+              workspacePath
+                ? {
+                    workspace: workspacePath,
+                    file_path: file.path,
+                    request_name: request.name,
+                    environment: environment || undefined,
+                    variables,
+                    env_glob: useSettingsStore.getState().envFileGlob,
+                  }
+                : undefined,
+              request.metadata?.auth
+            );
 
             results.push({
               request,
@@ -778,29 +896,6 @@ export const useAppStore = create<AppState>()(
               },
               duration: Date.now() - startTime,
             });
-
-            // Save to history
-            if (workspacePath) {
-              try {
-                await tauri.addHistoryEntry({
-                  workspace: workspacePath,
-                  file_path: file.path,
-                  request_name: request.name,
-                  method: request.method,
-                  url: substitutedUrl,
-                  request_headers: JSON.stringify(substitutedHeaders),
-                  request_body: substitutedBody,
-                  status: response.status,
-                  status_text: response.status_text,
-                  response_headers: JSON.stringify(response.headers),
-                  response_body: response.body,
-                  duration_ms: response.time,
-                  response_size: response.size,
-                });
-              } catch (historyError) {
-                console.warn("Failed to save to history:", historyError);
-              }
-            }
           } catch (error) {
             results.push({
               request,
@@ -888,16 +983,19 @@ export const useAppStore = create<AppState>()(
       setDiffResponses: (left, right) => {
         set({ diffLeftResponse: left, diffRightResponse: right });
       },
-      compareWithCurrent: (entry: HistoryEntry) => {
+      compareWithCurrent: async (entry: HistoryEntry) => {
         const { currentResponse } = get();
         if (!currentResponse) return;
 
+        // History lists leave bodies out
+        const bodies = await tauri.getHistoryEntryBody(entry.id);
+
         // Convert history entry to HttpResponse
         const historyResponse: HttpResponse = {
           status: entry.status,
           statusText: entry.status_text,
           headers: JSON.parse(entry.response_headers),
-          body: entry.response_body,
+          body: bodies?.response_body ?? "",
           time: entry.duration_ms,
           size: entry.response_size,
           timestamp: new Date(entry.timestamp),
@@ -975,6 +1073,7 @@ export const useAppStore = create<AppState>()(
       // History
       historyEntries: [],
       isLoadingHistory: false,
+      historyWarning: null,
 
       loadHistory: async () => {
         const { workspacePath } = get();
@@ -982,8 +1081,11 @@ export const useAppStore = create<AppState>()(
 
         set({ isLoadingHistory: true });
         try {
-          const entries = await tauri.getHistory(workspacePath, 100);
-          set({ historyEntries: entries, isLoadingHistory: false });
+          const [entries, historyWarning] = await Promise.all([
+            tauri.getHistory(workspacePath, 100),
+            tauri.getHistoryDbWarning(),
+          ]);
+          set({ historyEntries: entries, historyWarning, isLoadingHistory: false });
         } catch (error) {
           console.error("Failed to load history:", error);
           set({ isLoadingHistory: false });
@@ -1014,6 +1116,19 @@ export const useAppStore = create<AppState>()(
         }
       },
 
+      repairHistory: async () => {
+        try {
+          const backup = await tauri.repairHistoryDb();
+          if (backup) {
+            console.warn("Backed up the old history database to", backup);
+          }
+          set({ historyWarning: null });
+          await get().loadHistory();
+        } catch (error) {
+          console.error("Failed to repair history database:", error);
+        }
+      },
+
       viewHistoryEntry: async (entry: HistoryEntry) => {
         // If the entry has a file path and no file is currently open (or a different file),
         // open the associated file first
@@ -1027,11 +1142,12 @@ export const useAppStore = create<AppState>()(
         }
 
         // Parse the stored response and set it as current
+        const bodies = await tauri.getHistoryEntryBody(entry.id);
         const response: HttpResponse = {
           status: entry.status,
           statusText: entry.status_text,
           headers: JSON.parse(entry.response_headers),
-          body: entry.response_body,
+          body: bodies?.response_body ?? "",
           time: entry.duration_ms,
           size: entry.response_size,
           timestamp: new Date(entry.timestamp),
@@ -1043,7 +1159,7 @@ export const useAppStore = create<AppState>()(
           method: entry.method,
           url: entry.url,
           headers: JSON.parse(entry.request_headers),
-          body: entry.request_body,
+          body: bodies?.request_body ?? undefined,
           lineNumber: 0,
         };
 
@@ -1052,11 +1168,12 @@ export const useAppStore = create<AppState>()(
 
       rerunHistoryEntry: async (entry: HistoryEntry) => {
         const { executeRequest } = get();
+        const bodies = await tauri.getHistoryEntryBody(entry.id);
         const request: TauriHttpRequest = {
           method: entry.method,
           url: entry.url,
           headers: JSON.parse(entry.request_headers),
-          body: entry.request_body,
+          body: bodies?.request_body ?? undefined,
         };
         set({ showHistoryPanel: false });
         await executeRequest(request);
@@ -1,13 +1,25 @@
 import { create } from "zustand";
-import { persist } from "zustand/middleware";
 import { open } from "@tauri-apps/plugin-shell";
 import {
   oidcStartAuth,
   oidcWaitForCallback,
+  oidcCancelCallback,
+  oidcLogout,
+  oidcWaitForLogout,
   oidcExchangeCode,
-  oidcRefreshToken as oidcRefreshTokenApi,
+  oidcPasswordGrant,
+  oidcClientCredentials,
+  storeTokens,
+  getValidToken,
+  deleteTokens,
+  REAUTH_REQUIRED,
+  type CachedToken,
   type OidcConfig,
+  type OidcTokenResponse,
+  type TokenEndpointAuthMethod,
 } from "@/lib/tauri";
+import { useAppStore } from "@/stores/appStore";
+import { useSettingsStore } from "@/stores/settingsStore";
 
 /**
  * OAuth 2.0 Token response
@@ -33,6 +45,7 @@ export interface OAuth2Config {
   // Optional
   clientSecret?: string;
   scopes?: string[];
+  tokenEndpointAuthMethod?: TokenEndpointAuthMethod;
 
   // For password grant
   username?: string;
@@ -55,8 +68,13 @@ export interface OidcAuthConfig {
   clientId: string;
   redirectUrl: string;
   scopes: string[];
+  // Listen on a port the OS picks (loopback redirects, RFC 8252)
+  useRandomPort?: boolean;
+  // Send PKCE parameters (default true); off for providers that reject them
+  usePkce?: boolean;
   // Optional
   clientSecret?: string;
+  tokenEndpointAuthMethod?: TokenEndpointAuthMethod;
   extraParams?: Record<string, string>;
   // Custom identifier for this config (for caching)
   configId?: string;
@@ -74,7 +92,8 @@ export type AuthConfig =
   | { type: 'oidc'; config: OidcAuthConfig };
 
 interface AuthStore {
-  // Cached tokens (keyed by config hash)
+  // Tokens used this session (keyed by config hash); the backend keeps the
+  // persistent cache
   tokens: Record<string, OAuthToken>;
 
   // OIDC auth in progress
@@ -82,16 +101,20 @@ interface AuthStore {
 
   // Actions for OAuth2 (client credentials / password grant)
   getToken: (config: OAuth2Config) => Promise<string>;
-  refreshToken: (config: OAuth2Config) => Promise<string>;
   fetchNewToken: (config: OAuth2Config) => Promise<OAuthToken>;
 
   // Actions for OIDC (Authorization Code + PKCE)
   getOidcToken: (config: OidcAuthConfig) => Promise<string>;
   performOidcLogin: (config: OidcAuthConfig) => Promise<OAuthToken>;
-  refreshOidcToken: (config: OidcAuthConfig) => Promise<string>;
+  cancelOidcLogin: () => Promise<void>;
+  // Log in with the config a `# @auth oidc <name>` directive names
+  loginNamedOidcConfig: (name: string, config: OidcConfig) => Promise<OAuthToken>;
+  // Log out at the IdP, opening its end-session page; the cached token is
+  // cleared even when the provider has no end_session_endpoint
+  oidcLogout: (config: OidcAuthConfig, postLogoutRedirectUri?: string) => Promise<void>;
 
   // Common actions
-  clearToken: (configId: string) => void;
+  clearToken: (configId: string) => Promise<void>;
   clearAllTokens: () => void;
 
   // Check if token is valid
@@ -133,332 +156,225 @@ function toTauriOidcConfig(config: OidcAuthConfig): OidcConfig {
     token_endpoint: config.tokenEndpoint,
     client_id: config.clientId,
     client_secret: config.clientSecret,
+    token_endpoint_auth_method: config.tokenEndpointAuthMethod,
     redirect_url: config.redirectUrl,
+    use_random_port: config.useRandomPort,
+    use_pkce: config.usePkce,
     scopes: config.scopes,
     extra_params: config.extraParams,
   };
 }
 
 /**
- * Fetch a new OAuth token from the token endpoint
+ * Convert OAuth2Config to OidcConfig for the backend's token endpoint calls
  */
-async function fetchOAuthToken(config: OAuth2Config): Promise<OAuthToken> {
-  const params = new URLSearchParams();
-  params.set('grant_type', config.grantType);
-  params.set('client_id', config.clientId);
-
-  if (config.clientSecret) {
-    params.set('client_secret', config.clientSecret);
-  }
+function toTauriOAuth2Config(config: OAuth2Config): OidcConfig {
+  return {
+    token_endpoint: config.tokenUrl,
+    client_id: config.clientId,
+    client_secret: config.clientSecret,
+    token_endpoint_auth_method: config.tokenEndpointAuthMethod,
+    redirect_url: '',
+    scopes: config.scopes ?? [],
+  };
+}
 
-  if (config.scopes && config.scopes.length > 0) {
-    params.set('scope', config.scopes.join(' '));
-  }
+function toOAuthToken(token: CachedToken): OAuthToken {
+  return {
+    accessToken: token.access_token,
+    refreshToken: token.refresh_token,
+    tokenType: token.token_type || 'Bearer',
+    expiresAt: token.expires_at ? token.expires_at * 1000 : undefined,
+    scope: token.scope,
+    idToken: token.id_token,
+  };
+}
 
-  // Password grant specific
-  if (config.grantType === 'password') {
-    if (config.username) params.set('username', config.username);
-    if (config.password) params.set('password', config.password);
-  }
+export function isReauthRequired(error: unknown): boolean {
+  return error instanceof Error && error.message.startsWith(REAUTH_REQUIRED);
+}
 
-  const response = await fetch(config.tokenUrl, {
-    method: 'POST',
-    headers: {
-      'Content-Type': 'application/x-www-form-urlencoded',
-      'Accept': 'application/json',
-    },
-    body: params,
-  });
+/** Workspace tokens are cached for */
+function currentWorkspace(): string {
+  return useAppStore.getState().workspacePath ?? '';
+}
 
-  if (!response.ok) {
-    const errorText = await response.text();
-    throw new Error(`OAuth token request failed: ${response.status} ${errorText}`);
-  }
+export const useAuthStore = create<AuthStore>()((set, get) => {
+  /** Keep a token in the backend cache and remember it for this session */
+  const cacheToken = async (key: string, response: OidcTokenResponse) => {
+    const { token, warning } = await storeTokens(currentWorkspace(), key, response);
+    if (warning) {
+      console.warn(warning);
+    }
+    return remember(key, token);
+  };
 
-  const data = await response.json();
+  const remember = (key: string, cached: CachedToken) => {
+    const token = toOAuthToken(cached);
+    set((state) => ({ tokens: { ...state.tokens, [key]: token } }));
+    return token;
+  };
 
-  // Calculate expiration time
-  let expiresAt: number | undefined;
-  if (data.expires_in) {
-    // expires_in is in seconds, subtract 60s buffer for safety
-    expiresAt = Date.now() + (data.expires_in - 60) * 1000;
-  }
+  /**
+   * The cached token, refreshed by the backend when close to expiry, or null
+   * when a new one has to be obtained
+   */
+  const validToken = async (key: string, config: OidcConfig) => {
+    try {
+      const cached = await getValidToken(
+        currentWorkspace(),
+        key,
+        config,
+        useSettingsStore.getState().tokenRefreshSkew
+      );
+      return remember(key, cached);
+    } catch (error) {
+      if (isReauthRequired(error)) {
+        return null;
+      }
+      throw error;
+    }
+  };
 
   return {
-    accessToken: data.access_token,
-    refreshToken: data.refresh_token,
-    tokenType: data.token_type || 'Bearer',
-    expiresAt,
-    scope: data.scope,
-  };
-}
+    tokens: {},
+    oidcAuthInProgress: false,
+
+    isTokenValid: (configId: string) => {
+      const token = get().tokens[configId];
+      if (!token) return false;
+      if (!token.expiresAt) return true; // No expiry = valid
+      return token.expiresAt > Date.now();
+    },
 
-/**
- * Refresh an existing OAuth token
- */
-async function refreshOAuthToken(
-  config: OAuth2Config,
-  refreshToken: string
-): Promise<OAuthToken> {
-  const params = new URLSearchParams();
-  params.set('grant_type', 'refresh_token');
-  params.set('refresh_token', refreshToken);
-  params.set('client_id', config.clientId);
-
-  if (config.clientSecret) {
-    params.set('client_secret', config.clientSecret);
-  }
+    getToken: async (config: OAuth2Config) => {
+      const existing = await validToken(getConfigKey(config), toTauriOAuth2Config(config));
+      if (existing) {
+        return existing.accessToken;
+      }
 
-  const response = await fetch(config.tokenUrl, {
-    method: 'POST',
-    headers: {
-      'Content-Type': 'application/x-www-form-urlencoded',
-      'Accept': 'application/json',
+      // Fetch new token
+      const token = await get().fetchNewToken(config);
+      return token.accessToken;
     },
-    body: params,
-  });
 
-  if (!response.ok) {
-    throw new Error(`Token refresh failed: ${response.status}`);
-  }
+    fetchNewToken: async (config: OAuth2Config) => {
+      const response =
+        config.grantType === 'password'
+          ? await oidcPasswordGrant(
+              toTauriOAuth2Config(config),
+              config.username ?? '',
+              config.password ?? ''
+            )
+          : await oidcClientCredentials(toTauriOAuth2Config(config));
+      return cacheToken(getConfigKey(config), response);
+    },
 
-  const data = await response.json();
+    // OIDC methods
+    getOidcToken: async (config: OidcAuthConfig) => {
+      const existing = await validToken(getOidcConfigKey(config), toTauriOidcConfig(config));
+      if (existing) {
+        return existing.accessToken;
+      }
 
-  let expiresAt: number | undefined;
-  if (data.expires_in) {
-    expiresAt = Date.now() + (data.expires_in - 60) * 1000;
-  }
+      // Perform interactive OIDC login
+      const token = await get().performOidcLogin(config);
+      return token.accessToken;
+    },
 
-  return {
-    accessToken: data.access_token,
-    refreshToken: data.refresh_token || refreshToken, // Keep old refresh token if not provided
-    tokenType: data.token_type || 'Bearer',
-    expiresAt,
-    scope: data.scope,
-  };
-}
+    performOidcLogin: async (config: OidcAuthConfig) => {
+      // Prevent concurrent auth flows
+      if (get().oidcAuthInProgress) {
+        throw new Error('OIDC authentication already in progress');
+      }
 
-export const useAuthStore = create<AuthStore>()(
-  persist(
-    (set, get) => ({
-      tokens: {},
-      oidcAuthInProgress: false,
-
-      isTokenValid: (configId: string) => {
-        const token = get().tokens[configId];
-        if (!token) return false;
-        if (!token.expiresAt) return true; // No expiry = valid
-        return token.expiresAt > Date.now();
-      },
-
-      getToken: async (config: OAuth2Config) => {
-        const key = getConfigKey(config);
-        const existing = get().tokens[key];
-
-        // Check if we have a valid cached token
-        if (existing) {
-          // Token exists and not expired
-          if (!existing.expiresAt || existing.expiresAt > Date.now()) {
-            return existing.accessToken;
-          }
-
-          // Token expired, try refresh if we have refresh token
-          if (existing.refreshToken) {
-            try {
-              return await get().refreshToken(config);
-            } catch (error) {
-              console.warn('Token refresh failed, fetching new token:', error);
-              // Fall through to fetch new token
-            }
-          }
-        }
-
-        // Fetch new token
-        const token = await get().fetchNewToken(config);
-        return token.accessToken;
-      },
-
-      refreshToken: async (config: OAuth2Config) => {
-        const key = getConfigKey(config);
-        const existing = get().tokens[key];
-
-        if (!existing?.refreshToken) {
-          throw new Error('No refresh token available');
-        }
-
-        const newToken = await refreshOAuthToken(config, existing.refreshToken);
-
-        set((state) => ({
-          tokens: {
-            ...state.tokens,
-            [key]: newToken,
-          },
-        }));
-
-        return newToken.accessToken;
-      },
-
-      fetchNewToken: async (config: OAuth2Config) => {
-        const key = getConfigKey(config);
-        const token = await fetchOAuthToken(config);
-
-        set((state) => ({
-          tokens: {
-            ...state.tokens,
-            [key]: token,
-          },
-        }));
-
-        return token;
-      },
-
-      // OIDC methods
-      getOidcToken: async (config: OidcAuthConfig) => {
-        const key = getOidcConfigKey(config);
-        const existing = get().tokens[key];
-
-        // Check if we have a valid cached token
-        if (existing) {
-          // Token exists and not expired
-          if (!existing.expiresAt || existing.expiresAt > Date.now()) {
-            return existing.accessToken;
-          }
-
-          // Token expired, try refresh if we have refresh token
-          if (existing.refreshToken) {
-            try {
-              return await get().refreshOidcToken(config);
-            } catch (error) {
-              console.warn('OIDC token refresh failed, starting new login:', error);
-              // Fall through to new login
-            }
-          }
-        }
-
-        // Perform interactive OIDC login
-        const token = await get().performOidcLogin(config);
-        return token.accessToken;
-      },
-
-      performOidcLogin: async (config: OidcAuthConfig) => {
-        const key = getOidcConfigKey(config);
-
-        // Prevent concurrent auth flows
-        if (get().oidcAuthInProgress) {
-          throw new Error('OIDC authentication already in progress');
-        }
-
-        set({ oidcAuthInProgress: true });
-
-        try {
-          const tauriConfig = toTauriOidcConfig(config);
-
-          // Start the auth flow - get auth URL and PKCE params
-          const authResult = await oidcStartAuth(tauriConfig);
-
-          // Open browser to authorization URL
-          await open(authResult.auth_url);
-
-          // Wait for callback on localhost
-          const code = await oidcWaitForCallback(
-            config.redirectUrl,
-            authResult.state
-          );
-
-          // Exchange code for tokens
-          const tokenResponse = await oidcExchangeCode(
-            tauriConfig,
-            code,
-            authResult.code_verifier
-          );
-
-          // Calculate expiration time
-          let expiresAt: number | undefined;
-          if (tokenResponse.expires_in) {
-            // expires_in is in seconds, subtract 60s buffer
-            expiresAt = Date.now() + (tokenResponse.expires_in - 60) * 1000;
-          }
-
-          const token: OAuthToken = {
-            accessToken: tokenResponse.access_token,
-            refreshToken: tokenResponse.refresh_token,
-            tokenType: tokenResponse.token_type || 'Bearer',
-            expiresAt,
-            scope: tokenResponse.scope,
-            idToken: tokenResponse.id_token,
-          };
-
-          set((state) => ({
-            tokens: {
-              ...state.tokens,
-              [key]: token,
-            },
-            oidcAuthInProgress: false,
-          }));
-
-          return token;
-        } catch (error) {
-          set({ oidcAuthInProgress: false });
-          throw error;
-        }
-      },
-
-      refreshOidcToken: async (config: OidcAuthConfig) => {
-        const key = getOidcConfigKey(config);
-        const existing = get().tokens[key];
-
-        if (!existing?.refreshToken) {
-          throw new Error('No OIDC refresh token available');
-        }
+      set({ oidcAuthInProgress: true });
 
+      try {
         const tauriConfig = toTauriOidcConfig(config);
-        const tokenResponse = await oidcRefreshTokenApi(
-          tauriConfig,
-          existing.refreshToken
+
+        // Start the auth flow - get auth URL and PKCE params
+        const authResult = await oidcStartAuth(tauriConfig, currentWorkspace() || undefined);
+
+        // Open browser to authorization URL
+        await open(authResult.auth_url);
+
+        // Wait for callback on localhost
+        const code = await oidcWaitForCallback(
+          authResult.redirect_url,
+          authResult.state
         );
 
-        let expiresAt: number | undefined;
-        if (tokenResponse.expires_in) {
-          expiresAt = Date.now() + (tokenResponse.expires_in - 60) * 1000;
-        }
-
-        const newToken: OAuthToken = {
-          accessToken: tokenResponse.access_token,
-          refreshToken: tokenResponse.refresh_token || existing.refreshToken,
-          tokenType: tokenResponse.token_type || 'Bearer',
-          expiresAt,
-          scope: tokenResponse.scope,
-          idToken: tokenResponse.id_token,
-        };
-
-        set((state) => ({
-          tokens: {
-            ...state.tokens,
-            [key]: newToken,
-          },
-        }));
-
-        return newToken.accessToken;
-      },
-
-      clearToken: (configId: string) => {
-        set((state) => {
-          const { [configId]: _, ...rest } = state.tokens;
-          return { tokens: rest };
-        });
-      },
-
-      clearAllTokens: () => {
-        set({ tokens: {} });
-      },
-    }),
-    {
-      name: 'kvile-auth',
-      // Only persist tokens, not oidcAuthInProgress
-      partialize: (state) => ({ tokens: state.tokens }),
-    }
-  )
-);
+        // Exchange code for tokens, with the redirect URI the IdP was sent
+        const tokenResponse = await oidcExchangeCode(
+          { ...tauriConfig, redirect_url: authResult.redirect_url },
+          code,
+          authResult.code_verifier,
+          authResult.nonce
+        );
+
+        return await cacheToken(getOidcConfigKey(config), tokenResponse);
+      } finally {
+        set({ oidcAuthInProgress: false });
+      }
+    },
+
+    loginNamedOidcConfig: async (name: string, config: OidcConfig) => {
+      // Cached under the config name, where the backend looks for it
+      return get().performOidcLogin({
+        issuer: config.issuer,
+        authorizationEndpoint: config.authorization_endpoint,
+        tokenEndpoint: config.token_endpoint,
+        clientId: config.client_id,
+        clientSecret: config.client_secret,
+        tokenEndpointAuthMethod: config.token_endpoint_auth_method,
+        redirectUrl: config.redirect_url,
+        useRandomPort: config.use_random_port,
+        usePkce: config.use_pkce,
+        scopes: config.scopes,
+        extraParams: config.extra_params,
+        configId: name,
+      });
+    },
+
+    cancelOidcLogin: async () => {
+      // The pending login fails with "Login cancelled" and resets the flag
+      await oidcCancelCallback();
+    },
+
+    oidcLogout: async (config: OidcAuthConfig, postLogoutRedirectUri?: string) => {
+      const configId = getOidcConfigKey(config);
+      set((state) => {
+        const { [configId]: _, ...rest } = state.tokens;
+        return { tokens: rest };
+      });
+
+      const result = await oidcLogout(
+        currentWorkspace(),
+        configId,
+        toTauriOidcConfig(config),
+        undefined,
+        postLogoutRedirectUri
+      );
+      await open(result.logout_url);
+      if (postLogoutRedirectUri) {
+        await oidcWaitForLogout(result.state).catch(() => {});
+      }
+    },
+
+    clearToken: async (configId: string) => {
+      await deleteTokens(currentWorkspace(), configId);
+      set((state) => {
+        const { [configId]: _, ...rest } = state.tokens;
+        return { tokens: rest };
+      });
+    },
+
+    clearAllTokens: () => {
+      set({ tokens: {} });
+    },
+  };
+});
 
 /**
  * Helper to generate Basic Auth header value
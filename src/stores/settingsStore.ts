@@ -20,11 +20,17 @@ export interface Settings {
   followRedirects: boolean;
   verifySsl: boolean;
   maxResponseSize: number;
+  /** Seconds before expiry a cached OAuth token is refreshed */
+  tokenRefreshSkew: number;
 
   // Environment
   autoLoadEnv: boolean;
   showVariableHints: boolean;
   maskSensitiveValues: boolean;
+  /** Look up {{NAME}} in the OS environment when nothing else defines it */
+  processEnvFallback: boolean;
+  /** Glob, relative to the workspace, for env files merged across it */
+  envFileGlob: string;
 
   // Editor View
   defaultEditorView: 'gui' | 'source';
@@ -49,11 +55,14 @@ export const defaultSettings: Settings = {
   followRedirects: true,
   verifySsl: true,
   maxResponseSize: 10 * 1024 * 1024, // 10MB
+  tokenRefreshSkew: 60,
 
   // Environment
   autoLoadEnv: true,
   showVariableHints: true,
   maskSensitiveValues: true,
+  processEnvFallback: false,
+  envFileGlob: '**/http-client.env.json',
 
   // Editor View
   defaultEditorView: 'gui',